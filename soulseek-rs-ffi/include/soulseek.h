@@ -0,0 +1,104 @@
+/*
+ * C bindings for soulseek-rs.
+ *
+ * Link against libsoulseek_ffi (built by `cargo build -p soulseek-rs-ffi`).
+ *
+ * Strings are UTF-8 and NUL-terminated. Strings passed to a callback are
+ * only valid during that call. Events are delivered on background threads,
+ * so callbacks must be thread-safe.
+ */
+
+#ifndef SOULSEEK_H
+#define SOULSEEK_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SlskClient SlskClient;
+
+typedef enum SlskStatus {
+    SLSK_OK = 0,
+    /* A required pointer argument was NULL. */
+    SLSK_ERR_NULL = 1,
+    /* A string argument was not valid UTF-8, or malformed (server address). */
+    SLSK_ERR_INVALID = 2,
+    SLSK_ERR_CONNECT = 3,
+    /* The login failed or the server rejected the credentials. */
+    SLSK_ERR_LOGIN = 4,
+    SLSK_ERR_SEARCH = 5,
+    SLSK_ERR_DOWNLOAD = 6,
+} SlskStatus;
+
+typedef enum SlskEventKind {
+    /* One file from a search; `id` is the search's id. */
+    SLSK_EVENT_SEARCH_RESULT = 1,
+    /* The search's timeout passed; no more results follow. */
+    SLSK_EVENT_SEARCH_DONE = 2,
+    /* `bytes` of `size` received so far. */
+    SLSK_EVENT_DOWNLOAD_PROGRESS = 3,
+    /* `message` is the path the file was saved to. */
+    SLSK_EVENT_DOWNLOAD_COMPLETED = 4,
+    /* `message` says why. */
+    SLSK_EVENT_DOWNLOAD_FAILED = 5,
+} SlskEventKind;
+
+typedef struct SlskEvent {
+    SlskEventKind kind;
+    /* The id returned by slsk_search or slsk_download. */
+    uint64_t id;
+    const char *username;
+    const char *filename;
+    uint64_t size;
+    uint64_t bytes;
+    /* NULL when the event kind has no message. */
+    const char *message;
+} SlskEvent;
+
+typedef void (*SlskCallback)(const SlskEvent *event, void *user_data);
+
+/* The library version, e.g. "5.0.0". Never freed. */
+const char *slsk_version(void);
+
+/* A description of the last error on the calling thread, or NULL. Valid
+ * until the next call into the library on this thread. */
+const char *slsk_last_error(void);
+
+/* A new client. `server` is "host:port", or NULL for the official server.
+ * Returns NULL if an argument is NULL or invalid. */
+SlskClient *slsk_client_new(const char *username, const char *password,
+                            const char *server);
+
+/* Shut the client down and free it. No callback runs once this returns.
+ * NULL is ignored. */
+void slsk_client_free(SlskClient *client);
+
+/* Receive events through `callback` (NULL stops them). `user_data` is
+ * passed back on every call. Callbacks run one at a time, and must not call
+ * slsk_client_set_callback or slsk_client_free. */
+SlskStatus slsk_client_set_callback(SlskClient *client, SlskCallback callback,
+                                    void *user_data);
+
+SlskStatus slsk_client_connect(SlskClient *client);
+
+SlskStatus slsk_client_login(SlskClient *client);
+
+/* Start a search lasting `timeout_secs`. Results arrive as events tagged
+ * with the id written to `search_id` (which may be NULL). */
+SlskStatus slsk_search(SlskClient *client, const char *query,
+                       uint32_t timeout_secs, uint64_t *search_id);
+
+/* Download `filename` from `username` into `directory`. `size` may be 0 if
+ * unknown. Progress arrives as events tagged with the id written to
+ * `download_id` (which may be NULL). */
+SlskStatus slsk_download(SlskClient *client, const char *username,
+                         const char *filename, uint64_t size,
+                         const char *directory, uint64_t *download_id);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SOULSEEK_H */
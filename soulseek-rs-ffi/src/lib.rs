@@ -0,0 +1,546 @@
+//! C bindings for soulseek-rs, declared in `include/soulseek.h`.
+//!
+//! A `SlskClient` owns a [`Client`]. Searches and downloads run on a
+//! background thread each, turning the library's result and status
+//! channels into calls to the registered callback. The callback lives
+//! behind a mutex that is held while it runs, so freeing the client (which
+//! clears it) guarantees no callback is running or will run.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use soulseek_rs::utils::lock::MutexExt;
+use soulseek_rs::{
+    Client, ClientSettings, DownloadStatus, LoginOutcome, PeerAddress,
+    SearchResult,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlskStatus {
+    Ok = 0,
+    ErrNull = 1,
+    ErrInvalid = 2,
+    ErrConnect = 3,
+    ErrLogin = 4,
+    ErrSearch = 5,
+    ErrDownload = 6,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlskEventKind {
+    SearchResult = 1,
+    SearchDone = 2,
+    DownloadProgress = 3,
+    DownloadCompleted = 4,
+    DownloadFailed = 5,
+}
+
+/// One event; its strings are only valid during the callback.
+#[repr(C)]
+pub struct SlskEvent {
+    pub kind: SlskEventKind,
+    pub id: u64,
+    pub username: *const c_char,
+    pub filename: *const c_char,
+    pub size: u64,
+    pub bytes: u64,
+    pub message: *const c_char,
+}
+
+pub type SlskCallback = Option<
+    unsafe extern "C" fn(event: *const SlskEvent, user_data: *mut c_void),
+>;
+
+struct Callback {
+    function: unsafe extern "C" fn(*const SlskEvent, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// SAFETY: the header requires callbacks (and so their user data) to be
+// usable from any thread.
+unsafe impl Send for Callback {}
+
+/// The registered callback, shared with the background threads.
+type Listener = Arc<Mutex<Option<Callback>>>;
+
+pub struct SlskClient {
+    client: Client,
+    listener: Listener,
+    next_id: AtomicU64,
+}
+
+/// An event before its strings are made C strings.
+#[derive(Clone, Copy)]
+struct Event<'a> {
+    kind: SlskEventKind,
+    id: u64,
+    username: &'a str,
+    filename: &'a str,
+    size: u64,
+    bytes: u64,
+    message: Option<&'a str>,
+}
+
+fn emit(listener: &Listener, event: &Event) {
+    let Ok(callback) = listener.lock_safe() else {
+        return;
+    };
+    let Some(callback) = callback.as_ref() else {
+        return;
+    };
+    let username = c_string(event.username);
+    let filename = c_string(event.filename);
+    let message = event.message.map(c_string);
+    let event = SlskEvent {
+        kind: event.kind,
+        id: event.id,
+        username: username.as_ptr(),
+        filename: filename.as_ptr(),
+        size: event.size,
+        bytes: event.bytes,
+        message: message.as_ref().map_or(ptr::null(), |m| m.as_ptr()),
+    };
+    // SAFETY: the caller registered a callback taking this event and user
+    // data, and every pointer in `event` outlives the call.
+    unsafe { (callback.function)(&raw const event, callback.user_data) };
+}
+
+/// `text` as a C string, dropping interior NULs rather than failing.
+fn c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail(status: SlskStatus, message: impl Into<String>) -> SlskStatus {
+    let message = c_string(&message.into());
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    status
+}
+
+/// Read a required string argument.
+///
+/// # Safety
+/// `text` must be NULL or a valid NUL-terminated string.
+unsafe fn read_str<'a>(
+    text: *const c_char,
+    name: &str,
+) -> Result<&'a str, SlskStatus> {
+    if text.is_null() {
+        return Err(fail(SlskStatus::ErrNull, format!("{name} is NULL")));
+    }
+    // SAFETY: non-NULL, and valid per the caller's contract.
+    unsafe { CStr::from_ptr(text) }.to_str().map_err(|_| {
+        fail(SlskStatus::ErrInvalid, format!("{name} is not UTF-8"))
+    })
+}
+
+/// The library version. The string is static.
+#[unsafe(no_mangle)]
+pub const extern "C" fn slsk_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// The last error on the calling thread, or NULL.
+#[unsafe(no_mangle)]
+pub extern "C" fn slsk_last_error() -> *const c_char {
+    LAST_ERROR
+        .with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// A new client, or NULL if an argument is invalid.
+///
+/// # Safety
+/// Each argument must be NULL or a valid NUL-terminated string; only
+/// `server` may be NULL.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_client_new(
+    username: *const c_char,
+    password: *const c_char,
+    server: *const c_char,
+) -> *mut SlskClient {
+    // SAFETY: forwarded from the caller's contract.
+    let arguments = unsafe {
+        (
+            read_str(username, "username"),
+            read_str(password, "password"),
+        )
+    };
+    let (Ok(username), Ok(password)) = arguments else {
+        return ptr::null_mut();
+    };
+    let mut settings = ClientSettings::new(username, password);
+    if !server.is_null() {
+        // SAFETY: non-NULL, and valid per the caller's contract.
+        let Ok(server) = (unsafe { read_str(server, "server") }) else {
+            return ptr::null_mut();
+        };
+        let Some((host, port)) = server
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+        else {
+            fail(SlskStatus::ErrInvalid, "server must be host:port");
+            return ptr::null_mut();
+        };
+        settings.server_address = PeerAddress::new(host.to_string(), port);
+    }
+    Box::into_raw(Box::new(SlskClient {
+        client: Client::with_settings(settings),
+        listener: Arc::new(Mutex::new(None)),
+        next_id: AtomicU64::new(1),
+    }))
+}
+
+/// Shut down and free a client. NULL is ignored.
+///
+/// # Safety
+/// `client` must be NULL or a pointer from [`slsk_client_new`] that hasn't
+/// been freed, and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_client_free(client: *mut SlskClient) {
+    if client.is_null() {
+        return;
+    }
+    // SAFETY: a live pointer from `slsk_client_new`, per the contract.
+    let client = unsafe { Box::from_raw(client) };
+    // Waits for a running callback; none starts after this.
+    if let Ok(mut callback) = client.listener.lock_safe() {
+        *callback = None;
+    }
+    drop(client);
+}
+
+/// Register (or with NULL, clear) the event callback.
+///
+/// # Safety
+/// `client` must be a live client; `callback` must be safe to call from
+/// any thread with `user_data`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_client_set_callback(
+    client: *mut SlskClient,
+    callback: SlskCallback,
+    user_data: *mut c_void,
+) -> SlskStatus {
+    // SAFETY: NULL or live, per the contract.
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return fail(SlskStatus::ErrNull, "client is NULL");
+    };
+    let Ok(mut slot) = client.listener.lock_safe() else {
+        return fail(SlskStatus::ErrInvalid, "callback lock poisoned");
+    };
+    *slot = callback.map(|function| Callback {
+        function,
+        user_data,
+    });
+    SlskStatus::Ok
+}
+
+/// Connect to the server.
+///
+/// # Safety
+/// `client` must be a live client not used by another thread meanwhile.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_client_connect(
+    client: *mut SlskClient,
+) -> SlskStatus {
+    // SAFETY: NULL or live and unaliased, per the contract.
+    let Some(client) = (unsafe { client.as_mut() }) else {
+        return fail(SlskStatus::ErrNull, "client is NULL");
+    };
+    match client.client.connect() {
+        Ok(()) => SlskStatus::Ok,
+        Err(e) => fail(SlskStatus::ErrConnect, e.to_string()),
+    }
+}
+
+/// Log in with the client's credentials.
+///
+/// # Safety
+/// `client` must be a live client.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_client_login(
+    client: *mut SlskClient,
+) -> SlskStatus {
+    // SAFETY: NULL or live, per the contract.
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return fail(SlskStatus::ErrNull, "client is NULL");
+    };
+    match client.client.login() {
+        Ok(LoginOutcome::Accepted { .. }) => SlskStatus::Ok,
+        Ok(LoginOutcome::Rejected(reason)) => {
+            fail(SlskStatus::ErrLogin, format!("login rejected: {reason}"))
+        }
+        Err(e) => fail(SlskStatus::ErrLogin, e.to_string()),
+    }
+}
+
+/// Start a search; results arrive as events.
+///
+/// # Safety
+/// `client` must be a live client, `query` a valid string, and
+/// `search_id` NULL or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_search(
+    client: *mut SlskClient,
+    query: *const c_char,
+    timeout_secs: u32,
+    search_id: *mut u64,
+) -> SlskStatus {
+    // SAFETY: NULL or live, per the contract.
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return fail(SlskStatus::ErrNull, "client is NULL");
+    };
+    // SAFETY: forwarded from the caller's contract.
+    let query = match unsafe { read_str(query, "query") } {
+        Ok(query) => query,
+        Err(status) => return status,
+    };
+    let results = match client
+        .client
+        .search_stream(query, Duration::from_secs(timeout_secs.into()))
+    {
+        Ok(results) => results,
+        Err(e) => return fail(SlskStatus::ErrSearch, e.to_string()),
+    };
+    let id = client.next_id.fetch_add(1, Ordering::Relaxed);
+    let listener = client.listener.clone();
+    thread::spawn(move || {
+        for result in results {
+            emit_result(&listener, id, &result);
+        }
+        emit(
+            &listener,
+            &Event {
+                kind: SlskEventKind::SearchDone,
+                id,
+                username: "",
+                filename: "",
+                size: 0,
+                bytes: 0,
+                message: None,
+            },
+        );
+    });
+    // SAFETY: NULL or writable, per the contract.
+    if let Some(out) = unsafe { search_id.as_mut() } {
+        *out = id;
+    }
+    SlskStatus::Ok
+}
+
+fn emit_result(listener: &Listener, id: u64, result: &SearchResult) {
+    for file in &result.files {
+        emit(
+            listener,
+            &Event {
+                kind: SlskEventKind::SearchResult,
+                id,
+                username: &result.username,
+                filename: &file.name,
+                size: file.size,
+                bytes: 0,
+                message: None,
+            },
+        );
+    }
+}
+
+/// Start a download; progress arrives as events.
+///
+/// # Safety
+/// `client` must be a live client, the strings valid, and `download_id`
+/// NULL or writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn slsk_download(
+    client: *mut SlskClient,
+    username: *const c_char,
+    filename: *const c_char,
+    size: u64,
+    directory: *const c_char,
+    download_id: *mut u64,
+) -> SlskStatus {
+    // SAFETY: NULL or live, per the contract.
+    let Some(client) = (unsafe { client.as_ref() }) else {
+        return fail(SlskStatus::ErrNull, "client is NULL");
+    };
+    // SAFETY: forwarded from the caller's contract.
+    let arguments = unsafe {
+        (
+            read_str(username, "username"),
+            read_str(filename, "filename"),
+            read_str(directory, "directory"),
+        )
+    };
+    let (username, filename, directory) = match arguments {
+        (Ok(username), Ok(filename), Ok(directory)) => {
+            (username, filename, directory)
+        }
+        (Err(status), ..) | (_, Err(status), _) | (.., Err(status)) => {
+            return status;
+        }
+    };
+    let statuses = match client.client.download(
+        filename.to_string(),
+        username.to_string(),
+        size,
+        directory.to_string(),
+    ) {
+        Ok((_, statuses)) => statuses,
+        Err(e) => return fail(SlskStatus::ErrDownload, e.to_string()),
+    };
+    let id = client.next_id.fetch_add(1, Ordering::Relaxed);
+    let listener = client.listener.clone();
+    let (username, filename) = (username.to_string(), filename.to_string());
+    thread::spawn(move || {
+        let base = Event {
+            kind: SlskEventKind::DownloadProgress,
+            id,
+            username: &username,
+            filename: &filename,
+            size,
+            bytes: 0,
+            message: None,
+        };
+        let outcome = statuses.into_iter().find_map(|status| match status {
+            DownloadStatus::InProgress {
+                bytes_downloaded,
+                total_bytes,
+                ..
+            } => {
+                emit(
+                    &listener,
+                    &Event {
+                        size: total_bytes,
+                        bytes: bytes_downloaded,
+                        ..base
+                    },
+                );
+                None
+            }
+            DownloadStatus::Completed(summary) => Some((
+                SlskEventKind::DownloadCompleted,
+                summary
+                    .map(|s| s.path.display().to_string())
+                    .unwrap_or_default(),
+            )),
+            DownloadStatus::Failed(reason) => Some((
+                SlskEventKind::DownloadFailed,
+                reason.map_or_else(
+                    || "failed".to_string(),
+                    |reason| reason.to_string(),
+                ),
+            )),
+            DownloadStatus::TimedOut => {
+                Some((SlskEventKind::DownloadFailed, "timed out".to_string()))
+            }
+            DownloadStatus::Queued | DownloadStatus::Paused { .. } => None,
+        });
+        // The channel closes without an outcome when the client shuts down.
+        let (kind, message) = outcome.unwrap_or_else(|| {
+            (
+                SlskEventKind::DownloadFailed,
+                "client shut down".to_string(),
+            )
+        });
+        let bytes = if kind == SlskEventKind::DownloadCompleted {
+            size
+        } else {
+            0
+        };
+        emit(
+            &listener,
+            &Event {
+                kind,
+                bytes,
+                message: Some(&message),
+                ..base
+            },
+        );
+    });
+    // SAFETY: NULL or writable, per the contract.
+    if let Some(out) = unsafe { download_id.as_mut() } {
+        *out = id;
+    }
+    SlskStatus::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        // SAFETY: set by the failing call just before, on this thread.
+        unsafe { CStr::from_ptr(slsk_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn invalid_arguments_fail_with_a_message() {
+        // SAFETY: NULL and valid literals only.
+        unsafe {
+            assert!(
+                slsk_client_new(ptr::null(), c"pw".as_ptr(), ptr::null())
+                    .is_null()
+            );
+            assert_eq!(last_error(), "username is NULL");
+            assert!(
+                slsk_client_new(
+                    c"me".as_ptr(),
+                    c"pw".as_ptr(),
+                    c"no-port".as_ptr()
+                )
+                .is_null()
+            );
+            assert_eq!(last_error(), "server must be host:port");
+            assert_eq!(slsk_client_login(ptr::null_mut()), SlskStatus::ErrNull);
+        }
+    }
+
+    #[test]
+    fn unconnected_client_rejects_searches_until_freed() {
+        // SAFETY: a client from `slsk_client_new`, freed once at the end.
+        unsafe {
+            let client = slsk_client_new(
+                c"me".as_ptr(),
+                c"pw".as_ptr(),
+                c"127.0.0.1:1".as_ptr(),
+            );
+            assert!(!client.is_null());
+            let mut id = 0;
+            assert_eq!(
+                slsk_search(client, c"query".as_ptr(), 1, &raw mut id),
+                SlskStatus::ErrSearch
+            );
+            assert_eq!(id, 0);
+            slsk_client_free(client);
+        }
+    }
+
+    #[test]
+    fn header_declares_every_export() {
+        let header = include_str!("../include/soulseek.h");
+        for function in [
+            "slsk_version(",
+            "slsk_last_error(",
+            "slsk_client_new(",
+            "slsk_client_free(",
+            "slsk_client_set_callback(",
+            "slsk_client_connect(",
+            "slsk_client_login(",
+            "slsk_search(",
+            "slsk_download(",
+        ] {
+            assert!(header.contains(function), "{function} missing");
+        }
+    }
+}
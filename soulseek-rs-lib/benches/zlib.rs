@@ -0,0 +1,68 @@
+//! Inflate/deflate throughput for the hand-rolled zlib codec.
+//!
+//! Run with `cargo bench -p soulseek-rs-lib --bench zlib`. Each case reports
+//! the median time per iteration and the throughput on uncompressed bytes.
+//!
+//! `fixtures/search_response.zlib` is a 2000-file search response as a peer
+//! sends it: compressed by the reference zlib at its default level, so it
+//! exercises dynamic Huffman blocks.
+
+use soulseek_rs::utils::zlib::{compress, compress_stored, deflate};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SEARCH_RESPONSE: &[u8] = include_bytes!("fixtures/search_response.zlib");
+
+/// Samples taken per case; each sample runs for at least `SAMPLE_TIME`.
+const SAMPLES: usize = 15;
+const SAMPLE_TIME: Duration = Duration::from_millis(100);
+
+fn bench(name: &str, bytes: usize, mut f: impl FnMut()) {
+    // Warm up, and size a sample so timer overhead doesn't matter.
+    let mut iterations = 1u32;
+    loop {
+        let start = Instant::now();
+        for _ in 0..iterations {
+            f();
+        }
+        if start.elapsed() >= SAMPLE_TIME {
+            break;
+        }
+        iterations *= 2;
+    }
+
+    let mut per_iteration: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                f();
+            }
+            start.elapsed() / iterations
+        })
+        .collect();
+    per_iteration.sort();
+    let median = per_iteration[SAMPLES / 2];
+    let mib_per_sec = bytes as f64 / median.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{name:<32} {median:>12.2?}/iter {mib_per_sec:>10.1} MiB/s");
+}
+
+fn main() {
+    let response = deflate(SEARCH_RESPONSE).expect("fixture inflates");
+    bench("inflate search response", response.len(), || {
+        black_box(deflate(black_box(SEARCH_RESPONSE)).ok());
+    });
+
+    let fixed = compress(&response);
+    bench("inflate fixed huffman", response.len(), || {
+        black_box(deflate(black_box(&fixed)).ok());
+    });
+
+    let stored = compress_stored(&response);
+    bench("inflate stored", response.len(), || {
+        black_box(deflate(black_box(&stored)).ok());
+    });
+
+    bench("compress search response", response.len(), || {
+        black_box(compress(black_box(&response)));
+    });
+}
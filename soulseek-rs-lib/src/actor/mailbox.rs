@@ -0,0 +1,337 @@
+//! Actor mailboxes, optionally bounded.
+//!
+//! An unbounded mailbox grows as fast as its senders produce, so a peer
+//! flooding an actor can grow memory without limit. A bounded one holds at
+//! most `capacity` user messages and applies its [`OverflowPolicy`] when
+//! full. Control messages (stop, socket readiness) never count towards the
+//! capacity and are never dropped, so stopping or waking an actor cannot
+//! block.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use super::ActorMessage;
+use crate::error::SoulseekRs;
+use crate::utils::lock::MutexExt;
+
+/// How an actor's mailbox holds pending messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mailbox {
+    /// No limit; senders never wait and nothing is dropped.
+    #[default]
+    Unbounded,
+    /// At most `capacity` pending user messages.
+    Bounded {
+        capacity: usize,
+        policy: OverflowPolicy,
+    },
+}
+
+impl Mailbox {
+    /// A bounded mailbox whose senders wait for room.
+    #[must_use]
+    pub const fn blocking(capacity: usize) -> Self {
+        Self::Bounded {
+            capacity,
+            policy: OverflowPolicy::Block,
+        }
+    }
+
+    /// A bounded mailbox that makes room by dropping its oldest message.
+    #[must_use]
+    pub const fn drop_oldest(capacity: usize) -> Self {
+        Self::Bounded {
+            capacity,
+            policy: OverflowPolicy::DropOldest,
+        }
+    }
+}
+
+/// What a full bounded mailbox does with a new message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// The sender waits until the actor has taken a message. An actor
+    /// sending to itself is never blocked, as it could not make room.
+    Block,
+    /// The oldest pending message is discarded to make room.
+    DropOldest,
+}
+
+struct State<M> {
+    queue: VecDeque<ActorMessage<M>>,
+    /// User messages in `queue`; only these count towards the capacity.
+    pending: usize,
+    senders: usize,
+    receiver_alive: bool,
+    /// The thread running the actor, which must never block on itself.
+    owner: Option<ThreadId>,
+    dropped: u64,
+}
+
+struct Shared<M> {
+    mailbox: Mailbox,
+    state: Mutex<State<M>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+/// A read-only view of a mailbox's occupancy, whatever its message type.
+/// Unlike a sender, it doesn't keep the mailbox open.
+pub trait MailboxProbe: Send + Sync {
+    fn depth(&self) -> usize;
+    fn dropped(&self) -> u64;
+}
+
+impl<M: Send> MailboxProbe for Shared<M> {
+    fn depth(&self) -> usize {
+        self.state.lock_safe().map_or(0, |state| state.pending)
+    }
+
+    fn dropped(&self) -> u64 {
+        self.state.lock_safe().map_or(0, |state| state.dropped)
+    }
+}
+
+pub struct MailboxSender<M> {
+    shared: Arc<Shared<M>>,
+}
+
+pub struct MailboxReceiver<M> {
+    shared: Arc<Shared<M>>,
+}
+
+/// A mailbox's sending and receiving ends.
+pub fn mailbox<M>(mailbox: Mailbox) -> (MailboxSender<M>, MailboxReceiver<M>) {
+    let shared = Arc::new(Shared {
+        mailbox,
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            pending: 0,
+            senders: 1,
+            receiver_alive: true,
+            owner: None,
+            dropped: 0,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        MailboxSender {
+            shared: shared.clone(),
+        },
+        MailboxReceiver { shared },
+    )
+}
+
+impl<M> MailboxSender<M> {
+    /// Queue `msg`, waiting for room or dropping the oldest user message if
+    /// the mailbox is full. Fails with [`SoulseekRs::ConnectionClosed`]
+    /// once the actor has stopped.
+    pub fn send(&self, msg: ActorMessage<M>) -> crate::Result<()> {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock_safe()?;
+        if let ActorMessage::UserMessage(_) = msg
+            && let Mailbox::Bounded { capacity, policy } = shared.mailbox
+        {
+            // A zero capacity would block every sender forever.
+            let capacity = capacity.max(1);
+            let own_thread = state.owner == Some(thread::current().id());
+            while state.receiver_alive && state.pending >= capacity {
+                match policy {
+                    OverflowPolicy::Block if own_thread => break,
+                    OverflowPolicy::Block => {
+                        state = shared
+                            .not_full
+                            .wait(state)
+                            .map_err(|_| SoulseekRs::LockPoisoned)?;
+                    }
+                    OverflowPolicy::DropOldest => {
+                        if let Some(oldest) = state.queue.iter().position(|m| {
+                            matches!(m, ActorMessage::UserMessage(_))
+                        }) {
+                            state.queue.remove(oldest);
+                            state.pending -= 1;
+                            state.dropped += 1;
+                        }
+                    }
+                }
+            }
+        }
+        if !state.receiver_alive {
+            return Err(SoulseekRs::ConnectionClosed);
+        }
+        if let ActorMessage::UserMessage(_) = msg {
+            state.pending += 1;
+        }
+        state.queue.push_back(msg);
+        drop(state);
+        shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// User messages dropped so far to make room.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.shared
+            .state
+            .lock_safe()
+            .map_or(0, |state| state.dropped)
+    }
+}
+
+impl<M> Clone for MailboxSender<M> {
+    fn clone(&self) -> Self {
+        if let Ok(mut state) = self.shared.state.lock_safe() {
+            state.senders += 1;
+        }
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<M> Drop for MailboxSender<M> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock_safe() {
+            state.senders -= 1;
+        }
+        self.shared.not_empty.notify_all();
+    }
+}
+
+impl<M: Send + 'static> MailboxReceiver<M> {
+    /// A probe of this mailbox for the actor's stats.
+    pub fn probe(&self) -> Arc<dyn MailboxProbe> {
+        self.shared.clone()
+    }
+}
+
+impl<M> MailboxReceiver<M> {
+    /// Mark the calling thread as the one running the actor.
+    pub fn bind_to_current_thread(&self) {
+        if let Ok(mut state) = self.shared.state.lock_safe() {
+            state.owner = Some(thread::current().id());
+        }
+    }
+
+    /// Like [`std::sync::mpsc::Receiver::recv_timeout`].
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<ActorMessage<M>, RecvTimeoutError> {
+        let shared = &*self.shared;
+        let deadline = Instant::now() + timeout;
+        let mut state = shared
+            .state
+            .lock_safe()
+            .map_err(|_| RecvTimeoutError::Disconnected)?;
+        loop {
+            if let Some(msg) = state.queue.pop_front() {
+                if let ActorMessage::UserMessage(_) = msg {
+                    state.pending -= 1;
+                    drop(state);
+                    shared.not_full.notify_one();
+                }
+                return Ok(msg);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = shared
+                .not_empty
+                .wait_timeout(state, remaining)
+                .map_err(|_| RecvTimeoutError::Disconnected)?
+                .0;
+        }
+    }
+}
+
+impl<M> Drop for MailboxReceiver<M> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.shared.state.lock_safe() {
+            state.receiver_alive = false;
+            state.queue.clear();
+            state.pending = 0;
+        }
+        self.shared.not_full.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(msg: &ActorMessage<u32>) -> Option<u32> {
+        match msg {
+            ActorMessage::UserMessage(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    const NOW: Duration = Duration::ZERO;
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_messages_and_every_control_message() {
+        let (tx, rx) = mailbox(Mailbox::drop_oldest(2));
+        for n in 1..=4 {
+            tx.send(ActorMessage::UserMessage(n)).unwrap();
+        }
+        tx.send(ActorMessage::Stop).unwrap();
+        assert_eq!(tx.dropped(), 2);
+
+        assert_eq!(user(&rx.recv_timeout(NOW).unwrap()), Some(3));
+        assert_eq!(user(&rx.recv_timeout(NOW).unwrap()), Some(4));
+        assert!(matches!(rx.recv_timeout(NOW), Ok(ActorMessage::Stop)));
+        assert!(matches!(
+            rx.recv_timeout(NOW),
+            Err(RecvTimeoutError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn blocking_sender_waits_for_room() {
+        let (tx, rx) = mailbox(Mailbox::blocking(1));
+        tx.send(ActorMessage::UserMessage(1)).unwrap();
+        // Control messages bypass the limit.
+        tx.send(ActorMessage::Ready).unwrap();
+
+        let sender = thread::spawn(move || {
+            tx.send(ActorMessage::UserMessage(2)).unwrap();
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!sender.is_finished());
+
+        assert_eq!(user(&rx.recv_timeout(NOW).unwrap()), Some(1));
+        sender.join().unwrap();
+        assert!(matches!(rx.recv_timeout(NOW), Ok(ActorMessage::Ready)));
+        assert_eq!(user(&rx.recv_timeout(NOW).unwrap()), Some(2));
+        assert!(matches!(
+            rx.recv_timeout(NOW),
+            Err(RecvTimeoutError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn owner_never_blocks_on_its_own_mailbox() {
+        let (tx, rx) = mailbox(Mailbox::blocking(1));
+        rx.bind_to_current_thread();
+        tx.send(ActorMessage::UserMessage(1)).unwrap();
+        tx.send(ActorMessage::UserMessage(2)).unwrap();
+        assert_eq!(user(&rx.recv_timeout(NOW).unwrap()), Some(1));
+    }
+
+    #[test]
+    fn sends_fail_once_the_receiver_is_gone() {
+        let (tx, rx) = mailbox::<u32>(Mailbox::blocking(1));
+        tx.send(ActorMessage::UserMessage(1)).unwrap();
+        drop(rx);
+        assert!(tx.send(ActorMessage::UserMessage(2)).is_err());
+    }
+}
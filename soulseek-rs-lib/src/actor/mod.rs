@@ -1,16 +1,32 @@
-use std::sync::Arc;
-use std::sync::mpsc::{Receiver, Sender, channel};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+use crate::error::SoulseekRs;
 use crate::trace;
+use crate::utils::lock::MutexExt;
 use crate::utils::thread_pool::ThreadPool;
+use mailbox::{MailboxReceiver, MailboxSender};
+use stats::Probe;
 
+pub use mailbox::{Mailbox, OverflowPolicy};
+pub use stats::ActorStats;
+
+mod mailbox;
 pub mod peer_actor;
 pub mod peer_registry;
+mod reactor;
+pub mod send_limiter;
 pub mod server_actor;
+mod stats;
 
+/// Where an actor's socket is; see [`crate::types::ConnectionState`] for the
+/// server connection as callers see it.
 #[derive(Debug, Clone)]
-pub enum ConnectionState {
+pub enum SocketState {
     Disconnected,
     Connecting { since: Instant },
     Connected,
@@ -30,57 +46,164 @@ pub trait Actor: Send + 'static {
 
     /// Optional periodic tick for background work
     fn tick(&mut self) {}
+
+    /// How long to wait for a message before the next tick.
+    fn tick_interval(&self) -> Duration {
+        DEFAULT_TICK_INTERVAL
+    }
+
+    /// Called when a socket registered through [`ActorHandle::waker`]
+    /// becomes readable (optional hook)
+    fn on_ready(&mut self) {}
+
+    /// How the actor appears in [`ActorSystem::stats`].
+    fn name(&self) -> String {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").next().unwrap_or(path).to_string()
+    }
+
+    /// The mailbox this actor is spawned with.
+    fn mailbox(&self) -> Mailbox {
+        Mailbox::Unbounded
+    }
+}
+
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long [`ActorHandle::ask`] waits for the reply.
+pub const DEFAULT_ASK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where an actor sends the answer to a request made with
+/// [`ActorHandle::ask`]. The request message carries it.
+pub struct ReplyTo<R>(mpsc::Sender<R>);
+
+impl<R> ReplyTo<R> {
+    /// Answer the request. Returns `false` if the asker gave up waiting.
+    pub fn reply(self, value: R) -> bool {
+        self.0.send(value).is_ok()
+    }
+}
+
+impl<R> Clone for ReplyTo<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R> fmt::Debug for ReplyTo<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReplyTo")
+    }
 }
 
-#[derive(Clone)]
 pub struct ActorHandle<M: Send> {
-    pub(crate) sender: Sender<ActorMessage<M>>,
+    pub(crate) sender: MailboxSender<M>,
+}
+
+impl<M: Send> Clone for ActorHandle<M> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
 }
 
 impl<M: Send> ActorHandle<M> {
-    pub fn send(&self, msg: M) -> Result<(), String> {
-        self.sender
-            .send(ActorMessage::UserMessage(msg))
-            .map_err(|e| format!("Failed to send message: {e}"))
+    /// Queue a message. With a bounded [`Mailbox`] this waits for room or
+    /// drops the oldest pending message, per its [`OverflowPolicy`].
+    ///
+    /// # Errors
+    /// [`SoulseekRs::ConnectionClosed`] if the actor has stopped.
+    pub fn send(&self, msg: M) -> crate::error::Result<()> {
+        self.sender.send(ActorMessage::UserMessage(msg))
+    }
+
+    /// Send the message built by `request` and wait up to
+    /// [`DEFAULT_ASK_TIMEOUT`] for the actor to answer through its
+    /// [`ReplyTo`].
+    ///
+    /// # Errors
+    /// [`SoulseekRs::NotConnected`] if the actor has stopped,
+    /// [`SoulseekRs::Timeout`] if it doesn't answer in time and
+    /// [`SoulseekRs::ConnectionClosed`] if it drops the request unanswered.
+    pub fn ask<R>(
+        &self,
+        request: impl FnOnce(ReplyTo<R>) -> M,
+    ) -> crate::error::Result<R> {
+        self.ask_timeout(request, DEFAULT_ASK_TIMEOUT)
+    }
+
+    /// [`ActorHandle::ask`], waiting up to `timeout`.
+    ///
+    /// # Errors
+    /// As [`ActorHandle::ask`].
+    pub fn ask_timeout<R>(
+        &self,
+        request: impl FnOnce(ReplyTo<R>) -> M,
+        timeout: Duration,
+    ) -> crate::error::Result<R> {
+        let (sender, receiver) = mpsc::channel();
+        self.send(request(ReplyTo(sender)))
+            .map_err(|_| SoulseekRs::NotConnected)?;
+        receiver.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => SoulseekRs::Timeout,
+            RecvTimeoutError::Disconnected => SoulseekRs::ConnectionClosed,
+        })
+    }
+
+    /// Messages dropped so far because the mailbox was full.
+    #[must_use]
+    pub fn dropped_messages(&self) -> u64 {
+        self.sender.dropped()
+    }
+
+    /// A reactor waker that calls the actor's `on_ready`.
+    pub(crate) fn waker(&self) -> reactor::Waker
+    where
+        M: 'static,
+    {
+        let sender = self.sender.clone();
+        std::sync::Arc::new(move || sender.send(ActorMessage::Ready).is_ok())
     }
 
     /// Request actor to stop gracefully
-    pub fn stop(&self) -> Result<(), String> {
-        self.sender
-            .send(ActorMessage::Stop)
-            .map_err(|e| format!("Failed to send stop signal: {e}"))
+    ///
+    /// # Errors
+    /// [`SoulseekRs::ConnectionClosed`] if the actor has already stopped.
+    pub fn stop(&self) -> crate::error::Result<()> {
+        self.sender.send(ActorMessage::Stop)
     }
 }
 
 /// Internal actor message wrapper
 pub(crate) enum ActorMessage<M> {
     UserMessage(M),
+    /// A registered socket became readable.
+    Ready,
     Stop,
 }
 
 /// Actor system that manages actor lifecycle
 pub struct ActorSystem {
     thread_pool: Arc<ThreadPool>,
+    /// Counters of every running actor, by id.
+    actors: Arc<Mutex<BTreeMap<u64, Arc<Probe>>>>,
+    next_id: AtomicU64,
 }
 
 impl ActorSystem {
     #[must_use]
-    pub const fn new(thread_pool: Arc<ThreadPool>) -> Self {
-        Self { thread_pool }
+    pub fn new(thread_pool: Arc<ThreadPool>) -> Self {
+        Self {
+            thread_pool,
+            actors: Arc::default(),
+            next_id: AtomicU64::new(1),
+        }
     }
 
     /// Spawn a new actor and return its handle
-    pub fn spawn<A: Actor>(&self, mut actor: A) -> ActorHandle<A::Message> {
-        let (sender, receiver) = channel::<ActorMessage<A::Message>>();
-        let handle = ActorHandle { sender };
-
-        self.thread_pool.execute(move || {
-            actor.on_start();
-            Self::run_actor_loop(&mut actor, receiver);
-            actor.on_stop();
-        });
-
-        handle
+    pub fn spawn<A: Actor>(&self, actor: A) -> ActorHandle<A::Message> {
+        self.spawn_with_handle(actor, |_, _| {})
     }
 
     /// Spawn a new actor with initialization callback and return its handle
@@ -93,49 +216,74 @@ impl ActorSystem {
     where
         F: FnOnce(&mut A, ActorHandle<A::Message>) + Send + 'static,
     {
-        let (sender, receiver) = channel::<ActorMessage<A::Message>>();
+        let (sender, receiver) = mailbox::mailbox(actor.mailbox());
         let handle = ActorHandle { sender };
         let handle_for_init = handle.clone();
 
-        self.thread_pool.execute(move || {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let name = actor.name();
+        let origin = format!("actor {name} #{id}");
+        let probe = Arc::new(Probe::new(id, name, receiver.probe()));
+        if let Ok(mut actors) = self.actors.lock_safe() {
+            actors.insert(id, probe.clone());
+        }
+        let actors = self.actors.clone();
+
+        self.thread_pool.execute_as(origin, move || {
             init(&mut actor, handle_for_init);
             actor.on_start();
-            Self::run_actor_loop(&mut actor, receiver);
+            Self::run_actor_loop(&mut actor, receiver, &probe);
             actor.on_stop();
+            if let Ok(mut actors) = actors.lock_safe() {
+                actors.remove(&id);
+            }
         });
 
         handle
     }
 
+    /// A snapshot of every running actor, oldest first.
+    #[must_use]
+    pub fn stats(&self) -> Vec<ActorStats> {
+        self.actors.lock_safe().map_or_else(
+            |_| Vec::new(),
+            |actors| actors.values().map(|probe| probe.snapshot()).collect(),
+        )
+    }
+
     fn run_actor_loop<A: Actor>(
         actor: &mut A,
-        receiver: Receiver<ActorMessage<A::Message>>,
+        receiver: MailboxReceiver<A::Message>,
+        probe: &Probe,
     ) {
-        let tick_interval = Duration::from_millis(100);
+        receiver.bind_to_current_thread();
         let mut last_tick = Instant::now();
-        let mut message_count = 0;
-        let mut tick_count = 0;
 
         loop {
+            let tick_interval = actor.tick_interval();
             match receiver.recv_timeout(tick_interval) {
                 Ok(ActorMessage::UserMessage(msg)) => {
-                    message_count += 1;
+                    probe.message();
                     actor.handle(msg);
                 }
+                Ok(ActorMessage::Ready) => {
+                    probe.touch();
+                    actor.on_ready();
+                }
                 Ok(ActorMessage::Stop) => {
                     trace!(
                         "[actor_system] Received Stop message, breaking loop"
                     );
                     break;
                 }
-                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                Err(RecvTimeoutError::Timeout) => {
                     if last_tick.elapsed() >= tick_interval {
-                        tick_count += 1;
+                        probe.tick();
                         actor.tick();
                         last_tick = Instant::now();
                     }
                 }
-                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                Err(RecvTimeoutError::Disconnected) => {
                     trace!(
                         "[actor_system] Channel disconnected, breaking loop"
                     );
@@ -145,7 +293,8 @@ impl ActorSystem {
         }
         trace!(
             "[actor_system] run_actor_loop ENDED - processed {} messages, {} ticks",
-            message_count, tick_count
+            probe.messages_processed(),
+            probe.ticks()
         );
     }
 }
@@ -205,4 +354,68 @@ mod tests {
         // Drop the thread pool explicitly to wait for all threads
         drop(thread_pool);
     }
+
+    #[derive(Clone)]
+    enum Request {
+        Double(u32, ReplyTo<u32>),
+        Ignore(ReplyTo<u32>),
+    }
+
+    struct Doubler;
+
+    impl Actor for Doubler {
+        type Message = Request;
+
+        fn handle(&mut self, msg: Self::Message) {
+            match msg {
+                Request::Double(n, reply) => {
+                    reply.reply(n * 2);
+                }
+                Request::Ignore(reply) => drop(reply),
+            }
+        }
+    }
+
+    #[test]
+    fn ask_waits_for_the_reply() {
+        let system = ActorSystem::new(Arc::new(ThreadPool::new(1)));
+        let handle = system.spawn(Doubler);
+
+        assert_eq!(handle.ask(|reply| Request::Double(21, reply)).unwrap(), 42);
+        assert!(matches!(
+            handle.ask(Request::Ignore),
+            Err(SoulseekRs::ConnectionClosed)
+        ));
+
+        handle.stop().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(matches!(
+            handle.ask_timeout(
+                |reply| Request::Double(1, reply),
+                Duration::from_millis(10)
+            ),
+            Err(SoulseekRs::NotConnected)
+        ));
+    }
+
+    #[test]
+    fn stats_track_running_actors() {
+        let system = ActorSystem::new(Arc::new(ThreadPool::new(1)));
+        let count = Arc::new(AtomicUsize::new(0));
+        let handle = system.spawn(CounterActor { count });
+        handle.send(1).unwrap();
+        handle.send(2).unwrap();
+        std::thread::sleep(Duration::from_millis(250));
+
+        let stats = system.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].name, "CounterActor");
+        assert_eq!(stats[0].messages_processed, 2);
+        assert_eq!(stats[0].mailbox_depth, 0);
+        assert!(stats[0].ticks >= 1);
+
+        handle.stop().unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(system.stats().is_empty());
+    }
 }
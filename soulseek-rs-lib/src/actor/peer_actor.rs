@@ -1,20 +1,28 @@
-use crate::actor::{Actor, ActorHandle, ConnectionState};
+use crate::actor::{
+    Actor, ActorHandle, DEFAULT_TICK_INTERVAL, Mailbox, SocketState, reactor,
+};
 use crate::client::ClientOperation;
-use crate::dispatcher::MessageDispatcher;
+use crate::dispatcher::{MessageDispatcher, Verdict};
 use crate::message::peer::{
-    FileSearchResponse, GetShareFileList, PeerInit, PlaceInQueueResponse,
-    QueueUploadHandler, SharedDirectory, SharedFileListResponseHandler,
-    TransferRequest, TransferResponse, UploadFailedHandler,
+    FileSearchResponse, GetShareFileList, PeerInit, PlaceInQueueRequestHandler,
+    PlaceInQueueResponse, QueueUploadHandler, SharedDirectory,
+    SharedFileListResponseHandler, TransferRequest, TransferResponse,
+    UploadDeniedHandler, UploadFailedHandler, UserInfoResponseHandler,
 };
 use crate::message::server::MessageFactory;
-use crate::message::{Handlers, Message, MessageReader, MessageType};
+use crate::message::{
+    CustomHandlers, Handlers, Message, MessageReader, MessageType,
+};
+use crate::metrics::Metrics;
 use crate::peer::Peer;
-use crate::types::{Download, SearchResult, Transfer};
+use crate::transport::{PeerStream, PeerTlsSettings};
+use crate::types::{Download, FailureReason, SearchResult, Transfer, UserInfo};
 use crate::utils::lock::RwLockExt;
+use crate::utils::logger::{self, LogLevel};
 use crate::{debug, error, trace, warn};
 
+use std::collections::HashSet;
 use std::io::{self, Error, Write};
-use std::net::TcpStream;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
@@ -24,7 +32,11 @@ pub enum PeerMessage {
     SendMessage(Message),
     FileSearchResult(SearchResult),
     TransferRequest(Transfer),
-    UploadFailed(String, String),
+    /// The peer refused or failed a file we queued (codes 50 and 46).
+    UploadFailed {
+        filename: String,
+        reason: FailureReason,
+    },
     TransferResponse {
         token: u32,
         allowed: bool,
@@ -39,10 +51,14 @@ pub enum PeerMessage {
     RequestTransfer(Download),
     /// A peer queued one of our shared files for download (they sent us code 43).
     IncomingQueueUpload(String),
+    /// A peer asked for its place in our upload queue (they sent us code 51).
+    PlaceInQueueRequested(String),
     /// A peer asked to browse our shared files (they sent us code 4).
     ShareListRequested,
     /// A peer we are browsing sent us their shared-file listing (code 5).
     ShareListReceived(Vec<SharedDirectory>),
+    /// A peer answered our `UserInfoRequest` (code 16).
+    UserInfoReceived(UserInfo),
     /// Offer the queued file to that peer: send an upload TransferRequest.
     ServeUpload {
         token: u32,
@@ -52,10 +68,78 @@ pub enum PeerMessage {
     ProcessRead,
 }
 
+/// Bytes of each message hexdumped for a traced peer.
+const TRACE_DUMP_LIMIT: usize = 512;
+/// Characters of a decoded message kept in a traced peer's log line.
+const TRACE_SUMMARY_LIMIT: usize = 400;
+
+/// Usernames whose peer connections are logged in full, whatever the
+/// global log level.
+///
+/// Shared by the client and every peer actor, so toggling a username also
+/// applies to connections already open.
+#[derive(Debug, Default)]
+pub struct PeerTrace {
+    peers: RwLock<HashSet<String>>,
+}
+
+impl PeerTrace {
+    pub fn set(&self, username: &str, enabled: bool) {
+        if let Ok(mut peers) = self.peers.write_safe() {
+            if enabled {
+                peers.insert(username.to_string());
+            } else {
+                peers.remove(username);
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_traced(&self, username: &str) -> bool {
+        self.peers
+            .read_safe()
+            .is_ok_and(|peers| peers.contains(username))
+    }
+}
+
+/// Code, size and hexdump appended to a traced peer's message line.
+fn trace_detail(message: &Message, code: u32) -> String {
+    format!(
+        " (code {code}, {} bytes)\n{}",
+        message.get_data().len(),
+        message.hex_dump(TRACE_DUMP_LIMIT)
+    )
+}
+
+/// The built-in handlers for messages on a peer connection.
+fn peer_handlers() -> Handlers<PeerMessage> {
+    let mut handlers = Handlers::new();
+    handlers.register_handler(FileSearchResponse);
+    handlers.register_handler(TransferRequest);
+    handlers.register_handler(TransferResponse);
+    handlers.register_handler(GetShareFileList);
+    handlers.register_handler(UploadFailedHandler);
+    handlers.register_handler(UploadDeniedHandler);
+    handlers.register_handler(PlaceInQueueResponse);
+    handlers.register_handler(QueueUploadHandler);
+    handlers.register_handler(PlaceInQueueRequestHandler);
+    handlers.register_handler(UserInfoResponseHandler);
+    handlers.register_handler(SharedFileListResponseHandler);
+    handlers.register_init_handler(PeerInit);
+    handlers
+}
+
+/// The tick interval once the reactor watches our socket.
+const IDLE_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct PeerActor {
     peer: Arc<RwLock<Peer>>,
-    stream: Option<TcpStream>,
-    connection_state: ConnectionState,
+    /// Set while the reactor wakes us on incoming data; without it the
+    /// socket is read on every tick. Declared before `stream` so it is
+    /// dropped first.
+    readiness: Option<reactor::Registration>,
+    stream: Option<PeerStream>,
+    connection_state: SocketState,
     reader: MessageReader,
     client_channel: Sender<ClientOperation>,
     self_handle: Option<ActorHandle<PeerMessage>>,
@@ -80,13 +164,19 @@ pub struct PeerActor {
     /// Transfer tokens for uploads we are serving to this peer. A TransferResponse
     /// for one of these is our upload being accepted, not a download offer.
     serving_tokens: std::collections::HashSet<u32>,
+    peer_trace: Arc<PeerTrace>,
+    metrics: Arc<Metrics>,
+    custom_handlers: Arc<CustomHandlers<PeerMessage>>,
+    mailbox: Mailbox,
+    /// Wraps the connection in TLS when we dial it.
+    tls: Option<Arc<PeerTlsSettings>>,
 }
 
 impl PeerActor {
     #[must_use]
     pub fn new(
         peer: Peer,
-        stream: Option<TcpStream>,
+        stream: Option<PeerStream>,
         reader: Option<MessageReader>,
         client_channel: Sender<ClientOperation>,
         own_username: String,
@@ -94,14 +184,15 @@ impl PeerActor {
     ) -> Self {
         let outbound = stream.is_none();
         let connection_state = if stream.is_some() {
-            ConnectionState::Connected
+            SocketState::Connected
         } else {
-            ConnectionState::Disconnected
+            SocketState::Disconnected
         };
 
         Self {
             peer: Arc::new(RwLock::new(peer)),
             stream,
+            readiness: None,
             connection_state,
             reader: reader.unwrap_or_default(),
             client_channel,
@@ -115,9 +206,51 @@ impl PeerActor {
             disconnect_reported: false,
             id,
             serving_tokens: std::collections::HashSet::new(),
+            peer_trace: Arc::default(),
+            metrics: Arc::default(),
+            custom_handlers: Arc::default(),
+            mailbox: Mailbox::Unbounded,
+            tls: None,
         }
     }
 
+    #[must_use]
+    pub fn with_peer_trace(mut self, peer_trace: Arc<PeerTrace>) -> Self {
+        self.peer_trace = peer_trace;
+        self
+    }
+
+    /// Run the embedder's `handlers` too.
+    #[must_use]
+    pub fn with_custom_handlers(
+        mut self,
+        handlers: Arc<CustomHandlers<PeerMessage>>,
+    ) -> Self {
+        self.custom_handlers = handlers;
+        self
+    }
+
+    /// Count messages in the client's shared metrics.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Spawn with `mailbox` instead of an unbounded one.
+    #[must_use]
+    pub const fn with_mailbox(mut self, mailbox: Mailbox) -> Self {
+        self.mailbox = mailbox;
+        self
+    }
+
+    /// Dial the peer over TLS with `tls`.
+    #[must_use]
+    pub fn with_tls(mut self, tls: Option<Arc<PeerTlsSettings>>) -> Self {
+        self.tls = tls;
+        self
+    }
+
     pub fn set_self_handle(&mut self, handle: ActorHandle<PeerMessage>) {
         self.self_handle = Some(handle);
     }
@@ -132,6 +265,24 @@ impl PeerActor {
         }
     }
 
+    /// Enter this connection's log span, for the length of one callback.
+    fn enter_span(&self) -> logger::SpanGuard {
+        logger::peer_span(|| self.peer_username())
+    }
+
+    fn is_traced(&self, username: &str) -> bool {
+        self.peer_trace.is_traced(username)
+    }
+
+    /// Log `line` at TRACE, forcing it out when this peer is being traced.
+    fn trace_peer(&self, username: &str, line: &str) {
+        if self.is_traced(username) {
+            logger::log_forced(LogLevel::Trace, line);
+        } else {
+            trace!("{}", line);
+        }
+    }
+
     fn peer_snapshot(&self) -> Option<Peer> {
         match self.peer.read_safe() {
             Ok(p) => Some(p.clone()),
@@ -148,22 +299,17 @@ impl PeerActor {
 
         self.dispatcher_receiver = Some(dispatcher_receiver);
 
-        let mut handlers = Handlers::new();
-        handlers.register_handler(FileSearchResponse);
-        handlers.register_handler(TransferRequest);
-        handlers.register_handler(TransferResponse);
-        handlers.register_handler(GetShareFileList);
-        handlers.register_handler(UploadFailedHandler);
-        handlers.register_handler(PlaceInQueueResponse);
-        handlers.register_handler(QueueUploadHandler);
-        handlers.register_handler(SharedFileListResponseHandler);
-        handlers.register_handler(PeerInit);
-
-        self.dispatcher = Some(MessageDispatcher::new(
-            "peer".to_string(),
-            dispatcher_sender,
-            handlers,
-        ));
+        let mut handlers = peer_handlers();
+        self.custom_handlers.install(&mut handlers);
+
+        self.dispatcher = Some(
+            MessageDispatcher::new(
+                "peer".to_string(),
+                dispatcher_sender,
+                handlers,
+            )
+            .with_interceptors(self.custom_handlers.interceptors()),
+        );
     }
 
     fn process_dispatcher_messages(&mut self) {
@@ -178,13 +324,26 @@ impl PeerActor {
                 msgs
             });
 
-        for msg in &messages {
-            self.handle_message(msg.clone());
+        for msg in messages {
+            self.handle_message(msg);
         }
     }
 
     fn handle_message(&mut self, msg: PeerMessage) {
-        if matches!(self.connection_state, ConnectionState::Connecting { .. }) {
+        let username = self.peer_username();
+        if self.is_traced(&username) && !matches!(msg, PeerMessage::ProcessRead)
+        {
+            let summary: String = format!("{msg:?}")
+                .chars()
+                .take(TRACE_SUMMARY_LIMIT)
+                .collect();
+            logger::log_forced(
+                LogLevel::Trace,
+                &format!("[peer:{username}] decoded {summary}"),
+            );
+        }
+
+        if matches!(self.connection_state, SocketState::Connecting { .. }) {
             match &msg {
                 PeerMessage::SetUsername(_) | PeerMessage::ProcessRead => {}
                 _ => {
@@ -225,6 +384,9 @@ impl PeerActor {
             PeerMessage::IncomingQueueUpload(filename) => {
                 self.handle_incoming_queue_upload(filename);
             }
+            PeerMessage::PlaceInQueueRequested(filename) => {
+                self.handle_place_in_queue_requested(filename);
+            }
             PeerMessage::ServeUpload {
                 token,
                 filename,
@@ -238,6 +400,9 @@ impl PeerActor {
             PeerMessage::ShareListReceived(directories) => {
                 self.handle_share_list_received(directories);
             }
+            PeerMessage::UserInfoReceived(info) => {
+                self.handle_user_info_received(info);
+            }
             PeerMessage::RequestTransfer(download) => {
                 let message = MessageFactory::build_transfer_request_message(
                     &download.filename,
@@ -248,8 +413,8 @@ impl PeerActor {
             PeerMessage::ProcessRead => {
                 self.process_read();
             }
-            PeerMessage::UploadFailed(username, filename) => {
-                self.handle_upload_failed(username, filename);
+            PeerMessage::UploadFailed { filename, reason } => {
+                self.handle_upload_failed(filename, reason);
             }
         }
     }
@@ -267,6 +432,7 @@ impl PeerActor {
         }
     }
 
+    /// Hand a peer's offer of a file to the client, which answers it.
     fn handle_transfer_request(&self, transfer: Transfer) {
         let username = self.peer_username();
         debug!("[peer:{}] TransferRequest for {}", username, transfer.token);
@@ -274,7 +440,7 @@ impl PeerActor {
         if let Err(e) =
             self.client_channel
                 .send(ClientOperation::UpdateDownloadTokens(
-                    transfer.clone(),
+                    transfer,
                     username.clone(),
                 ))
         {
@@ -283,19 +449,6 @@ impl PeerActor {
                 username, e
             );
         }
-
-        let transfer_response =
-            MessageFactory::build_transfer_response_message(transfer);
-
-        if let Some(ref handle) = self.self_handle
-            && let Err(e) =
-                handle.send(PeerMessage::SendMessage(transfer_response))
-        {
-            error!(
-                "[peer:{}] Failed to send TransferResponse message: {}",
-                username, e
-            );
-        }
     }
 
     fn handle_transfer_response(
@@ -314,11 +467,14 @@ impl PeerActor {
         // our offer — start streaming. This leaves the download path
         // (every other token) byte-for-byte unchanged.
         if self.serving_tokens.remove(&token) {
-            if allowed {
-                let _ = self
-                    .client_channel
-                    .send(ClientOperation::StartUpload { token });
-            }
+            let _ = if allowed {
+                self.client_channel
+                    .send(ClientOperation::StartUpload { token })
+            } else {
+                // Declined offers must release their upload slot.
+                self.client_channel
+                    .send(ClientOperation::UploadDeclined { token })
+            };
             return;
         }
 
@@ -393,6 +549,19 @@ impl PeerActor {
         }
     }
 
+    fn handle_place_in_queue_requested(&self, filename: String) {
+        let requester_key = self.peer_username();
+        if let Err(e) =
+            self.client_channel
+                .send(ClientOperation::PlaceInQueueRequested {
+                    requester_key,
+                    filename,
+                })
+        {
+            error!("[peer_actor] forward PlaceInQueueRequested: {}", e);
+        }
+    }
+
     fn handle_serve_upload(&mut self, token: u32, filename: String, size: u64) {
         self.serving_tokens.insert(token);
         let message = MessageFactory::build_upload_transfer_request(
@@ -423,10 +592,25 @@ impl PeerActor {
         }
     }
 
-    fn handle_upload_failed(&self, username: String, filename: String) {
+    fn handle_user_info_received(&self, mut info: UserInfo) {
+        info.username = self.peer_username();
+        if let Err(e) = self
+            .client_channel
+            .send(ClientOperation::UserInfoReceived(info))
+        {
+            error!("[peer_actor] forward UserInfoReceived: {}", e);
+        }
+    }
+
+    fn handle_upload_failed(&self, filename: String, reason: FailureReason) {
+        let username = self.peer_username();
+        debug!(
+            "[peer:{}] upload of {} failed: {}",
+            username, filename, reason
+        );
         if let Err(e) = self
             .client_channel
-            .send(ClientOperation::UploadFailed(username, filename))
+            .send(ClientOperation::UploadFailed(username, filename, reason))
         {
             error!("[peer_actor] failed to forward UploadFailed: {}", e);
         }
@@ -437,14 +621,26 @@ impl PeerActor {
             self.extract_and_process_messages();
         }
 
-        {
+        // Read until the socket would block, so one wake-up drains it.
+        loop {
             let Some(stream) = self.stream.as_mut() else {
                 return;
             };
 
+            let buffered = self.reader.buffer_len();
             match self.reader.read_from_socket(stream) {
-                Ok(()) => {}
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Ok(()) if self.reader.buffer_len() > buffered => {}
+                Ok(()) => {
+                    if self.readiness.is_some() {
+                        // Readable with nothing to read: the peer closed.
+                        // What it sent before closing still counts.
+                        self.extract_and_process_messages();
+                        self.close_after_eof();
+                        return;
+                    }
+                    break;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
                 Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
                     if let Ok(peer_lock) = self.peer.read_safe() {
                         debug!(
@@ -452,6 +648,7 @@ impl PeerActor {
                             peer_lock.host, peer_lock.port
                         );
                     }
+                    break;
                 }
                 Err(e) => {
                     let username = self.peer_username();
@@ -476,17 +673,20 @@ impl PeerActor {
             match self.reader.extract_message() {
                 Ok(Some(mut message)) => {
                     extracted_count += 1;
-                    trace!(
-                        "[peer:{}] ← Message #{}: {:?}",
-                        username,
-                        extracted_count,
-                        message
-                            .get_message_name(
-                                MessageType::Peer,
-                                u32::from(message.get_message_code())
-                            )
-                            .map_err(|e| e.to_string())
+                    let code = message.get_message_code_u32();
+                    self.metrics.peer_message_received(code);
+                    let name = message
+                        .get_message_name(MessageType::Peer, code)
+                        .map_err(|e| e.to_string());
+                    let detail = if self.is_traced(&username) {
+                        trace_detail(&message, code)
+                    } else {
+                        String::new()
+                    };
+                    let line = format!(
+                        "[peer:{username}] ← Message #{extracted_count}: {name:?}{detail}"
                     );
+                    self.trace_peer(&username, &line);
                     if let Some(ref dispatcher) = self.dispatcher {
                         dispatcher.dispatch(&mut message);
                     } else {
@@ -510,26 +710,31 @@ impl PeerActor {
         self.process_dispatcher_messages();
     }
 
-    fn send_message(&mut self, message: Message) {
+    fn send_message(&mut self, mut message: Message) {
+        if let Some(dispatcher) = &self.dispatcher
+            && dispatcher.outbound(&mut message) == Verdict::Drop
+        {
+            return;
+        }
         let username = self.peer_username();
+        let code = u32::from_le_bytes(
+            message.slice(0, 4).try_into().unwrap_or_default(),
+        );
+        let name = message
+            .get_message_name(MessageType::Peer, code)
+            .map_err(|e| e.to_string());
+        let detail = if self.is_traced(&username) {
+            trace_detail(&message, code)
+        } else {
+            String::new()
+        };
+        let line = format!("[peer:{username}] ➡ {name:?}{detail}");
+        self.trace_peer(&username, &line);
+
         let Some(stream) = self.stream.as_mut() else {
             error!("Cannot send message: stream is None");
             return;
         };
-
-        trace!(
-            "[peer:{}] ➡ {:?}",
-            username,
-            message
-                .get_message_name(
-                    MessageType::Peer,
-                    u32::from_le_bytes(
-                        message.get_slice(0, 4).try_into().unwrap_or_default()
-                    )
-                )
-                .map_err(|e| e.to_string())
-        );
-
         if let Err(e) = stream.write_all(&message.get_buffer()) {
             error!(
                 "[peer:{}] Error writing message: {}. Disconnecting.",
@@ -538,6 +743,7 @@ impl PeerActor {
             self.disconnect_with_error(e);
             return;
         }
+        self.metrics.peer_message_sent(code);
 
         if let Err(e) = stream.flush() {
             error!(
@@ -552,6 +758,7 @@ impl PeerActor {
         let username = self.peer_username();
         debug!("[peer:{}] disconnect", username);
 
+        self.readiness = None;
         self.stream.take();
 
         if self.disconnect_reported {
@@ -561,15 +768,25 @@ impl PeerActor {
 
         // A direct outbound connection that never established means the peer is
         // unreachable (likely firewalled): signal a connect failure so the
-        // client can fall back to server-brokered connect. Anything else is a
-        // normal disconnect.
+        // client can fall back to server-brokered connect. A dial the server
+        // brokered (it carries the server's token) is already that fallback,
+        // so it is reported back as failed instead. Anything else is a normal
+        // disconnect.
         let op = if self.outbound && !self.established {
-            ClientOperation::PeerConnectFailed(self.id, username)
+            match self.peer.read_safe().ok().and_then(|peer| peer.token) {
+                Some(token) => {
+                    ClientOperation::PierceFailed { token, username }
+                }
+                None => ClientOperation::PeerConnectFailed(self.id, username),
+            }
         } else {
             ClientOperation::PeerDisconnected(
                 self.id,
                 username,
-                Some(error.into()),
+                Some(crate::SoulseekRs::Io {
+                    op: "talk to the peer",
+                    source: error,
+                }),
             )
         };
 
@@ -577,10 +794,26 @@ impl PeerActor {
             error!("Failed to send disconnect notification: {}", e);
         }
     }
+
+    /// The peer closed the connection. Closing is how a peer ends an
+    /// established connection, so it is no error; an outbound one that never
+    /// got going still counts as failing to connect.
+    fn close_after_eof(&mut self) {
+        if self.outbound && !self.established {
+            self.disconnect_with_error(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by peer",
+            ));
+        } else {
+            self.disconnect();
+        }
+    }
+
     fn disconnect(&mut self) {
         let username = self.peer_username();
         debug!("[peer:{}] disconnect", username);
 
+        self.readiness = None;
         self.stream.take();
 
         if self.disconnect_reported {
@@ -612,7 +845,7 @@ impl PeerActor {
             Ok(addr) => {
                 // Use connect_timeout to prevent blocking the thread for too long
                 let timeout = Duration::from_secs(5);
-                match TcpStream::connect_timeout(&addr, timeout) {
+                match PeerStream::connect(addr, timeout, self.tls.as_deref()) {
                     Ok(stream) => {
                         if let Err(e) = stream.set_nonblocking(true) {
                             error!(
@@ -624,7 +857,7 @@ impl PeerActor {
                         }
                         stream.set_nodelay(true).ok();
                         self.stream = Some(stream);
-                        self.connection_state = ConnectionState::Connecting {
+                        self.connection_state = SocketState::Connecting {
                             since: Instant::now(),
                         };
                         true
@@ -650,8 +883,7 @@ impl PeerActor {
     }
 
     fn check_connection_status(&mut self) {
-        let ConnectionState::Connecting { since } = self.connection_state
-        else {
+        let SocketState::Connecting { since } = self.connection_state else {
             return;
         };
 
@@ -672,7 +904,7 @@ impl PeerActor {
 
         match stream.peer_addr() {
             Ok(_) => {
-                self.connection_state = ConnectionState::Connected;
+                self.connection_state = SocketState::Connected;
                 self.on_connection_established();
             }
             Err(ref e) if e.kind() == io::ErrorKind::NotConnected => {}
@@ -749,6 +981,9 @@ impl PeerActor {
         }
 
         if let Some(ref handle) = self.self_handle {
+            if let Some(ref stream) = self.stream {
+                self.readiness = reactor::register(stream, handle.waker());
+            }
             handle.send(PeerMessage::ProcessRead).ok();
         }
 
@@ -760,35 +995,91 @@ impl Actor for PeerActor {
     type Message = PeerMessage;
 
     fn handle(&mut self, msg: Self::Message) {
+        let _span = self.enter_span();
         self.handle_message(msg);
     }
 
+    fn name(&self) -> String {
+        format!("peer:{}", self.peer_username())
+    }
+
+    fn mailbox(&self) -> Mailbox {
+        self.mailbox
+    }
+
     fn on_start(&mut self) {
+        let _span = self.enter_span();
         if self.stream.is_none() {
             self.initiate_connection();
         } else {
-            self.connection_state = ConnectionState::Connected;
+            self.connection_state = SocketState::Connected;
             self.on_connection_established();
         }
     }
 
     fn on_stop(&mut self) {
+        let _span = self.enter_span();
         let username = self.peer_username();
         trace!("[peer:{}] actor stopping", username);
         self.disconnect();
     }
 
     fn tick(&mut self) {
+        let _span = self.enter_span();
         match self.connection_state {
-            ConnectionState::Connecting { .. } => {
+            SocketState::Connecting { .. } => {
                 self.check_connection_status();
             }
-            ConnectionState::Connected => {
-                if self.stream.is_some() {
+            SocketState::Connected => {
+                if self.stream.is_some() && self.readiness.is_none() {
                     self.process_read();
                 }
             }
-            ConnectionState::Disconnected => {}
+            SocketState::Disconnected => {}
+        }
+    }
+
+    fn tick_interval(&self) -> Duration {
+        // Connected and woken by the reactor, the tick has nothing to do.
+        if self.readiness.is_some() {
+            IDLE_TICK_INTERVAL
+        } else {
+            DEFAULT_TICK_INTERVAL
         }
     }
+
+    fn on_ready(&mut self) {
+        let _span = self.enter_span();
+        self.process_read();
+        if let Some(ref readiness) = self.readiness {
+            readiness.arm();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeerMessage, peer_handlers};
+    use crate::dispatcher::MessageDispatcher;
+    use crate::message::Message;
+    use crate::message::server::MessageFactory;
+    use crate::peer::ConnectionType;
+    use std::sync::mpsc;
+
+    #[test]
+    fn a_peer_init_frame_reaches_its_handler() {
+        let (sender, receiver) = mpsc::channel();
+        let dispatcher =
+            MessageDispatcher::new("peer".into(), sender, peer_handlers());
+        let init = MessageFactory::build_peer_init_message(
+            "bob",
+            ConnectionType::P,
+            7,
+        );
+        dispatcher.dispatch(&mut Message::new_with_data(init.get_buffer()));
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(PeerMessage::SetUsername(username)) if username == "bob"
+        ));
+    }
 }
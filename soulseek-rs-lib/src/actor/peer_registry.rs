@@ -1,13 +1,15 @@
-use crate::actor::peer_actor::{PeerActor, PeerMessage};
-use crate::actor::{ActorHandle, ActorSystem};
+use crate::actor::peer_actor::{PeerActor, PeerMessage, PeerTrace};
+use crate::actor::{ActorHandle, ActorSystem, Mailbox};
 use crate::client::ClientOperation;
-use crate::message::MessageReader;
+use crate::ignore_list::IgnoreList;
+use crate::message::{CustomHandlers, DEFAULT_MAX_MESSAGE_SIZE, MessageReader};
+use crate::metrics::Metrics;
 use crate::peer::Peer;
+use crate::transport::{PeerStream, PeerTlsSettings};
 use crate::utils::lock::MutexExt;
 use crate::{debug, error};
 
 use std::collections::HashMap;
-use std::net::TcpStream;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
@@ -25,6 +27,13 @@ pub struct PeerRegistry {
     actor_system: Arc<ActorSystem>,
     client_channel: Sender<ClientOperation>,
     own_username: String,
+    peer_trace: Arc<PeerTrace>,
+    ignore_list: Arc<IgnoreList>,
+    metrics: Arc<Metrics>,
+    custom_handlers: Arc<CustomHandlers<PeerMessage>>,
+    mailbox: Mailbox,
+    max_message_size: usize,
+    peer_tls: Option<Arc<PeerTlsSettings>>,
 }
 
 impl PeerRegistry {
@@ -39,36 +48,103 @@ impl PeerRegistry {
             actor_system,
             client_channel,
             own_username,
+            peer_trace: Arc::default(),
+            ignore_list: Arc::default(),
+            metrics: Arc::default(),
+            custom_handlers: Arc::default(),
+            mailbox: Mailbox::Unbounded,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            peer_tls: None,
         }
     }
 
+    /// Share the client's per-peer trace set with every actor spawned here.
+    #[must_use]
+    pub fn with_peer_trace(mut self, peer_trace: Arc<PeerTrace>) -> Self {
+        self.peer_trace = peer_trace;
+        self
+    }
+
+    /// Refuse connections with the users on the client's `ignore_list`.
+    #[must_use]
+    pub fn with_ignore_list(mut self, ignore_list: Arc<IgnoreList>) -> Self {
+        self.ignore_list = ignore_list;
+        self
+    }
+
+    /// Count every peer actor's messages in the client's shared metrics.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Install the embedder's `handlers` on every peer connection.
+    #[must_use]
+    pub fn with_custom_handlers(
+        mut self,
+        handlers: Arc<CustomHandlers<PeerMessage>>,
+    ) -> Self {
+        self.custom_handlers = handlers;
+        self
+    }
+
+    /// Spawn every peer actor with `mailbox`.
+    #[must_use]
+    pub const fn with_mailbox(mut self, mailbox: Mailbox) -> Self {
+        self.mailbox = mailbox;
+        self
+    }
+
+    /// Drop peers that announce a message longer than `max_size` bytes.
+    #[must_use]
+    pub const fn with_max_message_size(mut self, max_size: usize) -> Self {
+        self.max_message_size = max_size;
+        self
+    }
+
+    /// Dial peers over TLS with `tls`.
+    #[must_use]
+    pub fn with_peer_tls(mut self, tls: Option<Arc<PeerTlsSettings>>) -> Self {
+        self.peer_tls = tls;
+        self
+    }
+
     pub fn register_peer(
         &self,
         peer: Peer,
-        stream: Option<TcpStream>,
+        stream: Option<PeerStream>,
         reader: Option<MessageReader>,
-    ) -> Result<ActorHandle<PeerMessage>, String> {
+    ) -> crate::Result<ActorHandle<PeerMessage>> {
         let username = peer.username.clone();
+        if self.ignore_list.contains(&username) {
+            return Err(crate::SoulseekRs::Ignored { username });
+        }
         let id = NEXT_PEER_ID.fetch_add(1, Ordering::Relaxed);
 
+        let reader = reader
+            .unwrap_or_default()
+            .with_max_size(self.max_message_size);
         let actor = PeerActor::new(
             peer,
             stream,
-            reader,
+            Some(reader),
             self.client_channel.clone(),
             self.own_username.clone(),
             id,
-        );
+        )
+        .with_peer_trace(self.peer_trace.clone())
+        .with_metrics(self.metrics.clone())
+        .with_custom_handlers(self.custom_handlers.clone())
+        .with_mailbox(self.mailbox)
+        .with_tls(self.peer_tls.clone());
 
         let handle =
             self.actor_system.spawn_with_handle(actor, |actor, handle| {
                 actor.set_self_handle(handle);
             });
 
-        let mut peers = self
-            .peers
-            .lock_safe()
-            .map_err(|e| format!("peer registry lock poisoned: {e}"))?;
+        let mut peers = self.peers.lock_safe()?;
         // Stop any actor already registered under this username so it does not
         // become an orphan pinning a pool worker forever. Eviction on the
         // replaced actor's later shutdown is identity-aware (keyed on its id),
@@ -118,6 +194,20 @@ impl PeerRegistry {
         removed.map(|(_, handle)| handle)
     }
 
+    /// Stop and remove every peer actor.
+    pub fn stop_all(&self) {
+        let peers: Vec<_> = match self.peers.lock_safe() {
+            Ok(mut peers) => peers.drain().collect(),
+            Err(e) => {
+                error!("[peer_registry] stop_all: {}", e);
+                return;
+            }
+        };
+        for (_, (_, handle)) in &peers {
+            let _ = handle.stop();
+        }
+    }
+
     /// Remove and return the actor for `username` only if it is still the actor
     /// with `id`. A stale (replaced) actor's terminal notification therefore
     /// cannot evict the newer actor that now occupies the slot.
@@ -182,10 +272,12 @@ impl PeerRegistry {
         &self,
         username: &str,
         message: PeerMessage,
-    ) -> Result<(), String> {
-        let handle = self
-            .get_peer(username)
-            .ok_or_else(|| format!("Peer {username} not found in registry"))?;
+    ) -> crate::Result<()> {
+        let handle = self.get_peer(username).ok_or_else(|| {
+            crate::SoulseekRs::PeerUnreachable {
+                username: username.to_string(),
+            }
+        })?;
 
         handle.send(message)
     }
@@ -194,7 +286,7 @@ impl PeerRegistry {
         &self,
         username: &str,
         filename: String,
-    ) -> Result<(), String> {
+    ) -> crate::Result<()> {
         self.send_to_peer(username, PeerMessage::QueueUpload(filename))
     }
 }
@@ -206,6 +298,13 @@ impl Clone for PeerRegistry {
             actor_system: self.actor_system.clone(),
             client_channel: self.client_channel.clone(),
             own_username: self.own_username.clone(),
+            peer_trace: self.peer_trace.clone(),
+            ignore_list: self.ignore_list.clone(),
+            metrics: self.metrics.clone(),
+            custom_handlers: self.custom_handlers.clone(),
+            mailbox: self.mailbox,
+            max_message_size: self.max_message_size,
+            peer_tls: self.peer_tls.clone(),
         }
     }
 }
@@ -214,6 +313,7 @@ impl Clone for PeerRegistry {
 mod tests {
     use super::PeerRegistry;
     use crate::actor::ActorSystem;
+    use crate::ignore_list::IgnoreList;
     use crate::peer::{ConnectionType, Peer};
     use crate::utils::thread_pool::ThreadPool;
     use std::net::{TcpListener, TcpStream};
@@ -244,7 +344,9 @@ mod tests {
             0,
             0,
         );
-        registry.register_peer(peer, Some(stream), None).unwrap();
+        registry
+            .register_peer(peer, Some(stream.into()), None)
+            .unwrap();
         assert!(registry.contains("bob"));
 
         // A stale / wrong id must not evict the live actor.
@@ -257,4 +359,30 @@ mod tests {
         let _ = handle.unwrap().stop();
         assert!(!registry.contains("bob"));
     }
+
+    #[test]
+    fn ignored_peers_are_refused() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let system = Arc::new(ActorSystem::new(pool));
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let ignore_list = Arc::new(IgnoreList::new(["eve".to_string()]));
+        let registry = PeerRegistry::new(system, tx, "me".to_string())
+            .with_ignore_list(ignore_list);
+
+        let peer = Peer::new(
+            "eve".to_string(),
+            ConnectionType::P,
+            "127.0.0.1".to_string(),
+            2234,
+            None,
+            0,
+            0,
+            0,
+        );
+        assert!(matches!(
+            registry.register_peer(peer, None, None),
+            Err(crate::SoulseekRs::Ignored { username }) if username == "eve"
+        ));
+        assert!(!registry.contains("eve"));
+    }
 }
@@ -0,0 +1,280 @@
+//! Socket readiness for the actors.
+//!
+//! One background thread waits in `poll(2)` on every registered socket and
+//! wakes an actor only when its socket has data, instead of each actor
+//! reading on every 100ms tick. A wake-up disarms the socket until the actor
+//! has drained it and calls [`Registration::arm`]: `poll` is level-triggered,
+//! and an unread socket would otherwise wake it in a loop.
+//!
+//! `poll` comes from the C library std already links, so this needs no extra
+//! dependency. On other platforms [`register`] returns `None` and actors keep
+//! reading on their tick.
+
+use std::sync::Arc;
+
+/// Called from the reactor thread when a socket becomes readable. Returns
+/// `false` once its actor is gone, which drops the registration.
+pub type Waker = Arc<dyn Fn() -> bool + Send + Sync>;
+
+#[cfg(unix)]
+pub use imp::{Registration, register};
+
+#[cfg(not(unix))]
+pub use fallback::{Registration, register};
+
+#[cfg(unix)]
+mod imp {
+    use super::Waker;
+    use crate::utils::lock::MutexExt;
+    use crate::{error, trace};
+    use std::collections::HashMap;
+    use std::ffi::{c_int, c_short};
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::thread;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    type Nfds = std::ffi::c_ulong;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    type Nfds = std::ffi::c_uint;
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    const POLLIN: c_short = 0x1;
+
+    unsafe extern "C" {
+        fn poll(fds: *mut PollFd, nfds: Nfds, timeout: c_int) -> c_int;
+    }
+
+    struct Entry {
+        fd: RawFd,
+        armed: bool,
+        waker: Waker,
+    }
+
+    struct Shared {
+        entries: Mutex<HashMap<u64, Entry>>,
+        next_id: AtomicU64,
+        /// Written to interrupt `poll` when the set of armed sockets changes.
+        interrupt: UnixStream,
+    }
+
+    impl Shared {
+        fn interrupt(&self) {
+            // A full pipe already holds a pending interrupt.
+            let _ = (&self.interrupt).write(&[1]);
+        }
+    }
+
+    /// A socket's place in the reactor; dropping it unregisters the socket.
+    /// Drop it before closing the socket.
+    pub struct Registration {
+        id: u64,
+        shared: Arc<Shared>,
+    }
+
+    impl Registration {
+        /// Wake the actor again on the next data. Call after reading the
+        /// socket until it would block.
+        pub fn arm(&self) {
+            if let Ok(mut entries) = self.shared.entries.lock_safe()
+                && let Some(entry) = entries.get_mut(&self.id)
+                && !entry.armed
+            {
+                entry.armed = true;
+                drop(entries);
+                self.shared.interrupt();
+            }
+        }
+    }
+
+    impl Drop for Registration {
+        fn drop(&mut self) {
+            if let Ok(mut entries) = self.shared.entries.lock_safe() {
+                entries.remove(&self.id);
+            }
+            self.shared.interrupt();
+        }
+    }
+
+    /// Watch `socket`, calling `waker` when it becomes readable. The
+    /// registration starts armed.
+    pub fn register(
+        socket: &impl AsRawFd,
+        waker: Waker,
+    ) -> Option<Registration> {
+        let shared = reactor()?;
+        let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+        shared.entries.lock_safe().ok()?.insert(
+            id,
+            Entry {
+                fd: socket.as_raw_fd(),
+                armed: true,
+                waker,
+            },
+        );
+        shared.interrupt();
+        Some(Registration { id, shared })
+    }
+
+    /// The process-wide reactor, started on first use. `None` if it could
+    /// not be started.
+    fn reactor() -> Option<Arc<Shared>> {
+        static REACTOR: OnceLock<Option<Arc<Shared>>> = OnceLock::new();
+        REACTOR
+            .get_or_init(|| match start() {
+                Ok(shared) => Some(shared),
+                Err(e) => {
+                    error!("[reactor] failed to start, polling instead: {}", e);
+                    None
+                }
+            })
+            .clone()
+    }
+
+    fn start() -> io::Result<Arc<Shared>> {
+        let (interrupt, wakeups) = UnixStream::pair()?;
+        interrupt.set_nonblocking(true)?;
+        wakeups.set_nonblocking(true)?;
+        let shared = Arc::new(Shared {
+            entries: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            interrupt,
+        });
+        let for_thread = shared.clone();
+        thread::Builder::new()
+            .name("soulseek-reactor".to_string())
+            .spawn(move || run(&for_thread, wakeups))?;
+        Ok(shared)
+    }
+
+    fn run(shared: &Shared, mut wakeups: UnixStream) {
+        let mut fds = Vec::new();
+        let mut ids = Vec::new();
+        loop {
+            fds.clear();
+            ids.clear();
+            fds.push(PollFd {
+                fd: wakeups.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            });
+            match shared.entries.lock_safe() {
+                Ok(entries) => {
+                    for (&id, entry) in entries.iter().filter(|(_, e)| e.armed)
+                    {
+                        ids.push(id);
+                        fds.push(PollFd {
+                            fd: entry.fd,
+                            events: POLLIN,
+                            revents: 0,
+                        });
+                    }
+                }
+                Err(e) => {
+                    error!("[reactor] stopping: {}", e);
+                    return;
+                }
+            }
+
+            // SAFETY: `fds` is a live, exclusively borrowed array of
+            // `fds.len()` pollfd structs for the duration of the call.
+            let ready =
+                unsafe { poll(fds.as_mut_ptr(), fds.len() as Nfds, -1) };
+            if ready < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    error!("[reactor] poll failed: {}", e);
+                    thread::sleep(std::time::Duration::from_millis(100));
+                }
+                continue;
+            }
+
+            if fds[0].revents != 0 {
+                let mut drain = [0u8; 64];
+                while matches!(wakeups.read(&mut drain), Ok(n) if n > 0) {}
+            }
+
+            // Disarm under the lock, then wake outside it: a waker must not
+            // block the actors re-arming.
+            let mut woken = Vec::new();
+            if let Ok(mut entries) = shared.entries.lock_safe() {
+                for (id, pollfd) in ids.iter().zip(&fds[1..]) {
+                    if pollfd.revents != 0
+                        && let Some(entry) = entries.get_mut(id)
+                        && entry.armed
+                    {
+                        entry.armed = false;
+                        woken.push((*id, entry.waker.clone()));
+                    }
+                }
+            }
+            let gone: Vec<u64> = woken
+                .into_iter()
+                .filter(|(_, waker)| !waker())
+                .map(|(id, _)| id)
+                .collect();
+            if !gone.is_empty()
+                && let Ok(mut entries) = shared.entries.lock_safe()
+            {
+                trace!("[reactor] dropping {} stale sockets", gone.len());
+                for id in gone {
+                    entries.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod fallback {
+    use super::Waker;
+
+    pub struct Registration;
+
+    impl Registration {
+        pub const fn arm(&self) {}
+    }
+
+    pub fn register<S>(_socket: &S, _waker: Waker) -> Option<Registration> {
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn wakes_once_per_arm_when_data_arrives() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client =
+            TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let registration =
+            register(&server, Arc::new(move || tx.send(()).is_ok())).unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        client.write_all(b"hello").unwrap();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        // The data is still unread, but the socket is disarmed.
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        registration.arm();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+//! Pacing of messages sent to the server.
+//!
+//! The official server disconnects clients that burst messages at it (a
+//! result storm can trigger dozens of `GetPeerAddress` requests at once), so
+//! outgoing server messages go through a token bucket. Messages over the
+//! budget wait in a FIFO backlog that the server actor drains on every tick.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::message::Message;
+use crate::utils::token_bucket::TokenBucket;
+
+/// Login (1) and the keepalive ping (32) are never held back: delaying them
+/// only risks the very disconnect the limiter exists to avoid.
+const EXEMPT_CODES: [u32; 2] = [1, 32];
+
+/// Sustained rate and burst allowance for messages sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendRateLimit {
+    pub messages_per_sec: f64,
+    pub burst: f64,
+}
+
+impl Default for SendRateLimit {
+    fn default() -> Self {
+        Self {
+            messages_per_sec: 10.0,
+            burst: 20.0,
+        }
+    }
+}
+
+/// Counters describing the limiter's effect, shared with the client.
+#[derive(Debug, Default)]
+pub struct ServerSendStats {
+    sent: AtomicU64,
+    deferred: AtomicU64,
+    backlog: AtomicUsize,
+}
+
+/// A point-in-time copy of [`ServerSendStats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerSendSnapshot {
+    /// Messages written to the server.
+    pub sent: u64,
+    /// Messages that had to wait for budget before being written.
+    pub deferred: u64,
+    /// Messages currently waiting.
+    pub backlog: usize,
+}
+
+impl ServerSendStats {
+    #[must_use]
+    pub fn snapshot(&self) -> ServerSendSnapshot {
+        ServerSendSnapshot {
+            sent: self.sent.load(Ordering::Relaxed),
+            deferred: self.deferred.load(Ordering::Relaxed),
+            backlog: self.backlog.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct SendLimiter {
+    bucket: Option<TokenBucket>,
+    backlog: VecDeque<Message>,
+    stats: Arc<ServerSendStats>,
+}
+
+impl SendLimiter {
+    /// `limit` of `None` lets everything through immediately (stats are
+    /// still kept).
+    #[must_use]
+    pub fn new(
+        limit: Option<SendRateLimit>,
+        stats: Arc<ServerSendStats>,
+    ) -> Self {
+        Self {
+            bucket: limit
+                .map(|l| TokenBucket::new(l.messages_per_sec, l.burst)),
+            backlog: VecDeque::new(),
+            stats,
+        }
+    }
+
+    /// Admit `message` now, or park it in the backlog. Exempt messages skip
+    /// the queue and the budget; everything else keeps FIFO order.
+    pub fn admit(&mut self, message: Message) -> Option<Message> {
+        let exempt = EXEMPT_CODES.contains(&message_code(&message));
+        let admitted = exempt
+            || self.bucket.is_none()
+            || (self.backlog.is_empty()
+                && self.bucket.as_mut().is_some_and(|b| b.try_take(1.0)));
+        if admitted {
+            self.stats.sent.fetch_add(1, Ordering::Relaxed);
+            return Some(message);
+        }
+        self.backlog.push_back(message);
+        self.stats.deferred.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .backlog
+            .store(self.backlog.len(), Ordering::Relaxed);
+        None
+    }
+
+    /// Backlogged messages the budget now allows, oldest first.
+    pub fn drain_ready(&mut self) -> Vec<Message> {
+        let mut ready = Vec::new();
+        while !self.backlog.is_empty()
+            && self.bucket.as_mut().is_none_or(|b| b.try_take(1.0))
+        {
+            if let Some(message) = self.backlog.pop_front() {
+                ready.push(message);
+            }
+        }
+        self.stats
+            .sent
+            .fetch_add(ready.len() as u64, Ordering::Relaxed);
+        self.stats
+            .backlog
+            .store(self.backlog.len(), Ordering::Relaxed);
+        ready
+    }
+
+    /// Forget the backlog (the connection it was meant for is gone).
+    pub fn clear(&mut self) {
+        self.backlog.clear();
+        self.stats.backlog.store(0, Ordering::Relaxed);
+    }
+}
+
+fn message_code(message: &Message) -> u32 {
+    message
+        .get_data()
+        .get(0..4)
+        .and_then(|code| code.try_into().ok())
+        .map_or(0, u32::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SendLimiter, SendRateLimit, ServerSendStats};
+    use crate::message::Message;
+    use std::sync::Arc;
+
+    fn message(code: u32) -> Message {
+        Message::new_with_data(code.to_le_bytes().to_vec())
+    }
+
+    fn limiter(burst: f64) -> (SendLimiter, Arc<ServerSendStats>) {
+        let stats = Arc::new(ServerSendStats::default());
+        let limit = SendRateLimit {
+            messages_per_sec: 0.001,
+            burst,
+        };
+        (SendLimiter::new(Some(limit), stats.clone()), stats)
+    }
+
+    #[test]
+    fn messages_over_the_burst_are_deferred() {
+        let (mut limiter, stats) = limiter(2.0);
+        assert!(limiter.admit(message(26)).is_some());
+        assert!(limiter.admit(message(3)).is_some());
+        assert!(limiter.admit(message(3)).is_none());
+        assert!(limiter.admit(message(35)).is_none());
+
+        let snapshot = stats.snapshot();
+        assert_eq!(
+            (snapshot.sent, snapshot.deferred, snapshot.backlog),
+            (2, 2, 2)
+        );
+        // No budget has refilled yet.
+        assert!(limiter.drain_ready().is_empty());
+    }
+
+    #[test]
+    fn backlog_drains_oldest_first_as_budget_refills() {
+        let stats = Arc::new(ServerSendStats::default());
+        let limit = SendRateLimit {
+            messages_per_sec: 1000.0,
+            burst: 1.0,
+        };
+        let mut limiter = SendLimiter::new(Some(limit), stats);
+        assert!(limiter.admit(message(3)).is_some());
+        assert!(limiter.admit(message(26)).is_none());
+        assert!(limiter.admit(message(35)).is_none());
+
+        let mut drained = Vec::new();
+        while drained.len() < 2 {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            drained
+                .extend(limiter.drain_ready().iter().map(super::message_code));
+        }
+        assert_eq!(drained, [26, 35]);
+    }
+
+    #[test]
+    fn login_and_ping_are_never_deferred() {
+        let (mut limiter, _) = limiter(0.0);
+        assert!(limiter.admit(message(3)).is_none());
+        assert!(limiter.admit(message(1)).is_some());
+        assert!(limiter.admit(message(32)).is_some());
+    }
+
+    #[test]
+    fn unlimited_sender_passes_everything_through() {
+        let stats = Arc::new(ServerSendStats::default());
+        let mut limiter = SendLimiter::new(None, stats.clone());
+        for _ in 0..100 {
+            assert!(limiter.admit(message(3)).is_some());
+        }
+        assert_eq!(stats.snapshot().sent, 100);
+    }
+}
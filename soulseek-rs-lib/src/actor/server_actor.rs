@@ -1,37 +1,64 @@
-use crate::actor::{Actor, ActorHandle, ConnectionState};
+use crate::actor::{
+    Actor, ActorHandle, Mailbox, ReplyTo, SocketState, reactor,
+};
 use crate::client::ClientOperation;
-use crate::dispatcher::MessageDispatcher;
+use crate::dispatcher::{MessageDispatcher, Verdict};
+use crate::message::server::AdminMessageHandler;
+use crate::message::server::CantConnectToPeerHandler;
 use crate::message::server::ConnectToPeerHandler;
+use crate::message::server::DistributedAliveIntervalHandler;
 use crate::message::server::ExcludedSearchPhrasesHandler;
 use crate::message::server::FileSearchHandler;
 use crate::message::server::GetPeerAddressHandler;
+use crate::message::server::GetUserStatsHandler;
+use crate::message::server::GetUserStatusHandler;
 use crate::message::server::JoinRoomHandler;
 use crate::message::server::LeaveRoomHandler;
 use crate::message::server::LoginHandler;
 use crate::message::server::MessageFactory;
 use crate::message::server::MessageUser;
+use crate::message::server::MinParentsInCacheHandler;
+use crate::message::server::ParentInactivityTimeoutHandler;
 use crate::message::server::ParentMinSpeedHandler;
 use crate::message::server::ParentSpeedRatioHandler;
 use crate::message::server::PrivilegedUsersHandler;
+use crate::message::server::ReloggedHandler;
+use crate::message::server::ResetDistributedHandler;
 use crate::message::server::RoomListHandler;
+use crate::message::server::RoomTickerAddHandler;
+use crate::message::server::RoomTickerRemoveHandler;
+use crate::message::server::RoomTickersHandler;
 use crate::message::server::SayChatroomHandler;
+use crate::message::server::SearchInactivityTimeoutHandler;
 use crate::message::server::UserJoinedRoomHandler;
 use crate::message::server::UserLeftRoomHandler;
+use crate::message::server::WatchUserHandler;
 use crate::message::server::WishListIntervalHandler;
-use crate::message::{Handlers, MessageType};
-use crate::message::{Message, MessageReader};
+use crate::message::server::{
+    AddPrivilegedUserHandler, CheckPrivilegesHandler,
+};
+use crate::message::server::{ParentCandidate, PossibleParentsHandler};
+use crate::message::{CustomHandlers, Handlers, MessageType};
+use crate::message::{DEFAULT_MAX_MESSAGE_SIZE, Message, MessageReader};
+use crate::metrics::Metrics;
 use crate::peer::ConnectionType;
 use crate::peer::Peer;
-use crate::types::{RoomEvent, RoomInfo};
+use crate::types::{
+    ClientEvent, ConnectionState, LoginOutcome, RoomEvent, RoomInfo, UserInfo,
+    UserStatus,
+};
 use crate::utils::lock::RwLockExt;
 
+use super::send_limiter::{SendLimiter, SendRateLimit, ServerSendStats};
+use crate::transport::{ServerStream, TlsSettings};
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Error, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::net::ToSocketAddrs;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 
-use crate::{SoulseekRs, debug, error, trace, warn};
+use crate::{SoulseekRs, debug, error, info, trace, warn};
 
 #[derive(Debug, Clone)]
 pub struct PeerAddress {
@@ -144,12 +171,13 @@ impl UserMessage {
 #[derive(Debug, Clone)]
 pub enum ServerMessage {
     ProcessRead,
-    LoginStatus(bool),
+    LoginStatus(LoginOutcome),
     SendMessage(Message),
+    /// Log in, answered once the server accepts or rejects us.
     Login {
         username: String,
         password: String,
-        response: std::sync::mpsc::Sender<Result<bool, SoulseekRs>>,
+        reply: ReplyTo<Result<LoginOutcome, SoulseekRs>>,
     },
     FileSearch {
         token: u32,
@@ -165,7 +193,13 @@ pub enum ServerMessage {
     #[allow(dead_code)]
     ConnectToPeer(Peer),
     PierceFirewall(u32),
+    /// Look up a peer's address and connect to it.
     GetPeerAddress(String),
+    /// Look up a peer's address and answer with it, without connecting.
+    ResolvePeerAddress {
+        username: String,
+        reply: ReplyTo<PeerAddress>,
+    },
     GetPeerAddressResponse {
         username: String,
         host: String,
@@ -195,6 +229,44 @@ pub enum ServerMessage {
         room: String,
         username: String,
     },
+    /// Part of a user's status/stats from `WatchUser`, `GetUserStatus` or
+    /// `GetUserStats`.
+    UserInfoReceived(Box<UserInfo>),
+    RoomTickers {
+        room: String,
+        tickers: Vec<(String, String)>,
+    },
+    RoomTickerSet {
+        room: String,
+        username: String,
+        ticker: String,
+    },
+    RoomTickerRemoved {
+        room: String,
+        username: String,
+    },
+    Relogged,
+    /// Users we may join the distributed network under.
+    PossibleParents(Vec<ParentCandidate>),
+    /// Drop our distributed parent.
+    ResetDistributed,
+    /// The peer we asked to connect to us with this token could not.
+    CantConnectToPeer(u32),
+    /// Phrases the server won't have us search for or answer with.
+    ExcludedSearchPhrases(Vec<String>),
+    AdminMessage(String),
+    PrivilegedUsers(Vec<String>),
+    PrivilegedUserAdded(String),
+    /// Seconds of privileges we have left.
+    PrivilegesLeft(u32),
+    /// Show us as online or away, now and after every login.
+    SetStatus(UserStatus),
+    /// The share index changed: announce these counts now if logged in, and
+    /// on every later login.
+    SetSharedCounts {
+        folders: u32,
+        files: u32,
+    },
 }
 
 pub struct ServerActor {
@@ -202,9 +274,27 @@ pub struct ServerActor {
     context: Arc<RwLock<Context>>,
     listen_port: u16,
     enable_listen: bool,
-    stream: Option<TcpStream>,
-    connection_state: ConnectionState,
+    /// Set while the reactor wakes us on incoming data; without it the
+    /// socket is read on every tick. Declared before `stream` so it is
+    /// dropped first.
+    readiness: Option<reactor::Registration>,
+    stream: Option<ServerStream>,
+    tls: Option<TlsSettings>,
+    limiter: SendLimiter,
+    keepalive_interval: Duration,
+    silence_timeout: Duration,
+    last_frame_at: Instant,
+    last_ping_at: Instant,
+    probe_sent: bool,
+    /// Remembered after the first login so a reconnect can log in again.
+    credentials: Option<(String, String)>,
+    reconnect_at: Option<Instant>,
+    reconnect_when_relogged: bool,
+    metrics: Arc<Metrics>,
+    connection_state: SocketState,
     reader: MessageReader,
+    max_message_size: usize,
+    custom_handlers: Arc<CustomHandlers<ServerMessage>>,
     client_channel: Sender<ClientOperation>,
     self_handle: Option<ActorHandle<ServerMessage>>,
     dispatcher: Option<MessageDispatcher<ServerMessage>>,
@@ -213,17 +303,66 @@ pub struct ServerActor {
     queued_messages: Vec<ServerMessage>,
     shared_folder_count: u32,
     shared_file_count: u32,
+    /// Our status as other users see it, restored on reconnect.
+    status: UserStatus,
+    mailbox: Mailbox,
+    /// The state callers see, shared with the client.
+    public_state: Arc<RwLock<ConnectionState>>,
+    pending_login: Option<ReplyTo<Result<LoginOutcome, SoulseekRs>>>,
+    /// Callers waiting on `ResolvePeerAddress`, by username.
+    address_waiters: HashMap<String, Vec<ReplyTo<PeerAddress>>>,
+    /// Users looked up through `GetPeerAddress`, whose address goes to the
+    /// client so it connects.
+    forwarded_lookups: HashSet<String>,
+}
+
+/// Default gap between keepalive pings.
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_mins(2);
+/// Default silence after which the server connection is considered dead.
+pub const DEFAULT_SILENCE_TIMEOUT: Duration = Duration::from_mins(6);
+/// Wait before trying to reconnect after losing the server.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// What the keepalive check should do on this tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeepaliveAction {
+    Nothing,
+    Ping,
+    /// Silent for half the timeout: ask for something the server must
+    /// answer (our own status), since a ping gets no reply.
+    Probe,
+    /// Silent for the whole timeout: the connection is gone.
+    Dead,
+}
+
+fn keepalive_action(
+    silent_for: Duration,
+    since_ping: Duration,
+    probe_sent: bool,
+    interval: Duration,
+    silence_timeout: Duration,
+) -> KeepaliveAction {
+    if silent_for >= silence_timeout {
+        KeepaliveAction::Dead
+    } else if !probe_sent && silent_for >= silence_timeout / 2 {
+        KeepaliveAction::Probe
+    } else if since_ping >= interval {
+        KeepaliveAction::Ping
+    } else {
+        KeepaliveAction::Nothing
+    }
 }
 
 /// The messages a client sends right after a successful login: its shared-file
-/// counts, distributed-network opt-out, online status, and (when listening) the
-/// port peers should connect to. Kept as a free function so it can be tested
+/// counts, distributed-network opt-out, status, and (when listening) the port
+/// peers should connect to. Kept as a free function so it can be tested
 /// without a live connection.
 fn post_login_messages(
     enable_listen: bool,
     listen_port: u16,
     shared_folders: u32,
     shared_files: u32,
+    status: UserStatus,
 ) -> Vec<Message> {
     let mut messages = vec![
         MessageFactory::build_shared_folders_message(
@@ -231,7 +370,7 @@ fn post_login_messages(
             shared_files,
         ),
         MessageFactory::build_no_parent_message(),
-        MessageFactory::build_set_status_message(2),
+        MessageFactory::build_set_status_message(status.code()),
     ];
     if enable_listen {
         messages.push(MessageFactory::build_set_wait_port_message(listen_port));
@@ -255,19 +394,123 @@ impl ServerActor {
             listen_port,
             enable_listen,
             stream: None,
-            connection_state: ConnectionState::Disconnected,
+            readiness: None,
+            tls: None,
+            limiter: SendLimiter::new(None, Arc::default()),
+            keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
+            silence_timeout: DEFAULT_SILENCE_TIMEOUT,
+            last_frame_at: Instant::now(),
+            last_ping_at: Instant::now(),
+            probe_sent: false,
+            credentials: None,
+            reconnect_at: None,
+            reconnect_when_relogged: false,
+            metrics: Arc::default(),
+            connection_state: SocketState::Disconnected,
             dispatcher: None,
             dispatcher_receiver: None,
             dispatcher_sender: None,
             reader: MessageReader::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            custom_handlers: Arc::default(),
             client_channel,
             self_handle: None,
             queued_messages: Vec::new(),
             shared_folder_count,
             shared_file_count,
+            status: UserStatus::Online,
+            mailbox: Mailbox::Unbounded,
+            public_state: Arc::default(),
+            pending_login: None,
+            address_waiters: HashMap::new(),
+            forwarded_lookups: HashSet::new(),
         }
     }
 
+    /// Wrap the server connection in TLS (needs the `tls` feature).
+    #[must_use]
+    pub fn with_tls(mut self, tls: Option<TlsSettings>) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Pace messages to the server (`None` sends them as they come),
+    /// recording the limiter's effect in `stats`.
+    #[must_use]
+    pub fn with_send_limit(
+        mut self,
+        limit: Option<SendRateLimit>,
+        stats: Arc<ServerSendStats>,
+    ) -> Self {
+        self.limiter = SendLimiter::new(limit, stats);
+        self
+    }
+
+    /// Ping the server every `interval`, and reconnect when nothing has
+    /// been received for `silence_timeout`.
+    #[must_use]
+    pub const fn with_keepalive(
+        mut self,
+        interval: Duration,
+        silence_timeout: Duration,
+    ) -> Self {
+        self.keepalive_interval = interval;
+        self.silence_timeout = silence_timeout;
+        self
+    }
+
+    /// Reconnect after our account logs in elsewhere instead of staying
+    /// disconnected.
+    #[must_use]
+    pub const fn with_relogged_reconnect(mut self, reconnect: bool) -> Self {
+        self.reconnect_when_relogged = reconnect;
+        self
+    }
+
+    /// Drop the connection when the server announces a message longer than
+    /// `max_size` bytes, and reconnect.
+    #[must_use]
+    pub fn with_max_message_size(mut self, max_size: usize) -> Self {
+        self.reader = MessageReader::new().with_max_size(max_size);
+        self.max_message_size = max_size;
+        self
+    }
+
+    /// Run the embedder's `handlers` too, installed on each (re)connection.
+    #[must_use]
+    pub fn with_custom_handlers(
+        mut self,
+        handlers: Arc<CustomHandlers<ServerMessage>>,
+    ) -> Self {
+        self.custom_handlers = handlers;
+        self
+    }
+
+    /// Count messages in the client's shared metrics.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Publish connection state changes to `state` as well as to the
+    /// client's event stream.
+    #[must_use]
+    pub fn with_connection_state(
+        mut self,
+        state: Arc<RwLock<ConnectionState>>,
+    ) -> Self {
+        self.public_state = state;
+        self
+    }
+
+    /// Spawn with `mailbox` instead of an unbounded one.
+    #[must_use]
+    pub const fn with_mailbox(mut self, mailbox: Mailbox) -> Self {
+        self.mailbox = mailbox;
+        self
+    }
+
     #[must_use]
     pub const fn get_address(&self) -> &PeerAddress {
         &self.address
@@ -279,6 +522,7 @@ impl ServerActor {
     }
 
     fn initiate_connection(&mut self) -> bool {
+        self.publish_state(ConnectionState::Connecting);
         let host = self.address.host.clone();
         let port = self.address.port;
 
@@ -310,7 +554,8 @@ impl ServerActor {
             return false;
         };
 
-        let stream = match TcpStream::connect(addr) {
+        let stream = match ServerStream::connect(addr, &host, self.tls.as_ref())
+        {
             Ok(s) => s,
             Err(e) => {
                 self.disconnect_with_error(e);
@@ -326,7 +571,7 @@ impl ServerActor {
         stream.set_nodelay(true).ok();
 
         self.stream = Some(stream);
-        self.connection_state = ConnectionState::Connecting {
+        self.connection_state = SocketState::Connecting {
             since: Instant::now(),
         };
         true
@@ -365,16 +610,36 @@ impl ServerActor {
         handlers.register_handler(WishListIntervalHandler);
         handlers.register_handler(ParentMinSpeedHandler);
         handlers.register_handler(ParentSpeedRatioHandler);
-        handlers.register_handler(PrivilegedUsersHandler);
         handlers.register_handler(FileSearchHandler);
         handlers.register_handler(GetPeerAddressHandler);
         handlers.register_handler(ConnectToPeerHandler);
-
-        self.dispatcher = Some(MessageDispatcher::new(
-            "server".into(),
-            dispatcher_sender,
-            handlers,
-        ));
+        handlers.register_handler(WatchUserHandler);
+        handlers.register_handler(GetUserStatusHandler);
+        handlers.register_handler(GetUserStatsHandler);
+        handlers.register_handler(ReloggedHandler);
+        handlers.register_handler(AdminMessageHandler);
+        handlers.register_handler(AddPrivilegedUserHandler);
+        handlers.register_handler(CheckPrivilegesHandler);
+        handlers.register_handler(RoomTickersHandler);
+        handlers.register_handler(RoomTickerAddHandler);
+        handlers.register_handler(RoomTickerRemoveHandler);
+        handlers.register_handler(ParentInactivityTimeoutHandler);
+        handlers.register_handler(SearchInactivityTimeoutHandler);
+        handlers.register_handler(MinParentsInCacheHandler);
+        handlers.register_handler(DistributedAliveIntervalHandler);
+        handlers.register_handler(ResetDistributedHandler);
+        handlers.register_handler(PossibleParentsHandler);
+        handlers.register_handler(CantConnectToPeerHandler);
+        self.custom_handlers.install(&mut handlers);
+
+        self.dispatcher = Some(
+            MessageDispatcher::new(
+                "server".into(),
+                dispatcher_sender,
+                handlers,
+            )
+            .with_interceptors(self.custom_handlers.interceptors()),
+        );
     }
 
     fn process_dispatcher_messages(&mut self) {
@@ -389,8 +654,8 @@ impl ServerActor {
                 msgs
             });
 
-        for msg in &messages {
-            self.handle_message(msg.clone());
+        for msg in messages {
+            self.handle_message(msg);
         }
     }
 
@@ -401,7 +666,13 @@ impl ServerActor {
     }
 
     fn handle_message(&mut self, msg: ServerMessage) {
-        if !matches!(self.connection_state, ConnectionState::Connected) {
+        // Not queued while disconnected: the next login announces the
+        // latest counts anyway.
+        if let ServerMessage::SetSharedCounts { folders, files } = msg {
+            self.set_shared_counts(folders, files);
+            return;
+        }
+        if !matches!(self.connection_state, SocketState::Connected) {
             if matches!(&msg, ServerMessage::ProcessRead) {
                 // Always process read operations
             } else {
@@ -415,8 +686,8 @@ impl ServerActor {
             ServerMessage::ConnectToPeer(peer) => {
                 self.handle_connect_to_peer(peer);
             }
-            ServerMessage::LoginStatus(message) => {
-                self.handle_login_status(message);
+            ServerMessage::LoginStatus(outcome) => {
+                self.handle_login_status(outcome);
             }
             ServerMessage::PierceFirewall(token) => {
                 self.send_message(
@@ -426,10 +697,31 @@ impl ServerActor {
             ServerMessage::SendMessage(message) => {
                 self.send_message(message);
             }
+            ServerMessage::SetStatus(status) => {
+                self.status = status;
+                if self.is_logged_in() {
+                    self.send_message(
+                        MessageFactory::build_set_status_message(status.code()),
+                    );
+                }
+            }
+            ServerMessage::SetSharedCounts { folders, files } => {
+                self.set_shared_counts(folders, files);
+            }
             ServerMessage::GetPeerAddress(username) => {
                 self.send_message(MessageFactory::build_get_peer_address(
                     &username,
                 ));
+                self.forwarded_lookups.insert(username);
+            }
+            ServerMessage::ResolvePeerAddress { username, reply } => {
+                self.send_message(MessageFactory::build_get_peer_address(
+                    &username,
+                ));
+                self.address_waiters
+                    .entry(username)
+                    .or_default()
+                    .push(reply);
             }
             ServerMessage::GetPeerAddressResponse {
                 username,
@@ -478,15 +770,91 @@ impl ServerActor {
             ServerMessage::RoomUserLeft { room, username } => {
                 self.forward_room_event(RoomEvent::UserLeft { room, username });
             }
+            ServerMessage::UserInfoReceived(info) => {
+                if let Err(e) = self
+                    .client_channel
+                    .send(ClientOperation::UserInfoReceived(*info))
+                {
+                    error!("[server] failed to forward UserInfo: {}", e);
+                }
+            }
+            ServerMessage::RoomTickers { room, tickers } => {
+                self.forward_room_event(RoomEvent::Tickers { room, tickers });
+            }
+            ServerMessage::RoomTickerSet {
+                room,
+                username,
+                ticker,
+            } => {
+                self.forward_room_event(RoomEvent::TickerSet {
+                    room,
+                    username,
+                    ticker,
+                });
+            }
+            ServerMessage::RoomTickerRemoved { room, username } => {
+                self.forward_room_event(RoomEvent::TickerRemoved {
+                    room,
+                    username,
+                });
+            }
+            ServerMessage::Relogged => {
+                // The server drops us next; unless asked to, don't log back
+                // in and kick the other session off in turn.
+                if !self.reconnect_when_relogged {
+                    self.credentials = None;
+                }
+                self.forward_client_operation(ClientOperation::Relogged);
+            }
+            ServerMessage::PossibleParents(candidates) => {
+                self.forward_client_operation(
+                    ClientOperation::PossibleParents(candidates),
+                );
+            }
+            ServerMessage::CantConnectToPeer(token) => {
+                self.forward_client_operation(
+                    ClientOperation::CantConnectToPeer(token),
+                );
+            }
+            ServerMessage::ExcludedSearchPhrases(phrases) => {
+                self.forward_client_operation(
+                    ClientOperation::ExcludedSearchPhrases(phrases),
+                );
+            }
+            ServerMessage::ResetDistributed => {
+                self.forward_client_operation(
+                    ClientOperation::ResetDistributed,
+                );
+            }
+            ServerMessage::AdminMessage(text) => {
+                self.forward_client_operation(ClientOperation::Event(
+                    ClientEvent::AdminMessage(text),
+                ));
+            }
+            ServerMessage::PrivilegedUsers(users) => {
+                self.forward_client_operation(
+                    ClientOperation::PrivilegedUsers(users),
+                );
+            }
+            ServerMessage::PrivilegedUserAdded(username) => {
+                self.forward_client_operation(
+                    ClientOperation::PrivilegedUserAdded(username),
+                );
+            }
+            ServerMessage::PrivilegesLeft(seconds) => {
+                self.forward_client_operation(ClientOperation::PrivilegesLeft(
+                    seconds,
+                ));
+            }
             ServerMessage::ProcessRead => {
                 self.process_read();
             }
             ServerMessage::Login {
                 username,
                 password,
-                response,
+                reply,
             } => {
-                self.handle_login(username, password, response);
+                self.handle_login(username, password, reply);
             }
             ServerMessage::FileSearch { token, query } => {
                 self.file_search(token, &query);
@@ -513,31 +881,60 @@ impl ServerActor {
         }
     }
 
-    fn handle_login_status(&mut self, message: bool) {
+    fn handle_login_status(&mut self, outcome: LoginOutcome) {
+        let accepted = outcome.is_accepted();
         match self.context.write_safe() {
-            Ok(mut ctx) => ctx.logged_in = Some(message),
+            Ok(mut ctx) => ctx.logged_in = Some(accepted),
             Err(e) => {
                 error!("[server] LoginStatus write: {}", e);
             }
         }
+        if accepted {
+            self.publish_state(ConnectionState::LoggedIn);
+        }
+        if let Some(reply) = self.pending_login.take() {
+            reply.reply(Ok(outcome));
+        }
         // Send the post-login handshake exactly once, only on success,
         // on the live path (the old ServerActor::login did this but was
         // never called). Advertises real shared counts and, when
         // listening, the port peers must connect to.
-        if message {
+        if accepted {
             for msg in post_login_messages(
                 self.enable_listen,
                 self.listen_port,
                 self.shared_folder_count,
                 self.shared_file_count,
+                self.status,
             ) {
                 self.send_message(msg);
             }
         }
     }
 
+    fn is_logged_in(&self) -> bool {
+        self.public_state
+            .read_safe()
+            .is_ok_and(|state| *state == ConnectionState::LoggedIn)
+    }
+
+    fn set_shared_counts(&mut self, folders: u32, files: u32) {
+        if (folders, files)
+            == (self.shared_folder_count, self.shared_file_count)
+        {
+            return;
+        }
+        self.shared_folder_count = folders;
+        self.shared_file_count = files;
+        if self.is_logged_in() {
+            self.send_message(MessageFactory::build_shared_folders_message(
+                folders, files,
+            ));
+        }
+    }
+
     fn handle_get_peer_address_response(
-        &self,
+        &mut self,
         username: String,
         host: String,
         port: u32,
@@ -549,6 +946,18 @@ impl ServerActor {
             username, host, port, obfuscation_type, obfuscated_port
         );
 
+        let waiters = self.address_waiters.remove(&username);
+        let answered = waiters.is_some();
+        for reply in waiters.into_iter().flatten() {
+            reply.reply(PeerAddress::new(host.clone(), port as u16));
+        }
+        // Answer only the callers that asked, unless a `GetPeerAddress`
+        // wants the client to connect too.
+        let forwarded = self.forwarded_lookups.remove(&username);
+        if answered && !forwarded {
+            return;
+        }
+
         if let Err(e) =
             self.client_channel
                 .send(ClientOperation::GetPeerAddressResponse {
@@ -583,43 +992,13 @@ impl ServerActor {
         &mut self,
         username: String,
         password: String,
-        response: std::sync::mpsc::Sender<Result<bool, SoulseekRs>>,
+        reply: ReplyTo<Result<LoginOutcome, SoulseekRs>>,
     ) {
+        self.credentials = Some((username.clone(), password.clone()));
         self.queue_message(MessageFactory::build_login_message(
             &username, &password,
         ));
-
-        let start = std::time::Instant::now();
-        let timeout = Duration::from_secs(5);
-
-        let context = self.context.clone();
-        std::thread::spawn(move || {
-            loop {
-                if start.elapsed() >= timeout {
-                    let _ = response.send(Err(SoulseekRs::Timeout));
-                    break;
-                }
-
-                let logged_in = match context.read_safe() {
-                    Ok(ctx) => ctx.logged_in,
-                    Err(e) => {
-                        let _ = response.send(Err(e));
-                        break;
-                    }
-                };
-                if let Some(logged_in) = logged_in {
-                    let result = if logged_in {
-                        Ok(true)
-                    } else {
-                        Err(SoulseekRs::AuthenticationFailed)
-                    };
-                    let _ = response.send(result);
-                    break;
-                }
-
-                std::thread::sleep(Duration::from_millis(100));
-            }
-        });
+        self.pending_login = Some(reply);
     }
 
     fn handle_file_search_request(
@@ -644,16 +1023,33 @@ impl ServerActor {
             self.extract_and_process_messages();
         }
 
-        {
+        // Read until the socket would block, so one wake-up drains it.
+        loop {
             let Some(stream) = self.stream.as_mut() else {
                 return;
             };
 
+            let buffered = self.reader.buffer_len();
             match self.reader.read_from_socket(stream) {
-                Ok(()) => {}
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Ok(()) if self.reader.buffer_len() > buffered => {
+                    self.last_frame_at = Instant::now();
+                    self.probe_sent = false;
+                }
+                Ok(()) => {
+                    if self.readiness.is_some() {
+                        // Readable with nothing to read: the server closed.
+                        // What it sent before closing, like Relogged, still
+                        // counts.
+                        self.extract_and_process_messages();
+                        self.connection_lost(None);
+                        return;
+                    }
+                    break;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
                 Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
                     debug!("[server] Read operation timed out",);
+                    break;
                 }
                 Err(e) => {
                     error!(
@@ -675,14 +1071,13 @@ impl ServerActor {
             match self.reader.extract_message() {
                 Ok(Some(mut message)) => {
                     extracted_count += 1;
+                    let code = message.get_message_code_u32();
+                    self.metrics.server_message_received(code);
                     trace!(
                         "[server] ← Message #{}: {:?}",
                         extracted_count,
                         message
-                            .get_message_name(
-                                MessageType::Server,
-                                u32::from(message.get_message_code())
-                            )
+                            .get_message_name(MessageType::Server, code)
                             .map_err(|e| e.to_string())
                     );
                     if let Some(ref dispatcher) = self.dispatcher {
@@ -717,6 +1112,27 @@ impl ServerActor {
         }
     }
 
+    /// Record `state` for callers and report the change, if it is one.
+    fn publish_state(&self, state: ConnectionState) {
+        match self.public_state.write_safe() {
+            Ok(mut current) if *current != state => {
+                current.clone_from(&state);
+            }
+            Ok(_) => return,
+            Err(e) => error!("[server] connection state write: {}", e),
+        }
+        // The client may already be gone when we stop.
+        let _ = self.client_channel.send(ClientOperation::Event(
+            ClientEvent::ConnectionStateChanged(state),
+        ));
+    }
+
+    fn forward_client_operation(&self, operation: ClientOperation) {
+        if let Err(e) = self.client_channel.send(operation) {
+            error!("[server] Error forwarding to client: {}", e);
+        }
+    }
+
     fn queue_message(&mut self, message: Message) {
         if let Some(sender) = &self.dispatcher_sender {
             match sender.send(ServerMessage::SendMessage(message)) {
@@ -730,20 +1146,36 @@ impl ServerActor {
     }
 
     fn send_message(&mut self, message: Message) {
+        if let Some(message) = self.limiter.admit(message) {
+            self.write_message(message);
+        }
+    }
+
+    /// Write whatever the rate limiter has released since the last tick.
+    fn flush_deferred_messages(&mut self) {
+        for message in self.limiter.drain_ready() {
+            self.write_message(message);
+        }
+    }
+
+    fn write_message(&mut self, mut message: Message) {
+        if let Some(dispatcher) = &self.dispatcher
+            && dispatcher.outbound(&mut message) == Verdict::Drop
+        {
+            return;
+        }
         let Some(stream) = self.stream.as_mut() else {
             error!("[server] Cannot send message: stream is None");
             return;
         };
 
+        let code = u32::from_le_bytes(
+            message.slice(0, 4).try_into().unwrap_or_default(),
+        );
         trace!(
             "[server] ➡ {:?}",
             message
-                .get_message_name(
-                    MessageType::Server,
-                    u32::from_le_bytes(
-                        message.get_slice(0, 4).try_into().unwrap_or_default()
-                    )
-                )
+                .get_message_name(MessageType::Server, code)
                 .map_err(|e| e.to_string())
         );
 
@@ -752,6 +1184,7 @@ impl ServerActor {
             self.disconnect_with_error(e);
             return;
         }
+        self.metrics.server_message_sent(code);
 
         if let Err(e) = stream.flush() {
             error!("[server] Error flushing stream: {}. Disconnecting.", e);
@@ -759,21 +1192,95 @@ impl ServerActor {
         }
     }
 
-    fn disconnect_with_error(&mut self, _error: Error) {
+    fn disconnect_with_error(&mut self, error: Error) {
+        self.connection_lost(Some(error.to_string()));
+    }
+
+    /// The connection dropped, for `reason` or because the server closed it.
+    fn connection_lost(&mut self, reason: Option<String>) {
         debug!("[server] disconnect");
+        let cause = reason
+            .clone()
+            .unwrap_or_else(|| "closed by the server".into());
+        self.publish_state(ConnectionState::Disconnected { reason });
 
+        self.readiness = None;
         self.stream.take();
+        self.limiter.clear();
+        // Once logged in, a lost connection is recovered rather than left
+        // for dead; until then failures surface to the caller of login.
+        if self.credentials.is_some() {
+            warn!(
+                "[server] Connection lost ({}); reconnecting in {:?}",
+                cause, RECONNECT_DELAY
+            );
+            self.connection_state = SocketState::Disconnected;
+            self.reconnect_at = Some(Instant::now() + RECONNECT_DELAY);
+        }
+    }
+
+    /// Open a fresh connection and queue a login ahead of anything that
+    /// piled up while we were disconnected.
+    fn reconnect(&mut self) {
+        self.reconnect_at = None;
+        self.reader = MessageReader::new().with_max_size(self.max_message_size);
+        if let Some((username, password)) = &self.credentials {
+            self.queued_messages.insert(
+                0,
+                ServerMessage::SendMessage(
+                    MessageFactory::build_login_message(username, password),
+                ),
+            );
+        }
+        info!("[server] Reconnecting to {}", self.address.host);
+        self.initiate_connection();
+    }
+
+    fn check_keepalive(&mut self) {
+        let action = keepalive_action(
+            self.last_frame_at.elapsed(),
+            self.last_ping_at.elapsed(),
+            self.probe_sent,
+            self.keepalive_interval,
+            self.silence_timeout,
+        );
+        match action {
+            KeepaliveAction::Nothing => {}
+            KeepaliveAction::Ping => {
+                self.last_ping_at = Instant::now();
+                self.send_message(MessageFactory::build_server_ping());
+            }
+            KeepaliveAction::Probe => {
+                self.probe_sent = true;
+                if let Some((username, _)) = &self.credentials {
+                    let probe = MessageFactory::build_get_user_status(username);
+                    self.send_message(probe);
+                }
+            }
+            KeepaliveAction::Dead => {
+                warn!(
+                    "[server] Nothing received for {:?}; treating the connection as dead",
+                    self.silence_timeout
+                );
+                self.disconnect_with_error(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "server went silent",
+                ));
+            }
+        }
     }
 
     fn disconnect(&mut self) {
         debug!("[server] disconnected");
+        self.publish_state(ConnectionState::Disconnected { reason: None });
 
+        self.readiness = None;
         self.stream.take();
+        self.limiter.clear();
     }
 
     fn check_connection_status(&mut self) {
-        let ConnectionState::Connecting { since } = self.connection_state
-        else {
+        let SocketState::Connecting { since } = self.connection_state else {
             return;
         };
 
@@ -792,7 +1299,7 @@ impl ServerActor {
 
         match stream.peer_addr() {
             Ok(_) => {
-                self.connection_state = ConnectionState::Connected;
+                self.connection_state = SocketState::Connected;
                 self.on_connection_established();
             }
             Err(ref e) if e.kind() == io::ErrorKind::NotConnected => {}
@@ -808,7 +1315,11 @@ impl ServerActor {
             panic!("Stream should be available here")
         };
 
+        self.publish_state(ConnectionState::Connected);
         self.initialize_dispatcher();
+        self.last_frame_at = Instant::now();
+        self.last_ping_at = Instant::now();
+        self.probe_sent = false;
 
         let queued = std::mem::take(&mut self.queued_messages);
         for msg in queued {
@@ -816,6 +1327,9 @@ impl ServerActor {
         }
 
         if let Some(ref handle) = self.self_handle {
+            if let Some(ref stream) = self.stream {
+                self.readiness = reactor::register(stream, handle.waker());
+            }
             handle.send(ServerMessage::ProcessRead).ok();
         }
 
@@ -830,11 +1344,19 @@ impl Actor for ServerActor {
         self.handle_message(msg);
     }
 
+    fn name(&self) -> String {
+        "server".to_string()
+    }
+
+    fn mailbox(&self) -> Mailbox {
+        self.mailbox
+    }
+
     fn on_start(&mut self) {
         if self.stream.is_none() {
             self.initiate_connection();
         } else {
-            self.connection_state = ConnectionState::Connected;
+            self.connection_state = SocketState::Connected;
             self.on_connection_established();
         }
     }
@@ -846,23 +1368,62 @@ impl Actor for ServerActor {
 
     fn tick(&mut self) {
         match self.connection_state {
-            ConnectionState::Connecting { .. } => {
+            SocketState::Connecting { .. } => {
                 self.check_connection_status();
             }
-            ConnectionState::Connected => {
+            SocketState::Connected => {
                 if self.stream.is_some() {
-                    self.process_read();
+                    // Requests queued from here, like a search, otherwise
+                    // wait for the server to send us something.
+                    self.process_dispatcher_messages();
+                    self.flush_deferred_messages();
+                    if self.readiness.is_none() {
+                        self.process_read();
+                    }
+                }
+                if self.stream.is_some() {
+                    self.check_keepalive();
+                }
+            }
+            SocketState::Disconnected => {
+                if self.reconnect_at.is_some_and(|at| Instant::now() >= at) {
+                    self.reconnect();
                 }
             }
-            ConnectionState::Disconnected => {}
+        }
+    }
+
+    fn on_ready(&mut self) {
+        self.process_read();
+        if let Some(ref readiness) = self.readiness {
+            readiness.arm();
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::post_login_messages;
+    use super::{
+        KeepaliveAction, UserStatus, keepalive_action, post_login_messages,
+    };
     use crate::message::Message;
+    use std::time::Duration;
+
+    #[test]
+    fn keepalive_pings_probes_then_declares_the_connection_dead() {
+        let interval = Duration::from_mins(1);
+        let timeout = Duration::from_mins(5);
+        let secs = Duration::from_secs;
+        let action = |silent, since_ping, probe_sent| {
+            keepalive_action(silent, since_ping, probe_sent, interval, timeout)
+        };
+        assert_eq!(action(secs(10), secs(10), false), KeepaliveAction::Nothing);
+        assert_eq!(action(secs(10), secs(60), false), KeepaliveAction::Ping);
+        assert_eq!(action(secs(150), secs(10), false), KeepaliveAction::Probe);
+        // The probe goes out once per silent stretch.
+        assert_eq!(action(secs(150), secs(10), true), KeepaliveAction::Nothing);
+        assert_eq!(action(secs(300), secs(10), true), KeepaliveAction::Dead);
+    }
 
     fn code_of(message: &Message) -> u32 {
         u32::from_le_bytes(message.get_data()[0..4].try_into().unwrap())
@@ -870,7 +1431,7 @@ mod tests {
 
     #[test]
     fn post_login_messages_carry_counts_and_conditional_wait_port() {
-        let messages = post_login_messages(true, 4321, 3, 7);
+        let messages = post_login_messages(true, 4321, 3, 7, UserStatus::Away);
         let codes: Vec<u32> = messages.iter().map(code_of).collect();
         // SharedFolders, HaveNoParent, SetStatus, SetWaitPort.
         assert_eq!(codes, vec![35, 71, 28, 2]);
@@ -879,10 +1440,111 @@ mod tests {
         let shared = messages[0].get_data();
         assert_eq!(u32::from_le_bytes(shared[4..8].try_into().unwrap()), 3);
         assert_eq!(u32::from_le_bytes(shared[8..12].try_into().unwrap()), 7);
+        // The SetStatus message carries the status we last asked for.
+        let status = messages[2].get_data();
+        assert_eq!(u32::from_le_bytes(status[4..8].try_into().unwrap()), 1);
 
         // Not listening omits SetWaitPort (code 2).
-        let no_listen = post_login_messages(false, 4321, 3, 7);
+        let no_listen =
+            post_login_messages(false, 4321, 3, 7, UserStatus::Online);
         let codes: Vec<u32> = no_listen.iter().map(code_of).collect();
         assert_eq!(codes, vec![35, 71, 28]);
     }
+
+    #[test]
+    fn state_changes_are_published_once() {
+        use super::{
+            ClientEvent, ClientOperation, ConnectionState, PeerAddress,
+        };
+        use std::sync::mpsc;
+
+        let (sender, receiver) = mpsc::channel();
+        let actor = super::ServerActor::new(
+            PeerAddress::new("localhost".to_string(), 2242),
+            sender,
+            2234,
+            false,
+            0,
+            0,
+        );
+        actor.publish_state(ConnectionState::Connecting);
+        actor.publish_state(ConnectionState::Connecting);
+        actor.publish_state(ConnectionState::LoggedIn);
+
+        let changes: Vec<ConnectionState> = receiver
+            .try_iter()
+            .filter_map(|op| match op {
+                ClientOperation::Event(
+                    ClientEvent::ConnectionStateChanged(state),
+                ) => Some(state),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [ConnectionState::Connecting, ConnectionState::LoggedIn]
+        );
+        assert_eq!(
+            *actor.public_state.read().unwrap(),
+            ConnectionState::LoggedIn
+        );
+    }
+
+    #[test]
+    fn shared_counts_apply_while_disconnected() {
+        use super::{PeerAddress, ServerMessage};
+        use std::sync::mpsc;
+
+        let (sender, _receiver) = mpsc::channel();
+        let mut actor = super::ServerActor::new(
+            PeerAddress::new("localhost".to_string(), 2242),
+            sender,
+            2234,
+            false,
+            1,
+            2,
+        );
+        actor.handle_message(ServerMessage::SetSharedCounts {
+            folders: 3,
+            files: 7,
+        });
+        // Nothing is queued; the next login's handshake carries them.
+        assert!(actor.queued_messages.is_empty());
+        assert_eq!(
+            (actor.shared_folder_count, actor.shared_file_count),
+            (3, 7)
+        );
+    }
+
+    #[test]
+    fn relogged_stays_disconnected_unless_asked_to_reconnect() {
+        use super::{ClientOperation, PeerAddress, ServerMessage, SocketState};
+        use std::sync::mpsc;
+
+        for reconnect in [false, true] {
+            let (sender, receiver) = mpsc::channel();
+            let mut actor = super::ServerActor::new(
+                PeerAddress::new("localhost".to_string(), 2242),
+                sender,
+                2234,
+                false,
+                0,
+                0,
+            )
+            .with_relogged_reconnect(reconnect);
+            actor.credentials = Some(("me".into(), "pw".into()));
+            actor.connection_state = SocketState::Connected;
+
+            actor.handle_message(ServerMessage::Relogged);
+            // The server closes the connection right after.
+            actor.disconnect_with_error(std::io::ErrorKind::BrokenPipe.into());
+
+            assert_eq!(actor.reconnect_at.is_some(), reconnect);
+            assert!(
+                receiver
+                    .try_iter()
+                    .any(|op| matches!(op, ClientOperation::Relogged))
+            );
+        }
+    }
 }
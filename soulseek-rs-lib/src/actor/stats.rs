@@ -0,0 +1,98 @@
+//! Per-actor counters, read through [`ActorSystem::stats`].
+//!
+//! [`ActorSystem::stats`]: super::ActorSystem::stats
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::mailbox::MailboxProbe;
+use crate::utils::lock::MutexExt;
+
+/// A snapshot of one running actor.
+#[derive(Debug, Clone)]
+pub struct ActorStats {
+    /// Unique for the life of the actor system, in spawn order.
+    pub id: u64,
+    /// What the actor is, e.g. `server` or `peer:alice`.
+    pub name: String,
+    /// User messages waiting in the mailbox.
+    pub mailbox_depth: usize,
+    /// User messages dropped because a bounded mailbox was full.
+    pub dropped_messages: u64,
+    pub messages_processed: u64,
+    pub ticks: u64,
+    /// When the actor last started handling a message or a tick.
+    pub last_activity: Instant,
+}
+
+impl ActorStats {
+    /// How long the actor has gone without handling anything. A large value
+    /// with a non-empty mailbox means the actor is stuck.
+    #[must_use]
+    pub fn idle(&self) -> Duration {
+        self.last_activity.elapsed()
+    }
+}
+
+/// The live counters behind an [`ActorStats`], updated by the actor loop.
+pub struct Probe {
+    id: u64,
+    name: String,
+    mailbox: Arc<dyn MailboxProbe>,
+    messages_processed: AtomicU64,
+    ticks: AtomicU64,
+    last_activity: Mutex<Instant>,
+}
+
+impl Probe {
+    pub fn new(id: u64, name: String, mailbox: Arc<dyn MailboxProbe>) -> Self {
+        Self {
+            id,
+            name,
+            mailbox,
+            messages_processed: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            last_activity: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn message(&self) {
+        self.messages_processed.fetch_add(1, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn tick(&self) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn touch(&self) {
+        if let Ok(mut last) = self.last_activity.lock_safe() {
+            *last = Instant::now();
+        }
+    }
+
+    pub fn messages_processed(&self) -> u64 {
+        self.messages_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> ActorStats {
+        ActorStats {
+            id: self.id,
+            name: self.name.clone(),
+            mailbox_depth: self.mailbox.depth(),
+            dropped_messages: self.mailbox.dropped(),
+            messages_processed: self.messages_processed(),
+            ticks: self.ticks(),
+            last_activity: self
+                .last_activity
+                .lock_safe()
+                .map_or_else(|_| Instant::now(), |last| *last),
+        }
+    }
+}
@@ -1,9 +1,17 @@
 use super::{
-    Arc, Client, ClientContext, ClientOperation, ConnectionType, DownloadPeer,
-    DownloadStatus, Listen, Peer, PeerRegistry, Receiver, Result, RwLock,
-    RwLockExt, Sender, ServerActor, ServerMessage, Shares, SoulseekRs,
-    TcpStream, debug, error, info, mpsc, thread, trace,
+    Arc, AtomicBool, Client, ClientContext, ClientOperation, ConnectionType,
+    DownloadPeer, DownloadStatus, Duration, Instant, Listen, Ordering, Peer,
+    PeerRegistry, PeerStream, Receiver, Result, RwLock, RwLockExt, Sender,
+    ServerActor, ServerMessage, Shares, SoulseekRs, TcpStream, debug, error,
+    info, mpsc, scan_shares, share_refresh::ShareRefresh, thread, trace, warn,
 };
+use crate::PeerAddress;
+use crate::peer::DownloadError;
+use crate::types::LoginOutcome;
+use std::net::{Ipv4Addr, SocketAddr};
+
+/// How long [`Client::shutdown`] waits for the actors to stop.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 impl Client {
     pub fn connect(&mut self) -> Result<()> {
@@ -12,13 +20,21 @@ impl Client {
             Receiver<ClientOperation>,
         ) = mpsc::channel();
 
+        self.readiness.connecting();
         let mut ctx = self.context.write_safe()?;
         ctx.sender = Some(sender.clone());
         let peer_registry = PeerRegistry::new(
             ctx.actor_system.clone(),
             sender.clone(),
             self.username.clone(),
-        );
+        )
+        .with_peer_trace(self.peer_trace.clone())
+        .with_ignore_list(self.ignore_list.clone())
+        .with_metrics(ctx.metrics.clone())
+        .with_custom_handlers(self.peer_handlers.clone())
+        .with_mailbox(self.peer_mailbox)
+        .with_max_message_size(ctx.message_size_limits.peer)
+        .with_peer_tls(ctx.peer_tls.clone());
         ctx.peer_registry = Some(peer_registry);
 
         let listen_sender = sender.clone();
@@ -34,7 +50,11 @@ impl Client {
         let shares = if roots.is_empty() {
             Arc::new(Shares::empty())
         } else {
-            let scanned = Shares::scan_many(&roots);
+            let scanned = scan_shares(
+                &roots,
+                &ctx.shares,
+                self.share_index_file.as_deref(),
+            );
             info!(
                 "Sharing {} files in {} folders from {} directories",
                 scanned.file_count(),
@@ -55,7 +75,16 @@ impl Client {
             self.enable_listen,
             shared_folder_count,
             shared_file_count,
-        );
+        )
+        .with_tls(self.tls.clone())
+        .with_send_limit(self.server_send_rate, self.server_send_stats.clone())
+        .with_keepalive(self.keepalive_interval, self.server_silence_timeout)
+        .with_relogged_reconnect(self.reconnect_when_relogged)
+        .with_max_message_size(ctx.message_size_limits.server)
+        .with_custom_handlers(self.server_handlers.clone())
+        .with_metrics(ctx.metrics.clone())
+        .with_mailbox(self.server_mailbox)
+        .with_connection_state(self.connection_state.clone());
 
         self.server_handle = Some(ctx.actor_system.spawn_with_handle(
             server_actor,
@@ -64,48 +93,144 @@ impl Client {
             },
         ));
 
+        if let Some(server) = self.server_handle.clone() {
+            let stop = Arc::new(AtomicBool::new(false));
+            self.stop_share_refresh = stop.clone();
+            let refresh = ShareRefresh {
+                context: self.context.clone(),
+                server,
+                index_file: self.share_index_file.clone(),
+                rescan_interval: self.share_rescan_interval,
+                stop,
+            };
+            self.threads.push(thread::spawn(move || refresh.run()));
+        }
+
         if self.enable_listen {
             let listen_port = self.listen_port;
             let client_sender = listen_sender;
             let context = self.context.clone();
             let own_username = self.username.clone();
+            let readiness = self.readiness.clone();
+            let stop = Arc::new(AtomicBool::new(false));
+            self.stop_listener = stop.clone();
 
-            thread::spawn(move || {
+            self.threads.push(thread::spawn(move || {
                 Listen::start(
                     listen_port,
                     client_sender,
                     context,
                     own_username,
+                    &readiness,
+                    &stop,
                 );
-            });
+            }));
         }
 
-        Self::listen_to_client_operations(
+        self.threads.push(Self::listen_to_client_operations(
             message_reader,
             self.context.clone(),
             self.username.clone(),
-        );
+        ));
+        // The registry is in the context and the loop owns its receiver, so
+        // anything sent to it from here on is handled.
+        drop(ctx);
+        self.readiness.operations_ready();
 
         Ok(())
     }
 
-    pub fn login(&self) -> Result<bool> {
-        info!("Logging in as {}", self.username);
-        if let Some(handle) = &self.server_handle {
-            let (tx, rx) = std::sync::mpsc::channel();
-            let _ = handle.send(ServerMessage::Login {
-                username: self.username.clone(),
-                password: self.password.clone(),
-                response: tx,
-            });
+    /// Stop the server and peer actors, the listener and the operations
+    /// loop, and join the listener and loop threads. Unfinished downloads
+    /// are saved to [`ClientSettings::download_queue_file`], if set, then
+    /// fail with "client shut down", and active uploads are cancelled;
+    /// transfers already streaming stop on their own once their socket
+    /// errors. Waits up to five seconds for the actors to stop.
+    ///
+    /// Safe to call more than once; dropping the client calls it too.
+    pub fn shutdown(&mut self) {
+        if let Some(handle) = self.server_handle.take() {
+            let _ = handle.stop();
+        }
+        if let Some(file) = self.download_queue_file.take()
+            && let Err(e) = self.save_downloads(&file)
+        {
+            error!(
+                "[client] cannot save the download queue to {}: {}",
+                file.display(),
+                e
+            );
+        }
+        let (sender, registry) = match self.context.write_safe() {
+            Ok(mut ctx) => {
+                ctx.stop_transfers("client shut down");
+                let _ = ctx.leave_distributed();
+                (ctx.sender.take(), ctx.peer_registry.take())
+            }
+            Err(e) => {
+                error!("[client] shutdown: {}", e);
+                (None, None)
+            }
+        };
+        if let Some(registry) = registry {
+            registry.stop_all();
+        }
+        if let Some(sender) = sender {
+            let _ = sender.send(ClientOperation::Shutdown);
+        }
+        self.stop_share_refresh.store(true, Ordering::Release);
+        if !self.stop_listener.swap(true, Ordering::AcqRel)
+            && self.enable_listen
+            && !self.threads.is_empty()
+        {
+            // Wake the listener out of `accept` so it sees the flag.
+            let _ = TcpStream::connect_timeout(
+                &SocketAddr::from((Ipv4Addr::LOCALHOST, self.listen_port)),
+                Duration::from_secs(1),
+            );
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
 
-            match rx.recv() {
-                Ok(result) => result,
-                Err(_) => Err(SoulseekRs::Timeout),
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while self
+            .context
+            .read_safe()
+            .is_ok_and(|ctx| !ctx.actor_system.stats().is_empty())
+        {
+            if Instant::now() >= deadline {
+                warn!(
+                    "[client] actors still running after {:?}",
+                    SHUTDOWN_TIMEOUT
+                );
+                break;
             }
-        } else {
-            Err(SoulseekRs::NotConnected)
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Log in with our credentials and wait for the server's answer.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`] if the client is not connected.
+    /// A refusal is not an error: it comes back as
+    /// [`LoginOutcome::Rejected`] with the server's reason.
+    pub fn login(&self) -> Result<LoginOutcome> {
+        info!("Logging in as {}", self.username);
+        let handle = self
+            .server_handle
+            .as_ref()
+            .ok_or(SoulseekRs::NotConnected)?;
+        let outcome = handle.ask(|reply| ServerMessage::Login {
+            username: self.username.clone(),
+            password: self.password.clone(),
+            reply,
+        })??;
+        if outcome.is_accepted() {
+            self.readiness.logged_in();
         }
+        Ok(outcome)
     }
 
     /// Ask the server for a peer's address and open a direct control
@@ -125,6 +250,33 @@ impl Client {
         Ok(())
     }
 
+    /// Ask the server where `username` accepts peer connections, without
+    /// connecting to them: to tell whether a user is online and reachable
+    /// before queueing anything from them.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`] if the client is not connected,
+    /// [`SoulseekRs::Timeout`] if the server doesn't answer in time and
+    /// [`SoulseekRs::PeerUnreachable`] if it has no address for them, as for
+    /// users who are offline.
+    pub fn get_peer_address(&self, username: &str) -> Result<PeerAddress> {
+        let handle = self
+            .server_handle
+            .as_ref()
+            .ok_or(SoulseekRs::NotConnected)?;
+        let address =
+            handle.ask(|reply| ServerMessage::ResolvePeerAddress {
+                username: username.to_string(),
+                reply,
+            })?;
+        if address.get_port() == 0 {
+            return Err(SoulseekRs::PeerUnreachable {
+                username: username.to_string(),
+            });
+        }
+        Ok(address)
+    }
+
     #[allow(dead_code)]
     pub fn remove_peer(&self, username: &str) {
         let context = match self.context.read_safe() {
@@ -145,7 +297,7 @@ impl Client {
         peer: Peer,
         client_context: Arc<RwLock<ClientContext>>,
         own_username: String,
-        stream: Option<TcpStream>,
+        stream: Option<PeerStream>,
     ) {
         let client_context = client_context;
 
@@ -170,7 +322,7 @@ impl Client {
                         Ok(_) => (),
                         Err(e) => {
                             trace!(
-                                "Failed to spawn peer actor for {:?}: {:?}",
+                                "Failed to spawn peer actor for {:?}: {}",
                                 username, e
                             );
                         }
@@ -192,8 +344,9 @@ impl Client {
                     );
                     return;
                 };
+                let username = peer.username;
                 let download_peer = DownloadPeer::new(
-                    peer.username,
+                    username.clone(),
                     peer.host,
                     peer.port,
                     token,
@@ -206,16 +359,19 @@ impl Client {
                     None,
                     None,
                 ) {
-                    Ok((download, filename)) => {
+                    Ok((download, summary)) => {
                         trace!(
-                            "[client] downloaded {} bytes {:?} ",
-                            filename, download.size
+                            "[client] downloaded {} bytes to {} in {:?}",
+                            download.size,
+                            summary.path.display(),
+                            summary.elapsed
                         );
-                        let _ = download.sender.send(DownloadStatus::Completed);
+                        let status = DownloadStatus::Completed(Some(summary));
+                        let _ = download.sender.send(status.clone());
                         match client_context.write_safe() {
                             Ok(mut ctx) => ctx.update_download_with_status(
                                 download.token,
-                                DownloadStatus::Completed,
+                                status,
                             ),
                             Err(e) => error!(
                                 "[client] connect_to_peer F write: {}",
@@ -223,6 +379,16 @@ impl Client {
                             ),
                         }
                     }
+                    Err(DownloadError::ConnectionFailed(e)) => {
+                        // We were asked to connect back and could not.
+                        trace!(
+                            "[client] cannot connect to {}: {}",
+                            username, e
+                        );
+                        if let Ok(ctx) = client_context.read_safe() {
+                            ctx.report_cant_connect(token, &username);
+                        }
+                    }
                     Err(e) => {
                         trace!("[client] failed to download: {}", e);
                     }
@@ -0,0 +1,159 @@
+//! Joining the distributed search network as a child.
+//!
+//! While we have no parent the server sends us candidates now and then. One
+//! thread ([`crate::peer::parent`]) tries them in order; once a parent takes
+//! us we tell the server our branch (one below the parent's, same root) and
+//! answer the searches it relays. When the parent goes, so does our branch,
+//! and the server sends new candidates.
+
+use super::{
+    Arc, AtomicBool, Client, ClientContext, Ordering, RwLockExt, ServerMessage,
+    debug, info, thread,
+};
+use crate::message::Message;
+use crate::message::server::{MessageFactory, ParentCandidate};
+use crate::peer::parent;
+
+/// The parent we joined the distributed network under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistributedParent {
+    pub username: String,
+    /// Our depth in the network: one below the parent's.
+    pub branch_level: u32,
+    /// The user at the top of our branch.
+    pub branch_root: String,
+}
+
+/// The parent search in progress, if any, and the parent it found.
+#[derive(Debug, Default)]
+pub struct ParentLink {
+    /// Numbers each search, so reports from a cancelled one are ignored.
+    attempt: u64,
+    /// Set while a search or parent connection runs; storing `true` ends it.
+    stop: Option<Arc<AtomicBool>>,
+    parent: Option<DistributedParent>,
+}
+
+impl ClientContext {
+    /// Try `candidates` as our parent, unless a search or parent connection
+    /// is already running.
+    pub fn parent_candidates(
+        &mut self,
+        candidates: Vec<ParentCandidate>,
+        own_username: &str,
+    ) {
+        if self.parent_link.stop.is_some() || candidates.is_empty() {
+            return;
+        }
+        let Some(sender) = self.sender.clone() else {
+            return;
+        };
+        debug!("[client] trying {} possible parents", candidates.len());
+        self.parent_link.attempt += 1;
+        let attempt = self.parent_link.attempt;
+        let stop = Arc::new(AtomicBool::new(false));
+        self.parent_link.stop = Some(stop.clone());
+        let own_username = own_username.to_string();
+        let max_message_size = self.message_size_limits.distributed;
+        let tls = self.peer_tls.clone();
+        thread::spawn(move || {
+            parent::join(
+                attempt,
+                candidates,
+                &own_username,
+                max_message_size,
+                tls.as_deref(),
+                &sender,
+                &stop,
+            );
+        });
+    }
+
+    /// A parent told us its branch: advertise ours.
+    pub fn parent_branch(
+        &mut self,
+        attempt: u64,
+        username: String,
+        level: u32,
+        root: String,
+    ) {
+        if attempt != self.parent_link.attempt {
+            return;
+        }
+        if self.parent_link.parent.is_none() {
+            info!("Joined the distributed network under {}", username);
+            self.send_to_server(MessageFactory::build_have_parent_message());
+        }
+        let branch_level = level.saturating_add(1);
+        self.send_to_server(MessageFactory::build_branch_level_message(
+            branch_level,
+        ));
+        self.send_to_server(MessageFactory::build_branch_root_message(&root));
+        self.parent_link.parent = Some(DistributedParent {
+            username,
+            branch_level,
+            branch_root: root,
+        });
+    }
+
+    /// The search ended, by finding no parent or by losing the one found.
+    pub fn parent_gone(&mut self, attempt: u64, own_username: &str) {
+        if attempt != self.parent_link.attempt {
+            return;
+        }
+        self.parent_link.stop = None;
+        if let Some(parent) = self.parent_link.parent.take() {
+            info!("Lost distributed parent {}", parent.username);
+            self.announce_no_parent(own_username);
+        }
+    }
+
+    /// The server reset our distributed connections: drop the parent and
+    /// wait for new candidates.
+    pub fn reset_distributed(&mut self, own_username: &str) {
+        if self.leave_distributed().is_some() {
+            self.announce_no_parent(own_username);
+        }
+    }
+
+    /// Stop any parent search or connection, returning the parent we had.
+    pub fn leave_distributed(&mut self) -> Option<DistributedParent> {
+        if let Some(stop) = self.parent_link.stop.take() {
+            stop.store(true, Ordering::Release);
+        }
+        // Whatever the stopped thread still reports is stale.
+        self.parent_link.attempt += 1;
+        self.parent_link.parent.take()
+    }
+
+    #[must_use]
+    pub const fn distributed_parent(&self) -> Option<&DistributedParent> {
+        self.parent_link.parent.as_ref()
+    }
+
+    fn announce_no_parent(&self, own_username: &str) {
+        self.send_to_server(MessageFactory::build_no_parent_message());
+        self.send_to_server(MessageFactory::build_branch_level_message(0));
+        self.send_to_server(MessageFactory::build_branch_root_message(
+            own_username,
+        ));
+    }
+
+    fn send_to_server(&self, message: Message) {
+        if let Some(server) = &self.server_sender {
+            let _ = server.send(ServerMessage::SendMessage(message));
+        }
+    }
+}
+
+impl Client {
+    /// The parent we joined the distributed network under, through which
+    /// other users' searches reach us. `None` while we have none.
+    #[must_use]
+    pub fn distributed_parent(&self) -> Option<DistributedParent> {
+        self.context
+            .read_safe()
+            .ok()
+            .and_then(|ctx| ctx.distributed_parent().cloned())
+    }
+}
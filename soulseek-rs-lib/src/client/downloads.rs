@@ -1,9 +1,156 @@
+use super::sources::base_name;
 use super::{
-    Arc, Client, ClientContext, Download, DownloadMetadata, DownloadStatus,
-    Receiver, Result, RwLock, RwLockExt, Sender, error, info, md5, mpsc,
+    ActorHandle, Arc, Client, ClientContext, Download, DownloadMetadata,
+    DownloadStatus, Duration, Receiver, Result, RwLock, RwLockExt, Sender,
+    ServerMessage, SoulseekRs, TokenOwner, debug, error, info, mpsc, thread,
+    warn,
 };
+use crate::download_history::HistoryEntry;
+use crate::download_queue::{self, QueuedDownload};
+use crate::message::server::MessageFactory;
+use crate::types::{FailureReason, File};
+use crate::utils::lock::MutexExt;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::{RecvTimeoutError, SyncSender};
+
+/// Collects a [`Client::download_to_vec`] transfer, refusing to grow past
+/// the cap whatever size the peer announced.
+struct CappedBuffer {
+    buffer: Arc<Mutex<Vec<u8>>>,
+    max_size: u64,
+}
+
+impl Write for CappedBuffer {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut buffer = self.buffer.lock_safe().map_err(io::Error::other)?;
+        if (buffer.len() + data.len()) as u64 > self.max_size {
+            return Err(io::Error::other("download is over the size cap"));
+        }
+        buffer.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Chunks a [`DownloadStream`] may fall behind the peer before the
+/// transfer waits for the reader.
+const STREAM_CHUNKS_BUFFERED: usize = 64;
+
+/// How often a waiting [`DownloadStream`] checks whether its download
+/// ended without closing the stream, e.g. because it was removed.
+const STREAM_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Hands a transfer's chunks to the [`DownloadStream`] reading it.
+struct ChunkSender(SyncSender<Vec<u8>>);
+
+impl Write for ChunkSender {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(data.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A download read as it arrives, from [`Client::download_stream`], e.g. to
+/// pipe a track into a player while it downloads.
+///
+/// Reads block until more of the transfer is in. The stream ends once the
+/// download completes and fails if the download fails or is removed first.
+/// Dropping it fails the download.
+pub struct DownloadStream {
+    chunks: Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+    context: Arc<RwLock<ClientContext>>,
+    token: u32,
+}
+
+impl DownloadStream {
+    /// What a read gets once no more chunks are coming: the end of the
+    /// stream if the download completed, an error otherwise.
+    fn end(&self) -> io::Result<usize> {
+        let status = self
+            .context
+            .read_safe()
+            .map_err(io::Error::other)?
+            .get_download_by_token(self.token)
+            .map(|download| download.status.clone());
+        match status {
+            Some(DownloadStatus::Completed(_)) => Ok(0),
+            Some(DownloadStatus::Failed(Some(reason))) => {
+                Err(io::Error::other(reason.to_string()))
+            }
+            Some(DownloadStatus::TimedOut) => {
+                Err(io::Error::from(io::ErrorKind::TimedOut))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the download did not complete",
+            )),
+        }
+    }
+
+    /// Whether the download is over, or gone.
+    fn finished(&self) -> bool {
+        self.context.read_safe().map_or(true, |context| {
+            context
+                .get_download_by_token(self.token)
+                .is_none_or(Download::is_finished)
+        })
+    }
+}
+
+impl Read for DownloadStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.position == self.chunk.len() {
+            match self.chunks.recv_timeout(STREAM_POLL_INTERVAL) {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    // The last chunks are sent before the download is
+                    // marked finished, so nothing more is coming.
+                    if self.finished() {
+                        match self.chunks.try_recv() {
+                            Ok(chunk) => {
+                                self.chunk = chunk;
+                                self.position = 0;
+                            }
+                            Err(_) => return self.end(),
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return self.end(),
+            }
+        }
+        let count = buf.len().min(self.chunk.len() - self.position);
+        buf[..count]
+            .copy_from_slice(&self.chunk[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// How long a source may go without any status update once its transfer
+/// started before [`Client::download_any`] gives up on it and tries the
+/// next one. A source still queued is waited on.
+const FAILOVER_STALL_TIMEOUT: Duration = Duration::from_mins(1);
 
 impl Client {
+    /// Every download, in priority order.
     #[must_use]
     pub fn get_all_downloads(&self) -> Vec<Download> {
         self.context
@@ -12,6 +159,35 @@ impl Client {
             .unwrap_or_default()
     }
 
+    /// Downloads that finished, oldest first, including those recorded in
+    /// [`ClientSettings::history_file`](super::ClientSettings::history_file)
+    /// by earlier runs.
+    #[must_use]
+    pub fn download_history(&self) -> Vec<HistoryEntry> {
+        self.context
+            .read_safe()
+            .map(|ctx| ctx.download_history().entries().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Whether `username` could be reached neither directly nor through the
+    /// server on the last attempt. Cleared once a connection succeeds.
+    #[must_use]
+    pub fn is_peer_unreachable(&self, username: &str) -> bool {
+        self.context
+            .read_safe()
+            .is_ok_and(|ctx| ctx.is_peer_unreachable(username))
+    }
+
+    /// Whether a file with this name and size was downloaded before, from
+    /// any user.
+    #[must_use]
+    pub fn has_downloaded(&self, filename: &str, size: u64) -> bool {
+        self.context.read_safe().is_ok_and(|ctx| {
+            ctx.download_history().has_downloaded(filename, size)
+        })
+    }
+
     #[must_use]
     pub fn pause_download(&self, username: &str, filename: &str) -> bool {
         match self.context.write_safe() {
@@ -34,6 +210,33 @@ impl Client {
         }
     }
 
+    /// Cancel the download of `filename` from `username`, whether queued,
+    /// paused or under way; a transfer stops at its next chunk. It stays
+    /// listed as failed with [`FailureReason::Aborted`] so it can be
+    /// retried. Returns whether there was an unfinished download to cancel.
+    #[must_use]
+    pub fn cancel_download(&self, username: &str, filename: &str) -> bool {
+        let mut ctx = match self.context.write_safe() {
+            Ok(ctx) => ctx,
+            Err(e) => {
+                error!("[client] cancel_download: {}", e);
+                return false;
+            }
+        };
+        let Some(download) = ctx
+            .downloads
+            .get_by_file(username, filename)
+            .filter(|download| !download.is_finished())
+            .cloned()
+        else {
+            return false;
+        };
+        let status = DownloadStatus::Failed(Some(FailureReason::Aborted));
+        let _ = download.sender.send(status.clone());
+        ctx.update_download_with_status(download.token, status);
+        true
+    }
+
     #[must_use]
     pub fn remove_queued_download(
         &self,
@@ -42,7 +245,10 @@ impl Client {
     ) -> bool {
         match self.context.write_safe() {
             Ok(mut ctx) => {
-                ctx.downloads.remove_queued_by_file(username, filename)
+                let removed =
+                    ctx.downloads.remove_queued_by_file(username, filename);
+                ctx.start_held_downloads();
+                removed
             }
             Err(e) => {
                 error!("[client] remove_queued_download: {}", e);
@@ -51,16 +257,77 @@ impl Client {
         }
     }
 
+    /// Ask `username` where our queued download of `filename` stands in their
+    /// upload queue. The reply arrives asynchronously; read it with
+    /// [`Client::download_queue_position`].
+    ///
+    /// # Errors
+    /// Returns an error if the client context is unavailable.
+    pub fn request_queue_position(
+        &self,
+        username: &str,
+        filename: &str,
+    ) -> Result<()> {
+        self.send_peer_request(
+            username,
+            MessageFactory::build_place_in_queue_request(filename),
+        )
+    }
+
+    /// The last queue position `username` reported for our download of
+    /// `filename`, if any.
+    #[must_use]
+    pub fn download_queue_position(
+        &self,
+        username: &str,
+        filename: &str,
+    ) -> Option<u32> {
+        self.context.read_safe().ok().and_then(|ctx| {
+            ctx.downloads
+                .get_by_file(username, filename)
+                .and_then(|download| download.queue_position)
+        })
+    }
+
+    /// Where the queued download `token` last stood in its uploader's queue,
+    /// asking them again for the next call. `None` until they answer, and
+    /// for downloads that are not queued.
+    #[must_use]
+    pub fn queue_position(&self, token: u32) -> Option<u32> {
+        let (username, filename, position) = {
+            let ctx = self.context.read_safe().ok()?;
+            let download = ctx.downloads.get_by_token(token)?;
+            if !matches!(download.status, DownloadStatus::Queued) {
+                return None;
+            }
+            (
+                download.username.clone(),
+                download.filename.clone(),
+                download.queue_position,
+            )
+        };
+        if let Err(e) = self.request_queue_position(&username, &filename) {
+            debug!("[client] queue_position {}: {}", token, e);
+        }
+        position
+    }
+
     /// Remove every download for `username`/`filename` regardless of status.
     /// Call this before re-issuing [`Client::download`] for a failed download,
-    /// otherwise the stale entry (whose md5-derived token equals the retry's)
-    /// shadows the fresh one and its completion is misrouted.
+    /// otherwise the stale entry shadows the fresh one wherever downloads are
+    /// looked up by file.
     ///
     /// Returns whether anything was removed.
     #[must_use]
     pub fn remove_download(&self, username: &str, filename: &str) -> bool {
         match self.context.write_safe() {
-            Ok(mut ctx) => ctx.downloads.remove_by_file(username, filename),
+            Ok(mut ctx) => {
+                ctx.download_writers
+                    .remove(&(username.to_string(), filename.to_string()));
+                let removed = ctx.downloads.remove_by_file(username, filename);
+                ctx.start_held_downloads();
+                removed
+            }
             Err(e) => {
                 error!("[client] remove_download: {}", e);
                 false
@@ -92,20 +359,352 @@ impl Client {
         download_directory: String,
         metadata: DownloadMetadata,
     ) -> Result<(Download, Receiver<DownloadStatus>)> {
-        info!("[client] Downloading {} from {}", filename, username);
+        Self::start_download(
+            &self.context,
+            self.server_handle.as_ref(),
+            filename,
+            username,
+            size,
+            download_directory,
+            metadata,
+        )
+    }
+
+    /// Download `filename` from `username` into `writer` rather than a
+    /// file, e.g. a pipe, an encrypted store or an HTTP response. The
+    /// transfer is written as it arrives. The conflict policy
+    /// and the free space check don't apply, and [`Client::save_downloads`]
+    /// skips the download, since a writer can't be restored.
+    ///
+    /// # Errors
+    /// As [`Client::download`].
+    pub fn download_to_writer(
+        &self,
+        filename: String,
+        username: String,
+        size: u64,
+        writer: impl Write + Send + 'static,
+    ) -> Result<(Download, Receiver<DownloadStatus>)> {
+        let key = (username.clone(), filename.clone());
+        self.context.write_safe()?.add_download_writer(
+            username.clone(),
+            filename.clone(),
+            writer,
+        );
+        Self::start_download(
+            &self.context,
+            self.server_handle.as_ref(),
+            filename,
+            username,
+            size,
+            String::new(),
+            DownloadMetadata::default(),
+        )
+        .inspect_err(|_| {
+            if let Ok(mut ctx) = self.context.write_safe() {
+                ctx.download_writers.remove(&key);
+            }
+        })
+    }
+
+    /// Download `filename` from `username` into memory and wait for it to
+    /// finish, for small files like cover art, logs or cue sheets. Files
+    /// over `max_size` bytes are refused before the peer is asked for them.
+    ///
+    /// # Errors
+    /// [`SoulseekRs::InvalidArgument`] if `size` is over `max_size`,
+    /// [`SoulseekRs::TransferRejected`] if the download fails,
+    /// [`SoulseekRs::Timeout`] if it times out, or as [`Client::download`].
+    pub fn download_to_vec(
+        &self,
+        filename: String,
+        username: String,
+        size: u64,
+        max_size: u64,
+    ) -> Result<Vec<u8>> {
+        if size > max_size {
+            return Err(SoulseekRs::InvalidArgument(format!(
+                "{filename} is {size} bytes, over the {max_size} byte cap"
+            )));
+        }
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let writer = CappedBuffer {
+            buffer: Arc::clone(&buffer),
+            max_size,
+        };
+        let (_, statuses) =
+            self.download_to_writer(filename, username, size, writer)?;
+        loop {
+            match statuses.recv() {
+                Ok(DownloadStatus::Completed(_)) => {
+                    return Ok(std::mem::take(&mut *buffer.lock_safe()?));
+                }
+                Ok(DownloadStatus::Failed(reason)) => {
+                    return Err(reason
+                        .unwrap_or_else(|| {
+                            FailureReason::Other("Download failed".into())
+                        })
+                        .into());
+                }
+                Ok(DownloadStatus::TimedOut) => {
+                    return Err(SoulseekRs::Timeout);
+                }
+                Ok(_) => {}
+                Err(_) => return Err(SoulseekRs::ConnectionClosed),
+            }
+        }
+    }
+
+    /// Download `filename` from `username`, read as it arrives through the
+    /// returned [`DownloadStream`] rather than saved, as with
+    /// [`Client::download_to_writer`].
+    ///
+    /// # Errors
+    /// As [`Client::download`].
+    pub fn download_stream(
+        &self,
+        filename: String,
+        username: String,
+        size: u64,
+    ) -> Result<(Download, Receiver<DownloadStatus>, DownloadStream)> {
+        let (sender, chunks) = mpsc::sync_channel(STREAM_CHUNKS_BUFFERED);
+        let (download, statuses) = self.download_to_writer(
+            filename,
+            username,
+            size,
+            ChunkSender(sender),
+        )?;
+        let stream = DownloadStream {
+            chunks,
+            chunk: Vec::new(),
+            position: 0,
+            context: Arc::clone(&self.context),
+            token: download.token,
+        };
+        Ok((download, statuses, stream))
+    }
+
+    /// Write the unfinished downloads to `file`, replacing what it held,
+    /// for [`Client::restore_downloads`]. Returns how many were saved.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::Io`] if `file` cannot be written.
+    pub fn save_downloads(&self, file: &std::path::Path) -> Result<usize> {
+        let entries: Vec<_> = {
+            let ctx = self.context.read_safe()?;
+            ctx.get_downloads()
+                .iter()
+                .filter(|download| !ctx.has_download_writer(download))
+                .filter_map(QueuedDownload::from_download)
+                .collect()
+        };
+        download_queue::save(file, &entries)
+            .map_err(SoulseekRs::io("save the download queue"))?;
+        Ok(entries.len())
+    }
+
+    /// Queue again the downloads [`Client::save_downloads`] wrote to
+    /// `file`, each resuming from the part of its file already on disk.
+    /// Downloads already in progress here are left alone. A missing file
+    /// restores nothing.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::Io`] if `file` cannot be read.
+    pub fn restore_downloads(
+        &self,
+        file: &std::path::Path,
+    ) -> Result<Vec<(Download, Receiver<DownloadStatus>)>> {
+        let mut restored = Vec::new();
+        let entries = download_queue::load(file)
+            .map_err(SoulseekRs::io("read the download queue"))?;
+        for entry in entries {
+            {
+                let mut context = self.context.write_safe()?;
+                let tracked = context
+                    .downloads
+                    .get_by_file(&entry.username, &entry.filename)
+                    .is_some_and(|download| !download.is_finished());
+                if tracked {
+                    continue;
+                }
+                context
+                    .resuming
+                    .insert((entry.username.clone(), entry.filename.clone()));
+            }
+            info!(
+                "[client] Restoring {} from {} at {} of {} bytes",
+                entry.filename, entry.username, entry.bytes_written, entry.size
+            );
+            restored.push(Self::start_download(
+                &self.context,
+                self.server_handle.as_ref(),
+                entry.filename,
+                entry.username,
+                entry.size,
+                entry.download_directory,
+                DownloadMetadata::default(),
+            )?);
+        }
+        Ok(restored)
+    }
+
+    /// Download one file that several users offer, trying `candidates` in
+    /// order. If a source is declined, drops mid-transfer or stalls (no
+    /// progress for a minute once sending), the next one is tried from
+    /// scratch, and the abandoned one is cancelled with its uploader.
+    ///
+    /// The returned receiver carries every source's progress and ends with
+    /// `Completed`, or with `Failed` once all sources are exhausted.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::InvalidArgument`] if `candidates` is empty, or
+    /// if they don't all have the first one's file name and size.
+    pub fn download_any(
+        &self,
+        candidates: Vec<File>,
+        download_directory: String,
+    ) -> Result<Receiver<DownloadStatus>> {
+        if candidates.is_empty() {
+            return Err(SoulseekRs::InvalidArgument(
+                "download_any needs at least one source".to_string(),
+            ));
+        }
+        let first = &candidates[0];
+        if let Some(other) = candidates.iter().find(|candidate| {
+            candidate.size != first.size
+                || !base_name(&candidate.name)
+                    .eq_ignore_ascii_case(base_name(&first.name))
+        }) {
+            return Err(SoulseekRs::InvalidArgument(format!(
+                "download_any sources must be the same file: {} ({} bytes) \
+                 is not {} ({} bytes)",
+                other.name, other.size, first.name, first.size
+            )));
+        }
+        let (sender, receiver) = mpsc::channel();
+        let context = self.context.clone();
+        let server_handle = self.server_handle.clone();
+        thread::spawn(move || {
+            let sources = candidates.len();
+            let mut last_reason = None;
+            for candidate in candidates {
+                match Self::try_source(
+                    &context,
+                    server_handle.as_ref(),
+                    &candidate,
+                    &download_directory,
+                    &sender,
+                ) {
+                    Ok(()) => return,
+                    Err(reason) => {
+                        warn!(
+                            "[client] {} from {} failed ({}), trying next source",
+                            candidate.name, candidate.username, reason
+                        );
+                        last_reason = Some(reason);
+                    }
+                }
+            }
+            let _ = sender.send(DownloadStatus::Failed(Some(
+                format!(
+                    "All {sources} sources failed; last: {}",
+                    last_reason.map(|e| e.to_string()).unwrap_or_default()
+                )
+                .into(),
+            )));
+        });
+        Ok(receiver)
+    }
 
-        let hash = md5::md5(&filename);
-        let token = u32::from_str_radix(&hash[0..5], 16)?;
+    /// Run one source of a [`Client::download_any`] to completion, relaying
+    /// its progress to `relay`. Returns why it failed otherwise; the failed
+    /// attempt is removed from the store so a retry can't be shadowed by it,
+    /// and refused when its uploader offers it.
+    fn try_source(
+        context: &Arc<RwLock<ClientContext>>,
+        server_handle: Option<&ActorHandle<ServerMessage>>,
+        candidate: &File,
+        download_directory: &str,
+        relay: &Sender<DownloadStatus>,
+    ) -> Result<()> {
+        let forget = |abandon: bool| {
+            if let Ok(mut ctx) = context.write_safe() {
+                ctx.downloads
+                    .remove_by_file(&candidate.username, &candidate.name);
+                if abandon {
+                    ctx.abandoned_downloads.insert((
+                        candidate.username.clone(),
+                        candidate.name.clone(),
+                    ));
+                }
+            }
+        };
+        forget(false);
+        let (_, statuses) = Self::start_download(
+            context,
+            server_handle,
+            candidate.name.clone(),
+            candidate.username.clone(),
+            candidate.size,
+            download_directory.to_string(),
+            DownloadMetadata::default(),
+        )?;
+        // Only a transfer under way can stall; queued or held, a source
+        // sends nothing for as long as it waits its turn.
+        let mut sending = false;
+        loop {
+            let status = if sending {
+                statuses.recv_timeout(FAILOVER_STALL_TIMEOUT)
+            } else {
+                statuses
+                    .recv()
+                    .map_err(|_| mpsc::RecvTimeoutError::Disconnected)
+            };
+            let error = match status {
+                Ok(DownloadStatus::Completed(summary)) => {
+                    let _ = relay.send(DownloadStatus::Completed(summary));
+                    return Ok(());
+                }
+                Ok(DownloadStatus::Failed(reason)) => {
+                    SoulseekRs::from(reason.unwrap_or_else(|| {
+                        FailureReason::from("download failed")
+                    }))
+                }
+                Ok(DownloadStatus::TimedOut)
+                | Err(mpsc::RecvTimeoutError::Timeout) => SoulseekRs::Timeout,
+                Ok(status) => {
+                    sending =
+                        matches!(status, DownloadStatus::InProgress { .. });
+                    let _ = relay.send(status);
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    SoulseekRs::ConnectionClosed
+                }
+            };
+            forget(true);
+            return Err(error);
+        }
+    }
 
+    pub(crate) fn start_download(
+        client_context: &Arc<RwLock<ClientContext>>,
+        server_handle: Option<&ActorHandle<ServerMessage>>,
+        filename: String,
+        username: String,
+        size: u64,
+        download_directory: String,
+        metadata: DownloadMetadata,
+    ) -> Result<(Download, Receiver<DownloadStatus>)> {
         let (download_sender, download_receiver): (
             Sender<DownloadStatus>,
             Receiver<DownloadStatus>,
         ) = mpsc::channel();
 
         let download = Download {
-            username: username.clone(),
+            username,
             filename,
-            token,
+            token: 0,
             size,
             download_directory,
             status: DownloadStatus::Queued,
@@ -113,9 +712,77 @@ impl Client {
             queue_position: None,
             metadata,
         };
+        let download = Self::queue_download(client_context, download, |msg| {
+            server_handle.is_some_and(|handle| handle.send(msg).is_ok())
+        })?;
+        Ok((download, download_receiver))
+    }
+
+    /// Add `download` to the store under a fresh token and ask its uploader
+    /// for the file. Without a connection to them yet, one is requested
+    /// through `to_server`, which returns whether the message went out.
+    /// Failures are reported on the download's own channel.
+    pub(crate) fn queue_download(
+        client_context: &Arc<RwLock<ClientContext>>,
+        mut download: Download,
+        to_server: impl FnOnce(ServerMessage) -> bool,
+    ) -> Result<Download> {
+        let username = download.username.clone();
+        info!(
+            "[client] Downloading {} from {}",
+            download.filename, username
+        );
 
-        let mut context = self.context.write_safe()?;
+        let mut context = client_context.write_safe()?;
+        // Tokens of downloads removed since are free again.
+        let ClientContext {
+            tokens, downloads, ..
+        } = &mut *context;
+        tokens.retain(|token, owner| {
+            !matches!(owner, TokenOwner::Download { .. })
+                || downloads.get_by_token(token).is_some()
+        });
+        let token = context.tokens.issue(TokenOwner::Download {
+            username: username.clone(),
+            filename: download.filename.clone(),
+        });
+        download.token = token;
+
+        let hold = !context.has_download_slot();
         context.add_download(download.clone());
+        // Asked for again, so no longer refused.
+        context
+            .abandoned_downloads
+            .remove(&(username.clone(), download.filename.clone()));
+
+        // Fail now rather than after queueing for hours and transferring.
+        let margin = context.disk_space_margin;
+        if !context.has_download_writer(&download)
+            && let Err(e) = crate::peer::check_disk_space(
+                &download.download_directory,
+                download.size,
+                margin,
+            )
+        {
+            let reason = Some(e.to_string().into());
+            let _ =
+                download.sender.send(DownloadStatus::Failed(reason.clone()));
+            context.update_download_with_status(
+                token,
+                DownloadStatus::Failed(reason),
+            );
+            return Ok(download);
+        }
+
+        // Over the limit, the download waits its turn unrequested.
+        if hold {
+            debug!(
+                "[client] Holding {} from {} until a download finishes",
+                download.filename, username
+            );
+            context.downloads.hold(token);
+            return Ok(download);
+        }
 
         // If we already have a control connection to this peer, queue the
         // upload immediately. Otherwise open one directly (server GetPeerAddress
@@ -135,8 +802,8 @@ impl Client {
             !queued_now
         } else {
             // No existing connection: initiate one. Only a genuinely
-            // unconnected client (no server handle) fails outright here.
-            self.connect_peer(&username).is_err()
+            // unconnected client (no server to ask) fails outright here.
+            !to_server(ServerMessage::GetPeerAddress(username))
         };
 
         if failed {
@@ -147,21 +814,42 @@ impl Client {
             };
             let _ = download
                 .sender
-                .send(DownloadStatus::Failed(Some(reason.to_string())));
-            self.context.write_safe()?.update_download_with_status(
+                .send(DownloadStatus::Failed(Some(reason.into())));
+            client_context.write_safe()?.update_download_with_status(
                 token,
-                DownloadStatus::Failed(Some(reason.to_string())),
+                DownloadStatus::Failed(Some(reason.into())),
             );
         }
 
-        Ok((download, download_receiver))
+        Ok(download)
+    }
+
+    /// Mark `username` unreachable and fail its queued downloads, once
+    /// neither a direct nor a brokered connection got through.
+    pub(crate) fn peer_unreachable(
+        client_context: &Arc<RwLock<ClientContext>>,
+        username: &str,
+    ) {
+        match client_context.write_safe() {
+            Ok(mut ctx) => ctx.mark_peer_unreachable(username),
+            Err(e) => error!("[client] peer_unreachable write: {}", e),
+        }
+        Self::fail_queued_downloads(
+            client_context,
+            username,
+            &format!(
+                "Cannot connect to {username}, directly or through the server"
+            ),
+        );
     }
 
-    /// Fail every still-`Queued` download for `username`, both on the caller's
-    /// status channel (so a blocked `Receiver` unblocks) and in the store.
+    /// Fail every still-`Queued` download for `username` with `reason`, both
+    /// on the caller's status channel (so a blocked `Receiver` unblocks) and
+    /// in the store.
     pub(crate) fn fail_queued_downloads(
         client_context: &Arc<RwLock<ClientContext>>,
         username: &str,
+        reason: &str,
     ) {
         let mut context = match client_context.write_safe() {
             Ok(c) => c,
@@ -180,7 +868,7 @@ impl Client {
             .map(|d| (d.token, d.sender.clone()))
             .collect();
         for (token, sender) in doomed {
-            let reason = Some("The user went offline".to_string());
+            let reason = Some(FailureReason::from(reason));
             let _ = sender.send(DownloadStatus::Failed(reason.clone()));
             context.update_download_with_status(
                 token,
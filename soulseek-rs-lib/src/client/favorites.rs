@@ -0,0 +1,141 @@
+use super::{Client, ClientContext, RwLockExt, ServerMessage};
+use crate::message::server::MessageFactory;
+use crate::types::{ClientEvent, ConnectionState, UserStatus};
+
+impl ClientContext {
+    /// Ask the server to watch every favorite, so it reports their status
+    /// changes. Watches don't survive a reconnect, so this runs on each
+    /// login.
+    pub(super) fn watch_favorites(&self) {
+        let Some(sender) = &self.server_sender else {
+            return;
+        };
+        for username in &self.favorites {
+            let _ = sender.send(ServerMessage::SendMessage(
+                MessageFactory::build_watch_username(username),
+            ));
+        }
+    }
+
+    /// Report [`ClientEvent::FavoriteOnline`] if `username` is a favorite
+    /// whose status just went from offline (or unknown) to `status`.
+    pub(super) fn note_favorite_status(
+        &mut self,
+        username: &str,
+        was_online: bool,
+        status: Option<UserStatus>,
+    ) {
+        let online = status.is_some_and(|status| status != UserStatus::Offline);
+        if online && !was_online && self.favorites.contains(username) {
+            self.events
+                .push(ClientEvent::FavoriteOnline(username.to_string()));
+        }
+    }
+}
+
+impl Client {
+    /// Add `username` to the favorites: the server watches them and
+    /// [`ClientEvent::FavoriteOnline`] is reported whenever they come
+    /// online, including when they already are. Returns `false` if they
+    /// already were a favorite. The list starts from
+    /// [`ClientSettings::favorite_users`] and isn't saved; persist it there.
+    ///
+    /// [`ClientSettings::favorite_users`]: super::ClientSettings::favorite_users
+    #[must_use]
+    pub fn add_favorite(&self, username: &str) -> bool {
+        let added = self
+            .context
+            .write_safe()
+            .is_ok_and(|mut ctx| ctx.favorites.insert(username.to_string()));
+        // Before login, logging in watches every favorite.
+        if added && self.connection_state() == ConnectionState::LoggedIn {
+            let _ = self.send_server_message(
+                MessageFactory::build_watch_username(username),
+            );
+        }
+        added
+    }
+
+    /// Stop reporting `username` coming online; `false` if they weren't a
+    /// favorite.
+    #[must_use]
+    pub fn remove_favorite(&self, username: &str) -> bool {
+        self.context
+            .write_safe()
+            .is_ok_and(|mut ctx| ctx.favorites.remove(username))
+    }
+
+    #[must_use]
+    pub fn is_favorite(&self, username: &str) -> bool {
+        self.context
+            .read_safe()
+            .is_ok_and(|ctx| ctx.favorites.contains(username))
+    }
+
+    /// The favorite usernames, sorted.
+    #[must_use]
+    pub fn favorites(&self) -> Vec<String> {
+        let mut favorites: Vec<String> = self
+            .context
+            .read_safe()
+            .map(|ctx| ctx.favorites.iter().cloned().collect())
+            .unwrap_or_default();
+        favorites.sort();
+        favorites
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UserInfo;
+    use std::sync::mpsc;
+
+    fn status(username: &str, status: UserStatus) -> UserInfo {
+        UserInfo {
+            status: Some(status),
+            ..UserInfo::new(username)
+        }
+    }
+
+    #[test]
+    fn favorites_coming_online_are_reported_once() {
+        let mut ctx = ClientContext::new();
+        ctx.favorites.insert("collector".to_string());
+        ctx.merge_user_info(status("collector", UserStatus::Offline));
+        ctx.merge_user_info(status("collector", UserStatus::Online));
+        ctx.merge_user_info(status("collector", UserStatus::Away));
+        ctx.merge_user_info(UserInfo {
+            shared_files: Some(10),
+            ..UserInfo::new("collector")
+        });
+        ctx.merge_user_info(status("stranger", UserStatus::Online));
+        ctx.merge_user_info(status("collector", UserStatus::Offline));
+        ctx.merge_user_info(status("collector", UserStatus::Away));
+        assert_eq!(
+            ctx.take_events(),
+            [
+                ClientEvent::FavoriteOnline("collector".to_string()),
+                ClientEvent::FavoriteOnline("collector".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn favorites_are_watched_on_login() {
+        let mut ctx = ClientContext::new();
+        let (sender, receiver) = mpsc::channel();
+        ctx.server_sender = Some(sender);
+        ctx.favorites.insert("collector".to_string());
+        ctx.watch_favorites();
+        let Ok(ServerMessage::SendMessage(message)) = receiver.try_recv()
+        else {
+            panic!("expected a WatchUser message");
+        };
+        assert_eq!(
+            message.get_data(),
+            MessageFactory::build_watch_username("collector").get_data()
+        );
+        assert!(receiver.try_recv().is_err());
+    }
+}
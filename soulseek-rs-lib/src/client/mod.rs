@@ -1,28 +1,56 @@
-use crate::actor::ActorHandle;
+use crate::actor::peer_actor::PeerTrace;
+use crate::actor::send_limiter::{
+    SendRateLimit, ServerSendSnapshot, ServerSendStats,
+};
 use crate::actor::server_actor::{
-    PeerAddress, ServerActor, ServerMessage, UserMessage,
+    DEFAULT_KEEPALIVE_INTERVAL, DEFAULT_SILENCE_TIMEOUT, PeerAddress,
+    ServerActor, ServerMessage, UserMessage,
 };
+use crate::actor::{ActorHandle, ActorStats, Mailbox};
+use crate::download_history::{DownloadHistory, HistoryEntry};
+use crate::download_hook::DownloadHook;
 use crate::download_store::{DownloadStore, collect_failed_tokens};
-use crate::types::{DownloadMetadata, DownloadStatus, RoomEvent, RoomInfo};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::types::{
+    ClientEvent, ConflictPolicy, ConnectionState, DownloadMetadata,
+    DownloadStatus, FailureReason, RoomEvent, RoomInfo, UserInfo,
+};
+use crate::utils::charset::{self, Charset};
+use crate::utils::deprecation;
 use crate::utils::logger;
 use crate::{
     Transfer,
     actor::{ActorSystem, peer_registry::PeerRegistry},
+    dispatcher::Interceptor,
     error::{Result, SoulseekRs},
+    ignore_list::IgnoreList,
+    leech_filter::{LeechFilter, LeechVerdict},
     message::peer::{FileEntry, SharedDirectory, build_file_search_response},
+    message::server::ParentCandidate,
+    message::{CustomHandlers, MessageHandler, MessageSizeLimits},
     peer::{
         ConnectionType, DownloadPeer, NewPeer, Peer, PeerMessage,
         listen::Listen,
     },
-    shares::Shares,
+    result_ranker::{self, RankedFile, ResultRanker},
+    search_filter::SearchFilter,
+    search_limiter::{SearchLimiter, SearchLimits},
+    shares::{ShareCache, Shares},
+    transport::{PeerStream, PeerTlsSettings, TlsSettings},
     types::{Download, Search, SearchResult},
-    utils::{lock::RwLockExt, md5, thread_pool::ThreadPool},
+    utils::{
+        lock::RwLockExt,
+        thread_pool::ThreadPool,
+        token_bucket::TokenBucket,
+        token_generator::{TokenGenerator, TokenOwner},
+    },
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    io::Write,
     net::TcpStream,
     sync::{
-        RwLock,
+        Mutex, RwLock,
         atomic::{AtomicBool, AtomicU32, Ordering},
         mpsc::{Receiver, Sender},
     },
@@ -35,6 +63,14 @@ use std::{
 
 use crate::{debug, error, info, trace, warn};
 const DEFAULT_LISTEN_PORT: u16 = 2234;
+const DEFAULT_UPLOAD_SLOTS: usize = 2;
+const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_WORKER_THREADS: usize = 256;
+const DEFAULT_MAX_RESULTS_PER_SEARCH: usize = 5_000;
+const DEFAULT_MAX_SEARCH_RESULTS: usize = 50_000;
+const DEFAULT_SEARCH_TTL: Duration = Duration::from_hours(1);
+const DEFAULT_SHARE_RESCAN_INTERVAL: Duration = Duration::from_hours(1);
+const DEFAULT_SOURCE_SEARCH_TIME: Duration = Duration::from_secs(10);
 
 /// How long to wait for a server-brokered (firewalled) peer to connect back
 /// before giving up and failing the download. Matches the direct-dial timeout.
@@ -48,7 +84,7 @@ fn next_connect_token() -> u32 {
 }
 
 /// Upload tokens are minted in the high half of the space so they never collide
-/// with download tokens (md5-derived, always < 2^20).
+/// with search and download tokens (always below 2^31).
 static NEXT_UPLOAD_TOKEN: AtomicU32 = AtomicU32::new(0x8000_0000);
 
 fn next_upload_token() -> u32 {
@@ -63,6 +99,22 @@ struct UploadJob {
     size: u64,
 }
 
+/// A peer's request for one of our files, waiting for a free upload slot.
+struct QueuedUpload {
+    /// Registry key of the requesting peer actor (may carry `:direct`).
+    requester_key: String,
+    downloader: String,
+    filename: String,
+}
+
+/// Upload requests held until their requester's share counts arrive.
+#[derive(Default)]
+struct LeechCheck {
+    /// When the server was last asked for the requester's stats.
+    asked: Option<Instant>,
+    held: Vec<QueuedUpload>,
+}
+
 /// Live bookkeeping for an upload being served (or recently finished).
 struct ActiveUpload {
     username: String,
@@ -73,6 +125,45 @@ struct ActiveUpload {
     status: crate::types::UploadStatus,
 }
 
+/// Scan `roots` into a share index, reusing what `previous` (or, if it is
+/// empty, the saved index) knows of unchanged files, and save the result.
+fn scan_shares(
+    roots: &[std::path::PathBuf],
+    previous: &Shares,
+    index_file: Option<&std::path::Path>,
+) -> Shares {
+    if roots.is_empty() {
+        return Shares::empty();
+    }
+    let cache = if previous.files().is_empty() {
+        index_file
+            .map(|file| {
+                ShareCache::load(file).unwrap_or_else(|e| {
+                    warn!(
+                        "[client] cannot read share index {}: {}",
+                        file.display(),
+                        e
+                    );
+                    ShareCache::default()
+                })
+            })
+            .unwrap_or_default()
+    } else {
+        previous.cache()
+    };
+    let shares = Shares::scan_many_cached(roots, &cache);
+    if let Some(file) = index_file {
+        save_share_index(&shares, file);
+    }
+    shares
+}
+
+fn save_share_index(shares: &Shares, file: &std::path::Path) {
+    if let Err(e) = shares.cache().save(file) {
+        warn!("[client] cannot save share index {}: {}", file.display(), e);
+    }
+}
+
 /// Build a `FileSearchResponse` for `query` against `shares`, or `None` if
 /// nothing matches. `own_username` is the name the searcher will download from.
 fn build_search_response(
@@ -97,7 +188,7 @@ fn build_search_response(
         own_username,
         token,
         &entries,
-        1,
+        true,
         0,
     ))
 }
@@ -112,6 +203,109 @@ pub struct ClientSettings {
     /// Directories whose files are shared with (uploaded to) other peers.
     /// Empty means nothing is shared.
     pub shared_directories: Vec<String>,
+    /// File the share index's audio attributes are kept in between runs,
+    /// so only new or changed files have their headers read. `None`
+    /// reads every file on each scan.
+    pub share_index_file: Option<std::path::PathBuf>,
+    /// How often the shared directories are rescanned in full while
+    /// connected. With the `watch` feature changes are picked up as they
+    /// happen and this only catches what the watcher missed. `None` never
+    /// rescans.
+    pub share_rescan_interval: Option<Duration>,
+    /// How many uploads may be offered or in flight at once; further
+    /// `QueueUpload` requests wait in a queue until a slot frees up.
+    pub upload_slots: usize,
+    /// Cap on total upload bandwidth across all slots, in KiB/s (the unit
+    /// Soulseek clients use for speeds). `None` means unlimited.
+    pub max_upload_rate_kbps: Option<u32>,
+    /// How often an active download reports `InProgress` on its channel.
+    pub progress_interval: Duration,
+    /// Wrap the server connection in TLS, for private deployments. Needs the
+    /// `tls` feature; `None` connects in plain TCP like the official network.
+    pub tls: Option<TlsSettings>,
+    /// Wrap every peer connection, dialled or accepted, in TLS. Only for
+    /// private deployments whose clients all set it; needs the `tls`
+    /// feature. `None` talks plain TCP like the official network.
+    pub peer_tls: Option<PeerTlsSettings>,
+    /// Drop unwanted search results before they are stored or streamed.
+    pub search_filter: Option<SearchFilter>,
+    /// Order search results by relevance. `None` keeps arrival order.
+    pub result_ranker: Option<Arc<dyn ResultRanker>>,
+    /// Peer responses kept per search. Past it the oldest are dropped, or
+    /// the lowest scored if a ranker is set. `None` keeps them all.
+    pub max_results_per_search: Option<usize>,
+    /// Peer responses kept across all searches. Past it the searches that
+    /// received a result longest ago give theirs up first.
+    pub max_search_results: Option<usize>,
+    /// How long a search that receives nothing is kept before it is
+    /// forgotten. `None` keeps searches until [`Client::clear_search`].
+    pub search_ttl: Option<Duration>,
+    /// Deny upload requests from users sharing too little. `None` serves
+    /// everyone.
+    pub leech_filter: Option<LeechFilter>,
+    /// Users whose search results, peer connections and searches are
+    /// ignored. Change it later with [`Client::ignore_user`].
+    pub ignored_users: Vec<String>,
+    /// Users watched for coming online, reported as
+    /// [`ClientEvent::FavoriteOnline`]. Change it later with
+    /// [`Client::add_favorite`].
+    pub favorite_users: Vec<String>,
+    /// How many other users' searches we answer. `None` answers all of
+    /// them.
+    pub search_limits: Option<SearchLimits>,
+    /// Pace messages sent to the server so bursts don't get us disconnected.
+    /// `None` disables pacing.
+    pub server_send_rate: Option<SendRateLimit>,
+    /// How often to send the server a keepalive ping.
+    pub keepalive_interval: Duration,
+    /// Treat the server connection as dead, and reconnect, after receiving
+    /// nothing for this long.
+    pub server_silence_timeout: Duration,
+    /// Reconnect after our account logs in from somewhere else. Off by
+    /// default, since two clients that both reconnect keep kicking each
+    /// other off.
+    pub reconnect_when_relogged: bool,
+    /// Charsets tried, most plausible first, on peer strings that aren't
+    /// UTF-8. Process-wide: the most recently created client sets them.
+    pub fallback_charsets: Vec<Charset>,
+    /// Mailbox of each peer connection's actor. Bound it to cap the memory a
+    /// flooding peer can make us buffer.
+    pub peer_mailbox: Mailbox,
+    /// Mailbox of the server connection's actor.
+    pub server_mailbox: Mailbox,
+    /// Largest message accepted from the server, peers and our distributed
+    /// parent. A connection announcing a bigger one is dropped.
+    pub message_size_limits: MessageSizeLimits,
+    /// Worker threads kept running for the connection actors. Defaults to
+    /// the available parallelism.
+    pub min_worker_threads: usize,
+    /// Most worker threads run at once. Each peer connection holds one for
+    /// its lifetime, so the pool grows towards this as connections open.
+    pub max_worker_threads: usize,
+    /// Bytes to keep free on the download filesystem besides the file
+    /// itself. A download that would eat into it fails before it starts.
+    pub disk_space_margin: u64,
+    /// What a download does when its file already exists.
+    pub conflict_policy: ConflictPolicy,
+    /// JSON-lines file finished downloads are recorded in, so the history
+    /// outlives the client. `None` keeps it in memory only.
+    pub history_file: Option<std::path::PathBuf>,
+    /// JSON-lines file unfinished downloads are saved to on shutdown, for
+    /// [`Client::restore_downloads`]. `None` saves nothing.
+    pub download_queue_file: Option<std::path::PathBuf>,
+    /// Command run after each download saved to a file completes. `None`
+    /// runs nothing.
+    pub on_download_complete: Option<DownloadHook>,
+    /// When an uploader disconnects with our downloads still queued, or a
+    /// transfer breaks off, search this long for other users sharing the
+    /// same files (name and size) and download from the best of them
+    /// instead. `None` fails those downloads.
+    pub source_search_time: Option<Duration>,
+    /// Most downloads asked for at once. Later ones are held, in priority
+    /// order, until one finishes; change the order with
+    /// [`Client::reorder_download`]. `None` asks for every download straight
+    /// away.
+    pub max_active_downloads: Option<usize>,
 }
 
 impl ClientSettings {
@@ -139,6 +333,39 @@ impl Default for ClientSettings {
             enable_listen: true,
             listen_port: DEFAULT_LISTEN_PORT,
             shared_directories: Vec::new(),
+            share_index_file: None,
+            share_rescan_interval: Some(DEFAULT_SHARE_RESCAN_INTERVAL),
+            upload_slots: DEFAULT_UPLOAD_SLOTS,
+            max_upload_rate_kbps: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            tls: None,
+            peer_tls: None,
+            search_filter: None,
+            result_ranker: None,
+            max_results_per_search: Some(DEFAULT_MAX_RESULTS_PER_SEARCH),
+            max_search_results: Some(DEFAULT_MAX_SEARCH_RESULTS),
+            search_ttl: Some(DEFAULT_SEARCH_TTL),
+            leech_filter: None,
+            ignored_users: Vec::new(),
+            favorite_users: Vec::new(),
+            search_limits: Some(SearchLimits::default()),
+            server_send_rate: Some(SendRateLimit::default()),
+            keepalive_interval: DEFAULT_KEEPALIVE_INTERVAL,
+            server_silence_timeout: DEFAULT_SILENCE_TIMEOUT,
+            reconnect_when_relogged: false,
+            fallback_charsets: vec![Charset::Latin1],
+            peer_mailbox: Mailbox::Unbounded,
+            server_mailbox: Mailbox::Unbounded,
+            message_size_limits: MessageSizeLimits::default(),
+            min_worker_threads: default_worker_threads(),
+            max_worker_threads: DEFAULT_MAX_WORKER_THREADS,
+            disk_space_margin: 0,
+            conflict_policy: ConflictPolicy::Overwrite,
+            history_file: None,
+            download_queue_file: None,
+            on_download_complete: None,
+            source_search_time: Some(DEFAULT_SOURCE_SEARCH_TIME),
+            max_active_downloads: None,
         }
     }
 }
@@ -160,7 +387,9 @@ pub enum ClientOperation {
         obfuscation_type: u32,
         obfuscated_port: u16,
     },
-    UploadFailed(String, String),
+    /// A peer refused or failed a file we queued: username, filename and
+    /// why.
+    UploadFailed(String, String, FailureReason),
     PlaceInQueueUpdate {
         username: String,
         filename: String,
@@ -185,6 +414,19 @@ pub enum ClientOperation {
     StartUpload {
         token: u32,
     },
+    /// The peer declined our upload offer for `token`; release its slot.
+    UploadDeclined {
+        token: u32,
+    },
+    /// The upload for `token` stopped (completed, failed or cancelled).
+    UploadFinished {
+        token: u32,
+    },
+    /// A peer asked for its place in our upload queue for `filename`.
+    PlaceInQueueRequested {
+        requester_key: String,
+        filename: String,
+    },
     /// A peer asked to browse our shared files; send our SharedFileListResponse.
     ShareListRequested {
         requester_key: String,
@@ -198,20 +440,91 @@ pub enum ClientOperation {
     /// established — the peer is likely firewalled, so fall back to asking the
     /// server to broker the connection. Carries the reporting actor's id.
     PeerConnectFailed(u64, String),
+    /// The server could not broker a connection for our ConnectToPeer
+    /// `token` either: the peer is unreachable in both directions.
+    CantConnectToPeer(u32),
+    /// We could not dial `username` back for the brokered connection the
+    /// server asked us to make under `token`.
+    PierceFailed {
+        token: u32,
+        username: String,
+    },
+    /// Our account logged in from somewhere else.
+    Relogged,
     /// Something happened in the chat-room subsystem (list refreshed, a room
     /// joined/left, a message said, a member joined/left).
     RoomEvent(RoomEvent),
+    /// Part of a user's status, stats or peer-reported info arrived.
+    UserInfoReceived(UserInfo),
+    /// A server notice for the client/UI to drain.
+    Event(ClientEvent),
+    /// The server's list of phrases we may not search for.
+    ExcludedSearchPhrases(Vec<String>),
+    /// The server's full list of privileged users.
+    PrivilegedUsers(Vec<String>),
+    PrivilegedUserAdded(String),
+    /// Seconds of privileges we have left.
+    PrivilegesLeft(u32),
+    /// Users the server offers as our distributed parent.
+    PossibleParents(Vec<ParentCandidate>),
+    /// The server reset our distributed connections.
+    ResetDistributed,
+    /// The parent tried in `attempt` reported its branch level and root.
+    ParentBranch {
+        attempt: u64,
+        username: String,
+        level: u32,
+        root: String,
+    },
+    /// The parent search numbered `attempt` ended without a parent.
+    ParentGone {
+        attempt: u64,
+    },
+    /// Stop the operations loop.
+    Shutdown,
 }
+
+/// A writer a download goes to instead of a file, shared with the peer
+/// connection writing the transfer into it.
+pub(crate) type DownloadWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
 pub struct ClientContext {
     pub peer_registry: Option<PeerRegistry>,
     pub downloads: DownloadStore,
     sender: Option<Sender<ClientOperation>>,
     server_sender: Option<Sender<ServerMessage>>,
     searches: HashMap<String, Search>,
+    /// What each search and download token was issued for.
+    tokens: TokenGenerator,
+    /// Channels streaming each search's results, keyed by search token.
+    search_listeners: HashMap<u32, Vec<Sender<SearchResult>>>,
+    /// Applied to every incoming search result; `None` keeps all of them.
+    search_filter: Option<SearchFilter>,
+    /// Counters shared with the server and peer connections.
+    metrics: Arc<Metrics>,
+    /// Phrases the server excludes from searches, as it sent them.
+    excluded_phrases: Vec<String>,
+    /// Orders `get_search_results`; `None` keeps arrival order.
+    result_ranker: Option<Arc<dyn ResultRanker>>,
+    /// Caps and lifetime of stored search results.
+    pub max_results_per_search: Option<usize>,
+    pub max_search_results: Option<usize>,
+    pub search_ttl: Option<Duration>,
+    /// How long to search for a new source of a download that lost its own.
+    source_search_time: Option<Duration>,
+    /// Files, by uploader and name, that [`Client::download_any`] gave up
+    /// on. Their uploader offering one is refused as cancelled, which
+    /// drops it from their queue.
+    abandoned_downloads: HashSet<(String, String)>,
+    /// Most downloads asked for at once; `None` for no limit.
+    max_active_downloads: Option<usize>,
     private_messages: Vec<UserMessage>,
     /// Correlation tokens for server-brokered (firewalled) connections, mapping
     /// a token we sent in a ConnectToPeer to the peer we expect back.
     pending_connect_tokens: HashMap<u32, String>,
+    /// Peers we could reach neither directly nor through the server, until
+    /// a connection to them succeeds.
+    unreachable_peers: HashSet<String>,
     /// Files we share with peers (read-only after connect).
     pub shares: Arc<Shares>,
     /// The directories the current share index was built from.
@@ -225,12 +538,60 @@ pub struct ClientContext {
     active_uploads: HashMap<u32, ActiveUpload>,
     /// Upload tokens waiting for the downloader's address to be resolved.
     pending_serves: HashMap<String, Vec<u32>>,
+    /// Upload requests waiting for a free slot, oldest first.
+    upload_queue: VecDeque<QueuedUpload>,
+    upload_slots: usize,
+    leech_filter: Option<LeechFilter>,
+    /// The client's ignored users, whose search results are dropped and
+    /// whose searches go unanswered.
+    ignore_list: Arc<IgnoreList>,
+    /// Admits other users' searches; `None` answers all of them.
+    search_limiter: Option<SearchLimiter>,
+    /// Our place in the distributed search network.
+    parent_link: distributed::ParentLink,
+    /// Upload requests waiting for the requester's share counts, keyed by
+    /// username, so the leech filter can judge them.
+    leech_checks: HashMap<String, LeechCheck>,
+    /// Shared across every upload so the rate cap is global, not per slot.
+    upload_throttle: Option<Arc<Mutex<TokenBucket>>>,
+    /// How often downloads report progress.
+    pub progress_interval: Duration,
+    /// Free space a download must leave on its filesystem.
+    pub disk_space_margin: u64,
+    /// What a download does when its file already exists.
+    pub conflict_policy: ConflictPolicy,
+    /// Largest message accepted on each kind of connection.
+    pub message_size_limits: MessageSizeLimits,
+    /// TLS for peer connections; `None` keeps them plain TCP.
+    pub peer_tls: Option<Arc<PeerTlsSettings>>,
+    /// Downloads that finished, this run and, if backed by a file, before.
+    history: DownloadHistory,
+    /// Restored downloads, by username and filename, which resume from the
+    /// part on disk whatever the conflict policy.
+    resuming: HashSet<(String, String)>,
+    /// Run after each download saved to a file completes.
+    download_hook: Option<DownloadHook>,
+    /// Writers that downloads started by [`Client::download_to_writer`] are
+    /// written to instead of a file, by username and filename.
+    download_writers: HashMap<(String, String), DownloadWriter>,
     /// Shared-file listings received from peers we browsed.
     browse_results: HashMap<String, Vec<SharedDirectory>>,
     /// Latest snapshot of the public chat-room list (from `RoomList`, code 64).
     room_list: Vec<RoomInfo>,
     /// Chat-room events awaiting consumption by the client/UI.
     room_events: Vec<RoomEvent>,
+    /// Everything learned about users we looked up, keyed by username.
+    user_info: HashMap<String, UserInfo>,
+    /// Users whose coming online is reported; watched on every login.
+    favorites: HashSet<String>,
+    /// Users the server reports as privileged (they jump upload queues).
+    privileged_users: HashSet<String>,
+    /// Our own privileges as last reported, in seconds, and when.
+    privileges: Option<(u32, Instant)>,
+    /// `check_privileges` calls waiting for the server's answer.
+    privilege_waiters: Vec<Sender<u32>>,
+    /// Server notices awaiting consumption by the client/UI.
+    events: Vec<ClientEvent>,
     actor_system: Arc<ActorSystem>,
 }
 impl Default for ClientContext {
@@ -278,7 +639,120 @@ impl ClientContext {
         token: u32,
         status: DownloadStatus,
     ) {
+        let was_finished = self
+            .downloads
+            .get_by_token(token)
+            .is_some_and(Download::is_finished);
+        // A cancelled download stays cancelled, whatever its transfer
+        // reports as it stops.
+        if self
+            .downloads
+            .get_by_token(token)
+            .is_some_and(Download::is_aborted)
+        {
+            return;
+        }
+        let failed = matches!(status, DownloadStatus::Failed(_));
+        // Downloads written to a writer or stream have no file to hand on.
+        let saved = match &status {
+            DownloadStatus::Completed(Some(summary))
+                if self.download_hook.is_some()
+                    && !summary.path.as_os_str().is_empty() =>
+            {
+                Some(summary.clone())
+            }
+            _ => None,
+        };
         self.downloads.update_status(token, status);
+        if !was_finished
+            && self
+                .downloads
+                .get_by_token(token)
+                .is_some_and(Download::is_finished)
+        {
+            self.metrics.download_finished(failed);
+            if let Some(download) = self.downloads.get_by_token(token) {
+                let key =
+                    (download.username.clone(), download.filename.clone());
+                self.resuming.remove(&key);
+                self.download_writers.remove(&key);
+            }
+        }
+        if !was_finished
+            && let Some(entry) = self
+                .downloads
+                .get_by_token(token)
+                .and_then(HistoryEntry::from_download)
+        {
+            self.history.record(entry);
+        }
+        if !was_finished
+            && let (Some(hook), Some(summary)) = (&self.download_hook, saved)
+            && let Some(download) = self.downloads.get_by_token(token)
+        {
+            hook.run(download, &summary);
+        }
+        if !was_finished
+            && self
+                .downloads
+                .get_by_token(token)
+                .is_some_and(Download::is_finished)
+        {
+            self.start_held_downloads();
+        }
+    }
+    #[must_use]
+    pub const fn download_history(&self) -> &DownloadHistory {
+        &self.history
+    }
+    /// Whether `download` was restored, and so resumes from the part of
+    /// its file already on disk.
+    #[must_use]
+    pub fn is_resuming(&self, download: &Download) -> bool {
+        self.resuming
+            .contains(&(download.username.clone(), download.filename.clone()))
+    }
+    /// Whether `download` is written to a writer rather than a file.
+    #[must_use]
+    pub fn has_download_writer(&self, download: &Download) -> bool {
+        self.download_writers.contains_key(&(
+            download.username.clone(),
+            download.filename.clone(),
+        ))
+    }
+    /// Write the download of `filename` from `username` to `writer` rather
+    /// than a file.
+    pub(crate) fn add_download_writer(
+        &mut self,
+        username: String,
+        filename: String,
+        writer: impl Write + Send + 'static,
+    ) {
+        self.download_writers.insert(
+            (username, filename),
+            Arc::new(Mutex::new(Box::new(writer))),
+        );
+    }
+    /// The writer `download` is written to, if it has one. It stays
+    /// registered until the download finishes.
+    pub(crate) fn download_writer(
+        &self,
+        download: &Download,
+    ) -> Option<DownloadWriter> {
+        self.download_writers
+            .get(&(download.username.clone(), download.filename.clone()))
+            .cloned()
+    }
+    /// Whether to answer `username`'s search for `query`, per the search
+    /// limits.
+    pub fn admit_incoming_search(
+        &mut self,
+        username: &str,
+        query: &str,
+    ) -> bool {
+        self.search_limiter.as_mut().is_none_or(|limiter| {
+            limiter.admit(username, query, Instant::now())
+        })
     }
     pub fn remove_queued_download_by_file(
         &mut self,
@@ -319,6 +793,245 @@ fn test_client_context_downloads() {
     assert!(context.get_download_by_token(1234).is_none());
 }
 
+#[test]
+fn finished_downloads_are_recorded_once() {
+    let mut context = ClientContext::new();
+    context.add_download(Download {
+        username: "amy".to_string(),
+        filename: "Music\\song.flac".to_string(),
+        token: 7,
+        size: 100,
+        download_directory: "test".to_string(),
+        status: DownloadStatus::Queued,
+        sender: mpsc::channel().0,
+        queue_position: None,
+        metadata: DownloadMetadata::default(),
+    });
+    context.update_download_with_status(7, DownloadStatus::Queued);
+    assert!(context.download_history().entries().is_empty());
+
+    let summary = crate::types::DownloadSummary::new(
+        "/music/song.flac".into(),
+        100,
+        Duration::from_secs(2),
+    )
+    .with_md5("abc");
+    context.update_download_with_status(
+        7,
+        DownloadStatus::Completed(Some(summary)),
+    );
+    context.update_download_with_status(7, DownloadStatus::Completed(None));
+
+    let entries = context.download_history().entries();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].completed());
+    assert_eq!(entries[0].md5.as_deref(), Some("abc"));
+    assert_eq!(entries[0].elapsed, Duration::from_secs(2));
+    assert!(context.download_history().has_downloaded("song.flac", 100));
+    let metrics = context.metrics_snapshot();
+    assert_eq!(
+        (metrics.downloads_completed, metrics.downloads_failed),
+        (1, 0)
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn the_download_hook_runs_once_the_file_is_saved() {
+    let dir = std::env::temp_dir()
+        .join(format!("soulseek-hook-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("song.flac");
+    let done = dir.join("done");
+    std::fs::write(&path, b"music").unwrap();
+
+    let mut context = ClientContext::new();
+    context.download_hook = Some(DownloadHook::new(format!(
+        "sh -c 'echo \"$0 $1\" > {}' {{user}} {{size}}",
+        done.display()
+    )));
+    context.add_download(Download {
+        username: "amy".to_string(),
+        filename: "Music\\song.flac".to_string(),
+        token: 7,
+        size: 5,
+        download_directory: dir.display().to_string(),
+        status: DownloadStatus::Queued,
+        sender: mpsc::channel().0,
+        queue_position: None,
+        metadata: DownloadMetadata::default(),
+    });
+    let summary =
+        crate::types::DownloadSummary::new(path, 5, Duration::from_secs(1));
+    context.update_download_with_status(
+        7,
+        DownloadStatus::Completed(Some(summary)),
+    );
+
+    // The hook runs in the background.
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let mut output = String::new();
+    while output != "amy 5\n" && std::time::Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+        output = std::fs::read_to_string(&done).unwrap_or_default();
+    }
+    assert_eq!(output, "amy 5\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn room_list_follows_joins_and_leaves() {
+    let mut context = ClientContext::new();
+    context.apply_room_event(RoomEvent::List(vec![RoomInfo {
+        name: "jazz".to_string(),
+        user_count: 7,
+        ..RoomInfo::default()
+    }]));
+    context.apply_room_event(RoomEvent::Joined {
+        room: "jazz".to_string(),
+        users: vec!["me".to_string(), "amy".to_string()],
+    });
+    context.apply_room_event(RoomEvent::UserJoined {
+        room: "jazz".to_string(),
+        username: "bob".to_string(),
+    });
+    context.apply_room_event(RoomEvent::UserLeft {
+        room: "jazz".to_string(),
+        username: "amy".to_string(),
+    });
+    // A room created by joining it shows up before the next list.
+    context.apply_room_event(RoomEvent::Joined {
+        room: "new".to_string(),
+        users: vec!["me".to_string()],
+    });
+    let counts: Vec<_> = context
+        .room_list()
+        .into_iter()
+        .map(|room| (room.name, room.user_count))
+        .collect();
+    assert_eq!(counts, [("jazz".to_string(), 2), ("new".to_string(), 1)]);
+    assert_eq!(context.take_room_events().len(), 5);
+}
+
+#[test]
+fn download_writers_are_dropped_when_the_download_finishes() {
+    let client = Client::new("test-user", "test-password");
+    // Unconnected, so the download fails straight away.
+    let (download, statuses) = client
+        .download_to_writer(
+            "song.mp3".to_string(),
+            "peer".to_string(),
+            100,
+            Vec::new(),
+        )
+        .unwrap();
+    assert!(matches!(
+        statuses.recv().unwrap(),
+        DownloadStatus::Failed(_)
+    ));
+    let mut context = client.context.write().unwrap();
+    assert!(!context.has_download_writer(&download));
+
+    context.add_download_writer(
+        "peer".to_string(),
+        "song.mp3".to_string(),
+        Vec::new(),
+    );
+    context.update_download_with_status(download.token, DownloadStatus::Queued);
+    assert!(context.download_writer(&download).is_some());
+    context.update_download_with_status(
+        download.token,
+        DownloadStatus::Completed(None),
+    );
+    assert!(context.download_writer(&download).is_none());
+}
+
+#[test]
+fn download_to_vec_refuses_files_over_the_cap() {
+    let client = Client::new("test-user", "test-password");
+    assert!(matches!(
+        client.download_to_vec(
+            "cover.jpg".to_string(),
+            "peer".to_string(),
+            2048,
+            1024
+        ),
+        Err(SoulseekRs::InvalidArgument(_))
+    ));
+    assert!(client.get_all_downloads().is_empty());
+    // Unconnected, so the download fails rather than waiting.
+    assert!(matches!(
+        client.download_to_vec(
+            "cover.jpg".to_string(),
+            "peer".to_string(),
+            512,
+            1024
+        ),
+        Err(SoulseekRs::TransferRejected { .. })
+    ));
+}
+
+#[test]
+fn a_download_stream_fails_with_its_download() {
+    use std::io::Read;
+
+    let client = Client::new("test-user", "test-password");
+    // Unconnected, so the download fails before anything arrives.
+    let (_, _, mut stream) = client
+        .download_stream("song.mp3".to_string(), "peer".to_string(), 100)
+        .unwrap();
+    let mut data = Vec::new();
+    assert!(stream.read_to_end(&mut data).is_err());
+    assert!(data.is_empty());
+}
+
+#[test]
+fn cancelled_downloads_stay_cancelled() {
+    let client = Client::new("test-user", "test-password");
+    let (sender, statuses) = mpsc::channel();
+    client.context.write().unwrap().add_download(Download {
+        username: "peer".to_string(),
+        filename: "song.mp3".to_string(),
+        token: 123,
+        size: 100,
+        download_directory: "test".to_string(),
+        status: DownloadStatus::Queued,
+        sender,
+        queue_position: None,
+        metadata: DownloadMetadata::default(),
+    });
+
+    assert!(client.cancel_download("peer", "song.mp3"));
+    assert!(!client.cancel_download("peer", "song.mp3"));
+    assert!(matches!(
+        statuses.try_recv().unwrap(),
+        DownloadStatus::Failed(Some(FailureReason::Aborted))
+    ));
+    // The transfer failing as it stops doesn't change why.
+    client.context.write().unwrap().update_download_with_status(
+        123,
+        DownloadStatus::Failed(Some("Cancelled".into())),
+    );
+    assert!(
+        client
+            .context
+            .read()
+            .unwrap()
+            .get_download_by_token(123)
+            .unwrap()
+            .is_aborted()
+    );
+    assert_eq!(
+        client
+            .context
+            .read()
+            .unwrap()
+            .metrics_snapshot()
+            .downloads_failed,
+        1
+    );
+}
+
 #[test]
 fn test_client_pause_and_resume_download() {
     let client = Client::new("test-user", "test-password");
@@ -333,6 +1046,8 @@ fn test_client_pause_and_resume_download() {
             bytes_downloaded: 25,
             total_bytes: 100,
             speed_bytes_per_sec: 10.0,
+            average_speed_bytes_per_sec: 10.0,
+            eta: None,
         },
         sender: download_sender,
         queue_position: None,
@@ -375,11 +1090,138 @@ fn test_client_pause_and_resume_download() {
         DownloadStatus::InProgress {
             bytes_downloaded: 25,
             total_bytes: 100,
-            speed_bytes_per_sec: 0.0
+            speed_bytes_per_sec: 0.0,
+            average_speed_bytes_per_sec: 0.0,
+            eta: None
         }
     ));
 }
 
+#[test]
+fn queue_position_returns_the_cached_place_and_asks_again() {
+    let client = Client::new("test-user", "test-password");
+    for (token, status) in [
+        (1, DownloadStatus::Queued),
+        (2, DownloadStatus::Failed(None)),
+    ] {
+        client.context.write().unwrap().add_download(Download {
+            username: "peer".to_string(),
+            filename: format!("song{token}.mp3"),
+            token,
+            size: 100,
+            download_directory: "test".to_string(),
+            status,
+            sender: mpsc::channel().0,
+            queue_position: Some(4),
+            metadata: DownloadMetadata::default(),
+        });
+    }
+
+    assert_eq!(client.queue_position(1), Some(4));
+    assert_eq!(client.queue_position(2), None);
+    assert_eq!(client.queue_position(3), None);
+    // Not connected to the uploader yet, so the request waits for them.
+    assert_eq!(
+        client
+            .context
+            .write()
+            .unwrap()
+            .take_peer_messages("peer")
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn shutdown_fails_unfinished_downloads() {
+    let mut client =
+        Client::with_settings(ClientSettings::new("test-user", "pw"));
+    let (download_sender, download_receiver) = mpsc::channel();
+    for (token, status) in [
+        (1, DownloadStatus::Queued),
+        (2, DownloadStatus::Completed(None)),
+    ] {
+        client.context.write().unwrap().add_download(Download {
+            username: "peer".to_string(),
+            filename: format!("{token}.mp3"),
+            token,
+            size: 100,
+            download_directory: "test".to_string(),
+            status,
+            sender: download_sender.clone(),
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        });
+    }
+
+    client.shutdown();
+    client.shutdown();
+
+    let statuses: Vec<_> = client
+        .get_all_downloads()
+        .into_iter()
+        .map(|download| download.status)
+        .collect();
+    assert!(matches!(
+        statuses.as_slice(),
+        [
+            DownloadStatus::Failed(Some(_)),
+            DownloadStatus::Completed(None)
+        ]
+    ));
+    assert!(matches!(
+        download_receiver.try_recv(),
+        Ok(DownloadStatus::Failed(Some(_)))
+    ));
+    assert!(download_receiver.try_recv().is_err());
+}
+
+#[test]
+fn shutdown_saves_the_queue_for_a_restore() {
+    let dir = std::env::temp_dir()
+        .join(format!("soulseek-restore-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let file = dir.join("queue.jsonl");
+    let mut client = Client::with_settings(ClientSettings {
+        download_queue_file: Some(file.clone()),
+        ..ClientSettings::new("test-user", "pw")
+    });
+    let (download_sender, _download_receiver) = mpsc::channel();
+    for (token, status) in [
+        (1, DownloadStatus::Queued),
+        (2, DownloadStatus::Completed(None)),
+    ] {
+        client.context.write().unwrap().add_download(Download {
+            username: "peer".to_string(),
+            filename: format!("{token}.mp3"),
+            token,
+            size: 100,
+            download_directory: dir.to_string_lossy().into_owned(),
+            status,
+            sender: download_sender.clone(),
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        });
+    }
+
+    client.shutdown();
+    // A second shutdown must not replace the queue with nothing.
+    client.shutdown();
+
+    let restoring =
+        Client::with_settings(ClientSettings::new("test-user", "pw"));
+    let restored = restoring.restore_downloads(&file).unwrap();
+    assert_eq!(restored.len(), 1);
+    let (download, _) = &restored[0];
+    assert_eq!(
+        (download.username.as_str(), download.filename.as_str()),
+        ("peer", "1.mp3")
+    );
+    assert_eq!(download.size, 100);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[test]
 fn download_without_a_connection_resolves_failed() {
     // A client that never connected has no server handle and no peer registry,
@@ -400,6 +1242,504 @@ fn download_without_a_connection_resolves_failed() {
     ));
 }
 
+#[test]
+fn download_any_tries_every_source_then_fails() {
+    // Without a connection every source fails immediately; download_any must
+    // walk through all of them and report the overall failure once.
+    let client = Client::new("test-user", "test-password");
+    let candidate = |user: &str| crate::types::File {
+        username: user.to_string(),
+        name: "song.mp3".to_string(),
+        size: 100,
+        attributes: crate::types::FileAttributes::default(),
+        name_charset: None,
+        locked: false,
+    };
+    let receiver = client
+        .download_any(vec![candidate("a"), candidate("b")], "test".to_string())
+        .expect("download_any() should accept candidates");
+    match receiver.recv_timeout(Duration::from_secs(1)) {
+        Ok(DownloadStatus::Failed(Some(reason))) => {
+            let reason = reason.to_string();
+            assert!(reason.starts_with("All 2 sources failed"), "{reason}");
+        }
+        other => panic!("unexpected status: {other:?}"),
+    }
+    assert!(client.download_any(Vec::new(), "test".to_string()).is_err());
+    // Only sources of the same file are accepted.
+    let resized = crate::types::File {
+        size: 99,
+        ..candidate("c")
+    };
+    assert!(matches!(
+        client.download_any(vec![candidate("a"), resized], "test".to_string()),
+        Err(SoulseekRs::InvalidArgument(_))
+    ));
+    // The failed sources are refused when their uploaders offer them later,
+    // until they are asked for again.
+    let key = ("a".to_string(), "song.mp3".to_string());
+    assert!(
+        client
+            .context
+            .read()
+            .unwrap()
+            .abandoned_downloads
+            .contains(&key)
+    );
+    let _ = client.download(
+        "song.mp3".to_string(),
+        "a".to_string(),
+        100,
+        "test".to_string(),
+    );
+    assert!(
+        !client
+            .context
+            .read()
+            .unwrap()
+            .abandoned_downloads
+            .contains(&key)
+    );
+}
+
+#[test]
+fn search_listeners_receive_matching_results_until_dropped() {
+    let mut context = ClientContext::new();
+    let (sender, receiver) = mpsc::channel();
+    context.search_listeners.insert(42, vec![sender]);
+    let result = |token| SearchResult {
+        token,
+        files: Vec::new(),
+        free_slot: true,
+        speed: 0,
+        queue_length: 0,
+        username: "peer".to_string(),
+        locked_files: Vec::new(),
+    };
+
+    context.notify_search_listeners(&result(7));
+    context.notify_search_listeners(&result(42));
+    assert_eq!(
+        receiver.try_iter().map(|r| r.token).collect::<Vec<_>>(),
+        [42]
+    );
+
+    // Once the stream is dropped its listener is pruned on the next result.
+    drop(receiver);
+    context.notify_search_listeners(&result(42));
+    assert!(!context.search_listeners.contains_key(&42));
+}
+
+#[test]
+fn excluded_phrases_drop_matching_files() {
+    let mut context = ClientContext::new();
+    let file = |name: &str| crate::types::File {
+        username: "peer".to_string(),
+        name: name.to_string(),
+        size: 100,
+        attributes: crate::types::FileAttributes::default(),
+        name_charset: None,
+        locked: false,
+    };
+    let result = |names: &[&str]| SearchResult {
+        token: 1,
+        files: names.iter().map(|name| file(name)).collect(),
+        free_slot: true,
+        speed: 0,
+        queue_length: 0,
+        username: "peer".to_string(),
+        locked_files: Vec::new(),
+    };
+    assert!(!context.is_excluded("Bad Band - Leak.mp3"));
+
+    context.excluded_phrases = vec!["bad band".to_string()];
+    assert!(context.is_excluded("Music\\BAD BAND\\song.mp3"));
+    let kept = context
+        .without_excluded(result(&["Bad Band - a.mp3", "Good Band - b.mp3"]))
+        .unwrap();
+    assert_eq!(kept.files.len(), 1);
+    assert_eq!(kept.files[0].name, "Good Band - b.mp3");
+    assert!(
+        context
+            .without_excluded(result(&["bad band.mp3"]))
+            .is_none()
+    );
+}
+
+#[test]
+fn search_results_are_routed_by_token() {
+    let mut context = ClientContext::new();
+    let result = |token| SearchResult {
+        token,
+        files: Vec::new(),
+        free_slot: true,
+        speed: 0,
+        queue_length: 0,
+        username: "peer".to_string(),
+        locked_files: Vec::new(),
+    };
+    let (sender, receiver) = mpsc::channel();
+    let first = context.register_search("aphex twin", sender.clone());
+    let other = context.register_search("autechre", sender.clone());
+    context.store_search_result(result(other));
+    assert!(context.expects_search_result(first));
+    // Tokens are never 0.
+    assert!(!context.expects_search_result(0));
+
+    // A rerun takes over the query; the first run is no longer streamed or
+    // stored.
+    let rerun = context.register_search("aphex twin", sender);
+    assert!(!context.expects_search_result(first));
+    assert!(!context.search_listeners.contains_key(&first));
+    context.store_search_result(result(first));
+    context.store_search_result(result(rerun));
+    assert_eq!(context.searches["aphex twin"].results.len(), 1);
+    assert_eq!(context.searches["autechre"].results.len(), 1);
+
+    drop(receiver);
+    assert!(context.remove_search("aphex twin"));
+    assert!(!context.expects_search_result(rerun));
+}
+
+#[test]
+fn ignored_users_results_are_dropped() {
+    let client = Client::with_settings(ClientSettings {
+        ignored_users: vec!["spammer".to_string()],
+        ..ClientSettings::new("test-user", "pw")
+    });
+    let result = |token, username: &str| SearchResult {
+        token,
+        files: vec![crate::types::File {
+            username: username.to_string(),
+            name: "song.flac".to_string(),
+            size: 100,
+            attributes: crate::types::FileAttributes::default(),
+            name_charset: None,
+            locked: false,
+        }],
+        free_slot: true,
+        speed: 0,
+        queue_length: 0,
+        username: username.to_string(),
+        locked_files: Vec::new(),
+    };
+    let (listener, _results) = mpsc::channel();
+    let token = client
+        .context
+        .write()
+        .unwrap()
+        .register_search("song", listener);
+    let (sender, reader) = mpsc::channel();
+    for username in ["spammer", "peer", "faker"] {
+        sender
+            .send(ClientOperation::SearchResult(result(token, username)))
+            .unwrap();
+    }
+    sender.send(ClientOperation::Shutdown).unwrap();
+    Client::listen_to_client_operations(
+        reader,
+        client.context.clone(),
+        "test-user".to_string(),
+    )
+    .join()
+    .unwrap();
+    assert_eq!(client.get_search_results_count("song"), 2);
+
+    // Ignoring someone later also drops what they already sent.
+    assert!(client.ignore_user("faker"));
+    assert!(!client.ignore_user("faker"));
+    let results = client.get_search_results("song");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].username, "peer");
+    assert_eq!(client.ignored_users(), ["faker", "spammer"]);
+
+    assert!(client.unignore_user("spammer"));
+    assert!(!client.is_ignored("spammer"));
+}
+
+#[test]
+fn search_results_are_paged_in_ranked_order() {
+    let client = Client::with_settings(ClientSettings {
+        result_ranker: Some(Arc::new(result_ranker::DefaultRanker)),
+        ..ClientSettings::new("test-user", "pw")
+    });
+    let result = |token, speed| SearchResult {
+        token,
+        files: vec![crate::types::File {
+            username: "peer".to_string(),
+            name: "song.flac".to_string(),
+            size: 100,
+            attributes: crate::types::FileAttributes::default(),
+            name_charset: None,
+            locked: false,
+        }],
+        free_slot: true,
+        speed,
+        queue_length: 0,
+        username: "peer".to_string(),
+        locked_files: Vec::new(),
+    };
+    {
+        let mut context = client.context.write().unwrap();
+        let token = context.register_search("song", mpsc::channel().0);
+        for speed in [10_000, 30_000, 20_000] {
+            context.store_search_result(result(token, speed));
+        }
+    }
+
+    let page = client.get_search_results_page("song", 1, 5);
+    assert_eq!(page.total, 3);
+    let speeds: Vec<u32> = page.results.iter().map(|r| r.speed).collect();
+    assert_eq!(speeds, [20_000, 10_000]);
+    assert!(
+        client
+            .get_search_results_page("song", 3, 5)
+            .results
+            .is_empty()
+    );
+    assert_eq!(client.get_search_results_page("other", 0, 5).total, 0);
+}
+
+#[test]
+fn stored_search_results_are_capped_and_expire() {
+    let mut context = ClientContext::new();
+    context.max_results_per_search = Some(10);
+    context.max_search_results = Some(15);
+    context.search_ttl = Some(Duration::from_mins(1));
+    let result = |token, username: &str| SearchResult {
+        token,
+        files: Vec::new(),
+        free_slot: true,
+        speed: 0,
+        queue_length: 0,
+        username: username.to_string(),
+        locked_files: Vec::new(),
+    };
+    let (sender, _receiver) = mpsc::channel();
+    let old = context.register_search("old", sender.clone());
+    let new = context.register_search("new", sender);
+
+    for n in 0..11 {
+        context.store_search_result(result(old, &format!("peer{n}")));
+    }
+    // Past the cap the oldest go, down to a little under it.
+    let old = &context.searches["old"].results;
+    assert_eq!(old.len(), 9);
+    assert_eq!(old[0].username, "peer2");
+
+    for n in 0..8 {
+        context.store_search_result(result(new, &format!("peer{n}")));
+    }
+    // The global cap takes from the search fed longest ago.
+    assert_eq!(context.searches["new"].results.len(), 8);
+    assert_eq!(context.searches["old"].results.len(), 7);
+    context.store_search_result(result(99, "stray"));
+
+    context.searches.get_mut("old").unwrap().updated -= Duration::from_secs(61);
+    context.store_search_result(result(new, "late"));
+    assert!(!context.searches.contains_key("old"));
+    assert!(context.remove_search("new"));
+    assert!(!context.remove_search("new"));
+}
+
+#[test]
+fn parent_branches_are_advertised_and_withdrawn() {
+    let mut context = ClientContext::new();
+    let (server, sent) = mpsc::channel();
+    context.server_sender = Some(server);
+    let codes = |sent: &Receiver<ServerMessage>| -> Vec<u32> {
+        sent.try_iter()
+            .filter_map(|msg| match msg {
+                ServerMessage::SendMessage(message) => {
+                    Some(message.get_message_code_send().into())
+                }
+                _ => None,
+            })
+            .collect()
+    };
+
+    context.parent_branch(0, "bob".into(), 2, "carol".into());
+    // HaveNoParent(false), BranchLevel, BranchRoot.
+    assert_eq!(codes(&sent), [71, 126, 127]);
+    let parent = context.distributed_parent().unwrap();
+    assert_eq!(
+        (parent.branch_level, parent.branch_root.as_str()),
+        (3, "carol")
+    );
+
+    // A stale search's report changes nothing.
+    context.parent_gone(7, "me");
+    assert!(codes(&sent).is_empty());
+
+    context.parent_gone(0, "me");
+    assert_eq!(codes(&sent), [71, 126, 127]);
+    assert!(context.distributed_parent().is_none());
+}
+
+#[test]
+fn cant_connect_to_peer_fails_the_peers_downloads() {
+    let client = Client::new("u", "p");
+    let (sender, receiver) = mpsc::channel();
+    {
+        let mut ctx = client.context.write().unwrap();
+        ctx.add_pending_connect(5, "peer".to_string());
+        ctx.add_download(Download {
+            username: "peer".to_string(),
+            filename: "f.mp3".to_string(),
+            token: 7,
+            size: 10,
+            download_directory: "d".to_string(),
+            status: DownloadStatus::Queued,
+            sender,
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        });
+    }
+    let (ops, reader) = mpsc::channel();
+    let operations = Client::listen_to_client_operations(
+        reader,
+        client.context.clone(),
+        "u".to_string(),
+    );
+    // An unknown token is ignored.
+    ops.send(ClientOperation::CantConnectToPeer(6)).unwrap();
+    ops.send(ClientOperation::CantConnectToPeer(5)).unwrap();
+    ops.send(ClientOperation::Shutdown).unwrap();
+    operations.join().unwrap();
+
+    assert!(matches!(
+        receiver.try_recv(),
+        Ok(DownloadStatus::Failed(Some(reason))) if reason.to_string().contains("peer")
+    ));
+    assert!(client.is_peer_unreachable("peer"));
+    assert!(matches!(
+        &client.take_events()[..],
+        [ClientEvent::PeerUnreachable(user)] if user == "peer"
+    ));
+
+    // A later connection clears it.
+    client.context.write().unwrap().mark_peer_reachable("peer");
+    assert!(!client.is_peer_unreachable("peer"));
+}
+
+#[test]
+fn relogged_fails_unfinished_downloads() {
+    let client = Client::new("u", "p");
+    let (sender, receiver) = mpsc::channel();
+    client.context.write().unwrap().add_download(Download {
+        username: "peer".to_string(),
+        filename: "f.mp3".to_string(),
+        token: 7,
+        size: 10,
+        download_directory: "d".to_string(),
+        status: DownloadStatus::Queued,
+        sender,
+        queue_position: None,
+        metadata: DownloadMetadata::default(),
+    });
+    let (ops, reader) = mpsc::channel();
+    let operations = Client::listen_to_client_operations(
+        reader,
+        client.context.clone(),
+        "u".to_string(),
+    );
+    ops.send(ClientOperation::Relogged).unwrap();
+    ops.send(ClientOperation::Shutdown).unwrap();
+    operations.join().unwrap();
+
+    assert!(matches!(
+        receiver.try_recv(),
+        Ok(DownloadStatus::Failed(Some(reason)))
+            if reason == "Logged in from another location".into()
+    ));
+    assert_eq!(client.take_events(), [ClientEvent::Relogged]);
+}
+
+#[test]
+fn upload_denied_fails_the_download_with_its_reason() {
+    let client = Client::new("u", "p");
+    let (sender, receiver) = mpsc::channel();
+    client.context.write().unwrap().add_download(Download {
+        username: "peer".to_string(),
+        filename: "f.mp3".to_string(),
+        token: 7,
+        size: 10,
+        download_directory: "d".to_string(),
+        status: DownloadStatus::Queued,
+        sender,
+        queue_position: None,
+        metadata: DownloadMetadata::default(),
+    });
+    let (ops, reader) = mpsc::channel();
+    let operations = Client::listen_to_client_operations(
+        reader,
+        client.context.clone(),
+        "u".to_string(),
+    );
+    ops.send(ClientOperation::UploadFailed(
+        "peer".to_string(),
+        "f.mp3".to_string(),
+        FailureReason::FileNotShared,
+    ))
+    .unwrap();
+    ops.send(ClientOperation::Shutdown).unwrap();
+    operations.join().unwrap();
+
+    assert!(matches!(
+        receiver.try_recv(),
+        Ok(DownloadStatus::Failed(Some(FailureReason::FileNotShared)))
+    ));
+    assert!(client.get_all_downloads().is_empty());
+}
+
+#[test]
+fn operations_loop_reports_stopping_without_a_shutdown() {
+    let client = Client::new("u", "p");
+    let (ops, reader) = mpsc::channel();
+    let operations = Client::listen_to_client_operations(
+        reader,
+        client.context.clone(),
+        "u".to_string(),
+    );
+    ops.send(ClientOperation::Relogged).unwrap();
+    drop(ops);
+    operations.join().unwrap();
+
+    assert!(matches!(
+        &client.take_events()[..],
+        [ClientEvent::Relogged, ClientEvent::OperationsStopped(_)]
+    ));
+}
+
+#[test]
+fn dropping_a_search_stream_removes_its_listener() {
+    let client = Client::new("test-user", "test-password");
+    let (sender, receiver) = mpsc::channel();
+    let token = client
+        .context
+        .write()
+        .unwrap()
+        .register_search("query", sender);
+    let stream =
+        SearchStream::new(&client.context, token, receiver, Duration::ZERO);
+    assert!(client.context.read().unwrap().expects_search_result(token));
+
+    drop(stream);
+    let context = client.context.read().unwrap();
+    assert!(!context.search_listeners.contains_key(&token));
+    // The search itself is still kept.
+    assert!(context.expects_search_result(token));
+}
+
+#[test]
+fn search_stream_requires_a_connection() {
+    let client = Client::new("test-user", "test-password");
+    assert!(matches!(
+        client.search_stream("query", Duration::from_secs(1)),
+        Err(SoulseekRs::NotConnected)
+    ));
+}
+
 #[test]
 fn fail_queued_downloads_notifies_receiver_and_store() {
     // When a brokered connect times out, every Queued download for the peer
@@ -418,7 +1758,7 @@ fn fail_queued_downloads_notifies_receiver_and_store() {
         metadata: DownloadMetadata::default(),
     });
 
-    Client::fail_queued_downloads(&client.context, "peer");
+    Client::fail_queued_downloads(&client.context, "peer", "gone");
 
     assert!(matches!(receiver.try_recv(), Ok(DownloadStatus::Failed(_))));
     assert!(matches!(
@@ -456,6 +1796,17 @@ fn build_search_response_matches_shares_and_echoes_token() {
     let _ = std::fs::remove_dir_all(dir);
 }
 
+#[test]
+fn peer_trace_is_toggled_per_username() {
+    let client = Client::new("test-user", "test-password");
+    client.set_peer_trace("alice", true);
+    assert!(client.is_peer_traced("alice"));
+    assert!(!client.is_peer_traced("bob"));
+
+    client.set_peer_trace("alice", false);
+    assert!(!client.is_peer_traced("alice"));
+}
+
 #[test]
 fn test_client_removes_only_queued_downloads() {
     let client = Client::new("test-user", "test-password");
@@ -480,6 +1831,8 @@ fn test_client_removes_only_queued_downloads() {
             bytes_downloaded: 25,
             total_bytes: 100,
             speed_bytes_per_sec: 10.0,
+            average_speed_bytes_per_sec: 10.0,
+            eta: None,
         },
         sender: mpsc::channel().0,
         queue_position: None,
@@ -499,13 +1852,28 @@ fn test_client_removes_only_queued_downloads() {
     assert!(context.get_download_by_token(456).is_some());
 }
 
+fn default_worker_threads() -> usize {
+    thread::available_parallelism().map_or(8, std::num::NonZero::get)
+}
+
 impl ClientContext {
     #[must_use]
     pub fn new() -> Self {
-        let max_threads =
-            thread::available_parallelism().map_or(8, std::num::NonZero::get);
+        Self::with_worker_threads(
+            default_worker_threads(),
+            DEFAULT_MAX_WORKER_THREADS,
+        )
+    }
 
-        let thread_pool = Arc::new(ThreadPool::new(max_threads));
+    /// A context whose actors run on between `min` and `max` threads.
+    #[must_use]
+    pub fn with_worker_threads(min: usize, max: usize) -> Self {
+        let thread_pool = Arc::new(ThreadPool::with_bounds(
+            "peer-worker",
+            min,
+            max.max(min).max(1),
+            ThreadPool::DEFAULT_IDLE_TIMEOUT,
+        ));
         let actor_system = Arc::new(ActorSystem::new(thread_pool));
 
         Self {
@@ -513,8 +1881,22 @@ impl ClientContext {
             sender: None,
             server_sender: None,
             searches: HashMap::new(),
+            search_listeners: HashMap::new(),
+            search_filter: None,
+            excluded_phrases: Vec::new(),
+            metrics: Arc::default(),
+            result_ranker: None,
+            max_results_per_search: Some(DEFAULT_MAX_RESULTS_PER_SEARCH),
+            max_search_results: Some(DEFAULT_MAX_SEARCH_RESULTS),
+            search_ttl: Some(DEFAULT_SEARCH_TTL),
+            source_search_time: Some(DEFAULT_SOURCE_SEARCH_TIME),
+            abandoned_downloads: HashSet::new(),
+            max_active_downloads: None,
+            leech_filter: None,
+            ignore_list: Arc::default(),
             private_messages: Vec::new(),
             pending_connect_tokens: HashMap::new(),
+            unreachable_peers: HashSet::new(),
             shares: Arc::new(Shares::empty()),
             shared_directories: Vec::new(),
             peer_addresses: HashMap::new(),
@@ -522,24 +1904,75 @@ impl ClientContext {
             uploads: HashMap::new(),
             active_uploads: HashMap::new(),
             pending_serves: HashMap::new(),
+            upload_queue: VecDeque::new(),
+            upload_slots: DEFAULT_UPLOAD_SLOTS,
+            leech_checks: HashMap::new(),
+            search_limiter: None,
+            parent_link: distributed::ParentLink::default(),
+            upload_throttle: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            disk_space_margin: 0,
+            conflict_policy: ConflictPolicy::Overwrite,
+            message_size_limits: MessageSizeLimits::default(),
+            peer_tls: None,
+            history: DownloadHistory::in_memory(),
+            resuming: HashSet::new(),
+            download_hook: None,
+            download_writers: HashMap::new(),
             browse_results: HashMap::new(),
             room_list: Vec::new(),
             room_events: Vec::new(),
+            user_info: HashMap::new(),
+            favorites: HashSet::new(),
+            privileged_users: HashSet::new(),
+            privileges: None,
+            privilege_waiters: Vec::new(),
+            events: Vec::new(),
             downloads: DownloadStore::new(),
+            tokens: TokenGenerator::new(),
             actor_system,
         }
     }
 
     /// Apply a chat-room event: keep the room-list snapshot current and queue
     /// the event for the client/UI to drain.
+    ///
+    /// Between lists the server only reports who joins and leaves the rooms
+    /// we are in, so those rooms' user counts follow that.
     pub fn apply_room_event(&mut self, event: RoomEvent) {
-        if let RoomEvent::List(rooms) = &event {
-            self.room_list.clone_from(rooms);
+        let listed = |list: &mut Vec<RoomInfo>, name: &str| {
+            list.iter_mut().position(|room| room.name == name)
+        };
+        match &event {
+            RoomEvent::List(rooms) => self.room_list.clone_from(rooms),
+            RoomEvent::Joined { room, users } => {
+                let user_count = users.len() as u32;
+                match listed(&mut self.room_list, room) {
+                    Some(i) => self.room_list[i].user_count = user_count,
+                    None => self.room_list.push(RoomInfo {
+                        name: room.clone(),
+                        user_count,
+                        ..RoomInfo::default()
+                    }),
+                }
+            }
+            RoomEvent::UserJoined { room, .. } => {
+                if let Some(i) = listed(&mut self.room_list, room) {
+                    self.room_list[i].user_count += 1;
+                }
+            }
+            RoomEvent::UserLeft { room, .. } => {
+                if let Some(i) = listed(&mut self.room_list, room) {
+                    let count = &mut self.room_list[i].user_count;
+                    *count = count.saturating_sub(1);
+                }
+            }
+            _ => {}
         }
         self.room_events.push(event);
     }
 
-    /// The latest snapshot of the public chat-room list.
+    /// The latest snapshot of the chat-room list.
     #[must_use]
     pub fn room_list(&self) -> Vec<RoomInfo> {
         self.room_list.clone()
@@ -551,6 +1984,34 @@ impl ClientContext {
         std::mem::take(&mut self.room_events)
     }
 
+    /// Replace the privileged-user list with the server's.
+    pub fn set_privileged_users(&mut self, users: Vec<String>) {
+        self.privileged_users = users.into_iter().collect();
+        self.events.push(ClientEvent::PrivilegedUsersUpdated(
+            self.privileged_users.len(),
+        ));
+    }
+
+    pub fn add_privileged_user(&mut self, username: String) {
+        self.privileged_users.insert(username.clone());
+        self.events.push(ClientEvent::PrivilegedUserAdded(username));
+    }
+
+    #[must_use]
+    pub fn is_privileged(&self, username: &str) -> bool {
+        self.privileged_users.contains(username)
+    }
+
+    pub fn push_event(&mut self, event: ClientEvent) {
+        self.events.push(event);
+    }
+
+    /// Remove and return all server notices received since the last call.
+    #[must_use]
+    pub fn take_events(&mut self) -> Vec<ClientEvent> {
+        std::mem::take(&mut self.events)
+    }
+
     /// Cache a peer's listen address learned from a GetPeerAddress response.
     pub fn cache_peer_address(
         &mut self,
@@ -618,6 +2079,74 @@ impl ClientContext {
         self.pending_connect_tokens.remove(&token)
     }
 
+    /// The counters shared with the server and peer connections.
+    #[must_use]
+    pub const fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// The metrics, with the peers connected now and the bytes of uploads
+    /// still running.
+    fn metrics_snapshot(&self) -> MetricsSnapshot {
+        let mut snapshot = self.metrics.snapshot();
+        snapshot.peers_connected =
+            self.peer_registry.as_ref().map_or(0, PeerRegistry::count);
+        snapshot.bytes_uploaded += self
+            .active_uploads
+            .values()
+            .filter(|upload| {
+                matches!(upload.status, crate::types::UploadStatus::InProgress)
+            })
+            .map(|upload| upload.bytes_sent.load(Ordering::Relaxed))
+            .sum::<u64>();
+        snapshot
+    }
+
+    /// Fail unfinished downloads with `reason` and cancel active uploads.
+    /// Streams already running stop once their socket errors.
+    pub fn stop_transfers(&mut self, reason: &str) {
+        let failed = self.downloads.fail_unfinished(reason);
+        for _ in 0..failed {
+            self.metrics.download_finished(true);
+        }
+        if failed > 0 {
+            info!("Failed {} unfinished downloads: {}", failed, reason);
+        }
+        for upload in self.active_uploads.values() {
+            upload.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Record that `username` cannot be connected to in either direction.
+    /// Pushes [`ClientEvent::PeerUnreachable`] the first time.
+    pub fn mark_peer_unreachable(&mut self, username: &str) {
+        if self.unreachable_peers.insert(username.to_string()) {
+            self.events
+                .push(ClientEvent::PeerUnreachable(username.to_string()));
+        }
+    }
+
+    /// Forget that `username` was unreachable; a connection just succeeded.
+    pub fn mark_peer_reachable(&mut self, username: &str) {
+        self.unreachable_peers.remove(username);
+    }
+
+    #[must_use]
+    pub fn is_peer_unreachable(&self, username: &str) -> bool {
+        self.unreachable_peers.contains(username)
+    }
+
+    /// Tell the server we could not connect back to `username` for its
+    /// brokered `token`, so it can report CantConnectToPeer to them.
+    pub fn report_cant_connect(&self, token: u32, username: &str) {
+        if let Some(server) = &self.server_sender {
+            let message = crate::message::server::MessageFactory::build_cant_connect_to_peer(
+                token, username,
+            );
+            let _ = server.send(ServerMessage::SendMessage(message));
+        }
+    }
+
     /// Record a private message received from another user.
     pub fn push_private_message(&mut self, message: UserMessage) {
         self.private_messages.push(message);
@@ -635,21 +2164,85 @@ pub struct Client {
     username: String,
     password: String,
     shared_directories: Vec<String>,
+    share_index_file: Option<std::path::PathBuf>,
+    share_rescan_interval: Option<Duration>,
+    /// Taken by the first shutdown, so a second can't overwrite the saved
+    /// queue with the downloads the first one failed.
+    download_queue_file: Option<std::path::PathBuf>,
+    tls: Option<TlsSettings>,
+    server_send_rate: Option<SendRateLimit>,
+    server_send_stats: Arc<ServerSendStats>,
+    peer_trace: Arc<PeerTrace>,
+    ignore_list: Arc<IgnoreList>,
+    server_handlers: Arc<CustomHandlers<ServerMessage>>,
+    peer_handlers: Arc<CustomHandlers<PeerMessage>>,
+    readiness: Arc<readiness::Readiness>,
+    keepalive_interval: Duration,
+    server_silence_timeout: Duration,
+    reconnect_when_relogged: bool,
+    peer_mailbox: Mailbox,
+    server_mailbox: Mailbox,
     server_handle: Option<ActorHandle<ServerMessage>>,
     context: Arc<RwLock<ClientContext>>,
+    connection_state: Arc<RwLock<ConnectionState>>,
+    /// Tells the listener thread to exit on its next wake-up.
+    stop_listener: Arc<AtomicBool>,
+    /// Tells the share refresh thread to exit.
+    stop_share_refresh: Arc<AtomicBool>,
+    /// The listener, operations-loop and share refresh threads, joined on
+    /// shutdown.
+    threads: Vec<thread::JoinHandle<()>>,
 }
 
 impl Client {
+    /// Deprecated: use [`Client::with_settings`], which takes every option.
     pub fn new(
         username: impl Into<String>,
         password: impl Into<String>,
     ) -> Self {
+        deprecation::warn_once(&deprecation::CLIENT_NEW);
         Self::with_settings(ClientSettings::new(username, password))
     }
 
     #[must_use]
     pub fn with_settings(settings: ClientSettings) -> Self {
         logger::init();
+        charset::set_fallbacks(&settings.fallback_charsets);
+        let mut context = ClientContext::with_worker_threads(
+            settings.min_worker_threads,
+            settings.max_worker_threads,
+        );
+        context.upload_slots = settings.upload_slots;
+        context.upload_throttle =
+            settings.max_upload_rate_kbps.map(uploads::upload_throttle);
+        context.progress_interval = settings.progress_interval;
+        context.disk_space_margin = settings.disk_space_margin;
+        context.conflict_policy = settings.conflict_policy;
+        context.download_hook = settings.on_download_complete;
+        context.message_size_limits = settings.message_size_limits;
+        context.peer_tls = settings.peer_tls.map(Arc::new);
+        if let Some(file) = settings.history_file {
+            match DownloadHistory::open(&file) {
+                Ok(history) => context.history = history,
+                Err(e) => warn!(
+                    "[client] cannot read download history {}: {}",
+                    file.display(),
+                    e
+                ),
+            }
+        }
+        context.search_filter = settings.search_filter;
+        context.result_ranker = settings.result_ranker;
+        context.max_results_per_search = settings.max_results_per_search;
+        context.max_search_results = settings.max_search_results;
+        context.search_ttl = settings.search_ttl;
+        context.source_search_time = settings.source_search_time;
+        context.max_active_downloads = settings.max_active_downloads;
+        context.leech_filter = settings.leech_filter;
+        let ignore_list = Arc::new(IgnoreList::new(settings.ignored_users));
+        context.ignore_list = ignore_list.clone();
+        context.favorites = settings.favorite_users.into_iter().collect();
+        context.search_limiter = settings.search_limits.map(SearchLimiter::new);
         Self {
             enable_listen: settings.enable_listen,
             listen_port: settings.listen_port,
@@ -657,11 +2250,213 @@ impl Client {
             username: settings.username,
             password: settings.password,
             shared_directories: settings.shared_directories,
-            context: Arc::new(RwLock::new(ClientContext::new())),
+            share_index_file: settings.share_index_file,
+            share_rescan_interval: settings.share_rescan_interval,
+            download_queue_file: settings.download_queue_file,
+            tls: settings.tls,
+            server_send_rate: settings.server_send_rate,
+            server_send_stats: Arc::default(),
+            peer_trace: Arc::default(),
+            ignore_list,
+            server_handlers: Arc::default(),
+            peer_handlers: Arc::default(),
+            readiness: Arc::default(),
+            keepalive_interval: settings.keepalive_interval,
+            server_silence_timeout: settings.server_silence_timeout,
+            reconnect_when_relogged: settings.reconnect_when_relogged,
+            peer_mailbox: settings.peer_mailbox,
+            server_mailbox: settings.server_mailbox,
+            context: Arc::new(RwLock::new(context)),
             server_handle: None,
+            connection_state: Arc::default(),
+            stop_listener: Arc::default(),
+            stop_share_refresh: Arc::default(),
+            threads: Vec::new(),
         }
     }
 
+    /// Counters of what the client has done since it was created: bytes
+    /// transferred, messages by code, searches, finished transfers and the
+    /// peers connected now.
+    #[must_use]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.context
+            .read_safe()
+            .map(|ctx| ctx.metrics_snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Serve [`Client::metrics`] on `address` for Prometheus to scrape, at
+    /// `/metrics`, until the returned exporter is dropped.
+    ///
+    /// # Errors
+    /// Returns the error binding `address`.
+    #[cfg(feature = "metrics-prometheus")]
+    pub fn serve_metrics(
+        &self,
+        address: impl std::net::ToSocketAddrs,
+    ) -> std::io::Result<crate::metrics::prometheus::Exporter> {
+        let context = self.context.clone();
+        crate::metrics::prometheus::Exporter::spawn(address, move || {
+            context
+                .read_safe()
+                .map(|ctx| ctx.metrics_snapshot())
+                .unwrap_or_default()
+        })
+    }
+
+    /// How many server messages were sent, and how many had to wait for the
+    /// send rate limiter.
+    #[must_use]
+    pub fn server_send_stats(&self) -> ServerSendSnapshot {
+        self.server_send_stats.snapshot()
+    }
+
+    /// Counters of every running actor (the server connection and one per
+    /// peer connection), for spotting stuck or flooded connections.
+    #[must_use]
+    pub fn diagnostics(&self) -> Vec<ActorStats> {
+        self.context
+            .read_safe()
+            .map(|ctx| ctx.actor_system.stats())
+            .unwrap_or_default()
+    }
+
+    /// Log everything exchanged with `username` (decoded message summaries
+    /// and hexdumps), whatever the global log level. Applies to connections
+    /// already open as well as later ones; pass `false` to stop.
+    pub fn set_peer_trace(&self, username: &str, enabled: bool) {
+        self.peer_trace.set(username, enabled);
+    }
+
+    /// Run `handler` on server messages with its code, for messages this
+    /// crate doesn't decode, or to observe ones it does (its own handler
+    /// runs first). The server connection installs it when it next
+    /// connects, so register before [`Client::connect`] to see every message.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::LockPoisoned`] if a panic poisoned the handler
+    /// list.
+    pub fn register_server_handler<H>(&self, handler: H) -> Result<()>
+    where
+        H: 'static + MessageHandler<ServerMessage> + Sync,
+    {
+        self.server_handlers.add(handler)
+    }
+
+    /// Like [`Client::register_server_handler`], for peer messages. Peer
+    /// connections opened from then on install it.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::LockPoisoned`] if a panic poisoned the handler
+    /// list.
+    pub fn register_peer_handler<H>(&self, handler: H) -> Result<()>
+    where
+        H: 'static + MessageHandler<PeerMessage> + Sync,
+    {
+        self.peer_handlers.add(handler)
+    }
+
+    /// Pass server messages no handler takes to `handler`, instead of
+    /// logging and dropping them. Replaces any earlier fallback.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::LockPoisoned`] if a panic poisoned the handler
+    /// list.
+    pub fn set_server_fallback<H>(&self, handler: H) -> Result<()>
+    where
+        H: 'static + MessageHandler<ServerMessage> + Sync,
+    {
+        self.server_handlers.set_fallback(handler)
+    }
+
+    /// Like [`Client::set_server_fallback`], for peer messages.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::LockPoisoned`] if a panic poisoned the handler
+    /// list.
+    pub fn set_peer_fallback<H>(&self, handler: H) -> Result<()>
+    where
+        H: 'static + MessageHandler<PeerMessage> + Sync,
+    {
+        self.peer_handlers.set_fallback(handler)
+    }
+
+    /// Run `interceptor` on every server message, received or sent, before
+    /// it is handled or written. It may rewrite the message or drop it.
+    /// Installed, like handlers, when the server connection next connects.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::LockPoisoned`] if a panic poisoned the
+    /// interceptor list.
+    pub fn add_server_interceptor<I>(&self, interceptor: I) -> Result<()>
+    where
+        I: 'static + Interceptor,
+    {
+        self.server_handlers.add_interceptor(interceptor)
+    }
+
+    /// Like [`Client::add_server_interceptor`], for the messages of peer
+    /// connections opened from then on.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::LockPoisoned`] if a panic poisoned the
+    /// interceptor list.
+    pub fn add_peer_interceptor<I>(&self, interceptor: I) -> Result<()>
+    where
+        I: 'static + Interceptor,
+    {
+        self.peer_handlers.add_interceptor(interceptor)
+    }
+
+    /// The username this client logs in as.
+    #[must_use]
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Where the client is in its startup sequence.
+    #[must_use]
+    pub fn state(&self) -> ClientState {
+        self.readiness.state()
+    }
+
+    /// The server connection's current state. Changes are also reported
+    /// through [`Client::take_events`] as
+    /// [`ClientEvent::ConnectionStateChanged`].
+    #[must_use]
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection_state
+            .read_safe()
+            .map(|state| state.clone())
+            .unwrap_or_default()
+    }
+
+    /// A channel receiving each startup state change from now on.
+    #[must_use]
+    pub fn subscribe_state(&self) -> Receiver<ClientState> {
+        self.readiness.subscribe()
+    }
+
+    /// Remove and return all server notices (admin messages, privilege
+    /// updates, being logged in elsewhere, favorites coming online,
+    /// downloads moved to another source) received since the last call.
+    #[must_use]
+    pub fn take_events(&self) -> Vec<ClientEvent> {
+        match self.context.write_safe() {
+            Ok(mut ctx) => ctx.take_events(),
+            Err(e) => {
+                error!("[client] take_events: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn is_peer_traced(&self, username: &str) -> bool {
+        self.peer_trace.is_traced(username)
+    }
+
     /// The directories whose files are currently shared with other peers.
     #[must_use]
     pub fn shared_directories(&self) -> Vec<String> {
@@ -680,7 +2475,7 @@ impl Client {
     }
 
     /// Snapshot of the uploads served this session (active and finished),
-    /// most recent last.
+    /// most recent last, followed by requests still waiting for a slot.
     #[must_use]
     pub fn uploads(&self) -> Vec<crate::types::UploadInfo> {
         self.context.read_safe().map_or_else(
@@ -688,6 +2483,18 @@ impl Client {
             |ctx| {
                 let mut tokens: Vec<&u32> = ctx.active_uploads.keys().collect();
                 tokens.sort_unstable();
+                let queued = ctx.upload_queue.iter().zip(1u32..).map(
+                    |(queued, place)| crate::types::UploadInfo {
+                        username: queued.downloader.clone(),
+                        filename: queued.filename.clone(),
+                        size: ctx
+                            .shares
+                            .get(&queued.filename)
+                            .map_or(0, |file| file.size),
+                        bytes_sent: 0,
+                        status: crate::types::UploadStatus::Queued { place },
+                    },
+                );
                 tokens
                     .into_iter()
                     .map(|token| {
@@ -702,6 +2509,7 @@ impl Client {
                             status: upload.status.clone(),
                         }
                     })
+                    .chain(queued)
                     .collect()
             },
         )
@@ -740,11 +2548,9 @@ impl Client {
             .filter(|dir| !dir.trim().is_empty())
             .map(std::path::PathBuf::from)
             .collect();
-        let shares = if roots.is_empty() {
-            Shares::empty()
-        } else {
-            Shares::scan_many(&roots)
-        };
+        let previous = self.context.read_safe()?.shares.clone();
+        let shares =
+            scan_shares(&roots, &previous, self.share_index_file.as_deref());
         info!(
             "Now sharing {} files in {} folders from {} directories",
             shares.file_count(),
@@ -758,18 +2564,38 @@ impl Client {
             ctx.shares = Arc::new(shares);
             ctx.shared_directories = dirs;
         }
-        self.send_server_message(
-            crate::message::server::MessageFactory::build_shared_folders_message(
-                folder_count,
-                file_count,
-            ),
-        )
+        self.server_handle
+            .as_ref()
+            .ok_or(SoulseekRs::NotConnected)?
+            .send(ServerMessage::SetSharedCounts {
+                folders: folder_count,
+                files: file_count,
+            })
+            .map_err(|_| SoulseekRs::NotConnected)
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shutdown();
     }
 }
 
 mod connection;
+mod distributed;
 mod downloads;
+mod favorites;
 mod operations;
+mod priority;
+mod readiness;
 mod rooms;
 mod search;
+mod share_refresh;
+mod sources;
 mod uploads;
+mod users;
+
+pub use distributed::DistributedParent;
+pub use downloads::DownloadStream;
+pub use readiness::{ClientState, Readiness};
+pub use search::{SearchPage, SearchStream};
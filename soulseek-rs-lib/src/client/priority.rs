@@ -0,0 +1,144 @@
+//! Download priority: past
+//! [`ClientSettings::max_active_downloads`](super::ClientSettings::max_active_downloads),
+//! new downloads are held and asked for in the store's order, which
+//! [`Client::reorder_download`] changes.
+
+use super::{
+    Client, ClientContext, Download, DownloadStatus, RwLockExt, ServerMessage,
+    error, info,
+};
+use crate::types::FailureReason;
+
+impl ClientContext {
+    /// Whether one more download may be asked for without going over the
+    /// limit of active ones.
+    pub(super) fn has_download_slot(&self) -> bool {
+        self.max_active_downloads
+            .is_none_or(|max| self.downloads.active_count() < max)
+    }
+
+    /// Ask for held downloads, first to last, while there is room.
+    pub(super) fn start_held_downloads(&mut self) {
+        while self.has_download_slot()
+            && let Some(download) = self.downloads.next_held().cloned()
+        {
+            self.downloads.release(download.token);
+            info!(
+                "[client] Asking {} for held {}",
+                download.username, download.filename
+            );
+            if !self.request_download(&download) {
+                let reason =
+                    Some(FailureReason::from("Could not connect to the user"));
+                let _ = download
+                    .sender
+                    .send(DownloadStatus::Failed(reason.clone()));
+                self.update_download_with_status(
+                    download.token,
+                    DownloadStatus::Failed(reason),
+                );
+            }
+        }
+    }
+
+    /// Ask `download`'s uploader for it, over our connection to them or,
+    /// lacking one, by asking the server where to reach them.
+    fn request_download(&self, download: &Download) -> bool {
+        match &self.peer_registry {
+            Some(registry) if registry.contains(&download.username) => registry
+                .queue_upload(&download.username, download.filename.clone())
+                .is_ok(),
+            _ => self.server_sender.as_ref().is_some_and(|server| {
+                server
+                    .send(ServerMessage::GetPeerAddress(
+                        download.username.clone(),
+                    ))
+                    .is_ok()
+            }),
+        }
+    }
+}
+
+impl Client {
+    /// Move download `token` to `position` in the priority order, `0` being
+    /// first and anything past the end last. Held downloads are asked for
+    /// in this order, so moving one up starts it sooner;
+    /// [`Client::get_all_downloads`] lists them in it. Returns whether the
+    /// download exists.
+    #[must_use]
+    pub fn reorder_download(&self, token: u32, position: usize) -> bool {
+        match self.context.write_safe() {
+            Ok(mut ctx) => ctx.downloads.move_to(token, position),
+            Err(e) => {
+                error!("[client] reorder_download: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Whether download `token` is held, not yet asked for, until enough
+    /// others finish.
+    #[must_use]
+    pub fn is_download_held(&self, token: u32) -> bool {
+        self.context
+            .read_safe()
+            .is_ok_and(|ctx| ctx.downloads.is_held(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DownloadMetadata;
+    use std::sync::{Arc, RwLock, mpsc};
+
+    #[test]
+    fn held_downloads_start_in_priority_order_as_others_finish() {
+        let mut ctx = ClientContext::new();
+        let (server, sent) = mpsc::channel();
+        ctx.server_sender = Some(server);
+        ctx.max_active_downloads = Some(1);
+        let ctx = Arc::new(RwLock::new(ctx));
+
+        let tokens: Vec<u32> = ["amy", "bob", "cat"]
+            .into_iter()
+            .map(|username| {
+                let download = Download {
+                    username: username.to_string(),
+                    filename: format!("{username}.flac"),
+                    token: 0,
+                    size: 1,
+                    download_directory: std::env::temp_dir()
+                        .to_string_lossy()
+                        .into_owned(),
+                    status: DownloadStatus::Queued,
+                    sender: mpsc::channel().0,
+                    queue_position: None,
+                    metadata: DownloadMetadata::default(),
+                };
+                Client::queue_download(&ctx, download, |_| true)
+                    .unwrap()
+                    .token
+            })
+            .collect();
+
+        let mut ctx = ctx.write().unwrap();
+        assert!(!ctx.downloads.is_held(tokens[0]));
+        assert!(ctx.downloads.is_held(tokens[1]));
+        assert!(ctx.downloads.is_held(tokens[2]));
+
+        assert!(ctx.downloads.move_to(tokens[2], 0));
+        ctx.update_download_with_status(
+            tokens[0],
+            DownloadStatus::Completed(None),
+        );
+        assert!(!ctx.downloads.is_held(tokens[2]));
+        assert!(ctx.downloads.is_held(tokens[1]));
+        let Ok(ServerMessage::GetPeerAddress(username)) = sent.try_recv()
+        else {
+            panic!("expected a GetPeerAddress");
+        };
+        assert_eq!(username, "cat");
+        assert!(sent.try_recv().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Client startup state, and the barrier the listener waits on.
+//!
+//! `connect` starts the peer listener on its own thread while login is still
+//! in flight, so a peer can connect before the operations loop and the peer
+//! registry are in place. The listener holds accepted connections until the
+//! client reaches [`ClientState::Ready`].
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::utils::lock::MutexExt;
+
+/// Where the client is in its startup sequence. States only move forward,
+/// `Disconnected` → `Connecting` → `LoggedIn` → `Ready`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ClientState {
+    /// `connect` has not been called.
+    #[default]
+    Disconnected,
+    /// Connected to the server; login has not succeeded yet.
+    Connecting,
+    /// The server accepted our login.
+    LoggedIn,
+    /// Logged in, with the operations loop and peer registry running:
+    /// incoming peer connections are handled from here on.
+    Ready,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: ClientState,
+    /// Set once the operations loop and registry are in place, which may
+    /// happen before or after login.
+    operations_ready: bool,
+    listeners: Vec<Sender<ClientState>>,
+}
+
+/// Shared startup state with a blocking wait for [`ClientState::Ready`].
+#[derive(Debug, Default)]
+pub struct Readiness {
+    inner: Mutex<Inner>,
+    changed: Condvar,
+}
+
+impl Readiness {
+    #[must_use]
+    pub fn state(&self) -> ClientState {
+        self.inner
+            .lock_safe()
+            .map_or(ClientState::Disconnected, |inner| inner.state)
+    }
+
+    /// A channel receiving every later state change.
+    pub fn subscribe(&self) -> Receiver<ClientState> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut inner) = self.inner.lock_safe() {
+            inner.listeners.push(sender);
+        }
+        receiver
+    }
+
+    pub fn connecting(&self) {
+        self.update(|inner| {
+            inner.state = inner.state.max(ClientState::Connecting);
+        });
+    }
+
+    pub fn logged_in(&self) {
+        self.update(|inner| {
+            inner.state = inner.state.max(ClientState::LoggedIn);
+        });
+    }
+
+    pub fn operations_ready(&self) {
+        self.update(|inner| inner.operations_ready = true);
+    }
+
+    /// Block until the client is [`ClientState::Ready`], for at most
+    /// `timeout`. Returns whether it got there.
+    #[must_use]
+    pub fn wait_ready(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let Ok(mut inner) = self.inner.lock_safe() else {
+            return false;
+        };
+        while inner.state != ClientState::Ready {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            inner = match self.changed.wait_timeout(inner, deadline - now) {
+                Ok((guard, _)) => guard,
+                Err(_) => return false,
+            };
+        }
+        true
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Inner)) {
+        let Ok(mut inner) = self.inner.lock_safe() else {
+            return;
+        };
+        let before = inner.state;
+        change(&mut inner);
+        if inner.state == ClientState::LoggedIn && inner.operations_ready {
+            inner.state = ClientState::Ready;
+        }
+        let after = inner.state;
+        if after != before {
+            // Report each step, so subscribers see LoggedIn before Ready.
+            let steps = [
+                ClientState::Connecting,
+                ClientState::LoggedIn,
+                ClientState::Ready,
+            ];
+            inner.listeners.retain(|listener| {
+                steps
+                    .iter()
+                    .filter(|&&step| step > before && step <= after)
+                    .all(|&step| listener.send(step).is_ok())
+            });
+            self.changed.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientState, Readiness};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn ready_needs_both_login_and_the_operations_loop() {
+        let readiness = Readiness::default();
+        let events = readiness.subscribe();
+        readiness.operations_ready();
+        assert_eq!(readiness.state(), ClientState::Disconnected);
+        readiness.connecting();
+        readiness.logged_in();
+        assert_eq!(readiness.state(), ClientState::Ready);
+        // States never move backwards.
+        readiness.connecting();
+        assert_eq!(readiness.state(), ClientState::Ready);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [
+                ClientState::Connecting,
+                ClientState::LoggedIn,
+                ClientState::Ready
+            ]
+        );
+    }
+
+    #[test]
+    fn wait_ready_blocks_until_ready_or_timeout() {
+        let readiness = Arc::new(Readiness::default());
+        assert!(!readiness.wait_ready(Duration::from_millis(10)));
+
+        let waiter = {
+            let readiness = readiness.clone();
+            std::thread::spawn(move || {
+                readiness.wait_ready(Duration::from_secs(5))
+            })
+        };
+        readiness.logged_in();
+        readiness.operations_ready();
+        assert!(waiter.join().unwrap());
+    }
+}
@@ -89,7 +89,8 @@ impl Client {
         )
     }
 
-    /// The latest snapshot of the public chat-room list.
+    /// The chat rooms the server last listed, public and private, with the
+    /// user counts of rooms we are in kept current since.
     #[must_use]
     pub fn room_list(&self) -> Vec<RoomInfo> {
         match self.context.read_safe() {
@@ -119,8 +120,19 @@ impl Client {
     /// # Errors
     /// Returns an error if the client's context lock is poisoned.
     pub fn browse_user(&self, username: &str) -> Result<()> {
-        let request =
-            crate::message::server::MessageFactory::build_get_share_file_list();
+        self.send_peer_request(
+            username,
+            crate::message::server::MessageFactory::build_get_share_file_list(),
+        )
+    }
+
+    /// Send `request` to `username` over the existing peer connection, or
+    /// queue it and resolve their address so it goes out once connected.
+    pub(super) fn send_peer_request(
+        &self,
+        username: &str,
+        request: crate::message::Message,
+    ) -> Result<()> {
         let (connected, registry) = {
             let ctx = self.context.read_safe()?;
             (
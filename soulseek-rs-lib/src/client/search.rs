@@ -1,15 +1,357 @@
 use super::{
-    Arc, AtomicBool, Client, Duration, HashMap, Instant, Ordering, Result,
-    RwLockExt, Search, SearchResult, ServerMessage, SoulseekRs, info, md5,
-    sleep,
+    Arc, AtomicBool, Client, ClientContext, Duration, HashMap, Instant,
+    Ordering, RankedFile, Receiver, Result, ResultRanker, RwLockExt, Search,
+    SearchFilter, SearchResult, Sender, ServerMessage, SoulseekRs, TokenOwner,
+    debug, deprecation, error, info, mpsc, result_ranker, warn,
 };
+use crate::result_ranker::DefaultRanker;
+use std::sync::{RwLock, Weak};
+
+/// How often a blocking search wakes up to check its cancel flag.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Search results as they arrive, until the search's timeout elapses.
+///
+/// Returned by [`Client::search_stream`]. Each item is one peer's
+/// `FileSearchResponse`; iteration ends at the deadline (or if the client
+/// shuts down). The results are also kept, as with [`Client::search`].
+/// Dropping the stream stops forwarding results to it.
+pub struct SearchStream {
+    receiver: Receiver<SearchResult>,
+    deadline: Instant,
+    token: u32,
+    context: Weak<RwLock<ClientContext>>,
+}
+
+impl SearchStream {
+    /// Stream search `token`'s results from `receiver` until `timeout`
+    /// elapses.
+    pub(super) fn new(
+        context: &Arc<RwLock<ClientContext>>,
+        token: u32,
+        receiver: Receiver<SearchResult>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            receiver,
+            deadline: Instant::now() + timeout,
+            token,
+            context: Arc::downgrade(context),
+        }
+    }
+}
+
+impl Drop for SearchStream {
+    fn drop(&mut self) {
+        if let Some(context) = self.context.upgrade()
+            && let Ok(mut context) = context.write_safe()
+        {
+            context.end_search_stream(self.token);
+        }
+    }
+}
+
+impl Iterator for SearchStream {
+    type Item = SearchResult;
+
+    fn next(&mut self) -> Option<SearchResult> {
+        let remaining = self.deadline.checked_duration_since(Instant::now())?;
+        self.receiver.recv_timeout(remaining).ok()
+    }
+}
+
+/// One page of a search's results, from
+/// [`Client::get_search_results_page`].
+#[derive(Debug, Clone, Default)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    /// How many results the search holds in all.
+    pub total: usize,
+}
+
+impl ClientContext {
+    /// Forward a freshly received result to everyone streaming its search,
+    /// dropping listeners that went away.
+    pub(super) fn notify_search_listeners(&mut self, result: &SearchResult) {
+        if let Some(listeners) = self.search_listeners.get_mut(&result.token) {
+            listeners.retain(|listener| listener.send(result.clone()).is_ok());
+            if listeners.is_empty() {
+                self.search_listeners.remove(&result.token);
+            }
+        }
+    }
+
+    /// Stop forwarding the results of search `token`. The search itself is
+    /// still kept.
+    pub(super) fn end_search_stream(&mut self, token: u32) {
+        self.search_listeners.remove(&token);
+    }
+
+    /// Whether `text` contains a phrase the server excludes, ignoring case.
+    pub(super) fn is_excluded(&self, text: &str) -> bool {
+        if self.excluded_phrases.is_empty() {
+            return false;
+        }
+        let text = text.to_lowercase();
+        self.excluded_phrases
+            .iter()
+            .any(|phrase| text.contains(&phrase.to_lowercase()))
+    }
+
+    /// Drop the files in `result` whose path contains an excluded phrase;
+    /// `None` if that leaves nothing.
+    pub(super) fn without_excluded(
+        &self,
+        mut result: SearchResult,
+    ) -> Option<SearchResult> {
+        result.files.retain(|file| !self.is_excluded(&file.name));
+        result
+            .locked_files
+            .retain(|file| !self.is_excluded(&file.name));
+        (!result.files.is_empty() || !result.locked_files.is_empty())
+            .then_some(result)
+    }
+
+    /// Drop every kept result from `username`.
+    pub(super) fn forget_results_from(&mut self, username: &str) {
+        for search in self.searches.values_mut() {
+            search.results.retain(|result| result.username != username);
+        }
+    }
+
+    /// Keep a search for `query` under a fresh token, streaming its results
+    /// to `listener`, and return the token. A search already kept for the
+    /// query is replaced.
+    pub(super) fn register_search(
+        &mut self,
+        query: &str,
+        listener: Sender<SearchResult>,
+    ) -> u32 {
+        self.expire_searches(Instant::now());
+        let token = self.tokens.issue(TokenOwner::Search {
+            query: query.to_string(),
+        });
+        let previous = self.searches.insert(
+            query.to_string(),
+            Search {
+                token,
+                results: Vec::new(),
+                updated: Instant::now(),
+            },
+        );
+        // The query now belongs to the new token; results still arriving
+        // for the previous run are dropped, and its stream ends.
+        if let Some(previous) = previous {
+            self.tokens.release(previous.token);
+            self.end_search_stream(previous.token);
+        }
+        self.search_listeners
+            .entry(token)
+            .or_default()
+            .push(listener);
+        token
+    }
+
+    /// The query of the search `token` was issued for, while the search is
+    /// kept.
+    fn search_query(&self, token: u32) -> Option<&str> {
+        match self.tokens.owner(token)? {
+            TokenOwner::Search { query } => Some(query),
+            TokenOwner::Download { .. } | TokenOwner::SourceSearch { .. } => {
+                None
+            }
+        }
+    }
+
+    /// Whether a result with `token` answers a search we still keep, or a
+    /// source search still streamed.
+    pub(super) fn expects_search_result(&self, token: u32) -> bool {
+        self.search_query(token).is_some()
+            || self.search_listeners.contains_key(&token)
+    }
+
+    /// Add a peer's response to the search it answers, then enforce the
+    /// result caps and forget searches idle past the TTL.
+    pub(super) fn store_search_result(&mut self, result: SearchResult) {
+        let now = Instant::now();
+        let Some(query) = self.search_query(result.token).map(str::to_owned)
+        else {
+            return;
+        };
+        if let Some(search) = self.searches.get_mut(&query) {
+            search.results.push(result);
+            search.updated = now;
+            if let Some(cap) = self.max_results_per_search
+                && search.results.len() > cap
+            {
+                let ranker = self.result_ranker.as_deref();
+                trim_search(search, &query, ranker, with_slack(cap));
+            }
+        }
+        self.enforce_global_cap();
+        self.expire_searches(now);
+    }
+
+    /// Trim the searches fed longest ago until the total is under
+    /// [`ClientContext::max_search_results`].
+    fn enforce_global_cap(&mut self) {
+        let Some(cap) = self.max_search_results else {
+            return;
+        };
+        let mut total: usize =
+            self.searches.values().map(|s| s.results.len()).sum();
+        if total <= cap {
+            return;
+        }
+        let target = with_slack(cap);
+        let mut stalest: Vec<String> = self.searches.keys().cloned().collect();
+        stalest.sort_by_key(|query| self.searches[query].updated);
+        let ranker = self.result_ranker.clone();
+        for query in stalest {
+            if total <= target {
+                break;
+            }
+            let Some(search) = self.searches.get_mut(&query) else {
+                continue;
+            };
+            let len = search.results.len();
+            let keep = len.saturating_sub(total - target);
+            trim_search(search, &query, ranker.as_deref(), keep);
+            total -= len - search.results.len();
+        }
+    }
+
+    /// Forget searches that received nothing for longer than the TTL.
+    fn expire_searches(&mut self, now: Instant) {
+        let Some(ttl) = self.search_ttl else {
+            return;
+        };
+        let expired: Vec<String> = self
+            .searches
+            .iter()
+            .filter(|(_, search)| now.duration_since(search.updated) > ttl)
+            .map(|(query, _)| query.clone())
+            .collect();
+        for query in expired {
+            debug!("[client] search for {} expired", query);
+            self.remove_search(&query);
+        }
+    }
+
+    /// Drop the search for `query`, its token and anyone streaming it.
+    pub(super) fn remove_search(&mut self, query: &str) -> bool {
+        let Some(search) = self.searches.remove(query) else {
+            return false;
+        };
+        self.tokens.release(search.token);
+        self.search_listeners.remove(&search.token);
+        true
+    }
+
+    /// A copy of `limit` of the search's results from `offset` on, in the
+    /// order of [`ClientContext::sorted_search_results`].
+    fn search_results_page(
+        &self,
+        search_key: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Option<SearchPage> {
+        let results = &self.searches.get(search_key)?.results;
+        let page = match &self.result_ranker {
+            Some(ranker) => {
+                let page = result_ranker::ranked_order(
+                    ranker.as_ref(),
+                    search_key,
+                    results,
+                )
+                .into_iter()
+                .skip(offset)
+                .take(limit)
+                .map(|index| results[index].clone())
+                .collect();
+                // Already in order; this orders each result's files.
+                result_ranker::sort_results(ranker.as_ref(), search_key, page)
+            }
+            None => results.iter().skip(offset).take(limit).cloned().collect(),
+        };
+        Some(SearchPage {
+            results: page,
+            total: results.len(),
+        })
+    }
+
+    /// A copy of the search's results, ordered by the ranker if one is set.
+    fn sorted_search_results(
+        &self,
+        search_key: &str,
+    ) -> Option<Vec<SearchResult>> {
+        let results = self.searches.get(search_key)?.results.clone();
+        Some(match &self.result_ranker {
+            Some(ranker) => result_ranker::sort_results(
+                ranker.as_ref(),
+                search_key,
+                results,
+            ),
+            None => results,
+        })
+    }
+}
+
+/// Cut back to a little under `cap`, so a full search is trimmed once per
+/// batch of results rather than on every one.
+const fn with_slack(cap: usize) -> usize {
+    cap - cap / 10
+}
+
+/// Keep `keep` of the search's results: the best scored if there is a
+/// ranker, else the newest. Kept results stay in arrival order.
+fn trim_search(
+    search: &mut Search,
+    query: &str,
+    ranker: Option<&dyn ResultRanker>,
+    keep: usize,
+) {
+    let len = search.results.len();
+    if len <= keep {
+        return;
+    }
+    let Some(ranker) = ranker else {
+        search.results.drain(..len - keep);
+        return;
+    };
+    let scores: Vec<f64> = search
+        .results
+        .iter()
+        .map(|result| {
+            result
+                .files
+                .iter()
+                .map(|file| ranker.score(query, result, file))
+                .fold(f64::NEG_INFINITY, f64::max)
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..len).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let mut kept = vec![false; len];
+    for &index in &order[..keep] {
+        kept[index] = true;
+    }
+    let mut index = 0;
+    search.results.retain(|_| {
+        index += 1;
+        kept[index - 1]
+    });
+}
 
 impl Client {
+    /// Deprecated: use [`Client::search_stream`], which yields results as
+    /// they arrive instead of blocking for the whole `timeout`.
     pub fn search(
         &self,
         query: &str,
         timeout: Duration,
     ) -> Result<Vec<SearchResult>> {
+        deprecation::warn_once(&deprecation::CLIENT_SEARCH);
         self.search_with_cancel(query, timeout, None)
     }
 
@@ -19,32 +361,10 @@ impl Client {
         timeout: Duration,
         cancel_flag: Option<Arc<AtomicBool>>,
     ) -> Result<Vec<SearchResult>> {
-        info!("Searching for {}", query);
-
-        if let Some(handle) = &self.server_handle {
-            let hash = md5::md5(query);
-            let token = u32::from_str_radix(&hash[0..5], 16)?;
+        let (token, receiver) = self.start_search(query)?;
 
-            self.context.write_safe()?.searches.insert(
-                query.to_string(),
-                Search {
-                    token,
-                    results: Vec::new(),
-                },
-            );
-
-            let _ = handle.send(ServerMessage::FileSearch {
-                token,
-                query: query.to_string(),
-            });
-        } else {
-            return Err(SoulseekRs::NotConnected);
-        }
-
-        let start = Instant::now();
+        let deadline = Instant::now() + timeout;
         loop {
-            sleep(Duration::from_millis(100));
-
             // Check if cancelled
             if let Some(ref flag) = cancel_flag
                 && flag.load(Ordering::Relaxed)
@@ -54,14 +374,98 @@ impl Client {
             }
 
             // Check if timeout reached
-            if start.elapsed() >= timeout {
+            let Some(remaining) =
+                deadline.checked_duration_since(Instant::now())
+            else {
+                break;
+            };
+            // The operations loop stores the results; this only waits, waking
+            // early on each one, so cancellation is still noticed promptly.
+            let wait = remaining.min(CANCEL_POLL_INTERVAL);
+            if matches!(
+                receiver.recv_timeout(wait),
+                Err(mpsc::RecvTimeoutError::Disconnected)
+            ) {
                 break;
             }
         }
 
+        if let Ok(mut context) = self.context.write_safe() {
+            context.end_search_stream(token);
+        }
         Ok(self.get_search_results(query))
     }
 
+    /// Start a search and yield each peer's results as they arrive, instead
+    /// of blocking for the whole `timeout`.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`] if the client isn't connected.
+    pub fn search_stream(
+        &self,
+        query: &str,
+        timeout: Duration,
+    ) -> Result<SearchStream> {
+        let (token, receiver) = self.start_search(query)?;
+        Ok(SearchStream::new(&self.context, token, receiver, timeout))
+    }
+
+    /// Phrases the server excludes from searches. Searches containing one
+    /// are not sent, and received files whose path contains one are dropped.
+    #[must_use]
+    pub fn excluded_phrases(&self) -> Vec<String> {
+        self.context
+            .read_safe()
+            .map(|ctx| ctx.excluded_phrases.clone())
+            .unwrap_or_default()
+    }
+
+    /// Replace the filter applied to incoming search results. Results
+    /// already received are left as they are.
+    pub fn set_search_filter(&self, filter: Option<SearchFilter>) {
+        match self.context.write_safe() {
+            Ok(mut ctx) => ctx.search_filter = filter,
+            Err(e) => error!("[client] set_search_filter: {}", e),
+        }
+    }
+
+    /// Register the search (and a listener for its results) and send it to
+    /// the server. The listener is in place before the request goes out, so
+    /// no early response is missed. Returns the search's token and the
+    /// listener.
+    fn start_search(
+        &self,
+        query: &str,
+    ) -> Result<(u32, Receiver<SearchResult>)> {
+        info!("Searching for {}", query);
+
+        let Some(handle) = &self.server_handle else {
+            return Err(SoulseekRs::NotConnected);
+        };
+        let (sender, receiver) = mpsc::channel();
+        let (token, excluded) = {
+            let mut context = self.context.write_safe()?;
+            let token = context.register_search(query, sender);
+            let excluded = context.is_excluded(query);
+            if !excluded {
+                context.metrics.search_issued();
+            }
+            (token, excluded)
+        };
+
+        // The search is kept, so it ends empty like any other that found
+        // nothing.
+        if excluded {
+            warn!("Not searching for {}: the server excludes it", query);
+        } else {
+            let _ = handle.send(ServerMessage::FileSearch {
+                token,
+                query: query.to_string(),
+            });
+        }
+        Ok((token, receiver))
+    }
+
     #[must_use]
     pub fn get_search_results_count(&self, search_key: &str) -> usize {
         self.context
@@ -73,14 +477,31 @@ impl Client {
             .unwrap_or(0)
     }
 
+    /// Results of the search for `search_key`, most relevant first if a
+    /// [`ResultRanker`] is set, else in arrival order.
     #[must_use]
     pub fn get_search_results(&self, search_key: &str) -> Vec<SearchResult> {
         self.context
             .read_safe()
             .ok()
-            .and_then(|ctx| {
-                ctx.searches.get(search_key).map(|s| s.results.clone())
-            })
+            .and_then(|ctx| ctx.sorted_search_results(search_key))
+            .unwrap_or_default()
+    }
+
+    /// Up to `limit` results of the search for `search_key` from `offset`
+    /// on, ordered as by [`Client::get_search_results`], with the total.
+    /// Only the page is copied, so a UI can poll a large search cheaply.
+    #[must_use]
+    pub fn get_search_results_page(
+        &self,
+        search_key: &str,
+        offset: usize,
+        limit: usize,
+    ) -> SearchPage {
+        self.context
+            .read_safe()
+            .ok()
+            .and_then(|ctx| ctx.search_results_page(search_key, offset, limit))
             .unwrap_or_default()
     }
 
@@ -90,9 +511,48 @@ impl Client {
         &self,
         search_key: &str,
     ) -> Option<Vec<SearchResult>> {
-        self.context.try_read().ok().and_then(|ctx| {
-            ctx.searches.get(search_key).map(|s| s.results.clone())
-        })
+        self.context
+            .try_read()
+            .ok()
+            .and_then(|ctx| ctx.sorted_search_results(search_key))
+    }
+
+    /// Every file found by the search for `search_key` with its relevance
+    /// score, best first. Scored by the configured [`ResultRanker`], or
+    /// [`DefaultRanker`] if none is set.
+    #[must_use]
+    pub fn ranked_search_results(&self, search_key: &str) -> Vec<RankedFile> {
+        let Ok(ctx) = self.context.read_safe() else {
+            return Vec::new();
+        };
+        let Some(search) = ctx.searches.get(search_key) else {
+            return Vec::new();
+        };
+        let ranker: &dyn ResultRanker =
+            ctx.result_ranker.as_deref().unwrap_or(&DefaultRanker);
+        result_ranker::rank(ranker, search_key, &search.results)
+    }
+
+    /// Forget the search for `query` and its results, ending any stream
+    /// of it. Returns whether there was one.
+    #[must_use]
+    pub fn clear_search(&self, query: &str) -> bool {
+        match self.context.write_safe() {
+            Ok(mut ctx) => ctx.remove_search(query),
+            Err(e) => {
+                error!("[client] clear_search: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Replace the ranker ordering [`Client::get_search_results`]; `None`
+    /// restores arrival order.
+    pub fn set_result_ranker(&self, ranker: Option<Arc<dyn ResultRanker>>) {
+        match self.context.write_safe() {
+            Ok(mut ctx) => ctx.result_ranker = ranker,
+            Err(e) => error!("[client] set_result_ranker: {}", e),
+        }
     }
 
     #[must_use]
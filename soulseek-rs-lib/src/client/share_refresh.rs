@@ -0,0 +1,229 @@
+//! Keeping the share index in step with the shared directories while
+//! connected.
+//!
+//! With the `watch` feature the directories are watched (inotify, FSEvents
+//! or ReadDirectoryChangesW) and changed paths are re-indexed in batches
+//! once they settle. Whether or not they are watched, everything is
+//! rescanned every [`ClientSettings::share_rescan_interval`], which also
+//! catches whatever the watcher missed (network mounts, overflowed event
+//! queues). Counts that changed are re-announced to the server.
+//!
+//! [`ClientSettings::share_rescan_interval`]: super::ClientSettings::share_rescan_interval
+
+use super::{
+    ActorHandle, Arc, AtomicBool, ClientContext, Duration, Instant, Ordering,
+    RwLock, RwLockExt, ServerMessage, Shares, info, save_share_index,
+    scan_shares, warn,
+};
+use std::path::{Path, PathBuf};
+
+/// How often the thread checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long changed paths must stay quiet before they are re-indexed, so a
+/// file being copied in is read once it is complete.
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// The background refresher's state, owned by its thread.
+pub struct ShareRefresh {
+    pub context: Arc<RwLock<ClientContext>>,
+    pub server: ActorHandle<ServerMessage>,
+    pub index_file: Option<PathBuf>,
+    pub rescan_interval: Option<Duration>,
+    pub stop: Arc<AtomicBool>,
+}
+
+impl ShareRefresh {
+    pub fn run(self) {
+        let mut watcher = watch::Watcher::new();
+        let mut pending: Vec<PathBuf> = Vec::new();
+        let mut changed_at = Instant::now();
+        let mut scanned_at = Instant::now();
+
+        while !self.stop.load(Ordering::Acquire) {
+            let Some(current) = self.current_shares() else {
+                return;
+            };
+            watcher.follow(&current.roots().collect::<Vec<_>>());
+            let changed = watcher.changes(POLL_INTERVAL);
+            if !changed.is_empty() {
+                changed_at = Instant::now();
+                for path in changed {
+                    if !pending.contains(&path) {
+                        pending.push(path);
+                    }
+                }
+            }
+
+            if self
+                .rescan_interval
+                .is_some_and(|interval| scanned_at.elapsed() >= interval)
+            {
+                let roots: Vec<PathBuf> =
+                    current.roots().map(Path::to_path_buf).collect();
+                let shares =
+                    scan_shares(&roots, &current, self.index_file.as_deref());
+                self.replace(&current, shares);
+                pending.clear();
+                scanned_at = Instant::now();
+            } else if !pending.is_empty()
+                && changed_at.elapsed() >= SETTLE_DELAY
+            {
+                let shares = current.with_changes(&pending);
+                if let Some(file) = &self.index_file {
+                    save_share_index(&shares, file);
+                }
+                self.replace(&current, shares);
+                pending.clear();
+            }
+        }
+    }
+
+    fn current_shares(&self) -> Option<Arc<Shares>> {
+        self.context.read_safe().ok().map(|ctx| ctx.shares.clone())
+    }
+
+    /// Serve `shares` in place of `previous`, unless the index was replaced
+    /// meanwhile (the shared directories changed), and re-announce the
+    /// counts if they moved.
+    fn replace(&self, previous: &Arc<Shares>, shares: Shares) {
+        let folder_count = shares.folder_count();
+        let file_count = shares.file_count();
+        {
+            let Ok(mut ctx) = self.context.write_safe() else {
+                return;
+            };
+            if !Arc::ptr_eq(&ctx.shares, previous) {
+                return;
+            }
+            ctx.shares = Arc::new(shares);
+        }
+        if folder_count == previous.folder_count()
+            && file_count == previous.file_count()
+        {
+            return;
+        }
+        info!(
+            "Now sharing {} files in {} folders",
+            file_count, folder_count
+        );
+        let counts = ServerMessage::SetSharedCounts {
+            folders: folder_count,
+            files: file_count,
+        };
+        if self.server.send(counts).is_err() {
+            warn!("[client] cannot re-announce shared folder counts");
+        }
+    }
+}
+
+#[cfg(feature = "watch")]
+mod watch {
+    use super::{Duration, Path, PathBuf, warn};
+    use notify::{EventKind, RecursiveMode, Watcher as _};
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+
+    /// Recursive watches on the shared roots.
+    pub struct Watcher {
+        inner: Option<notify::RecommendedWatcher>,
+        events: Option<Receiver<notify::Result<notify::Event>>>,
+        roots: Vec<PathBuf>,
+    }
+
+    impl Watcher {
+        pub const fn new() -> Self {
+            Self {
+                inner: None,
+                events: None,
+                roots: Vec::new(),
+            }
+        }
+
+        /// Watch `roots`, replacing the watches if they differ from the
+        /// current ones.
+        pub fn follow(&mut self, roots: &[&Path]) {
+            if self
+                .roots
+                .iter()
+                .map(PathBuf::as_path)
+                .eq(roots.iter().copied())
+            {
+                return;
+            }
+            self.roots = roots.iter().map(|root| root.to_path_buf()).collect();
+            self.inner = None;
+            self.events = None;
+            if roots.is_empty() {
+                return;
+            }
+            let (sender, events) = mpsc::channel();
+            let mut inner = match notify::recommended_watcher(sender) {
+                Ok(inner) => inner,
+                Err(e) => {
+                    warn!("[client] cannot watch shared directories: {}", e);
+                    return;
+                }
+            };
+            for root in roots {
+                if let Err(e) = inner.watch(root, RecursiveMode::Recursive) {
+                    warn!("[client] cannot watch {}: {}", root.display(), e);
+                }
+            }
+            self.inner = Some(inner);
+            self.events = Some(events);
+        }
+
+        /// Paths created, removed, renamed or modified, waiting up to
+        /// `timeout` for the first.
+        pub fn changes(&self, timeout: Duration) -> Vec<PathBuf> {
+            let Some(events) = &self.events else {
+                std::thread::sleep(timeout);
+                return Vec::new();
+            };
+            let mut paths = Vec::new();
+            let mut next = events.recv_timeout(timeout);
+            loop {
+                match next {
+                    Ok(Ok(event)) => {
+                        if !matches!(event.kind, EventKind::Access(_)) {
+                            paths.extend(event.paths);
+                        }
+                    }
+                    Ok(Err(e)) => warn!("[client] share watcher: {}", e),
+                    Err(
+                        RecvTimeoutError::Timeout
+                        | RecvTimeoutError::Disconnected,
+                    ) => {
+                        break;
+                    }
+                }
+                next = events.try_recv().map_err(|_| RecvTimeoutError::Timeout);
+            }
+            paths
+        }
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+mod watch {
+    use super::{Duration, Path, PathBuf};
+
+    /// Without the `watch` feature nothing is watched; only the periodic
+    /// rescan picks up changes.
+    pub struct Watcher;
+
+    // Mirrors the watching `Watcher` so the loop needs no cfg of its own.
+    #[allow(clippy::unused_self, clippy::needless_pass_by_ref_mut)]
+    impl Watcher {
+        pub const fn new() -> Self {
+            Self
+        }
+
+        pub const fn follow(&mut self, _roots: &[&Path]) {}
+
+        pub fn changes(&self, timeout: Duration) -> Vec<PathBuf> {
+            std::thread::sleep(timeout);
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,478 @@
+//! Replacing the uploader of a download who disconnected, or whose transfer
+//! broke off: the file is searched for again and queued from another user
+//! sharing the same name and size.
+
+use super::{
+    Arc, Client, ClientContext, ClientEvent, Download, DownloadStatus,
+    Duration, Instant, RwLock, RwLockExt, SearchResult, Sender, ServerMessage,
+    TokenOwner, error, info, mpsc, thread, warn,
+};
+use crate::types::{FailureReason, File};
+
+impl ClientContext {
+    /// Whether `download` may be searched for elsewhere: searching for
+    /// sources is enabled, and it isn't written to a writer or stream (which
+    /// can't start over) or held (never asked for).
+    fn can_replace(&self, download: &Download) -> bool {
+        self.source_search_time.is_some()
+            && self.server_sender.is_some()
+            && !self.has_download_writer(download)
+            && !self.downloads.is_held(download.token)
+    }
+
+    /// `username`'s queued downloads to look for other sources of. They stay
+    /// in the store meanwhile. Ones in progress are left alone: their file
+    /// connection doesn't depend on the one that dropped, and is only
+    /// replaced if it breaks off too.
+    fn replaceable_downloads(&self, username: &str) -> Vec<Download> {
+        self.get_downloads()
+            .iter()
+            .filter(|download| {
+                download.username == username
+                    && matches!(download.status, DownloadStatus::Queued)
+                    && self.can_replace(download)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The download under `token`, queued again to look for another source
+    /// now that its transfer broke off, if it is still wanted and can be.
+    fn broken_transfer(&mut self, token: u32) -> Option<Download> {
+        let download = self.get_download_by_token(token)?.clone();
+        if download.is_finished() || !self.can_replace(&download) {
+            return None;
+        }
+        self.downloads.update_status(token, DownloadStatus::Queued);
+        Some(download)
+    }
+
+    /// Search for `filename` under a fresh token, streaming the results to
+    /// `listener` without keeping them. Returns the token, or `None` when
+    /// not connected.
+    fn start_source_search(
+        &mut self,
+        filename: &str,
+        listener: Sender<SearchResult>,
+    ) -> Option<u32> {
+        let server = self.server_sender.clone()?;
+        let token = self.tokens.issue(TokenOwner::SourceSearch {
+            filename: filename.to_string(),
+        });
+        self.search_listeners
+            .entry(token)
+            .or_default()
+            .push(listener);
+        let query = source_query(filename);
+        if server
+            .send(ServerMessage::FileSearch { token, query })
+            .is_err()
+        {
+            self.end_source_search(token);
+            return None;
+        }
+        Some(token)
+    }
+
+    fn end_source_search(&mut self, token: u32) {
+        self.search_listeners.remove(&token);
+        self.tokens.release(token);
+    }
+}
+
+impl Client {
+    /// Look for other sources of `username`'s downloads that were still
+    /// queued, now that they disconnected. Each is queued from the best one
+    /// found, or fails if none is.
+    pub(crate) fn replace_sources(
+        client_context: &Arc<RwLock<ClientContext>>,
+        username: &str,
+    ) {
+        let (downloads, search_time) = match client_context.read_safe() {
+            Ok(ctx) => (
+                ctx.replaceable_downloads(username),
+                ctx.source_search_time.unwrap_or_default(),
+            ),
+            Err(e) => {
+                error!("[client] replace_sources read: {}", e);
+                return;
+            }
+        };
+        for download in downloads {
+            let client_context = client_context.clone();
+            thread::spawn(move || {
+                Self::replace_source(&client_context, download, search_time);
+            });
+        }
+    }
+
+    /// Look for another source of the download under `token`, whose
+    /// transfer broke off. Returns false if it isn't replaced, and should
+    /// fail as usual.
+    pub(crate) fn replace_broken_transfer(
+        client_context: &Arc<RwLock<ClientContext>>,
+        token: u32,
+    ) -> bool {
+        let (download, search_time) = match client_context.write_safe() {
+            Ok(mut ctx) => match ctx.broken_transfer(token) {
+                Some(download) => {
+                    (download, ctx.source_search_time.unwrap_or_default())
+                }
+                None => return false,
+            },
+            Err(e) => {
+                error!("[client] replace_broken_transfer write: {}", e);
+                return false;
+            }
+        };
+        let client_context = client_context.clone();
+        thread::spawn(move || {
+            Self::replace_source(&client_context, download, search_time);
+        });
+        true
+    }
+
+    fn replace_source(
+        client_context: &Arc<RwLock<ClientContext>>,
+        download: Download,
+        search_time: Duration,
+    ) {
+        info!(
+            "[client] lost {} from {}, looking for it elsewhere",
+            download.filename, download.username
+        );
+        let _ = download.sender.send(DownloadStatus::Queued);
+        let (listener, results) = mpsc::channel();
+        let token = match client_context.write_safe() {
+            Ok(mut ctx) => {
+                ctx.start_source_search(&download.filename, listener)
+            }
+            Err(e) => {
+                error!("[client] replace_source write: {}", e);
+                None
+            }
+        };
+        let Some(token) = token else {
+            Self::fail_replacement(client_context, &download);
+            return;
+        };
+
+        let deadline = Instant::now() + search_time;
+        let mut found = Vec::new();
+        while let Some(remaining) =
+            deadline.checked_duration_since(Instant::now())
+            && let Ok(result) = results.recv_timeout(remaining)
+        {
+            found.push(result);
+        }
+
+        let server = match client_context.write_safe() {
+            Ok(mut ctx) => {
+                ctx.end_source_search(token);
+                ctx.server_sender.clone()
+            }
+            Err(e) => {
+                error!("[client] replace_source write: {}", e);
+                None
+            }
+        };
+        let Some(source) = best_source(&found, &download) else {
+            warn!(
+                "[client] No other source for {} ({} results)",
+                download.filename,
+                found.len()
+            );
+            Self::fail_replacement(client_context, &download);
+            return;
+        };
+
+        // Cancelled or removed while searching: nothing to replace. Else
+        // the replacement takes the download's place in the store.
+        let wanted = match client_context.write_safe() {
+            Ok(mut ctx) => {
+                let wanted = ctx
+                    .get_download_by_token(download.token)
+                    .is_some_and(|download| !download.is_finished());
+                if wanted {
+                    ctx.downloads.remove(download.token);
+                }
+                wanted
+            }
+            Err(e) => {
+                error!("[client] replace_source write: {}", e);
+                false
+            }
+        };
+        if !wanted {
+            return;
+        }
+
+        let replacement = Download {
+            username: source.username.clone(),
+            filename: source.name.clone(),
+            status: DownloadStatus::Queued,
+            queue_position: None,
+            ..download.clone()
+        };
+        let queued = Self::queue_download(client_context, replacement, |msg| {
+            server.is_some_and(|server| server.send(msg).is_ok())
+        });
+        let replacement = match queued {
+            Ok(replacement) => replacement,
+            Err(e) => {
+                error!("[client] requeue {}: {}", download.filename, e);
+                Self::fail_replacement(client_context, &download);
+                return;
+            }
+        };
+        info!(
+            "[client] {} now comes from {}",
+            download.filename, replacement.username
+        );
+        match client_context.write_safe() {
+            Ok(mut ctx) => ctx.push_event(ClientEvent::SourceReplaced {
+                username: download.username,
+                filename: download.filename,
+                new_username: replacement.username,
+                new_filename: replacement.filename,
+            }),
+            Err(e) => error!("[client] replace_source write: {}", e),
+        }
+    }
+
+    /// Fail `download` the way losing its uploader did before sources were
+    /// replaced.
+    fn fail_replacement(
+        client_context: &Arc<RwLock<ClientContext>>,
+        download: &Download,
+    ) {
+        let status = DownloadStatus::Failed(Some(FailureReason::UploadFailed));
+        let _ = download.sender.send(status.clone());
+        match client_context.write_safe() {
+            Ok(mut ctx) => {
+                ctx.update_download_with_status(download.token, status);
+            }
+            Err(e) => error!("[client] fail_replacement write: {}", e),
+        }
+    }
+}
+
+/// What to search for to find `filename` elsewhere: the words of its base
+/// name, without the extension.
+fn source_query(filename: &str) -> String {
+    let name = base_name(filename);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The best file in `results` that is `download`'s under the same name and
+/// size, from anyone but its uploader: a free slot first, then the shorter
+/// queue, then the faster uploader. Ties go to whoever answered first.
+fn best_source<'a>(
+    results: &'a [SearchResult],
+    download: &Download,
+) -> Option<&'a File> {
+    let name = base_name(&download.filename);
+    results
+        .iter()
+        .filter(|result| result.username != download.username)
+        .flat_map(|result| result.files.iter().map(move |file| (result, file)))
+        .filter(|(_, file)| {
+            file.size == download.size
+                && base_name(&file.name).eq_ignore_ascii_case(name)
+        })
+        .rev()
+        .max_by_key(|(result, _)| {
+            (
+                result.free_slot,
+                std::cmp::Reverse(result.queue_length),
+                result.speed,
+            )
+        })
+        .map(|(_, file)| file)
+}
+
+pub(super) fn base_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DownloadMetadata, FileAttributes};
+
+    fn download(
+        status: DownloadStatus,
+    ) -> (Download, mpsc::Receiver<DownloadStatus>) {
+        let (sender, receiver) = mpsc::channel();
+        let download = Download {
+            username: "gone".to_string(),
+            filename: "Music\\SAW\\01 Xtal.flac".to_string(),
+            token: 7,
+            size: 42,
+            download_directory: "/music".to_string(),
+            status,
+            sender,
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        };
+        (download, receiver)
+    }
+
+    fn result(
+        username: &str,
+        free_slot: bool,
+        queue_length: u32,
+        files: &[(&str, u64)],
+    ) -> SearchResult {
+        SearchResult {
+            token: 1,
+            files: files
+                .iter()
+                .map(|&(name, size)| File {
+                    username: username.to_string(),
+                    name: name.to_string(),
+                    size,
+                    attributes: FileAttributes::default(),
+                    name_charset: None,
+                    locked: false,
+                })
+                .collect(),
+            free_slot,
+            speed: 0,
+            queue_length,
+            username: username.to_string(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_same_file_from_the_most_available_other_user_is_picked() {
+        let (download, _) = download(DownloadStatus::Queued);
+        let results = [
+            result("gone", true, 0, &[("Music\\SAW\\01 Xtal.flac", 42)]),
+            result("resized", true, 0, &[("A\\01 Xtal.flac", 41)]),
+            result("busy", false, 3, &[("B\\01 XTAL.FLAC", 42)]),
+            result("queued", true, 9, &[("C\\01 Xtal.flac", 42)]),
+            result("free", true, 0, &[("D\\01 Xtal.flac", 42)]),
+            result("later", true, 0, &[("E\\01 Xtal.flac", 42)]),
+        ];
+        let best = best_source(&results, &download).unwrap();
+        assert_eq!(
+            (best.username.as_str(), best.name.as_str()),
+            ("free", "D\\01 Xtal.flac")
+        );
+        assert!(best_source(&results[..2], &download).is_none());
+        assert_eq!(
+            source_query("Music\\SAW\\01 - Xtal (remaster).flac"),
+            "01 Xtal remaster"
+        );
+    }
+
+    #[test]
+    fn a_disconnected_uploaders_downloads_are_searched_for_elsewhere() {
+        let mut ctx = ClientContext::new();
+        let (server, sent) = mpsc::channel();
+        ctx.server_sender = Some(server);
+        let (queued, _) = download(DownloadStatus::Queued);
+        let (mut paused, _) = download(DownloadStatus::Paused {
+            bytes_downloaded: 1,
+            total_bytes: 42,
+        });
+        paused.token = 8;
+        paused.filename = "Music\\SAW\\02 Tha.flac".to_string();
+        let (mut arriving, _) = download(DownloadStatus::InProgress {
+            bytes_downloaded: 1,
+            total_bytes: 42,
+            speed_bytes_per_sec: 0.0,
+            average_speed_bytes_per_sec: 0.0,
+            eta: None,
+        });
+        arriving.token = 9;
+        arriving.filename = "Music\\SAW\\03 Pulsewidth.flac".to_string();
+        ctx.add_download(queued);
+        ctx.add_download(paused);
+        ctx.add_download(arriving);
+
+        // The transfer in progress has its own connection and carries on.
+        let replaceable = ctx.replaceable_downloads("gone");
+        assert_eq!(replaceable.len(), 1);
+        assert_eq!(replaceable[0].token, 7);
+        assert!(matches!(
+            ctx.get_download_by_token(9).unwrap().status,
+            DownloadStatus::InProgress { .. }
+        ));
+
+        let (listener, _results) = mpsc::channel();
+        let token = ctx
+            .start_source_search(&replaceable[0].filename, listener)
+            .unwrap();
+        let Ok(ServerMessage::FileSearch { query, .. }) = sent.try_recv()
+        else {
+            panic!("expected a FileSearch");
+        };
+        assert_eq!(query, "01 Xtal");
+        assert!(ctx.expects_search_result(token));
+        ctx.end_source_search(token);
+        assert!(!ctx.expects_search_result(token));
+        assert!(ctx.searches.is_empty());
+    }
+
+    #[test]
+    fn without_a_search_time_nothing_is_replaced() {
+        let mut ctx = ClientContext::new();
+        ctx.server_sender = Some(mpsc::channel().0);
+        ctx.source_search_time = None;
+        ctx.add_download(download(DownloadStatus::Queued).0);
+        assert!(ctx.replaceable_downloads("gone").is_empty());
+        assert!(ctx.get_download_by_token(7).is_some());
+    }
+
+    #[test]
+    fn a_broken_off_transfer_is_queued_for_another_source() {
+        let mut ctx = ClientContext::new();
+        ctx.server_sender = Some(mpsc::channel().0);
+        ctx.add_download(
+            download(DownloadStatus::InProgress {
+                bytes_downloaded: 1,
+                total_bytes: 42,
+                speed_bytes_per_sec: 0.0,
+                average_speed_bytes_per_sec: 0.0,
+                eta: None,
+            })
+            .0,
+        );
+
+        assert_eq!(ctx.broken_transfer(7).map(|d| d.token), Some(7));
+        assert!(matches!(
+            ctx.get_download_by_token(7).unwrap().status,
+            DownloadStatus::Queued
+        ));
+        assert!(ctx.broken_transfer(8).is_none());
+        ctx.update_download_with_status(7, DownloadStatus::Failed(None));
+        assert!(ctx.broken_transfer(7).is_none());
+    }
+
+    #[test]
+    fn a_download_without_another_source_fails_in_the_store() {
+        let mut ctx = ClientContext::new();
+        let (download, receiver) = download(DownloadStatus::Queued);
+        ctx.add_download(download.clone());
+        let ctx = Arc::new(RwLock::new(ctx));
+
+        Client::fail_replacement(&ctx, &download);
+        let failed = |status: &DownloadStatus| {
+            matches!(
+                status,
+                DownloadStatus::Failed(Some(FailureReason::UploadFailed))
+            )
+        };
+        assert!(failed(&receiver.try_recv().unwrap()));
+        let ctx = ctx.read().unwrap();
+        assert!(failed(&ctx.get_download_by_token(7).unwrap().status));
+        assert_eq!(ctx.download_history().entries().len(), 1);
+    }
+}
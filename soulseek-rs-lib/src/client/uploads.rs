@@ -1,11 +1,318 @@
 use super::{
-    ActiveUpload, Arc, Client, ClientContext, DownloadStatus, RwLock,
-    RwLockExt, collect_failed_tokens, error, thread,
+    ActiveUpload, Arc, Client, ClientContext, ClientOperation, DownloadStatus,
+    LeechCheck, LeechFilter, LeechVerdict, Mutex, PeerMessage, QueuedUpload,
+    RwLock, RwLockExt, ServerMessage, TokenBucket, UploadJob,
+    collect_failed_tokens, debug, error, next_upload_token, thread,
 };
-use crate::types::UploadStatus;
-use std::sync::atomic::{AtomicBool, AtomicU64};
+use crate::message::server::MessageFactory;
+use crate::types::{FailureReason, UploadStatus};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long a requester's stats may take to arrive before the next request
+/// from them asks the server again, in case the reply was lost.
+const LEECH_CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A bucket shared by every upload, refilling at `kbps` KiB/s with one
+/// second of burst.
+pub(super) fn upload_throttle(kbps: u32) -> Arc<Mutex<TokenBucket>> {
+    let rate = f64::from(kbps.max(1)) * 1024.0;
+    Arc::new(Mutex::new(TokenBucket::new(rate, rate)))
+}
+
+impl ClientContext {
+    /// Uploads holding a slot: offered and awaiting the peer's reply, or
+    /// streaming.
+    fn upload_slots_in_use(&self) -> usize {
+        self.uploads.len()
+            + self
+                .active_uploads
+                .values()
+                .filter(|upload| upload.status == UploadStatus::InProgress)
+                .count()
+    }
+
+    /// Queue a request, ignoring duplicates, and return its 1-based place.
+    fn enqueue_upload(&mut self, request: QueuedUpload) -> u32 {
+        if let Some(place) =
+            self.upload_queue_place(&request.downloader, &request.filename)
+        {
+            return place;
+        }
+        self.upload_queue.push_back(request);
+        u32::try_from(self.upload_queue.len()).unwrap_or(u32::MAX)
+    }
+
+    /// Pop the next request to serve. Peers without an upload already in a
+    /// slot go first, so one user queueing a whole album cannot starve
+    /// everybody else; otherwise the queue is first come, first served.
+    fn take_next_queued_upload(&mut self) -> Option<QueuedUpload> {
+        let busy: HashSet<&str> = self
+            .uploads
+            .values()
+            .map(|job| job.downloader.as_str())
+            .chain(
+                self.active_uploads
+                    .values()
+                    .filter(|upload| upload.status == UploadStatus::InProgress)
+                    .map(|upload| upload.username.as_str()),
+            )
+            .collect();
+        let index = self
+            .upload_queue
+            .iter()
+            .position(|queued| !busy.contains(queued.downloader.as_str()))
+            .unwrap_or(0);
+        self.upload_queue.remove(index)
+    }
+
+    /// The 1-based place of `downloader`'s request for `filename`, if queued.
+    fn upload_queue_place(
+        &self,
+        downloader: &str,
+        filename: &str,
+    ) -> Option<u32> {
+        self.upload_queue
+            .iter()
+            .position(|queued| {
+                queued.downloader == downloader && queued.filename == filename
+            })
+            .and_then(|index| u32::try_from(index + 1).ok())
+    }
+
+    fn leech_verdict(&self, username: &str) -> LeechVerdict {
+        self.leech_filter
+            .as_ref()
+            .map_or(LeechVerdict::Allow, |filter| {
+                filter.verdict(username, self.user_info.get(username))
+            })
+    }
+
+    /// Hold `request` until its requester's share counts arrive, asking the
+    /// server for them with the first request held, and again with a later
+    /// one once [`LEECH_CHECK_TIMEOUT`] has passed without a reply.
+    fn await_leech_check(&mut self, request: QueuedUpload, now: Instant) {
+        let downloader = request.downloader.clone();
+        let check: &mut LeechCheck =
+            self.leech_checks.entry(downloader.clone()).or_default();
+        if !check
+            .held
+            .iter()
+            .any(|queued| queued.filename == request.filename)
+        {
+            check.held.push(request);
+        }
+        let due = check.asked.is_none_or(|asked| {
+            now.duration_since(asked) >= LEECH_CHECK_TIMEOUT
+        });
+        if due && let Some(sender) = &self.server_sender {
+            check.asked = Some(now);
+            let _ = sender.send(ServerMessage::SendMessage(
+                MessageFactory::build_get_user_stats(&downloader),
+            ));
+        }
+    }
+}
 
 impl Client {
+    /// A peer queued one of our shared files: remember the request and serve
+    /// it as soon as an upload slot is free.
+    pub(crate) fn queue_incoming_upload(
+        client_context: &Arc<RwLock<ClientContext>>,
+        requester_key: String,
+        filename: String,
+    ) {
+        let downloader = requester_key
+            .strip_suffix(":direct")
+            .unwrap_or(&requester_key)
+            .to_string();
+        let request = QueuedUpload {
+            requester_key,
+            downloader,
+            filename,
+        };
+        let denied = match client_context.write_safe() {
+            Ok(mut ctx) => {
+                if ctx.shares.get(&request.filename).is_none() {
+                    debug!(
+                        "[client] QueueUpload for unknown file {}",
+                        request.filename
+                    );
+                    return;
+                }
+                match ctx.leech_verdict(&request.downloader) {
+                    LeechVerdict::Allow => {
+                        let place = ctx.enqueue_upload(request);
+                        debug!("[client] upload queued at place {}", place);
+                        None
+                    }
+                    LeechVerdict::NeedStats => {
+                        ctx.await_leech_check(request, Instant::now());
+                        return;
+                    }
+                    LeechVerdict::Deny => {
+                        let reason = ctx
+                            .leech_filter
+                            .as_ref()
+                            .map(|filter| filter.reason().to_string())
+                            .unwrap_or_default();
+                        Some((request, reason, ctx.peer_registry.clone()))
+                    }
+                }
+            }
+            Err(e) => {
+                error!("[client] QueueUpload write: {}", e);
+                return;
+            }
+        };
+        if let Some((request, reason, registry)) = denied {
+            debug!(
+                "[client] denying {} to {}: shares below the leech filter",
+                request.filename, request.downloader
+            );
+            if let Some(registry) = registry {
+                let message = MessageFactory::build_upload_denied(
+                    &request.filename,
+                    &reason,
+                );
+                let _ = registry.send_to_peer(
+                    &request.requester_key,
+                    PeerMessage::SendMessage(message),
+                );
+            }
+            return;
+        }
+        Self::promote_queued_uploads(client_context);
+    }
+
+    /// `username`'s stats arrived: judge the upload requests held for them.
+    pub(crate) fn resume_leech_checks(
+        client_context: &Arc<RwLock<ClientContext>>,
+        username: &str,
+    ) {
+        let held = match client_context.write_safe() {
+            Ok(mut ctx) => {
+                if ctx.leech_verdict(username) == LeechVerdict::NeedStats {
+                    return;
+                }
+                ctx.leech_checks
+                    .remove(username)
+                    .map(|check| check.held)
+                    .unwrap_or_default()
+            }
+            Err(e) => {
+                error!("[client] resume_leech_checks write: {}", e);
+                return;
+            }
+        };
+        for request in held {
+            Self::queue_incoming_upload(
+                client_context,
+                request.requester_key,
+                request.filename,
+            );
+        }
+    }
+
+    /// Replace the leech filter applied to upload requests from now on.
+    pub fn set_leech_filter(&self, filter: Option<LeechFilter>) {
+        match self.context.write_safe() {
+            Ok(mut ctx) => ctx.leech_filter = filter,
+            Err(e) => error!("[client] set_leech_filter: {}", e),
+        }
+    }
+
+    /// Offer queued uploads to their peers while slots are free.
+    pub(crate) fn promote_queued_uploads(
+        client_context: &Arc<RwLock<ClientContext>>,
+    ) {
+        loop {
+            let (token, registry, requester_key, filename, size) =
+                match client_context.write_safe() {
+                    Ok(mut ctx) => {
+                        if ctx.upload_slots_in_use() >= ctx.upload_slots {
+                            return;
+                        }
+                        let Some(queued) = ctx.take_next_queued_upload() else {
+                            return;
+                        };
+                        // The share index may have been replaced meanwhile.
+                        let Some(file) = ctx.shares.get(&queued.filename)
+                        else {
+                            continue;
+                        };
+                        let size = file.size;
+                        let real_path = file.real_path.clone();
+                        let token = next_upload_token();
+                        ctx.uploads.insert(
+                            token,
+                            UploadJob {
+                                downloader: queued.downloader,
+                                real_path,
+                                virtual_path: queued.filename.clone(),
+                                size,
+                            },
+                        );
+                        (
+                            token,
+                            ctx.peer_registry.clone(),
+                            queued.requester_key,
+                            queued.filename,
+                            size,
+                        )
+                    }
+                    Err(e) => {
+                        error!("[client] promote_queued_uploads write: {}", e);
+                        return;
+                    }
+                };
+            let offered = registry.is_some_and(|registry| {
+                registry
+                    .send_to_peer(
+                        &requester_key,
+                        PeerMessage::ServeUpload {
+                            token,
+                            filename,
+                            size,
+                        },
+                    )
+                    .is_ok()
+            });
+            // The peer went away while queued: free the slot and move on.
+            if !offered && let Ok(mut ctx) = client_context.write_safe() {
+                ctx.uploads.remove(&token);
+            }
+        }
+    }
+
+    /// Drop an offer the peer declined and hand its slot to the next request.
+    pub(crate) fn release_upload(
+        client_context: &Arc<RwLock<ClientContext>>,
+        token: u32,
+    ) {
+        if let Ok(mut ctx) = client_context.write_safe() {
+            ctx.uploads.remove(&token);
+        }
+        Self::promote_queued_uploads(client_context);
+    }
+
+    /// Where `requester_key`'s request for `filename` stands in our upload
+    /// queue, or `None` if it is not waiting.
+    pub(crate) fn upload_queue_place(
+        client_context: &Arc<RwLock<ClientContext>>,
+        requester_key: &str,
+        filename: &str,
+    ) -> Option<u32> {
+        let downloader = requester_key
+            .strip_suffix(":direct")
+            .unwrap_or(requester_key);
+        client_context
+            .read_safe()
+            .ok()?
+            .upload_queue_place(downloader, filename)
+    }
+
     /// Consume the upload job for `token` and stream the file to `host:port`
     /// on a background thread.
     pub(crate) fn spawn_serve(
@@ -34,6 +341,8 @@ impl Client {
                 status: UploadStatus::InProgress,
             },
         );
+        let throttle = ctx.upload_throttle.clone();
+        let tls = ctx.peer_tls.clone();
         drop(ctx);
         let own = own_username.to_string();
         let real_path = job.real_path;
@@ -47,6 +356,8 @@ impl Client {
                 &real_path,
                 &bytes_sent,
                 &cancel,
+                throttle.as_deref(),
+                tls.as_deref(),
             );
             let status = match &result {
                 Ok(()) => UploadStatus::Completed,
@@ -58,23 +369,43 @@ impl Client {
                     UploadStatus::Failed(e.to_string())
                 }
             };
-            if let Ok(mut ctx) = context.write_safe()
-                && let Some(upload) = ctx.active_uploads.get_mut(&token)
-            {
-                upload.status = status;
+            let sender = context.write_safe().ok().and_then(|mut ctx| {
+                // Count the bytes as the upload stops counting as running,
+                // so a snapshot never sees them twice.
+                ctx.metrics.add_uploaded(bytes_sent.load(Ordering::Relaxed));
+                if !matches!(status, UploadStatus::Cancelled) {
+                    ctx.metrics.upload_finished(matches!(
+                        status,
+                        UploadStatus::Failed(_)
+                    ));
+                }
+                if let Some(upload) = ctx.active_uploads.get_mut(&token) {
+                    upload.status = status;
+                }
+                ctx.sender.clone()
+            });
+            // The slot is free again: let the next queued request in.
+            if let Some(sender) = sender {
+                let _ = sender.send(ClientOperation::UploadFinished { token });
             }
         });
     }
 
+    /// Fail `username`'s downloads, or only `filename`, with `reason` and
+    /// drop them from the store.
     pub(crate) fn process_failed_uploads(
         client_context: Arc<RwLock<ClientContext>>,
         username: &str,
         filename: Option<&str>,
+        reason: &FailureReason,
     ) {
         let failed_tokens = match client_context.read_safe() {
-            Ok(context) => {
-                collect_failed_tokens(&context.downloads, username, filename)
-            }
+            Ok(context) => collect_failed_tokens(
+                &context.downloads,
+                username,
+                filename,
+                reason,
+            ),
             Err(e) => {
                 error!("[client] process_failed_uploads read: {}", e);
                 return;
@@ -88,14 +419,14 @@ impl Client {
         match client_context.write_safe() {
             Ok(mut context) => {
                 for token in failed_tokens {
+                    context.metrics.download_finished(true);
                     context.downloads.update_status(
                         token,
-                        DownloadStatus::Failed(Some(
-                            "The upload failed on the other side".to_string(),
-                        )),
+                        DownloadStatus::Failed(Some(reason.clone())),
                     );
                     context.downloads.remove(token);
                 }
+                context.start_held_downloads();
             }
             Err(e) => {
                 error!("[client] process_failed_uploads write: {}", e);
@@ -103,3 +434,104 @@ impl Client {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ClientContext, LEECH_CHECK_TIMEOUT, LeechFilter, LeechVerdict,
+        QueuedUpload, UploadJob,
+    };
+    use crate::types::UserInfo;
+    use std::time::{Duration, Instant};
+
+    fn request(downloader: &str, filename: &str) -> QueuedUpload {
+        QueuedUpload {
+            requester_key: downloader.to_string(),
+            downloader: downloader.to_string(),
+            filename: filename.to_string(),
+        }
+    }
+
+    #[test]
+    fn enqueue_reports_places_and_ignores_duplicates() {
+        let mut ctx = ClientContext::new();
+        assert_eq!(ctx.enqueue_upload(request("alice", "a1")), 1);
+        assert_eq!(ctx.enqueue_upload(request("bob", "b1")), 2);
+        assert_eq!(ctx.enqueue_upload(request("alice", "a1")), 1);
+        assert_eq!(ctx.upload_queue.len(), 2);
+        assert_eq!(ctx.upload_queue_place("bob", "b1"), Some(2));
+        assert_eq!(ctx.upload_queue_place("bob", "nope"), None);
+    }
+
+    #[test]
+    fn peers_without_a_slot_are_served_first() {
+        let mut ctx = ClientContext::new();
+        ctx.enqueue_upload(request("alice", "a2"));
+        ctx.enqueue_upload(request("bob", "b1"));
+        // alice already holds a slot, so bob jumps ahead of her second file.
+        ctx.uploads.insert(
+            1,
+            UploadJob {
+                downloader: "alice".to_string(),
+                real_path: std::path::PathBuf::from("a1"),
+                virtual_path: "a1".to_string(),
+                size: 1,
+            },
+        );
+        assert_eq!(ctx.upload_slots_in_use(), 1);
+        let next = ctx.take_next_queued_upload().unwrap();
+        assert_eq!(next.downloader, "bob");
+        // Only alice is left: she is served even though she is busy.
+        let next = ctx.take_next_queued_upload().unwrap();
+        assert_eq!(next.downloader, "alice");
+        assert!(ctx.take_next_queued_upload().is_none());
+    }
+
+    #[test]
+    fn leech_checks_hold_requests_and_ask_for_stats_once() {
+        let (sender, server) = std::sync::mpsc::channel();
+        let mut ctx = ClientContext::new();
+        ctx.server_sender = Some(sender);
+        ctx.leech_filter = Some(LeechFilter::new().min_shared_files(10));
+        assert_eq!(ctx.leech_verdict("bob"), LeechVerdict::NeedStats);
+
+        let now = Instant::now();
+        ctx.await_leech_check(request("bob", "b1"), now);
+        ctx.await_leech_check(request("bob", "b1"), now);
+        ctx.await_leech_check(request("bob", "b2"), now);
+        assert_eq!(ctx.leech_checks["bob"].held.len(), 2);
+        assert_eq!(server.try_iter().count(), 1);
+
+        ctx.merge_user_info(UserInfo {
+            shared_files: Some(3),
+            shared_folders: Some(1),
+            ..UserInfo::new("bob")
+        });
+        assert_eq!(ctx.leech_verdict("bob"), LeechVerdict::Deny);
+    }
+
+    #[test]
+    fn a_lost_stats_reply_is_asked_for_again() {
+        let (sender, server) = std::sync::mpsc::channel();
+        let mut ctx = ClientContext::new();
+        ctx.server_sender = Some(sender);
+        ctx.leech_filter = Some(LeechFilter::new().min_shared_files(10));
+
+        let start = Instant::now();
+        ctx.await_leech_check(request("bob", "b1"), start);
+        assert_eq!(server.try_iter().count(), 1);
+        // No reply yet, but not overdue either.
+        ctx.await_leech_check(
+            request("bob", "b1"),
+            start + Duration::from_secs(5),
+        );
+        assert_eq!(server.try_iter().count(), 0);
+        // The reply never came: the peer's retry asks again.
+        let retry = start + LEECH_CHECK_TIMEOUT;
+        ctx.await_leech_check(request("bob", "b1"), retry);
+        assert_eq!(server.try_iter().count(), 1);
+        ctx.await_leech_check(request("bob", "b2"), retry);
+        assert_eq!(server.try_iter().count(), 0);
+        assert_eq!(ctx.leech_checks["bob"].held.len(), 2);
+    }
+}
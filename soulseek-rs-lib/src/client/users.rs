@@ -0,0 +1,266 @@
+use super::{
+    Client, ClientContext, Duration, Instant, Result, RwLockExt, ServerMessage,
+    SoulseekRs, UserInfo, mpsc,
+};
+use crate::message::server::MessageFactory;
+use crate::types::{ClientEvent, UserStatus};
+
+/// How long [`Client::check_privileges`] waits for the server.
+const CHECK_PRIVILEGES_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Slack allowed between the countdown and a fresh report before the
+/// privileges count as extended.
+const PRIVILEGES_DRIFT: Duration = Duration::from_mins(1);
+
+impl ClientContext {
+    /// Fold a partial update into what we know about its user, reporting a
+    /// favorite coming online.
+    pub fn merge_user_info(&mut self, info: UserInfo) {
+        let username = info.username.clone();
+        let status = info.status;
+        let known = self
+            .user_info
+            .entry(username.clone())
+            .or_insert_with(|| UserInfo::new(username.clone()));
+        let was_online = known
+            .status
+            .is_some_and(|status| status != UserStatus::Offline);
+        known.merge(info);
+        self.note_favorite_status(&username, was_online, status);
+    }
+
+    /// Record the server's report of our privileges, answering waiting
+    /// [`Client::check_privileges`] calls. Reports an event the first time,
+    /// and when the privileges start, end or are extended.
+    pub fn set_privileges_left(&mut self, seconds: u32) {
+        let left = Duration::from_secs(seconds.into());
+        let changed = self.privileges_left().is_none_or(|expected| {
+            expected.is_zero() != left.is_zero()
+                || left > expected + PRIVILEGES_DRIFT
+        });
+        self.privileges = Some((seconds, Instant::now()));
+        if changed {
+            self.events.push(ClientEvent::PrivilegesChanged(left));
+        }
+        for waiter in self.privilege_waiters.drain(..) {
+            let _ = waiter.send(seconds);
+        }
+    }
+
+    /// Our privileges as last reported, counted down since.
+    #[must_use]
+    pub fn privileges_left(&self) -> Option<Duration> {
+        self.privileges.map(|(seconds, at)| {
+            Duration::from_secs(seconds.into()).saturating_sub(at.elapsed())
+        })
+    }
+}
+
+impl Client {
+    /// Look a user up: watch them and ask the server for their status and
+    /// stats, and ask the peer itself for its description, queue and slots.
+    /// Replies arrive asynchronously; read them with [`Client::user_info`].
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`](crate::SoulseekRs::NotConnected)
+    /// if the client is not connected.
+    pub fn request_user_info(&self, username: &str) -> Result<()> {
+        self.send_server_message(MessageFactory::build_watch_username(
+            username,
+        ))?;
+        self.send_server_message(MessageFactory::build_get_user_status(
+            username,
+        ))?;
+        self.send_server_message(MessageFactory::build_get_user_stats(
+            username,
+        ))?;
+        self.send_peer_request(
+            username,
+            MessageFactory::build_user_info_request(),
+        )
+    }
+
+    /// Everything received so far about `username`, if anything.
+    #[must_use]
+    pub fn user_info(&self, username: &str) -> Option<UserInfo> {
+        self.context
+            .read_safe()
+            .ok()
+            .and_then(|ctx| ctx.user_info.get(username).cloned())
+    }
+
+    /// Ask the server how long our privileges last. The answer arrives
+    /// asynchronously; read it with [`Client::privileges_left`].
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`] if the client is not connected.
+    pub fn request_privileges(&self) -> Result<()> {
+        self.send_server_message(MessageFactory::build_check_privileges())
+    }
+
+    /// Ask the server how long our privileges last and wait for the answer,
+    /// in seconds (0 when we have none).
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`] if the client is not connected,
+    /// or [`SoulseekRs::Timeout`] if the server does not answer.
+    pub fn check_privileges(&self) -> Result<u32> {
+        let (sender, receiver) = mpsc::channel();
+        self.context.write_safe()?.privilege_waiters.push(sender);
+        self.request_privileges()?;
+        receiver
+            .recv_timeout(CHECK_PRIVILEGES_TIMEOUT)
+            .map_err(|_| SoulseekRs::Timeout)
+    }
+
+    /// How long our privileges last, counted down from the server's last
+    /// report; `None` until privileges were checked.
+    #[must_use]
+    pub fn privileges_left(&self) -> Option<Duration> {
+        self.context
+            .read_safe()
+            .ok()
+            .and_then(|ctx| ctx.privileges_left())
+    }
+
+    /// Show us to other users as `Online` or `Away`. Kept across
+    /// reconnects.
+    ///
+    /// # Errors
+    /// Returns [`SoulseekRs::NotConnected`] if the client is not connected,
+    /// or [`SoulseekRs::InvalidArgument`] for `Offline`, which only the
+    /// server sets (disconnect instead).
+    pub fn set_status(&self, status: UserStatus) -> Result<()> {
+        if status == UserStatus::Offline {
+            return Err(SoulseekRs::InvalidArgument(
+                "cannot set status to offline".to_string(),
+            ));
+        }
+        self.server_handle
+            .as_ref()
+            .ok_or(SoulseekRs::NotConnected)?
+            .send(ServerMessage::SetStatus(status))
+            .map_err(|_| SoulseekRs::NotConnected)
+    }
+
+    /// Whether the server lists `username` as privileged. The list arrives
+    /// shortly after login.
+    #[must_use]
+    pub fn is_privileged(&self, username: &str) -> bool {
+        self.context
+            .read_safe()
+            .is_ok_and(|ctx| ctx.is_privileged(username))
+    }
+
+    /// Ignore `username`: drop their search results, kept and to come,
+    /// refuse their peer connections, closing any open now, and stop
+    /// answering their searches. Returns `false` if they already were.
+    /// The list starts from [`ClientSettings::ignored_users`] and isn't
+    /// saved; persist it there.
+    ///
+    /// [`ClientSettings::ignored_users`]: super::ClientSettings::ignored_users
+    #[must_use]
+    pub fn ignore_user(&self, username: &str) -> bool {
+        if !self.ignore_list.ignore(username) {
+            return false;
+        }
+        if let Ok(mut ctx) = self.context.write_safe() {
+            ctx.forget_results_from(username);
+            if let Some(handle) = ctx
+                .peer_registry
+                .as_ref()
+                .and_then(|registry| registry.remove_peer(username))
+            {
+                let _ = handle.stop();
+            }
+        }
+        true
+    }
+
+    /// Stop ignoring `username`; `false` if they weren't ignored.
+    #[must_use]
+    pub fn unignore_user(&self, username: &str) -> bool {
+        self.ignore_list.unignore(username)
+    }
+
+    #[must_use]
+    pub fn is_ignored(&self, username: &str) -> bool {
+        self.ignore_list.contains(username)
+    }
+
+    /// The ignored usernames, sorted.
+    #[must_use]
+    pub fn ignored_users(&self) -> Vec<String> {
+        self.ignore_list.users()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_updates_merge_per_user() {
+        let mut ctx = ClientContext::new();
+        ctx.merge_user_info(UserInfo {
+            status: Some(UserStatus::Away),
+            privileged: Some(true),
+            ..UserInfo::new("alice")
+        });
+        ctx.merge_user_info(UserInfo {
+            status: Some(UserStatus::Online),
+            queue_length: Some(3),
+            ..UserInfo::new("alice")
+        });
+        let info = &ctx.user_info["alice"];
+        assert_eq!(info.status, Some(UserStatus::Online));
+        assert_eq!(info.privileged, Some(true));
+        assert_eq!(info.queue_length, Some(3));
+        assert!(!ctx.user_info.contains_key("bob"));
+    }
+
+    #[test]
+    fn privileged_list_is_replaced_then_extended() {
+        let mut ctx = ClientContext::new();
+        ctx.add_privileged_user("stale".to_string());
+        ctx.set_privileged_users(vec!["alice".to_string(), "bob".to_string()]);
+        ctx.add_privileged_user("carol".to_string());
+        assert!(ctx.is_privileged("alice"));
+        assert!(ctx.is_privileged("carol"));
+        assert!(!ctx.is_privileged("stale"));
+        assert_eq!(
+            ctx.take_events(),
+            [
+                ClientEvent::PrivilegedUserAdded("stale".to_string()),
+                ClientEvent::PrivilegedUsersUpdated(2),
+                ClientEvent::PrivilegedUserAdded("carol".to_string()),
+            ]
+        );
+        assert!(ctx.take_events().is_empty());
+    }
+
+    #[test]
+    fn privileges_report_changes_but_not_the_countdown() {
+        let mut ctx = ClientContext::new();
+        let (waiter, answer) = mpsc::channel();
+        ctx.privilege_waiters.push(waiter);
+        ctx.set_privileges_left(0);
+        assert_eq!(answer.try_recv(), Ok(0));
+        // Still none, then bought, then the same privileges counting down.
+        ctx.set_privileges_left(0);
+        ctx.set_privileges_left(7200);
+        ctx.set_privileges_left(7190);
+        ctx.set_privileges_left(14_400);
+        let hours = |h: u64| Duration::from_secs(h * 3600);
+        assert_eq!(
+            ctx.take_events(),
+            [
+                ClientEvent::PrivilegesChanged(Duration::ZERO),
+                ClientEvent::PrivilegesChanged(hours(2)),
+                ClientEvent::PrivilegesChanged(hours(4)),
+            ]
+        );
+        assert!(ctx.privileges_left().is_some_and(|left| left <= hours(4)));
+        assert!(ctx.privilege_waiters.is_empty());
+    }
+}
@@ -1,12 +1,62 @@
-use crate::message::{Message, handlers::Handlers};
+use crate::message::{
+    Message,
+    handlers::{Handlers, MessageHandler},
+};
+use std::sync::Arc;
 use std::sync::mpsc::Sender;
 
-use crate::warn;
+use crate::{debug, warn};
+
+/// Which way a message is going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Whether an intercepted message goes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    /// Drop the message: an inbound one reaches no handler, an outbound one
+    /// is not sent.
+    Drop,
+}
+
+/// Sees every message of a connection before its handler does, or before
+/// it is sent, and may change or drop it.
+///
+/// `message` is positioned after its code. Inbound messages still carry
+/// their length prefix; outbound ones start with their code. A peer
+/// connection's first message has a one-byte code, widened to `code`.
+pub trait Interceptor: Send + Sync {
+    fn intercept(
+        &self,
+        direction: Direction,
+        code: u32,
+        message: &mut Message,
+    ) -> Verdict;
+}
+
+impl<F> Interceptor for F
+where
+    F: Fn(Direction, u32, &mut Message) -> Verdict + Send + Sync,
+{
+    fn intercept(
+        &self,
+        direction: Direction,
+        code: u32,
+        message: &mut Message,
+    ) -> Verdict {
+        self(direction, code, message)
+    }
+}
 
 pub struct MessageDispatcher<Op> {
     owner_name: String,
     sender: Sender<Op>,
     handlers: Handlers<Op>,
+    interceptors: Vec<Arc<dyn Interceptor>>,
 }
 
 impl<Op> MessageDispatcher<Op> {
@@ -20,21 +70,195 @@ impl<Op> MessageDispatcher<Op> {
             owner_name,
             sender,
             handlers,
+            interceptors: Vec::new(),
         }
     }
 
-    pub fn dispatch(&self, message: &mut Message) {
-        let code = message.get_message_code();
+    /// Run `interceptors`, in order, on every message before it is
+    /// handled or sent. The first to drop a message stops the rest.
+    #[must_use]
+    pub fn with_interceptors(
+        mut self,
+        interceptors: Vec<Arc<dyn Interceptor>>,
+    ) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    fn intercept(
+        &self,
+        direction: Direction,
+        code: u32,
+        message: &mut Message,
+        payload_at: usize,
+    ) -> Verdict {
+        for interceptor in &self.interceptors {
+            message.set_pointer(payload_at);
+            if interceptor.intercept(direction, code, message) == Verdict::Drop
+            {
+                debug!(
+                    "[{}:dispatcher] {:?} message code {} dropped by an interceptor",
+                    self.owner_name, direction, code
+                );
+                return Verdict::Drop;
+            }
+        }
+        Verdict::Pass
+    }
+
+    /// Run the interceptors on `message`, which is about to be sent.
+    pub fn outbound(&self, message: &mut Message) -> Verdict {
+        let code = message
+            .get_data()
+            .get(0..4)
+            .and_then(|code| code.try_into().ok())
+            .map_or(0, u32::from_le_bytes);
+        let verdict = self.intercept(Direction::Outbound, code, message, 4);
+        message.set_pointer(0);
+        verdict
+    }
 
+    /// The code of `message`, where its payload starts, and the handler it
+    /// goes to. Server and peer codes are 32 bits (CantConnectToPeer is
+    /// 1001); the init messages opening a peer connection have a one-byte
+    /// code.
+    fn route(
+        &self,
+        message: &Message,
+    ) -> (u32, usize, Option<&(dyn MessageHandler<Op> + Send)>) {
+        let code = message.get_message_code_u32();
         if let Some(handler) = self.handlers.get_handler(code) {
-            message.set_pointer(8);
-            handler.handle(message, self.sender.clone());
+            return (code, 8, Some(handler));
+        }
+        let init_code = message.get_message_code();
+        if let Some(handler) = self.handlers.get_init_handler(init_code) {
+            return (u32::from(init_code), 5, Some(handler));
+        }
+        (code, 8, self.handlers.get_fallback())
+    }
+
+    pub fn dispatch(&self, message: &mut Message) {
+        let (code, payload_at, handler) = self.route(message);
+        if self.intercept(Direction::Inbound, code, message, payload_at)
+            == Verdict::Drop
+        {
+            return;
+        }
+
+        if let Some(handler) = handler {
+            message.set_pointer(payload_at);
+            if let Err(e) = handler.handle(message, self.sender.clone()) {
+                warn!(
+                    "[{}:dispatcher] Dropping malformed message code {}: {}",
+                    self.owner_name, code, e
+                );
+            }
         } else {
             warn!(
                 "[{}:dispatcher] No handler found for message code: {}",
-                self.owner_name,
-                message.get_message_code()
+                self.owner_name, code
             );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Interceptor, MessageDispatcher, Verdict};
+    use crate::message::{Handlers, Message, MessageHandler};
+    use std::sync::Arc;
+    use std::sync::mpsc::{self, Sender};
+
+    struct Known;
+
+    impl MessageHandler<(u32, u32)> for Known {
+        fn get_code(&self) -> u32 {
+            1
+        }
+        fn handle(
+            &self,
+            message: &mut Message,
+            sender: Sender<(u32, u32)>,
+        ) -> crate::Result<()> {
+            let _ = sender.send((1, message.try_read_int32()?));
+            Ok(())
+        }
+    }
+
+    struct Unknown;
+
+    impl MessageHandler<(u32, u32)> for Unknown {
+        fn get_code(&self) -> u32 {
+            0
+        }
+        fn handle(
+            &self,
+            message: &mut Message,
+            sender: Sender<(u32, u32)>,
+        ) -> crate::Result<()> {
+            let code = message.get_message_code_u32();
+            let _ = sender.send((code, message.try_read_int32()?));
+            Ok(())
+        }
+    }
+
+    fn framed(code: u32, value: u32) -> Message {
+        let mut message = Message::new();
+        message.write_int32(8).write_int32(code).write_int32(value);
+        message
+    }
+
+    #[test]
+    fn unknown_codes_go_to_the_fallback() {
+        let (sender, receiver) = mpsc::channel();
+        let mut handlers = Handlers::new();
+        handlers.register_handler(Known);
+        let mut dispatcher =
+            MessageDispatcher::new("test".into(), sender.clone(), handlers);
+        dispatcher.dispatch(&mut framed(500, 7));
+        assert!(receiver.try_recv().is_err());
+
+        let mut handlers = Handlers::new();
+        handlers.register_handler(Known).set_fallback(Unknown);
+        dispatcher = MessageDispatcher::new("test".into(), sender, handlers);
+        dispatcher.dispatch(&mut framed(1, 5));
+        dispatcher.dispatch(&mut framed(500, 7));
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [(1, 5), (500, 7)]);
+    }
+
+    #[test]
+    fn interceptors_can_rewrite_or_drop_messages_both_ways() {
+        let (sender, receiver) = mpsc::channel();
+        let mut handlers = Handlers::new();
+        handlers.register_handler(Known);
+        let double = |_: Direction, code: u32, message: &mut Message| {
+            if code == 500 {
+                return Verdict::Drop;
+            }
+            let at = message.get_pointer();
+            let value = message.read_int32();
+            let mut rewritten = Message::new();
+            rewritten
+                .write_raw_bytes(message.slice(0, at))
+                .write_int32(value * 2);
+            *message = rewritten;
+            Verdict::Pass
+        };
+        let interceptors: Vec<Arc<dyn Interceptor>> = vec![Arc::new(double)];
+        let dispatcher =
+            MessageDispatcher::new("test".into(), sender, handlers)
+                .with_interceptors(interceptors);
+
+        dispatcher.dispatch(&mut framed(500, 7));
+        dispatcher.dispatch(&mut framed(1, 5));
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [(1, 10)]);
+
+        let mut outgoing = Message::new();
+        outgoing.write_int32(1).write_int32(3);
+        assert_eq!(dispatcher.outbound(&mut outgoing), Verdict::Pass);
+        assert_eq!(outgoing.get_data(), [1, 0, 0, 0, 6, 0, 0, 0]);
+        let mut outgoing = Message::new();
+        outgoing.write_int32(500);
+        assert_eq!(dispatcher.outbound(&mut outgoing), Verdict::Drop);
+    }
+}
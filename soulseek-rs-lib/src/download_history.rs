@@ -0,0 +1,370 @@
+//! Finished downloads, kept across runs in a JSON-lines file.
+//!
+//! Every completed or failed download appends one line, so past grabs can
+//! be listed and skipped. Lines that don't parse (a newer format, a torn
+//! write) are skipped on load.
+
+use crate::types::{Download, DownloadStatus};
+use crate::{error, warn};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One finished download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub username: String,
+    /// The peer's path to the file.
+    pub filename: String,
+    pub size: u64,
+    /// Where the file was saved; `None` if it failed.
+    pub path: Option<PathBuf>,
+    /// Time the transfer took; zero if it failed.
+    pub elapsed: Duration,
+    /// Hex MD5 of the saved file.
+    pub md5: Option<String>,
+    /// Why it failed; `None` if it completed.
+    pub error: Option<String>,
+    pub finished_at: SystemTime,
+}
+
+impl HistoryEntry {
+    /// The entry for `download`, if it has finished.
+    #[must_use]
+    pub fn from_download(download: &Download) -> Option<Self> {
+        let (summary, error) = match &download.status {
+            DownloadStatus::Completed(summary) => (summary.as_ref(), None),
+            DownloadStatus::Failed(reason) => {
+                (
+                    None,
+                    Some(reason.as_ref().map_or_else(
+                        || "failed".to_string(),
+                        ToString::to_string,
+                    )),
+                )
+            }
+            DownloadStatus::TimedOut => (None, Some("timed out".to_string())),
+            _ => return None,
+        };
+        Some(Self {
+            username: download.username.clone(),
+            filename: download.filename.clone(),
+            size: download.size,
+            path: summary.map(|summary| summary.path.clone()),
+            elapsed: summary.map_or(Duration::ZERO, |summary| summary.elapsed),
+            md5: summary.and_then(|summary| summary.md5.clone()),
+            error,
+            finished_at: SystemTime::now(),
+        })
+    }
+
+    #[must_use]
+    pub const fn completed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn to_json(&self) -> String {
+        let string = |value: &str| format!("\"{}\"", escape(value));
+        let optional = |value: Option<&str>| {
+            value.map_or_else(|| "null".to_string(), string)
+        };
+        let path = self.path.as_ref().map(|path| path.to_string_lossy());
+        let finished_at = self
+            .finished_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        format!(
+            "{{\"username\":{},\"filename\":{},\"size\":{},\"path\":{},\"elapsed_ms\":{},\"md5\":{},\"error\":{},\"finished_at\":{}}}",
+            string(&self.username),
+            string(&self.filename),
+            self.size,
+            optional(path.as_deref()),
+            self.elapsed.as_millis(),
+            optional(self.md5.as_deref()),
+            optional(self.error.as_deref()),
+            finished_at,
+        )
+    }
+
+    fn from_json(line: &str) -> Option<Self> {
+        let mut fields = parse_object(line)?;
+        let mut string = |key: &str| match fields.remove(key) {
+            Some(Value::String(value)) => Some(Some(value)),
+            Some(Value::Null) | None => Some(None),
+            Some(Value::Number(_)) => None,
+        };
+        let username = string("username")??;
+        let filename = string("filename")??;
+        let path = string("path")?.map(PathBuf::from);
+        let md5 = string("md5")?;
+        let error = string("error")?;
+        let number = |key: &str| match fields.get(key) {
+            Some(Value::Number(value)) => Some(*value),
+            _ => None,
+        };
+        Some(Self {
+            username,
+            filename,
+            size: number("size")?,
+            path,
+            elapsed: Duration::from_millis(number("elapsed_ms").unwrap_or(0)),
+            md5,
+            error,
+            finished_at: UNIX_EPOCH
+                + Duration::from_secs(number("finished_at").unwrap_or(0)),
+        })
+    }
+}
+
+/// The finished downloads, oldest first, optionally backed by a file.
+#[derive(Debug, Default)]
+pub struct DownloadHistory {
+    file: Option<PathBuf>,
+    entries: Vec<HistoryEntry>,
+}
+
+impl DownloadHistory {
+    /// A history that is lost when the client is dropped.
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Load the history kept in `file`, which later entries are appended
+    /// to. A missing file is an empty history.
+    ///
+    /// # Errors
+    /// Returns the error reading an existing `file` failed with.
+    pub fn open(file: impl Into<PathBuf>) -> io::Result<Self> {
+        let file = file.into();
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut entries = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(entry) = HistoryEntry::from_json(line) {
+                entries.push(entry);
+            } else {
+                warn!(
+                    "[history] {}:{}: skipping malformed entry",
+                    file.display(),
+                    number + 1
+                );
+            }
+        }
+        Ok(Self {
+            file: Some(file),
+            entries,
+        })
+    }
+
+    /// Add `entry`, appending it to the file if there is one.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        if let Some(file) = &self.file
+            && let Err(e) = append_line(file, &entry.to_json())
+        {
+            error!("[history] cannot write {}: {}", file.display(), e);
+        }
+        self.entries.push(entry);
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Whether a file with the same name and size has been downloaded
+    /// before, from anyone.
+    #[must_use]
+    pub fn has_downloaded(&self, filename: &str, size: u64) -> bool {
+        let name = base_name(filename);
+        self.entries.iter().any(|entry| {
+            entry.completed()
+                && entry.size == size
+                && base_name(&entry.filename) == name
+        })
+    }
+}
+
+fn base_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn append_line(file: &PathBuf, line: &str) -> io::Result<()> {
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(file)?;
+    writeln!(file, "{line}")
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    use std::fmt::Write as _;
+
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A value of the flat objects the history is written as.
+pub(crate) enum Value {
+    String(String),
+    Number(u64),
+    Null,
+}
+
+/// Parse a one-level JSON object of strings, unsigned integers and nulls.
+pub(crate) fn parse_object(line: &str) -> Option<HashMap<String, Value>> {
+    let mut chars = line.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    if chars.next()? != '{' {
+        return None;
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            '}' => break,
+            '"' => {}
+            _ => return None,
+        }
+        let key = parse_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_whitespace(&mut chars);
+        let value = match *chars.peek()? {
+            '"' => {
+                chars.next();
+                Value::String(parse_string(&mut chars)?)
+            }
+            'n' => {
+                let null: String = chars.by_ref().take(4).collect();
+                (null == "null").then_some(Value::Null)?
+            }
+            '0'..='9' => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek()
+                    && c.is_ascii_digit()
+                {
+                    digits.push(c);
+                    chars.next();
+                }
+                Value::Number(digits.parse().ok()?)
+            }
+            _ => return None,
+        };
+        fields.insert(key, value);
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => {}
+            '}' => break,
+            _ => return None,
+        }
+    }
+    Some(fields)
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+/// The rest of a string whose opening quote was consumed.
+fn parse_string(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+) -> Option<String> {
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).ok()?;
+                    value.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DownloadHistory, HistoryEntry};
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn entry(filename: &str, error: Option<&str>) -> HistoryEntry {
+        HistoryEntry {
+            username: "amy".to_string(),
+            filename: filename.to_string(),
+            size: 42,
+            path: error
+                .is_none()
+                .then(|| PathBuf::from("/music/a \"b\".flac")),
+            elapsed: Duration::from_millis(1500),
+            md5: error
+                .is_none()
+                .then(|| "d41d8cd98f00b204e9800998ecf8427e".into()),
+            error: error.map(String::from),
+            finished_at: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn entries_survive_a_reload_and_dedupe_by_name_and_size() {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-history-{}", std::process::id()));
+        let file = dir.join("history.jsonl");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut history = DownloadHistory::open(&file).unwrap();
+        assert!(history.entries().is_empty());
+        let done = entry("Music\\Album\\01 Tab\tand\nnewline.flac", None);
+        let failed = entry("Music\\02.flac", Some("The user went offline"));
+        history.record(done.clone());
+        history.record(failed.clone());
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&file)
+            .and_then(|mut f| std::io::Write::write_all(&mut f, b"{torn\n"))
+            .unwrap();
+
+        let reloaded = DownloadHistory::open(&file).unwrap();
+        assert_eq!(reloaded.entries(), [done, failed]);
+        assert!(
+            reloaded.has_downloaded("other\\01 Tab\tand\nnewline.flac", 42)
+        );
+        assert!(!reloaded.has_downloaded("01 Tab\tand\nnewline.flac", 43));
+        // Failed downloads don't count as grabbed.
+        assert!(!reloaded.has_downloaded("02.flac", 42));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! A command run after each completed download.
+//!
+//! Set one with `ClientSettings::on_download_complete`, e.g. `beet import
+//! {path}`, to hand finished files to a tagging or import pipeline. The
+//! command runs directly rather than through a shell, with `{path}`, `{user}`
+//! and `{size}` replaced in its arguments, so a peer's file name stays one
+//! argument. That only holds while the template doesn't hand a placeholder to
+//! a shell itself: in `sh -c 'cp {path} ~/in'` the file name becomes shell
+//! code. Pass it as a positional argument (`sh -c 'cp "$0" ~/in' {path}`)
+//! instead.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::types::{Download, DownloadSummary};
+use crate::{debug, warn};
+
+/// A command template like `beet import {path}`.
+///
+/// It is split into arguments on whitespace, where single or double quotes
+/// keep an argument together, before `{path}` (the saved file), `{user}`
+/// (the uploader) and `{size}` (in bytes) are filled in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadHook {
+    template: String,
+}
+
+impl DownloadHook {
+    #[must_use]
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    /// The template the hook was made with.
+    #[must_use]
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// The program and arguments run for a `size`-byte file saved at `path`
+    /// from `user`. Empty if the template is.
+    #[must_use]
+    pub fn command(&self, path: &Path, user: &str, size: u64) -> Vec<String> {
+        let path = path.to_string_lossy();
+        let size = size.to_string();
+        split_words(&self.template)
+            .into_iter()
+            .map(|word| fill(&word, &path, user, &size))
+            .collect()
+    }
+
+    /// Run the command for `download`, saved as `summary` says, in the
+    /// background. Its exit status is only logged.
+    pub(crate) fn run(&self, download: &Download, summary: &DownloadSummary) {
+        let command =
+            self.command(&summary.path, &download.username, download.size);
+        let Some((program, args)) = command.split_first() else {
+            return;
+        };
+        let (program, args) = (program.clone(), args.to_vec());
+        thread::spawn(move || {
+            let status = Command::new(&program)
+                .args(&args)
+                .stdin(Stdio::null())
+                .status();
+            match status {
+                Ok(status) if status.success() => {
+                    debug!("[download_hook] {} finished", program);
+                }
+                Ok(status) => {
+                    warn!("[download_hook] {} exited with {}", program, status);
+                }
+                Err(e) => {
+                    warn!("[download_hook] cannot run {}: {}", program, e);
+                }
+            }
+        });
+    }
+}
+
+/// Fill the placeholders in `word` in one pass, so a value that itself
+/// contains `{user}` or `{size}` is left as it is.
+#[allow(clippy::literal_string_with_formatting_args)] // the placeholders
+fn fill(word: &str, path: &str, user: &str, size: &str) -> String {
+    let mut filled = String::with_capacity(word.len());
+    let mut rest = word;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = [("{path}", path), ("{user}", user), ("{size}", size)]
+            .into_iter()
+            .find(|(placeholder, _)| rest.starts_with(placeholder));
+        if let Some((placeholder, value)) = value {
+            filled.push_str(value);
+            rest = &rest[placeholder.len()..];
+        } else {
+            filled.push('{');
+            rest = &rest[1..];
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Split `template` on whitespace, keeping quoted runs together and
+/// dropping the quotes.
+fn split_words(template: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in template.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DownloadHook;
+    use std::path::Path;
+
+    #[test]
+    fn placeholders_fill_whole_arguments() {
+        let hook = DownloadHook::new("beet import -q {path}");
+        assert_eq!(
+            hook.command(Path::new("/music/My Song.flac"), "amy", 42),
+            ["beet", "import", "-q", "/music/My Song.flac"]
+        );
+    }
+
+    #[test]
+    fn quotes_keep_an_argument_together() {
+        let hook = DownloadHook::new(
+            "notify-send 'Got {path}' \"from {user}, {size} B\"",
+        );
+        assert_eq!(
+            hook.command(Path::new("a.mp3"), "amy", 42),
+            ["notify-send", "Got a.mp3", "from amy, 42 B"]
+        );
+        assert!(
+            DownloadHook::new("  ")
+                .command(Path::new("a"), "b", 1)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn a_file_name_with_placeholders_is_not_filled_again() {
+        let hook = DownloadHook::new("mv {path} /in/{user}");
+        assert_eq!(
+            hook.command(Path::new("/dl/{user} {size}.mp3"), "amy", 42),
+            ["mv", "/dl/{user} {size}.mp3", "/in/amy"]
+        );
+    }
+}
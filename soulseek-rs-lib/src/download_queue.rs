@@ -0,0 +1,191 @@
+//! Unfinished downloads, saved so a restarted client can pick them up.
+//!
+//! [`Client::save_downloads`](crate::Client::save_downloads) writes one
+//! JSON line per queued or running download;
+//! [`Client::restore_downloads`](crate::Client::restore_downloads) queues
+//! them again, resuming from whatever part of each file is already on disk.
+
+use crate::download_history::{Value, escape, parse_object};
+use crate::peer::download_destination;
+use crate::types::Download;
+use crate::warn;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One download to pick up again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedDownload {
+    pub username: String,
+    /// The peer's path to the file.
+    pub filename: String,
+    /// The token it had; a restored download is issued a new one.
+    pub token: u32,
+    pub size: u64,
+    pub download_directory: String,
+    /// The file it is saved as, if that could be worked out.
+    pub destination: Option<PathBuf>,
+    /// Bytes of the file already on disk at `destination`.
+    pub bytes_written: u64,
+}
+
+impl QueuedDownload {
+    /// The entry for `download`, unless it has finished.
+    #[must_use]
+    pub fn from_download(download: &Download) -> Option<Self> {
+        if download.is_finished() {
+            return None;
+        }
+        let destination = download_destination(download);
+        let bytes_written = destination
+            .as_deref()
+            .and_then(|path| fs::metadata(path).ok())
+            .filter(fs::Metadata::is_file)
+            .map_or(0, |meta| meta.len().min(download.size));
+        Some(Self {
+            username: download.username.clone(),
+            filename: download.filename.clone(),
+            token: download.token,
+            size: download.size,
+            download_directory: download.download_directory.clone(),
+            destination,
+            bytes_written,
+        })
+    }
+
+    fn to_json(&self) -> String {
+        let string = |value: &str| format!("\"{}\"", escape(value));
+        let destination = self.destination.as_ref().map_or_else(
+            || "null".to_string(),
+            |path| string(&path.to_string_lossy()),
+        );
+        format!(
+            "{{\"username\":{},\"filename\":{},\"token\":{},\"size\":{},\"download_directory\":{},\"destination\":{},\"bytes_written\":{}}}",
+            string(&self.username),
+            string(&self.filename),
+            self.token,
+            self.size,
+            string(&self.download_directory),
+            destination,
+            self.bytes_written,
+        )
+    }
+
+    fn from_json(line: &str) -> Option<Self> {
+        let mut fields = parse_object(line)?;
+        let mut string = |key: &str| match fields.remove(key) {
+            Some(Value::String(value)) => Some(Some(value)),
+            Some(Value::Null) | None => Some(None),
+            Some(Value::Number(_)) => None,
+        };
+        let username = string("username")??;
+        let filename = string("filename")??;
+        let download_directory = string("download_directory")??;
+        let destination = string("destination")?.map(PathBuf::from);
+        let number = |key: &str| match fields.get(key) {
+            Some(Value::Number(value)) => Some(*value),
+            _ => None,
+        };
+        Some(Self {
+            username,
+            filename,
+            token: u32::try_from(number("token").unwrap_or(0)).ok()?,
+            size: number("size")?,
+            download_directory,
+            destination,
+            bytes_written: number("bytes_written").unwrap_or(0),
+        })
+    }
+}
+
+/// Replace `file` with `entries`. The new contents are written beside it
+/// first, so a crash mid-save leaves the previous queue intact.
+///
+/// # Errors
+/// Returns the error writing or renaming the file failed with.
+pub fn save(file: &Path, entries: &[QueuedDownload]) -> io::Result<()> {
+    if let Some(parent) = file.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = file.with_extension("partial");
+    let mut out = io::BufWriter::new(fs::File::create(&partial)?);
+    for entry in entries {
+        writeln!(out, "{}", entry.to_json())?;
+    }
+    out.into_inner()?.sync_all()?;
+    fs::rename(&partial, file)
+}
+
+/// The downloads saved in `file`. A missing file is an empty queue, and
+/// lines that don't parse are skipped.
+///
+/// # Errors
+/// Returns the error reading an existing `file` failed with.
+pub fn load(file: &Path) -> io::Result<Vec<QueuedDownload>> {
+    let text = match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(entry) = QueuedDownload::from_json(line) {
+            entries.push(entry);
+        } else {
+            warn!(
+                "[queue] {}:{}: skipping malformed entry",
+                file.display(),
+                number + 1
+            );
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QueuedDownload, load, save};
+    use std::path::PathBuf;
+
+    #[test]
+    fn entries_survive_a_save_and_load() {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-queue-{}", std::process::id()));
+        let file = dir.join("queue.jsonl");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(load(&file).unwrap().is_empty());
+
+        let entries = [
+            QueuedDownload {
+                username: "amy".to_string(),
+                filename: "Music\\Album\\01 \"Intro\".flac".to_string(),
+                token: 7,
+                size: 1000,
+                download_directory: "~/Downloads".to_string(),
+                destination: Some(PathBuf::from("/home/amy/01 Intro.flac")),
+                bytes_written: 250,
+            },
+            QueuedDownload {
+                username: "bo".to_string(),
+                filename: "02.mp3".to_string(),
+                token: 8,
+                size: 5,
+                download_directory: "/tmp".to_string(),
+                destination: None,
+                bytes_written: 0,
+            },
+        ];
+        save(&file, &entries).unwrap();
+        assert_eq!(load(&file).unwrap(), entries);
+
+        save(&file, &entries[1..]).unwrap();
+        assert_eq!(load(&file).unwrap(), entries[1..]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
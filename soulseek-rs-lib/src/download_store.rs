@@ -1,8 +1,12 @@
-use crate::types::{Download, DownloadStatus};
+use crate::types::{Download, DownloadStatus, FailureReason};
+use std::collections::HashSet;
 
+/// The downloads, in priority order: held ones are asked for first to last.
 #[derive(Default)]
 pub struct DownloadStore {
     downloads: Vec<Download>,
+    /// Downloads not asked for yet because enough others are active.
+    held: HashSet<u32>,
 }
 
 impl DownloadStore {
@@ -17,6 +21,52 @@ impl DownloadStore {
 
     pub fn remove(&mut self, token: u32) {
         self.downloads.retain(|d| d.token != token);
+        self.held.remove(&token);
+    }
+
+    /// Move download `token` to `position`, or last if that is past the
+    /// end. Returns whether it exists.
+    pub fn move_to(&mut self, token: u32, position: usize) -> bool {
+        let Some(index) = self.downloads.iter().position(|d| d.token == token)
+        else {
+            return false;
+        };
+        let download = self.downloads.remove(index);
+        let position = position.min(self.downloads.len());
+        self.downloads.insert(position, download);
+        true
+    }
+
+    /// Keep download `token` from being asked for until it is released.
+    pub fn hold(&mut self, token: u32) {
+        self.held.insert(token);
+    }
+
+    /// Stop holding download `token`; `false` if it wasn't held.
+    pub fn release(&mut self, token: u32) -> bool {
+        self.held.remove(&token)
+    }
+
+    #[must_use]
+    pub fn is_held(&self, token: u32) -> bool {
+        self.held.contains(&token)
+    }
+
+    /// How many downloads were asked for and haven't finished.
+    #[must_use]
+    pub fn active_count(&self) -> usize {
+        self.downloads
+            .iter()
+            .filter(|d| !d.is_finished() && !self.is_held(d.token))
+            .count()
+    }
+
+    /// The held download to ask for next: the first in priority order.
+    #[must_use]
+    pub fn next_held(&self) -> Option<&Download> {
+        self.downloads
+            .iter()
+            .find(|d| self.is_held(d.token) && !d.is_finished())
     }
 
     #[must_use]
@@ -28,6 +78,17 @@ impl DownloadStore {
         self.downloads.iter_mut().find(|d| d.token == token)
     }
 
+    #[must_use]
+    pub fn get_by_file(
+        &self,
+        username: &str,
+        filename: &str,
+    ) -> Option<&Download> {
+        self.downloads
+            .iter()
+            .find(|d| d.username == username && d.filename == filename)
+    }
+
     pub fn get_by_file_mut(
         &mut self,
         username: &str,
@@ -80,21 +141,40 @@ impl DownloadStore {
             return false;
         };
 
-        self.downloads.remove(index);
+        let removed = self.downloads.remove(index);
+        self.held.remove(&removed.token);
         true
     }
 
     /// Remove every download matching `username`/`filename` regardless of
-    /// status. Used before retrying a failed download so the stale entry (whose
-    /// md5-derived token collides with the retry's) can't shadow the fresh one.
+    /// status. Used before retrying a failed download so the stale entry can't
+    /// shadow the fresh one in lookups by file.
     /// Returns whether anything was removed.
     pub fn remove_by_file(&mut self, username: &str, filename: &str) -> bool {
         let before = self.downloads.len();
-        self.downloads
-            .retain(|d| !(d.username == username && d.filename == filename));
+        let held = &mut self.held;
+        self.downloads.retain(|d| {
+            let matches = d.username == username && d.filename == filename;
+            if matches {
+                held.remove(&d.token);
+            }
+            !matches
+        });
         self.downloads.len() != before
     }
 
+    /// Fail every unfinished download with `reason`, telling its receiver.
+    /// Returns how many were failed.
+    pub fn fail_unfinished(&mut self, reason: &str) -> usize {
+        let mut failed = 0;
+        for download in self.downloads.iter_mut().filter(|d| !d.is_finished()) {
+            download.status = DownloadStatus::Failed(Some(reason.into()));
+            let _ = download.sender.send(download.status.clone());
+            failed += 1;
+        }
+        failed
+    }
+
     pub fn pause_by_file(&mut self, username: &str, filename: &str) -> bool {
         let Some(download) = self.get_by_file_mut(username, filename) else {
             return false;
@@ -131,6 +211,8 @@ impl DownloadStore {
                 bytes_downloaded: *bytes_downloaded,
                 total_bytes: *total_bytes,
                 speed_bytes_per_sec: 0.0,
+                average_speed_bytes_per_sec: 0.0,
+                eta: None,
             },
             DownloadStatus::InProgress { .. } => return true,
             _ => return false,
@@ -143,7 +225,8 @@ impl DownloadStore {
 }
 
 /// Returns the tokens of downloads matching `username` (and optionally a
-/// `filename`) after notifying their senders of `Failed`.
+/// `filename`) after notifying their senders of `Failed`. Held downloads
+/// weren't asked for yet, so they are left alone.
 ///
 /// Caller is responsible for then calling `update_status` and `remove` for
 /// each token, typically under a write lock.
@@ -152,17 +235,18 @@ pub fn collect_failed_tokens(
     store: &DownloadStore,
     username: &str,
     filename: Option<&str>,
+    reason: &FailureReason,
 ) -> Vec<u32> {
     store
         .list()
         .iter()
         .filter(|d| {
-            d.username == username && filename.is_none_or(|f| d.filename == *f)
+            d.username == username
+                && filename.is_none_or(|f| d.filename == *f)
+                && !store.is_held(d.token)
         })
         .map(|d| {
-            let _ = d.sender.send(DownloadStatus::Failed(Some(
-                "The upload failed on the other side".to_string(),
-            )));
+            let _ = d.sender.send(DownloadStatus::Failed(Some(reason.clone())));
             d.token
         })
         .collect()
@@ -227,6 +311,8 @@ mod tests {
                 bytes_downloaded: 25,
                 total_bytes: 100,
                 speed_bytes_per_sec: 10.0,
+                average_speed_bytes_per_sec: 10.0,
+                eta: None,
             },
         );
         download.sender = tx;
@@ -254,7 +340,9 @@ mod tests {
             DownloadStatus::InProgress {
                 bytes_downloaded: 25,
                 total_bytes: 100,
-                speed_bytes_per_sec: 0.0
+                speed_bytes_per_sec: 0.0,
+                average_speed_bytes_per_sec: 0.0,
+                eta: None
             }
         ));
     }
@@ -269,6 +357,8 @@ mod tests {
                 bytes_downloaded: 25,
                 total_bytes: 100,
                 speed_bytes_per_sec: 10.0,
+                average_speed_bytes_per_sec: 10.0,
+                eta: None,
             },
         ));
         // Override second download's filename so they don't collide
@@ -331,12 +421,50 @@ mod tests {
         store.add(b);
         store.add(c);
 
-        let tokens = collect_failed_tokens(&store, "peer", Some("song.mp3"));
+        let tokens = collect_failed_tokens(
+            &store,
+            "peer",
+            Some("song.mp3"),
+            &FailureReason::Banned,
+        );
 
         assert_eq!(tokens, vec![1]);
         assert!(matches!(
             rx_match.try_recv().unwrap(),
-            DownloadStatus::Failed(_)
+            DownloadStatus::Failed(Some(FailureReason::Banned))
         ));
     }
+
+    #[test]
+    fn held_downloads_wait_in_priority_order() {
+        let mut store = DownloadStore::new();
+        for token in 1..=4 {
+            store.add(make_download(token, DownloadStatus::Queued));
+        }
+        store.hold(3);
+        store.hold(4);
+        assert_eq!(store.active_count(), 2);
+        assert_eq!(store.next_held().map(|d| d.token), Some(3));
+
+        assert!(store.move_to(4, 0));
+        assert!(store.move_to(1, 99));
+        assert_eq!(store.tokens(), vec![4, 2, 3, 1]);
+        assert_eq!(store.next_held().map(|d| d.token), Some(4));
+        assert!(!store.move_to(5, 0));
+
+        // A held download isn't connected to its uploader to fail with them.
+        let failed = collect_failed_tokens(
+            &store,
+            "peer",
+            None,
+            &FailureReason::UploadFailed,
+        );
+        assert_eq!(failed, vec![2, 1]);
+
+        assert!(store.release(4));
+        assert!(!store.release(4));
+        store.remove(3);
+        assert_eq!(store.next_held().map(|d| d.token), None);
+        assert_eq!(store.active_count(), 3);
+    }
 }
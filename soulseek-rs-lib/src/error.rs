@@ -1,46 +1,95 @@
-use std::{error::Error, fmt};
+use crate::types::FailureReason;
+use std::{error::Error, fmt, io};
 
 /// Custom error type for the Soulseek download library
 #[derive(Debug)]
 pub enum SoulseekRs {
-    NetworkError(std::io::Error),
-    /// Authentication failed during login
-    AuthenticationFailed,
-    /// Error parsing messages or data
-    ParseError(String),
+    /// An I/O operation failed; `op` says which
+    Io { op: &'static str, source: io::Error },
+    /// A message from the server or a peer could not be decoded. `code` is
+    /// the message's, once known.
+    Protocol { code: Option<u32>, detail: String },
+    /// A call was given something it cannot use
+    InvalidArgument(String),
     /// Operation timed out
     Timeout,
     /// Connection was closed unexpectedly
     ConnectionClosed,
-    /// Invalid message format or content
-    InvalidMessage(String),
     /// Server not connected
     NotConnected,
+    /// No connection to `username` is open or could be made
+    PeerUnreachable { username: String },
+    /// `username` is on the ignore list, so no connection is made with them
+    Ignored { username: String },
+    /// The peer refused or failed a transfer
+    TransferRejected { reason: FailureReason },
     /// Compression/decompression error
     CompressionError(String),
+    /// A zlib stream decompressed, but its Adler-32 trailer didn't match
+    /// the output (the payload was corrupted in transit or by the sender)
+    ChecksumMismatch { expected: u32, actual: u32 },
     /// A lock was poisoned by a panic in another thread
     LockPoisoned,
 }
 
+impl SoulseekRs {
+    /// For `map_err`: wrap an I/O error from `op`.
+    pub fn io(op: &'static str) -> impl FnOnce(io::Error) -> Self {
+        move |source| Self::Io { op, source }
+    }
+
+    /// A protocol error not yet tied to a message code.
+    pub fn protocol(detail: impl Into<String>) -> Self {
+        Self::Protocol {
+            code: None,
+            detail: detail.into(),
+        }
+    }
+
+    /// Attach `code` to a protocol error that doesn't have one yet.
+    #[must_use]
+    pub fn in_message(self, code: u32) -> Self {
+        match self {
+            Self::Protocol { code: None, detail } => Self::Protocol {
+                code: Some(code),
+                detail,
+            },
+            other => other,
+        }
+    }
+}
+
 impl fmt::Display for SoulseekRs {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::NetworkError(err) => {
-                write!(f, "Network error: {err}")
+            Self::Io { op, source } => write!(f, "Cannot {op}: {source}"),
+            Self::Protocol {
+                code: Some(code),
+                detail,
+            } => write!(f, "Bad message {code}: {detail}"),
+            Self::Protocol { code: None, detail } => {
+                write!(f, "Bad message: {detail}")
             }
-            Self::AuthenticationFailed => {
-                write!(f, "Authentication failed")
+            Self::InvalidArgument(msg) => {
+                write!(f, "Invalid argument: {msg}")
             }
-            Self::ParseError(msg) => write!(f, "Parse error: {msg}"),
             Self::Timeout => write!(f, "Operation timed out"),
             Self::ConnectionClosed => write!(f, "Connection closed"),
-            Self::InvalidMessage(msg) => {
-                write!(f, "Invalid message: {msg}")
-            }
             Self::NotConnected => write!(f, "Not connected to server"),
+            Self::PeerUnreachable { username } => {
+                write!(f, "Cannot reach {username}")
+            }
+            Self::Ignored { username } => write!(f, "{username} is ignored"),
+            Self::TransferRejected { reason } => {
+                write!(f, "Transfer rejected: {reason}")
+            }
             Self::CompressionError(msg) => {
                 write!(f, "Compression error: {msg}")
             }
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Checksum mismatch: expected {expected:#010x}, got {actual:#010x}"
+            ),
             Self::LockPoisoned => {
                 write!(f, "Lock poisoned by panicking thread")
             }
@@ -51,27 +100,15 @@ impl fmt::Display for SoulseekRs {
 impl Error for SoulseekRs {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            Self::NetworkError(err) => Some(err),
+            Self::Io { source, .. } => Some(source),
             _ => None,
         }
     }
 }
 
-impl From<std::io::Error> for SoulseekRs {
-    fn from(err: std::io::Error) -> Self {
-        Self::NetworkError(err)
-    }
-}
-
-impl From<std::num::ParseIntError> for SoulseekRs {
-    fn from(err: std::num::ParseIntError) -> Self {
-        Self::ParseError(format!("Integer parse error: {err}"))
-    }
-}
-
-impl From<String> for SoulseekRs {
-    fn from(err: String) -> Self {
-        Self::CompressionError(err)
+impl From<FailureReason> for SoulseekRs {
+    fn from(reason: FailureReason) -> Self {
+        Self::TransferRejected { reason }
     }
 }
 
@@ -0,0 +1,58 @@
+//! Ignoring users, such as known fake-file spammers.
+//!
+//! The client drops an ignored user's search results, refuses their peer
+//! connections and doesn't answer their searches. The list is shared by the
+//! client and its peer registry, so ignoring someone also applies to
+//! connections made from then on.
+
+use crate::utils::lock::RwLockExt;
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+/// Usernames the client ignores.
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    users: RwLock<HashSet<String>>,
+}
+
+impl IgnoreList {
+    #[must_use]
+    pub fn new(users: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            users: RwLock::new(users.into_iter().collect()),
+        }
+    }
+
+    /// Ignore `username`; `false` if they already were.
+    pub fn ignore(&self, username: &str) -> bool {
+        self.users
+            .write_safe()
+            .is_ok_and(|mut users| users.insert(username.to_string()))
+    }
+
+    /// Stop ignoring `username`; `false` if they weren't.
+    pub fn unignore(&self, username: &str) -> bool {
+        self.users
+            .write_safe()
+            .is_ok_and(|mut users| users.remove(username))
+    }
+
+    #[must_use]
+    pub fn contains(&self, username: &str) -> bool {
+        self.users
+            .read_safe()
+            .is_ok_and(|users| users.contains(username))
+    }
+
+    /// The ignored usernames, sorted.
+    #[must_use]
+    pub fn users(&self) -> Vec<String> {
+        let mut users: Vec<String> = self
+            .users
+            .read_safe()
+            .map(|users| users.iter().cloned().collect())
+            .unwrap_or_default();
+        users.sort();
+        users
+    }
+}
@@ -0,0 +1,170 @@
+//! Refusing uploads to users who share little or nothing.
+//!
+//! A common courtesy rule on Soulseek: only serve peers who share files
+//! themselves. With a [`LeechFilter`] set on the client, a `QueueUpload` from
+//! a user whose shared file or folder count (from `GetUserStats`) is below the
+//! thresholds is answered with `UploadDenied` instead of being queued.
+
+use std::collections::HashSet;
+
+use crate::types::UserInfo;
+
+/// Sent to denied users unless [`LeechFilter::deny_reason`] replaces it.
+pub const DEFAULT_DENY_REASON: &str =
+    "Sorry, please share some files before downloading from me.";
+
+/// Thresholds a requesting user's shares must meet before we queue their
+/// upload. Build one with the chained setters; an unset threshold accepts
+/// everyone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeechFilter {
+    min_shared_files: u32,
+    min_shared_folders: u32,
+    whitelist: HashSet<String>,
+    deny_reason: String,
+}
+
+impl Default for LeechFilter {
+    fn default() -> Self {
+        Self {
+            min_shared_files: 0,
+            min_shared_folders: 0,
+            whitelist: HashSet::new(),
+            deny_reason: DEFAULT_DENY_REASON.to_string(),
+        }
+    }
+}
+
+/// What a [`LeechFilter`] makes of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeechVerdict {
+    Allow,
+    Deny,
+    /// The user's share counts aren't known yet; ask the server for them.
+    NeedStats,
+}
+
+impl LeechFilter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deny users sharing fewer than `count` files.
+    #[must_use]
+    pub const fn min_shared_files(mut self, count: u32) -> Self {
+        self.min_shared_files = count;
+        self
+    }
+
+    /// Deny users sharing fewer than `count` folders.
+    #[must_use]
+    pub const fn min_shared_folders(mut self, count: u32) -> Self {
+        self.min_shared_folders = count;
+        self
+    }
+
+    /// Users always served, whatever they share.
+    #[must_use]
+    pub fn whitelist<I, S>(mut self, usernames: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.whitelist = usernames.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The message sent along with the denial.
+    #[must_use]
+    pub fn deny_reason(mut self, reason: impl Into<String>) -> Self {
+        self.deny_reason = reason.into();
+        self
+    }
+
+    #[must_use]
+    pub fn reason(&self) -> &str {
+        &self.deny_reason
+    }
+
+    /// Judge `username` by what we know about them.
+    #[must_use]
+    pub fn verdict(
+        &self,
+        username: &str,
+        info: Option<&UserInfo>,
+    ) -> LeechVerdict {
+        if self.whitelist.contains(username)
+            || (self.min_shared_files == 0 && self.min_shared_folders == 0)
+        {
+            return LeechVerdict::Allow;
+        }
+        let Some((files, folders)) =
+            info.and_then(|info| info.shared_files.zip(info.shared_folders))
+        else {
+            return LeechVerdict::NeedStats;
+        };
+        if files < self.min_shared_files || folders < self.min_shared_folders {
+            LeechVerdict::Deny
+        } else {
+            LeechVerdict::Allow
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sharing(files: u32, folders: u32) -> UserInfo {
+        UserInfo {
+            shared_files: Some(files),
+            shared_folders: Some(folders),
+            ..UserInfo::new("bob")
+        }
+    }
+
+    #[test]
+    fn default_filter_allows_everyone_without_stats() {
+        let filter = LeechFilter::new();
+        assert_eq!(filter.verdict("bob", None), LeechVerdict::Allow);
+    }
+
+    #[test]
+    fn thresholds_need_stats_then_judge_them() {
+        let filter = LeechFilter::new()
+            .min_shared_files(100)
+            .min_shared_folders(2);
+        assert_eq!(filter.verdict("bob", None), LeechVerdict::NeedStats);
+        assert_eq!(
+            filter.verdict("bob", Some(&UserInfo::new("bob"))),
+            LeechVerdict::NeedStats
+        );
+        assert_eq!(
+            filter.verdict("bob", Some(&sharing(0, 0))),
+            LeechVerdict::Deny
+        );
+        assert_eq!(
+            filter.verdict("bob", Some(&sharing(500, 1))),
+            LeechVerdict::Deny
+        );
+        assert_eq!(
+            filter.verdict("bob", Some(&sharing(500, 12))),
+            LeechVerdict::Allow
+        );
+    }
+
+    #[test]
+    fn whitelisted_users_are_always_allowed() {
+        let filter = LeechFilter::new()
+            .min_shared_files(100)
+            .whitelist(["bob"])
+            .deny_reason("Share first");
+        assert_eq!(
+            filter.verdict("bob", Some(&sharing(0, 0))),
+            LeechVerdict::Allow
+        );
+        assert_eq!(filter.verdict("eve", None), LeechVerdict::NeedStats);
+        assert_eq!(filter.reason(), "Share first");
+    }
+}
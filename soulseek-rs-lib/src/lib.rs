@@ -2,11 +2,23 @@
 pub mod actor;
 pub mod client;
 pub mod dispatcher;
+pub mod download_history;
+pub mod download_hook;
+pub mod download_queue;
 pub mod download_store;
 pub mod error;
+pub mod ignore_list;
+pub mod leech_filter;
 pub mod message;
+pub mod metrics;
 pub mod peer;
+pub mod result_ranker;
+pub mod search_filter;
+pub mod search_limiter;
 pub mod shares;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod transport;
 pub mod types;
 #[macro_use]
 pub mod utils;
@@ -15,14 +27,29 @@ pub mod utils;
 pub mod prelude {
     pub use crate::actor::server_actor::PeerAddress;
     pub use crate::types::{
-        DownloadStatus, File, Search, SearchResult, Transfer,
+        DownloadStatus, DownloadSummary, File, FileAttributes, Search,
+        SearchResult, Transfer,
     };
     pub use crate::{debug, error, info, trace, warn};
 }
 
 // Re-export commonly used types
 pub use actor::server_actor::{PeerAddress, UserMessage};
-pub use client::{Client, ClientSettings};
+pub use client::{Client, ClientSettings, ClientState, DistributedParent};
+pub use download_hook::DownloadHook;
 pub use error::{Result, SoulseekRs};
+pub use ignore_list::IgnoreList;
+pub use leech_filter::LeechFilter;
+pub use message::MessageSizeLimits;
 pub use message::peer::SharedDirectory;
-pub use types::{DownloadStatus, File, Search, SearchResult, Transfer};
+pub use metrics::{MessageCounts, MetricsSnapshot};
+pub use result_ranker::{DefaultRanker, RankedFile, ResultRanker};
+pub use search_filter::SearchFilter;
+pub use search_limiter::SearchLimits;
+pub use transport::{PeerTlsSettings, TlsSettings};
+pub use types::{
+    ClientEvent, ConflictPolicy, ConnectionState, DownloadStatus,
+    DownloadSummary, FailureReason, File, FileAttributes, LoginOutcome,
+    LoginRejection, Search, SearchResult, Transfer, UserStatus,
+};
+pub use utils::charset::Charset;
@@ -0,0 +1,140 @@
+//! A cheaply cloned, sliceable byte buffer.
+//!
+//! Inbound messages are parsed where they were read: cloning a
+//! [`Message`](super::Message), or taking a view of part of one, shares the
+//! buffer taken off the socket instead of copying it. Writing to a buffer
+//! that is shared, or that is a view, copies it first.
+
+use std::fmt;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+#[derive(Clone, Default)]
+pub struct Bytes {
+    buf: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl Bytes {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The bytes in `range`, sharing this buffer.
+    ///
+    /// # Panics
+    /// If `range` is out of bounds, like slice indexing.
+    #[must_use]
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range {range:?} out of bounds for {} bytes",
+            self.len()
+        );
+        Self {
+            buf: self.buf.clone(),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        let buf = self.make_mut();
+        buf.extend_from_slice(bytes);
+        self.end = buf.len();
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        let buf = self.make_mut();
+        buf.push(byte);
+        self.end = buf.len();
+    }
+
+    /// The bytes as a vector, moved out when nothing else shares them.
+    #[must_use]
+    pub fn into_vec(self) -> Vec<u8> {
+        if self.start == 0 && self.end == self.buf.len() {
+            Arc::unwrap_or_clone(self.buf)
+        } else {
+            self.to_vec()
+        }
+    }
+
+    /// The whole buffer, owned by us alone, for appending to.
+    fn make_mut(&mut self) -> &mut Vec<u8> {
+        if self.start != 0 || self.end != self.buf.len() {
+            self.buf = Arc::new(self.to_vec());
+            self.start = 0;
+        }
+        Arc::make_mut(&mut self.buf)
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(buf: Vec<u8>) -> Self {
+        let end = buf.len();
+        Self {
+            buf: Arc::new(buf),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Bytes {}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bytes;
+
+    #[test]
+    fn slices_share_the_buffer_until_written() {
+        let whole = Bytes::from(vec![1, 2, 3, 4, 5]);
+        let mut middle = whole.slice(1..4);
+        assert_eq!(&*middle, [2, 3, 4]);
+        assert_eq!(whole[1..].as_ptr(), middle.as_ptr());
+
+        middle.push(9);
+        assert_eq!(&*middle, [2, 3, 4, 9]);
+        assert_eq!(&*whole, [1, 2, 3, 4, 5]);
+        assert_eq!(middle.slice(2..4).into_vec(), vec![4, 9]);
+    }
+}
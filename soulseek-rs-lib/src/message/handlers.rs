@@ -1,14 +1,57 @@
 use std::collections::HashMap;
 
+use crate::dispatcher::Interceptor;
+use crate::error::Result;
 use crate::message::Message;
+use crate::utils::lock::RwLockExt;
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, RwLock};
 
 pub trait MessageHandler<Op>: Send {
-    fn get_code(&self) -> u8;
-    fn handle(&self, message: &mut Message, sender: Sender<Op>);
+    /// The full message code handled, as the message carries it.
+    fn get_code(&self) -> u32;
+    /// Decode `message` and forward the result on `sender`. A malformed
+    /// message is reported as an error instead of being forwarded.
+    fn handle(&self, message: &mut Message, sender: Sender<Op>) -> Result<()>;
 }
+
+impl<Op, H> MessageHandler<Op> for Arc<H>
+where
+    H: MessageHandler<Op> + Sync + ?Sized,
+{
+    fn get_code(&self) -> u32 {
+        (**self).get_code()
+    }
+    fn handle(&self, message: &mut Message, sender: Sender<Op>) -> Result<()> {
+        (**self).handle(message, sender)
+    }
+}
+
+/// Two handlers for one code, run in turn on the same message.
+struct Chained<Op> {
+    first: Box<dyn MessageHandler<Op> + Send>,
+    then: Box<dyn MessageHandler<Op> + Send>,
+}
+
+impl<Op> MessageHandler<Op> for Chained<Op> {
+    fn get_code(&self) -> u32 {
+        self.first.get_code()
+    }
+    fn handle(&self, message: &mut Message, sender: Sender<Op>) -> Result<()> {
+        let start = message.get_pointer();
+        let first = self.first.handle(message, sender.clone());
+        message.set_pointer(start);
+        let then = self.then.handle(message, sender);
+        first.and(then)
+    }
+}
+
 pub struct Handlers<Op> {
-    handlers: HashMap<u8, Box<dyn MessageHandler<Op> + Send>>,
+    by_code: HashMap<u32, Box<dyn MessageHandler<Op> + Send>>,
+    /// Handlers for the first message on a peer connection, whose code is a
+    /// single byte.
+    by_init_code: HashMap<u32, Box<dyn MessageHandler<Op> + Send>>,
+    fallback: Option<Box<dyn MessageHandler<Op> + Send>>,
 }
 
 impl<Op> Default for Handlers<Op> {
@@ -21,7 +64,9 @@ impl<Op> Handlers<Op> {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            handlers: HashMap::new(),
+            by_code: HashMap::new(),
+            by_init_code: HashMap::new(),
+            fallback: None,
         }
     }
 
@@ -29,14 +74,187 @@ impl<Op> Handlers<Op> {
     where
         H: 'static + MessageHandler<Op> + Send + Sync,
     {
-        self.handlers.insert(handler.get_code(), Box::new(handler));
+        self.by_code.insert(handler.get_code(), Box::new(handler));
+        self
+    }
+    /// Like [`Self::register_handler`], but a handler already registered
+    /// for the code is kept and runs first, so the new one only adds to it.
+    pub fn add_handler<H>(&mut self, handler: H) -> &mut Self
+    where
+        H: 'static + MessageHandler<Op> + Send + Sync,
+        Op: 'static,
+    {
+        let code = handler.get_code();
+        let then: Box<dyn MessageHandler<Op> + Send> = Box::new(handler);
+        let handler = match self.by_code.remove(&code) {
+            Some(first) => Box::new(Chained { first, then }),
+            None => then,
+        };
+        self.by_code.insert(code, handler);
+        self
+    }
+
+    /// Register `handler` for a message with a one-byte code, like
+    /// `PeerInit`. It is only used when no handler matches the message's
+    /// full code.
+    pub fn register_init_handler<H>(&mut self, handler: H) -> &mut Self
+    where
+        H: 'static + MessageHandler<Op> + Send + Sync,
+    {
+        self.by_init_code
+            .insert(handler.get_code(), Box::new(handler));
         self
     }
+
     #[must_use]
-    pub fn get_handler(
+    pub fn get_init_handler(
         &self,
         code: u8,
     ) -> Option<&(dyn MessageHandler<Op> + Send)> {
-        self.handlers.get(&code).map(|v| &**v)
+        self.by_init_code.get(&u32::from(code)).map(|v| &**v)
+    }
+
+    #[must_use]
+    pub fn get_handler(
+        &self,
+        code: u32,
+    ) -> Option<&(dyn MessageHandler<Op> + Send)> {
+        self.by_code.get(&code).map(|v| &**v)
+    }
+
+    /// Pass messages whose code has no handler to `handler` instead of
+    /// dropping them, to observe or decode messages this crate doesn't know
+    /// yet. Its `get_code` is not consulted.
+    pub fn set_fallback<H>(&mut self, handler: H) -> &mut Self
+    where
+        H: 'static + MessageHandler<Op> + Send + Sync,
+    {
+        self.fallback = Some(Box::new(handler));
+        self
+    }
+
+    #[must_use]
+    pub fn get_fallback(&self) -> Option<&(dyn MessageHandler<Op> + Send)> {
+        self.fallback.as_deref()
+    }
+}
+
+type SharedHandler<Op> = Arc<dyn MessageHandler<Op> + Sync>;
+
+/// Handlers and interceptors an embedder added for one kind of connection,
+/// shared by the client and every such connection. Each installs them on
+/// its dispatcher when it opens.
+pub struct CustomHandlers<Op> {
+    handlers: RwLock<Vec<SharedHandler<Op>>>,
+    fallback: RwLock<Option<SharedHandler<Op>>>,
+    interceptors: RwLock<Vec<Arc<dyn Interceptor>>>,
+}
+
+impl<Op> Default for CustomHandlers<Op> {
+    fn default() -> Self {
+        Self {
+            handlers: RwLock::new(Vec::new()),
+            fallback: RwLock::new(None),
+            interceptors: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl<Op: 'static> CustomHandlers<Op> {
+    pub fn add<H>(&self, handler: H) -> Result<()>
+    where
+        H: 'static + MessageHandler<Op> + Sync,
+    {
+        self.handlers.write_safe()?.push(Arc::new(handler));
+        Ok(())
+    }
+
+    pub fn set_fallback<H>(&self, handler: H) -> Result<()>
+    where
+        H: 'static + MessageHandler<Op> + Sync,
+    {
+        *self.fallback.write_safe()? = Some(Arc::new(handler));
+        Ok(())
+    }
+
+    /// Run `interceptor` after those added before it.
+    pub fn add_interceptor<I>(&self, interceptor: I) -> Result<()>
+    where
+        I: 'static + Interceptor,
+    {
+        self.interceptors.write_safe()?.push(Arc::new(interceptor));
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn interceptors(&self) -> Vec<Arc<dyn Interceptor>> {
+        self.interceptors
+            .read_safe()
+            .map(|interceptors| interceptors.clone())
+            .unwrap_or_default()
+    }
+
+    /// Add every handler to `handlers`, after the ones already there, and
+    /// the fallback.
+    pub fn install(&self, handlers: &mut Handlers<Op>) {
+        if let Ok(custom) = self.handlers.read_safe() {
+            for handler in custom.iter() {
+                handlers.add_handler(handler.clone());
+            }
+        }
+        if let Ok(fallback) = self.fallback.read_safe()
+            && let Some(fallback) = fallback.as_ref()
+        {
+            handlers.set_fallback(fallback.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CustomHandlers, Handlers, MessageHandler};
+    use crate::message::Message;
+    use std::sync::mpsc::{self, Sender};
+
+    /// Reports its tag and the int32 it reads.
+    struct Reads(u32, &'static str);
+
+    impl MessageHandler<(&'static str, u32)> for Reads {
+        fn get_code(&self) -> u32 {
+            self.0
+        }
+        fn handle(
+            &self,
+            message: &mut Message,
+            sender: Sender<(&'static str, u32)>,
+        ) -> crate::Result<()> {
+            let _ = sender.send((self.1, message.try_read_int32()?));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_handlers_run_after_the_built_in_ones() {
+        let custom = CustomHandlers::default();
+        custom.add(Reads(1, "custom")).unwrap();
+        custom.add(Reads(2, "new")).unwrap();
+        custom.set_fallback(Reads(0, "fallback")).unwrap();
+
+        let mut handlers = Handlers::new();
+        handlers.register_handler(Reads(1, "built-in"));
+        custom.install(&mut handlers);
+
+        let (sender, receiver) = mpsc::channel();
+        for code in [1, 2] {
+            let mut message = Message::new_with_data(vec![9, 0, 0, 0]);
+            let handler = handlers.get_handler(code).unwrap();
+            handler.handle(&mut message, sender.clone()).unwrap();
+        }
+        assert!(handlers.get_handler(3).is_none());
+        assert!(handlers.get_fallback().is_some());
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            [("built-in", 9), ("custom", 9), ("new", 9)]
+        );
     }
 }
@@ -1,14 +1,45 @@
+use std::collections::VecDeque;
 use std::io::{self, Read};
-use std::{collections::VecDeque, net::TcpStream};
 
+use crate::error::SoulseekRs;
 use crate::message::Message;
 
 // Soulseek messages are length-prefixed (u32 LE size, then payload). TCP gives us
 // arbitrary-sized chunks, so we accumulate into a buffer and only emit a Message
 // once size + 4 bytes are available.
 
+/// Largest message a reader built with [`MessageReader::new`] accepts.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Largest message accepted on each kind of connection, in bytes. A length
+/// prefix past it drops the connection before the message is buffered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSizeLimits {
+    /// The server connection.
+    pub server: usize,
+    /// Peer connections, which carry the biggest messages: a large share's
+    /// compressed file list.
+    pub peer: usize,
+    /// Our distributed parent, which only relays searches.
+    pub distributed: usize,
+}
+
+impl Default for MessageSizeLimits {
+    fn default() -> Self {
+        Self {
+            server: 16 * 1024 * 1024,
+            peer: DEFAULT_MAX_MESSAGE_SIZE,
+            distributed: 1024 * 1024,
+        }
+    }
+}
+
 pub struct MessageReader {
     buffer: VecDeque<u8>,
+    max_size: usize,
+    /// Offset in `buffer` of the first length prefix not yet checked
+    /// against `max_size`.
+    unchecked: usize,
 }
 
 impl Default for MessageReader {
@@ -22,20 +53,56 @@ impl MessageReader {
     pub const fn new() -> Self {
         Self {
             buffer: VecDeque::new(),
+            max_size: DEFAULT_MAX_MESSAGE_SIZE,
+            unchecked: 0,
         }
     }
 
+    /// Reject messages longer than `max_size` bytes, length prefix
+    /// excluded.
+    #[must_use]
+    pub const fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
     #[cfg(test)]
     #[must_use]
     pub fn new_with_buffer(buffer: Vec<u8>) -> Self {
         Self {
             buffer: buffer.into(),
+            ..Self::new()
+        }
+    }
+
+    /// Fails with `InvalidData` once a buffered length prefix exceeds the
+    /// maximum, so the caller drops the connection instead of waiting for
+    /// (and buffering) the rest.
+    fn check_size(&self, size: usize) -> io::Result<()> {
+        if size <= self.max_size {
+            return Ok(());
         }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            SoulseekRs::protocol(format!(
+                "message of {size} bytes exceeds the {} byte limit",
+                self.max_size
+            )),
+        ))
+    }
+
+    fn size_at(&self, offset: usize) -> usize {
+        u32::from_le_bytes([
+            self.buffer[offset],
+            self.buffer[offset + 1],
+            self.buffer[offset + 2],
+            self.buffer[offset + 3],
+        ]) as usize
     }
 
-    pub fn read_from_socket(
+    pub fn read_from_socket<R: Read>(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut R,
     ) -> io::Result<()> {
         let mut temp_buffer = [0; 1024]; // Temporary buffer for reading from the socket
         let bytes_read = stream.read(&mut temp_buffer)?;
@@ -46,6 +113,14 @@ impl MessageReader {
         // Add the read bytes to the internal buffer
         self.buffer.extend(&temp_buffer[..bytes_read]);
 
+        // Callers read until the socket runs dry before extracting, so check
+        // every length prefix as it arrives, not only the first.
+        while self.unchecked + 4 <= self.buffer.len() {
+            let size = self.size_at(self.unchecked);
+            self.check_size(size)?;
+            self.unchecked += size + 4;
+        }
+
         Ok(())
     }
 
@@ -55,6 +130,7 @@ impl MessageReader {
     }
 
     pub fn get_buffer(&mut self) -> Vec<u8> {
+        self.unchecked = 0;
         self.buffer.drain(..).collect()
     }
 
@@ -64,12 +140,8 @@ impl MessageReader {
             return Ok(None);
         }
 
-        let message_size = u32::from_le_bytes([
-            self.buffer[0],
-            self.buffer[1],
-            self.buffer[2],
-            self.buffer[3],
-        ]) as usize;
+        let message_size = self.size_at(0);
+        self.check_size(message_size)?;
 
         let total_size = message_size + 4;
 
@@ -77,6 +149,7 @@ impl MessageReader {
             return Ok(None);
         }
 
+        self.unchecked = self.unchecked.saturating_sub(total_size);
         let message_buffer: Vec<u8> = self.buffer.drain(..total_size).collect();
         Ok(Some(Message::new_with_data(message_buffer)))
     }
@@ -84,6 +157,8 @@ impl MessageReader {
 
 #[cfg(test)]
 mod tests {
+    use std::io;
+
     use crate::message::MessageReader;
 
     #[test]
@@ -121,4 +196,33 @@ mod tests {
         assert!(buffered_reader.buffer.is_empty());
         assert_eq!(vec![1, 2, 3], rest);
     }
+
+    #[test]
+    fn oversized_messages_are_rejected_before_they_are_buffered() {
+        let mut reader = MessageReader::new().with_max_size(16);
+        let mut stream: &[u8] = &[0xff, 0xff, 0xff, 0xff, 1, 2];
+        let err = reader.read_from_socket(&mut stream).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut reader =
+            MessageReader::new_with_buffer(vec![17, 0, 0, 0]).with_max_size(16);
+        assert!(reader.extract_message().is_err());
+    }
+
+    #[test]
+    fn every_buffered_length_prefix_is_checked() {
+        let mut reader = MessageReader::new().with_max_size(16);
+        // A small message followed by an oversized one in the same read.
+        let mut stream: &[u8] = &[4, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1];
+        assert!(reader.read_from_socket(&mut stream).is_err());
+
+        let mut reader = MessageReader::new().with_max_size(16);
+        let mut stream: &[u8] = &[4, 0, 0, 0, 1, 0, 0, 0, 16, 0, 0, 0];
+        reader.read_from_socket(&mut stream).unwrap();
+        let message = reader.extract_message().unwrap().unwrap();
+        assert_eq!(message.get_data(), vec![4, 0, 0, 0, 1, 0, 0, 0]);
+        assert!(reader.extract_message().unwrap().is_none());
+        let mut stream: &[u8] = &[20, 0, 0, 0];
+        reader.read_from_socket(&mut stream).unwrap();
+    }
 }
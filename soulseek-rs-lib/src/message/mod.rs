@@ -1,14 +1,25 @@
 pub use crate::{debug, error, info, trace, warn};
 
+mod bytes;
 pub mod handlers;
 mod message_reader;
 pub mod peer;
+pub mod protocol;
 pub mod server;
+pub mod wire;
 
-pub use handlers::{Handlers, MessageHandler};
-pub use message_reader::MessageReader;
+pub use bytes::Bytes;
+pub use handlers::{CustomHandlers, Handlers, MessageHandler};
+pub use message_reader::{
+    DEFAULT_MAX_MESSAGE_SIZE, MessageReader, MessageSizeLimits,
+};
+pub use protocol::{
+    DistributedMessageIn, PeerInitMessage, PeerMessageIn, PeerMessageOut,
+    ServerMessageIn, ServerMessageOut, TransferReply,
+};
 
-use std::str;
+use crate::error::SoulseekRs;
+use crate::utils::charset::{self, Charset};
 
 #[derive(Debug, PartialEq, Eq)]
 #[allow(dead_code)]
@@ -30,9 +41,11 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+/// A message and a read cursor into it. The bytes are shared, so a clone or
+/// a [`Self::view`] costs no copy.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Message {
-    data: Vec<u8>,
+    data: Bytes,
     pointer: usize,
 }
 
@@ -44,55 +57,59 @@ impl Default for Message {
 
 impl Message {
     #[must_use]
-    pub const fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            data: Vec::new(),
+            data: Bytes::new(),
             pointer: 0,
         }
     }
 
     pub fn print_hex(&self) {
-        const BYTES_PER_LINE: usize = 16;
-        let data = &self.data;
+        print!("{}", self.hex_dump(self.data.len()));
+    }
 
-        let chunks = data.chunks(BYTES_PER_LINE);
-        for (i, chunk) in chunks.enumerate() {
-            print!("{:04x}  ", i * BYTES_PER_LINE);
+    /// A classic offset / hex / ASCII dump of the first `limit` bytes, one
+    /// line per 16 bytes, noting how many bytes were left out.
+    #[must_use]
+    pub fn hex_dump(&self, limit: usize) -> String {
+        use std::fmt::Write as _;
+
+        const BYTES_PER_LINE: usize = 16;
+        let shown = &self.data[..self.data.len().min(limit)];
+        let mut out = String::new();
 
+        for (i, chunk) in shown.chunks(BYTES_PER_LINE).enumerate() {
+            let _ = write!(out, "{:04x}  ", i * BYTES_PER_LINE);
             for j in 0..BYTES_PER_LINE {
-                if j < chunk.len() {
-                    print!("{:02x} ", chunk[j]);
-                } else {
-                    print!(" ");
+                match chunk.get(j) {
+                    Some(byte) => {
+                        let _ = write!(out, "{byte:02x} ");
+                    }
+                    None => out.push_str("   "),
                 }
-
                 // Add extra space in the middle
                 if j == 7 {
-                    print!(" ");
+                    out.push(' ');
                 }
             }
-
-            print!("  ");
-
-            let mut i = 0;
+            out.push(' ');
             for &byte in chunk {
-                i += 1;
-                if byte.is_ascii_graphic() || byte.is_ascii_whitespace() {
-                    print!("{}", byte as char);
+                out.push(if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
                 } else {
-                    print!(".");
-                }
-
-                if i == 8 {
-                    print!(" ");
-                }
+                    '.'
+                });
             }
+            out.push('\n');
+        }
 
-            trace!("");
+        let omitted = self.data.len() - shown.len();
+        if omitted > 0 {
+            let _ = writeln!(out, "… {omitted} more bytes");
         }
+        out
     }
 
-    #[allow(dead_code)]
     #[must_use]
     pub fn get_message_code_u32(&self) -> u32 {
         if self.data.len() < 8 {
@@ -118,8 +135,24 @@ impl Message {
     }
 
     #[must_use]
-    pub const fn new_with_data(data: Vec<u8>) -> Self {
-        Self { data, pointer: 0 }
+    pub fn new_with_data(data: impl Into<Bytes>) -> Self {
+        Self {
+            data: data.into(),
+            pointer: 0,
+        }
+    }
+
+    /// Bytes `from..to` as a message of their own, read from the start. It
+    /// shares this message's buffer.
+    #[must_use]
+    pub fn view(&self, from: usize, to: usize) -> Self {
+        Self::new_with_data(self.data.slice(from..to))
+    }
+
+    /// The bytes not read yet, as a [`Self::view`].
+    #[must_use]
+    pub fn remaining(&self) -> Self {
+        self.view(self.pointer.min(self.data.len()), self.data.len())
     }
     #[allow(dead_code)]
     pub const fn reset_pointer(&mut self) {
@@ -138,120 +171,129 @@ impl Message {
     }
 
     #[must_use]
-    pub fn get_data(&self) -> Vec<u8> {
-        self.data.clone()
+    pub fn get_data(&self) -> &[u8] {
+        &self.data
+    }
+
+    #[must_use]
+    pub fn slice(&self, from: usize, to: usize) -> &[u8] {
+        &self.data[from..to]
     }
 
+    /// The bytes, moved out when no clone or view shares them.
     #[must_use]
-    pub fn get_slice(&self, from: usize, to: usize) -> Vec<u8> {
-        self.data[from..to].to_vec()
+    pub fn into_data(self) -> Vec<u8> {
+        self.data.into_vec()
     }
 
     /// gets buffer with the message length prepended
     #[must_use]
     pub fn get_buffer(&self) -> Vec<u8> {
-        let mut b = vec![0u8; 4];
         let length = self.data.len() as u32;
-        b[0..4].copy_from_slice(&length.to_le_bytes());
-        let mut combined = b;
-        combined.extend(&self.data);
+        let mut combined = Vec::with_capacity(self.data.len() + 4);
+        combined.extend_from_slice(&length.to_le_bytes());
+        combined.extend_from_slice(&self.data);
         combined
     }
 
+    /// Consume the next `n` bytes. A message too short for them (truncated,
+    /// or with a bogus length field from an untrusted sender) is an error,
+    /// and consumes nothing.
+    fn take(&mut self, n: usize) -> crate::Result<&[u8]> {
+        let Some(end) = self
+            .pointer
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+        else {
+            return Err(SoulseekRs::protocol(format!(
+                "truncated: wanted {n} bytes at offset {} of {}",
+                self.pointer,
+                self.data.len()
+            )));
+        };
+        let bytes = &self.data[self.pointer..end];
+        self.pointer = end;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> crate::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// Read a length-prefixed string. Bytes that aren't valid UTF-8 (older
+    /// clients send legacy charsets) are repaired with the configured
+    /// fallback charsets rather than rejected.
+    pub fn try_read_string(&mut self) -> crate::Result<String> {
+        self.try_read_string_decoded().map(|(s, _)| s)
+    }
+
+    /// [`Self::try_read_string`], also reporting the fallback charset the
+    /// string was repaired with, if it wasn't UTF-8.
+    pub fn try_read_string_decoded(
+        &mut self,
+    ) -> crate::Result<(String, Option<Charset>)> {
+        let start = self.pointer;
+        let size = self.try_read_int32()? as usize;
+        let data = match self.take(size) {
+            Ok(data) => data,
+            Err(e) => {
+                self.pointer = start;
+                return Err(e);
+            }
+        };
+        Ok(charset::decode(data))
+    }
+
+    pub fn try_read_int8(&mut self) -> crate::Result<u8> {
+        Ok(self.take_array::<1>()?[0])
+    }
+
+    pub fn try_read_int32(&mut self) -> crate::Result<u32> {
+        self.take_array().map(u32::from_le_bytes)
+    }
+
+    pub fn try_read_int64(&mut self) -> crate::Result<u64> {
+        self.take_array().map(u64::from_le_bytes)
+    }
+
+    pub fn try_read_bool(&mut self) -> crate::Result<bool> {
+        Ok(self.try_read_int8()? == 1)
+    }
+
+    /// The next four bytes, uninterpreted.
+    pub fn try_read_raw_byte(&mut self) -> crate::Result<[u8; 4]> {
+        self.take_array()
+    }
+
+    /// Like [`Self::try_read_string`], but a malformed string reads as
+    /// empty and consumes the rest of the message.
     pub fn read_string(&mut self) -> String {
-        if self.pointer + 4 > self.data.len() {
+        self.try_read_string().unwrap_or_else(|_| {
             self.pointer = self.data.len();
-            return String::new();
-        }
-        let size = u32::from_le_bytes([
-            self.data[self.pointer],
-            self.data[self.pointer + 1],
-            self.data[self.pointer + 2],
-            self.data[self.pointer + 3],
-        ]) as usize;
-
-        self.pointer += 4;
-        // A truncated / malformed length field from an untrusted peer must not
-        // slice past the buffer end; treat it as an empty string and consume
-        // the rest of the message.
-        if self.pointer + size > self.data.len() {
-            self.pointer = self.data.len();
-            return String::new();
-        }
-        let data = &self.data[self.pointer..self.pointer + size];
-        self.pointer += size;
-
-        match String::from_utf8(data.to_vec()) {
-            Ok(s) => s,
-            Err(_) => data.iter().map(|&b| b as char).collect::<String>(),
-        }
+            String::new()
+        })
     }
 
     pub fn read_int8(&mut self) -> u8 {
-        if self.pointer >= self.data.len() {
-            return 0;
-        }
-        let val = self.data[self.pointer];
-        self.pointer += 1;
-        val
+        self.try_read_int8().unwrap_or_default()
     }
 
-    #[allow(dead_code)]
     pub fn read_int64(&mut self) -> u64 {
-        if self.pointer + 8 > self.data.len() {
-            return 0;
-        }
-        let val = u64::from_le_bytes([
-            self.data[self.pointer],
-            self.data[self.pointer + 1],
-            self.data[self.pointer + 2],
-            self.data[self.pointer + 3],
-            self.data[self.pointer + 4],
-            self.data[self.pointer + 5],
-            self.data[self.pointer + 6],
-            self.data[self.pointer + 7],
-        ]);
-        self.pointer += 8;
-        val
-    }
-
-    pub fn read_raw_byte(&mut self) -> Vec<u8> {
-        if self.pointer + 4 > self.data.len() {
-            return vec![];
-        }
+        self.try_read_int64().unwrap_or_default()
+    }
 
-        let val = vec![
-            self.data[self.pointer],
-            self.data[self.pointer + 1],
-            self.data[self.pointer + 2],
-            self.data[self.pointer + 3],
-        ];
-        self.pointer += 4;
-        val
+    pub fn read_raw_byte(&mut self) -> [u8; 4] {
+        self.try_read_raw_byte().unwrap_or_default()
     }
 
     pub fn read_int32(&mut self) -> u32 {
-        if self.pointer + 4 > self.data.len() {
-            return 0;
-        }
-
-        let val = u32::from_le_bytes([
-            self.data[self.pointer],
-            self.data[self.pointer + 1],
-            self.data[self.pointer + 2],
-            self.data[self.pointer + 3],
-        ]);
-        self.pointer += 4;
-        val
+        self.try_read_int32().unwrap_or_default()
     }
 
     pub fn read_bool(&mut self) -> bool {
-        if self.pointer >= self.data.len() {
-            return false;
-        }
-        let val = self.data[self.pointer] == 1;
-        self.pointer += 1;
-        val
+        self.try_read_bool().unwrap_or_default()
     }
 
     pub fn write_string(&mut self, val: &str) -> &mut Self {
@@ -276,8 +318,8 @@ impl Message {
         self.data.extend_from_slice(&value.to_le_bytes());
         self
     }
-    pub fn write_raw_bytes(&mut self, value: Vec<u8>) -> &mut Self {
-        self.data.extend_from_slice(&value);
+    pub fn write_raw_bytes(&mut self, value: impl AsRef<[u8]>) -> &mut Self {
+        self.data.extend_from_slice(value.as_ref());
         self
     }
 
@@ -316,6 +358,8 @@ impl Message {
                 13 => Ok("SayChatroom"),
                 14 => Ok("JoinRoom"),
                 15 => Ok("LeaveRoom"),
+                16 => Ok("UserJoinedRoom"),
+                17 => Ok("UserLeftRoom"),
                 18 => Ok("ConnectToPeer"),
                 22 => Ok("MessageUser"),
                 23 => Ok("MessageAcked"),
@@ -327,15 +371,27 @@ impl Message {
                 41 => Ok("Relogged"),
                 42 => Ok("UserSearch"),
                 64 => Ok("RoomList"),
+                66 => Ok("AdminMessage"),
                 69 => Ok("PrivilegedUsers"),
                 71 => Ok("HaveNoParent"),
                 83 => Ok("ParentMinSpeed"),
                 84 => Ok("ParentSpeedRatio"),
+                86 => Ok("ParentInactivityTimeout"),
+                87 => Ok("SearchInactivityTimeout"),
+                88 => Ok("MinParentsInCache"),
+                90 => Ok("DistribAliveInterval"),
+                91 => Ok("AddPrivilegedUser"),
                 92 => Ok("CheckPrivileges"),
                 93 => Ok("EmbeddedMessage"),
                 100 => Ok("AcceptChildren"),
                 102 => Ok("PossibleParents"),
                 104 => Ok("WishlistInterval"),
+                113 => Ok("RoomTickers"),
+                114 => Ok("RoomTickerAdd"),
+                115 => Ok("RoomTickerRemove"),
+                126 => Ok("BranchLevel"),
+                127 => Ok("BranchRoot"),
+                130 => Ok("ResetDistributed"),
                 160 => Ok("ExcludedSearchPhrases"),
                 1001 => Ok("CantConnectToPeer"),
                 _ => Err(Error(format!("Unknown server message code: {code}"))),
@@ -366,9 +422,11 @@ impl Message {
                 _ => Err(Error(format!("Unknown peer message code: {code}"))),
             },
             MessageType::Distributed => match code {
+                0 => Ok("Ping"),
                 3 => Ok("SearchRequest"),
                 4 => Ok("BranchLevel"),
                 5 => Ok("BranchRoot"),
+                7 => Ok("ChildDepth"),
                 93 => Ok("EmbeddedMessage"),
                 _ => Err(Error(format!(
                     "Unknown distributed message code: {code}"
@@ -509,3 +567,65 @@ fn test_read_string_invalid_utf82() {
         r"g:\disk4\semiramis\chill, dub, downbeat, ambient\various artists\pott headz - dope smokin´beats kbs 128 1996\05 - blue train.mp3"
     );
 }
+
+#[test]
+fn hex_dump_shows_offsets_hex_and_ascii_and_caps_output() {
+    let msg =
+        Message::new_with_data(b"Hello, peer!\x00\x01\x02\x03xyz".to_vec());
+    let dump = msg.hex_dump(usize::MAX);
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "0000  48 65 6c 6c 6f 2c 20 70  65 65 72 21 00 01 02 03  Hello, peer!....",
+            "0010  78 79 7a                                          xyz",
+        ]
+    );
+
+    let capped = msg.hex_dump(4);
+    assert!(capped.starts_with("0000  48 65 6c 6c "));
+    assert!(capped.ends_with("… 15 more bytes\n"));
+}
+
+#[test]
+fn try_reads_report_truncation_without_consuming() {
+    let mut msg = Message::new_with_data(vec![7, 0, 0, 0, 1, 2]);
+    assert_eq!(msg.try_read_int32().unwrap(), 7);
+    assert!(matches!(
+        msg.try_read_int32(),
+        Err(SoulseekRs::Protocol { .. })
+    ));
+    assert!(msg.try_read_int64().is_err());
+    assert_eq!(msg.get_pointer(), 4);
+    assert_eq!(msg.try_read_int8().unwrap(), 1);
+    assert!(msg.try_read_bool().is_ok());
+    assert!(msg.try_read_bool().is_err());
+}
+
+#[test]
+fn views_read_part_of_a_message_without_copying_it() {
+    let mut msg = Message::new_with_data(vec![1, 0, 0, 0, 2, 0, 0, 0, 3]);
+    assert_eq!(msg.read_int32(), 1);
+
+    let mut rest = msg.remaining();
+    assert_eq!(rest.get_data(), [2, 0, 0, 0, 3]);
+    assert_eq!(rest.get_data().as_ptr(), msg.get_data()[4..].as_ptr());
+    assert_eq!(rest.read_int32(), 2);
+    assert_eq!(msg.view(8, 9).read_int8(), 3);
+
+    // Writing to a view leaves the original alone.
+    rest.write_int8(4);
+    assert_eq!(rest.into_data(), [2, 0, 0, 0, 3, 4]);
+    assert_eq!(msg.get_size(), 9);
+}
+
+#[test]
+fn try_read_string_rejects_an_overlong_length_and_keeps_the_pointer() {
+    let mut msg = Message::new_with_data(vec![10, 0, 0, 0, 65, 66]);
+    assert!(msg.try_read_string().is_err());
+    assert_eq!(msg.get_pointer(), 0);
+
+    // Latin-1 from older clients still decodes.
+    let mut msg = Message::new_with_data(vec![2, 0, 0, 0, b'n', 0xE9]);
+    assert_eq!(msg.try_read_string().unwrap(), "n\u{e9}");
+}
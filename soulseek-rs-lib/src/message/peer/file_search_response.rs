@@ -1,7 +1,7 @@
-use crate::message::{Message, MessageHandler};
+use crate::message::{Message, MessageHandler, PeerMessageIn};
 use crate::peer::PeerMessage;
-use crate::types::SearchResult;
-use crate::utils::zlib::compress_stored;
+use crate::types::{File, SearchResult};
+use crate::utils::zlib::compress;
 use std::sync::mpsc::Sender;
 
 /// A borrowed view of one file to advertise in a search response, kept
@@ -20,14 +20,80 @@ pub fn build_file_search_response(
     own_username: &str,
     token: u32,
     files: &[FileEntry],
-    slots: u8,
+    free_slot: bool,
     speed: u32,
+) -> Message {
+    let mut message = Message::new();
+    message.write_int32(9);
+    let payload = payload(own_username, token, files, free_slot, speed, 0);
+    message.write_raw_bytes(compress(payload.get_data()));
+    message
+}
+
+/// Write `result` as a compressed `FileSearchResponse` payload.
+pub fn write_search_result(result: &SearchResult, message: &mut Message) {
+    let attribs = attribs(&result.files);
+    let mut payload = payload(
+        &result.username,
+        result.token,
+        &entries(&result.files, &attribs),
+        result.free_slot,
+        result.speed,
+        result.queue_length,
+    );
+    if !result.locked_files.is_empty() {
+        let attribs = self::attribs(&result.locked_files);
+        payload.write_int32(0);
+        write_files(&mut payload, &entries(&result.locked_files, &attribs));
+    }
+    message.write_raw_bytes(compress(payload.get_data()));
+}
+
+fn attribs(files: &[File]) -> Vec<Vec<(u32, u32)>> {
+    files
+        .iter()
+        .map(|file| file.attributes.to_pairs())
+        .collect()
+}
+
+fn entries<'a>(
+    files: &'a [File],
+    attribs: &'a [Vec<(u32, u32)>],
+) -> Vec<FileEntry<'a>> {
+    files
+        .iter()
+        .zip(attribs)
+        .map(|(file, attribs)| FileEntry {
+            name: &file.name,
+            size: file.size,
+            attribs,
+        })
+        .collect()
+}
+
+/// The uncompressed payload up to the locked files, which we never send
+/// for our own shares.
+fn payload(
+    username: &str,
+    token: u32,
+    files: &[FileEntry],
+    free_slot: bool,
+    speed: u32,
+    queue_length: u32,
 ) -> Message {
     let mut payload = Message::new();
+    payload.write_string(username).write_int32(token);
+    write_files(&mut payload, files);
+    payload
+        .write_int8(u8::from(free_slot))
+        .write_int32(speed)
+        .write_int32(queue_length);
     payload
-        .write_string(own_username)
-        .write_int32(token)
-        .write_int32(files.len() as u32);
+}
+
+/// A count followed by that many file entries.
+fn write_files(payload: &mut Message, files: &[FileEntry]) {
+    payload.write_int32(files.len() as u32);
     for file in files {
         payload
             .write_int8(1)
@@ -39,27 +105,24 @@ pub fn build_file_search_response(
             payload.write_int32(code).write_int32(value);
         }
     }
-    payload.write_int8(slots).write_int32(speed).write_int32(0); // free upload slots / queue length (well-formed trailer)
-
-    let compressed = compress_stored(&payload.get_data());
-    Message::new()
-        .write_int32(9)
-        .write_raw_bytes(compressed)
-        .clone()
 }
 
 pub struct FileSearchResponse;
 impl MessageHandler<PeerMessage> for FileSearchResponse {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         9
     }
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        // Skip malformed search results
-        let Ok(file_search) = SearchResult::new_from_message(message) else {
-            return;
-        };
-
-        let _ = sender.send(PeerMessage::FileSearchResult(file_search));
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::FileSearchResponse(file_search) =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::FileSearchResult(file_search));
+        }
+        Ok(())
     }
 }
 
@@ -86,7 +149,8 @@ fn test_new_from_message() {
     let mut message = Message::new_with_data(data);
     message.set_pointer(8);
 
-    let file_search = SearchResult::new_from_message(&mut message).unwrap();
+    let file_search =
+        crate::types::SearchResult::new_from_message(&mut message).unwrap();
     assert_eq!(file_search.token, 882125677);
     assert_eq!(file_search.files.len(), 2);
     let file = &file_search.files[0];
@@ -112,23 +176,70 @@ fn build_file_search_response_roundtrips_through_the_decoder() {
             attribs: &[],
         },
     ];
-    let message = build_file_search_response("e2e_sharer", 42, &files, 1, 0);
+    let message = build_file_search_response("e2e_sharer", 42, &files, true, 0);
 
     // Decode via the exact production decoder used for real peer responses:
     // the dispatcher positions the pointer at 8 (past length + code).
     let mut decoded = Message::new_with_data(message.get_buffer());
     decoded.set_pointer(8);
-    let result = SearchResult::new_from_message(&mut decoded).unwrap();
+    let result =
+        crate::types::SearchResult::new_from_message(&mut decoded).unwrap();
 
     assert_eq!(result.username, "e2e_sharer");
     assert_eq!(result.token, 42);
     assert_eq!(result.files.len(), 2);
     assert_eq!(result.files[0].name, "music\\album\\song.mp3");
     assert_eq!(result.files[0].size, 47_184_516);
-    assert_eq!(result.files[0].attribs.get(&1), Some(&320));
-    assert_eq!(result.files[0].attribs.get(&4), Some(&44100));
+    let attributes = result.files[0].attributes;
+    assert_eq!(attributes.duration_seconds, Some(320));
+    assert_eq!(attributes.sample_rate, Some(44100));
+    assert_eq!(attributes.bit_depth, Some(16));
     assert_eq!(result.files[1].name, "b.flac");
     assert_eq!(result.files[1].size, 456);
-    assert!(result.files[1].attribs.is_empty());
-    assert_eq!(result.slots, 1);
+    assert_eq!(
+        result.files[1].attributes,
+        crate::types::FileAttributes::default()
+    );
+    assert!(result.free_slot);
+}
+
+#[test]
+fn locked_files_roundtrip_in_their_own_section() {
+    let file = |name: &str, locked| File {
+        username: "peer".to_string(),
+        name: name.to_string(),
+        size: 10,
+        attributes: crate::types::FileAttributes {
+            bitrate: Some(320),
+            ..crate::types::FileAttributes::default()
+        },
+        name_charset: None,
+        locked,
+    };
+    let result = SearchResult {
+        token: 7,
+        files: vec![file("open.mp3", false)],
+        free_slot: true,
+        speed: 100,
+        queue_length: 2,
+        username: "peer".to_string(),
+        locked_files: vec![file("buddies\\a.mp3", true), file("b.mp3", true)],
+    };
+    let mut message = Message::new();
+    message.write_int32(9);
+    write_search_result(&result, &mut message);
+
+    let mut decoded = Message::new_with_data(message.get_buffer());
+    decoded.set_pointer(8);
+    let decoded = SearchResult::new_from_message(&mut decoded).unwrap();
+    assert_eq!(decoded.files.len(), 1);
+    assert!(!decoded.files[0].locked);
+    assert_eq!(decoded.queue_length, 2);
+    let locked: Vec<_> = decoded
+        .locked_files
+        .iter()
+        .map(|file| (file.name.as_str(), file.locked))
+        .collect();
+    assert_eq!(locked, [("buddies\\a.mp3", true), ("b.mp3", true)]);
+    assert_eq!(decoded.locked_files[0].attributes.bitrate, Some(320));
 }
@@ -1,25 +1,34 @@
 mod file_search_response;
 mod get_share_file_list;
 mod peer_init;
+mod place_in_queue_request;
 mod place_in_queue_response;
 mod queue_upload;
 mod shared_file_list;
 mod transfer_request;
 mod transfer_response;
+mod upload_denied;
 mod upload_failed;
+mod user_info;
 
 // Re-export handlers
 pub use file_search_response::{
     FileEntry, FileSearchResponse, build_file_search_response,
+    write_search_result,
 };
 pub use get_share_file_list::GetShareFileList;
 pub use peer_init::PeerInit;
+pub use place_in_queue_request::PlaceInQueueRequestHandler;
 pub use place_in_queue_response::PlaceInQueueResponse;
 pub use queue_upload::QueueUploadHandler;
 pub use shared_file_list::{
     SharedDirectory, SharedFileListResponseHandler, build_shared_file_list,
-    parse_shared_file_list,
+    parse_shared_file_list, write_shared_file_list,
 };
 pub use transfer_request::TransferRequest;
 pub use transfer_response::TransferResponse;
+pub use upload_denied::UploadDeniedHandler;
 pub use upload_failed::UploadFailedHandler;
+pub use user_info::{
+    UserInfoResponseHandler, parse_user_info_response, write_user_info_response,
+};
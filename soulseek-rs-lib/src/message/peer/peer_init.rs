@@ -1,28 +1,36 @@
 use std::sync::mpsc::Sender;
 
 use crate::{
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, PeerInitMessage},
     peer::PeerMessage,
     trace,
 };
 
 pub struct PeerInit;
 impl MessageHandler<PeerMessage> for PeerInit {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         1
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        message.set_pointer(4);
-        let _message_code = message.read_int8();
-        let username = message.read_string();
-        let connection_type = message.read_string();
-        let token = message.read_int32();
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        let PeerInitMessage::PeerInit {
+            username,
+            connection_type,
+            token,
+        } = PeerInitMessage::decode(message)?
+        else {
+            return Ok(());
+        };
         trace!(
             "PeerInit: username: {}, connection_type: {}, token: {}",
             username, connection_type, token
         );
 
         let _ = sender.send(PeerMessage::SetUsername(username));
+        Ok(())
     }
 }
@@ -0,0 +1,27 @@
+use crate::{
+    message::{Message, MessageHandler, PeerMessageIn},
+    peer::PeerMessage,
+};
+use std::sync::mpsc::Sender;
+
+/// A peer asking where one of its queued downloads from us stands (peer code 51).
+pub struct PlaceInQueueRequestHandler;
+
+impl MessageHandler<PeerMessage> for PlaceInQueueRequestHandler {
+    fn get_code(&self) -> u32 {
+        51
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::PlaceInQueueRequest { filename } =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::PlaceInQueueRequested(filename));
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, PeerMessageIn},
     peer::PeerMessage,
 };
 use std::sync::mpsc::Sender;
@@ -7,15 +7,21 @@ use std::sync::mpsc::Sender;
 pub struct PlaceInQueueResponse;
 
 impl MessageHandler<PeerMessage> for PlaceInQueueResponse {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         44
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        let filename = message.read_string();
-        let place = message.read_int32();
-
-        let _ =
-            sender.send(PeerMessage::PlaceInQueueResponse { filename, place });
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::PlaceInQueueResponse { filename, place } =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender
+                .send(PeerMessage::PlaceInQueueResponse { filename, place });
+        }
+        Ok(())
     }
 }
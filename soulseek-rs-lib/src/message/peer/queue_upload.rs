@@ -1,5 +1,5 @@
 use crate::{
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, PeerMessageIn},
     peer::PeerMessage,
 };
 use std::sync::mpsc::Sender;
@@ -8,12 +8,20 @@ use std::sync::mpsc::Sender;
 pub struct QueueUploadHandler;
 
 impl MessageHandler<PeerMessage> for QueueUploadHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         43
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        let filename = message.read_string();
-        let _ = sender.send(PeerMessage::IncomingQueueUpload(filename));
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::QueueUpload { filename } =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::IncomingQueueUpload(filename));
+        }
+        Ok(())
     }
 }
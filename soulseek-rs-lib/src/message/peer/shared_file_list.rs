@@ -2,9 +2,9 @@
 //! sent in reply to `GetShareFileList` (code 4). The payload is zlib-compressed
 //! and groups files by their virtual directory.
 
-use crate::message::{Message, MessageHandler};
+use crate::message::{Message, MessageHandler, PeerMessageIn};
 use crate::peer::PeerMessage;
-use crate::utils::zlib::{compress_stored, deflate};
+use crate::utils::zlib::{compress, deflate};
 use std::sync::mpsc::Sender;
 
 /// One shared directory and the files directly in it (basename + size).
@@ -17,18 +17,34 @@ pub struct SharedDirectory {
 /// Receives a peer's `SharedFileListResponse` (peer code 5) when browsing them.
 pub struct SharedFileListResponseHandler;
 impl MessageHandler<PeerMessage> for SharedFileListResponseHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         5
     }
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        let directories = parse_shared_file_list(message);
-        let _ = sender.send(PeerMessage::ShareListReceived(directories));
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::SharedFileListResponse(directories) =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::ShareListReceived(directories));
+        }
+        Ok(())
     }
 }
 
 /// Build a `SharedFileListResponse` (peer code 5) from the directory listing.
 #[must_use]
 pub fn build_shared_file_list(dirs: &[SharedDirectory]) -> Message {
+    let mut message = Message::new();
+    message.write_int32(5);
+    write_shared_file_list(dirs, &mut message);
+    message
+}
+
+/// Write the compressed `SharedFileListResponse` payload for `dirs`.
+pub fn write_shared_file_list(dirs: &[SharedDirectory], message: &mut Message) {
     let mut payload = Message::new();
     payload.write_int32(dirs.len() as u32);
     for dir in dirs {
@@ -47,28 +63,20 @@ pub fn build_shared_file_list(dirs: &[SharedDirectory]) -> Message {
     payload.write_int32(0); // unknown
     payload.write_int32(0); // number of private directories
 
-    let compressed = compress_stored(&payload.get_data());
-    Message::new()
-        .write_int32(5)
-        .write_raw_bytes(compressed)
-        .clone()
+    message.write_raw_bytes(compress(payload.get_data()));
 }
 
 /// Parse the (zlib-compressed) `SharedFileListResponse` payload. `message` must
 /// be positioned at the compressed blob (the dispatcher sets pointer 8).
-///
-/// Returns an empty listing if the payload is malformed.
-#[must_use]
-pub fn parse_shared_file_list(message: &mut Message) -> Vec<SharedDirectory> {
+pub fn parse_shared_file_list(
+    message: &mut Message,
+) -> crate::Result<Vec<SharedDirectory>> {
     let pointer = message.get_pointer();
     let size = message.get_size();
-    let compressed = message.get_slice(pointer, size);
-    let Ok(data) = deflate(&compressed) else {
-        return Vec::new();
-    };
+    let data = deflate(message.slice(pointer, size))?;
 
     let mut body = Message::new_with_data(data);
-    let dir_count = body.read_int32();
+    let dir_count = body.try_read_int32()?;
     let mut dirs = Vec::new();
     for _ in 0..dir_count {
         // Stop if a hostile count outruns the (decompressed) payload, so a
@@ -76,32 +84,32 @@ pub fn parse_shared_file_list(message: &mut Message) -> Vec<SharedDirectory> {
         if body.get_pointer() >= body.get_size() {
             break;
         }
-        let name = body.read_string();
-        let file_count = body.read_int32();
+        let name = body.try_read_string()?;
+        let file_count = body.try_read_int32()?;
         let mut files = Vec::new();
         for _ in 0..file_count {
             if body.get_pointer() >= body.get_size() {
                 break;
             }
-            body.read_int8(); // code
-            let filename = body.read_string();
-            let file_size = body.read_int64();
-            body.read_string(); // extension
-            let attr_count = body.read_int32();
+            body.try_read_int8()?; // code
+            let filename = body.try_read_string()?;
+            let file_size = body.try_read_int64()?;
+            body.try_read_string()?; // extension
+            let attr_count = body.try_read_int32()?;
             for _ in 0..attr_count {
-                // Each attribute is two int32s (8 bytes); read_int32 does not
-                // advance past the end, so bound the loop explicitly.
+                // Each attribute is two int32s (8 bytes); stop at a bogus
+                // count rather than rejecting the whole listing.
                 if body.get_pointer() + 8 > body.get_size() {
                     break;
                 }
-                body.read_int32();
-                body.read_int32();
+                body.try_read_int32()?;
+                body.try_read_int32()?;
             }
             files.push((filename, file_size));
         }
         dirs.push(SharedDirectory { name, files });
     }
-    dirs
+    Ok(dirs)
 }
 
 #[test]
@@ -114,7 +122,7 @@ fn hostile_dir_count_does_not_hang() {
     message.write_raw_bytes(vec![0u8; 8]);
     message.write_raw_bytes(compressed);
     message.set_pointer(8);
-    assert!(parse_shared_file_list(&mut message).is_empty());
+    assert!(parse_shared_file_list(&mut message).unwrap().is_empty());
 }
 
 #[test]
@@ -137,5 +145,5 @@ fn shared_file_list_roundtrips() {
     // Decode via the same offset the dispatcher would use.
     let mut decoded = Message::new_with_data(message.get_buffer());
     decoded.set_pointer(8);
-    assert_eq!(parse_shared_file_list(&mut decoded), dirs);
+    assert_eq!(parse_shared_file_list(&mut decoded).unwrap(), dirs);
 }
@@ -1,18 +1,24 @@
 use crate::{
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, PeerMessageIn},
     peer::PeerMessage,
-    types::Transfer,
 };
 use std::sync::mpsc::Sender;
 
 pub struct TransferRequest;
 impl MessageHandler<PeerMessage> for TransferRequest {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         40
     }
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        let transfer = Transfer::new_from_message(message);
-
-        let _ = sender.send(PeerMessage::TransferRequest(transfer));
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::TransferRequest(transfer) =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::TransferRequest(transfer));
+        }
+        Ok(())
     }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, PeerMessageIn},
     peer::PeerMessage,
 };
 use std::sync::mpsc::Sender;
@@ -7,19 +7,24 @@ use std::sync::mpsc::Sender;
 pub struct TransferResponse;
 
 impl MessageHandler<PeerMessage> for TransferResponse {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         41
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<PeerMessage>) {
-        let token = message.read_int32();
-        let allowed = message.read_int8();
-        let reason = (allowed == 0).then(|| message.read_string());
-
-        let _ = sender.send(PeerMessage::TransferResponse {
-            token,
-            allowed: allowed == 1,
-            reason,
-        });
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::TransferResponse { token, reply } =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::TransferResponse {
+                token,
+                allowed: reply.is_allowed(),
+                reason: reply.into_reason(),
+            });
+        }
+        Ok(())
     }
 }
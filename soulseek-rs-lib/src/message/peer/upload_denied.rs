@@ -0,0 +1,31 @@
+use crate::{
+    message::{Message, MessageHandler, PeerMessageIn},
+    peer::PeerMessage,
+    types::FailureReason,
+};
+use std::sync::mpsc::Sender;
+
+/// The uploader refused a file we queued (code 50, formerly `QueueFailed`).
+pub struct UploadDeniedHandler;
+
+impl MessageHandler<PeerMessage> for UploadDeniedHandler {
+    fn get_code(&self) -> u32 {
+        50
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::UploadDenied { filename, reason } =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::UploadFailed {
+                filename,
+                reason: FailureReason::from_peer(&reason),
+            });
+        }
+        Ok(())
+    }
+}
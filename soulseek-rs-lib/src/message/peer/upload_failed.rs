@@ -1,18 +1,28 @@
-use crate::info;
 use crate::{
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, PeerMessageIn},
     peer::PeerMessage,
-    types::UploadFailed,
+    types::FailureReason,
 };
 use std::sync::mpsc::Sender;
 
 pub struct UploadFailedHandler;
 impl MessageHandler<PeerMessage> for UploadFailedHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         46
     }
-    fn handle(&self, message: &mut Message, _sender: Sender<PeerMessage>) {
-        let upload_failed = UploadFailed::new_from_message(message);
-        info!("Upload failed for ${}", upload_failed.filename);
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::UploadFailed { filename } =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::UploadFailed {
+                filename,
+                reason: FailureReason::UploadFailed,
+            });
+        }
+        Ok(())
     }
 }
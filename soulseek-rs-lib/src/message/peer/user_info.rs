@@ -0,0 +1,84 @@
+use crate::{
+    message::{Message, MessageHandler, PeerMessageIn},
+    peer::PeerMessage,
+    types::UserInfo,
+};
+use std::sync::mpsc::Sender;
+
+/// A peer's reply to our `UserInfoRequest` (peer code 16).
+pub struct UserInfoResponseHandler;
+
+impl MessageHandler<PeerMessage> for UserInfoResponseHandler {
+    fn get_code(&self) -> u32 {
+        16
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<PeerMessage>,
+    ) -> crate::Result<()> {
+        if let PeerMessageIn::UserInfoResponse(info) =
+            PeerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(PeerMessage::UserInfoReceived(info));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `UserInfoResponse` into a [`UserInfo`] with an empty username.
+///
+/// The payload is a description, an optional picture (skipped), total
+/// uploads, queue length and whether a slot is free. The receiving actor
+/// knows who it is talking to and fills in the username.
+pub fn parse_user_info_response(
+    message: &mut Message,
+) -> crate::Result<UserInfo> {
+    let description = message.try_read_string()?;
+    if message.try_read_bool()? {
+        // Picture bytes are length-prefixed like a string; we don't keep them.
+        let _picture = message.try_read_string()?;
+    }
+    Ok(UserInfo {
+        description: Some(description),
+        total_uploads: Some(message.try_read_int32()?),
+        queue_length: Some(message.try_read_int32()?),
+        free_slots: Some(message.try_read_bool()?),
+        ..UserInfo::default()
+    })
+}
+
+/// Write `info` as a `UserInfoResponse` payload, without a picture.
+pub fn write_user_info_response(info: &UserInfo, message: &mut Message) {
+    message
+        .write_string(info.description.as_deref().unwrap_or_default())
+        .write_bool(false)
+        .write_int32(info.total_uploads.unwrap_or(0))
+        .write_int32(info.queue_length.unwrap_or(0))
+        .write_bool(info.free_slots.unwrap_or(false));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_description_and_skips_the_picture() {
+        let mut message = Message::new();
+        message
+            .write_raw_bytes(vec![0u8; 8])
+            .write_string("hello")
+            .write_bool(true)
+            .write_string("\u{1}\u{2}\u{3}")
+            .write_int32(77)
+            .write_int32(4)
+            .write_bool(true);
+        message.set_pointer(8);
+        let info = parse_user_info_response(&mut message).unwrap();
+        assert_eq!(info.description.as_deref(), Some("hello"));
+        assert_eq!(info.total_uploads, Some(77));
+        assert_eq!(info.queue_length, Some(4));
+        assert_eq!(info.free_slots, Some(true));
+    }
+}
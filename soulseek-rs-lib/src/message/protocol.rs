@@ -0,0 +1,533 @@
+//! Typed protocol messages.
+//!
+//! Each enum lists the messages of one direction and connection kind, with
+//! their fields in wire order and their codes. [`wire_enum!`] derives
+//! `encode` and `decode` from the declarations, so these are the only places
+//! that know a message's layout: `MessageFactory` builds through the `*Out`
+//! enums and the handlers read through the `*In` enums, and adding a message
+//! means adding a variant.
+//!
+//! `encode` returns the code and body without the length prefix, as
+//! `MessageFactory` always has. `decode` takes a message as received, length
+//! prefix included; handlers, whose message the dispatcher has already
+//! positioned past the code, use `decode_body` instead.
+
+use std::net::Ipv4Addr;
+
+use crate::{
+    error::Result,
+    message::{
+        Message,
+        peer::{
+            SharedDirectory, parse_shared_file_list, parse_user_info_response,
+            write_search_result, write_shared_file_list,
+            write_user_info_response,
+        },
+        server::{
+            ParentCandidate, parse_get_user_stats, parse_room_list,
+            parse_watch_user, write_get_user_stats, write_room_list,
+            write_watch_user,
+        },
+        wire::{Wire, wire_enum},
+    },
+    peer::{ConnectionType, Peer},
+    types::{
+        LoginOutcome, RoomInfo, SearchResult, Transfer, UserInfo, UserStatus,
+    },
+    utils::md5::md5,
+};
+
+/// The client version we report at login.
+const LOGIN_VERSION: u32 = 157;
+/// The minor version sent after the login hash.
+const LOGIN_MINOR_VERSION: u32 = 100;
+
+/// Read a count-prefixed list of strings. Stops early when the count outruns
+/// the payload, so a hostile count can't spin us into a long loop.
+fn read_string_list(message: &mut Message) -> Result<Vec<String>> {
+    let count = message.try_read_int32()?;
+    let mut items = Vec::new();
+    for _ in 0..count {
+        // Each entry is a length-prefixed string (>= 4 bytes).
+        if message.get_pointer() + 4 > message.get_size() {
+            break;
+        }
+        items.push(message.try_read_string()?);
+    }
+    Ok(items)
+}
+
+fn write_string_list(items: &[String], message: &mut Message) {
+    message.write_int32(items.len() as u32);
+    for item in items {
+        message.write_string(item);
+    }
+}
+
+/// A room-join flag, sent as a `u32`.
+fn read_flag(message: &mut Message) -> Result<bool> {
+    Ok(message.try_read_int32()? != 0)
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn write_flag(flag: &bool, message: &mut Message) {
+    message.write_int32(u32::from(*flag));
+}
+
+wire_enum! {
+    /// Requests we send to the server.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ServerMessageOut: u32 {
+        /// Build with [`ServerMessageOut::login`], which fills in the
+        /// version and hash.
+        Login {
+            username: String,
+            password: String,
+            version: u32,
+            hash: String,
+            minor_version: u32,
+        } = 1,
+        SetWaitPort { port: u32 } = 2,
+        GetPeerAddress { username: String } = 3,
+        /// Watch a user: the server replies with their status and stats, and
+        /// keeps us posted on status changes.
+        WatchUser { username: String } = 5,
+        GetUserStatus { username: String } = 7,
+        SayChatroom { room: String, message: String } = 13,
+        JoinRoom {
+            room: String,
+            private: bool as (read_flag, write_flag),
+        } = 14,
+        LeaveRoom { room: String } = 15,
+        /// Ask the server to broker a connection to a firewalled peer: it
+        /// tells that peer to connect back to us, quoting `token`.
+        ConnectToPeer {
+            token: u32,
+            username: String,
+            connection_type: ConnectionType,
+        } = 18,
+        MessageUser { username: String, message: String } = 22,
+        /// Acknowledge a private message so the server stops re-delivering
+        /// it.
+        MessageAcked { id: u32 } = 23,
+        FileSearch { token: u32, query: String } = 26,
+        SetStatus { status: u32 } = 28,
+        /// Keepalive with no payload.
+        ServerPing = 32,
+        SharedFoldersFiles { folders: u32, files: u32 } = 35,
+        GetUserStats { username: String } = 36,
+        RoomList = 64,
+        HaveNoParent(bool) = 71,
+        /// Ask how much of our privileges is left.
+        CheckPrivileges = 92,
+        /// How deep in the distributed network we are; 0 without a parent.
+        BranchLevel { level: u32 } = 126,
+        /// The user at the top of our branch; ourselves without a parent.
+        BranchRoot { root: String } = 127,
+        /// We could not connect to `username` as its `ConnectToPeer` with
+        /// `token` asked.
+        CantConnectToPeer { token: u32, username: String } = 1001,
+    }
+}
+
+impl ServerMessageOut {
+    /// A login request for the client version we report.
+    #[must_use]
+    pub fn login(username: &str, password: &str) -> Self {
+        Self::Login {
+            username: username.to_string(),
+            password: password.to_string(),
+            version: LOGIN_VERSION,
+            hash: md5(&format!("{username}{password}")),
+            minor_version: LOGIN_MINOR_VERSION,
+        }
+    }
+}
+
+wire_enum! {
+    /// Responses and notices the server sends us.
+    #[derive(Debug, Clone)]
+    pub enum ServerMessageIn: u32 {
+        Login(LoginOutcome) = 1,
+        GetPeerAddress {
+            username: String,
+            ip: Ipv4Addr,
+            port: u32,
+            obfuscation_type: u32,
+            obfuscated_port: u32,
+        } = 3,
+        WatchUser(UserInfo as (parse_watch_user, write_watch_user)) = 5,
+        GetUserStatus {
+            username: String,
+            status: UserStatus,
+            privileged: bool,
+        } = 7,
+        SayChatroom { room: String, username: String, message: String } = 13,
+        /// The room's member names; the per-user stats that follow are
+        /// skipped.
+        JoinRoom {
+            room: String,
+            users: Vec<String> as (read_string_list, write_string_list),
+        } = 14,
+        LeaveRoom { room: String } = 15,
+        /// The user's stats that follow are skipped.
+        UserJoinedRoom { room: String, username: String } = 16,
+        UserLeftRoom { room: String, username: String } = 17,
+        ConnectToPeer(Peer) = 18,
+        MessageUser {
+            id: u32,
+            timestamp: u32,
+            username: String,
+            message: String,
+            new_message: bool,
+        } = 22,
+        /// Another user's search, distributed to us by the server.
+        FileSearch { username: String, token: u32, query: String } = 26,
+        GetUserStats(
+            UserInfo as (parse_get_user_stats, write_get_user_stats)
+        ) = 36,
+        Relogged = 41,
+        RoomList(Vec<RoomInfo> as (parse_room_list, write_room_list)) = 64,
+        AdminMessage(String) = 66,
+        PrivilegedUsers(Vec<String>) = 69,
+        ParentMinSpeed(u32) = 83,
+        ParentSpeedRatio(u32) = 84,
+        ParentInactivityTimeout(u32) = 86,
+        SearchInactivityTimeout(u32) = 87,
+        MinParentsInCache(u32) = 88,
+        DistributedAliveInterval(u32) = 90,
+        AddPrivilegedUser(String) = 91,
+        /// Seconds of privileges we have left.
+        CheckPrivileges(u32) = 92,
+        /// Users we may join the distributed network under.
+        PossibleParents(Vec<ParentCandidate>) = 102,
+        WishlistInterval(u32) = 104,
+        /// `(username, ticker)` pairs.
+        RoomTickers { room: String, tickers: Vec<(String, String)> } = 113,
+        RoomTickerAdd { room: String, username: String, ticker: String } = 114,
+        RoomTickerRemove { room: String, username: String } = 115,
+        ResetDistributed = 130,
+        ExcludedSearchPhrases(
+            Vec<String> as (read_string_list, write_string_list)
+        ) = 160,
+        /// The peer could not connect to us for our `ConnectToPeer` with
+        /// `token`. Some servers leave out the username.
+        CantConnectToPeer { token: u32, username: Option<String> } = 1001,
+    }
+}
+
+/// A peer's answer to a `TransferRequest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferReply {
+    Allowed,
+    /// Refused, with the peer's reason (e.g. "Queued").
+    Denied(String),
+}
+
+impl TransferReply {
+    #[must_use]
+    pub const fn is_allowed(&self) -> bool {
+        matches!(self, Self::Allowed)
+    }
+
+    #[must_use]
+    pub fn into_reason(self) -> Option<String> {
+        match self {
+            Self::Allowed => None,
+            Self::Denied(reason) => Some(reason),
+        }
+    }
+}
+
+/// An allowed flag, then the reason only when refused.
+impl Wire for TransferReply {
+    fn write_to(&self, message: &mut Message) {
+        match self {
+            Self::Allowed => {
+                message.write_bool(true);
+            }
+            Self::Denied(reason) => {
+                message.write_bool(false).write_string(reason);
+            }
+        }
+    }
+
+    fn read_from(message: &mut Message) -> Result<Self> {
+        if message.try_read_int8()? == 1 {
+            Ok(Self::Allowed)
+        } else {
+            Ok(Self::Denied(message.try_read_string()?))
+        }
+    }
+}
+
+wire_enum! {
+    /// Messages we send to a peer over a `P` connection.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PeerMessageOut: u32 {
+        GetShareFileList = 4,
+        UserInfoRequest = 15,
+        /// Direction 0 asks the peer to upload `filename` to us; direction 1
+        /// offers our upload of it, and carries its size.
+        TransferRequest(Transfer) = 40,
+        TransferResponse { token: u32, reply: TransferReply } = 41,
+        QueueUpload { filename: String } = 43,
+        /// Where the peer's queued download of `filename` stands in our
+        /// upload queue.
+        PlaceInQueueResponse { filename: String, place: u32 } = 44,
+        /// Refuse a `QueueUpload` of `filename`.
+        UploadDenied { filename: String, reason: String } = 50,
+        /// Ask where our queued download of `filename` stands in the peer's
+        /// upload queue; answered with a `PlaceInQueueResponse`.
+        PlaceInQueueRequest { filename: String } = 51,
+    }
+}
+
+wire_enum! {
+    /// Messages a peer sends us over a `P` connection.
+    #[derive(Debug, Clone)]
+    pub enum PeerMessageIn: u32 {
+        GetShareFileList = 4,
+        SharedFileListResponse(
+            Vec<SharedDirectory>
+                as (parse_shared_file_list, write_shared_file_list)
+        ) = 5,
+        FileSearchResponse(
+            SearchResult as (SearchResult::new_from_message, write_search_result)
+        ) = 9,
+        UserInfoRequest = 15,
+        /// The peer's description, queue and slot info. The username is left
+        /// empty: the receiving actor knows who it is talking to.
+        UserInfoResponse(
+            UserInfo as (parse_user_info_response, write_user_info_response)
+        ) = 16,
+        TransferRequest(Transfer) = 40,
+        TransferResponse { token: u32, reply: TransferReply } = 41,
+        QueueUpload { filename: String } = 43,
+        PlaceInQueueResponse { filename: String, place: u32 } = 44,
+        UploadFailed { filename: String } = 46,
+        /// The uploader refused a queued file, and why.
+        UploadDenied { filename: String, reason: String } = 50,
+        PlaceInQueueRequest { filename: String } = 51,
+    }
+}
+
+wire_enum! {
+    /// Messages our distributed parent sends us over a `D` connection, which
+    /// have one-byte codes.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum DistributedMessageIn: u8 {
+        Ping = 0,
+        /// Another user's search, relayed down the branch.
+        Search {
+            unknown: u32,
+            username: String,
+            token: u32,
+            query: String,
+        } = 3,
+        /// The parent's depth in the network.
+        BranchLevel(u32) = 4,
+        /// The user at the top of the parent's branch.
+        BranchRoot(String) = 5,
+        ChildDepth(u32) = 7,
+    }
+}
+
+wire_enum! {
+    /// The first message on a new peer connection, which has a one-byte
+    /// code.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum PeerInitMessage: u8 {
+        /// Answers a `ConnectToPeer` we were sent, quoting its token.
+        PierceFirewall { token: u32 } = 0,
+        /// An unknown connection type from an untrusted peer is a decode
+        /// error, not a panic.
+        PeerInit {
+            username: String,
+            connection_type: ConnectionType,
+            token: u32,
+        } = 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frame an encoded message the way it arrives on the wire.
+    fn received(message: &Message) -> Message {
+        Message::new_with_data(message.get_buffer())
+    }
+
+    #[test]
+    fn server_requests_round_trip() {
+        let requests = [
+            ServerMessageOut::login("alice", "secret"),
+            ServerMessageOut::SetWaitPort { port: 2234 },
+            ServerMessageOut::GetPeerAddress {
+                username: "bob".into(),
+            },
+            ServerMessageOut::JoinRoom {
+                room: "jazz".into(),
+                private: true,
+            },
+            ServerMessageOut::ConnectToPeer {
+                token: 9,
+                username: "bob".into(),
+                connection_type: ConnectionType::F,
+            },
+            ServerMessageOut::FileSearch {
+                token: 12,
+                query: "trance wax".into(),
+            },
+            ServerMessageOut::SharedFoldersFiles {
+                folders: 3,
+                files: 40,
+            },
+            ServerMessageOut::ServerPing,
+            ServerMessageOut::HaveNoParent(true),
+            ServerMessageOut::BranchLevel { level: 2 },
+            ServerMessageOut::CantConnectToPeer {
+                token: 42,
+                username: "bob".into(),
+            },
+            ServerMessageOut::BranchRoot {
+                root: "carol".into(),
+            },
+        ];
+        for request in requests {
+            let decoded =
+                ServerMessageOut::decode(&mut received(&request.encode()))
+                    .unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+
+    #[test]
+    fn peer_requests_round_trip() {
+        let requests = [
+            PeerMessageOut::GetShareFileList,
+            PeerMessageOut::TransferRequest(Transfer {
+                direction: 1,
+                token: 555,
+                filename: "song.mp3".into(),
+                size: 4096,
+            }),
+            PeerMessageOut::TransferResponse {
+                token: 7,
+                reply: TransferReply::Denied("Queued".into()),
+            },
+            PeerMessageOut::PlaceInQueueResponse {
+                filename: "a.mp3".into(),
+                place: 3,
+            },
+            PeerMessageOut::UploadDenied {
+                filename: "a.mp3".into(),
+                reason: "Too many files".into(),
+            },
+        ];
+        for request in requests {
+            let decoded =
+                PeerMessageOut::decode(&mut received(&request.encode()))
+                    .unwrap();
+            assert_eq!(decoded, request);
+        }
+    }
+
+    #[test]
+    fn peer_init_round_trips() {
+        let init = PeerInitMessage::PeerInit {
+            username: "bob".into(),
+            connection_type: ConnectionType::P,
+            token: 7,
+        };
+        let decoded =
+            PeerInitMessage::decode(&mut received(&init.encode())).unwrap();
+        assert_eq!(decoded, init);
+    }
+
+    #[test]
+    fn distributed_messages_have_one_byte_codes() {
+        let mut message = Message::new();
+        message
+            .write_int8(3)
+            .write_int32(49)
+            .write_string("alice")
+            .write_int32(1234)
+            .write_string("aphex twin");
+        assert_eq!(
+            DistributedMessageIn::decode(&mut received(&message)).unwrap(),
+            DistributedMessageIn::Search {
+                unknown: 49,
+                username: "alice".into(),
+                token: 1234,
+                query: "aphex twin".into(),
+            }
+        );
+        let level = DistributedMessageIn::BranchLevel(2);
+        assert_eq!(level.encode().get_data(), [4, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_a_received_server_message() {
+        let mut message = Message::new();
+        message
+            .write_int32(114)
+            .write_string("jazz")
+            .write_string("alice")
+            .write_string("on air");
+        match ServerMessageIn::decode(&mut received(&message)).unwrap() {
+            ServerMessageIn::RoomTickerAdd {
+                room,
+                username,
+                ticker,
+            } => {
+                assert_eq!(room, "jazz");
+                assert_eq!(username, "alice");
+                assert_eq!(ticker, "on air");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn server_notices_encode_as_they_decode() {
+        let notice = ServerMessageIn::GetPeerAddress {
+            username: "bob".into(),
+            ip: Ipv4Addr::new(10, 0, 0, 7),
+            port: 2234,
+            obfuscation_type: 0,
+            obfuscated_port: 0,
+        };
+        let encoded = notice.encode();
+        assert_eq!(&encoded.get_data()[11..15], [7, 0, 0, 10]);
+        match ServerMessageIn::decode(&mut received(&encoded)).unwrap() {
+            ServerMessageIn::GetPeerAddress { ip, port, .. } => {
+                assert_eq!(ip, Ipv4Addr::new(10, 0, 0, 7));
+                assert_eq!(port, 2234);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_and_truncated_messages_are_errors() {
+        let unknown = Message::new().write_int32(9999).clone();
+        assert!(ServerMessageIn::decode(&mut received(&unknown)).is_err());
+        assert!(PeerMessageIn::decode(&mut received(&unknown)).is_err());
+        assert!(ServerMessageIn::decode(&mut Message::new()).is_err());
+
+        let truncated = Message::new().write_int32(15).clone();
+        assert!(matches!(
+            ServerMessageIn::decode(&mut received(&truncated)),
+            Err(crate::SoulseekRs::Protocol { code: Some(15), .. })
+        ));
+        assert!(matches!(
+            PeerMessageIn::decode(&mut received(&unknown)),
+            Err(crate::SoulseekRs::Protocol {
+                code: Some(9999),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::info;
+use std::sync::mpsc::Sender;
+
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler, ServerMessageIn},
+};
+
+/// A broadcast from the server administrators (server code 66).
+pub struct AdminMessageHandler;
+
+impl MessageHandler<ServerMessage> for AdminMessageHandler {
+    fn get_code(&self) -> u32 {
+        66
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::AdminMessage(text) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            info!("[server] Admin message: {}", text);
+            let _ = sender.send(ServerMessage::AdminMessage(text));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_admin_message() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_string("Maintenance at 10:00 UTC");
+        message.set_pointer(8);
+
+        AdminMessageHandler.handle(&mut message, tx).unwrap();
+        match rx.try_recv() {
+            Ok(ServerMessage::AdminMessage(text)) => {
+                assert_eq!(text, "Maintenance at 10:00 UTC");
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::actor::server_actor::ServerMessage;
+use crate::message::{Message, MessageHandler, ServerMessageIn};
+use std::sync::mpsc::Sender;
+
+/// A peer we asked the server to send our way could not connect to us
+/// (server code 1001), quoting the token of our `ConnectToPeer`.
+pub struct CantConnectToPeerHandler;
+
+impl MessageHandler<ServerMessage> for CantConnectToPeerHandler {
+    fn get_code(&self) -> u32 {
+        1001
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::CantConnectToPeer { token, .. } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::CantConnectToPeer(token));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_the_token_with_or_without_a_username() {
+        for username in [None, Some("bob")] {
+            let (tx, rx) = std::sync::mpsc::channel();
+            let mut message = Message::new();
+            message.write_int32(0).write_int32(1001).write_int32(42);
+            if let Some(username) = username {
+                message.write_string(username);
+            }
+            message.set_pointer(8);
+
+            CantConnectToPeerHandler.handle(&mut message, tx).unwrap();
+            assert!(matches!(
+                rx.try_recv(),
+                Ok(ServerMessage::CantConnectToPeer(42))
+            ));
+        }
+    }
+}
@@ -1,17 +1,22 @@
 use crate::actor::server_actor::ServerMessage;
-use crate::message::{Message, MessageHandler};
-use crate::peer::Peer;
+use crate::message::{Message, MessageHandler, ServerMessageIn};
 use std::sync::mpsc::Sender;
 pub struct ConnectToPeerHandler;
 
 impl MessageHandler<ServerMessage> for ConnectToPeerHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         18
     }
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let Some(peer) = Peer::new_from_message(message) else {
-            return;
-        };
-        let _ = sender.send(ServerMessage::ConnectToPeer(peer));
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::ConnectToPeer(peer) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::ConnectToPeer(peer));
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,118 @@
+//! Settings for the distributed search network, pushed by the server after
+//! login. We only join the network as a child, so most are logged.
+
+use crate::debug;
+use std::sync::mpsc::Sender;
+
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler, ServerMessageIn},
+};
+
+/// Seconds without a parent before we should look for a new one (code 86).
+pub struct ParentInactivityTimeoutHandler;
+
+impl MessageHandler<ServerMessage> for ParentInactivityTimeoutHandler {
+    fn get_code(&self) -> u32 {
+        86
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        _sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::ParentInactivityTimeout(seconds) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Parent inactivity timeout: {} seconds", seconds);
+        }
+        Ok(())
+    }
+}
+
+/// Seconds without a distributed search before the parent is dropped
+/// (code 87).
+pub struct SearchInactivityTimeoutHandler;
+
+impl MessageHandler<ServerMessage> for SearchInactivityTimeoutHandler {
+    fn get_code(&self) -> u32 {
+        87
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        _sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::SearchInactivityTimeout(seconds) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Search inactivity timeout: {} seconds", seconds);
+        }
+        Ok(())
+    }
+}
+
+/// How many parent candidates to keep around (code 88).
+pub struct MinParentsInCacheHandler;
+
+impl MessageHandler<ServerMessage> for MinParentsInCacheHandler {
+    fn get_code(&self) -> u32 {
+        88
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        _sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::MinParentsInCache(number) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Min parents in cache: {}", number);
+        }
+        Ok(())
+    }
+}
+
+/// Interval for the distributed network keepalive (code 90).
+pub struct DistributedAliveIntervalHandler;
+
+impl MessageHandler<ServerMessage> for DistributedAliveIntervalHandler {
+    fn get_code(&self) -> u32 {
+        90
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        _sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::DistributedAliveInterval(seconds) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Distributed alive interval: {} seconds", seconds);
+        }
+        Ok(())
+    }
+}
+
+/// Drop our distributed parent and children (code 130).
+pub struct ResetDistributedHandler;
+
+impl MessageHandler<ServerMessage> for ResetDistributedHandler {
+    fn get_code(&self) -> u32 {
+        130
+    }
+
+    fn handle(
+        &self,
+        _message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        debug!("Server reset our distributed connections");
+        let _ = sender.send(ServerMessage::ResetDistributed);
+        Ok(())
+    }
+}
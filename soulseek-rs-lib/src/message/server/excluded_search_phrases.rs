@@ -1,30 +1,29 @@
-use crate::debug;
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 use std::sync::mpsc::Sender;
 
+/// Phrases the server forbids searching for or answering with (server code
+/// 160). Sent after login.
 pub struct ExcludedSearchPhrasesHandler;
 
 impl MessageHandler<ServerMessage> for ExcludedSearchPhrasesHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         160
     }
 
-    fn handle(&self, message: &mut Message, _sender: Sender<ServerMessage>) {
-        let item_count = message.read_int32();
-
-        let mut exluded_phrases: Vec<String> = Vec::new();
-        for _ in 0..item_count {
-            // Guard against a hostile item_count outrunning the payload.
-            if message.get_pointer() + 4 > message.get_size() {
-                break;
-            }
-            let phrase = message.read_string();
-            exluded_phrases.push(phrase);
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::ExcludedSearchPhrases(phrases) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::ExcludedSearchPhrases(phrases));
         }
-        debug!("Excluded search phrases: {:?}", exluded_phrases);
+        Ok(())
     }
 }
 
@@ -41,6 +40,28 @@ mod tests {
         message.write_raw_bytes(vec![0u8; 8]);
         message.write_int32(u32::MAX);
         message.set_pointer(8);
-        ExcludedSearchPhrasesHandler.handle(&mut message, tx);
+        ExcludedSearchPhrasesHandler
+            .handle(&mut message, tx)
+            .unwrap();
+    }
+
+    #[test]
+    fn forwards_the_phrases() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message
+            .write_int32(2)
+            .write_string("bad band")
+            .write_string("leak");
+        message.set_pointer(8);
+        ExcludedSearchPhrasesHandler
+            .handle(&mut message, tx)
+            .unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerMessage::ExcludedSearchPhrases(phrases))
+                if phrases == ["bad band", "leak"]
+        ));
     }
 }
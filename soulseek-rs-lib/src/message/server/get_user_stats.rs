@@ -0,0 +1,112 @@
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{
+        Message, MessageHandler, ServerMessageIn,
+        wire::{Wire, wire_struct},
+    },
+    types::UserInfo,
+};
+use std::sync::mpsc::Sender;
+
+/// A user's upload and share statistics (server code 36).
+pub struct GetUserStatsHandler;
+
+impl MessageHandler<ServerMessage> for GetUserStatsHandler {
+    fn get_code(&self) -> u32 {
+        36
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::GetUserStats(info) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ =
+                sender.send(ServerMessage::UserInfoReceived(Box::new(info)));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `GetUserStats` reply: the username, then the stats block.
+pub fn parse_get_user_stats(message: &mut Message) -> crate::Result<UserInfo> {
+    let mut info = UserInfo::new(message.try_read_string()?);
+    read_user_stats(message, &mut info)?;
+    Ok(info)
+}
+
+pub fn write_get_user_stats(info: &UserInfo, message: &mut Message) {
+    message.write_string(&info.username);
+    write_user_stats(message, info);
+}
+
+wire_struct! {
+    /// The stats block shared by `GetUserStats` and `WatchUser`.
+    struct UserStats {
+        avg_speed: u32,
+        upload_count: u32,
+        unknown: u32,
+        files: u32,
+        folders: u32,
+    }
+}
+
+/// Read the stats block into `info`.
+pub fn read_user_stats(
+    message: &mut Message,
+    info: &mut UserInfo,
+) -> crate::Result<()> {
+    let stats = UserStats::read_from(message)?;
+    info.avg_speed = Some(stats.avg_speed);
+    info.upload_count = Some(stats.upload_count);
+    info.shared_files = Some(stats.files);
+    info.shared_folders = Some(stats.folders);
+    Ok(())
+}
+
+/// Write the stats block, with zero for unknown values.
+pub fn write_user_stats(message: &mut Message, info: &UserInfo) {
+    UserStats {
+        avg_speed: info.avg_speed.unwrap_or(0),
+        upload_count: info.upload_count.unwrap_or(0),
+        unknown: 0,
+        files: info.shared_files.unwrap_or(0),
+        folders: info.shared_folders.unwrap_or(0),
+    }
+    .write_to(message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handler_forwards_stats() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message
+            .write_raw_bytes(vec![0u8; 8])
+            .write_string("alice")
+            .write_int32(2048)
+            .write_int32(12)
+            .write_int32(0)
+            .write_int32(300)
+            .write_int32(20);
+        message.set_pointer(8);
+        GetUserStatsHandler.handle(&mut message, tx).unwrap();
+        match rx.try_recv() {
+            Ok(ServerMessage::UserInfoReceived(info)) => {
+                assert_eq!(info.username, "alice");
+                assert_eq!(info.avg_speed, Some(2048));
+                assert_eq!(info.upload_count, Some(12));
+                assert_eq!(info.shared_files, Some(300));
+                assert_eq!(info.shared_folders, Some(20));
+                assert_eq!(info.status, None);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler, ServerMessageIn},
+    types::UserInfo,
+};
+use std::sync::mpsc::Sender;
+
+/// A user's presence and privilege flag (server code 7).
+pub struct GetUserStatusHandler;
+
+impl MessageHandler<ServerMessage> for GetUserStatusHandler {
+    fn get_code(&self) -> u32 {
+        7
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::GetUserStatus {
+            username,
+            status,
+            privileged,
+        } = ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let mut info = UserInfo::new(username);
+            info.status = Some(status);
+            info.privileged = Some(privileged);
+            let _ =
+                sender.send(ServerMessage::UserInfoReceived(Box::new(info)));
+        }
+        Ok(())
+    }
+}
@@ -1,32 +1,27 @@
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 use std::sync::mpsc::Sender;
 
 pub struct JoinRoomHandler;
 
 impl MessageHandler<ServerMessage> for JoinRoomHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         14
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        // JoinRoom (code 14): room name, then a vector of member usernames.
-        // Per-user stat vectors follow but are not needed here, so we stop
-        // after reading the names.
-        let room = message.read_string();
-        let user_count = message.read_int32();
-        let mut users = Vec::new();
-        for _ in 0..user_count {
-            // Guard against a hostile user_count outrunning the payload; each
-            // username is a length-prefixed string (>= 4 bytes).
-            if message.get_pointer() + 4 > message.get_size() {
-                break;
-            }
-            users.push(message.read_string());
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::JoinRoom { room, users } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomJoined { room, users });
         }
-        let _ = sender.send(ServerMessage::RoomJoined { room, users });
+        Ok(())
     }
 }
 
@@ -45,7 +40,7 @@ mod tests {
         message.write_int32(u32::MAX);
         message.set_pointer(8);
 
-        JoinRoomHandler.handle(&mut message, tx);
+        JoinRoomHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomJoined { users, .. }) => {
                 assert!(users.is_empty());
@@ -65,7 +60,7 @@ mod tests {
         message.write_string("bob");
         message.set_pointer(8);
 
-        JoinRoomHandler.handle(&mut message, tx);
+        JoinRoomHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomJoined { room, users }) => {
                 assert_eq!(room, "nicotine");
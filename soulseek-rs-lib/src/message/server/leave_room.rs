@@ -1,19 +1,27 @@
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 use std::sync::mpsc::Sender;
 
 pub struct LeaveRoomHandler;
 
 impl MessageHandler<ServerMessage> for LeaveRoomHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         15
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let room = message.read_string();
-        let _ = sender.send(ServerMessage::RoomLeft { room });
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::LeaveRoom { room } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomLeft { room });
+        }
+        Ok(())
     }
 }
 
@@ -29,7 +37,7 @@ mod tests {
         message.write_string("jazz");
         message.set_pointer(8);
 
-        LeaveRoomHandler.handle(&mut message, tx);
+        LeaveRoomHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomLeft { room }) => assert_eq!(room, "jazz"),
             other => panic!("unexpected: {other:?}"),
@@ -1,29 +1,124 @@
 use crate::{
     actor::server_actor::ServerMessage, debug, info, message::Message,
+    types::LoginOutcome, warn,
 };
 use std::sync::mpsc::Sender;
 
-use crate::message::MessageHandler;
+use crate::message::{MessageHandler, ServerMessageIn};
 
 pub struct LoginHandler;
 
 impl MessageHandler<ServerMessage> for LoginHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         1
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let response = message.read_int8();
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::Login(outcome) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            match &outcome {
+                LoginOutcome::Accepted { greeting, ip, .. } => {
+                    info!("Login successful");
+                    debug!("Server greeting: {:?}, our ip: {:?}", greeting, ip);
+                }
+                LoginOutcome::Rejected(reason) => {
+                    warn!("Login rejected: {}", reason);
+                }
+            }
+            let _ = sender.send(ServerMessage::LoginStatus(outcome));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LoginRejection;
+    use std::net::Ipv4Addr;
 
-        if response != 1 {
-            let _ = sender.send(ServerMessage::LoginStatus(false));
-            return;
+    fn handle(build: impl FnOnce(&mut Message)) -> LoginOutcome {
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        build(&mut message);
+        message.set_pointer(8);
+        let (tx, rx) = std::sync::mpsc::channel();
+        LoginHandler.handle(&mut message, tx).unwrap();
+        match rx.try_recv() {
+            Ok(ServerMessage::LoginStatus(outcome)) => outcome,
+            other => panic!("unexpected: {other:?}"),
         }
+    }
+
+    #[test]
+    fn reads_the_address_and_digest_of_an_accepted_login() {
+        let outcome = handle(|m| {
+            m.write_bool(true)
+                .write_string("welcome")
+                .write_int32(u32::from(Ipv4Addr::new(203, 0, 113, 9)))
+                .write_string("5f4dcc3b5aa765d61d8327deb882cf99")
+                .write_bool(false);
+        });
+        assert_eq!(
+            outcome,
+            LoginOutcome::Accepted {
+                greeting: "welcome".into(),
+                ip: Some(Ipv4Addr::new(203, 0, 113, 9)),
+                password_md5: Some("5f4dcc3b5aa765d61d8327deb882cf99".into()),
+            }
+        );
+
+        // Older servers stop after the greeting.
+        let outcome = handle(|m| {
+            m.write_bool(true).write_string("hi");
+        });
+        assert!(matches!(
+            outcome,
+            LoginOutcome::Accepted {
+                ip: None,
+                password_md5: None,
+                ..
+            }
+        ));
+    }
 
-        info!("Login successful");
-        let greeting = message.read_string();
-        debug!("Server greeting: {:?}", greeting);
+    #[test]
+    fn names_the_reason_for_a_rejection() {
+        let rejected = |reason: &str| {
+            handle(|m| {
+                m.write_bool(false).write_string(reason);
+            })
+        };
+        assert_eq!(
+            rejected("INVALIDPASS"),
+            LoginOutcome::Rejected(LoginRejection::InvalidPassword)
+        );
+        assert_eq!(
+            rejected("INVALIDVERSION"),
+            LoginOutcome::Rejected(LoginRejection::InvalidVersion)
+        );
+        assert_eq!(
+            rejected("You are banned"),
+            LoginOutcome::Rejected(LoginRejection::Other(
+                "You are banned".into()
+            ))
+        );
 
-        let _ = sender.send(ServerMessage::LoginStatus(true));
+        let outcome = handle(|m| {
+            m.write_bool(false)
+                .write_string("INVALIDUSERNAME")
+                .write_string("too long");
+        });
+        assert_eq!(
+            outcome,
+            LoginOutcome::Rejected(LoginRejection::InvalidUsername(Some(
+                "too long".into()
+            )))
+        );
     }
 }
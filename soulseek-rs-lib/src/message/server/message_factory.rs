@@ -1,47 +1,26 @@
 use crate::{
-    message::Message, peer::ConnectionType, types::Transfer, utils::md5::md5,
+    message::{
+        Message, PeerInitMessage, PeerMessageOut, ServerMessageOut,
+        TransferReply,
+    },
+    peer::ConnectionType,
+    types::Transfer,
 };
 
+/// Shorthands for encoding the messages we send. The wire layout of each
+/// lives in [`crate::message::protocol`].
 pub struct MessageFactory;
 impl MessageFactory {
     #[must_use]
     pub fn build_get_peer_address(username: &str) -> Message {
-        let mut message = Message::new();
-
-        message.write_int32(3);
-        message.write_string(username);
-        message
+        ServerMessageOut::GetPeerAddress {
+            username: username.to_string(),
+        }
+        .encode()
     }
     #[must_use]
     pub fn build_login_message(username: &str, password: &str) -> Message {
-        // Message::new_with_data(
-        //     [
-        //         1, 0, 0, 0, 20, 0, 0, 0, 105, 110, 115, 97, 110, 101, 95, 105, 110, 95, 116, 104, 101,
-        //         95, 98, 114, 97, 105, 110, 50, 8, 0, 0, 0, 49, 51, 51, 55, 53, 49, 51, 55, 160, 0, 0,
-        //         0, 32, 0, 0, 0, 50, 101, 100, 102, 53, 49, 100, 48, 51, 55, 57, 52, 51, 55, 56, 102,
-        //         56, 98, 98, 54, 51, 49, 48, 100, 52, 54, 48, 99, 50, 50, 98, 49, 17, 0, 0,
-        //         0,
-        //         //0, // 84, 0, 0, 0, 1, 0, 0, 0, 20, 0, 0, 0, 105, 110, 115, 97, 110, 101, 95, 105, 110, 95,
-        //         // 116, 104, 101, 95, 98, 114, 97, 105, 110, 50, 8, 0, 0, 0, 49, 51, 51, 55, 53, 49, 51,
-        //         // 55, 160, 0, 0, 0, 32, 0, 0, 0, 50, 101, 100, 102, 53, 49, 100, 48, 51, 55, 57, 52, 51,
-        //         // 55, 56, 102, 56, 98, 98, 54, 51, 49, 48, 100, 52, 54, 48, 99, 50, 50, 98, 49, 17, 0, 0,
-        //         // 0,
-        //     ]
-        //     .to_vec(),
-        // )
-        // .clone()fac
-        let hash = md5([username, password].join("").as_str());
-
-        let mut message = Message::new();
-
-        message
-            .write_int32(1)
-            .write_string(username)
-            .write_string(password)
-            .write_int32(157) // version
-            .write_string(&hash)
-            .write_int32(100)
-            .clone()
+        ServerMessageOut::login(username, password).encode()
     }
 
     #[must_use]
@@ -49,35 +28,35 @@ impl MessageFactory {
         folder_count: u32,
         file_count: u32,
     ) -> Message {
-        Message::new()
-            .write_int32(35)
-            .write_int32(folder_count)
-            .write_int32(file_count)
-            .clone()
+        ServerMessageOut::SharedFoldersFiles {
+            folders: folder_count,
+            files: file_count,
+        }
+        .encode()
     }
     #[must_use]
     pub fn build_file_search_message(token: u32, query: &str) -> Message {
-        Message::new()
-            .write_int32(26)
-            .write_int32(token)
-            .write_string(query)
-            .clone()
+        ServerMessageOut::FileSearch {
+            token,
+            query: query.to_string(),
+        }
+        .encode()
     }
     /// Build a private message (server code 22) to send to another user.
     #[must_use]
     pub fn build_message_user(username: &str, message: &str) -> Message {
-        Message::new()
-            .write_int32(22)
-            .write_string(username)
-            .write_string(message)
-            .clone()
+        ServerMessageOut::MessageUser {
+            username: username.to_string(),
+            message: message.to_string(),
+        }
+        .encode()
     }
 
     /// Acknowledge a received private message (server code 23) so the server
     /// stops re-delivering it.
     #[must_use]
     pub fn build_message_acked(id: u32) -> Message {
-        Message::new().write_int32(23).write_int32(id).clone()
+        ServerMessageOut::MessageAcked { id }.encode()
     }
 
     /// Ask the server (code 18) to broker a connection to a firewalled peer:
@@ -88,84 +67,189 @@ impl MessageFactory {
         username: &str,
         connection_type: ConnectionType,
     ) -> Message {
-        Message::new()
-            .write_int32(18)
-            .write_int32(token)
-            .write_string(username)
-            .write_string(&connection_type.to_string())
-            .clone()
+        ServerMessageOut::ConnectToPeer {
+            token,
+            username: username.to_string(),
+            connection_type,
+        }
+        .encode()
+    }
+
+    /// Tell the server (code 1001) we could not connect to `username` for
+    /// its `ConnectToPeer` with `token`, so the peer stops waiting for us.
+    #[must_use]
+    pub fn build_cant_connect_to_peer(token: u32, username: &str) -> Message {
+        ServerMessageOut::CantConnectToPeer {
+            token,
+            username: username.to_string(),
+        }
+        .encode()
     }
 
     #[must_use]
     pub fn build_set_status_message(status_code: u32) -> Message {
-        Message::new()
-            .write_int32(28)
-            .write_int32(status_code)
-            .clone()
+        ServerMessageOut::SetStatus {
+            status: status_code,
+        }
+        .encode()
+    }
+    /// Keepalive with no payload (server code 32).
+    #[must_use]
+    pub fn build_server_ping() -> Message {
+        ServerMessageOut::ServerPing.encode()
     }
     #[must_use]
     pub fn build_no_parent_message() -> Message {
-        Message::new().write_int32(71).write_bool(true).clone()
+        ServerMessageOut::HaveNoParent(true).encode()
+    }
+    /// Tell the server (code 71) we found a distributed parent.
+    #[must_use]
+    pub fn build_have_parent_message() -> Message {
+        ServerMessageOut::HaveNoParent(false).encode()
+    }
+    #[must_use]
+    pub fn build_branch_level_message(level: u32) -> Message {
+        ServerMessageOut::BranchLevel { level }.encode()
+    }
+    #[must_use]
+    pub fn build_branch_root_message(root: &str) -> Message {
+        ServerMessageOut::BranchRoot {
+            root: root.to_string(),
+        }
+        .encode()
     }
     #[must_use]
     pub fn build_set_wait_port_message(port: u16) -> Message {
-        Message::new()
-            .write_int32(2)
-            .write_int32(port.into())
-            .clone()
+        ServerMessageOut::SetWaitPort { port: port.into() }.encode()
     }
     #[must_use]
     pub fn build_watch_user(token: u32) -> Message {
         Message::new()
-            .write_raw_bytes([5, 0, 0, 0, 0].to_vec())
+            .write_raw_bytes([5, 0, 0, 0, 0])
             .write_int32(token)
             .clone()
     }
 
+    /// Watch `username` (server code 5): the server replies with their
+    /// status and stats, and keeps us posted on status changes.
+    #[must_use]
+    pub fn build_watch_username(username: &str) -> Message {
+        ServerMessageOut::WatchUser {
+            username: username.to_string(),
+        }
+        .encode()
+    }
+
+    /// Ask for a user's status and privilege flag (server code 7).
+    #[must_use]
+    pub fn build_get_user_status(username: &str) -> Message {
+        ServerMessageOut::GetUserStatus {
+            username: username.to_string(),
+        }
+        .encode()
+    }
+
+    /// Ask for a user's upload and share statistics (server code 36).
+    #[must_use]
+    pub fn build_get_user_stats(username: &str) -> Message {
+        ServerMessageOut::GetUserStats {
+            username: username.to_string(),
+        }
+        .encode()
+    }
+
+    /// Ask the server (code 92) how long our privileges last.
+    #[must_use]
+    pub fn build_check_privileges() -> Message {
+        ServerMessageOut::CheckPrivileges.encode()
+    }
+
     /// Ask the server (code 64) for the list of public chat rooms.
     #[must_use]
     pub fn build_room_list_request() -> Message {
-        Message::new().write_int32(64).clone()
+        ServerMessageOut::RoomList.encode()
     }
 
     /// Join a chat room (server code 14). `private` requests a private room.
     #[must_use]
     pub fn build_join_room(room: &str, private: bool) -> Message {
-        Message::new()
-            .write_int32(14)
-            .write_string(room)
-            .write_int32(u32::from(private))
-            .clone()
+        ServerMessageOut::JoinRoom {
+            room: room.to_string(),
+            private,
+        }
+        .encode()
     }
 
     /// Leave a chat room (server code 15).
     #[must_use]
     pub fn build_leave_room(room: &str) -> Message {
-        Message::new().write_int32(15).write_string(room).clone()
+        ServerMessageOut::LeaveRoom {
+            room: room.to_string(),
+        }
+        .encode()
     }
 
     /// Say `message` in chat room `room` (server code 13).
     #[must_use]
     pub fn build_say_chatroom(room: &str, message: &str) -> Message {
-        Message::new()
-            .write_int32(13)
-            .write_string(room)
-            .write_string(message)
-            .clone()
+        ServerMessageOut::SayChatroom {
+            room: room.to_string(),
+            message: message.to_string(),
+        }
+        .encode()
     }
 
     /// Ask a peer for their shared-file listing (peer code 4, no body).
     #[must_use]
     pub fn build_get_share_file_list() -> Message {
-        Message::new().write_int32(4).clone()
+        PeerMessageOut::GetShareFileList.encode()
+    }
+
+    /// Ask a peer for their description, queue and slot info (peer code 15,
+    /// no body).
+    #[must_use]
+    pub fn build_user_info_request() -> Message {
+        PeerMessageOut::UserInfoRequest.encode()
+    }
+
+    #[must_use]
+    pub fn build_place_in_queue_request(filename: &str) -> Message {
+        PeerMessageOut::PlaceInQueueRequest {
+            filename: filename.to_string(),
+        }
+        .encode()
     }
 
     #[must_use]
     pub fn build_queue_upload_message(filename: &str) -> Message {
-        Message::new()
-            .write_int32(43)
-            .write_string(filename)
-            .clone()
+        PeerMessageOut::QueueUpload {
+            filename: filename.to_string(),
+        }
+        .encode()
+    }
+
+    /// Tell a peer where its queued download of `filename` stands in our
+    /// upload queue (peer code 44).
+    #[must_use]
+    pub fn build_place_in_queue_response(
+        filename: &str,
+        place: u32,
+    ) -> Message {
+        PeerMessageOut::PlaceInQueueResponse {
+            filename: filename.to_string(),
+            place,
+        }
+        .encode()
+    }
+
+    /// Refuse a peer's `QueueUpload` of `filename` (peer code 50).
+    #[must_use]
+    pub fn build_upload_denied(filename: &str, reason: &str) -> Message {
+        PeerMessageOut::UploadDenied {
+            filename: filename.to_string(),
+            reason: reason.to_string(),
+        }
+        .encode()
     }
 
     #[must_use]
@@ -173,12 +257,13 @@ impl MessageFactory {
         filename: &str,
         token: u32,
     ) -> Message {
-        Message::new()
-            .write_int32(40) // code
-            .write_int32(0) // direction
-            .write_int32(token)
-            .write_string(filename)
-            .clone()
+        PeerMessageOut::TransferRequest(Transfer {
+            direction: 0,
+            token,
+            filename: filename.to_string(),
+            size: 0,
+        })
+        .encode()
     }
     /// A TransferRequest (peer code 40) initiating an *upload*: we offer a file
     /// to a peer who queued it, quoting our transfer token and its size.
@@ -188,29 +273,35 @@ impl MessageFactory {
         token: u32,
         size: u64,
     ) -> Message {
-        Message::new()
-            .write_int32(40)
-            .write_int32(1) // direction: upload
-            .write_int32(token)
-            .write_string(filename)
-            .write_int64(size)
-            .clone()
+        PeerMessageOut::TransferRequest(Transfer {
+            direction: 1,
+            token,
+            filename: filename.to_string(),
+            size,
+        })
+        .encode()
     }
 
     #[must_use]
     pub fn build_transfer_response_message(transfer: Transfer) -> Message {
-        Message::new()
-            .write_int32(41)
-            .write_int32(transfer.token)
-            .write_bool(true)
-            .clone()
+        PeerMessageOut::TransferResponse {
+            token: transfer.token,
+            reply: TransferReply::Allowed,
+        }
+        .encode()
+    }
+    /// Refuse the file a peer offered with `token`, e.g. as "Cancelled".
+    #[must_use]
+    pub fn build_transfer_denied_message(token: u32, reason: &str) -> Message {
+        PeerMessageOut::TransferResponse {
+            token,
+            reply: TransferReply::Denied(reason.to_string()),
+        }
+        .encode()
     }
     #[must_use]
     pub fn build_pierce_firewall_message(token: u32) -> Message {
-        Message::new()
-            .write_int8(0) // PierceFirewall message code
-            .write_int32(token)
-            .clone()
+        PeerInitMessage::PierceFirewall { token }.encode()
     }
 
     #[must_use]
@@ -219,12 +310,12 @@ impl MessageFactory {
         connection_type: ConnectionType,
         token: u32,
     ) -> Message {
-        Message::new()
-            .write_int8(1)
-            .write_string(own_username)
-            .write_string(&connection_type.to_string())
-            .write_int32(token)
-            .clone()
+        PeerInitMessage::PeerInit {
+            username: own_username.to_string(),
+            connection_type,
+            token,
+        }
+        .encode()
     }
 }
 
@@ -262,7 +353,7 @@ fn test_build_upload_transfer_request() {
     // Decode via the production Transfer parser (dispatcher starts at offset 8).
     let mut decoded = Message::new_with_data(message.get_buffer());
     decoded.set_pointer(8);
-    let transfer = Transfer::new_from_message(&mut decoded);
+    let transfer = Transfer::new_from_message(&mut decoded).unwrap();
     assert_eq!(transfer.direction, 1); // upload
     assert_eq!(transfer.token, 555);
     assert_eq!(transfer.filename, "song.mp3");
@@ -355,3 +446,58 @@ fn test_build_file_search_message() {
     .to_vec();
     assert_eq!(expect, message.get_data());
 }
+
+#[test]
+fn test_build_place_in_queue_response() {
+    let message = MessageFactory::build_place_in_queue_response("a.mp3", 3);
+    let expect: Vec<u8> = [
+        44, 0, 0, 0, // code
+        5, 0, 0, 0, 97, 46, 109, 112, 51, // "a.mp3"
+        3, 0, 0, 0, // place
+    ]
+    .to_vec();
+    assert_eq!(expect, message.get_data());
+}
+
+#[test]
+fn test_build_user_lookups() {
+    let name = [3, 0, 0, 0, 98, 111, 98]; // "bob"
+    let with_code = |code: u8| {
+        let mut expect = vec![code, 0, 0, 0];
+        expect.extend_from_slice(&name);
+        expect
+    };
+    assert_eq!(
+        with_code(5),
+        MessageFactory::build_watch_username("bob").get_data()
+    );
+    assert_eq!(
+        with_code(7),
+        MessageFactory::build_get_user_status("bob").get_data()
+    );
+    assert_eq!(
+        with_code(36),
+        MessageFactory::build_get_user_stats("bob").get_data()
+    );
+    assert_eq!(
+        vec![15, 0, 0, 0],
+        MessageFactory::build_user_info_request().get_data()
+    );
+}
+
+#[test]
+fn test_build_place_in_queue_request() {
+    let message = MessageFactory::build_place_in_queue_request("a.mp3");
+    assert_eq!(
+        vec![51, 0, 0, 0, 5, 0, 0, 0, b'a', b'.', b'm', b'p', b'3'],
+        message.get_data()
+    );
+}
+
+#[test]
+fn test_build_server_ping() {
+    assert_eq!(
+        vec![32, 0, 0, 0],
+        MessageFactory::build_server_ping().get_data()
+    );
+}
@@ -1,23 +1,32 @@
 use crate::actor::server_actor::{ServerMessage, UserMessage};
 use crate::info;
 use crate::message::server::MessageFactory;
-use crate::message::{Message, MessageHandler};
+use crate::message::{Message, MessageHandler, ServerMessageIn};
 
 use std::sync::mpsc::Sender;
 
 pub struct MessageUser;
 
 impl MessageHandler<ServerMessage> for MessageUser {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         22
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let id = message.read_int32();
-        let timestamp = message.read_int32();
-        let username = message.read_string();
-        let message_content = message.read_string();
-        let new_message = message.read_bool();
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        let ServerMessageIn::MessageUser {
+            id,
+            timestamp,
+            username,
+            message: message_content,
+            new_message,
+        } = ServerMessageIn::decode_body(self.get_code(), message)?
+        else {
+            return Ok(());
+        };
         let user_message = UserMessage::new(
             id,
             timestamp,
@@ -40,5 +49,6 @@ impl MessageHandler<ServerMessage> for MessageUser {
         // Surface the message to the client so it can be read via the API.
         let _ =
             sender.send(ServerMessage::PrivateMessageReceived(user_message));
+        Ok(())
     }
 }
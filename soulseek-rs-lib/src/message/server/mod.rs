@@ -1,7 +1,12 @@
+mod admin_message;
+mod cant_connect_to_peer;
 mod connect_to_peer;
+mod distributed_settings;
 mod excluded_search_phrases;
 mod file_search;
 mod get_peer_address;
+mod get_user_stats;
+mod get_user_status;
 mod join_room;
 mod leave_room;
 mod login;
@@ -9,17 +14,32 @@ mod message_factory;
 mod message_user;
 mod parent_min_speed;
 mod parent_speed_ratio;
+mod possible_parents;
 mod privileged_users;
+mod relogged;
 mod room_list;
+mod room_tickers;
 mod say_chatroom;
 mod user_joined_room;
 mod user_left_room;
+mod watch_user;
 mod wish_list_interval;
 
+pub use admin_message::AdminMessageHandler;
+pub use cant_connect_to_peer::CantConnectToPeerHandler;
 pub use connect_to_peer::ConnectToPeerHandler;
+pub use distributed_settings::{
+    DistributedAliveIntervalHandler, MinParentsInCacheHandler,
+    ParentInactivityTimeoutHandler, ResetDistributedHandler,
+    SearchInactivityTimeoutHandler,
+};
 pub use excluded_search_phrases::ExcludedSearchPhrasesHandler;
 pub use file_search::FileSearchHandler;
 pub use get_peer_address::GetPeerAddressHandler;
+pub use get_user_stats::{
+    GetUserStatsHandler, parse_get_user_stats, write_get_user_stats,
+};
+pub use get_user_status::GetUserStatusHandler;
 pub use join_room::JoinRoomHandler;
 pub use leave_room::LeaveRoomHandler;
 pub use login::LoginHandler;
@@ -27,9 +47,17 @@ pub use message_factory::MessageFactory;
 pub use message_user::MessageUser;
 pub use parent_min_speed::ParentMinSpeedHandler;
 pub use parent_speed_ratio::ParentSpeedRatioHandler;
-pub use privileged_users::PrivilegedUsersHandler;
-pub use room_list::RoomListHandler;
+pub use possible_parents::{ParentCandidate, PossibleParentsHandler};
+pub use privileged_users::{
+    AddPrivilegedUserHandler, CheckPrivilegesHandler, PrivilegedUsersHandler,
+};
+pub use relogged::ReloggedHandler;
+pub use room_list::{RoomListHandler, parse_room_list, write_room_list};
+pub use room_tickers::{
+    RoomTickerAddHandler, RoomTickerRemoveHandler, RoomTickersHandler,
+};
 pub use say_chatroom::SayChatroomHandler;
 pub use user_joined_room::UserJoinedRoomHandler;
 pub use user_left_room::UserLeftRoomHandler;
+pub use watch_user::{WatchUserHandler, parse_watch_user, write_watch_user};
 pub use wish_list_interval::WishListIntervalHandler;
@@ -3,19 +3,26 @@ use std::sync::mpsc::Sender;
 
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 
 pub struct ParentMinSpeedHandler;
 
 impl MessageHandler<ServerMessage> for ParentMinSpeedHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         83
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let _ = sender;
-        let number = message.read_int32();
-        debug!("Parent min speed: {}", number);
+    fn handle(
+        &self,
+        message: &mut Message,
+        _sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::ParentMinSpeed(speed) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Parent min speed: {}", speed);
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,83 @@
+use std::sync::mpsc::Sender;
+
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler, ServerMessageIn, wire::wire_struct},
+};
+
+wire_struct! {
+    /// A user we may join the distributed network under, and where to
+    /// reach them.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct ParentCandidate {
+        pub username: String,
+        pub ip: std::net::Ipv4Addr,
+        pub port: u32,
+    }
+}
+
+/// Users we may take as our distributed parent (server code 102), sent
+/// while we have none.
+pub struct PossibleParentsHandler;
+
+impl MessageHandler<ServerMessage> for PossibleParentsHandler {
+    fn get_code(&self) -> u32 {
+        102
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::PossibleParents(candidates) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::PossibleParents(candidates));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_candidates_in_order() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message
+            .write_int32(0)
+            .write_int32(102)
+            .write_int32(2)
+            .write_string("alice")
+            .write_raw_bytes(vec![4, 3, 2, 1])
+            .write_int32(2234)
+            .write_string("bob")
+            .write_raw_bytes(vec![1, 0, 0, 127])
+            .write_int32(2235);
+        message.set_pointer(8);
+
+        PossibleParentsHandler.handle(&mut message, tx).unwrap();
+        let Ok(ServerMessage::PossibleParents(candidates)) = rx.try_recv()
+        else {
+            panic!("expected PossibleParents");
+        };
+        assert_eq!(
+            candidates,
+            [
+                ParentCandidate {
+                    username: "alice".into(),
+                    ip: std::net::Ipv4Addr::new(1, 2, 3, 4),
+                    port: 2234,
+                },
+                ParentCandidate {
+                    username: "bob".into(),
+                    ip: std::net::Ipv4Addr::LOCALHOST,
+                    port: 2235,
+                },
+            ]
+        );
+    }
+}
@@ -3,18 +3,125 @@ use std::sync::mpsc::Sender;
 
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 
+/// The full list of privileged users, sent after login (server code 69).
 pub struct PrivilegedUsersHandler;
 
 impl MessageHandler<ServerMessage> for PrivilegedUsersHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         69
     }
 
-    fn handle(&self, message: &mut Message, _sender: Sender<ServerMessage>) {
-        let number = message.read_int32();
-        debug!("Number of privileged users: {}", number);
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::PrivilegedUsers(users) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Number of privileged users: {}", users.len());
+            let _ = sender.send(ServerMessage::PrivilegedUsers(users));
+        }
+        Ok(())
+    }
+}
+
+/// A user gained privileges (server code 91).
+pub struct AddPrivilegedUserHandler;
+
+impl MessageHandler<ServerMessage> for AddPrivilegedUserHandler {
+    fn get_code(&self) -> u32 {
+        91
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::AddPrivilegedUser(username) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::PrivilegedUserAdded(username));
+        }
+        Ok(())
+    }
+}
+
+/// Seconds of privileges we have left (server code 92).
+pub struct CheckPrivilegesHandler;
+
+impl MessageHandler<ServerMessage> for CheckPrivilegesHandler {
+    fn get_code(&self) -> u32 {
+        92
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::CheckPrivileges(seconds) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Privileges left: {}s", seconds);
+            let _ = sender.send(ServerMessage::PrivilegesLeft(seconds));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_privileged_user_list() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_int32(2);
+        message.write_string("alice");
+        message.write_string("bob");
+        message.set_pointer(8);
+
+        PrivilegedUsersHandler.handle(&mut message, tx).unwrap();
+        match rx.try_recv() {
+            Ok(ServerMessage::PrivilegedUsers(users)) => {
+                assert_eq!(users, ["alice", "bob"]);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn forwards_privileges_left() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_int32(86_400);
+        message.set_pointer(8);
+
+        CheckPrivilegesHandler.handle(&mut message, tx).unwrap();
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerMessage::PrivilegesLeft(86_400))
+        ));
+    }
+
+    #[test]
+    fn hostile_count_is_an_error() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_int32(u32::MAX);
+        message.write_string("alice");
+        message.set_pointer(8);
+
+        assert!(PrivilegedUsersHandler.handle(&mut message, tx).is_err());
+        assert!(rx.try_recv().is_err());
     }
 }
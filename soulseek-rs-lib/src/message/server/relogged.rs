@@ -0,0 +1,42 @@
+use crate::warn;
+use std::sync::mpsc::Sender;
+
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler},
+};
+
+/// Someone logged in with our account from elsewhere (server code 41). The
+/// server closes our connection right after sending it.
+pub struct ReloggedHandler;
+
+impl MessageHandler<ServerMessage> for ReloggedHandler {
+    fn get_code(&self) -> u32 {
+        41
+    }
+
+    fn handle(
+        &self,
+        _message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        warn!("[server] Logged in from another location");
+        let _ = sender.send(ServerMessage::Relogged);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_relogged() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new_with_data(vec![0u8; 8]);
+        message.set_pointer(8);
+
+        ReloggedHandler.handle(&mut message, tx).unwrap();
+        assert!(matches!(rx.try_recv(), Ok(ServerMessage::Relogged)));
+    }
+}
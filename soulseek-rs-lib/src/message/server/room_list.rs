@@ -1,6 +1,6 @@
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
     types::RoomInfo,
 };
 use std::sync::mpsc::Sender;
@@ -8,46 +8,104 @@ use std::sync::mpsc::Sender;
 pub struct RoomListHandler;
 
 impl MessageHandler<ServerMessage> for RoomListHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         64
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let rooms = parse_room_list(message);
-        let _ = sender.send(ServerMessage::RoomListReceived(rooms));
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::RoomList(rooms) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomListReceived(rooms));
+        }
+        Ok(())
+    }
+}
+
+/// Parse the rooms out of a `RoomList` (code 64) message.
+///
+/// The payload holds three sections of room names followed by their user
+/// counts: public rooms, private rooms we own, and private rooms we are a
+/// member of; then the names of rooms we operate, which are ignored. Servers
+/// that only send the public section are accepted. `message` must be
+/// positioned at the payload (the dispatcher sets pointer 8).
+pub fn parse_room_list(message: &mut Message) -> crate::Result<Vec<RoomInfo>> {
+    let mut rooms = read_section(message, false, false)?;
+    if has_more(message) {
+        rooms.extend(read_section(message, true, true)?);
     }
+    if has_more(message) {
+        rooms.extend(read_section(message, true, false)?);
+    }
+    Ok(rooms)
+}
+
+/// Whether the payload can hold another field. Checked before every read so
+/// a bogus (possibly hostile) count cannot spin us into an OOM allocation
+/// loop; the 4-byte floor also avoids read_int32 stalling on a 1-3 byte tail.
+const fn has_more(message: &mut Message) -> bool {
+    message.get_pointer() + 4 <= message.get_size()
 }
 
-/// Parse the public rooms out of a `RoomList` (code 64) message: a vector of
-/// room names followed by a vector of user counts. The remaining private-room
-/// sections are ignored. `message` must be positioned at the payload (the
-/// dispatcher sets pointer 8).
-#[must_use]
-pub fn parse_room_list(message: &mut Message) -> Vec<RoomInfo> {
-    let name_count = message.read_int32();
+/// One vector of room names and the vector of their user counts.
+fn read_section(
+    message: &mut Message,
+    private: bool,
+    owned: bool,
+) -> crate::Result<Vec<RoomInfo>> {
+    let name_count = message.try_read_int32()?;
     let mut names = Vec::new();
     for _ in 0..name_count {
-        // Stop once the payload can't hold another field: a bogus (possibly
-        // hostile) count must not spin us into an OOM allocation loop. A
-        // 4-byte floor also avoids read_int32 stalling on a 1-3 byte tail.
-        if message.get_pointer() + 4 > message.get_size() {
+        if !has_more(message) {
             break;
         }
-        names.push(message.read_string());
+        names.push(message.try_read_string()?);
     }
-    let count_count = message.read_int32();
+    let count_count = message.try_read_int32()?;
     let mut counts = Vec::new();
     for _ in 0..count_count {
-        if message.get_pointer() + 4 > message.get_size() {
+        if !has_more(message) {
             break;
         }
-        counts.push(message.read_int32());
+        counts.push(message.try_read_int32()?);
     }
-    names
+    Ok(names
         .into_iter()
         .zip(counts)
-        .map(|(name, user_count)| RoomInfo { name, user_count })
-        .collect()
+        .map(|(name, user_count)| RoomInfo {
+            name,
+            user_count,
+            private,
+            owned,
+        })
+        .collect())
+}
+
+/// Write `rooms` as a `RoomList` payload, each in the section its flags
+/// place it in, with no operated rooms.
+pub fn write_room_list(rooms: &[RoomInfo], message: &mut Message) {
+    let public = rooms.iter().filter(|room| !room.private);
+    let owned = rooms.iter().filter(|room| room.private && room.owned);
+    let member = rooms.iter().filter(|room| room.private && !room.owned);
+    for section in [
+        public.collect::<Vec<_>>(),
+        owned.collect(),
+        member.collect(),
+    ] {
+        message.write_int32(section.len() as u32);
+        for room in &section {
+            message.write_string(&room.name);
+        }
+        message.write_int32(section.len() as u32);
+        for room in &section {
+            message.write_int32(room.user_count);
+        }
+    }
+    message.write_int32(0);
 }
 
 #[cfg(test)]
@@ -74,40 +132,67 @@ mod tests {
             m.write_int32(42);
             m.write_int32(7);
         });
-        let rooms = parse_room_list(&mut message);
+        let rooms = parse_room_list(&mut message).unwrap();
         assert_eq!(
             rooms,
             vec![
                 RoomInfo {
                     name: "nicotine".to_string(),
-                    user_count: 42
+                    user_count: 42,
+                    ..RoomInfo::default()
                 },
                 RoomInfo {
                     name: "jazz".to_string(),
-                    user_count: 7
+                    user_count: 7,
+                    ..RoomInfo::default()
                 },
             ]
         );
     }
 
+    #[test]
+    fn private_sections_set_the_flags_and_round_trip() {
+        let rooms = vec![
+            RoomInfo {
+                name: "public".to_string(),
+                user_count: 9,
+                ..RoomInfo::default()
+            },
+            RoomInfo {
+                name: "mine".to_string(),
+                user_count: 2,
+                private: true,
+                owned: true,
+            },
+            RoomInfo {
+                name: "friends".to_string(),
+                user_count: 4,
+                private: true,
+                owned: false,
+            },
+        ];
+        let mut message = framed(|m| write_room_list(&rooms, m));
+        assert_eq!(parse_room_list(&mut message).unwrap(), rooms);
+    }
+
     #[test]
     fn empty_room_list_parses_to_empty() {
         let mut message = framed(|m| {
             m.write_int32(0);
             m.write_int32(0);
         });
-        assert!(parse_room_list(&mut message).is_empty());
+        assert!(parse_room_list(&mut message).unwrap().is_empty());
     }
 
     #[test]
     fn hostile_counts_do_not_hang_or_overallocate() {
         // A tiny frame claiming ~4 billion names/counts must return promptly
-        // (bounded by the payload) rather than looping into an OOM.
+        // (bounded by the payload) rather than looping into an OOM. The user
+        // count list it then lacks makes the message malformed.
         let mut message = framed(|m| {
             m.write_int32(u32::MAX);
         });
-        let rooms = parse_room_list(&mut message);
-        assert!(rooms.is_empty());
+        assert!(parse_room_list(&mut message).is_err());
     }
 
     #[test]
@@ -119,7 +204,7 @@ mod tests {
             m.write_int32(1);
             m.write_int32(5);
         });
-        RoomListHandler.handle(&mut message, tx);
+        RoomListHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomListReceived(rooms)) => {
                 assert_eq!(rooms.len(), 1);
@@ -0,0 +1,142 @@
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler, ServerMessageIn},
+};
+use std::sync::mpsc::Sender;
+
+/// Every ticker (a member's short status line) in a room we just joined
+/// (server code 113).
+pub struct RoomTickersHandler;
+
+impl MessageHandler<ServerMessage> for RoomTickersHandler {
+    fn get_code(&self) -> u32 {
+        113
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::RoomTickers { room, tickers } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomTickers { room, tickers });
+        }
+        Ok(())
+    }
+}
+
+/// A room member set their ticker (server code 114).
+pub struct RoomTickerAddHandler;
+
+impl MessageHandler<ServerMessage> for RoomTickerAddHandler {
+    fn get_code(&self) -> u32 {
+        114
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::RoomTickerAdd {
+            room,
+            username,
+            ticker,
+        } = ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomTickerSet {
+                room,
+                username,
+                ticker,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A room member cleared their ticker (server code 115).
+pub struct RoomTickerRemoveHandler;
+
+impl MessageHandler<ServerMessage> for RoomTickerRemoveHandler {
+    fn get_code(&self) -> u32 {
+        115
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::RoomTickerRemove { room, username } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender
+                .send(ServerMessage::RoomTickerRemoved { room, username });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_room_tickers() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_string("jazz");
+        message.write_int32(1);
+        message.write_string("carol");
+        message.write_string("listening to Coltrane");
+        message.set_pointer(8);
+
+        RoomTickersHandler.handle(&mut message, tx).unwrap();
+        match rx.try_recv() {
+            Ok(ServerMessage::RoomTickers { room, tickers }) => {
+                assert_eq!(room, "jazz");
+                assert_eq!(
+                    tickers,
+                    [(
+                        "carol".to_string(),
+                        "listening to Coltrane".to_string()
+                    )]
+                );
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn forwards_ticker_set_and_removed() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_string("jazz");
+        message.write_string("carol");
+        message.write_string("brb");
+        message.set_pointer(8);
+        RoomTickerAddHandler
+            .handle(&mut message, tx.clone())
+            .unwrap();
+
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_string("jazz");
+        message.write_string("carol");
+        message.set_pointer(8);
+        RoomTickerRemoveHandler.handle(&mut message, tx).unwrap();
+
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerMessage::RoomTickerSet { ticker, .. }) if ticker == "brb"
+        ));
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ServerMessage::RoomTickerRemoved { username, .. }) if username == "carol"
+        ));
+    }
+}
@@ -1,25 +1,34 @@
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 use std::sync::mpsc::Sender;
 
 pub struct SayChatroomHandler;
 
 impl MessageHandler<ServerMessage> for SayChatroomHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         13
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let room = message.read_string();
-        let username = message.read_string();
-        let message_text = message.read_string();
-        let _ = sender.send(ServerMessage::RoomMessageReceived {
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::SayChatroom {
             room,
             username,
-            message: message_text,
-        });
+            message,
+        } = ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomMessageReceived {
+                room,
+                username,
+                message,
+            });
+        }
+        Ok(())
     }
 }
 
@@ -37,7 +46,7 @@ mod tests {
         message.write_string("hello everyone");
         message.set_pointer(8);
 
-        SayChatroomHandler.handle(&mut message, tx);
+        SayChatroomHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomMessageReceived {
                 room,
@@ -51,4 +60,17 @@ mod tests {
             other => panic!("unexpected: {other:?}"),
         }
     }
+
+    #[test]
+    fn truncated_message_is_an_error_and_not_forwarded() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        message.write_string("jazz");
+        message.write_int32(100); // username length with no bytes behind it
+        message.set_pointer(8);
+
+        assert!(SayChatroomHandler.handle(&mut message, tx).is_err());
+        assert!(rx.try_recv().is_err());
+    }
 }
@@ -1,22 +1,28 @@
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 use std::sync::mpsc::Sender;
 
 pub struct UserJoinedRoomHandler;
 
 impl MessageHandler<ServerMessage> for UserJoinedRoomHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         16
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        // UserJoinedRoom (code 16): room, username, then that user's stats,
-        // which we don't need. Reading the first two fields is enough.
-        let room = message.read_string();
-        let username = message.read_string();
-        let _ = sender.send(ServerMessage::RoomUserJoined { room, username });
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::UserJoinedRoom { room, username } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ =
+                sender.send(ServerMessage::RoomUserJoined { room, username });
+        }
+        Ok(())
     }
 }
 
@@ -33,7 +39,7 @@ mod tests {
         message.write_string("carol");
         message.set_pointer(8);
 
-        UserJoinedRoomHandler.handle(&mut message, tx);
+        UserJoinedRoomHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomUserJoined { room, username }) => {
                 assert_eq!(room, "jazz");
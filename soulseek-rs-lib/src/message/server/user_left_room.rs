@@ -1,20 +1,27 @@
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 use std::sync::mpsc::Sender;
 
 pub struct UserLeftRoomHandler;
 
 impl MessageHandler<ServerMessage> for UserLeftRoomHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         17
     }
 
-    fn handle(&self, message: &mut Message, sender: Sender<ServerMessage>) {
-        let room = message.read_string();
-        let username = message.read_string();
-        let _ = sender.send(ServerMessage::RoomUserLeft { room, username });
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::UserLeftRoom { room, username } =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ = sender.send(ServerMessage::RoomUserLeft { room, username });
+        }
+        Ok(())
     }
 }
 
@@ -31,7 +38,7 @@ mod tests {
         message.write_string("carol");
         message.set_pointer(8);
 
-        UserLeftRoomHandler.handle(&mut message, tx);
+        UserLeftRoomHandler.handle(&mut message, tx).unwrap();
         match rx.try_recv() {
             Ok(ServerMessage::RoomUserLeft { room, username }) => {
                 assert_eq!(room, "jazz");
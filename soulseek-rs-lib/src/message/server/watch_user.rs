@@ -0,0 +1,135 @@
+use crate::{
+    actor::server_actor::ServerMessage,
+    message::{Message, MessageHandler, ServerMessageIn},
+    types::{UserInfo, UserStatus},
+};
+use std::sync::mpsc::Sender;
+
+use super::get_user_stats::{read_user_stats, write_user_stats};
+
+/// The server's reply to watching a user (server code 5).
+pub struct WatchUserHandler;
+
+impl MessageHandler<ServerMessage> for WatchUserHandler {
+    fn get_code(&self) -> u32 {
+        5
+    }
+
+    fn handle(
+        &self,
+        message: &mut Message,
+        sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::WatchUser(info) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            let _ =
+                sender.send(ServerMessage::UserInfoReceived(Box::new(info)));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `WatchUser` reply: the username and whether it exists, then (for
+/// existing users) status, the stats block and, for users who are not
+/// offline, an optional country code.
+pub fn parse_watch_user(message: &mut Message) -> crate::Result<UserInfo> {
+    let mut info = UserInfo::new(message.try_read_string()?);
+    let exists = message.try_read_bool()?;
+    info.exists = Some(exists);
+    if !exists {
+        return Ok(info);
+    }
+    let status = UserStatus::from_code(message.try_read_int32()?);
+    info.status = Some(status);
+    read_user_stats(message, &mut info)?;
+    if status != UserStatus::Offline
+        && message.get_pointer() < message.get_size()
+    {
+        let country = message.try_read_string()?;
+        if !country.is_empty() {
+            info.country = Some(country);
+        }
+    }
+    Ok(info)
+}
+
+/// Write `info` as a `WatchUser` reply; unknown users are sent as existing
+/// and offline.
+pub fn write_watch_user(info: &UserInfo, message: &mut Message) {
+    let exists = info.exists.unwrap_or(true);
+    message.write_string(&info.username).write_bool(exists);
+    if !exists {
+        return;
+    }
+    let status = info.status.unwrap_or(UserStatus::Offline);
+    message.write_int32(status.code());
+    write_user_stats(message, info);
+    if status != UserStatus::Offline {
+        message.write_string(info.country.as_deref().unwrap_or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn framed(build: impl FnOnce(&mut Message)) -> Message {
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![0u8; 8]);
+        build(&mut message);
+        message.set_pointer(8);
+        message
+    }
+
+    #[test]
+    fn parses_an_online_user_with_country() {
+        let mut message = framed(|m| {
+            m.write_string("alice")
+                .write_bool(true)
+                .write_int32(2)
+                .write_int32(1000)
+                .write_int32(5)
+                .write_int32(0)
+                .write_int32(42)
+                .write_int32(3)
+                .write_string("NL");
+        });
+        let info = parse_watch_user(&mut message).unwrap();
+        assert_eq!(info.exists, Some(true));
+        assert_eq!(info.status, Some(UserStatus::Online));
+        assert_eq!(info.avg_speed, Some(1000));
+        assert_eq!(info.shared_files, Some(42));
+        assert_eq!(info.shared_folders, Some(3));
+        assert_eq!(info.country.as_deref(), Some("NL"));
+    }
+
+    #[test]
+    fn unknown_user_only_reports_existence() {
+        let mut message = framed(|m| {
+            m.write_string("ghost").write_bool(false);
+        });
+        let info = parse_watch_user(&mut message).unwrap();
+        assert_eq!(info.username, "ghost");
+        assert_eq!(info.exists, Some(false));
+        assert_eq!(info.status, None);
+        assert_eq!(info.avg_speed, None);
+    }
+
+    #[test]
+    fn offline_user_without_country_parses() {
+        let mut message = framed(|m| {
+            m.write_string("bob")
+                .write_bool(true)
+                .write_int32(0)
+                .write_int32(0)
+                .write_int32(0)
+                .write_int32(0)
+                .write_int32(0)
+                .write_int32(0);
+        });
+        let info = parse_watch_user(&mut message).unwrap();
+        assert_eq!(info.status, Some(UserStatus::Offline));
+        assert_eq!(info.country, None);
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::mpsc::Sender;
 
 use crate::{
     actor::server_actor::ServerMessage,
-    message::{Message, MessageHandler},
+    message::{Message, MessageHandler, ServerMessageIn},
 };
 
 pub struct WishListIntervalHandler;
@@ -11,12 +11,20 @@ pub struct WishListIntervalHandler;
 // The server tells us the wishlist search interval.
 // This interval is almost always 12 minutes, or 2 minutes for privileged users.
 impl MessageHandler<ServerMessage> for WishListIntervalHandler {
-    fn get_code(&self) -> u8 {
+    fn get_code(&self) -> u32 {
         104
     }
 
-    fn handle(&self, message: &mut Message, _sender: Sender<ServerMessage>) {
-        let number = message.read_int32();
-        debug!("Wishlist search interval: {} in seconds", number);
+    fn handle(
+        &self,
+        message: &mut Message,
+        _sender: Sender<ServerMessage>,
+    ) -> crate::Result<()> {
+        if let ServerMessageIn::WishlistInterval(seconds) =
+            ServerMessageIn::decode_body(self.get_code(), message)?
+        {
+            debug!("Wishlist search interval: {} in seconds", seconds);
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,449 @@
+//! Field encodings, and the DSL that derives message codecs from them.
+//!
+//! Every field of every message is one of a handful of shapes: little-endian
+//! integers, one-byte bools, strings and lists prefixed with a `u32` length.
+//! [`Wire`] gives each field type its encoding once, and [`wire_enum!`] and
+//! [`wire_struct!`] turn a list of typed fields into `encode`/`decode`, so a
+//! message is declared rather than hand-written:
+//!
+//! ```ignore
+//! wire_enum! {
+//!     pub enum ServerMessageOut: u32 {
+//!         GetPeerAddress { username: String } = 3,
+//!         HaveNoParent(bool) = 71,
+//!         RoomList = 64,
+//!     }
+//! }
+//! ```
+//!
+//! Fields are read and written in declaration order. A payload with no
+//! regular layout names its codec, `Field: Ty as (read_fn, write_fn)`, with
+//! `read_fn(&mut Message) -> Result<Ty>` and `write_fn(&Ty, &mut Message)`.
+
+use std::net::Ipv4Addr;
+
+use crate::{error::Result, message::Message};
+
+/// A value with a wire encoding.
+pub trait Wire: Sized {
+    fn write_to(&self, message: &mut Message);
+    fn read_from(message: &mut Message) -> Result<Self>;
+}
+
+impl Wire for u8 {
+    fn write_to(&self, message: &mut Message) {
+        message.write_int8(*self);
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        message.try_read_int8()
+    }
+}
+
+impl Wire for u32 {
+    fn write_to(&self, message: &mut Message) {
+        message.write_int32(*self);
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        message.try_read_int32()
+    }
+}
+
+impl Wire for u64 {
+    fn write_to(&self, message: &mut Message) {
+        message.write_int64(*self);
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        message.try_read_int64()
+    }
+}
+
+impl Wire for bool {
+    fn write_to(&self, message: &mut Message) {
+        message.write_bool(*self);
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        message.try_read_bool()
+    }
+}
+
+impl Wire for String {
+    fn write_to(&self, message: &mut Message) {
+        message.write_string(self);
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        message.try_read_string()
+    }
+}
+
+/// An IPv4 address, sent as a little-endian `u32`.
+impl Wire for Ipv4Addr {
+    fn write_to(&self, message: &mut Message) {
+        message.write_int32(u32::from(*self));
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        Ok(Self::from(message.try_read_int32()?))
+    }
+}
+
+/// A count-prefixed list. Items are read one by one, so a hostile count
+/// fails on the first missing item instead of preallocating.
+impl<T: Wire> Wire for Vec<T> {
+    fn write_to(&self, message: &mut Message) {
+        message.write_int32(self.len() as u32);
+        for item in self {
+            item.write_to(message);
+        }
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        let count = message.try_read_int32()?;
+        let mut items = Self::new();
+        for _ in 0..count {
+            items.push(T::read_from(message)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<A: Wire, B: Wire> Wire for (A, B) {
+    fn write_to(&self, message: &mut Message) {
+        self.0.write_to(message);
+        self.1.write_to(message);
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        Ok((A::read_from(message)?, B::read_from(message)?))
+    }
+}
+
+/// A trailing field that older peers leave out: read when bytes remain.
+impl<T: Wire> Wire for Option<T> {
+    fn write_to(&self, message: &mut Message) {
+        if let Some(value) = self {
+            value.write_to(message);
+        }
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        if message.get_pointer() < message.get_size() {
+            T::read_from(message).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Declare a message enum with its code type and the code of each variant,
+/// and derive `code`, `encode`, `decode` and `decode_body`. See the module
+/// docs for the syntax.
+///
+/// `encode` returns the code and body without the length prefix, which
+/// `Message::get_buffer` adds. `decode` takes a message as received, length
+/// prefix included; `decode_body` reads the body of a message whose code has
+/// already been read.
+macro_rules! wire_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident: $code_ty:ty {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident
+                $({
+                    $(
+                        $(#[$field_meta:meta])*
+                        $field:ident: $field_ty:ty
+                        $(as ($field_read:path, $field_write:path))?
+                    ),* $(,)?
+                })?
+                $((
+                    $payload_ty:ty
+                    $(as ($payload_read:path, $payload_write:path))?
+                ))?
+                = $code:literal
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant
+                $({ $($(#[$field_meta])* $field: $field_ty),* })?
+                $(($payload_ty))?,
+            )*
+        }
+
+        impl $name {
+            #[must_use]
+            pub const fn code(&self) -> $code_ty {
+                match self {
+                    $(Self::$variant { .. } => $code,)*
+                }
+            }
+
+            #[must_use]
+            pub fn encode(&self) -> $crate::message::Message {
+                let mut message = $crate::message::Message::new();
+                $crate::message::wire::Wire::write_to(
+                    &self.code(),
+                    &mut message,
+                );
+                match self {
+                    $(
+                        Self::$variant
+                        $({ $($field),* })?
+                        $(($crate::message::wire::wire_binding!(
+                            $payload_ty,
+                            payload
+                        )))? => {
+                            $($(
+                                $crate::message::wire::wire_write!(
+                                    &mut message,
+                                    $field,
+                                    $field_ty
+                                    $(as ($field_read, $field_write))?
+                                );
+                            )*)?
+                            $(
+                                $crate::message::wire::wire_write!(
+                                    &mut message,
+                                    payload,
+                                    $payload_ty
+                                    $(as ($payload_read, $payload_write))?
+                                );
+                            )?
+                        }
+                    )*
+                }
+                message
+            }
+
+            /// Decode a message as received, length prefix included.
+            pub fn decode(
+                message: &mut $crate::message::Message,
+            ) -> $crate::error::Result<Self> {
+                message.set_pointer(4);
+                let code = <$code_ty as $crate::message::wire::Wire>::read_from(
+                    message,
+                )?;
+                Self::decode_body(code, message)
+            }
+
+            /// Decode the body of a message with `code`, reading from the
+            /// current position of `message`.
+            pub fn decode_body(
+                code: $code_ty,
+                message: &mut $crate::message::Message,
+            ) -> $crate::error::Result<Self> {
+                Self::decode_fields(code, message)
+                    .map_err(|e| e.in_message(u32::from(code)))
+            }
+
+            fn decode_fields(
+                code: $code_ty,
+                message: &mut $crate::message::Message,
+            ) -> $crate::error::Result<Self> {
+                Ok(match code {
+                    $(
+                        $code => Self::$variant
+                        $({ $(
+                            $field: $crate::message::wire::wire_read!(
+                                message,
+                                $field_ty
+                                $(as ($field_read, $field_write))?
+                            ),
+                        )* })?
+                        $(($crate::message::wire::wire_read!(
+                            message,
+                            $payload_ty
+                            $(as ($payload_read, $payload_write))?
+                        )))?,
+                    )*
+                    _ => {
+                        return Err($crate::error::SoulseekRs::protocol(
+                            format!("unknown {} code", stringify!($name)),
+                        ));
+                    }
+                })
+            }
+        }
+    };
+}
+
+/// Declare a struct whose fields are encoded in order, and derive its
+/// [`Wire`] impl.
+macro_rules! wire_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident: $field_ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $field_ty),*
+        }
+
+        impl $crate::message::wire::Wire for $name {
+            fn write_to(&self, message: &mut $crate::message::Message) {
+                $($crate::message::wire::Wire::write_to(&self.$field, message);)*
+            }
+
+            fn read_from(
+                message: &mut $crate::message::Message,
+            ) -> $crate::error::Result<Self> {
+                Ok(Self {
+                    $($field: $crate::message::wire::Wire::read_from(message)?,)*
+                })
+            }
+        }
+    };
+}
+
+/// Bind a tuple variant's payload to `$binding` in `wire_enum!`'s `encode`.
+macro_rules! wire_binding {
+    ($payload_ty:ty, $binding:ident) => {
+        $binding
+    };
+}
+
+macro_rules! wire_write {
+    ($message:expr, $value:expr, $ty:ty) => {
+        $crate::message::wire::Wire::write_to($value, $message)
+    };
+    ($message:expr, $value:expr, $ty:ty as ($read:path, $write:path)) => {
+        $write($value, $message)
+    };
+}
+
+macro_rules! wire_read {
+    ($message:expr, $ty:ty) => {
+        <$ty as $crate::message::wire::Wire>::read_from($message)?
+    };
+    ($message:expr, $ty:ty as ($read:path, $write:path)) => {
+        $read($message)?
+    };
+}
+
+pub(crate) use {wire_binding, wire_enum, wire_read, wire_struct, wire_write};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    wire_struct! {
+        #[derive(Debug, PartialEq, Eq)]
+        struct Entry {
+            name: String,
+            size: u64,
+            tags: Vec<(u32, String)>,
+        }
+    }
+
+    wire_enum! {
+        #[derive(Debug, PartialEq, Eq)]
+        enum Sample: u8 {
+            Empty = 0,
+            Flag(bool) = 1,
+            Pair { left: u32, right: String } = 2,
+            Shouted(String as (read_shouted, write_shouted)) = 3,
+            Entries { entries: Vec<Entry> } = 4,
+            Noted { id: u32, note: Option<String> } = 5,
+        }
+    }
+
+    fn read_shouted(message: &mut Message) -> Result<String> {
+        Ok(message.try_read_string()?.to_uppercase())
+    }
+
+    fn write_shouted(value: &str, message: &mut Message) {
+        message.write_string(&value.to_lowercase());
+    }
+
+    fn received(message: &Message) -> Message {
+        Message::new_with_data(message.get_buffer())
+    }
+
+    #[test]
+    fn fields_are_encoded_in_declaration_order() {
+        let encoded = Sample::Pair {
+            left: 7,
+            right: "x".into(),
+        }
+        .encode();
+        assert_eq!(encoded.get_data(), [2, 7, 0, 0, 0, 1, 0, 0, 0, b'x']);
+    }
+
+    #[test]
+    fn variants_round_trip() {
+        let samples = [
+            Sample::Empty,
+            Sample::Flag(true),
+            Sample::Pair {
+                left: 1,
+                right: "two".into(),
+            },
+            Sample::Shouted("LOUD".into()),
+            Sample::Entries {
+                entries: vec![
+                    Entry {
+                        name: "a.mp3".into(),
+                        size: 1 << 40,
+                        tags: vec![(1, "rock".into())],
+                    },
+                    Entry {
+                        name: "b.mp3".into(),
+                        size: 3,
+                        tags: Vec::new(),
+                    },
+                ],
+            },
+            Sample::Noted { id: 1, note: None },
+            Sample::Noted {
+                id: 2,
+                note: Some("last".into()),
+            },
+        ];
+        for sample in samples {
+            let encoded = sample.encode();
+            assert_eq!(encoded.get_data()[0], sample.code());
+            assert_eq!(
+                Sample::decode(&mut received(&encoded)).unwrap(),
+                sample
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_option_is_read_only_when_bytes_remain() {
+        let mut message = Message::new();
+        message.write_string("tail");
+        assert_eq!(
+            Option::<String>::read_from(&mut message)
+                .unwrap()
+                .as_deref(),
+            Some("tail")
+        );
+        assert_eq!(Option::<String>::read_from(&mut message).unwrap(), None);
+    }
+
+    #[test]
+    fn hostile_counts_and_unknown_codes_are_errors() {
+        let mut message = Message::new();
+        message.write_int32(u32::MAX).write_string("only one");
+        assert!(Vec::<String>::read_from(&mut message).is_err());
+
+        let unknown = Message::new().write_int8(9).clone();
+        assert!(Sample::decode(&mut received(&unknown)).is_err());
+    }
+
+    #[test]
+    fn ipv4_addresses_are_little_endian() {
+        let mut message = Message::new();
+        message.write_raw_bytes(vec![4, 3, 2, 1]);
+        assert_eq!(
+            Ipv4Addr::read_from(&mut message).unwrap(),
+            Ipv4Addr::new(1, 2, 3, 4)
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Counters describing what a client has done since it was created.
+//!
+//! One [`Metrics`] is shared by the client, its server connection and every
+//! peer connection; [`Client::metrics`](crate::Client::metrics) takes a
+//! [`MetricsSnapshot`] of it. With the `metrics-prometheus` feature,
+//! [`prometheus`] serves them for scraping.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::utils::lock::MutexExt;
+
+#[cfg(feature = "metrics-prometheus")]
+pub mod prometheus;
+
+/// Live counters, updated as the client works.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    bytes_downloaded: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    searches_issued: AtomicU64,
+    downloads_completed: AtomicU64,
+    downloads_failed: AtomicU64,
+    uploads_completed: AtomicU64,
+    uploads_failed: AtomicU64,
+    server_messages: Mutex<MessageCounts>,
+    peer_messages: Mutex<MessageCounts>,
+}
+
+/// Messages received and sent on one kind of connection, by message code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub received: BTreeMap<u32, u64>,
+    pub sent: BTreeMap<u32, u64>,
+}
+
+impl MessageCounts {
+    #[must_use]
+    pub fn total_received(&self) -> u64 {
+        self.received.values().sum()
+    }
+
+    #[must_use]
+    pub fn total_sent(&self) -> u64 {
+        self.sent.values().sum()
+    }
+}
+
+/// A point-in-time copy of [`Metrics`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// File bytes received from peers, including downloads still running.
+    pub bytes_downloaded: u64,
+    /// File bytes sent to peers, including uploads still running.
+    pub bytes_uploaded: u64,
+    /// Peers we currently hold a control connection to.
+    pub peers_connected: usize,
+    pub searches_issued: u64,
+    pub downloads_completed: u64,
+    pub downloads_failed: u64,
+    pub uploads_completed: u64,
+    pub uploads_failed: u64,
+    /// Messages exchanged with the server.
+    pub server_messages: MessageCounts,
+    /// Messages exchanged with peers, over all peer connections.
+    pub peer_messages: MessageCounts,
+}
+
+impl Metrics {
+    pub fn add_downloaded(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_uploaded(&self, bytes: u64) {
+        self.bytes_uploaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn search_issued(&self) {
+        self.searches_issued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn download_finished(&self, failed: bool) {
+        let counter = if failed {
+            &self.downloads_failed
+        } else {
+            &self.downloads_completed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn upload_finished(&self, failed: bool) {
+        let counter = if failed {
+            &self.uploads_failed
+        } else {
+            &self.uploads_completed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn server_message_received(&self, code: u32) {
+        count(&self.server_messages, code, |counts| &mut counts.received);
+    }
+
+    pub fn server_message_sent(&self, code: u32) {
+        count(&self.server_messages, code, |counts| &mut counts.sent);
+    }
+
+    pub fn peer_message_received(&self, code: u32) {
+        count(&self.peer_messages, code, |counts| &mut counts.received);
+    }
+
+    pub fn peer_message_sent(&self, code: u32) {
+        count(&self.peer_messages, code, |counts| &mut counts.sent);
+    }
+
+    /// Copy the counters. Connection counts are not tracked here, so
+    /// `peers_connected` is left at zero for the caller to fill in.
+    #[must_use]
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let messages = |counts: &Mutex<MessageCounts>| {
+            counts
+                .lock_safe()
+                .map(|counts| counts.clone())
+                .unwrap_or_default()
+        };
+        MetricsSnapshot {
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.load(Ordering::Relaxed),
+            peers_connected: 0,
+            searches_issued: self.searches_issued.load(Ordering::Relaxed),
+            downloads_completed: self
+                .downloads_completed
+                .load(Ordering::Relaxed),
+            downloads_failed: self.downloads_failed.load(Ordering::Relaxed),
+            uploads_completed: self.uploads_completed.load(Ordering::Relaxed),
+            uploads_failed: self.uploads_failed.load(Ordering::Relaxed),
+            server_messages: messages(&self.server_messages),
+            peer_messages: messages(&self.peer_messages),
+        }
+    }
+}
+
+fn count(
+    counts: &Mutex<MessageCounts>,
+    code: u32,
+    side: impl FnOnce(&mut MessageCounts) -> &mut BTreeMap<u32, u64>,
+) {
+    if let Ok(mut counts) = counts.lock_safe() {
+        *side(&mut counts).entry(code).or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+
+    #[test]
+    fn messages_are_counted_per_code_and_direction() {
+        let metrics = Metrics::default();
+        metrics.server_message_received(64);
+        metrics.server_message_received(64);
+        metrics.server_message_sent(26);
+        metrics.peer_message_received(9);
+        metrics.add_downloaded(100);
+        metrics.add_downloaded(23);
+        metrics.download_finished(true);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.server_messages.received.get(&64), Some(&2));
+        assert_eq!(snapshot.server_messages.total_sent(), 1);
+        assert_eq!(snapshot.peer_messages.total_received(), 1);
+        assert!(snapshot.peer_messages.sent.is_empty());
+        assert_eq!(snapshot.bytes_downloaded, 123);
+        assert_eq!(
+            (snapshot.downloads_failed, snapshot.downloads_completed),
+            (1, 0)
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! The client metrics over HTTP, in the Prometheus text exposition format.
+//!
+//! [`Client::serve_metrics`](crate::Client::serve_metrics) starts an
+//! [`Exporter`] answering `GET /metrics`; scrape it like any other target.
+
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{MessageCounts, MetricsSnapshot};
+use crate::debug;
+
+/// How often the accept loop checks whether to stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long a scraper has to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request head we read.
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// Serves metrics until dropped.
+pub struct Exporter {
+    address: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Exporter {
+    /// Listen on `address` and answer each scrape with a fresh
+    /// `snapshot()`.
+    ///
+    /// # Errors
+    /// Returns the error binding `address`.
+    pub fn spawn<F>(
+        address: impl ToSocketAddrs,
+        snapshot: F,
+    ) -> io::Result<Self>
+    where
+        F: Fn() -> MetricsSnapshot + Send + 'static,
+    {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        let address = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || serve(&listener, &stop, &snapshot))
+        };
+        Ok(Self {
+            address,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address scrapers connect to.
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(
+    listener: &TcpListener,
+    stop: &AtomicBool,
+    snapshot: &dyn Fn() -> MetricsSnapshot,
+) {
+    while !stop.load(Ordering::Acquire) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if let Err(e) = answer(stream, snapshot) {
+                    debug!("[metrics] scrape from {}: {}", peer, e);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(e) => {
+                debug!("[metrics] accept: {}", e);
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+fn answer(
+    mut stream: TcpStream,
+    snapshot: &dyn Fn() -> MetricsSnapshot,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let head = read_head(&mut stream)?;
+    let mut words = head.split_whitespace();
+    let (status, body) = match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(&snapshot())),
+        (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "only GET\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Read up to the blank line ending the request head.
+fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "request too large",
+            ));
+        }
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// `snapshot` in the text exposition format.
+#[must_use]
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let mut out = String::new();
+    header(
+        &mut out,
+        "downloaded_bytes_total",
+        "counter",
+        "File bytes received from peers.",
+    );
+    sample(
+        &mut out,
+        "downloaded_bytes_total",
+        "",
+        snapshot.bytes_downloaded,
+    );
+    header(
+        &mut out,
+        "uploaded_bytes_total",
+        "counter",
+        "File bytes sent to peers.",
+    );
+    sample(
+        &mut out,
+        "uploaded_bytes_total",
+        "",
+        snapshot.bytes_uploaded,
+    );
+    header(
+        &mut out,
+        "peers_connected",
+        "gauge",
+        "Peers we hold a control connection to.",
+    );
+    sample(
+        &mut out,
+        "peers_connected",
+        "",
+        snapshot.peers_connected as u64,
+    );
+    header(
+        &mut out,
+        "searches_total",
+        "counter",
+        "Searches sent to the server.",
+    );
+    sample(&mut out, "searches_total", "", snapshot.searches_issued);
+
+    for (name, help, completed, failed) in [
+        (
+            "downloads_total",
+            "Downloads that finished.",
+            snapshot.downloads_completed,
+            snapshot.downloads_failed,
+        ),
+        (
+            "uploads_total",
+            "Uploads that finished.",
+            snapshot.uploads_completed,
+            snapshot.uploads_failed,
+        ),
+    ] {
+        header(&mut out, name, "counter", help);
+        sample(&mut out, name, "result=\"completed\"", completed);
+        sample(&mut out, name, "result=\"failed\"", failed);
+    }
+
+    header(
+        &mut out,
+        "messages_total",
+        "counter",
+        "Protocol messages by connection, direction and code.",
+    );
+    for (connection, counts) in [
+        ("server", &snapshot.server_messages),
+        ("peer", &snapshot.peer_messages),
+    ] {
+        write_messages(&mut out, connection, counts);
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP soulseek_{name} {help}");
+    let _ = writeln!(out, "# TYPE soulseek_{name} {kind}");
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: u64) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "soulseek_{name} {value}");
+    } else {
+        let _ = writeln!(out, "soulseek_{name}{{{labels}}} {value}");
+    }
+}
+
+fn write_messages(out: &mut String, connection: &str, counts: &MessageCounts) {
+    for (direction, by_code) in
+        [("received", &counts.received), ("sent", &counts.sent)]
+    {
+        for (code, count) in by_code {
+            let labels = format!(
+                "connection=\"{connection}\",direction=\"{direction}\",code=\"{code}\""
+            );
+            sample(out, "messages_total", &labels, *count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Exporter, MetricsSnapshot, render};
+    use std::io::{Read, Write};
+    use std::net::{Ipv4Addr, TcpStream};
+
+    fn snapshot() -> MetricsSnapshot {
+        let mut snapshot = MetricsSnapshot {
+            bytes_downloaded: 2048,
+            peers_connected: 3,
+            downloads_failed: 1,
+            ..MetricsSnapshot::default()
+        };
+        snapshot.server_messages.received.insert(64, 5);
+        snapshot
+    }
+
+    #[test]
+    fn renders_counters_and_labelled_series() {
+        let text = render(&snapshot());
+        assert!(
+            text.contains("# TYPE soulseek_downloaded_bytes_total counter\n")
+        );
+        assert!(text.contains("\nsoulseek_downloaded_bytes_total 2048\n"));
+        assert!(text.contains("\nsoulseek_peers_connected 3\n"));
+        assert!(
+            text.contains("soulseek_downloads_total{result=\"failed\"} 1\n")
+        );
+        assert!(text.contains(
+            "soulseek_messages_total{connection=\"server\",direction=\"received\",code=\"64\"} 5\n"
+        ));
+    }
+
+    #[test]
+    fn answers_scrapes_over_http() {
+        let exporter =
+            Exporter::spawn((Ipv4Addr::LOCALHOST, 0), snapshot).unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(exporter.local_addr()).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("soulseek_peers_connected 3\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -1,22 +1,24 @@
-use std::fs;
-use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
 use std::net::ToSocketAddrs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::client::ClientContext;
+use crate::client::{ClientContext, DownloadWriter};
 use crate::message::server::MessageFactory;
 use crate::trace;
-use crate::types::{Download, DownloadStatus};
-use crate::utils::path::expand_tilde;
+use crate::transport::{PeerStream, PeerTlsSettings};
+use crate::types::{
+    ConflictPolicy, Download, DownloadStatus, DownloadSummary, FailureReason,
+};
+use crate::utils::disk::available_space;
+use crate::utils::md5_bytes;
+use crate::utils::path::{download_path, expand_tilde, unused_path};
 
-const START_DOWNLOAD: [u8; 8] =
-    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
 const READ_BUFFER_SIZE: usize = 8192;
-const PROGRESS_UPDATE_CHUNKS: usize = 15; // ~120KB (15 * 8192 bytes)
 
 #[derive(Debug)]
 pub enum DownloadError {
@@ -31,7 +33,19 @@ pub enum DownloadError {
     PathResolutionError(String),
     InvalidTokenBytes,
     LockPoisoned,
-    IncompleteDownload { received: usize, expected: usize },
+    IncompleteDownload {
+        received: usize,
+        expected: usize,
+    },
+    /// The download directory's filesystem has too little room left.
+    NoSpace {
+        needed: u64,
+        available: u64,
+    },
+    /// The file exists and the conflict policy says to keep it.
+    FileExists(PathBuf),
+    /// The download was cancelled.
+    Aborted,
 }
 
 impl std::fmt::Display for DownloadError {
@@ -62,10 +76,30 @@ impl std::fmt::Display for DownloadError {
                 f,
                 "Incomplete download: received {received} of {expected} bytes"
             ),
+            Self::NoSpace { needed, available } => write!(
+                f,
+                "Not enough disk space: need {needed} bytes, {available} free"
+            ),
+            Self::FileExists(path) => {
+                write!(f, "File already exists: {}", path.display())
+            }
+            Self::Aborted => write!(f, "Cancelled by you"),
         }
     }
 }
 
+impl DownloadError {
+    /// Whether the transfer started and then broke off, as opposed to never
+    /// starting or failing on this side.
+    #[must_use]
+    pub const fn broke_off(&self) -> bool {
+        matches!(
+            self,
+            Self::StreamReadError(_) | Self::IncompleteDownload { .. }
+        )
+    }
+}
+
 impl std::error::Error for DownloadError {}
 
 impl From<io::Error> for DownloadError {
@@ -74,6 +108,67 @@ impl From<io::Error> for DownloadError {
     }
 }
 
+/// Fail with [`DownloadError::NoSpace`] unless `download_directory` has
+/// room for `size` bytes plus `margin`. Passes when the free space cannot
+/// be determined.
+pub fn check_disk_space(
+    download_directory: &str,
+    size: u64,
+    margin: u64,
+) -> Result<(), DownloadError> {
+    let needed = size.saturating_add(margin);
+    match available_space(&expand_tilde(download_directory)) {
+        Some(available) if available < needed => {
+            Err(DownloadError::NoSpace { needed, available })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The file `download` is saved as.
+pub fn download_destination(download: &Download) -> Option<PathBuf> {
+    DownloadPeer::resolve_download_path(download)
+        .ok()
+        .map(PathBuf::from)
+}
+
+/// Where a transfer is written, and from which byte of the file it starts.
+#[derive(Debug, PartialEq, Eq)]
+struct Target {
+    path: PathBuf,
+    offset: u64,
+}
+
+/// Apply `policy` to saving a `size`-byte download at `path`.
+fn plan_target(
+    path: PathBuf,
+    size: u64,
+    policy: ConflictPolicy,
+) -> Result<Target, DownloadError> {
+    let existing = fs::metadata(&path)
+        .ok()
+        .filter(fs::Metadata::is_file)
+        .map(|meta| meta.len());
+    match (existing, policy) {
+        (None, _) | (Some(_), ConflictPolicy::Overwrite) => {
+            Ok(Target { path, offset: 0 })
+        }
+        (Some(_), ConflictPolicy::Skip) => Err(DownloadError::FileExists(path)),
+        (Some(_), ConflictPolicy::Rename) => Ok(Target {
+            path: unused_path(&path),
+            offset: 0,
+        }),
+        // A whole file has nothing left to fetch, so the transfer completes
+        // straight away. One larger than the download isn't a part of it:
+        // keep it and save the download next to it.
+        (Some(len), ConflictPolicy::Resume) if len > size => Ok(Target {
+            path: unused_path(&path),
+            offset: 0,
+        }),
+        (Some(len), ConflictPolicy::Resume) => Ok(Target { path, offset: len }),
+    }
+}
+
 struct FileManager;
 
 impl FileManager {
@@ -84,19 +179,81 @@ impl FileManager {
             .unwrap_or(full_path)
     }
 
+    /// Only the last component of the peer's path is kept, sanitized, so
+    /// the file always lands directly in `output_directory`.
     fn create_download_path_from_filename(
-        output_directory: PathBuf,
+        output_directory: &Path,
         filename: &str,
-    ) -> PathBuf {
+    ) -> Result<PathBuf, DownloadError> {
         let filename_only = Self::extract_filename_from_path(filename);
-        output_directory.join(filename_only)
+        download_path(output_directory, filename_only).ok_or_else(|| {
+            DownloadError::PathResolutionError(format!(
+                "No room for a file name in: {}",
+                output_directory.display()
+            ))
+        })
+    }
+}
+
+/// Where a transfer goes as it arrives: the target file, or the writer the
+/// download was started with.
+enum Sink {
+    File(BufWriter<File>),
+    Writer(DownloadWriter),
+}
+
+impl Sink {
+    /// Open `target` for writing: appended to the existing part when
+    /// resuming, replacing whatever is there otherwise.
+    fn open(target: &Target) -> Result<Self, DownloadError> {
+        if let Some(parent) = target.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(DownloadError::FileWriteError)?;
+        }
+        let file = if target.offset > 0 {
+            OpenOptions::new().append(true).open(&target.path)
+        } else {
+            File::create(&target.path)
+        }
+        .map_err(DownloadError::FileWriteError)?;
+        Ok(Self::File(BufWriter::new(file)))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), DownloadError> {
+        match self {
+            Self::File(file) => file.write_all(data),
+            Self::Writer(writer) => writer
+                .lock()
+                .map_err(|_| DownloadError::LockPoisoned)?
+                .write_all(data),
+        }
+        .map_err(DownloadError::FileWriteError)
+    }
+
+    /// Flush what's written. A file is then hashed whole, resumed part
+    /// included; a writer's data is gone, so it has no hash.
+    fn finish(self, target: &Target) -> Result<Option<String>, DownloadError> {
+        match self {
+            Self::File(mut file) => {
+                file.flush().map_err(DownloadError::FileWriteError)?;
+                drop(file);
+                Ok(fs::read(&target.path).ok().map(|data| md5_bytes(&data)))
+            }
+            Self::Writer(writer) => {
+                writer
+                    .lock()
+                    .map_err(|_| DownloadError::LockPoisoned)?
+                    .flush()
+                    .map_err(DownloadError::FileWriteError)?;
+                Ok(None)
+            }
+        }
     }
 }
 
 struct StreamProcessor {
     total_bytes: usize,
     received: bool,
-    buffer: Vec<u8>,
 }
 
 impl StreamProcessor {
@@ -104,13 +261,34 @@ impl StreamProcessor {
         Self {
             total_bytes: 0,
             received: false,
-            buffer: Vec::new(),
         }
     }
 
-    fn process_data_chunk(&mut self, data: &[u8]) {
-        self.buffer.extend_from_slice(data);
+    /// Count the part of `data` within `expected_size` as received and
+    /// return it. A peer that sends trailing bytes past the expected size
+    /// has them trimmed off.
+    fn process_data_chunk<'a>(
+        &mut self,
+        data: &'a [u8],
+        expected_size: usize,
+    ) -> &'a [u8] {
+        let wanted = expected_size.saturating_sub(self.total_bytes);
+        let data = &data[..data.len().min(wanted)];
         self.total_bytes += data.len();
+        data
+    }
+
+    /// Check the transfer against the size the peer promised. A peer that
+    /// closes the connection early yields fewer bytes than expected — that
+    /// must be reported as a failure, not as a truncated "completed" file.
+    const fn finish(&self, expected_size: usize) -> Result<(), DownloadError> {
+        if self.total_bytes < expected_size {
+            return Err(DownloadError::IncompleteDownload {
+                received: self.total_bytes,
+                expected: expected_size,
+            });
+        }
+        Ok(())
     }
 
     const fn should_continue(&self, expected_size: Option<usize>) -> bool {
@@ -122,6 +300,101 @@ impl StreamProcessor {
     }
 }
 
+/// How far back the rolling average speed looks.
+const AVERAGE_WINDOW: Duration = Duration::from_secs(10);
+
+/// `bytes` over `elapsed`, in bytes per second.
+fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 { bytes as f64 / secs } else { 0.0 }
+}
+
+/// The `InProgress` status for `received` of `total` bytes, given that
+/// `window_bytes` of them arrived during the last `window` and they have
+/// averaged `average` bytes per second lately.
+fn progress_status(
+    received: u64,
+    total: u64,
+    window_bytes: u64,
+    window: Duration,
+    average: f64,
+) -> DownloadStatus {
+    let speed = rate(window_bytes, window);
+    let remaining = total.saturating_sub(received);
+    let eta = (speed > 0.0)
+        .then(|| Duration::from_secs_f64(remaining as f64 / speed));
+    DownloadStatus::InProgress {
+        bytes_downloaded: received,
+        total_bytes: total,
+        speed_bytes_per_sec: speed,
+        average_speed_bytes_per_sec: average,
+        eta,
+    }
+}
+
+/// Emits a progress status at most once per `interval`, measuring speed over
+/// the bytes received since the previous one, and its rolling average over
+/// the last [`AVERAGE_WINDOW`].
+struct ProgressMeter {
+    interval: Duration,
+    /// When and at how many bytes the meter started and emitted each
+    /// status since, oldest first. Never empty.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl ProgressMeter {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            samples: VecDeque::from([(Instant::now(), 0)]),
+        }
+    }
+
+    /// Count from `received` bytes onward, e.g. a resumed file's offset.
+    fn start_at(&mut self, received: u64) {
+        self.samples.clear();
+        self.samples.push_back((Instant::now(), received));
+    }
+
+    fn sample(&mut self, received: u64, total: u64) -> Option<DownloadStatus> {
+        self.sample_at(Instant::now(), received, total)
+    }
+
+    fn sample_at(
+        &mut self,
+        now: Instant,
+        received: u64,
+        total: u64,
+    ) -> Option<DownloadStatus> {
+        let &(last, last_bytes) = self.samples.back()?;
+        let window = now.saturating_duration_since(last);
+        if window < self.interval {
+            return None;
+        }
+        // Drop samples while the next one is a window old too, so the
+        // average spans the whole window once the download is that old.
+        while self.samples.len() > 1
+            && now.saturating_duration_since(self.samples[1].0)
+                >= AVERAGE_WINDOW
+        {
+            self.samples.pop_front();
+        }
+        let &(first, first_bytes) = self.samples.front()?;
+        let average = rate(
+            received.saturating_sub(first_bytes),
+            now.saturating_duration_since(first),
+        );
+        self.samples.push_back((now, received));
+        Some(progress_status(
+            received,
+            total,
+            received.saturating_sub(last_bytes),
+            window,
+            average,
+        ))
+    }
+}
+
 pub struct DownloadPeer {
     username: String,
     host: String,
@@ -152,7 +425,10 @@ impl DownloadPeer {
         }
     }
 
-    fn establish_connection(&self) -> Result<TcpStream, DownloadError> {
+    fn establish_connection(
+        &self,
+        tls: Option<&PeerTlsSettings>,
+    ) -> Result<PeerStream, DownloadError> {
         let socket_address = format!("{}:{}", self.host, self.port)
             .to_socket_addrs()
             .map_err(DownloadError::ConnectionFailed)?
@@ -164,11 +440,9 @@ impl DownloadPeer {
                 ))
             })?;
 
-        let stream = TcpStream::connect_timeout(
-            &socket_address,
-            Duration::from_secs(20),
-        )
-        .map_err(DownloadError::ConnectionFailed)?;
+        let stream =
+            PeerStream::connect(socket_address, Duration::from_secs(20), tls)
+                .map_err(DownloadError::ConnectionFailed)?;
 
         stream
             .set_read_timeout(Some(Duration::from_secs(30)))
@@ -185,7 +459,7 @@ impl DownloadPeer {
 
     fn perform_handshake(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), DownloadError> {
         trace!(
             "[download_peer:{}] performing handshake no_pierce: {}",
@@ -212,7 +486,6 @@ impl DownloadPeer {
     fn handle_pierce_firewall_response(
         &self,
         data: &[u8],
-        stream: &mut TcpStream,
         client_context: &Arc<RwLock<ClientContext>>,
     ) -> Result<Download, DownloadError> {
         let token_bytes =
@@ -227,10 +500,6 @@ impl DownloadPeer {
             self.username, token_u32
         );
 
-        stream
-            .write_all(&START_DOWNLOAD)
-            .map_err(DownloadError::StreamWriteError)?;
-
         let client_guard = client_context
             .read()
             .map_err(|_| DownloadError::LockPoisoned)?;
@@ -241,43 +510,112 @@ impl DownloadPeer {
         download_info.ok_or(DownloadError::TokenNotFound(token_u32))
     }
 
+    /// Decide where `download` is saved, refuse it if the directory has no
+    /// room, open it, then ask the peer to start sending from the target's
+    /// offset.
+    fn start_transfer(
+        stream: &mut PeerStream,
+        client_context: &Arc<RwLock<ClientContext>>,
+        download: &Download,
+    ) -> Result<(Target, Sink), DownloadError> {
+        let (margin, policy, writer) = {
+            let context = client_context
+                .read()
+                .map_err(|_| DownloadError::LockPoisoned)?;
+            // Cancelled while queued: leave any file as it is.
+            if context
+                .get_download_by_token(download.token)
+                .is_some_and(Download::is_aborted)
+            {
+                return Err(DownloadError::Aborted);
+            }
+            // A restored download carries on from what's already on disk.
+            let policy = if context.is_resuming(download) {
+                ConflictPolicy::Resume
+            } else {
+                context.conflict_policy
+            };
+            (
+                context.disk_space_margin,
+                policy,
+                context.download_writer(download),
+            )
+        };
+        let (target, sink) = if let Some(writer) = writer {
+            let target = Target {
+                path: PathBuf::new(),
+                offset: 0,
+            };
+            (target, Sink::Writer(writer))
+        } else {
+            let path = PathBuf::from(Self::resolve_download_path(download)?);
+            let target = plan_target(path, download.size, policy)?;
+            check_disk_space(
+                &download.download_directory,
+                download.size - target.offset,
+                margin,
+            )?;
+            let sink = Sink::open(&target)?;
+            (target, sink)
+        };
+        stream
+            .write_all(&target.offset.to_le_bytes())
+            .map_err(DownloadError::StreamWriteError)?;
+        Self::send_download_status(
+            client_context,
+            download,
+            DownloadStatus::InProgress {
+                bytes_downloaded: target.offset,
+                total_bytes: download.size,
+                speed_bytes_per_sec: 0.0,
+                average_speed_bytes_per_sec: 0.0,
+                eta: None,
+            },
+        );
+        Ok((target, sink))
+    }
+
     fn read_download_stream(
         &self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
         client_context: &Arc<RwLock<ClientContext>>,
         mut download: Option<Download>,
-    ) -> Result<(Vec<u8>, Download), DownloadError> {
+    ) -> Result<(Download, Target, Sink, usize), DownloadError> {
         let mut processor = StreamProcessor::new();
         let mut read_buffer = [1u8; READ_BUFFER_SIZE];
-        let mut chunk_counter = 0;
-        let start_time = Instant::now();
-        let mut last_update_time = start_time;
+        let (progress_interval, metrics) = {
+            let context = client_context
+                .read()
+                .map_err(|_| DownloadError::LockPoisoned)?;
+            (context.progress_interval, context.metrics().clone())
+        };
+        let mut meter = ProgressMeter::new(progress_interval);
 
         trace!(
             "[download_peer:{}] Starting to read data from peer",
             self.username
         );
 
-        if download.is_some() {
-            stream
-                .write_all(&START_DOWNLOAD)
-                .map_err(DownloadError::StreamWriteError)?;
-            if let Some(ref dl) = download {
-                Self::send_download_status(
-                    client_context,
-                    dl,
-                    DownloadStatus::InProgress {
-                        bytes_downloaded: 0,
-                        total_bytes: dl.size,
-                        speed_bytes_per_sec: 0.0,
-                    },
-                );
+        let (mut target, mut sink) = match download {
+            Some(ref dl) => {
+                let (target, sink) =
+                    Self::start_transfer(stream, client_context, dl)?;
+                (Some(target), Some(sink))
             }
-        }
+            None => (None, None),
+        };
+        let offset =
+            |target: &Option<Target>| target.as_ref().map_or(0, |t| t.offset);
+        meter.start_at(offset(&target));
 
         loop {
             if let Some(ref dl) = download {
                 Self::wait_while_paused(client_context, dl)?;
+                // A resumed file may have nothing left to fetch.
+                let remaining = (dl.size - offset(&target)) as usize;
+                if !processor.should_continue(Some(remaining)) {
+                    break;
+                }
             }
 
             match stream.read(&mut read_buffer) {
@@ -295,60 +633,47 @@ impl DownloadPeer {
                         let new_download = self
                             .handle_pierce_firewall_response(
                                 data,
-                                stream,
                                 client_context,
                             )?;
                         trace!(
                             "[download_peer:{}] got download info for token: {} - filename: {}",
                             self.username, self.token, new_download.filename
                         );
+                        let (started, opened) = Self::start_transfer(
+                            stream,
+                            client_context,
+                            &new_download,
+                        )?;
+                        meter.start_at(started.offset);
+                        target = Some(started);
+                        sink = Some(opened);
                         download = Some(new_download);
                         processor.received = true;
-                        if let Some(ref dl) = download {
-                            Self::send_download_status(
-                                client_context,
-                                dl,
-                                DownloadStatus::InProgress {
-                                    bytes_downloaded: 0,
-                                    total_bytes: dl.size,
-                                    speed_bytes_per_sec: 0.0,
-                                },
-                            );
-                        }
                         continue;
                     }
 
-                    processor.process_data_chunk(data);
-                    chunk_counter += 1;
+                    metrics.add_downloaded(bytes_read as u64);
+                    let expected_size = download
+                        .as_ref()
+                        .ok_or(DownloadError::DownloadInfoMissing(self.token))?
+                        .size
+                        - offset(&target);
+                    let data = processor
+                        .process_data_chunk(data, expected_size as usize);
+                    sink.as_mut()
+                        .ok_or(DownloadError::DownloadInfoMissing(self.token))?
+                        .write(data)?;
 
+                    let received =
+                        offset(&target) + processor.total_bytes as u64;
                     if let Some(ref dl) = download
-                        && chunk_counter % PROGRESS_UPDATE_CHUNKS == 0
+                        && let Some(status) = meter.sample(received, dl.size)
                     {
-                        let elapsed = last_update_time.elapsed().as_secs_f64();
-                        let bytes_since_last_update =
-                            PROGRESS_UPDATE_CHUNKS * READ_BUFFER_SIZE;
-                        let speed = if elapsed > 0.0 {
-                            bytes_since_last_update as f64 / elapsed
-                        } else {
-                            0.0
-                        };
-
-                        let status = DownloadStatus::InProgress {
-                            bytes_downloaded: processor.total_bytes as u64,
-                            total_bytes: dl.size,
-                            speed_bytes_per_sec: speed,
-                        };
                         Self::send_download_status(client_context, dl, status);
-
-                        last_update_time = Instant::now();
                     }
 
-                    let expected_size = download
-                        .as_ref()
-                        .ok_or(DownloadError::DownloadInfoMissing(self.token))?
-                        .size as usize;
-
-                    if !processor.should_continue(Some(expected_size)) {
+                    if !processor.should_continue(Some(expected_size as usize))
+                    {
                         break;
                     }
                 }
@@ -365,13 +690,14 @@ impl DownloadPeer {
 
         let download =
             download.ok_or(DownloadError::DownloadInfoMissing(self.token))?;
+        let target =
+            target.ok_or(DownloadError::DownloadInfoMissing(self.token))?;
+        let sink =
+            sink.ok_or(DownloadError::DownloadInfoMissing(self.token))?;
 
-        let buffer = Self::finalize_download_buffer(
-            processor.buffer,
-            download.size as usize,
-        )?;
+        processor.finish((download.size - target.offset) as usize)?;
 
-        Ok((buffer, download))
+        Ok((download, target, sink, processor.total_bytes))
     }
 
     fn send_download_status(
@@ -385,6 +711,8 @@ impl DownloadPeer {
         }
     }
 
+    /// Hold the transfer while `download` is paused, and stop it once it
+    /// is cancelled.
     fn wait_while_paused(
         client_context: &Arc<RwLock<ClientContext>>,
         download: &Download,
@@ -397,8 +725,12 @@ impl DownloadPeer {
                 .map(|download| download.status.clone())
                 .ok_or(DownloadError::TokenNotFound(download.token))?;
 
-            if !matches!(status, DownloadStatus::Paused { .. }) {
-                return Ok(());
+            match status {
+                DownloadStatus::Paused { .. } => {}
+                DownloadStatus::Failed(Some(FailureReason::Aborted)) => {
+                    return Err(DownloadError::Aborted);
+                }
+                _ => return Ok(()),
             }
 
             thread::sleep(Duration::from_millis(200));
@@ -424,9 +756,9 @@ impl DownloadPeer {
         }
 
         let final_path = FileManager::create_download_path_from_filename(
-            expanded_path,
+            &expanded_path,
             &download.filename,
-        );
+        )?;
 
         final_path
             .to_str()
@@ -439,45 +771,16 @@ impl DownloadPeer {
             .map(String::from)
     }
 
-    /// Validate and normalize a fully-read download buffer against the size the
-    /// peer promised in the transfer request. A peer that closes the connection
-    /// early yields fewer bytes than expected — that must be reported as a
-    /// failure, not silently saved as a truncated "completed" file. A peer that
-    /// sends trailing bytes past the expected size has them trimmed off.
-    fn finalize_download_buffer(
-        mut buffer: Vec<u8>,
-        expected_size: usize,
-    ) -> Result<Vec<u8>, DownloadError> {
-        if buffer.len() < expected_size {
-            return Err(DownloadError::IncompleteDownload {
-                received: buffer.len(),
-                expected: expected_size,
-            });
-        }
-        buffer.truncate(expected_size);
-        Ok(buffer)
-    }
-
-    fn save_downloaded_file(
-        path: &str,
-        data: &[u8],
-    ) -> Result<(), DownloadError> {
-        if let Some(parent) = Path::new(path).parent() {
-            fs::create_dir_all(parent)
-                .map_err(DownloadError::FileWriteError)?;
-        }
-
-        fs::write(path, data).map_err(DownloadError::FileWriteError)?;
-
-        Ok(())
-    }
-
     pub fn download_file(
         self,
         client_context: Arc<RwLock<ClientContext>>,
         download: Option<Download>,
-        stream: Option<TcpStream>,
-    ) -> Result<(Download, String), DownloadError> {
+        stream: Option<PeerStream>,
+    ) -> Result<(Download, DownloadSummary), DownloadError> {
+        let _span = crate::utils::logger::download_span(
+            &self.username,
+            download.as_ref().map_or(self.token, |dl| dl.token),
+        );
         trace!(
             "[download_peer:{}] download_file: download is present?: {:?}, stream is present?: {:?}, no_pierce: {}",
             self.username,
@@ -487,16 +790,30 @@ impl DownloadPeer {
         );
 
         if let Some(ref dl) = download {
-            let _ = dl.sender.send(DownloadStatus::Queued);
-            client_context
+            let mut context = client_context
                 .write()
-                .map_err(|_| DownloadError::LockPoisoned)?
+                .map_err(|_| DownloadError::LockPoisoned)?;
+            if context
+                .get_download_by_token(dl.token)
+                .is_some_and(Download::is_aborted)
+            {
+                return Err(DownloadError::Aborted);
+            }
+            let _ = dl.sender.send(DownloadStatus::Queued);
+            context
                 .update_download_with_status(dl.token, DownloadStatus::Queued);
         }
 
-        let mut stream = match stream {
-            Some(s) => s,
-            None => self.establish_connection()?,
+        let started = Instant::now();
+        let mut stream = if let Some(s) = stream {
+            s
+        } else {
+            let tls = client_context
+                .read()
+                .map_err(|_| DownloadError::LockPoisoned)?
+                .peer_tls
+                .clone();
+            self.establish_connection(tls.as_deref())?
         };
 
         trace!("[download_peer:{}] connected", self.username);
@@ -504,35 +821,204 @@ impl DownloadPeer {
         self.perform_handshake(&mut stream)?;
         trace!("[download_peer:{}] handshake completed", self.username);
 
-        let (buffer, download) =
+        let (download, target, sink, received) =
             self.read_download_stream(&mut stream, &client_context, download)?;
-
-        let final_path = Self::resolve_download_path(&download)?;
-        Self::save_downloaded_file(&final_path, &buffer)?;
+        let md5 = sink.finish(&target)?;
 
         trace!(
-            "[download_peer:{}] download completed successfully: {} bytes, saved to: {}",
+            "[download_peer:{}] download completed successfully: {} bytes from offset {}, saved to: {}",
             self.username,
-            buffer.len(),
-            final_path
+            received,
+            target.offset,
+            target.path.display()
         );
 
-        Ok((download, final_path))
+        let mut summary = DownloadSummary::new(
+            target.path,
+            received as u64,
+            started.elapsed(),
+        );
+        if let Some(md5) = md5 {
+            summary = summary.with_md5(md5);
+        }
+        Ok((download, summary))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DownloadError, DownloadPeer, FileManager};
+    use super::{
+        ConflictPolicy, DownloadError, DownloadPeer, DownloadStatus,
+        FileManager, ProgressMeter, Sink, StreamProcessor, Target,
+        check_disk_space, plan_target, progress_status,
+    };
+    use crate::client::ClientContext;
+    use crate::types::{Download, DownloadMetadata};
+    use std::io::{Read, Write};
+    use std::net::{Ipv4Addr, TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex, RwLock, mpsc};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Collects what a download writes, for the test to look at.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transfers_are_written_to_the_download_writer_as_they_arrive() {
+        let data: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sent = data.clone();
+        let peer = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut offset = [0u8; 8];
+            stream.read_exact(&mut offset).unwrap();
+            assert_eq!(u64::from_le_bytes(offset), 0);
+            // In pieces, with trailing bytes the download must drop.
+            for piece in sent.chunks(7_000) {
+                stream.write_all(piece).unwrap();
+                stream.flush().unwrap();
+            }
+            // The client may have hung up already.
+            let _ = stream.write_all(b"extra");
+        });
+
+        let (sender, _statuses) = mpsc::channel();
+        let download = Download {
+            username: "peer".to_string(),
+            filename: "song.mp3".to_string(),
+            token: 7,
+            size: data.len() as u64,
+            download_directory: String::new(),
+            status: DownloadStatus::Queued,
+            sender,
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        };
+        let written = Shared::default();
+        let mut context = ClientContext::new();
+        context.add_download(download.clone());
+        context.add_download_writer(
+            "peer".to_string(),
+            "song.mp3".to_string(),
+            written.clone(),
+        );
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        let (_, summary) = DownloadPeer::new(
+            "peer".to_string(),
+            "127.0.0.1".to_string(),
+            u32::from(port),
+            7,
+            true,
+            "me".to_string(),
+        )
+        .download_file(
+            Arc::new(RwLock::new(context)),
+            Some(download),
+            Some(stream.into()),
+        )
+        .unwrap();
+        peer.join().unwrap();
+
+        assert_eq!(*written.0.lock().unwrap(), data);
+        assert_eq!(summary.path.as_os_str(), "");
+        assert_eq!(summary.md5, None);
+    }
+
+    #[test]
+    fn progress_reports_speed_and_eta_over_the_window() {
+        let status =
+            progress_status(500, 1500, 250, Duration::from_millis(500), 400.0);
+        match status {
+            DownloadStatus::InProgress {
+                bytes_downloaded,
+                total_bytes,
+                speed_bytes_per_sec,
+                average_speed_bytes_per_sec,
+                eta,
+            } => {
+                assert_eq!(bytes_downloaded, 500);
+                assert_eq!(total_bytes, 1500);
+                assert!((speed_bytes_per_sec - 500.0).abs() < f64::EPSILON);
+                assert!(
+                    (average_speed_bytes_per_sec - 400.0).abs() < f64::EPSILON
+                );
+                assert_eq!(eta, Some(Duration::from_secs(2)));
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn progress_without_a_speed_has_no_eta() {
+        let status =
+            progress_status(0, 100, 0, Duration::from_millis(500), 0.0);
+        assert!(matches!(
+            status,
+            DownloadStatus::InProgress { eta: None, .. }
+        ));
+    }
+
+    #[test]
+    fn meter_waits_for_the_interval() {
+        let mut meter = ProgressMeter::new(Duration::from_mins(1));
+        assert!(meter.sample(100, 1000).is_none());
+        let mut meter = ProgressMeter::new(Duration::ZERO);
+        assert!(meter.sample(100, 1000).is_some());
+    }
+
+    #[test]
+    fn meter_averages_over_the_last_window() {
+        let speeds = |status: Option<DownloadStatus>| match status {
+            Some(DownloadStatus::InProgress {
+                speed_bytes_per_sec,
+                average_speed_bytes_per_sec,
+                ..
+            }) => (speed_bytes_per_sec, average_speed_bytes_per_sec),
+            other => panic!("unexpected: {other:?}"),
+        };
+        let mut meter = ProgressMeter::new(Duration::from_secs(1));
+        meter.start_at(1000);
+        let start = meter.samples[0].0;
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // 100 B/s for five seconds, then a one-second burst.
+        for secs in 1..=5 {
+            meter.sample_at(at(secs), 1000 + secs * 100, 1_000_000);
+        }
+        let (now, average) = speeds(meter.sample_at(at(6), 2100, 1_000_000));
+        assert!((now - 600.0).abs() < f64::EPSILON);
+        assert!((average - 1100.0 / 6.0).abs() < 1e-9);
+
+        // Stalled since: the burst ages out of the window.
+        for secs in 7..=16 {
+            meter.sample_at(at(secs), 2100, 1_000_000);
+        }
+        let (now, average) = speeds(meter.sample_at(at(17), 2100, 1_000_000));
+        assert!(now.abs() < f64::EPSILON);
+        assert!(average.abs() < f64::EPSILON);
+    }
 
     #[test]
     fn finalize_rejects_truncated_download() {
         // Peer closed early: 5 of 10 promised bytes. Must be a failure so the
         // partial file is never reported as Completed.
-        let result =
-            DownloadPeer::finalize_download_buffer(vec![1, 2, 3, 4, 5], 10);
+        let mut processor = StreamProcessor::new();
+        processor.process_data_chunk(&[1, 2, 3, 4, 5], 10);
         assert!(matches!(
-            result,
+            processor.finish(10),
             Err(DownloadError::IncompleteDownload {
                 received: 5,
                 expected: 10
@@ -543,16 +1029,19 @@ mod tests {
     #[test]
     fn finalize_trims_overshoot_to_expected_size() {
         // Peer sent 12 bytes for a 10-byte file (trailing bytes coalesced in).
-        let result =
-            DownloadPeer::finalize_download_buffer((0..12).collect(), 10);
-        assert_eq!(result.unwrap(), (0..10).collect::<Vec<u8>>());
+        let mut processor = StreamProcessor::new();
+        let data: Vec<u8> = (0..12).collect();
+        assert_eq!(processor.process_data_chunk(&data[..6], 10), &data[..6]);
+        assert_eq!(processor.process_data_chunk(&data[6..], 10), &data[6..10]);
+        assert!(processor.finish(10).is_ok());
     }
 
     #[test]
     fn finalize_accepts_exact_size() {
-        let result =
-            DownloadPeer::finalize_download_buffer((0..10).collect(), 10);
-        assert_eq!(result.unwrap(), (0..10).collect::<Vec<u8>>());
+        let mut processor = StreamProcessor::new();
+        let data: Vec<u8> = (0..10).collect();
+        assert_eq!(processor.process_data_chunk(&data, 10), &data[..]);
+        assert!(processor.finish(10).is_ok());
     }
 
     #[test]
@@ -565,7 +1054,7 @@ mod tests {
             false,
             "own_user".to_string(),
         );
-        let result = download_peer.establish_connection();
+        let result = download_peer.establish_connection(None);
         assert!(result.is_err());
     }
 
@@ -590,4 +1079,76 @@ mod tests {
             "file.mp3"
         );
     }
+
+    #[test]
+    fn conflict_policy_picks_the_target_and_offset() {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-conflict-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("song.flac");
+        let plan = |policy| plan_target(path.clone(), 10, policy);
+
+        // Nothing there yet: every policy writes the file from the start.
+        assert_eq!(
+            plan(ConflictPolicy::Skip).unwrap(),
+            Target {
+                path: path.clone(),
+                offset: 0
+            }
+        );
+
+        std::fs::write(&path, b"1234").unwrap();
+        assert_eq!(plan(ConflictPolicy::Overwrite).unwrap().offset, 0);
+        assert!(matches!(
+            plan(ConflictPolicy::Skip),
+            Err(DownloadError::FileExists(_))
+        ));
+        assert_eq!(
+            plan(ConflictPolicy::Rename).unwrap().path,
+            dir.join("song (1).flac")
+        );
+        let resumed = plan(ConflictPolicy::Resume).unwrap();
+        assert_eq!(resumed.offset, 4);
+        let mut sink = Sink::open(&resumed).unwrap();
+        sink.write(b"567890").unwrap();
+        sink.finish(&resumed).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"1234567890");
+        // Already whole: nothing left to fetch, and the file is kept.
+        assert_eq!(plan(ConflictPolicy::Resume).unwrap().offset, 10);
+        // Larger than the download: left alone, saved next to it instead.
+        std::fs::write(&path, b"1234567890abc").unwrap();
+        assert_eq!(
+            plan(ConflictPolicy::Resume).unwrap(),
+            Target {
+                path: dir.join("song (1).flac"),
+                offset: 0
+            }
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn downloads_larger_than_the_free_space_are_refused() {
+        let dir = std::env::temp_dir();
+        let dir = dir.to_str().unwrap();
+        assert!(check_disk_space(dir, 1, 0).is_ok());
+        if cfg!(target_os = "linux") {
+            assert!(matches!(
+                check_disk_space(dir, u64::MAX / 2, u64::MAX / 2),
+                Err(DownloadError::NoSpace { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn download_path_stays_in_the_output_directory() {
+        let dir = std::path::Path::new("/music");
+        for remote in ["@@peer\\..", "..\\..\\etc\\passwd", "a\\b\\"] {
+            let path =
+                FileManager::create_download_path_from_filename(dir, remote)
+                    .unwrap();
+            assert_eq!(path.parent(), Some(dir), "{remote}");
+        }
+    }
 }
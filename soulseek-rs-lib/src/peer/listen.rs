@@ -1,24 +1,33 @@
 use std::io;
 use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, RwLock};
 use std::thread;
+use std::time::Duration;
 
-use crate::client::{ClientContext, ClientOperation};
+use crate::client::{Client, ClientContext, ClientOperation, Readiness};
 
-use crate::message::{Message, MessageReader};
+use crate::message::{
+    DEFAULT_MAX_MESSAGE_SIZE, Message, MessageReader, PeerInitMessage,
+};
 use crate::peer::{ConnectionType, DownloadPeer, Peer};
+use crate::transport::{PeerStream, PeerTlsSettings};
 use crate::types::Download;
 use crate::utils::lock::RwLockExt;
 use crate::{DownloadStatus, debug, error, info, trace};
 
-const PEER_INIT_MESSAGE_CODE: u8 = 1;
+/// How long an accepted connection waits for the client to become ready
+/// before it is dropped (login failing, or taking unusually long).
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
 struct ConnectionContext {
     client_sender: Sender<ClientOperation>,
     client_context: Arc<RwLock<ClientContext>>,
     own_username: String,
+    max_message_size: usize,
+    tls: Option<Arc<PeerTlsSettings>>,
 }
 
 struct PeerInitData {
@@ -28,35 +37,33 @@ struct PeerInitData {
 }
 
 fn read_peer_init_message(
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     reader: &mut MessageReader,
 ) -> io::Result<Message> {
     loop {
         reader.read_from_socket(stream)?;
 
-        if let Ok(Some(msg)) = reader.extract_message() {
+        if let Some(msg) = reader.extract_message()? {
             return Ok(msg);
         }
     }
 }
 
 fn parse_peer_init_message(mut message: Message) -> Option<PeerInitData> {
-    message.set_pointer(4);
-    let message_code = message.read_int8();
-
-    if message_code != PEER_INIT_MESSAGE_CODE {
-        return None;
+    // An untrusted peer can send any connection-type string; decoding rejects
+    // an unknown value instead of panicking the listener accept loop.
+    match PeerInitMessage::decode(&mut message).ok()? {
+        PeerInitMessage::PeerInit {
+            username,
+            connection_type,
+            token,
+        } => Some(PeerInitData {
+            username,
+            connection_type,
+            token,
+        }),
+        PeerInitMessage::PierceFirewall { .. } => None,
     }
-
-    let username = message.read_string();
-    // An untrusted peer can send any connection-type string; an unknown value
-    // must be rejected, not panic the listener accept loop.
-    let connection_type = message.read_string().parse().ok()?;
-    Some(PeerInitData {
-        username,
-        connection_type,
-        token: message.read_int32(),
-    })
 }
 
 fn parse_token_from_buffer(buffer: &[u8], username: &str) -> Option<u32> {
@@ -108,7 +115,7 @@ fn extract_download_from_buffer(
 
 fn handle_peer_connection(
     peer: Peer,
-    stream: TcpStream,
+    stream: PeerStream,
     reader: MessageReader,
     context: &ConnectionContext,
     _peer_ip: &str,
@@ -138,7 +145,7 @@ fn handle_peer_connection(
             Ok(_) => (),
             Err(e) => {
                 error!(
-                    "Failed to spawn peer actor for {:?}: {:?}",
+                    "Failed to spawn peer actor for {:?}: {}",
                     peer.username, e
                 );
             }
@@ -150,7 +157,7 @@ fn handle_peer_connection(
 
 fn handle_file_connection(
     peer: Peer,
-    stream: TcpStream,
+    stream: PeerStream,
     mut reader: MessageReader,
     token: u32,
     context: &ConnectionContext,
@@ -185,34 +192,47 @@ fn handle_file_connection(
         download,
         Some(stream),
     ) {
-        Ok((download, filename)) => {
-            let _ = download.sender.send(DownloadStatus::Completed);
+        Ok((download, summary)) => {
+            info!(
+                "Successfully downloaded {} bytes to {} in {:.1}s ({:.0} B/s)",
+                download.size,
+                summary.path.display(),
+                summary.elapsed.as_secs_f64(),
+                summary.average_speed_bytes_per_sec
+            );
+            let status = DownloadStatus::Completed(Some(summary));
+            let _ = download.sender.send(status.clone());
             match context.client_context.write_safe() {
-                Ok(mut ctx) => ctx.update_download_with_status(
-                    download.token,
-                    DownloadStatus::Completed,
-                ),
+                Ok(mut ctx) => {
+                    ctx.update_download_with_status(download.token, status);
+                }
                 Err(e) => {
                     error!("[listener] handle_file_connection write: {}", e);
                 }
             }
-            info!(
-                "Successfully downloaded {} bytes to {}",
-                download.size, filename
-            );
         }
         Err(e) => {
             error!(
                 "Failed to download file from {}:{} (token: {}) - Error: {}",
                 peer.host, peer.port, token, e
             );
+            // A transfer that broke off is searched for elsewhere instead.
+            if let Some(failure_token) = failure_token
+                && e.broke_off()
+                && Client::replace_broken_transfer(
+                    &context.client_context,
+                    failure_token,
+                )
+            {
+                return;
+            }
             // A failed incoming transfer (e.g. a truncated/incomplete download)
             // must not leave the download stuck as Queued/InProgress forever.
             if let Some(failure_token) = failure_token {
                 match context.client_context.write_safe() {
                     Ok(mut ctx) => ctx.update_download_with_status(
                         failure_token,
-                        DownloadStatus::Failed(Some(e.to_string())),
+                        DownloadStatus::Failed(Some(e.to_string().into())),
                     ),
                     Err(e) => {
                         error!(
@@ -232,14 +252,20 @@ fn handle_file_connection(
 /// connection and tell the client the connection is live.
 fn handle_pierce_firewall(
     mut message: Message,
-    stream: TcpStream,
+    stream: PeerStream,
     reader: MessageReader,
     context: &ConnectionContext,
     peer_ip: &str,
     peer_port: u16,
 ) {
-    message.set_pointer(5); // skip length prefix (4) + int8 code (1)
-    let token = message.read_int32();
+    let Ok(PeerInitMessage::PierceFirewall { token }) =
+        PeerInitMessage::decode(&mut message)
+    else {
+        debug!(
+            "[listener:{peer_ip}:{peer_port}] PierceFirewall without a token; ignoring"
+        );
+        return;
+    };
 
     let username = match context.client_context.write_safe() {
         Ok(mut ctx) => ctx.take_pending_connect(token),
@@ -282,8 +308,15 @@ fn handle_incoming_connection(stream: TcpStream, context: ConnectionContext) {
 
     let peer_ip = peer_addr.ip().to_string();
     let peer_port = peer_addr.port();
-    let mut stream = stream;
-    let mut reader = MessageReader::new();
+    let mut stream = match PeerStream::accept(stream, context.tls.as_deref()) {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("[listener:{peer_ip}:{peer_port}] TLS handshake: {}", e);
+            return;
+        }
+    };
+    let mut reader =
+        MessageReader::new().with_max_size(context.max_message_size);
 
     let Ok(message) = read_peer_init_message(&mut stream, &mut reader) else {
         error!(
@@ -362,19 +395,33 @@ impl Listen {
         client_sender: Sender<ClientOperation>,
         client_context: Arc<RwLock<ClientContext>>,
         own_username: String,
+        readiness: &Readiness,
+        stop: &AtomicBool,
     ) {
         info!("[listener] starting listener on port {port}");
 
         let listener = TcpListener::bind(format!("0.0.0.0:{port}"))
             .expect("Failed to bind listener to port");
 
+        let (max_message_size, tls) = client_context
+            .read_safe()
+            .map_or((DEFAULT_MAX_MESSAGE_SIZE, None), |ctx| {
+                (ctx.message_size_limits.peer, ctx.peer_tls.clone())
+            });
         let context = ConnectionContext {
             client_sender,
             client_context,
             own_username,
+            max_message_size,
+            tls,
         };
 
         for stream in listener.incoming() {
+            // `Client::shutdown` connects to us to wake this loop.
+            if stop.load(Ordering::Acquire) {
+                info!("[listener] stopping listener on port {port}");
+                break;
+            }
             let Ok(stream) = stream else {
                 error!(
                     "[listener] Failed to accept connection: {}",
@@ -383,6 +430,16 @@ impl Listen {
                 continue;
             };
 
+            // Peers can connect while login is still in flight; hold them
+            // until the registry and operations loop can take them.
+            if !readiness.wait_ready(READY_TIMEOUT) {
+                debug!(
+                    "[listener] client not ready after {:?}; dropping connection",
+                    READY_TIMEOUT
+                );
+                continue;
+            }
+
             let context = context.clone();
             handle_incoming_connection(stream, context);
         }
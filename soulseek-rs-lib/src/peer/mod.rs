@@ -1,5 +1,6 @@
 mod download_peer;
 pub mod listen;
+pub mod parent;
 pub mod upload_peer;
 
 // Export actor types
@@ -7,10 +8,14 @@ pub use crate::actor::peer_actor::{PeerActor, PeerMessage};
 pub use crate::actor::peer_registry::PeerRegistry;
 
 pub use download_peer::DownloadPeer;
+pub(crate) use download_peer::{
+    DownloadError, check_disk_space, download_destination,
+};
 
-use crate::message::Message;
+use crate::message::{Message, wire::Wire};
+use crate::transport::PeerStream;
 use core::fmt;
-use std::{net::TcpStream, str::FromStr};
+use std::{net::Ipv4Addr, str::FromStr};
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -18,18 +23,18 @@ pub struct NewPeer {
     pub username: String,
     pub connection_type: ConnectionType,
     pub token: u32,
-    pub tcp_stream: TcpStream,
+    pub tcp_stream: PeerStream,
 }
 impl NewPeer {
     pub fn new_from_message(
         message: &mut Message,
-        tcp_stream: TcpStream,
-    ) -> Option<Self> {
-        let username = message.read_string();
-        let connection_type = message.read_string().parse().ok()?;
-        let token = message.read_int32();
+        tcp_stream: PeerStream,
+    ) -> crate::Result<Self> {
+        let username = message.try_read_string()?;
+        let connection_type = message.try_read_string()?.parse()?;
+        let token = message.try_read_int32()?;
 
-        Some(Self {
+        Ok(Self {
             username,
             connection_type,
             token,
@@ -38,7 +43,7 @@ impl NewPeer {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConnectionType {
     P,
     F,
@@ -56,6 +61,12 @@ impl fmt::Display for ParseConnectionTypeError {
 
 impl std::error::Error for ParseConnectionTypeError {}
 
+impl From<ParseConnectionTypeError> for crate::SoulseekRs {
+    fn from(err: ParseConnectionTypeError) -> Self {
+        Self::protocol(err.to_string())
+    }
+}
+
 impl FromStr for ConnectionType {
     type Err = ParseConnectionTypeError;
 
@@ -69,6 +80,15 @@ impl FromStr for ConnectionType {
     }
 }
 
+impl Wire for ConnectionType {
+    fn write_to(&self, message: &mut Message) {
+        message.write_string(&self.to_string());
+    }
+    fn read_from(message: &mut Message) -> crate::Result<Self> {
+        Ok(message.try_read_string()?.parse()?)
+    }
+}
+
 impl fmt::Display for ConnectionType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match self {
@@ -117,29 +137,29 @@ impl Peer {
         }
     }
     #[allow(dead_code)]
-    pub fn new_from_message(message: &mut Message) -> Option<Self> {
-        let username = message.read_string();
+    pub fn new_from_message(message: &mut Message) -> crate::Result<Self> {
+        let username = message.try_read_string()?;
         // The connection type is an untrusted string; an unknown value must not
         // panic the actor that parses it.
-        let connection_type = message.read_string().parse().ok()?;
+        let connection_type = message.try_read_string()?.parse()?;
 
         let ip = [
-            message.read_int8(),
-            message.read_int8(),
-            message.read_int8(),
-            message.read_int8(),
+            message.try_read_int8()?,
+            message.try_read_int8()?,
+            message.try_read_int8()?,
+            message.try_read_int8()?,
         ];
         let host = format!("{}.{}.{}.{}", ip[3], ip[2], ip[1], ip[0]);
 
         let (port, token, privileged, unknown, obfuscated_port) = (
-            message.read_int32(),
-            message.read_int32(),
-            message.read_int8(),
-            message.read_int8(),
-            message.read_int8(),
+            message.try_read_int32()?,
+            message.try_read_int32()?,
+            message.try_read_int8()?,
+            message.try_read_int8()?,
+            message.try_read_int8()?,
         );
 
-        Some(Self {
+        Ok(Self {
             username,
             connection_type,
             host,
@@ -151,6 +171,25 @@ impl Peer {
         })
     }
 }
+/// A `ConnectToPeer` from the server.
+impl Wire for Peer {
+    fn write_to(&self, message: &mut Message) {
+        let ip = self.host.parse().unwrap_or(Ipv4Addr::UNSPECIFIED);
+        message.write_string(&self.username);
+        self.connection_type.write_to(message);
+        ip.write_to(message);
+        message
+            .write_int32(self.port)
+            .write_int32(self.token.unwrap_or(0))
+            .write_int8(self.privileged.unwrap_or(0))
+            .write_int8(self.unknown.unwrap_or(0))
+            .write_int8(self.obfuscated_port.unwrap_or(0) as u8);
+    }
+    fn read_from(message: &mut Message) -> crate::Result<Self> {
+        Self::new_from_message(message)
+    }
+}
+
 #[test]
 fn new_accepts_a_full_range_obfuscated_port() {
     // A real obfuscated port is a u16; it must be stored without truncation.
@@ -170,7 +209,7 @@ fn new_accepts_a_full_range_obfuscated_port() {
 }
 
 #[test]
-fn new_from_message_returns_none_on_invalid_connection_type() {
+fn new_from_message_rejects_an_invalid_connection_type() {
     // username "ab", connection_type "X" (not P/F/D) from an untrusted server.
     let mut data: Vec<u8> = vec![0, 0, 0, 0, 0, 0, 0, 0];
     data.extend([2, 0, 0, 0, 97, 98]); // username = "ab"
@@ -182,7 +221,7 @@ fn new_from_message_returns_none_on_invalid_connection_type() {
     let mut message = Message::new_with_data(data);
     message.set_pointer(8);
 
-    assert!(Peer::new_from_message(&mut message).is_none());
+    assert!(Peer::new_from_message(&mut message).is_err());
 }
 
 #[test]
@@ -0,0 +1,253 @@
+//! Our connection to a distributed parent.
+//!
+//! The parent relays other users' searches down its branch to us, so we
+//! answer them without the server sending each one. A candidate is adopted
+//! once it tells us its branch level (and, below the top, its root); one that
+//! refuses the connection or stays silent is skipped for the next.
+
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::client::ClientOperation;
+use crate::message::server::{MessageFactory, ParentCandidate};
+use crate::message::{DistributedMessageIn, MessageReader};
+use crate::peer::ConnectionType;
+use crate::transport::{PeerStream, PeerTlsSettings};
+use crate::{debug, trace};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a candidate has to tell us its branch once connected.
+const BRANCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a read waits before checking whether to stop.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Try `candidates` in order until one takes us as a child.
+///
+/// Its searches are relayed to `client` until it disconnects or `stop` is
+/// set. Reports the branch as [`ClientOperation::ParentBranch`] and the end as
+/// [`ClientOperation::ParentGone`], both tagged with `attempt`. `tls` wraps
+/// the connections in TLS.
+pub fn join(
+    attempt: u64,
+    candidates: Vec<ParentCandidate>,
+    own_username: &str,
+    max_message_size: usize,
+    tls: Option<&PeerTlsSettings>,
+    client: &Sender<ClientOperation>,
+    stop: &AtomicBool,
+) {
+    for candidate in candidates {
+        if stop.load(Ordering::Acquire) {
+            break;
+        }
+        match connect(&candidate, own_username, tls) {
+            Ok(stream) => {
+                // Once adopted, a lost parent is replaced from the fresh
+                // candidates the server sends, not from this list.
+                let reader =
+                    MessageReader::new().with_max_size(max_message_size);
+                if serve(attempt, &candidate, stream, reader, client, stop) {
+                    break;
+                }
+            }
+            Err(e) => debug!(
+                "[parent:{}] cannot connect to {}:{}: {}",
+                candidate.username, candidate.ip, candidate.port, e
+            ),
+        }
+    }
+    let _ = client.send(ClientOperation::ParentGone { attempt });
+}
+
+fn connect(
+    candidate: &ParentCandidate,
+    own_username: &str,
+    tls: Option<&PeerTlsSettings>,
+) -> io::Result<PeerStream> {
+    let port = u16::try_from(candidate.port)
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mut stream = PeerStream::connect(
+        SocketAddr::from((candidate.ip, port)),
+        CONNECT_TIMEOUT,
+        tls,
+    )?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let init = MessageFactory::build_peer_init_message(
+        own_username,
+        ConnectionType::D,
+        0,
+    );
+    stream.write_all(&init.get_buffer())?;
+    Ok(stream)
+}
+
+/// Read from `candidate` until it closes or `stop` is set. Returns whether
+/// it was adopted as our parent.
+fn serve(
+    attempt: u64,
+    candidate: &ParentCandidate,
+    mut stream: PeerStream,
+    mut reader: MessageReader,
+    client: &Sender<ClientOperation>,
+    stop: &AtomicBool,
+) -> bool {
+    let connected_at = Instant::now();
+    let mut level = None;
+    let mut root = None;
+    let mut announced: Option<(u32, String)> = None;
+
+    while !stop.load(Ordering::Acquire) {
+        if announced.is_none() && connected_at.elapsed() >= BRANCH_TIMEOUT {
+            debug!("[parent:{}] sent no branch level", candidate.username);
+            return false;
+        }
+        let buffered = reader.buffer_len();
+        match reader.read_from_socket(&mut stream) {
+            // `read` returning nothing means the parent closed.
+            Ok(()) if reader.buffer_len() == buffered => break,
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                debug!("[parent:{}] read: {}", candidate.username, e);
+                break;
+            }
+        }
+        while let Ok(Some(mut message)) = reader.extract_message() {
+            match DistributedMessageIn::decode(&mut message) {
+                Ok(DistributedMessageIn::Search {
+                    username,
+                    token,
+                    query,
+                    ..
+                }) => {
+                    let _ = client.send(ClientOperation::IncomingSearch {
+                        username,
+                        token,
+                        query,
+                    });
+                }
+                Ok(DistributedMessageIn::BranchLevel(value)) => {
+                    level = Some(value);
+                }
+                Ok(DistributedMessageIn::BranchRoot(value)) => {
+                    root = Some(value);
+                }
+                Ok(
+                    DistributedMessageIn::Ping
+                    | DistributedMessageIn::ChildDepth(_),
+                ) => {}
+                Err(e) => trace!("[parent:{}] {}", candidate.username, e),
+            }
+            // At the top of its branch the parent is its own root.
+            let branch = match (level, &root) {
+                (Some(0), _) => Some((0, candidate.username.clone())),
+                (Some(level), Some(root)) => Some((level, root.clone())),
+                _ => None,
+            };
+            if let Some((level, root)) = &branch
+                && branch != announced
+            {
+                let _ = client.send(ClientOperation::ParentBranch {
+                    attempt,
+                    username: candidate.username.clone(),
+                    level: *level,
+                    root: root.clone(),
+                });
+                announced = branch;
+            }
+        }
+    }
+    announced.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::join;
+    use crate::client::ClientOperation;
+    use crate::message::server::ParentCandidate;
+    use crate::message::{
+        DEFAULT_MAX_MESSAGE_SIZE, DistributedMessageIn, PeerInitMessage,
+    };
+    use crate::peer::ConnectionType;
+    use crate::testing::MockPeerBuilder;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc;
+
+    fn candidate(username: &str, port: u16) -> ParentCandidate {
+        ParentCandidate {
+            username: username.into(),
+            ip: Ipv4Addr::LOCALHOST,
+            port: port.into(),
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_next_candidate_and_relays_searches() {
+        // Nothing listens on a port we just released.
+        let dead = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let parent = MockPeerBuilder::new("bob")
+            .greet(DistributedMessageIn::BranchLevel(2).encode())
+            .greet(DistributedMessageIn::BranchRoot("carol".into()).encode())
+            .greet(
+                DistributedMessageIn::Search {
+                    unknown: 49,
+                    username: "dave".into(),
+                    token: 77,
+                    query: "autechre".into(),
+                }
+                .encode(),
+            )
+            .hang_up()
+            .build()
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        join(
+            3,
+            vec![candidate("gone", dead), candidate("bob", parent.port())],
+            "me",
+            DEFAULT_MAX_MESSAGE_SIZE,
+            None,
+            &sender,
+            &AtomicBool::new(false),
+        );
+
+        assert!(matches!(
+            &parent.introductions()[..],
+            [PeerInitMessage::PeerInit {
+                username,
+                connection_type: ConnectionType::D,
+                ..
+            }] if username == "me"
+        ));
+        let ops: Vec<ClientOperation> = receiver.try_iter().collect();
+        assert!(matches!(
+            &ops[..],
+            [
+                ClientOperation::ParentBranch { attempt: 3, username, level: 2, root },
+                ClientOperation::IncomingSearch { username: searcher, token: 77, query },
+                ClientOperation::ParentGone { attempt: 3 },
+            ] if username == "bob"
+                && root == "carol"
+                && searcher == "dave"
+                && query == "autechre"
+        ));
+    }
+}
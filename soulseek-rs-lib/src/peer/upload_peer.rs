@@ -2,14 +2,21 @@
 
 use std::fs::File;
 use std::io::{self, Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::net::ToSocketAddrs;
 use std::path::Path;
+use std::sync::Mutex;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::message::server::MessageFactory;
 use crate::peer::ConnectionType;
 use crate::trace;
+use crate::transport::{PeerStream, PeerTlsSettings};
+use crate::utils::lock::MutexExt;
+use crate::utils::token_bucket::TokenBucket;
+
+/// Longest single sleep while throttled, so cancellation stays responsive.
+const THROTTLE_SLICE: Duration = Duration::from_millis(100);
 
 /// Connect to the downloader's file listener and stream `path`'s bytes.
 ///
@@ -19,10 +26,14 @@ use crate::trace;
 /// START_DOWNLOAD offset before we stream the file.
 ///
 /// `bytes_sent` is updated as the transfer progresses, and setting `cancel`
-/// aborts the stream with an [`io::ErrorKind::Interrupted`] error.
+/// aborts the stream with an [`io::ErrorKind::Interrupted`] error. When a
+/// `throttle` is given, every chunk is paid for from it before being sent, so
+/// uploads sharing one bucket share its bandwidth. `tls` wraps the
+/// connection in TLS.
 ///
 /// # Errors
 /// Returns any I/O error opening the file or talking to the peer.
+#[allow(clippy::too_many_arguments)]
 pub fn serve_file(
     host: &str,
     port: u32,
@@ -31,6 +42,8 @@ pub fn serve_file(
     path: &Path,
     bytes_sent: &AtomicU64,
     cancel: &AtomicBool,
+    throttle: Option<&Mutex<TokenBucket>>,
+    tls: Option<&PeerTlsSettings>,
 ) -> io::Result<()> {
     let mut file = File::open(path)?;
 
@@ -40,8 +53,7 @@ pub fn serve_file(
         .ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidInput, "no address")
         })?;
-    let mut stream =
-        TcpStream::connect_timeout(&socket, Duration::from_secs(20))?;
+    let mut stream = PeerStream::connect(socket, Duration::from_secs(20), tls)?;
     stream.set_nodelay(true).ok();
 
     // PeerInit(F) + the 4-byte token in a single write so they coalesce.
@@ -61,16 +73,18 @@ pub fn serve_file(
 
     let mut buffer = vec![0u8; 64 * 1024];
     loop {
-        if cancel.load(Ordering::Relaxed) {
-            return Err(io::Error::new(
-                io::ErrorKind::Interrupted,
-                "upload cancelled",
-            ));
-        }
+        check_cancel(cancel)?;
         let read = file.read(&mut buffer)?;
         if read == 0 {
             break;
         }
+        if let Some(throttle) = throttle {
+            let wait =
+                throttle.lock_safe().map_or(Duration::ZERO, |mut bucket| {
+                    bucket.reserve(read as f64)
+                });
+            wait_throttled(wait, cancel)?;
+        }
         stream.write_all(&buffer[..read])?;
         bytes_sent.fetch_add(read as u64, Ordering::Relaxed);
     }
@@ -82,6 +96,29 @@ pub fn serve_file(
     Ok(())
 }
 
+fn check_cancel(cancel: &AtomicBool) -> io::Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "upload cancelled",
+        ));
+    }
+    Ok(())
+}
+
+/// Sleep off a throttle debt in short slices, bailing out on cancellation.
+fn wait_throttled(wait: Duration, cancel: &AtomicBool) -> io::Result<()> {
+    let deadline = Instant::now() + wait;
+    loop {
+        check_cancel(cancel)?;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        std::thread::sleep(remaining.min(THROTTLE_SLICE));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::serve_file;
@@ -113,6 +150,8 @@ mod tests {
                 &path,
                 &sent_counter,
                 &AtomicBool::new(false),
+                None,
+                None,
             )
         });
 
@@ -164,6 +203,8 @@ mod tests {
                 &path,
                 &AtomicU64::new(0),
                 &cancel_flag,
+                None,
+                None,
             )
         });
 
@@ -0,0 +1,246 @@
+//! Ordering search results by relevance instead of arrival.
+//!
+//! A [`ResultRanker`] scores each file of a result against the query that
+//! found it. Set one on the client and
+//! [`Client::get_search_results`](crate::Client::get_search_results) returns
+//! the best matches first; [`rank`] exposes the scores themselves.
+
+use crate::types::{File, SearchResult};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Scores a search hit; higher is more relevant. Scores only need to be
+/// comparable with each other, not within any range.
+pub trait ResultRanker: fmt::Debug + Send + Sync {
+    fn score(&self, query: &str, result: &SearchResult, file: &File) -> f64;
+}
+
+/// File extensions treated as lossless audio.
+const LOSSLESS_EXTENSIONS: [&str; 6] =
+    ["flac", "wav", "aiff", "aif", "ape", "alac"];
+
+/// Upload speed, in bytes per second, past which a peer is not considered
+/// any faster.
+const FAST_SPEED: u32 = 1024 * 1024;
+
+/// Queue length past which a peer is not considered any busier.
+const LONG_QUEUE: u32 = 50;
+
+/// Scores a hit out of 100.
+///
+/// Up to 50 for how well the file name matches the query, 20 for quality (lossless, else by bitrate up to 320 kbps),
+/// 15 for a free slot and 15 for speed, less up to 10 for a long queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultRanker;
+
+impl DefaultRanker {
+    /// Share of the query's words found in the file's path, plus a bonus
+    /// when they are all in the file name itself.
+    fn match_quality(query: &str, file: &File) -> f64 {
+        let path = file.name.to_lowercase();
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(&path);
+        let words: Vec<String> = query
+            .split_whitespace()
+            .filter(|word| !word.starts_with('-'))
+            .map(str::to_lowercase)
+            .collect();
+        if words.is_empty() {
+            return 0.0;
+        }
+        let found = words.iter().filter(|w| path.contains(w.as_str())).count();
+        let in_name = words.iter().all(|w| name.contains(w.as_str()));
+        let share = found as f64 / words.len() as f64;
+        share.mul_add(40.0, if in_name { 10.0 } else { 0.0 })
+    }
+
+    fn quality(file: &File) -> f64 {
+        let lossless = file.name.rsplit_once('.').is_some_and(|(_, ext)| {
+            LOSSLESS_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+        });
+        if lossless {
+            20.0
+        } else {
+            let bitrate = file.attributes.bitrate.unwrap_or(0).min(320);
+            f64::from(bitrate) / 320.0 * 20.0
+        }
+    }
+}
+
+impl ResultRanker for DefaultRanker {
+    fn score(&self, query: &str, result: &SearchResult, file: &File) -> f64 {
+        let slot = if result.free_slot { 15.0 } else { 0.0 };
+        let speed = f64::from(result.speed.min(FAST_SPEED))
+            / f64::from(FAST_SPEED)
+            * 15.0;
+        let queue = f64::from(result.queue_length.min(LONG_QUEUE))
+            / f64::from(LONG_QUEUE)
+            * 10.0;
+        Self::match_quality(query, file) + Self::quality(file) + slot + speed
+            - queue
+    }
+}
+
+/// A file from a search result with its relevance score.
+#[derive(Debug, Clone)]
+pub struct RankedFile {
+    pub score: f64,
+    pub file: File,
+    /// Whether the peer offering the file has an upload slot free.
+    pub free_slot: bool,
+    /// The peer's upload speed, in bytes per second.
+    pub speed: u32,
+    pub queue_length: u32,
+}
+
+/// Every file across `results`, best first. Ties keep arrival order.
+#[must_use]
+pub fn rank(
+    ranker: &dyn ResultRanker,
+    query: &str,
+    results: &[SearchResult],
+) -> Vec<RankedFile> {
+    let mut files: Vec<RankedFile> = results
+        .iter()
+        .flat_map(|result| {
+            result.files.iter().map(move |file| RankedFile {
+                score: ranker.score(query, result, file),
+                file: file.clone(),
+                free_slot: result.free_slot,
+                speed: result.speed,
+                queue_length: result.queue_length,
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| descending(a.score, b.score));
+    files
+}
+
+/// The order [`sort_results`] puts `results` in, as indices into it, so a
+/// slice of them can be taken without cloning the rest.
+#[must_use]
+pub fn ranked_order(
+    ranker: &dyn ResultRanker,
+    query: &str,
+    results: &[SearchResult],
+) -> Vec<usize> {
+    let best: Vec<f64> = results
+        .iter()
+        .map(|result| {
+            result
+                .files
+                .iter()
+                .map(|file| ranker.score(query, result, file))
+                .fold(f64::NEG_INFINITY, f64::max)
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..results.len()).collect();
+    order.sort_by(|&a, &b| descending(best[a], best[b]));
+    order
+}
+
+/// Order each result's files best first, then the results by their best
+/// file. Ties keep arrival order.
+#[must_use]
+pub fn sort_results(
+    ranker: &dyn ResultRanker,
+    query: &str,
+    results: Vec<SearchResult>,
+) -> Vec<SearchResult> {
+    let mut keyed: Vec<(f64, SearchResult)> = results
+        .into_iter()
+        .map(|mut result| {
+            let mut files: Vec<(f64, File)> = std::mem::take(&mut result.files)
+                .into_iter()
+                .map(|file| (ranker.score(query, &result, &file), file))
+                .collect();
+            files.sort_by(|a, b| descending(a.0, b.0));
+            let best = files.first().map_or(f64::NEG_INFINITY, |f| f.0);
+            result.files = files.into_iter().map(|(_, file)| file).collect();
+            (best, result)
+        })
+        .collect();
+    keyed.sort_by(|a, b| descending(a.0, b.0));
+    keyed.into_iter().map(|(_, result)| result).collect()
+}
+
+fn descending(a: f64, b: f64) -> Ordering {
+    b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultRanker, rank, sort_results};
+    use crate::types::{File, FileAttributes, SearchResult};
+
+    fn result(
+        username: &str,
+        free_slot: bool,
+        queue_length: u32,
+        files: &[(&str, Option<u32>)],
+    ) -> SearchResult {
+        SearchResult {
+            token: 1,
+            files: files
+                .iter()
+                .map(|&(name, bitrate)| File {
+                    username: username.into(),
+                    name: name.into(),
+                    size: 1,
+                    attributes: FileAttributes {
+                        bitrate,
+                        ..FileAttributes::default()
+                    },
+                    name_charset: None,
+                    locked: false,
+                })
+                .collect(),
+            free_slot,
+            speed: 100,
+            queue_length,
+            username: username.into(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn name_match_outranks_quality_and_slots_break_ties() {
+        let results = [
+            result("flac", true, 0, &[("Music\\Other Song.flac", None)]),
+            result(
+                "busy",
+                false,
+                40,
+                &[("Music\\Blinding Lights.mp3", Some(320))],
+            ),
+            result(
+                "mp3",
+                true,
+                0,
+                &[
+                    ("Music\\The Weeknd\\01.mp3", Some(320)),
+                    ("Music\\Blinding Lights.mp3", Some(192)),
+                ],
+            ),
+        ];
+        let ranked = rank(&DefaultRanker, "blinding lights -live", &results);
+        let order: Vec<_> = ranked
+            .iter()
+            .map(|r| (r.file.username.as_str(), r.file.name.as_str()))
+            .collect();
+        assert_eq!(
+            order[..2],
+            [
+                ("mp3", "Music\\Blinding Lights.mp3"),
+                ("busy", "Music\\Blinding Lights.mp3"),
+            ]
+        );
+        assert!(ranked.windows(2).all(|w| w[0].score >= w[1].score));
+
+        let sorted =
+            sort_results(&DefaultRanker, "blinding lights", results.to_vec());
+        let users: Vec<_> =
+            sorted.iter().map(|r| r.username.as_str()).collect();
+        assert_eq!(users, ["mp3", "busy", "flac"]);
+        assert_eq!(sorted[0].files[0].name, "Music\\Blinding Lights.mp3");
+    }
+}
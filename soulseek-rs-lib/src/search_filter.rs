@@ -0,0 +1,228 @@
+//! Client-side filtering of incoming search results.
+//!
+//! Soulseek servers forward every peer's response unfiltered, so every
+//! frontend ends up re-parsing file attributes to hide what it doesn't want.
+//! A [`SearchFilter`] set on the client does that once, before results are
+//! stored or streamed.
+
+use crate::types::{File, SearchResult};
+
+/// Criteria a search result must meet to be kept. Build one with the chained
+/// setters; an unset criterion accepts everything.
+///
+/// Per-file criteria (bitrate, extension, size) drop individual files; a
+/// result left without files is dropped entirely, as is one failing the
+/// per-peer criteria (free slots, upload speed, queue length).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchFilter {
+    min_bitrate: Option<u32>,
+    extensions: Vec<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    free_slots_only: bool,
+    min_speed: Option<u32>,
+    max_queue_length: Option<u32>,
+}
+
+impl SearchFilter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep files advertising at least `kbps`. Files without a bitrate
+    /// (typically lossless) are kept.
+    #[must_use]
+    pub const fn min_bitrate(mut self, kbps: u32) -> Self {
+        self.min_bitrate = Some(kbps);
+        self
+    }
+
+    /// Keep only files with one of these extensions (case-insensitive,
+    /// with or without the leading dot).
+    #[must_use]
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.extensions = extensions
+            .into_iter()
+            .map(|ext| ext.as_ref().trim_start_matches('.').to_lowercase())
+            .collect();
+        self
+    }
+
+    /// Keep files of at least `bytes`.
+    #[must_use]
+    pub const fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = Some(bytes);
+        self
+    }
+
+    /// Keep files of at most `bytes`.
+    #[must_use]
+    pub const fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Drop results from peers with no free upload slot.
+    #[must_use]
+    pub const fn free_slots_only(mut self) -> Self {
+        self.free_slots_only = true;
+        self
+    }
+
+    /// Drop results from peers reporting an upload speed below
+    /// `bytes_per_sec`.
+    #[must_use]
+    pub const fn min_speed(mut self, bytes_per_sec: u32) -> Self {
+        self.min_speed = Some(bytes_per_sec);
+        self
+    }
+
+    /// Drop results from peers with more than `uploads` queued.
+    #[must_use]
+    pub const fn max_queue_length(mut self, uploads: u32) -> Self {
+        self.max_queue_length = Some(uploads);
+        self
+    }
+
+    /// Whether a single file passes the per-file criteria.
+    #[must_use]
+    pub fn accepts_file(&self, file: &File) -> bool {
+        if let Some(min) = self.min_bitrate
+            && file.attributes.bitrate.is_some_and(|kbps| kbps < min)
+        {
+            return false;
+        }
+        if self.min_size.is_some_and(|min| file.size < min)
+            || self.max_size.is_some_and(|max| file.size > max)
+        {
+            return false;
+        }
+        if self.extensions.is_empty() {
+            return true;
+        }
+        file.name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .is_some_and(|ext| self.extensions.contains(&ext))
+    }
+
+    /// Apply the filter to a result, returning what's left of it (or `None`
+    /// if nothing is).
+    #[must_use]
+    pub fn apply(&self, mut result: SearchResult) -> Option<SearchResult> {
+        if (self.free_slots_only && !result.free_slot)
+            || self.min_speed.is_some_and(|min| result.speed < min)
+            || self
+                .max_queue_length
+                .is_some_and(|max| result.queue_length > max)
+        {
+            return None;
+        }
+        result.files.retain(|file| self.accepts_file(file));
+        result.locked_files.retain(|file| self.accepts_file(file));
+        (!result.files.is_empty() || !result.locked_files.is_empty())
+            .then_some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchFilter;
+    use crate::types::{File, FileAttributes, SearchResult};
+
+    fn file(name: &str, size: u64, bitrate: Option<u32>) -> File {
+        File {
+            username: "peer".to_string(),
+            name: name.to_string(),
+            size,
+            attributes: FileAttributes {
+                bitrate,
+                ..FileAttributes::default()
+            },
+            name_charset: None,
+            locked: false,
+        }
+    }
+
+    fn result(files: Vec<File>, free_slot: bool, speed: u32) -> SearchResult {
+        SearchResult {
+            token: 1,
+            files,
+            free_slot,
+            speed,
+            queue_length: 0,
+            username: "peer".to_string(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn default_filter_keeps_everything() {
+        let kept = SearchFilter::new()
+            .apply(result(vec![file("a.mp3", 1, Some(128))], false, 0))
+            .unwrap();
+        assert_eq!(kept.files.len(), 1);
+    }
+
+    #[test]
+    fn per_file_criteria_drop_individual_files() {
+        let filter = SearchFilter::new()
+            .min_bitrate(320)
+            .extensions([".MP3", "flac"])
+            .min_size(10)
+            .max_size(1000);
+        let kept = filter
+            .apply(result(
+                vec![
+                    file("keep.mp3", 100, Some(320)),
+                    file("lossless.FLAC", 500, None),
+                    file("low.mp3", 100, Some(192)),
+                    file("wrong.ogg", 100, Some(320)),
+                    file("tiny.mp3", 5, Some(320)),
+                    file("huge.flac", 5000, None),
+                ],
+                true,
+                0,
+            ))
+            .unwrap();
+        let names: Vec<&str> =
+            kept.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["keep.mp3", "lossless.FLAC"]);
+    }
+
+    #[test]
+    fn per_peer_criteria_and_empty_results_drop_the_result() {
+        let files = || vec![file("a.mp3", 100, Some(320))];
+        assert!(
+            SearchFilter::new()
+                .free_slots_only()
+                .apply(result(files(), false, 0))
+                .is_none()
+        );
+        assert!(
+            SearchFilter::new()
+                .min_speed(1000)
+                .apply(result(files(), true, 999))
+                .is_none()
+        );
+        let mut queued = result(files(), true, 0);
+        queued.queue_length = 500;
+        assert!(
+            SearchFilter::new()
+                .max_queue_length(50)
+                .apply(queued)
+                .is_none()
+        );
+        assert!(
+            SearchFilter::new()
+                .extensions(["flac"])
+                .apply(result(files(), true, 0))
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,153 @@
+//! Bounding the work other users' searches can make us do.
+//!
+//! Every search the server (or, later, a distributed parent) hands us is
+//! matched against the whole share index and may open a peer connection to
+//! answer it. A flood of them, or a parent replaying the same query, would
+//! keep the matcher and the upload pipe busy. [`SearchLimits`] caps how many
+//! searches per second are answered and ignores repeats of a query seen
+//! within a short window.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::utils::token_bucket::TokenBucket;
+
+/// Most remembered queries; past it the oldest are forgotten early.
+const MAX_REMEMBERED_QUERIES: usize = 10_000;
+
+/// How many incoming searches we answer, set on the client. Build one with
+/// the chained setters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchLimits {
+    per_second: f64,
+    burst: u32,
+    dedupe_window: Duration,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            per_second: 10.0,
+            burst: 50,
+            dedupe_window: Duration::from_secs(10),
+        }
+    }
+}
+
+impl SearchLimits {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer at most `count` searches per second on average.
+    #[must_use]
+    pub const fn per_second(mut self, count: f64) -> Self {
+        self.per_second = count;
+        self
+    }
+
+    /// Answer up to `count` searches in a row before the rate applies.
+    #[must_use]
+    pub const fn burst(mut self, count: u32) -> Self {
+        self.burst = count;
+        self
+    }
+
+    /// Ignore a query repeated by the same user within `window`.
+    /// `Duration::ZERO` answers every repeat.
+    #[must_use]
+    pub const fn dedupe_window(mut self, window: Duration) -> Self {
+        self.dedupe_window = window;
+        self
+    }
+}
+
+/// Admits incoming searches within a [`SearchLimits`].
+#[derive(Debug)]
+pub struct SearchLimiter {
+    bucket: TokenBucket,
+    dedupe_window: Duration,
+    /// Each (user, lowercased query) seen within the window, oldest first.
+    seen: VecDeque<(Instant, (String, String))>,
+    recent: HashSet<(String, String)>,
+    dropped: u64,
+}
+
+impl SearchLimiter {
+    #[must_use]
+    pub fn new(limits: SearchLimits) -> Self {
+        Self {
+            bucket: TokenBucket::new(
+                limits.per_second,
+                f64::from(limits.burst.max(1)),
+            ),
+            dedupe_window: limits.dedupe_window,
+            seen: VecDeque::new(),
+            recent: HashSet::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Whether to answer `username`'s search for `query` now. Repeats
+    /// within the dedupe window don't use up the rate.
+    pub fn admit(&mut self, username: &str, query: &str, now: Instant) -> bool {
+        let window = self.dedupe_window;
+        if !window.is_zero() {
+            while let Some((seen, _)) = self.seen.front()
+                && (now.duration_since(*seen) >= window
+                    || self.seen.len() >= MAX_REMEMBERED_QUERIES)
+            {
+                if let Some((_, key)) = self.seen.pop_front() {
+                    self.recent.remove(&key);
+                }
+            }
+            let key = (username.to_string(), query.to_lowercase());
+            if !self.recent.insert(key.clone()) {
+                self.dropped += 1;
+                return false;
+            }
+            self.seen.push_back((now, key));
+        }
+        let admitted = self.bucket.try_take(1.0);
+        if !admitted {
+            self.dropped += 1;
+        }
+        admitted
+    }
+
+    /// Searches refused so far, as repeats or over the rate.
+    #[must_use]
+    pub const fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SearchLimiter, SearchLimits};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn repeats_and_floods_are_dropped() {
+        let mut limiter = SearchLimiter::new(
+            SearchLimits::new()
+                .per_second(0.001)
+                .burst(3)
+                .dedupe_window(Duration::from_secs(10)),
+        );
+        let now = Instant::now();
+        assert!(limiter.admit("amy", "aphex twin", now));
+        assert!(!limiter.admit("amy", "Aphex Twin", now));
+        assert!(limiter.admit("bob", "aphex twin", now));
+        assert!(limiter.admit("amy", "autechre", now));
+        // The burst is spent.
+        assert!(!limiter.admit("amy", "boards of canada", now));
+        assert_eq!(limiter.dropped(), 2);
+
+        // Past the window the repeat is new again, but still over the rate.
+        let later = now + Duration::from_secs(11);
+        assert!(!limiter.admit("amy", "aphex twin", later));
+        assert_eq!(limiter.dropped(), 3);
+    }
+}
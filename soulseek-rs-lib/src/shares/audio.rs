@@ -0,0 +1,370 @@
+//! Reading the Soulseek audio attributes of a shared file from its headers.
+//!
+//! Only the first and, for Ogg, last few KiB of a file are read: enough for
+//! an MP3 frame header and its Xing/VBRI tag, a FLAC `STREAMINFO` block or
+//! the Vorbis/Opus identification header and final granule position.
+//! Anything unrecognised gets no attributes.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::types::FileAttributes;
+
+/// Bytes read from the start of the audio data (and the end, for Ogg).
+const WINDOW: u64 = 64 * 1024;
+
+/// The attributes of the audio file at `path`, judged by its extension.
+#[must_use]
+pub fn read_attributes(path: &Path) -> FileAttributes {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let parse = match extension.as_deref() {
+        Some("mp3") => mp3,
+        Some("flac") => flac,
+        Some("ogg" | "oga" | "opus") => ogg,
+        _ => return FileAttributes::default(),
+    };
+    File::open(path)
+        .and_then(|mut file| {
+            let size = file.metadata()?.len();
+            parse(&mut file, size)
+        })
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::new();
+    file.take(len).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Where the audio starts, past any ID3v2 tag.
+fn skip_id3v2(file: &mut File) -> io::Result<u64> {
+    let header = read_at(file, 0, 10)?;
+    if header.len() < 10 || &header[..3] != b"ID3" {
+        return Ok(0);
+    }
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |size, &b| (size << 7) | u64::from(b & 0x7f));
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok(10 + size + footer)
+}
+
+fn be32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Average kbps of `bytes` of audio lasting `seconds`.
+fn average_kbps(bytes: u64, seconds: f64) -> Option<u32> {
+    (seconds > 0.0).then(|| (bytes as f64 * 8.0 / seconds / 1000.0) as u32)
+}
+
+/// A parsed MPEG audio layer III frame header.
+struct Mp3Frame {
+    mpeg1: bool,
+    mono: bool,
+    bitrate: u32,
+    sample_rate: u32,
+    len: usize,
+}
+
+impl Mp3Frame {
+    const BITRATES_V1: [u32; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const BITRATES_V2: [u32; 15] =
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+    fn parse(header: &[u8]) -> Option<Self> {
+        let &[sync, b1, b2, b3, ..] = header else {
+            return None;
+        };
+        if sync != 0xff || b1 & 0xe0 != 0xe0 || (b1 >> 1) & 3 != 1 {
+            return None;
+        }
+        let (mpeg1, rate_divisor) = match (b1 >> 3) & 3 {
+            3 => (true, 1),
+            2 => (false, 2),
+            0 => (false, 4),
+            _ => return None,
+        };
+        let index = usize::from(b2 >> 4);
+        let bitrates = if mpeg1 {
+            Self::BITRATES_V1
+        } else {
+            Self::BITRATES_V2
+        };
+        let bitrate = *bitrates.get(index).filter(|&&b| b > 0)?;
+        let sample_rate = [44_100, 48_000, 32_000]
+            .get(usize::from((b2 >> 2) & 3))?
+            / rate_divisor;
+        let coefficient = if mpeg1 { 144 } else { 72 };
+        let len = coefficient * bitrate * 1000 / sample_rate
+            + u32::from((b2 >> 1) & 1);
+        Some(Self {
+            mpeg1,
+            mono: b3 >> 6 == 3,
+            bitrate,
+            sample_rate,
+            len: len as usize,
+        })
+    }
+
+    const fn samples(&self) -> u32 {
+        if self.mpeg1 { 1152 } else { 576 }
+    }
+
+    /// Where a Xing/Info tag would start, after the side information.
+    const fn xing_offset(&self) -> usize {
+        4 + match (self.mpeg1, self.mono) {
+            (true, false) => 32,
+            (true, true) | (false, false) => 17,
+            (false, true) => 9,
+        }
+    }
+}
+
+fn mp3(file: &mut File, size: u64) -> io::Result<Option<FileAttributes>> {
+    let start = skip_id3v2(file)?;
+    let data = read_at(file, start, WINDOW)?;
+    // The first header whose successor is where its length says, so a
+    // stray 0xFF in leftover tag bytes isn't taken for a frame.
+    let found = (0..data.len().saturating_sub(4)).find_map(|at| {
+        let frame = Mp3Frame::parse(&data[at..])?;
+        let next = data.get(at + frame.len..).filter(|next| next.len() >= 4);
+        match next {
+            Some(next) if Mp3Frame::parse(next).is_none() => None,
+            _ => Some((at, frame)),
+        }
+    });
+    let Some((at, frame)) = found else {
+        return Ok(None);
+    };
+    let audio_bytes = size.saturating_sub(start + at as u64);
+    let body = &data[at..];
+
+    // A Xing (VBR) or Info (CBR) tag, or a VBRI tag, counts the frames.
+    let xing = frame.xing_offset();
+    let (frames, vbr, tagged_bytes) = match body.get(xing..xing + 4) {
+        Some(tag @ (b"Xing" | b"Info")) => {
+            let flags = be32(body, xing + 4).unwrap_or(0);
+            let frames =
+                (flags & 1 != 0).then(|| be32(body, xing + 8)).flatten();
+            let bytes_at = xing + if flags & 1 != 0 { 12 } else { 8 };
+            let bytes =
+                (flags & 2 != 0).then(|| be32(body, bytes_at)).flatten();
+            (frames, tag == b"Xing", bytes)
+        }
+        _ if body.get(36..40) == Some(b"VBRI") => {
+            (be32(body, 50), true, be32(body, 46))
+        }
+        _ => (None, false, None),
+    };
+
+    let mut attributes = FileAttributes {
+        sample_rate: Some(frame.sample_rate),
+        vbr: Some(vbr),
+        ..FileAttributes::default()
+    };
+    if let Some(frames) = frames.filter(|&frames| frames > 0) {
+        let seconds = f64::from(frames) * f64::from(frame.samples())
+            / f64::from(frame.sample_rate);
+        attributes.duration_seconds = Some(seconds.round() as u32);
+        attributes.bitrate = if vbr {
+            let bytes = tagged_bytes.map_or(audio_bytes, u64::from);
+            average_kbps(bytes, seconds)
+        } else {
+            Some(frame.bitrate)
+        };
+    } else {
+        let seconds =
+            audio_bytes as f64 * 8.0 / f64::from(frame.bitrate * 1000);
+        attributes.duration_seconds = Some(seconds.round() as u32);
+        attributes.bitrate = Some(frame.bitrate);
+    }
+    Ok(Some(attributes))
+}
+
+fn flac(file: &mut File, size: u64) -> io::Result<Option<FileAttributes>> {
+    let start = skip_id3v2(file)?;
+    let data = read_at(file, start, 4 + 4 + 34)?;
+    // `fLaC`, then the first metadata block, which must be STREAMINFO.
+    if data.len() < 42 || &data[..4] != b"fLaC" || data[4] & 0x7f != 0 {
+        return Ok(None);
+    }
+    let info = &data[8..];
+    let sample_rate = (u32::from(info[10]) << 12)
+        | (u32::from(info[11]) << 4)
+        | u32::from(info[12] >> 4);
+    let bit_depth =
+        ((u32::from(info[12] & 1) << 4) | u32::from(info[13] >> 4)) + 1;
+    let samples = (u64::from(info[13] & 0x0f) << 32)
+        | u64::from(be32(info, 14).unwrap_or(0));
+    if sample_rate == 0 {
+        return Ok(None);
+    }
+    let seconds = samples as f64 / f64::from(sample_rate);
+    Ok(Some(FileAttributes {
+        bitrate: average_kbps(size.saturating_sub(start), seconds),
+        duration_seconds: Some(seconds.round() as u32),
+        sample_rate: Some(sample_rate),
+        bit_depth: Some(bit_depth),
+        ..FileAttributes::default()
+    }))
+}
+
+fn ogg(file: &mut File, size: u64) -> io::Result<Option<FileAttributes>> {
+    let head = read_at(file, 0, 4096)?;
+    if head.len() < 28 || &head[..4] != b"OggS" {
+        return Ok(None);
+    }
+    let packet = 27 + usize::from(head[26]);
+    let Some(packet) = head.get(packet..) else {
+        return Ok(None);
+    };
+
+    // Sample rate the granule positions count in, samples to skip, and
+    // the nominal bitrate if the stream states one.
+    let (rate, pre_skip, nominal, original_rate) =
+        if packet.get(..7) == Some(b"\x01vorbis") {
+            let Some(rate) = le32(packet, 12) else {
+                return Ok(None);
+            };
+            let nominal = le32(packet, 20)
+                .filter(|&bps| bps > 0 && bps < i32::MAX as u32)
+                .map(|bps| bps / 1000);
+            (rate, 0, nominal, rate)
+        } else if packet.get(..8) == Some(b"OpusHead") {
+            let pre_skip = packet
+                .get(10..12)
+                .map_or(0, |b| u64::from(u16::from_le_bytes([b[0], b[1]])));
+            // Opus always runs at 48 kHz; the header keeps the input rate.
+            (48_000, pre_skip, None, le32(packet, 12).unwrap_or(48_000))
+        } else {
+            return Ok(None);
+        };
+    if rate == 0 {
+        return Ok(None);
+    }
+
+    // The last page's granule position is the stream's length in samples.
+    let tail_start = size.saturating_sub(WINDOW);
+    let tail = read_at(file, tail_start, WINDOW)?;
+    let granule = tail
+        .windows(4)
+        .rposition(|window| window == b"OggS")
+        .and_then(|at| le64(&tail, at + 6))
+        .filter(|&granule| granule != u64::MAX);
+    let seconds = granule.map(|granule| {
+        granule.saturating_sub(pre_skip) as f64 / f64::from(rate)
+    });
+
+    Ok(Some(FileAttributes {
+        bitrate: nominal.or_else(|| average_kbps(size, seconds?)),
+        duration_seconds: seconds.map(|seconds| seconds.round() as u32),
+        sample_rate: (original_rate > 0).then_some(original_rate),
+        ..FileAttributes::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_attributes;
+    use crate::types::FileAttributes;
+    use std::path::PathBuf;
+
+    fn write(name: &str, bytes: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-audio-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// A 10-second stream of silent 128 kbps MPEG-1 frames behind a tag.
+    fn cbr_mp3() -> Vec<u8> {
+        let mut bytes = b"ID3\x04\x00\x00\x00\x00\x00\x05tag!!".to_vec();
+        let frame_len = 144 * 128_000 / 44_100;
+        let frames = 10 * 44_100 / 1152;
+        for _ in 0..frames {
+            let mut frame = vec![0u8; frame_len];
+            frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+            bytes.extend(frame);
+        }
+        bytes
+    }
+
+    fn flac(seconds: u32) -> Vec<u8> {
+        let samples = u64::from(seconds) * 44_100;
+        let mut info = vec![0u8; 34];
+        // 44100 Hz, 2 channels, 16 bits, then the 36-bit sample count.
+        let rate = 0xac44_u32;
+        info[10] = (rate >> 12) as u8;
+        info[11] = (rate >> 4) as u8;
+        info[12] = ((rate & 0x0f) << 4) as u8 | (1 << 1);
+        info[13] = (15 << 4) | ((samples >> 32) as u8 & 0x0f);
+        info[14..18].copy_from_slice(&(samples as u32).to_be_bytes());
+        let mut bytes = b"fLaC\x80\x00\x00\x22".to_vec();
+        bytes.extend(info);
+        bytes.resize(100_000, 0);
+        bytes
+    }
+
+    fn ogg_page(granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\x00\x00".to_vec();
+        page.extend(granule.to_le_bytes());
+        page.extend([0u8; 12]);
+        page.push(1);
+        page.push(packet.len() as u8);
+        page.extend(packet);
+        page
+    }
+
+    #[test]
+    fn mp3_flac_and_vorbis_headers_are_read() {
+        let mp3 = read_attributes(&write("cbr.mp3", &cbr_mp3()));
+        assert_eq!(mp3.bitrate, Some(128));
+        assert_eq!(mp3.duration_seconds, Some(10));
+        assert_eq!(mp3.sample_rate, Some(44_100));
+        assert_eq!(mp3.vbr, Some(false));
+
+        let flac = read_attributes(&write("a.flac", &flac(200)));
+        assert_eq!(flac.duration_seconds, Some(200));
+        assert_eq!(flac.sample_rate, Some(44_100));
+        assert_eq!(flac.bit_depth, Some(16));
+        assert_eq!(flac.bitrate, Some(4));
+
+        let mut ident = b"\x01vorbis\x00\x00\x00\x00\x02".to_vec();
+        ident.extend(48_000u32.to_le_bytes());
+        ident.extend(0u32.to_le_bytes());
+        ident.extend(192_000u32.to_le_bytes());
+        ident.extend(0u32.to_le_bytes());
+        let mut ogg = ogg_page(0, &ident);
+        ogg.extend(vec![0u8; 1000]);
+        ogg.extend(ogg_page(48_000 * 65, b"audio"));
+        let vorbis = read_attributes(&write("a.ogg", &ogg));
+        assert_eq!(vorbis.bitrate, Some(192));
+        assert_eq!(vorbis.duration_seconds, Some(65));
+        assert_eq!(vorbis.sample_rate, Some(48_000));
+
+        let junk = read_attributes(&write("junk.mp3", b"not audio"));
+        assert_eq!(junk, FileAttributes::default());
+        let text = read_attributes(&write("notes.txt", &cbr_mp3()));
+        assert_eq!(text, FileAttributes::default());
+    }
+}
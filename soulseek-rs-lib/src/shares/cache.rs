@@ -0,0 +1,144 @@
+//! What a previous scan learned about each shared file, so a rescan only
+//! reads the headers of files that changed.
+//!
+//! Saved as one tab-separated line per file: modification time, size,
+//! attribute pairs and the real path, last so it may contain tabs.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::SharedFile;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    modified: u64,
+    size: u64,
+    attributes: Vec<(u32, u32)>,
+}
+
+/// Attributes of files already scanned, keyed by real path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShareCache {
+    entries: HashMap<PathBuf, Entry>,
+}
+
+impl ShareCache {
+    /// The cache kept in `file`. A missing file is an empty cache and
+    /// lines that don't parse are skipped.
+    ///
+    /// # Errors
+    /// Returns the error reading an existing `file` failed with.
+    pub fn load(file: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let entries = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                let modified = fields.next()?.parse().ok()?;
+                let size = fields.next()?.parse().ok()?;
+                let attributes = parse_pairs(fields.next()?)?;
+                let path = PathBuf::from(fields.next()?);
+                Some((
+                    path,
+                    Entry {
+                        modified,
+                        size,
+                        attributes,
+                    },
+                ))
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+
+    /// Write the cache to `file`, replacing it.
+    ///
+    /// # Errors
+    /// Returns the error creating or writing `file` failed with.
+    pub fn save(&self, file: &Path) -> io::Result<()> {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut out = io::BufWriter::new(fs::File::create(file)?);
+        for (path, entry) in &self.entries {
+            let Some(path) = path.to_str().filter(|p| !p.contains('\n')) else {
+                continue;
+            };
+            let pairs: Vec<String> = entry
+                .attributes
+                .iter()
+                .map(|(code, value)| format!("{code}:{value}"))
+                .collect();
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}",
+                entry.modified,
+                entry.size,
+                pairs.join(","),
+                path
+            )?;
+        }
+        out.flush()
+    }
+
+    /// The cache describing `files`.
+    #[must_use]
+    pub fn from_files(files: &[SharedFile]) -> Self {
+        let entries = files
+            .iter()
+            .map(|file| {
+                (
+                    file.real_path.clone(),
+                    Entry {
+                        modified: file.modified,
+                        size: file.size,
+                        attributes: file.attributes.clone(),
+                    },
+                )
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// The attributes of `path` if it is unchanged since it was cached.
+    #[must_use]
+    pub fn attributes(
+        &self,
+        path: &Path,
+        size: u64,
+        modified: u64,
+    ) -> Option<&[(u32, u32)]> {
+        self.entries
+            .get(path)
+            .filter(|entry| entry.size == size && entry.modified == modified)
+            .map(|entry| entry.attributes.as_slice())
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn parse_pairs(text: &str) -> Option<Vec<(u32, u32)>> {
+    if text.is_empty() {
+        return Some(Vec::new());
+    }
+    text.split(',')
+        .map(|pair| {
+            let (code, value) = pair.split_once(':')?;
+            Some((code.parse().ok()?, value.parse().ok()?))
+        })
+        .collect()
+}
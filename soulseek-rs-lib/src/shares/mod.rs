@@ -3,10 +3,19 @@
 //! Scanning a directory produces a read-only [`Shares`] snapshot keyed by the
 //! peer-facing *virtual path* (the shared directory's own name followed by the
 //! backslash-separated relative path, matching the Soulseek wire convention).
+//! Audio files carry the attributes read from their headers; a
+//! [`ShareCache`] from an earlier scan spares re-reading unchanged files.
+
+mod audio;
+mod cache;
+
+pub use audio::read_attributes;
+pub use cache::ShareCache;
 
 use std::collections::{HashMap, HashSet};
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 
 /// One shared file: its peer-facing virtual path and where it lives on disk.
 #[derive(Debug, Clone)]
@@ -16,7 +25,9 @@ pub struct SharedFile {
     /// The real filesystem path used to serve the bytes.
     pub real_path: PathBuf,
     pub size: u64,
-    /// Optional `(code, value)` audio attributes (empty for now).
+    /// Modification time, in seconds since the Unix epoch.
+    pub modified: u64,
+    /// `(code, value)` audio attributes; empty for other files.
     pub attributes: Vec<(u32, u32)>,
 }
 
@@ -26,6 +37,8 @@ pub struct Shares {
     files: Vec<SharedFile>,
     by_virtual: HashMap<String, usize>,
     folder_count: usize,
+    /// Each scanned root and the virtual name its files are listed under.
+    roots: Vec<(PathBuf, String)>,
 }
 
 impl Shares {
@@ -44,15 +57,10 @@ impl Shares {
         // Fail fast if the root is unreadable; deeper failures are skipped.
         let _ = std::fs::read_dir(root)?;
 
+        let name = root_display_name(root);
         let mut files = Vec::new();
-        let mut folder_count = 0;
-        scan_root(
-            root,
-            &root_display_name(root),
-            &mut files,
-            &mut folder_count,
-        );
-        Ok(Self::from_files(files, folder_count))
+        scan_dir(root, &name, root, &ShareCache::default(), &mut files);
+        Ok(Self::from_files(files, vec![(root.to_path_buf(), name)]))
     }
 
     /// Scan several roots into one merged index. Unreadable roots are
@@ -61,8 +69,15 @@ impl Shares {
     /// their virtual paths cannot collide.
     #[must_use]
     pub fn scan_many(roots: &[PathBuf]) -> Self {
+        Self::scan_many_cached(roots, &ShareCache::default())
+    }
+
+    /// [`Shares::scan_many`], taking the attributes of files unchanged
+    /// since `cache` was made from it instead of reading them again.
+    #[must_use]
+    pub fn scan_many_cached(roots: &[PathBuf], cache: &ShareCache) -> Self {
         let mut files = Vec::new();
-        let mut folder_count = 0;
+        let mut scanned = Vec::new();
         let mut name_uses: HashMap<String, u32> = HashMap::new();
 
         for root in roots {
@@ -81,25 +96,81 @@ impl Shares {
             } else {
                 format!("{base} ({uses})")
             };
-            scan_root(root, &name, &mut files, &mut folder_count);
+            scan_dir(root, &name, root, cache, &mut files);
+            scanned.push((root.clone(), name));
         }
 
-        Self::from_files(files, folder_count)
+        Self::from_files(files, scanned)
     }
 
-    fn from_files(files: Vec<SharedFile>, folder_count: usize) -> Self {
+    /// This index updated for `changed` paths: files and directories that
+    /// are gone are dropped, and ones that exist are (re)read. Paths
+    /// outside the shared roots are ignored.
+    #[must_use]
+    pub fn with_changes(&self, changed: &[PathBuf]) -> Self {
+        let cache = self.cache();
+        let mut files: Vec<SharedFile> = self
+            .files
+            .iter()
+            .filter(|file| {
+                !changed.iter().any(|path| file.real_path.starts_with(path))
+            })
+            .cloned()
+            .collect();
+        for path in changed {
+            let Some((root, name)) =
+                self.roots.iter().find(|(root, _)| path.starts_with(root))
+            else {
+                continue;
+            };
+            let Ok(meta) = std::fs::symlink_metadata(path) else {
+                continue; // removed
+            };
+            if meta.is_dir() {
+                scan_dir(root, name, path, &cache, &mut files);
+            } else if meta.is_file() {
+                files.push(shared_file(
+                    root,
+                    name,
+                    path.clone(),
+                    &meta,
+                    &cache,
+                ));
+            }
+        }
+        // A file inside a changed directory may be listed twice.
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(file.real_path.clone()));
+        Self::from_files(files, self.roots.clone())
+    }
+
+    fn from_files(
+        files: Vec<SharedFile>,
+        roots: Vec<(PathBuf, String)>,
+    ) -> Self {
         let by_virtual = files
             .iter()
             .enumerate()
             .map(|(i, f)| (f.virtual_path.clone(), i))
             .collect();
+        let folder_count = files
+            .iter()
+            .filter_map(|file| file.real_path.parent())
+            .collect::<HashSet<_>>()
+            .len();
         Self {
             files,
             by_virtual,
             folder_count,
+            roots,
         }
     }
 
+    /// The directories this index was scanned from.
+    pub fn roots(&self) -> impl Iterator<Item = &Path> {
+        self.roots.iter().map(|(root, _)| root.as_path())
+    }
+
     /// Files whose virtual path contains *every* whitespace-separated term of
     /// `query` (case-insensitive). An empty query matches nothing.
     #[must_use]
@@ -121,6 +192,12 @@ impl Shares {
             .collect()
     }
 
+    /// What this scan learned, to speed up the next one.
+    #[must_use]
+    pub fn cache(&self) -> ShareCache {
+        ShareCache::from_files(&self.files)
+    }
+
     /// Look up a shared file by its exact virtual path.
     #[must_use]
     pub fn get(&self, virtual_path: &str) -> Option<&SharedFile> {
@@ -171,16 +248,17 @@ fn root_display_name(root: &Path) -> String {
     )
 }
 
-/// Recursively walk `root`, appending its files (under the virtual root name
-/// `root_name`) to `files` and counting folders that contain files.
-fn scan_root(
+/// Recursively walk `dir` under `root`, appending its files (under the
+/// virtual root name `root_name`) to `files`. Files unchanged since
+/// `cache` keep their cached attributes.
+fn scan_dir(
     root: &Path,
     root_name: &str,
+    dir: &Path,
+    cache: &ShareCache,
     files: &mut Vec<SharedFile>,
-    folder_count: &mut usize,
 ) {
-    let mut folders_with_files: HashSet<PathBuf> = HashSet::new();
-    let mut stack = vec![root.to_path_buf()];
+    let mut stack = vec![dir.to_path_buf()];
 
     while let Some(dir) = stack.pop() {
         let Ok(entries) = std::fs::read_dir(&dir) else {
@@ -198,18 +276,35 @@ fn scan_root(
             if meta.is_dir() {
                 stack.push(path);
             } else if meta.is_file() {
-                let virtual_path = virtual_path_for(root_name, root, &path);
-                files.push(SharedFile {
-                    virtual_path,
-                    real_path: path,
-                    size: meta.len(),
-                    attributes: Vec::new(),
-                });
-                folders_with_files.insert(dir.clone());
+                files.push(shared_file(root, root_name, path, &meta, cache));
             }
         }
     }
-    *folder_count += folders_with_files.len();
+}
+
+fn shared_file(
+    root: &Path,
+    root_name: &str,
+    path: PathBuf,
+    meta: &std::fs::Metadata,
+    cache: &ShareCache,
+) -> SharedFile {
+    let size = meta.len();
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    let attributes = cache
+        .attributes(&path, size, modified)
+        .map_or_else(|| read_attributes(&path).to_pairs(), <[_]>::to_vec);
+    SharedFile {
+        virtual_path: virtual_path_for(root_name, root, &path),
+        real_path: path,
+        size,
+        modified,
+        attributes,
+    }
 }
 
 /// Build the peer-facing virtual path for `path` under `root`: the root's own
@@ -225,7 +320,7 @@ fn virtual_path_for(root_name: &str, root: &Path, path: &Path) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::Shares;
+    use super::{ShareCache, Shares};
 
     fn temp_tree() -> std::path::PathBuf {
         use std::sync::atomic::{AtomicU32, Ordering};
@@ -328,6 +423,79 @@ mod tests {
         let _ = std::fs::remove_dir_all(root);
     }
 
+    #[test]
+    fn rescans_reuse_cached_attributes_of_unchanged_files() {
+        let root = temp_tree();
+        let song = root.join("album").join("song one.flac");
+        let shares = Shares::scan_many(std::slice::from_ref(&root));
+        let file = shares.files().iter().find(|f| f.real_path == song).unwrap();
+        // Not really FLAC, so nothing was read from it.
+        assert!(file.attributes.is_empty());
+
+        // A cached entry that still matches wins over the file's headers;
+        // it survives a save and load.
+        let mut cached = shares.files().to_vec();
+        for file in &mut cached {
+            file.attributes = vec![(1, 42)];
+        }
+        let index = root.join("index.tsv");
+        ShareCache::from_files(&cached).save(&index).unwrap();
+        let cache = ShareCache::load(&index).unwrap();
+        assert_eq!(cache.len(), 3);
+        std::fs::remove_file(&index).unwrap();
+        std::fs::write(root.join("top.mp3"), b"changed").unwrap();
+
+        let rescanned =
+            Shares::scan_many_cached(std::slice::from_ref(&root), &cache);
+        let attributes = |path: &std::path::Path| {
+            rescanned
+                .files()
+                .iter()
+                .find(|f| f.real_path == path)
+                .map(|f| f.attributes.clone())
+                .unwrap()
+        };
+        assert_eq!(attributes(&song), [(1, 42)]);
+        assert!(attributes(&root.join("top.mp3")).is_empty());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn changes_add_remove_and_rename_files() {
+        let root = temp_tree();
+        let base = root.file_name().unwrap().to_string_lossy().into_owned();
+        let shares = Shares::scan_many(std::slice::from_ref(&root));
+
+        std::fs::remove_file(root.join("top.mp3")).unwrap();
+        std::fs::rename(root.join("album"), root.join("renamed")).unwrap();
+        std::fs::create_dir(root.join("new")).unwrap();
+        std::fs::write(root.join("new").join("ripped.flac"), b"x").unwrap();
+        let updated = shares.with_changes(&[
+            root.join("top.mp3"),
+            root.join("album"),
+            root.join("renamed"),
+            root.join("new"),
+            root.join("new").join("ripped.flac"),
+            std::path::PathBuf::from("/elsewhere/file.mp3"),
+        ]);
+
+        assert!(updated.get(&format!("{base}\\top.mp3")).is_none());
+        assert!(
+            updated
+                .get(&format!("{base}\\album\\song one.flac"))
+                .is_none()
+        );
+        assert!(
+            updated
+                .get(&format!("{base}\\renamed\\song one.flac"))
+                .is_some()
+        );
+        assert!(updated.get(&format!("{base}\\new\\ripped.flac")).is_some());
+        assert_eq!(updated.file_count(), 3);
+        assert_eq!(updated.folder_count(), 2);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
     #[test]
     fn empty_shares_have_no_files_or_folders() {
         let shares = Shares::empty();
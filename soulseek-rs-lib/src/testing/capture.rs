@@ -0,0 +1,297 @@
+//! Capturing the wire and replaying it, to turn a protocol bug seen on the
+//! live network into a test.
+//!
+//! A [`Recorder`] is an [`Interceptor`]: add it with
+//! `Client::add_server_interceptor` (or `add_peer_interceptor`) and every
+//! message is appended to its file as a line of
+//! `<milliseconds>\t<in|out>\t<code>\t<framed bytes in hex>`. A [`Capture`]
+//! reads the file back, and [`Capture::replay`] feeds the inbound messages
+//! through a [`MessageReader`] and a dispatcher as if they had just arrived.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::dispatcher::{Direction, Interceptor, MessageDispatcher, Verdict};
+use crate::message::{Message, MessageReader};
+use crate::utils::lock::MutexExt;
+use crate::warn;
+
+/// One captured message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub direction: Direction,
+    /// Since the recorder was created.
+    pub at: Duration,
+    pub code: u32,
+    /// The message as framed on the wire, length prefix included.
+    pub bytes: Vec<u8>,
+}
+
+struct Output {
+    out: Box<dyn Write + Send>,
+    started: Instant,
+}
+
+/// Appends every message it intercepts to a capture. Clones share the
+/// output, so one recorder can watch several connections.
+#[derive(Clone)]
+pub struct Recorder {
+    output: Arc<Mutex<Output>>,
+}
+
+impl Recorder {
+    /// Record to `path`, replacing what it held.
+    ///
+    /// # Errors
+    /// Returns the error creating the file.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        File::create(path).map(Self::new)
+    }
+
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Arc::new(Mutex::new(Output {
+                out: Box::new(out),
+                started: Instant::now(),
+            })),
+        }
+    }
+
+    fn record(
+        &self,
+        direction: Direction,
+        code: u32,
+        bytes: &[u8],
+    ) -> crate::Result<()> {
+        let mut output = self.output.lock_safe()?;
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            output.started.elapsed().as_millis(),
+            match direction {
+                Direction::Inbound => "in",
+                Direction::Outbound => "out",
+            },
+            code,
+            hex(bytes)
+        );
+        // Flushed per message, so a capture survives the crash it is
+        // meant to explain.
+        output
+            .out
+            .write_all(line.as_bytes())
+            .and_then(|()| output.out.flush())
+            .map_err(crate::SoulseekRs::io("write capture"))
+    }
+}
+
+impl Interceptor for Recorder {
+    fn intercept(
+        &self,
+        direction: Direction,
+        code: u32,
+        message: &mut Message,
+    ) -> Verdict {
+        let result = match direction {
+            Direction::Inbound => {
+                self.record(direction, code, message.get_data())
+            }
+            Direction::Outbound => {
+                self.record(direction, code, &message.get_buffer())
+            }
+        };
+        if let Err(e) = result {
+            warn!("[capture] {}", e);
+        }
+        Verdict::Pass
+    }
+}
+
+/// A capture read back from a [`Recorder`]'s file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capture {
+    pub frames: Vec<Frame>,
+}
+
+impl Capture {
+    /// # Errors
+    /// Returns the error reading `path`, or `InvalidData` naming the first
+    /// line that isn't a captured message.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// # Errors
+    /// Returns `InvalidData` naming the first line that isn't a captured
+    /// message.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let frames = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                parse_frame(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("capture line {} is malformed", i + 1),
+                    )
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { frames })
+    }
+
+    /// The inbound messages back to back, as the connection delivered them.
+    #[must_use]
+    pub fn inbound_bytes(&self) -> Vec<u8> {
+        self.frames
+            .iter()
+            .filter(|frame| frame.direction == Direction::Inbound)
+            .flat_map(|frame| frame.bytes.iter().copied())
+            .collect()
+    }
+
+    /// Feed the inbound messages through a [`MessageReader`], in reads as
+    /// small as a socket's, and dispatch each one. Returns how many were
+    /// dispatched.
+    ///
+    /// # Errors
+    /// Returns the reader's error, e.g. for a message over its size limit.
+    pub fn replay<Op>(
+        &self,
+        dispatcher: &MessageDispatcher<Op>,
+    ) -> io::Result<usize> {
+        let bytes = self.inbound_bytes();
+        let mut stream = bytes.as_slice();
+        let mut reader = MessageReader::new();
+        let mut count = 0;
+        while !stream.is_empty() {
+            reader.read_from_socket(&mut stream)?;
+            while let Some(mut message) = reader.extract_message()? {
+                dispatcher.dispatch(&mut message);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+fn parse_frame(line: &str) -> Option<Frame> {
+    let mut fields = line.split('\t');
+    let at = Duration::from_millis(fields.next()?.parse().ok()?);
+    let direction = match fields.next()? {
+        "in" => Direction::Inbound,
+        "out" => Direction::Outbound,
+        _ => return None,
+    };
+    let code = fields.next()?.parse().ok()?;
+    let bytes = unhex(fields.next()?)?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Frame {
+        direction,
+        at,
+        code,
+        bytes,
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capture, Recorder};
+    use crate::dispatcher::{Direction, Interceptor, MessageDispatcher};
+    use crate::message::{Handlers, Message, MessageHandler};
+    use std::sync::Arc;
+    use std::sync::mpsc::{self, Sender};
+
+    struct Reads;
+
+    impl MessageHandler<u32> for Reads {
+        fn get_code(&self) -> u32 {
+            7
+        }
+        fn handle(
+            &self,
+            message: &mut Message,
+            sender: Sender<u32>,
+        ) -> crate::Result<()> {
+            let _ = sender.send(message.try_read_int32()?);
+            Ok(())
+        }
+    }
+
+    fn dispatcher(
+        sender: Sender<u32>,
+        interceptors: Vec<Arc<dyn Interceptor>>,
+    ) -> MessageDispatcher<u32> {
+        let mut handlers = Handlers::new();
+        handlers.register_handler(Reads);
+        MessageDispatcher::new("capture".into(), sender, handlers)
+            .with_interceptors(interceptors)
+    }
+
+    #[test]
+    fn a_recorded_session_replays_through_the_reader() {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("server.capture");
+
+        let (sender, receiver) = mpsc::channel();
+        let recorder = Recorder::create(&file).unwrap();
+        let live = dispatcher(sender.clone(), vec![Arc::new(recorder)]);
+        let mut outgoing = Message::new();
+        outgoing.write_int32(7).write_int32(1);
+        live.outbound(&mut outgoing);
+        // A file list sized message, so replay takes several reads.
+        let mut big = Message::new();
+        big.write_int32(7)
+            .write_int32(2)
+            .write_raw_bytes(vec![0; 3000]);
+        let mut small = Message::new();
+        small.write_int32(7).write_int32(3);
+        for message in [big, small] {
+            live.dispatch(&mut Message::new_with_data(message.get_buffer()));
+        }
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [2, 3]);
+
+        let capture = Capture::open(&file).unwrap();
+        let directions: Vec<_> =
+            capture.frames.iter().map(|f| f.direction).collect();
+        assert_eq!(
+            directions,
+            [Direction::Outbound, Direction::Inbound, Direction::Inbound]
+        );
+        assert!(capture.frames.iter().all(|f| f.code == 7));
+        assert_eq!(capture.frames[0].bytes, outgoing.get_buffer());
+
+        let replayed = dispatcher(sender, Vec::new());
+        assert_eq!(capture.replay(&replayed).unwrap(), 2);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [2, 3]);
+
+        assert!(Capture::parse("12\tsideways\t7\t00").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,770 @@
+//! A mock server and mock peers that answer a client from a script.
+//!
+//! [`MockServerBuilder`] listens for the client's server connection. It
+//! accepts every login and answers `GetPeerAddress` for the peers it was
+//! told about; any other request is answered by the script registered for
+//! its code, if there is one. [`MockPeerBuilder`] does the same for peer
+//! connections: it greets each connection the client opens, answers its
+//! requests from a script and uploads the files it shares, and
+//! [`MockPeer::connect_to`] opens a connection to the client's listener, as
+//! a peer returning search results does.
+//!
+//! Both keep the requests they were sent, decoded, to assert on. Messages
+//! the library doesn't know are skipped.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{
+    Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream,
+};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::actor::server_actor::PeerAddress;
+use crate::message::{
+    Message, PeerInitMessage, PeerMessageIn, PeerMessageOut, ServerMessageIn,
+    ServerMessageOut, TransferReply,
+};
+use crate::peer::ConnectionType;
+use crate::types::{LoginOutcome, Transfer};
+use crate::utils::lock::MutexExt;
+
+/// How often `wait_for` looks for the request again.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Script<Request, Reply> =
+    HashMap<u32, Box<dyn Fn(&Request) -> Vec<Reply> + Send + Sync>>;
+
+/// What a mock received, in order.
+struct Log<T>(Mutex<Vec<T>>);
+
+impl<T: Clone> Log<T> {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn record(&self, item: T) {
+        if let Ok(mut items) = self.0.lock_safe() {
+            items.push(item);
+        }
+    }
+
+    fn all(&self) -> Vec<T> {
+        self.0
+            .lock_safe()
+            .map(|items| items.clone())
+            .unwrap_or_default()
+    }
+
+    fn wait_for(
+        &self,
+        timeout: Duration,
+        matches: impl Fn(&T) -> bool,
+    ) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(item) =
+                self.all().into_iter().find(|item| matches(item))
+            {
+                return Some(item);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// A mock's open connections, to write to unprompted and to close when it
+/// is dropped.
+struct Connections {
+    streams: Mutex<Vec<TcpStream>>,
+    stop: AtomicBool,
+}
+
+impl Connections {
+    const fn new() -> Self {
+        Self {
+            streams: Mutex::new(Vec::new()),
+            stop: AtomicBool::new(false),
+        }
+    }
+
+    fn keep(&self, stream: &TcpStream) -> io::Result<()> {
+        let stream = stream.try_clone()?;
+        if let Ok(mut streams) = self.streams.lock_safe() {
+            streams.push(stream);
+        }
+        Ok(())
+    }
+
+    fn send(&self, message: &Message) -> io::Result<()> {
+        let buffer = message.get_buffer();
+        if let Ok(streams) = self.streams.lock_safe() {
+            for mut stream in streams.iter() {
+                stream.write_all(&buffer)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Close every connection and wake the accept loop listening on
+    /// `address`, so it sees it should stop.
+    fn close(&self, address: SocketAddr) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Ok(streams) = self.streams.lock_safe() {
+            for stream in streams.iter() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+        let _ = TcpStream::connect(address);
+    }
+}
+
+/// Read one message, length prefix included, as `decode` takes it.
+fn read_message(stream: &mut impl Read) -> io::Result<Message> {
+    let mut prefix = [0; 4];
+    stream.read_exact(&mut prefix)?;
+    let mut data = prefix.to_vec();
+    data.resize(4 + u32::from_le_bytes(prefix) as usize, 0);
+    stream.read_exact(&mut data[4..])?;
+    Ok(Message::new_with_data(data))
+}
+
+/// Record each request on `stream` and write back what `reply` returns for
+/// it, until the connection closes.
+fn serve<Request: Clone>(
+    mut stream: TcpStream,
+    log: &Log<Request>,
+    decode: fn(&mut Message) -> crate::Result<Request>,
+    reply: impl Fn(&Request) -> Vec<Message>,
+) -> io::Result<()> {
+    loop {
+        let mut message = read_message(&mut stream)?;
+        let Ok(request) = decode(&mut message) else {
+            continue;
+        };
+        log.record(request.clone());
+        for reply in reply(&request) {
+            stream.write_all(&reply.get_buffer())?;
+        }
+    }
+}
+
+/// Accept connections on `listener` until `connections` is closed, handing
+/// each to `handle` on a thread of its own.
+fn accept(
+    listener: TcpListener,
+    connections: Arc<Connections>,
+    handle: impl Fn(TcpStream) -> io::Result<()> + Send + Sync + 'static,
+) -> JoinHandle<()> {
+    let handle = Arc::new(handle);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if connections.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let Ok(stream) = stream else {
+                continue;
+            };
+            if connections.keep(&stream).is_err() {
+                continue;
+            }
+            let handle = handle.clone();
+            thread::spawn(move || handle(stream));
+        }
+    })
+}
+
+/// Builds a [`MockServer`].
+pub struct MockServerBuilder {
+    login: LoginOutcome,
+    peers: HashMap<String, SocketAddrV4>,
+    script: Script<ServerMessageOut, ServerMessageIn>,
+}
+
+impl Default for MockServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockServerBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            login: LoginOutcome::Accepted {
+                greeting: "Welcome to the mock server".to_string(),
+                ip: Some(Ipv4Addr::LOCALHOST),
+                password_md5: None,
+            },
+            peers: HashMap::new(),
+            script: HashMap::new(),
+        }
+    }
+
+    /// Answer logins with `outcome` instead of accepting them.
+    #[must_use]
+    pub fn login(mut self, outcome: LoginOutcome) -> Self {
+        self.login = outcome;
+        self
+    }
+
+    /// Answer `GetPeerAddress` for `username` with `address`. Other users
+    /// get the zero address, as the server answers for offline users.
+    #[must_use]
+    pub fn peer(
+        mut self,
+        username: impl Into<String>,
+        address: SocketAddrV4,
+    ) -> Self {
+        self.peers.insert(username.into(), address);
+        self
+    }
+
+    /// Answer each request with `code` with what `reply` returns for it,
+    /// in place of the built-in answer.
+    #[must_use]
+    pub fn on(
+        mut self,
+        code: u32,
+        reply: impl Fn(&ServerMessageOut) -> Vec<ServerMessageIn>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.script.insert(code, Box::new(reply));
+        self
+    }
+
+    /// Start listening on a free local port.
+    ///
+    /// # Errors
+    /// Returns the error binding the listener.
+    pub fn build(self) -> io::Result<MockServer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(ServerState {
+            script: self,
+            requests: Log::new(),
+            connections: Arc::new(Connections::new()),
+        });
+        let handling = state.clone();
+        let accepting =
+            accept(listener, state.connections.clone(), move |stream| {
+                serve(
+                    stream,
+                    &handling.requests,
+                    ServerMessageOut::decode,
+                    |request| handling.answer(request),
+                )
+            });
+        Ok(MockServer {
+            address,
+            state,
+            accepting: Some(accepting),
+        })
+    }
+}
+
+struct ServerState {
+    script: MockServerBuilder,
+    requests: Log<ServerMessageOut>,
+    connections: Arc<Connections>,
+}
+
+impl ServerState {
+    fn answer(&self, request: &ServerMessageOut) -> Vec<Message> {
+        let script = &self.script;
+        let replies = if let Some(reply) = script.script.get(&request.code()) {
+            reply(request)
+        } else {
+            match request {
+                ServerMessageOut::Login { .. } => {
+                    vec![ServerMessageIn::Login(script.login.clone())]
+                }
+                ServerMessageOut::GetPeerAddress { username } => {
+                    let address =
+                        script.peers.get(username).copied().unwrap_or(
+                            SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0),
+                        );
+                    vec![ServerMessageIn::GetPeerAddress {
+                        username: username.clone(),
+                        ip: *address.ip(),
+                        port: address.port().into(),
+                        obfuscation_type: 0,
+                        obfuscated_port: 0,
+                    }]
+                }
+                _ => Vec::new(),
+            }
+        };
+        replies.iter().map(ServerMessageIn::encode).collect()
+    }
+}
+
+/// A server on a local port, answering from its [`MockServerBuilder`]
+/// script until dropped.
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<ServerState>,
+    accepting: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Where to point [`ClientSettings::server_address`](crate::ClientSettings::server_address).
+    #[must_use]
+    pub fn address(&self) -> PeerAddress {
+        PeerAddress::new(self.address.ip().to_string(), self.address.port())
+    }
+
+    #[must_use]
+    pub const fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// The requests received so far.
+    #[must_use]
+    pub fn requests(&self) -> Vec<ServerMessageOut> {
+        self.state.requests.all()
+    }
+
+    /// The first request that `matches`, waiting up to `timeout` for it to
+    /// arrive.
+    pub fn wait_for(
+        &self,
+        timeout: Duration,
+        matches: impl Fn(&ServerMessageOut) -> bool,
+    ) -> Option<ServerMessageOut> {
+        self.state.requests.wait_for(timeout, matches)
+    }
+
+    /// Send `message` to every connected client unprompted, as the server
+    /// sends notices.
+    ///
+    /// # Errors
+    /// Returns the first error writing to a connection.
+    pub fn send(&self, message: &ServerMessageIn) -> io::Result<()> {
+        self.state.connections.send(&message.encode())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.state.connections.close(self.address);
+        if let Some(accepting) = self.accepting.take() {
+            let _ = accepting.join();
+        }
+    }
+}
+
+/// Builds a [`MockPeer`].
+pub struct MockPeerBuilder {
+    username: String,
+    greeting: Vec<Message>,
+    hang_up: bool,
+    script: Script<PeerMessageOut, PeerMessageIn>,
+    shares: HashMap<String, Vec<u8>>,
+    client_port: u16,
+}
+
+impl MockPeerBuilder {
+    #[must_use]
+    pub fn new(username: impl Into<String>) -> Self {
+        Self {
+            username: username.into(),
+            greeting: Vec::new(),
+            hang_up: false,
+            script: HashMap::new(),
+            shares: HashMap::new(),
+            client_port: 0,
+        }
+    }
+
+    /// Upload `content` as `filename` when the client queues it: offer it
+    /// with a `TransferRequest` and, once allowed, send it over an `F`
+    /// connection to the client's listener on
+    /// [`client_port`](Self::client_port).
+    #[must_use]
+    pub fn share(
+        mut self,
+        filename: impl Into<String>,
+        content: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.shares.insert(filename.into(), content.into());
+        self
+    }
+
+    /// The port the client listens on, where shared files are sent.
+    #[must_use]
+    pub const fn client_port(mut self, port: u16) -> Self {
+        self.client_port = port;
+        self
+    }
+
+    /// Send `message` on each connection the client opens, once the client
+    /// has introduced itself. It is taken encoded, so a distributed parent
+    /// can greet with [`DistributedMessageIn`](crate::message::DistributedMessageIn)s.
+    #[must_use]
+    pub fn greet(mut self, message: Message) -> Self {
+        self.greeting.push(message);
+        self
+    }
+
+    /// Close each connection the client opens once it is greeted, as a
+    /// peer going away does.
+    #[must_use]
+    pub const fn hang_up(mut self) -> Self {
+        self.hang_up = true;
+        self
+    }
+
+    /// Answer each request with `code` with what `reply` returns for it.
+    #[must_use]
+    pub fn on(
+        mut self,
+        code: u32,
+        reply: impl Fn(&PeerMessageOut) -> Vec<PeerMessageIn>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.script.insert(code, Box::new(reply));
+        self
+    }
+
+    /// Start listening on a free local port.
+    ///
+    /// # Errors
+    /// Returns the error binding the listener.
+    pub fn build(self) -> io::Result<MockPeer> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(PeerState {
+            script: self,
+            requests: Log::new(),
+            introductions: Log::new(),
+            offers: Mutex::new(HashMap::new()),
+            next_token: AtomicU32::new(1),
+            connections: Arc::new(Connections::new()),
+        });
+        let handling = state.clone();
+        let accepting =
+            accept(listener, state.connections.clone(), move |stream| {
+                handling.accepted(stream)
+            });
+        Ok(MockPeer {
+            address,
+            state,
+            accepting: Some(accepting),
+        })
+    }
+}
+
+struct PeerState {
+    script: MockPeerBuilder,
+    requests: Log<PeerMessageOut>,
+    introductions: Log<PeerInitMessage>,
+    /// Shared files offered to the client, by transfer token.
+    offers: Mutex<HashMap<u32, String>>,
+    next_token: AtomicU32,
+    connections: Arc<Connections>,
+}
+
+impl PeerState {
+    fn accepted(self: &Arc<Self>, mut stream: TcpStream) -> io::Result<()> {
+        let mut init = read_message(&mut stream)?;
+        if let Ok(init) = PeerInitMessage::decode(&mut init) {
+            self.introductions.record(init);
+        }
+        for message in &self.script.greeting {
+            stream.write_all(&message.get_buffer())?;
+        }
+        if self.script.hang_up {
+            // The clone kept for closing on drop holds the socket open.
+            return stream.shutdown(Shutdown::Both);
+        }
+        self.serve(stream)
+    }
+
+    fn serve(self: &Arc<Self>, stream: TcpStream) -> io::Result<()> {
+        serve(stream, &self.requests, PeerMessageOut::decode, |request| {
+            self.answer(request)
+                .iter()
+                .map(PeerMessageIn::encode)
+                .collect()
+        })
+    }
+
+    fn answer(
+        self: &Arc<Self>,
+        request: &PeerMessageOut,
+    ) -> Vec<PeerMessageIn> {
+        if let Some(reply) = self.script.script.get(&request.code()) {
+            return reply(request);
+        }
+        match request {
+            PeerMessageOut::QueueUpload { filename } => {
+                let Some(content) = self.script.shares.get(filename) else {
+                    return Vec::new();
+                };
+                let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+                if let Ok(mut offers) = self.offers.lock_safe() {
+                    offers.insert(token, filename.clone());
+                }
+                vec![PeerMessageIn::TransferRequest(Transfer {
+                    direction: 1,
+                    token,
+                    filename: filename.clone(),
+                    size: content.len() as u64,
+                })]
+            }
+            PeerMessageOut::TransferResponse {
+                token,
+                reply: TransferReply::Allowed,
+            } => {
+                let offered = self
+                    .offers
+                    .lock_safe()
+                    .ok()
+                    .and_then(|mut offers| offers.remove(token));
+                if let Some(filename) = offered {
+                    let state = self.clone();
+                    let token = *token;
+                    thread::spawn(move || state.upload(&filename, token));
+                }
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Send the shared `filename` over an `F` connection to the client,
+    /// from the offset it asks for.
+    fn upload(&self, filename: &str, token: u32) -> io::Result<()> {
+        let Some(content) = self.script.shares.get(filename) else {
+            return Ok(());
+        };
+        let mut stream =
+            TcpStream::connect((Ipv4Addr::LOCALHOST, self.script.client_port))?;
+        self.connections.keep(&stream)?;
+        let init = PeerInitMessage::PeerInit {
+            username: self.script.username.clone(),
+            connection_type: ConnectionType::F,
+            token,
+        };
+        // The transfer token goes out with the introduction, as the
+        // client looks the download up by it.
+        let mut greeting = init.encode().get_buffer();
+        greeting.extend_from_slice(&token.to_le_bytes());
+        stream.write_all(&greeting)?;
+        let mut offset = [0; 8];
+        stream.read_exact(&mut offset)?;
+        let offset = usize::try_from(u64::from_le_bytes(offset))
+            .unwrap_or(usize::MAX)
+            .min(content.len());
+        stream.write_all(&content[offset..])
+    }
+}
+
+/// A peer on a local port, answering from its [`MockPeerBuilder`] script
+/// until dropped.
+pub struct MockPeer {
+    address: SocketAddr,
+    state: Arc<PeerState>,
+    accepting: Option<JoinHandle<()>>,
+}
+
+impl MockPeer {
+    /// Where to tell the client the peer is, e.g. with
+    /// [`MockServerBuilder::peer`].
+    #[must_use]
+    pub const fn address(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(Ipv4Addr::LOCALHOST, self.address.port())
+    }
+
+    #[must_use]
+    pub const fn port(&self) -> u16 {
+        self.address.port()
+    }
+
+    /// How the connections the client opened so far introduced themselves.
+    #[must_use]
+    pub fn introductions(&self) -> Vec<PeerInitMessage> {
+        self.state.introductions.all()
+    }
+
+    /// The requests received so far, on any connection.
+    #[must_use]
+    pub fn requests(&self) -> Vec<PeerMessageOut> {
+        self.state.requests.all()
+    }
+
+    /// The first request that `matches`, waiting up to `timeout` for it to
+    /// arrive.
+    pub fn wait_for(
+        &self,
+        timeout: Duration,
+        matches: impl Fn(&PeerMessageOut) -> bool,
+    ) -> Option<PeerMessageOut> {
+        self.state.requests.wait_for(timeout, matches)
+    }
+
+    /// Open a `P` connection to a client listening on `port`, introduce
+    /// ourselves and send `messages`, e.g. search results. The connection
+    /// is then answered from the script like one the client opened.
+    ///
+    /// # Errors
+    /// Returns the error connecting or writing.
+    pub fn connect_to(
+        &self,
+        port: u16,
+        messages: impl IntoIterator<Item = PeerMessageIn>,
+    ) -> io::Result<()> {
+        let init = PeerInitMessage::PeerInit {
+            username: self.state.script.username.clone(),
+            connection_type: ConnectionType::P,
+            token: 0,
+        };
+        self.open(port, &init, messages)
+    }
+
+    /// Answer the client's `ConnectToPeer` with `token`, brokered by the
+    /// server, with a `PierceFirewall` to a client listening on `port`.
+    /// The connection is then answered from the script like one the client
+    /// opened.
+    ///
+    /// # Errors
+    /// Returns the error connecting or writing.
+    pub fn pierce(&self, port: u16, token: u32) -> io::Result<()> {
+        self.open(port, &PeerInitMessage::PierceFirewall { token }, [])
+    }
+
+    fn open(
+        &self,
+        port: u16,
+        init: &PeerInitMessage,
+        messages: impl IntoIterator<Item = PeerMessageIn>,
+    ) -> io::Result<()> {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+        self.state.connections.keep(&stream)?;
+        stream.write_all(&init.encode().get_buffer())?;
+        for message in messages {
+            stream.write_all(&message.encode().get_buffer())?;
+        }
+        let state = self.state.clone();
+        thread::spawn(move || state.serve(stream));
+        Ok(())
+    }
+}
+
+impl Drop for MockPeer {
+    fn drop(&mut self) {
+        self.state.connections.close(self.address);
+        if let Some(accepting) = self.accepting.take() {
+            let _ = accepting.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockPeerBuilder, MockServerBuilder};
+    use crate::message::{PeerMessageIn, ServerMessageOut};
+    use crate::types::{File, FileAttributes, SearchResult};
+    use crate::{Client, ClientSettings, SoulseekRs};
+    use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+    use std::time::Duration;
+
+    fn result(token: u32) -> SearchResult {
+        SearchResult {
+            token,
+            files: vec![File {
+                username: "bob".to_string(),
+                name: "Music\\Autechre\\Gantz Graf.flac".to_string(),
+                size: 1000,
+                attributes: FileAttributes::default(),
+                name_charset: None,
+                locked: false,
+            }],
+            free_slot: true,
+            speed: 100,
+            queue_length: 0,
+            username: "bob".to_string(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn a_client_logs_in_and_gets_results_from_a_peer() {
+        let listen_port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let peer = MockPeerBuilder::new("bob").build().unwrap();
+        let server = MockServerBuilder::new()
+            .on(26, move |request| {
+                if let ServerMessageOut::FileSearch { token, .. } = request {
+                    peer.connect_to(
+                        listen_port,
+                        [PeerMessageIn::FileSearchResponse(result(*token))],
+                    )
+                    .unwrap();
+                }
+                Vec::new()
+            })
+            .build()
+            .unwrap();
+
+        let mut client = Client::with_settings(ClientSettings {
+            server_address: server.address(),
+            enable_listen: true,
+            listen_port,
+            ..ClientSettings::new("alice", "pw")
+        });
+        client.connect().unwrap();
+        assert!(client.login().unwrap().is_accepted());
+        let found: Vec<_> = client
+            .search_stream("gantz graf", Duration::from_secs(5))
+            .unwrap()
+            .take(1)
+            .collect();
+        client.shutdown();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].username, "bob");
+        assert_eq!(found[0].files[0].size, 1000);
+        assert!(server.requests().iter().any(|request| matches!(
+            request,
+            ServerMessageOut::Login { username, .. } if username == "alice"
+        )));
+    }
+
+    #[test]
+    fn peer_addresses_come_from_the_server() {
+        let server = MockServerBuilder::new()
+            .peer("bob", SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 2234))
+            .build()
+            .unwrap();
+        let mut client = Client::with_settings(ClientSettings {
+            server_address: server.address(),
+            ..ClientSettings::new("alice", "pw")
+        });
+        client.connect().unwrap();
+        assert!(client.login().unwrap().is_accepted());
+
+        let address = client.get_peer_address("bob").unwrap();
+        let offline = client.get_peer_address("carol");
+        client.shutdown();
+
+        assert_eq!(address.to_string(), "10.0.0.7:2234");
+        assert!(matches!(
+            offline,
+            Err(SoulseekRs::PeerUnreachable { username }) if username == "carol"
+        ));
+    }
+}
@@ -0,0 +1,12 @@
+//! Utilities for testing against the library without the live network,
+//! behind the `testing` feature.
+//!
+//! [`capture`] records a session's messages and replays them through a
+//! dispatcher; [`mock`] stands in for the server and for peers, answering a
+//! client's requests from a script.
+
+pub mod capture;
+pub mod mock;
+
+pub use capture::{Capture, Frame, Recorder};
+pub use mock::{MockPeer, MockPeerBuilder, MockServer, MockServerBuilder};
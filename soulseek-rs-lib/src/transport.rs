@@ -0,0 +1,607 @@
+//! The byte streams the server and peer connections run over.
+//!
+//! Plain TCP, or TLS for private deployments that put their server behind a
+//! TLS terminator or whose clients all agree to talk to each other over TLS.
+//! TLS support needs the `tls` cargo feature (rustls). Peer TLS is off by
+//! default, since the official network's peers don't speak it.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// How a TLS-wrapped server connection is set up. The defaults verify the
+/// server against the bundled web PKI roots and send SNI.
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    /// Name sent as SNI and checked against the certificate. `None` uses the
+    /// server host from [`crate::ClientSettings::server_address`].
+    pub server_name: Option<String>,
+    /// Send the server name in the TLS handshake (SNI).
+    pub enable_sni: bool,
+    /// Reject certificates that aren't issued for `server_name`. Turning this
+    /// off still checks the chain, which suits self-signed private CAs that
+    /// are reached by IP.
+    pub verify_hostname: bool,
+    /// DER-encoded root certificates to trust instead of the web PKI roots.
+    pub root_certificates: Vec<Vec<u8>>,
+}
+
+impl Default for TlsSettings {
+    fn default() -> Self {
+        Self {
+            server_name: None,
+            enable_sni: true,
+            verify_hostname: true,
+            root_certificates: Vec::new(),
+        }
+    }
+}
+
+/// TLS between the cooperating clients of a private deployment. Every peer
+/// has to use it as well: a plain TCP peer fails the handshake both ways.
+#[derive(Debug, Clone, Default)]
+pub struct PeerTlsSettings {
+    /// How the peers we connect to are verified. `server_name: None` checks
+    /// their certificate against the IP address we dialled.
+    pub dial: TlsSettings,
+    /// DER-encoded certificate chain, leaf first, shown to peers that
+    /// connect to us.
+    pub certificate_chain: Vec<Vec<u8>>,
+    /// DER-encoded private key of the leaf certificate (PKCS#8, PKCS#1 or
+    /// SEC1).
+    pub private_key: Vec<u8>,
+}
+
+/// A connected server stream, TLS-wrapped or not.
+pub enum ServerStream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+}
+
+impl ServerStream {
+    /// Connect to `addr`, completing the TLS handshake (blocking) when `tls`
+    /// is set. `host` is the name the address was resolved from.
+    pub fn connect(
+        addr: SocketAddr,
+        host: &str,
+        tls: Option<&TlsSettings>,
+    ) -> io::Result<Self> {
+        let tcp = TcpStream::connect(addr)?;
+        match tls {
+            None => Ok(Self::Plain(tcp)),
+            #[cfg(feature = "tls")]
+            Some(settings) => tls::handshake(tcp, host, settings)
+                .map(|s| Self::Tls(Box::new(s))),
+            #[cfg(not(feature = "tls"))]
+            Some(_) => {
+                let _ = host;
+                Err(tls_unsupported())
+            }
+        }
+    }
+
+    const fn tcp(&self) -> &TcpStream {
+        match self {
+            Self::Plain(tcp) => tcp,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => &stream.sock,
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().peer_addr()
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.tcp().set_nonblocking(nonblocking)
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.tcp().set_nodelay(nodelay)
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for ServerStream {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.tcp().as_raw_fd()
+    }
+}
+
+impl Read for ServerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(tcp) => tcp.read(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for ServerStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(tcp) => tcp.write(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(tcp) => tcp.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A peer connection, TLS-wrapped or not. Connections we dial are the
+/// client side of the handshake, the ones we accept the server side.
+pub enum PeerStream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    TlsClient(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+    #[cfg(feature = "tls")]
+    TlsServer(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl PeerStream {
+    /// Connect to `addr` within `timeout`, completing the TLS handshake
+    /// (blocking) when `tls` is set.
+    pub fn connect(
+        addr: SocketAddr,
+        timeout: Duration,
+        tls: Option<&PeerTlsSettings>,
+    ) -> io::Result<Self> {
+        let tcp = TcpStream::connect_timeout(&addr, timeout)?;
+        match tls {
+            None => Ok(Self::Plain(tcp)),
+            #[cfg(feature = "tls")]
+            Some(settings) => {
+                tls::handshake(tcp, &addr.ip().to_string(), &settings.dial)
+                    .map(|s| Self::TlsClient(Box::new(s)))
+            }
+            #[cfg(not(feature = "tls"))]
+            Some(_) => Err(tls_unsupported()),
+        }
+    }
+
+    /// Take a connection accepted by our listener, completing the TLS
+    /// handshake (blocking) when `tls` is set.
+    pub fn accept(
+        tcp: TcpStream,
+        tls: Option<&PeerTlsSettings>,
+    ) -> io::Result<Self> {
+        match tls {
+            None => Ok(Self::Plain(tcp)),
+            #[cfg(feature = "tls")]
+            Some(settings) => {
+                tls::accept(tcp, settings).map(|s| Self::TlsServer(Box::new(s)))
+            }
+            #[cfg(not(feature = "tls"))]
+            Some(_) => Err(tls_unsupported()),
+        }
+    }
+
+    const fn tcp(&self) -> &TcpStream {
+        match self {
+            Self::Plain(tcp) => tcp,
+            #[cfg(feature = "tls")]
+            Self::TlsClient(stream) => &stream.sock,
+            #[cfg(feature = "tls")]
+            Self::TlsServer(stream) => &stream.sock,
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp().peer_addr()
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.tcp().set_nonblocking(nonblocking)
+    }
+
+    pub fn set_nodelay(&self, nodelay: bool) -> io::Result<()> {
+        self.tcp().set_nodelay(nodelay)
+    }
+
+    pub fn set_read_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        self.tcp().set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(
+        &self,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        self.tcp().set_write_timeout(timeout)
+    }
+}
+
+impl From<TcpStream> for PeerStream {
+    fn from(tcp: TcpStream) -> Self {
+        Self::Plain(tcp)
+    }
+}
+
+impl fmt::Debug for PeerStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Plain(_) => "Plain",
+            #[cfg(feature = "tls")]
+            Self::TlsClient(_) => "TlsClient",
+            #[cfg(feature = "tls")]
+            Self::TlsServer(_) => "TlsServer",
+        };
+        f.debug_tuple(kind).field(self.tcp()).finish()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for PeerStream {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.tcp().as_raw_fd()
+    }
+}
+
+impl Read for PeerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(tcp) => tcp.read(buf),
+            #[cfg(feature = "tls")]
+            Self::TlsClient(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Self::TlsServer(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for PeerStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(tcp) => tcp.write(buf),
+            #[cfg(feature = "tls")]
+            Self::TlsClient(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Self::TlsServer(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(tcp) => tcp.flush(),
+            #[cfg(feature = "tls")]
+            Self::TlsClient(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::TlsServer(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "TLS requested but soulseek-rs was built without the `tls` feature",
+    )
+}
+
+#[cfg(feature = "tls")]
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[cfg(feature = "tls")]
+mod tls {
+    use super::{HANDSHAKE_TIMEOUT, PeerTlsSettings, TlsSettings};
+    use rustls::client::WebPkiServerVerifier;
+    use rustls::client::danger::{
+        HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+    };
+    use rustls::pki_types::{
+        CertificateDer, PrivateKeyDer, ServerName, UnixTime,
+    };
+    use rustls::{
+        CertificateError, ClientConfig, ClientConnection, ConnectionCommon,
+        DigitallySignedStruct, RootCertStore, ServerConfig, ServerConnection,
+        SideData, SignatureScheme, StreamOwned,
+    };
+    use std::io;
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    /// Run the handshake in blocking mode so the (non-blocking) server actor
+    /// only ever sees an established session.
+    pub fn handshake(
+        mut tcp: TcpStream,
+        host: &str,
+        settings: &TlsSettings,
+    ) -> io::Result<StreamOwned<ClientConnection, TcpStream>> {
+        let name = settings.server_name.as_deref().unwrap_or(host);
+        let server_name = ServerName::try_from(name.to_string())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let mut connection = ClientConnection::new(
+            Arc::new(client_config(settings)?),
+            server_name,
+        )
+        .map_err(io::Error::other)?;
+        complete_handshake(&mut connection, &mut tcp)?;
+        Ok(StreamOwned::new(connection, tcp))
+    }
+
+    /// The server side of a peer handshake, for connections our listener
+    /// accepted.
+    pub fn accept(
+        mut tcp: TcpStream,
+        settings: &PeerTlsSettings,
+    ) -> io::Result<StreamOwned<ServerConnection, TcpStream>> {
+        let chain = settings
+            .certificate_chain
+            .iter()
+            .map(|der| CertificateDer::from(der.clone()))
+            .collect();
+        let key = PrivateKeyDer::try_from(settings.private_key.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let config = ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(io::Error::other)?;
+        let mut connection = ServerConnection::new(Arc::new(config))
+            .map_err(io::Error::other)?;
+        complete_handshake(&mut connection, &mut tcp)?;
+        Ok(StreamOwned::new(connection, tcp))
+    }
+
+    fn complete_handshake<Data: SideData>(
+        connection: &mut ConnectionCommon<Data>,
+        tcp: &mut TcpStream,
+    ) -> io::Result<()> {
+        tcp.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        tcp.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        while connection.is_handshaking() {
+            connection.complete_io(tcp)?;
+        }
+        tcp.set_read_timeout(None)?;
+        tcp.set_write_timeout(None)?;
+        Ok(())
+    }
+
+    fn client_config(settings: &TlsSettings) -> io::Result<ClientConfig> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        if settings.root_certificates.is_empty() {
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        } else {
+            for der in &settings.root_certificates {
+                roots
+                    .add(CertificateDer::from(der.clone()))
+                    .map_err(io::Error::other)?;
+            }
+        }
+        let verifier = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            provider.clone(),
+        )
+        .build()
+        .map_err(io::Error::other)?;
+
+        let builder = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+        let mut config = if settings.verify_hostname {
+            builder.with_webpki_verifier(verifier).with_no_client_auth()
+        } else {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(IgnoreHostname(
+                    verifier,
+                )))
+                .with_no_client_auth()
+        };
+        config.enable_sni = settings.enable_sni;
+        Ok(config)
+    }
+
+    /// Full web PKI verification, except that a certificate issued for a
+    /// different name is accepted.
+    #[derive(Debug)]
+    struct IgnoreHostname(Arc<WebPkiServerVerifier>);
+
+    impl ServerCertVerifier for IgnoreHostname {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            intermediates: &[CertificateDer<'_>],
+            server_name: &ServerName<'_>,
+            ocsp_response: &[u8],
+            now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            match self.0.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                ocsp_response,
+                now,
+            ) {
+                Err(rustls::Error::InvalidCertificate(
+                    CertificateError::NotValidForName
+                    | CertificateError::NotValidForNameContext { .. },
+                )) => Ok(ServerCertVerified::assertion()),
+                other => other,
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.0.verify_tls12_signature(message, cert, dss)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            self.0.verify_tls13_signature(message, cert, dss)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.supported_verify_schemes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PeerStream, PeerTlsSettings, ServerStream, TlsSettings};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    #[test]
+    fn plain_stream_round_trips_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client =
+            ServerStream::connect(addr, "localhost", None).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        assert_eq!(client.peer_addr().unwrap(), addr);
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn tls_without_the_feature_is_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let result = ServerStream::connect(
+            addr,
+            "localhost",
+            Some(&TlsSettings::default()),
+        );
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::Unsupported)
+        );
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_handshake_failure_is_an_error() {
+        // A peer that closes straight away can't complete a handshake.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let closer = std::thread::spawn(move || drop(listener.accept()));
+        let result = ServerStream::connect(
+            addr,
+            "localhost",
+            Some(&TlsSettings::default()),
+        );
+        closer.join().unwrap();
+        assert!(result.is_err());
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[test]
+    fn peer_tls_without_the_feature_is_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let result = PeerStream::connect(
+            addr,
+            Duration::from_secs(1),
+            Some(&PeerTlsSettings::default()),
+        );
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::Unsupported)
+        );
+    }
+
+    #[cfg(feature = "tls")]
+    fn peer_tls_settings() -> PeerTlsSettings {
+        let fixture = |name: &str| {
+            std::fs::read(
+                std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/fixtures")
+                    .join(name),
+            )
+            .unwrap()
+        };
+        PeerTlsSettings {
+            dial: TlsSettings {
+                root_certificates: vec![fixture("peer_ca.der")],
+                ..TlsSettings::default()
+            },
+            certificate_chain: vec![fixture("peer_cert.der")],
+            private_key: fixture("peer_key.der"),
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn peers_round_trip_bytes_over_tls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut stream =
+                PeerStream::accept(tcp, Some(&peer_tls_settings())).unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(b"pong").unwrap();
+            stream.flush().unwrap();
+            buf
+        });
+
+        // The certificate is issued for 127.0.0.1, the address we dial.
+        let mut client = PeerStream::connect(
+            addr,
+            Duration::from_secs(5),
+            Some(&peer_tls_settings()),
+        )
+        .unwrap();
+        assert!(matches!(client, PeerStream::TlsClient(_)));
+        client.write_all(b"ping").unwrap();
+        client.flush().unwrap();
+        let mut buf = [0; 4];
+        client.read_exact(&mut buf).unwrap();
+
+        assert_eq!(&buf, b"pong");
+        assert_eq!(&acceptor.join().unwrap(), b"ping");
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn a_plain_peer_fails_the_tls_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let acceptor = std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            PeerStream::accept(tcp, Some(&peer_tls_settings())).err()
+        });
+
+        let mut plain =
+            PeerStream::connect(addr, Duration::from_secs(5), None).unwrap();
+        plain
+            .write_all(b"\x05\x00\x00\x00\x00\x01\x02\x03")
+            .unwrap();
+        drop(plain);
+
+        assert!(acceptor.join().unwrap().is_some());
+    }
+}
@@ -1,6 +1,10 @@
-use std::{collections::HashMap, sync::mpsc::Sender};
+use std::{path::PathBuf, sync::mpsc::Sender};
 
-use crate::{error::Result, message::Message, utils::zlib::deflate};
+use crate::{
+    error::Result,
+    message::{Message, wire::Wire},
+    utils::{charset::Charset, zlib::deflate},
+};
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -8,16 +12,77 @@ pub struct File {
     pub username: String,
     pub name: String,
     pub size: u64,
-    pub attribs: HashMap<u32, u32>,
+    pub attributes: FileAttributes,
+    /// Set when the peer's file name wasn't UTF-8 and was repaired with
+    /// this fallback charset.
+    pub name_charset: Option<Charset>,
+    /// Listed in a search result's locked section: the peer shares it only
+    /// with users it trusts, so a download is likely to be denied.
+    pub locked: bool,
 }
+
+/// The audio attributes a peer reports for a shared file. Each is `None`
+/// when the peer left it out, which lossless files often do for bitrate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileAttributes {
+    /// Average bitrate in kbps.
+    pub bitrate: Option<u32>,
+    pub duration_seconds: Option<u32>,
+    /// Whether the bitrate is variable.
+    pub vbr: Option<bool>,
+    /// Sample rate in Hz.
+    pub sample_rate: Option<u32>,
+    /// Bits per sample.
+    pub bit_depth: Option<u32>,
+}
+
+impl FileAttributes {
+    const BITRATE: u32 = 0;
+    const DURATION: u32 = 1;
+    const VBR: u32 = 2;
+    const SAMPLE_RATE: u32 = 4;
+    const BIT_DEPTH: u32 = 5;
+
+    /// Collect the wire's `(code, value)` pairs. Unknown codes are skipped.
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (u32, u32)>) -> Self {
+        let mut attributes = Self::default();
+        for (code, value) in pairs {
+            match code {
+                Self::BITRATE => attributes.bitrate = Some(value),
+                Self::DURATION => attributes.duration_seconds = Some(value),
+                Self::VBR => attributes.vbr = Some(value != 0),
+                Self::SAMPLE_RATE => attributes.sample_rate = Some(value),
+                Self::BIT_DEPTH => attributes.bit_depth = Some(value),
+                _ => {}
+            }
+        }
+        attributes
+    }
+
+    /// The `(code, value)` pairs to send, in code order.
+    #[must_use]
+    pub fn to_pairs(&self) -> Vec<(u32, u32)> {
+        [
+            (Self::BITRATE, self.bitrate),
+            (Self::DURATION, self.duration_seconds),
+            (Self::VBR, self.vbr.map(u32::from)),
+            (Self::SAMPLE_RATE, self.sample_rate),
+            (Self::BIT_DEPTH, self.bit_depth),
+        ]
+        .into_iter()
+        .filter_map(|(code, value)| value.map(|value| (code, value)))
+        .collect()
+    }
+}
+
 pub struct UploadFailed {
     pub filename: String,
 }
 impl UploadFailed {
-    pub fn new_from_message(message: &mut Message) -> Self {
-        let filename = message.read_string();
+    pub fn new_from_message(message: &mut Message) -> Result<Self> {
+        let filename = message.try_read_string()?;
 
-        Self { filename }
+        Ok(Self { filename })
     }
 }
 #[derive(Debug, Clone)]
@@ -25,71 +90,105 @@ impl UploadFailed {
 pub struct SearchResult {
     pub token: u32,
     pub files: Vec<File>,
-    pub slots: u8,
+    /// Whether the peer has an upload slot free, so a download could start
+    /// right away.
+    pub free_slot: bool,
+    /// The peer's average upload speed, in bytes per second.
     pub speed: u32,
+    /// How many uploads the peer has queued ahead of a new request.
+    pub queue_length: u32,
     pub username: String,
+    /// Files the peer shares only with users it trusts, each marked
+    /// [`File::locked`]. Older clients send none.
+    pub locked_files: Vec<File>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Search {
     pub token: u32,
     pub results: Vec<SearchResult>,
+    /// When the search started or last received a result.
+    pub updated: std::time::Instant,
 }
 
 impl SearchResult {
     pub fn new_from_message(message: &mut Message) -> Result<Self> {
         let pointer = message.get_pointer();
         let size = message.get_size();
-        let data: Vec<u8> = message.get_slice(pointer, size);
-        let deflated = deflate(&data)?;
+        let deflated = deflate(message.slice(pointer, size))?;
         let mut message = Message::new_with_data(deflated);
 
-        let username = message.read_string();
-        let token = message.read_int32();
-        let n_files = message.read_int32();
-        let mut files: Vec<File> = Vec::new();
-        for _ in 0..n_files {
-            // Stop if a hostile n_files count outruns the payload, so a bogus
-            // length can't spin us into a huge allocation loop.
-            if message.get_pointer() >= message.get_size() {
-                break;
-            }
-            message.read_int8();
-            let name = message.read_string();
-            let size = message.read_int64();
-            message.read_string();
-            let n_attribs = message.read_int32();
-            let mut attribs: HashMap<u32, u32> = HashMap::new();
-
-            for _ in 0..n_attribs {
-                // Each attribute is two int32s (8 bytes); guard against a bogus
-                // count since read_int32 does not advance past the buffer end.
-                if message.get_pointer() + 8 > message.get_size() {
-                    break;
-                }
-                attribs.insert(message.read_int32(), message.read_int32());
-            }
-            files.push(File {
-                username: username.clone(),
-                name,
-                size,
-                attribs,
-            });
-        }
-        let slots = message.read_int8();
+        let username = message.try_read_string()?;
+        let token = message.try_read_int32()?;
+        let files = read_files(&mut message, &username, false)?;
+        // A response cut short after its file list still carries usable
+        // results, so the trailer defaults to zero when missing.
+        let free_slot = message.read_int8() != 0;
         let speed = message.read_int32();
+        let queue_length = message.read_int32();
+        // Newer clients follow with an unused int and the locked files. A
+        // damaged locked section doesn't cost us the public files.
+        let locked_files = if message.get_pointer() + 8 <= message.get_size() {
+            message.read_int32();
+            read_files(&mut message, &username, true).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
 
         Ok(Self {
             token,
             files,
-            slots,
+            free_slot,
             speed,
+            queue_length,
             username,
+            locked_files,
         })
     }
 }
 
-#[derive(Debug, Clone)]
+/// A count followed by that many file entries.
+fn read_files(
+    message: &mut Message,
+    username: &str,
+    locked: bool,
+) -> Result<Vec<File>> {
+    let n_files = message.try_read_int32()?;
+    let mut files: Vec<File> = Vec::new();
+    for _ in 0..n_files {
+        // Stop if a hostile n_files count outruns the payload, so a bogus
+        // length can't spin us into a huge allocation loop.
+        if message.get_pointer() >= message.get_size() {
+            break;
+        }
+        message.try_read_int8()?;
+        let (name, name_charset) = message.try_read_string_decoded()?;
+        let size = message.try_read_int64()?;
+        message.try_read_string()?;
+        let n_attribs = message.try_read_int32()?;
+        let mut pairs = Vec::new();
+
+        for _ in 0..n_attribs {
+            // Each attribute is two int32s (8 bytes); stop at a bogus
+            // count rather than rejecting the whole response.
+            if message.get_pointer() + 8 > message.get_size() {
+                break;
+            }
+            pairs.push((message.try_read_int32()?, message.try_read_int32()?));
+        }
+        files.push(File {
+            username: username.to_string(),
+            name,
+            size,
+            attributes: FileAttributes::from_pairs(pairs),
+            name_charset,
+            locked,
+        });
+    }
+    Ok(files)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[allow(dead_code)]
 pub struct Transfer {
     pub direction: u32,
@@ -119,11 +218,21 @@ pub struct Download {
 }
 
 impl Download {
+    /// Whether the download was cancelled with
+    /// [`Client::cancel_download`](crate::Client::cancel_download).
+    #[must_use]
+    pub const fn is_aborted(&self) -> bool {
+        matches!(
+            self.status,
+            DownloadStatus::Failed(Some(FailureReason::Aborted))
+        )
+    }
+
     #[must_use]
     pub const fn is_finished(&self) -> bool {
         matches!(
             self.status,
-            DownloadStatus::Completed
+            DownloadStatus::Completed(_)
                 | DownloadStatus::Failed(_)
                 | DownloadStatus::TimedOut
         )
@@ -138,7 +247,7 @@ impl Download {
             | DownloadStatus::Paused {
                 bytes_downloaded, ..
             } => *bytes_downloaded,
-            DownloadStatus::Completed => self.size,
+            DownloadStatus::Completed(_) => self.size,
             _ => 0,
         }
     }
@@ -153,6 +262,177 @@ impl Download {
             _ => 0.0,
         }
     }
+
+    /// The rolling average speed while in progress, otherwise 0.
+    #[must_use]
+    pub const fn average_speed_bytes_per_sec(&self) -> f64 {
+        match &self.status {
+            DownloadStatus::InProgress {
+                average_speed_bytes_per_sec,
+                ..
+            } => *average_speed_bytes_per_sec,
+            _ => 0.0,
+        }
+    }
+
+    /// Where and how fast a completed download was saved, if known.
+    #[must_use]
+    pub const fn summary(&self) -> Option<&DownloadSummary> {
+        match &self.status {
+            DownloadStatus::Completed(summary) => summary.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Estimated time left for an in-progress download, if known.
+    #[must_use]
+    pub const fn eta(&self) -> Option<std::time::Duration> {
+        match &self.status {
+            DownloadStatus::InProgress { eta, .. } => *eta,
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of a finished transfer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadSummary {
+    /// The path the file was written to, after name resolution. Empty for
+    /// a download written to a writer or read as a stream.
+    pub path: PathBuf,
+    /// Time from connecting to the peer until the file was on disk.
+    pub elapsed: std::time::Duration,
+    /// Mean throughput over `elapsed`, in bytes per second.
+    pub average_speed_bytes_per_sec: f64,
+    /// Hex MD5 of the whole file on disk, if it could be read back.
+    pub md5: Option<String>,
+}
+
+impl DownloadSummary {
+    #[must_use]
+    pub fn new(
+        path: PathBuf,
+        bytes: u64,
+        elapsed: std::time::Duration,
+    ) -> Self {
+        let secs = elapsed.as_secs_f64();
+        let average_speed_bytes_per_sec =
+            if secs > 0.0 { bytes as f64 / secs } else { 0.0 };
+        Self {
+            path,
+            elapsed,
+            average_speed_bytes_per_sec,
+            md5: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_md5(mut self, md5: impl Into<String>) -> Self {
+        self.md5 = Some(md5.into());
+        self
+    }
+}
+
+/// What to do when a download's file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and fail the download.
+    Skip,
+    /// Save under the first free name like `file (1).flac`.
+    Rename,
+    /// Treat the existing file as the start of this one and fetch only the
+    /// rest. A file already the download's size completes it as is; a larger
+    /// one is kept and the download saved under a free name, as with
+    /// [`ConflictPolicy::Rename`].
+    Resume,
+}
+
+/// Why a download failed.
+///
+/// Uploaders deny a queued file with `UploadDenied` (50, formerly
+/// `QueueFailed`) and a reason string; the common ones get their own
+/// variant, anything else is kept as [`FailureReason::Other`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureReason {
+    /// The uploader doesn't share the file ("File not shared.").
+    FileNotShared,
+    /// We queued more files than the uploader allows ("Too many files").
+    TooManyFiles,
+    /// We queued more data than the uploader allows ("Too many
+    /// megabytes").
+    TooManyMegabytes,
+    /// The uploader banned us ("Banned").
+    Banned,
+    /// The uploader doesn't share files of this type ("Disallowed
+    /// extension").
+    DisallowedExtension,
+    /// The uploader couldn't read the file ("File read error.").
+    FileReadError,
+    /// The uploader is shutting down ("Pending shutdown.").
+    PendingShutdown,
+    /// The uploader cancelled the transfer ("Cancelled").
+    Cancelled,
+    /// The uploader reported the transfer failed (`UploadFailed`, 46).
+    UploadFailed,
+    /// We cancelled the download ([`Client::cancel_download`]).
+    ///
+    /// [`Client::cancel_download`]: crate::Client::cancel_download
+    Aborted,
+    /// Anything else, in words.
+    Other(String),
+}
+
+impl FailureReason {
+    /// The variant for a reason string a peer sent with `UploadDenied`.
+    #[must_use]
+    pub fn from_peer(reason: &str) -> Self {
+        match reason.trim().trim_end_matches('.').to_lowercase().as_str() {
+            "file not shared" => Self::FileNotShared,
+            "too many files" => Self::TooManyFiles,
+            "too many megabytes" => Self::TooManyMegabytes,
+            "banned" => Self::Banned,
+            "disallowed extension" => Self::DisallowedExtension,
+            "file read error" => Self::FileReadError,
+            "pending shutdown" => Self::PendingShutdown,
+            "cancelled" => Self::Cancelled,
+            _ => Self::Other(reason.to_string()),
+        }
+    }
+}
+
+impl From<String> for FailureReason {
+    fn from(reason: String) -> Self {
+        Self::Other(reason)
+    }
+}
+
+impl From<&str> for FailureReason {
+    fn from(reason: &str) -> Self {
+        Self::Other(reason.to_string())
+    }
+}
+
+impl std::fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FileNotShared => write!(f, "File not shared"),
+            Self::TooManyFiles => write!(f, "Too many files queued"),
+            Self::TooManyMegabytes => write!(f, "Too many megabytes queued"),
+            Self::Banned => write!(f, "Banned by the user"),
+            Self::DisallowedExtension => write!(f, "File type not allowed"),
+            Self::FileReadError => write!(f, "The user cannot read the file"),
+            Self::PendingShutdown => write!(f, "The user is shutting down"),
+            Self::Cancelled => write!(f, "Cancelled by the uploader"),
+            Self::UploadFailed => {
+                write!(f, "The upload failed on the other side")
+            }
+            Self::Aborted => write!(f, "Cancelled by you"),
+            Self::Other(reason) => write!(f, "{reason}"),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -162,23 +442,35 @@ pub enum DownloadStatus {
     InProgress {
         bytes_downloaded: u64,
         total_bytes: u64,
+        /// Throughput over the last progress interval.
         speed_bytes_per_sec: f64,
+        /// Throughput over roughly the last ten seconds, steadier than
+        /// `speed_bytes_per_sec` for judging a source.
+        average_speed_bytes_per_sec: f64,
+        /// Time left at the current speed; `None` until a speed is known.
+        eta: Option<std::time::Duration>,
     },
     Paused {
         bytes_downloaded: u64,
         total_bytes: u64,
     },
-    Completed,
-    /// Failed, optionally with a human-readable reason.
-    Failed(Option<String>),
+    /// Finished and saved. The summary is `None` only for downloads that
+    /// were not transferred by this process (e.g. restored from disk).
+    Completed(Option<DownloadSummary>),
+    /// Failed, optionally with the reason.
+    Failed(Option<FailureReason>),
     TimedOut,
 }
 
-/// A public chat room advertised by the server (`RoomList`, code 64).
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A chat room advertised by the server (`RoomList`, code 64).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct RoomInfo {
     pub name: String,
     pub user_count: u32,
+    /// Only members may join.
+    pub private: bool,
+    /// A private room we own.
+    pub owned: bool,
 }
 
 /// Something that happened in the chat-room subsystem, surfaced to the client
@@ -201,28 +493,381 @@ pub enum RoomEvent {
     UserJoined { room: String, username: String },
     /// `username` left `room`.
     UserLeft { room: String, username: String },
+    /// Every member ticker in `room`, as `(username, ticker)`; sent on join.
+    Tickers {
+        room: String,
+        tickers: Vec<(String, String)>,
+    },
+    /// `username` set their ticker in `room`.
+    TickerSet {
+        room: String,
+        username: String,
+        ticker: String,
+    },
+    /// `username` cleared their ticker in `room`.
+    TickerRemoved { room: String, username: String },
 }
 
-impl Transfer {
-    pub fn new_from_message(message: &mut Message) -> Self {
-        let direction = message.read_int32();
-        let token = message.read_int32();
-        let filename = message.read_string();
-        let size = message.read_int64();
+/// The server connection, from `Client::connection_state`. Each change is
+/// also reported as [`ClientEvent::ConnectionStateChanged`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected. `reason` says why the connection was lost, and is
+    /// `None` before `connect` and after `shutdown`.
+    Disconnected {
+        reason: Option<String>,
+    },
+    Connecting,
+    /// Connected; login has not succeeded yet.
+    Connected,
+    LoggedIn,
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::Disconnected { reason: None }
+    }
+}
+
+/// A server notice not tied to a room or a user we looked up. Drained via
+/// `Client::take_events`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientEvent {
+    /// Our account logged in from somewhere else; the server is closing
+    /// this connection. Unfinished transfers were stopped, and the client
+    /// stays offline unless `ClientSettings::reconnect_when_relogged` is set.
+    Relogged,
+    /// A broadcast from the server administrators.
+    AdminMessage(String),
+    /// The server sent its list of privileged users, this many of them.
+    PrivilegedUsersUpdated(usize),
+    /// A user gained privileges.
+    PrivilegedUserAdded(String),
+    /// Our own privileges started, ended or were extended; how long they
+    /// now last.
+    PrivilegesChanged(std::time::Duration),
+    /// The server connection moved to a new state, e.g. when it drops and
+    /// the client reconnects.
+    ConnectionStateChanged(ConnectionState),
+    /// A favorite user came online, or was online when first watched.
+    FavoriteOnline(String),
+    /// The uploader of a queued or in-progress download disconnected, and
+    /// it was queued again from another user sharing the same file. Its
+    /// status channel carries on.
+    SourceReplaced {
+        username: String,
+        filename: String,
+        new_username: String,
+        new_filename: String,
+    },
+    /// We could connect to this user neither directly nor through the
+    /// server; their queued downloads failed.
+    PeerUnreachable(String),
+    /// Handling a client operation panicked, with this message. The
+    /// operation was dropped; later ones are still handled.
+    OperationPanicked(String),
+    /// The loop handling client operations stopped without a shutdown, for
+    /// this reason. The client no longer reacts to the network.
+    OperationsStopped(String),
+}
+
+/// The server's answer to our login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginOutcome {
+    Accepted {
+        /// The server's message of the day.
+        greeting: String,
+        /// Our public address as the server sees it. Older servers omit it.
+        ip: Option<std::net::Ipv4Addr>,
+        /// The MD5 hex digest of our password. Older servers omit it.
+        password_md5: Option<String>,
+    },
+    Rejected(LoginRejection),
+}
+
+impl LoginOutcome {
+    #[must_use]
+    pub const fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accepted { .. })
+    }
+}
+
+impl Wire for LoginOutcome {
+    fn write_to(&self, message: &mut Message) {
+        match self {
+            Self::Accepted {
+                greeting,
+                ip,
+                password_md5,
+            } => {
+                message.write_bool(true).write_string(greeting);
+                if let Some(ip) = ip {
+                    ip.write_to(message);
+                    message.write_string(
+                        password_md5.as_deref().unwrap_or_default(),
+                    );
+                }
+            }
+            Self::Rejected(rejection) => {
+                message.write_bool(false);
+                rejection.write_to(message);
+            }
+        }
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        if !message.try_read_bool()? {
+            return LoginRejection::read_from(message).map(Self::Rejected);
+        }
+        let greeting = message.try_read_string()?;
+        let (ip, password_md5) = if message.get_pointer() < message.get_size() {
+            (
+                Some(std::net::Ipv4Addr::read_from(message)?),
+                Some(message.try_read_string()?),
+            )
+        } else {
+            (None, None)
+        };
+        Ok(Self::Accepted {
+            greeting,
+            ip,
+            password_md5,
+        })
+    }
+}
 
+/// Why the server refused our login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginRejection {
+    /// The username is taken and the password does not match it.
+    InvalidPassword,
+    /// The server no longer accepts our client version.
+    InvalidVersion,
+    /// The username is not allowed, with the server's explanation if it
+    /// sent one.
+    InvalidUsername(Option<String>),
+    /// Any other reason, such as a ban notice, as the server worded it.
+    Other(String),
+}
+
+impl Wire for LoginRejection {
+    fn write_to(&self, message: &mut Message) {
+        match self {
+            Self::InvalidPassword => {
+                message.write_string("INVALIDPASS");
+            }
+            Self::InvalidVersion => {
+                message.write_string("INVALIDVERSION");
+            }
+            Self::InvalidUsername(detail) => {
+                message.write_string("INVALIDUSERNAME");
+                if let Some(detail) = detail {
+                    message.write_string(detail);
+                }
+            }
+            Self::Other(reason) => {
+                message.write_string(reason);
+            }
+        }
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        let reason = message.try_read_string()?;
+        Ok(match reason.as_str() {
+            "INVALIDPASS" => Self::InvalidPassword,
+            "INVALIDVERSION" => Self::InvalidVersion,
+            "INVALIDUSERNAME" => Self::InvalidUsername(
+                if message.get_pointer() < message.get_size() {
+                    Some(message.try_read_string()?)
+                } else {
+                    None
+                },
+            ),
+            _ => Self::Other(reason),
+        })
+    }
+}
+
+impl std::fmt::Display for LoginRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPassword => {
+                write!(f, "wrong password for this username")
+            }
+            Self::InvalidVersion => {
+                write!(f, "the server no longer accepts this client version")
+            }
+            Self::InvalidUsername(Some(detail)) => {
+                write!(f, "invalid username: {detail}")
+            }
+            Self::InvalidUsername(None) => write!(f, "invalid username"),
+            Self::Other(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+/// A user's presence as reported by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserStatus {
+    Offline,
+    Away,
+    Online,
+}
+
+impl UserStatus {
+    /// Map the server's status code (0 offline, 1 away, 2 online); unknown
+    /// codes are treated as offline.
+    #[must_use]
+    pub const fn from_code(code: u32) -> Self {
+        match code {
+            1 => Self::Away,
+            2 => Self::Online,
+            _ => Self::Offline,
+        }
+    }
+
+    /// The server's code for this status.
+    #[must_use]
+    pub const fn code(self) -> u32 {
+        match self {
+            Self::Offline => 0,
+            Self::Away => 1,
+            Self::Online => 2,
+        }
+    }
+}
+
+impl Wire for UserStatus {
+    fn write_to(&self, message: &mut Message) {
+        message.write_int32(self.code());
+    }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        Ok(Self::from_code(message.try_read_int32()?))
+    }
+}
+
+impl std::fmt::Display for UserStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Offline => write!(f, "offline"),
+            Self::Away => write!(f, "away"),
+            Self::Online => write!(f, "online"),
+        }
+    }
+}
+
+/// What we know about a user, merged from several replies.
+///
+/// Sources are the server's `WatchUser` (5), `GetUserStatus` (7) and
+/// `GetUserStats` (36) and the peer's own `UserInfoResponse` (16). Fields no
+/// reply has carried yet are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserInfo {
+    pub username: String,
+    /// Whether the server knows this account at all.
+    pub exists: Option<bool>,
+    pub status: Option<UserStatus>,
+    pub privileged: Option<bool>,
+    /// Average upload speed in bytes per second.
+    pub avg_speed: Option<u32>,
+    pub upload_count: Option<u32>,
+    pub shared_files: Option<u32>,
+    pub shared_folders: Option<u32>,
+    /// Two-letter country code, when the server reports one.
+    pub country: Option<String>,
+    pub description: Option<String>,
+    pub total_uploads: Option<u32>,
+    /// Length of the user's upload queue.
+    pub queue_length: Option<u32>,
+    pub free_slots: Option<bool>,
+}
+
+impl UserInfo {
+    #[must_use]
+    pub fn new(username: impl Into<String>) -> Self {
         Self {
+            username: username.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Overlay every field `update` carries onto `self`.
+    pub fn merge(&mut self, update: Self) {
+        fn take<T>(field: &mut Option<T>, value: Option<T>) {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+        take(&mut self.exists, update.exists);
+        take(&mut self.status, update.status);
+        take(&mut self.privileged, update.privileged);
+        take(&mut self.avg_speed, update.avg_speed);
+        take(&mut self.upload_count, update.upload_count);
+        take(&mut self.shared_files, update.shared_files);
+        take(&mut self.shared_folders, update.shared_folders);
+        take(&mut self.country, update.country);
+        take(&mut self.description, update.description);
+        take(&mut self.total_uploads, update.total_uploads);
+        take(&mut self.queue_length, update.queue_length);
+        take(&mut self.free_slots, update.free_slots);
+    }
+}
+
+impl Transfer {
+    pub fn new_from_message(message: &mut Message) -> Result<Self> {
+        let direction = message.try_read_int32()?;
+        let token = message.try_read_int32()?;
+        let filename = message.try_read_string()?;
+        // Only upload offers (direction 1) carry the file size.
+        let size = if direction == 1 {
+            message.try_read_int64()?
+        } else {
+            0
+        };
+
+        Ok(Self {
             direction,
             token,
             filename,
             size,
+        })
+    }
+}
+
+impl Wire for Transfer {
+    fn write_to(&self, message: &mut Message) {
+        message
+            .write_int32(self.direction)
+            .write_int32(self.token)
+            .write_string(&self.filename);
+        if self.direction == 1 {
+            message.write_int64(self.size);
         }
     }
+    fn read_from(message: &mut Message) -> Result<Self> {
+        Self::new_from_message(message)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn failure_reason_reads_the_peers_wording() {
+        assert_eq!(
+            FailureReason::from_peer("File not shared."),
+            FailureReason::FileNotShared
+        );
+        assert_eq!(
+            FailureReason::from_peer("Too many files"),
+            FailureReason::TooManyFiles
+        );
+        assert_eq!(FailureReason::from_peer("banned"), FailureReason::Banned);
+        assert_eq!(
+            FailureReason::from_peer("Away on holiday"),
+            FailureReason::Other("Away on holiday".to_string())
+        );
+    }
+
     // A FileSearchResponse whose n_files claims ~4 billion entries with no
     // file data must parse to an empty result promptly, not loop into an OOM.
     #[test]
@@ -239,22 +884,63 @@ mod tests {
         assert!(result.files.is_empty());
     }
 
-    // A truncated TransferRequest from an untrusted peer must parse to defaults
-    // rather than panic (the read_* primitives are bounds-checked).
     #[test]
-    fn transfer_new_from_truncated_message_does_not_panic() {
+    fn search_result_marks_repaired_file_names() {
+        let mut body = Message::new();
+        body.write_string("peer").write_int32(7).write_int32(2);
+        for name in [b"Caf\xe9.mp3".as_slice(), b"plain.mp3"] {
+            body.write_int8(1)
+                .write_int32(u32::try_from(name.len()).unwrap())
+                .write_raw_bytes(name)
+                .write_int64(1)
+                .write_string("mp3")
+                .write_int32(0);
+        }
+        let compressed = crate::utils::zlib::compress(body.get_data());
+        let mut message = Message::new_with_data(compressed);
+        let result = SearchResult::new_from_message(&mut message).unwrap();
+        // With the default fallback (Latin-1).
+        assert_eq!(result.files[0].name, "Café.mp3");
+        assert_eq!(result.files[0].name_charset, Some(Charset::Latin1));
+        assert_eq!(result.files[1].name_charset, None);
+    }
+
+    #[test]
+    fn file_attributes_round_trip_known_codes() {
+        let attributes =
+            FileAttributes::from_pairs([(1, 245), (2, 1), (4, 44100), (9, 3)]);
+        assert_eq!(
+            attributes,
+            FileAttributes {
+                duration_seconds: Some(245),
+                vbr: Some(true),
+                sample_rate: Some(44100),
+                ..FileAttributes::default()
+            }
+        );
+        // The unknown code 9 is dropped.
+        assert_eq!(attributes.to_pairs(), [(1, 245), (2, 1), (4, 44100)]);
+    }
+
+    // A truncated TransferRequest from an untrusted peer must be rejected
+    // rather than panic or be acted on with made-up fields.
+    #[test]
+    fn transfer_new_from_truncated_message_is_an_error() {
         let mut message = Message::new_with_data(vec![1, 0, 0]);
-        let transfer = Transfer::new_from_message(&mut message);
-        assert_eq!(transfer.direction, 0);
-        assert_eq!(transfer.token, 0);
-        assert_eq!(transfer.filename, "");
-        assert_eq!(transfer.size, 0);
+        assert!(matches!(
+            Transfer::new_from_message(&mut message),
+            Err(crate::SoulseekRs::Protocol { .. })
+        ));
     }
 }
 
 /// Lifecycle of a file we are serving to a peer.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UploadStatus {
+    /// Waiting for a free upload slot; `place` is 1-based.
+    Queued {
+        place: u32,
+    },
     InProgress,
     Completed,
     Cancelled,
@@ -0,0 +1,308 @@
+//! Decoding of peer-supplied strings that aren't UTF-8.
+//!
+//! Soulseek has no charset negotiation: clients send file and user names in
+//! whatever their platform uses, so old Windows clients send CP1252 or
+//! CP1251 and old Japanese ones Shift-JIS. When a string fails UTF-8
+//! validation, each configured fallback is tried and the most plausible
+//! decoding wins.
+//!
+//! The fallbacks are process-wide, like the log level; `Client` sets them
+//! from `ClientSettings::fallback_charsets`.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// A legacy charset to try when a string isn't valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Charset {
+    /// ISO-8859-1; decodes any byte, so it makes a safe last resort.
+    Latin1,
+    /// Windows Cyrillic.
+    Windows1251,
+    /// Windows Western European (Latin-1 plus typographic punctuation).
+    Windows1252,
+    /// Japanese. Only decodes with the `charsets` feature; without it this
+    /// fallback is skipped.
+    ShiftJis,
+}
+
+/// Used when no fallbacks were configured: the behaviour before fallbacks
+/// were configurable.
+const DEFAULT_FALLBACKS: [Charset; 1] = [Charset::Latin1];
+
+static FALLBACKS: RwLock<Option<Vec<Charset>>> = RwLock::new(None);
+
+/// Marks bytes a single-byte charset leaves unassigned.
+const UNDEFINED: char = '\u{FFFD}';
+
+/// CP1251 bytes 0x80-0xBF; 0xC0-0xFF map straight onto U+0410-U+044F.
+const WINDOWS_1251_HIGH: [char; 64] = [
+    '\u{0402}', '\u{0403}', '\u{201A}', '\u{0453}', '\u{201E}', '\u{2026}',
+    '\u{2020}', '\u{2021}', '\u{20AC}', '\u{2030}', '\u{0409}', '\u{2039}',
+    '\u{040A}', '\u{040C}', '\u{040B}', '\u{040F}', '\u{0452}', '\u{2018}',
+    '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    UNDEFINED, '\u{2122}', '\u{0459}', '\u{203A}', '\u{045A}', '\u{045C}',
+    '\u{045B}', '\u{045F}', '\u{00A0}', '\u{040E}', '\u{045E}', '\u{0408}',
+    '\u{00A4}', '\u{0490}', '\u{00A6}', '\u{00A7}', '\u{0401}', '\u{00A9}',
+    '\u{0404}', '\u{00AB}', '\u{00AC}', '\u{00AD}', '\u{00AE}', '\u{0407}',
+    '\u{00B0}', '\u{00B1}', '\u{0406}', '\u{0456}', '\u{0491}', '\u{00B5}',
+    '\u{00B6}', '\u{00B7}', '\u{0451}', '\u{2116}', '\u{0454}', '\u{00BB}',
+    '\u{0458}', '\u{0405}', '\u{0455}', '\u{0457}',
+];
+
+/// CP1252 bytes 0x80-0x9F; everything else matches Latin-1.
+const WINDOWS_1252_C1: [char; 32] = [
+    '\u{20AC}', UNDEFINED, '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}',
+    '\u{2020}', '\u{2021}', '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}',
+    '\u{0152}', UNDEFINED, '\u{017D}', UNDEFINED, UNDEFINED, '\u{2018}',
+    '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', UNDEFINED,
+    '\u{017E}', '\u{0178}',
+];
+
+impl Charset {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Latin1 => "latin1",
+            Self::Windows1251 => "cp1251",
+            Self::Windows1252 => "cp1252",
+            Self::ShiftJis => "shift_jis",
+        }
+    }
+
+    /// Decode `bytes`, or `None` if they aren't valid in this charset.
+    #[must_use]
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Self::Latin1 => {
+                Some(bytes.iter().map(|&b| char::from(b)).collect())
+            }
+            Self::Windows1251 => decode_single_byte(bytes, |b| match b {
+                0x80..=0xBF => WINDOWS_1251_HIGH[usize::from(b - 0x80)],
+                // U+0410 + (b - 0xC0), always a valid scalar value.
+                _ => char::from_u32(0x0350 + u32::from(b)).unwrap_or(UNDEFINED),
+            }),
+            Self::Windows1252 => decode_single_byte(bytes, |b| match b {
+                0x80..=0x9F => WINDOWS_1252_C1[usize::from(b - 0x80)],
+                _ => char::from(b),
+            }),
+            Self::ShiftJis => decode_shift_jis(bytes),
+        }
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Self::Latin1),
+            "cp1251" | "windows-1251" => Ok(Self::Windows1251),
+            "cp1252" | "windows-1252" => Ok(Self::Windows1252),
+            "shift-jis" | "sjis" | "cp932" => Ok(Self::ShiftJis),
+            _ => Err(format!("unknown charset: {s}")),
+        }
+    }
+}
+
+fn decode_single_byte(
+    bytes: &[u8],
+    high: impl Fn(u8) -> char,
+) -> Option<String> {
+    bytes
+        .iter()
+        .map(|&b| {
+            let c = if b < 0x80 { char::from(b) } else { high(b) };
+            (c != UNDEFINED).then_some(c)
+        })
+        .collect()
+}
+
+#[cfg(feature = "charsets")]
+fn decode_shift_jis(bytes: &[u8]) -> Option<String> {
+    encoding_rs::SHIFT_JIS
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(std::borrow::Cow::into_owned)
+}
+
+#[cfg(not(feature = "charsets"))]
+const fn decode_shift_jis(_bytes: &[u8]) -> Option<String> {
+    None
+}
+
+/// Replace the process-wide fallback charsets, tried in order. An empty
+/// list turns repair off: invalid bytes become U+FFFD.
+pub fn set_fallbacks(charsets: &[Charset]) {
+    if let Ok(mut fallbacks) = FALLBACKS.write() {
+        *fallbacks = Some(charsets.to_vec());
+    }
+}
+
+/// The fallback charsets currently in effect.
+#[must_use]
+pub fn fallbacks() -> Vec<Charset> {
+    FALLBACKS
+        .read()
+        .ok()
+        .and_then(|fallbacks| fallbacks.clone())
+        .unwrap_or_else(|| DEFAULT_FALLBACKS.to_vec())
+}
+
+/// Decode a peer-supplied string, returning the fallback charset used if it
+/// wasn't UTF-8.
+#[must_use]
+pub fn decode(bytes: &[u8]) -> (String, Option<Charset>) {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (s.to_string(), None),
+        Err(_) => decode_with(bytes, &fallbacks()),
+    }
+}
+
+/// Decode non-UTF-8 `bytes` with whichever of `fallbacks` gives the most
+/// plausible text (the earliest wins a tie). If none applies, the bytes are
+/// decoded as lossy UTF-8 and no charset is reported.
+#[must_use]
+pub fn decode_with(
+    bytes: &[u8],
+    fallbacks: &[Charset],
+) -> (String, Option<Charset>) {
+    let mut best: Option<(i64, String, Charset)> = None;
+    for &charset in fallbacks {
+        let Some(text) = charset.decode(bytes) else {
+            continue;
+        };
+        let score = plausibility(&text);
+        if best
+            .as_ref()
+            .is_none_or(|(best_score, ..)| score > *best_score)
+        {
+            best = Some((score, text, charset));
+        }
+    }
+    best.map_or_else(
+        || (String::from_utf8_lossy(bytes).into_owned(), None),
+        |(_, text, charset)| (text, Some(charset)),
+    )
+}
+
+/// How much `text` looks like a real name rather than a misread. Misreads
+/// give away C1 control characters, runs of accented letters (Cyrillic read
+/// as Latin-1), or Cyrillic glued to ASCII words (Latin-1 read as CP1251).
+/// Japanese scores per byte, as each character took two.
+fn plausibility(text: &str) -> i64 {
+    let mut score = 0;
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        score += match c {
+            '\u{80}'..='\u{9F}' | '\u{FFFD}' => -10,
+            c if c.is_ascii() => 0,
+            c if is_accented_latin(c) => {
+                if prev.is_some_and(is_accented_latin) {
+                    -2
+                } else {
+                    1
+                }
+            }
+            c if is_cyrillic(c) => {
+                if prev.is_some_and(|p| p.is_ascii_alphabetic()) {
+                    -2
+                } else {
+                    1
+                }
+            }
+            '\u{FF61}'..='\u{FF9F}' => 1,
+            c if is_japanese(c) => 2,
+            _ => 0,
+        };
+        prev = Some(c);
+    }
+    score
+}
+
+const fn is_accented_latin(c: char) -> bool {
+    matches!(c, '\u{C0}'..='\u{24F}') && c != '\u{D7}' && c != '\u{F7}'
+}
+
+const fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{400}'..='\u{4FF}')
+}
+
+const fn is_japanese(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{30FF}' | '\u{4E00}'..='\u{9FFF}' | '\u{FF01}'..='\u{FF5E}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Charset, decode_with};
+
+    /// "Привет.mp3" as CP1251.
+    const RUSSIAN: &[u8] = b"\xcf\xf0\xe8\xe2\xe5\xf2.mp3";
+    /// "Café del Mar.mp3" as Latin-1.
+    const FRENCH: &[u8] = b"Caf\xe9 del Mar.mp3";
+
+    #[test]
+    fn single_byte_tables_decode() {
+        assert_eq!(Charset::Windows1251.decode(RUSSIAN).unwrap(), "Привет.mp3");
+        assert_eq!(
+            Charset::Windows1252.decode(b"\x93quoted\x94 \x80").unwrap(),
+            "\u{201C}quoted\u{201D} \u{20AC}"
+        );
+        // 0x98 is unassigned in CP1251, 0x81 in CP1252.
+        assert!(Charset::Windows1251.decode(b"\x98").is_none());
+        assert!(Charset::Windows1252.decode(b"\x81").is_none());
+        assert_eq!(Charset::Latin1.decode(b"\x81").unwrap(), "\u{81}");
+    }
+
+    #[test]
+    fn most_plausible_fallback_wins_whatever_the_order() {
+        let both = [Charset::Latin1, Charset::Windows1251];
+        let reversed = [Charset::Windows1251, Charset::Latin1];
+        for fallbacks in [both, reversed] {
+            assert_eq!(
+                decode_with(RUSSIAN, &fallbacks),
+                ("Привет.mp3".to_string(), Some(Charset::Windows1251))
+            );
+            assert_eq!(
+                decode_with(FRENCH, &fallbacks),
+                ("Café del Mar.mp3".to_string(), Some(Charset::Latin1))
+            );
+        }
+    }
+
+    #[test]
+    fn without_fallbacks_bytes_are_replaced() {
+        assert_eq!(
+            decode_with(FRENCH, &[]),
+            ("Caf\u{FFFD} del Mar.mp3".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn parses_common_charset_names() {
+        assert_eq!("Windows-1251".parse(), Ok(Charset::Windows1251));
+        assert_eq!("ISO-8859-1".parse(), Ok(Charset::Latin1));
+        assert_eq!("shift_jis".parse(), Ok(Charset::ShiftJis));
+        assert!("ebcdic".parse::<Charset>().is_err());
+    }
+
+    #[cfg(feature = "charsets")]
+    #[test]
+    fn shift_jis_beats_single_byte_misreads() {
+        // "日本語.mp3" as Shift-JIS.
+        let japanese = b"\x93\xfa\x96\x7b\x8c\xea.mp3";
+        let fallbacks =
+            [Charset::Latin1, Charset::Windows1251, Charset::ShiftJis];
+        assert_eq!(
+            decode_with(japanese, &fallbacks),
+            ("日本語.mp3".to_string(), Some(Charset::ShiftJis))
+        );
+    }
+}
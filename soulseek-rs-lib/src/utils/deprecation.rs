@@ -0,0 +1,95 @@
+//! Runtime warnings for APIs kept only for compatibility.
+//!
+//! An old entry point calls [`warn_once`] with its [`Deprecation`] entry, so
+//! downstream users see a single structured log line naming its replacement
+//! the first time they use it. [`DEPRECATIONS`] lists every entry, and
+//! [`changelog`] renders them for the release notes.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::utils::lock::MutexExt;
+
+/// An API that still works but has a replacement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deprecation {
+    /// The old API, e.g. `Client::new`.
+    pub api: &'static str,
+    /// The release that deprecated it.
+    pub since: &'static str,
+    /// What to use instead.
+    pub replacement: &'static str,
+}
+
+pub const CLIENT_NEW: Deprecation = Deprecation {
+    api: "Client::new",
+    since: "5.1.0",
+    replacement: "Client::with_settings(ClientSettings::new(username, password))",
+};
+
+pub const CLIENT_SEARCH: Deprecation = Deprecation {
+    api: "Client::search",
+    since: "5.1.0",
+    replacement: "Client::search_stream, which yields results as they arrive",
+};
+
+/// Every deprecated API, oldest first.
+pub const DEPRECATIONS: &[Deprecation] = &[CLIENT_NEW, CLIENT_SEARCH];
+
+static WARNED: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+
+/// Log `deprecation` unless it has already been logged by this process.
+/// Returns whether it was logged.
+pub fn warn_once(deprecation: &Deprecation) -> bool {
+    let Ok(mut warned) = WARNED.lock_safe() else {
+        return false;
+    };
+    if !warned.get_or_insert_default().insert(deprecation.api) {
+        return false;
+    }
+    crate::warn!(
+        "[deprecated] api={} since={} replacement={}",
+        deprecation.api,
+        deprecation.since,
+        deprecation.replacement
+    );
+    true
+}
+
+/// The "Deprecated" section of the release notes, one bullet per entry.
+#[must_use]
+pub fn changelog() -> String {
+    let mut changelog = String::new();
+    for d in DEPRECATIONS {
+        let _ = writeln!(
+            changelog,
+            "- `{}` (since {}): use {}",
+            d.api, d.since, d.replacement
+        );
+    }
+    changelog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warns_once_per_api() {
+        let deprecation = Deprecation {
+            api: "test::warns_once_per_api",
+            since: "0.0.0",
+            replacement: "nothing",
+        };
+        assert!(warn_once(&deprecation));
+        assert!(!warn_once(&deprecation));
+    }
+
+    #[test]
+    fn changelog_lists_every_deprecation() {
+        let changelog = changelog();
+        assert_eq!(changelog.lines().count(), DEPRECATIONS.len());
+        assert!(changelog.starts_with("- `Client::new` (since 5.1.0): use "));
+    }
+}
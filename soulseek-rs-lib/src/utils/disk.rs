@@ -0,0 +1,97 @@
+//! Free space on the filesystem downloads are saved to.
+//!
+//! `statvfs` comes from the C library std already links, so this needs no
+//! extra dependency. Its layout is only declared for Linux; elsewhere
+//! [`available_space`] returns `None` and callers skip the check.
+
+use std::path::Path;
+
+/// Bytes an unprivileged process may still write on the filesystem holding
+/// `path`, or `None` if unknown. `path` need not exist yet: its nearest
+/// existing ancestor is asked.
+#[must_use]
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path
+        .ancestors()
+        .find(|dir| !dir.as_os_str().is_empty() && dir.exists())
+        .unwrap_or_else(|| Path::new("."));
+    imp::available_space(existing)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::{CString, c_char, c_int, c_ulong};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    #[cfg(target_env = "musl")]
+    type BlockCount = u64;
+    #[cfg(not(target_env = "musl"))]
+    type BlockCount = c_ulong;
+
+    /// The leading fields of `struct statvfs`; the padding covers the rest.
+    #[repr(C)]
+    struct StatVfs {
+        f_bsize: c_ulong,
+        f_frsize: c_ulong,
+        f_blocks: BlockCount,
+        f_bfree: BlockCount,
+        f_bavail: BlockCount,
+        rest: [u64; 16],
+    }
+
+    unsafe extern "C" {
+        fn statvfs(path: *const c_char, buf: *mut StatVfs) -> c_int;
+    }
+
+    pub fn available_space(path: &Path) -> Option<u64> {
+        let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat = StatVfs {
+            f_bsize: 0,
+            f_frsize: 0,
+            f_blocks: 0,
+            f_bfree: 0,
+            f_bavail: 0,
+            rest: [0; 16],
+        };
+        // SAFETY: `path` is NUL-terminated and `stat` is at least as large
+        // as the C struct.
+        if unsafe { statvfs(path.as_ptr(), &raw mut stat) } != 0 {
+            return None;
+        }
+        let block = if stat.f_frsize > 0 {
+            stat.f_frsize
+        } else {
+            stat.f_bsize
+        };
+        // `c_ulong` is only 32 bits wide on 32-bit targets.
+        #[allow(clippy::useless_conversion)]
+        Some(u64::from(stat.f_bavail).saturating_mul(u64::from(block)))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::path::Path;
+
+    pub const fn available_space(_path: &Path) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::available_space;
+
+    #[test]
+    fn missing_directories_report_their_filesystem() {
+        use std::path::Path;
+
+        let tmp = std::env::temp_dir();
+        let free = available_space(&tmp).unwrap();
+        assert!(free > 0);
+        let missing = tmp.join("soulseek-no-such-dir").join("nested");
+        assert!(available_space(&missing).is_some());
+        assert!(available_space(Path::new("relative/missing")).is_some());
+    }
+}
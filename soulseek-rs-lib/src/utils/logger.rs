@@ -1,9 +1,26 @@
+//! The crate's log macros (`error!` through `trace!`).
+//!
+//! Lines are filtered per subsystem by a [`LogFilter`] (the `LOG_LEVEL`
+//! environment variable, or [`set_log_filter`] at runtime) and written to
+//! stderr or a rotating log file (`LOG_FILE`, or [`set_log_file`] at
+//! runtime).
+//!
+//! With the `tracing` feature the macros emit [`tracing`] events instead,
+//! and work on a peer connection or a download runs inside a span carrying
+//! the peer's name (and the transfer token), so the application's
+//! subscriber does the filtering (by the events' module targets) and
+//! formatting. Lines forced out by
+//! `Client::set_peer_trace` still go to the built-in sinks.
+
 use std::env;
+use std::fmt;
 use std::fs::{File, OpenOptions};
-use std::io::Write;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{
-    Mutex, Once,
-    atomic::{AtomicBool, Ordering},
+    Mutex, Once, RwLock,
+    atomic::{AtomicBool, AtomicU8, Ordering},
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -15,46 +32,304 @@ pub enum LogLevel {
     Trace = 4,
 }
 
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "info" => Ok(Self::Info),
+            "debug" | "verbose" => Ok(Self::Debug),
+            "trace" => Ok(Self::Trace),
+            _ => Err(format!("unknown log level `{name}`")),
+        }
+    }
+}
+
+/// Which levels get logged, overall and per subsystem.
+///
+/// Written like `RUST_LOG`: `warn,peer=trace,server=info,zlib=warn`. A bare
+/// level sets the default; `name=level` applies to a subsystem (`peer`,
+/// `server`, `dispatcher`, `client`, `zlib`, ...) or to a module path such
+/// as `message::server`. The most specific match wins: a module path over
+/// a subsystem, and a longer path over a shorter one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    default: LogLevel,
+    directives: Vec<(String, LogLevel)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self::new(LogLevel::Warn)
+    }
+}
+
+impl LogFilter {
+    #[must_use]
+    pub const fn new(default: LogLevel) -> Self {
+        Self {
+            default,
+            directives: Vec::new(),
+        }
+    }
+
+    /// Log `target` (a subsystem or module path) at `level`.
+    #[must_use]
+    pub fn with_directive(
+        mut self,
+        target: impl Into<String>,
+        level: LogLevel,
+    ) -> Self {
+        self.directives.push((target.into(), level));
+        self
+    }
+
+    /// The most verbose level logged for the module at `module_path` (as
+    /// given by `module_path!()`).
+    #[must_use]
+    pub fn level_for(&self, module_path: &str) -> LogLevel {
+        // Directives may name the module with or without the crate.
+        let path = module_path.split_once("::").map_or("", |(_, path)| path);
+        let subsystem = subsystem(path);
+        self.directives
+            .iter()
+            .filter_map(|(target, level)| {
+                if is_module_prefix(target, path)
+                    || is_module_prefix(target, module_path)
+                {
+                    Some((target.len() + 1, *level))
+                } else {
+                    (target == subsystem).then_some((0, *level))
+                }
+            })
+            .max_by_key(|&(specificity, _)| specificity)
+            .map_or(self.default, |(_, level)| level)
+    }
+
+    /// The most verbose level anything is logged at.
+    fn max_level(&self) -> LogLevel {
+        self.directives
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut filter = Self::default();
+        for directive in spec.split(',').map(str::trim) {
+            if directive.is_empty() {
+                continue;
+            }
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    filter = filter
+                        .with_directive(target.trim(), level.trim().parse()?);
+                }
+                None => filter.default = directive.parse()?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.default)?;
+        for (target, level) in &self.directives {
+            write!(f, ",{target}={level:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `target` is the module at `path` or one of its parents.
+fn is_module_prefix(target: &str, path: &str) -> bool {
+    path.strip_prefix(target)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+/// The subsystem a module (its path below the crate root) logs as: the
+/// peer and server connections whichever layer they sit in, the dispatcher,
+/// each utility by its own name, and otherwise the top-level module.
+fn subsystem(path: &str) -> &str {
+    let mut segments = path.split("::");
+    match (segments.next(), segments.next()) {
+        (Some("actor"), Some("peer_actor" | "peer_registry"))
+        | (Some("message"), Some("peer")) => "peer",
+        (Some("actor"), Some("server_actor"))
+        | (Some("message"), Some("server")) => "server",
+        (Some("message"), Some("handlers")) => "dispatcher",
+        (Some("utils"), Some(utility)) => utility,
+        (Some(module), _) => module,
+        (None, _) => "",
+    }
+}
+
 static INIT: Once = Once::new();
-static mut LOG_LEVEL: LogLevel = LogLevel::Warn;
+static LOG_FILTER: RwLock<Option<LogFilter>> = RwLock::new(None);
+/// The filter's [`LogFilter::max_level`], so most disabled lines are
+/// skipped without taking the lock.
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Warn as u8);
 
 static BUFFER: Mutex<Vec<String>> = Mutex::new(Vec::new());
 static BUFFERING: AtomicBool = AtomicBool::new(false);
-static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+static LOG_FILE: Mutex<Option<FileSink>> = Mutex::new(None);
+
+/// A log file and when to rotate it.
+///
+/// Once a line would take the file past `max_bytes`, it is renamed to
+/// `<path>.1` (shifting older ones up to `<path>.<max_files>`, past which
+/// they are deleted) and a new file started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+    /// Rotated files kept besides the current one.
+    pub max_files: usize,
+}
+
+impl LogFile {
+    pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+    pub const DEFAULT_MAX_FILES: usize = 5;
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: Self::DEFAULT_MAX_BYTES,
+            max_files: Self::DEFAULT_MAX_FILES,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+}
+
+/// The open log file and how much of it is written.
+struct FileSink {
+    config: LogFile,
+    file: File,
+    len: u64,
+}
+
+impl FileSink {
+    fn open(config: LogFile) -> io::Result<Self> {
+        let file = open_append(&config.path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { config, file, len })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let needed = line.len() as u64 + 1;
+        if self.len > 0
+            && self.len + needed > self.config.max_bytes
+            && let Err(e) = self.rotate()
+        {
+            eprintln!("Failed to rotate {}: {e}", self.config.path.display());
+        }
+        if writeln!(self.file, "{line}").is_ok() {
+            self.len += needed;
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let max = self.config.max_files;
+        if max > 0 {
+            let _ = std::fs::remove_file(self.config.rotated(max));
+            for n in (1..max).rev() {
+                let _ = std::fs::rename(
+                    self.config.rotated(n),
+                    self.config.rotated(n + 1),
+                );
+            }
+            std::fs::rename(&self.config.path, self.config.rotated(1))?;
+        } else {
+            std::fs::remove_file(&self.config.path)?;
+        }
+        self.file = open_append(&self.config.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Write logs to `log_file` from now on, or back to stderr for `None`.
+/// Lines buffered while the TUI holds the screen go wherever the sink is
+/// when they are flushed.
+pub fn set_log_file(log_file: Option<LogFile>) -> io::Result<()> {
+    let sink = log_file.map(FileSink::open).transpose()?;
+    if let Ok(mut current) = LOG_FILE.lock() {
+        *current = sink;
+    }
+    Ok(())
+}
+
+/// Filter logs with `filter` from now on.
+pub fn set_log_filter(filter: LogFilter) {
+    if let Ok(mut current) = LOG_FILTER.write() {
+        MAX_LEVEL.store(filter.max_level() as u8, Ordering::Relaxed);
+        *current = Some(filter);
+    }
+}
+
+/// Whether a line at `level` from the module at `module_path` is logged.
+#[must_use]
+pub fn enabled(module_path: &str, level: LogLevel) -> bool {
+    if level as u8 > MAX_LEVEL.load(Ordering::Relaxed) {
+        return false;
+    }
+    LOG_FILTER.read().is_ok_and(|filter| {
+        filter
+            .as_ref()
+            .is_none_or(|filter| level <= filter.level_for(module_path))
+    })
+}
 
 pub fn init() {
     INIT.call_once(|| {
-        let level = env::var("LOG_LEVEL")
-            .or_else(|_| env::var("RUST_LOG"))
-            .unwrap_or_else(|_| "WARN".to_string())
-            .to_uppercase();
-
-        unsafe {
-            LOG_LEVEL = match level.as_str() {
-                "ERROR" => LogLevel::Error,
-                "INFO" => LogLevel::Info,
-                "DEBUG" | "VERBOSE" => LogLevel::Debug, // Map VERBOSE to DEBUG
-                "TRACE" => LogLevel::Trace,
-                _ => LogLevel::Warn, // "WARN" or default
-            };
+        // The filter from LOG_LEVEL, unless one was already set up with
+        // `set_log_filter`.
+        let unset = LOG_FILTER.read().is_ok_and(|filter| filter.is_none());
+        if unset {
+            let spec = env::var("LOG_LEVEL")
+                .or_else(|_| env::var("RUST_LOG"))
+                .unwrap_or_default();
+            set_log_filter(spec.parse().unwrap_or_else(|e| {
+                eprintln!("Ignoring LOG_LEVEL '{spec}': {e}");
+                LogFilter::default()
+            }));
         }
 
-        // Initialize log file if LOG_FILE env var is set
-        if let Ok(log_file_path) = env::var("LOG_FILE") {
-            match OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&log_file_path)
-            {
-                Ok(file) => {
-                    if let Ok(mut log_file) = LOG_FILE.lock() {
-                        *log_file = Some(file);
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Failed to open log file '{log_file_path}': {e}");
-                }
-            }
+        // Initialize log file if LOG_FILE env var is set, unless one was
+        // already set up with `set_log_file`.
+        if let Ok(log_file_path) = env::var("LOG_FILE")
+            && !has_log_file()
+            && let Err(e) = set_log_file(Some(LogFile::new(&log_file_path)))
+        {
+            eprintln!("Failed to open log file '{log_file_path}': {e}");
         }
     });
 }
@@ -84,107 +359,172 @@ fn has_log_file() -> bool {
     LOG_FILE.lock().is_ok_and(|f| f.is_some())
 }
 
+/// Log `message` at `level`, filtered by the default level.
 pub fn log(level: LogLevel, message: &str) {
-    unsafe {
-        if level <= LOG_LEVEL {
-            let level_str = match level {
-                LogLevel::Error => "\x1b[31mERROR\x1b[0m", // Red
-                LogLevel::Warn => "\x1b[33mWARN\x1b[0m",   // Yellow
-                LogLevel::Info => "\x1b[32mINFO\x1b[0m",   // Green
-                LogLevel::Debug => "\x1b[34mDEBUG\x1b[0m", // Blue
-                LogLevel::Trace => "\x1b[35mTRACE\x1b[0m", // Magenta
-            };
-
-            let now = std::time::SystemTime::now();
-            let datetime = now
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default();
-            let secs = datetime.as_secs();
-            let subsec_millis = datetime.subsec_millis();
-
-            // Format as YYYY-MM-DD HH:MM:SS.mmm
-            let days_since_epoch = secs / 86400;
-            let days_since_1970 = days_since_epoch as i32;
-
-            // Calculate year (approximately)
-            let mut year = 1970;
-            let mut remaining_days = days_since_1970;
-
-            while remaining_days >= 365 {
-                let is_leap =
-                    (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0);
-                let days_in_year = if is_leap { 366 } else { 365 };
-                if remaining_days >= days_in_year {
-                    remaining_days -= days_in_year;
-                    year += 1;
-                } else {
-                    break;
-                }
-            }
+    #[cfg(feature = "tracing")]
+    emit(level, message);
+    #[cfg(not(feature = "tracing"))]
+    if enabled("", level) {
+        write_line(level, message);
+    }
+}
 
-            // Calculate month and day (simplified)
-            let month_days = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
-            let is_leap =
-                (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0);
-            let mut month = 1;
-            let mut day = remaining_days + 1;
+/// Log a line from the module at `module_path`, formatting it only if that
+/// module logs `level`. What the macros expand to.
+pub fn log_in(module_path: &str, level: LogLevel, args: fmt::Arguments<'_>) {
+    if enabled(module_path, level) {
+        write_line(level, &args.to_string());
+    }
+}
 
-            for &days_in_month in &month_days {
-                let actual_days = if month == 2 && is_leap {
-                    29
-                } else {
-                    days_in_month
-                };
-                if day > actual_days {
-                    day -= actual_days;
-                    month += 1;
-                } else {
-                    break;
-                }
-            }
+/// Forward a line logged through [`log`] to `tracing`.
+#[cfg(feature = "tracing")]
+fn emit(level: LogLevel, message: &str) {
+    match level {
+        LogLevel::Error => tracing::error!("{message}"),
+        LogLevel::Warn => tracing::warn!("{message}"),
+        LogLevel::Info => tracing::info!("{message}"),
+        LogLevel::Debug => tracing::debug!("{message}"),
+        LogLevel::Trace => tracing::trace!("{message}"),
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing as __tracing;
+
+/// Keeps a span entered until dropped.
+#[cfg(feature = "tracing")]
+pub type SpanGuard = tracing::span::EnteredSpan;
+
+/// Keeps a span entered until dropped (nothing, without `tracing`).
+#[cfg(not(feature = "tracing"))]
+#[must_use]
+pub struct SpanGuard;
+
+/// Enter the span for work on the connection to a peer. `username` is only
+/// called when spans are recorded.
+#[cfg(feature = "tracing")]
+pub fn peer_span(username: impl FnOnce() -> String) -> SpanGuard {
+    tracing::debug_span!("peer", peer = username()).entered()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub fn peer_span(_username: impl FnOnce() -> String) -> SpanGuard {
+    SpanGuard
+}
+
+/// Enter the span for the transfer `token` from `username`.
+#[cfg(feature = "tracing")]
+pub fn download_span(username: &str, token: u32) -> SpanGuard {
+    tracing::debug_span!("download", peer = username, token).entered()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub const fn download_span(_username: &str, _token: u32) -> SpanGuard {
+    SpanGuard
+}
 
-            // Calculate time of day
-            let seconds_in_day = secs % 86400;
-            let hours = seconds_in_day / 3600;
-            let minutes = (seconds_in_day % 3600) / 60;
-            let seconds = seconds_in_day % 60;
-
-            let level_str_plain = match level {
-                LogLevel::Error => "ERROR",
-                LogLevel::Warn => "WARN",
-                LogLevel::Info => "INFO",
-                LogLevel::Debug => "DEBUG",
-                LogLevel::Trace => "TRACE",
-            };
-
-            let formatted_message = format!(
-                "[{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}:{seconds:02}.{subsec_millis:03}] [{level_str}] {message}"
-            );
-
-            let formatted_message_plain = format!(
-                "[{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}:{seconds:02}.{subsec_millis:03}] [{level_str_plain}] {message}"
-            );
-
-            match choose_sink(BUFFERING.load(Ordering::Relaxed), has_log_file())
+/// Write `message` whatever the configured level. Used for output the user
+/// asked for explicitly, such as a peer put under trace with
+/// `Client::set_peer_trace`.
+pub fn log_forced(level: LogLevel, message: &str) {
+    write_line(level, message);
+}
+
+fn write_line(level: LogLevel, message: &str) {
+    let level_str = match level {
+        LogLevel::Error => "\x1b[31mERROR\x1b[0m", // Red
+        LogLevel::Warn => "\x1b[33mWARN\x1b[0m",   // Yellow
+        LogLevel::Info => "\x1b[32mINFO\x1b[0m",   // Green
+        LogLevel::Debug => "\x1b[34mDEBUG\x1b[0m", // Blue
+        LogLevel::Trace => "\x1b[35mTRACE\x1b[0m", // Magenta
+    };
+
+    let now = std::time::SystemTime::now();
+    let datetime = now
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = datetime.as_secs();
+    let subsec_millis = datetime.subsec_millis();
+
+    // Format as YYYY-MM-DD HH:MM:SS.mmm
+    let days_since_epoch = secs / 86400;
+    let days_since_1970 = days_since_epoch as i32;
+
+    // Calculate year (approximately)
+    let mut year = 1970;
+    let mut remaining_days = days_since_1970;
+
+    while remaining_days >= 365 {
+        let is_leap = (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0);
+        let days_in_year = if is_leap { 366 } else { 365 };
+        if remaining_days >= days_in_year {
+            remaining_days -= days_in_year;
+            year += 1;
+        } else {
+            break;
+        }
+    }
+
+    // Calculate month and day (simplified)
+    let month_days = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let is_leap = (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0);
+    let mut month = 1;
+    let mut day = remaining_days + 1;
+
+    for &days_in_month in &month_days {
+        let actual_days = if month == 2 && is_leap {
+            29
+        } else {
+            days_in_month
+        };
+        if day > actual_days {
+            day -= actual_days;
+            month += 1;
+        } else {
+            break;
+        }
+    }
+
+    // Calculate time of day
+    let seconds_in_day = secs % 86400;
+    let hours = seconds_in_day / 3600;
+    let minutes = (seconds_in_day % 3600) / 60;
+    let seconds = seconds_in_day % 60;
+
+    let level_str_plain = match level {
+        LogLevel::Error => "ERROR",
+        LogLevel::Warn => "WARN",
+        LogLevel::Info => "INFO",
+        LogLevel::Debug => "DEBUG",
+        LogLevel::Trace => "TRACE",
+    };
+
+    let formatted_message = format!(
+        "[{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}:{seconds:02}.{subsec_millis:03}] [{level_str}] {message}"
+    );
+
+    let formatted_message_plain = format!(
+        "[{year:04}-{month:02}-{day:02} {hours:02}:{minutes:02}:{seconds:02}.{subsec_millis:03}] [{level_str_plain}] {message}"
+    );
+
+    match choose_sink(BUFFERING.load(Ordering::Relaxed), has_log_file()) {
+        LogSink::File => {
+            if let Ok(mut log_file) = LOG_FILE.lock()
+                && let Some(sink) = log_file.as_mut()
             {
-                LogSink::File => {
-                    if let Ok(mut log_file) = LOG_FILE.lock()
-                        && let Some(file) = log_file.as_mut()
-                    {
-                        let _ = writeln!(file, "{formatted_message_plain}");
-                        let _ = file.flush();
-                    }
-                }
-                LogSink::Buffer => {
-                    if let Ok(mut buffer) = BUFFER.lock() {
-                        buffer.push(formatted_message);
-                    }
-                }
-                LogSink::Stderr => {
-                    eprintln!("{formatted_message}");
-                }
+                sink.write_line(&formatted_message_plain);
+            }
+        }
+        LogSink::Buffer => {
+            if let Ok(mut buffer) = BUFFER.lock() {
+                buffer.push(formatted_message);
             }
         }
+        LogSink::Stderr => {
+            eprintln!("{formatted_message}");
+        }
     }
 }
 
@@ -202,13 +542,11 @@ pub fn flush_buffered_logs() {
     if let Ok(mut buffer) = BUFFER.lock() {
         // Write to file if configured, otherwise to stderr
         if let Ok(mut log_file) = LOG_FILE.lock() {
-            if let Some(file) = log_file.as_mut() {
+            if let Some(sink) = log_file.as_mut() {
                 for message in buffer.iter() {
                     // Strip ANSI codes for file output
-                    let plain_message = strip_ansi_codes(message);
-                    let _ = writeln!(file, "{plain_message}");
+                    sink.write_line(&strip_ansi_codes(message));
                 }
-                let _ = file.flush();
             } else {
                 for message in buffer.iter() {
                     eprintln!("{message}");
@@ -248,44 +586,109 @@ fn strip_ansi_codes(s: &str) -> String {
     result
 }
 
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::utils::logger::log_in(
+            module_path!(),
+            $crate::utils::logger::LogLevel::Error,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+#[cfg(feature = "tracing")]
 #[macro_export]
 macro_rules! error {
     ($($arg:tt)*) => {
-        $crate::utils::logger::log($crate::utils::logger::LogLevel::Error, &format!($($arg)*))
+        $crate::utils::logger::__tracing::error!($($arg)*)
     };
 }
 
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! warn {
     ($($arg:tt)*) => {
-        $crate::utils::logger::log($crate::utils::logger::LogLevel::Warn, &format!($($arg)*))
+        $crate::utils::logger::log_in(
+            module_path!(),
+            $crate::utils::logger::LogLevel::Warn,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::utils::logger::__tracing::warn!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::utils::logger::log_in(
+            module_path!(),
+            $crate::utils::logger::LogLevel::Info,
+            format_args!($($arg)*),
+        )
     };
 }
 
+#[cfg(feature = "tracing")]
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => {
-        $crate::utils::logger::log($crate::utils::logger::LogLevel::Info, &format!($($arg)*))
+        $crate::utils::logger::__tracing::info!($($arg)*)
     };
 }
 
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! debug {
     ($($arg:tt)*) => {
-        $crate::utils::logger::log($crate::utils::logger::LogLevel::Debug, &format!($($arg)*))
+        $crate::utils::logger::log_in(
+            module_path!(),
+            $crate::utils::logger::LogLevel::Debug,
+            format_args!($($arg)*),
+        )
     };
 }
 
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::utils::logger::__tracing::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
 #[macro_export]
 macro_rules! trace {
     ($($arg:tt)*) => {
-        $crate::utils::logger::log($crate::utils::logger::LogLevel::Trace, &format!($($arg)*))
+        $crate::utils::logger::log_in(
+            module_path!(),
+            $crate::utils::logger::LogLevel::Trace,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::utils::logger::__tracing::trace!($($arg)*)
     };
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{LogSink, choose_sink};
+    use super::{FileSink, LogFile, LogFilter, LogLevel, LogSink, choose_sink};
 
     #[test]
     fn a_configured_file_bypasses_buffering_so_lines_are_not_duplicated() {
@@ -301,4 +704,63 @@ mod tests {
         assert_eq!(choose_sink(true, false), LogSink::Buffer);
         assert_eq!(choose_sink(false, false), LogSink::Stderr);
     }
+
+    #[test]
+    fn filter_picks_the_most_specific_directive() {
+        let filter: LogFilter =
+            "info, peer=trace, server=warn, message::server::login=debug"
+                .parse()
+                .unwrap();
+        let level = |path| filter.level_for(path);
+        assert_eq!(level("soulseek_rs::client::search"), LogLevel::Info);
+        assert_eq!(level("soulseek_rs::peer::download_peer"), LogLevel::Trace);
+        assert_eq!(level("soulseek_rs::actor::peer_actor"), LogLevel::Trace);
+        assert_eq!(level("soulseek_rs::actor::server_actor"), LogLevel::Warn);
+        assert_eq!(
+            level("soulseek_rs::message::server::join_room"),
+            LogLevel::Warn
+        );
+        assert_eq!(
+            level("soulseek_rs::message::server::login"),
+            LogLevel::Debug
+        );
+        // `peer` names a module too, but not `peers`.
+        assert_eq!(level("soulseek_rs::peers"), LogLevel::Info);
+        assert_eq!(filter.max_level(), LogLevel::Trace);
+
+        let filter: LogFilter =
+            "soulseek_rs::utils=error,zlib=debug".parse().unwrap();
+        assert_eq!(
+            filter.level_for("soulseek_rs::utils::zlib"),
+            LogLevel::Error
+        );
+        assert_eq!(filter.level_for("soulseek_rs::dispatcher"), LogLevel::Warn);
+        assert!("peer=loud".parse::<LogFilter>().is_err());
+        assert_eq!(
+            "trace,zlib=warn".parse::<LogFilter>().unwrap().to_string(),
+            "Trace,zlib=Warn"
+        );
+    }
+
+    #[test]
+    fn file_sink_rotates_and_keeps_max_files() {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-logger-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = LogFile::new(dir.join("slsk.log"))
+            .with_max_bytes(10)
+            .with_max_files(2);
+        let mut sink = FileSink::open(config.clone()).unwrap();
+        for line in ["one", "two", "three", "four", "five", "six"] {
+            sink.write_line(line);
+        }
+        let read = |path| std::fs::read_to_string(path).unwrap();
+        // "one two" was rotated out past the two kept files.
+        assert_eq!(read(config.path.clone()), "six\n");
+        assert_eq!(read(config.rotated(1)), "four\nfive\n");
+        assert_eq!(read(config.rotated(2)), "three\n");
+        assert!(!config.rotated(3).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
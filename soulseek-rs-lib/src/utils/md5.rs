@@ -355,8 +355,9 @@ fn compute_md5_digest(mut v: Vec<u8>) -> String {
 * 3. With the last 64 bits, append the length in 64 bits
 *    (in lower-order bits first).
 */
-fn bit_padding(input: &str) -> Vec<u8> {
-    let mut input_vector: Vec<u8> = convert_str_to_vec(input);
+fn bit_padding(input: &[u8]) -> Vec<u8> {
+    let mut input_vector: Vec<u8> = Vec::with_capacity(input.len() + 72);
+    input_vector.extend_from_slice(input);
     let bit_length: u64 = (input.len() as u64) * 8u64;
 
     // 128_u8 is the equivalent of padding 1 as an unsigned 8-bit integer
@@ -396,14 +397,6 @@ fn construct_value_table() -> Vec<u32> {
     t
 }
 
-// this should only work with utf-8 encoding and not full unicode support
-// due to multi-byte unicode chars
-fn convert_str_to_vec(input: &str) -> Vec<u8> {
-    let mut byte_vec: Vec<u8> = Vec::new();
-    byte_vec.extend(input.as_bytes());
-    byte_vec
-}
-
 /**
 * A Basic Overview of this MD5 Implementation:
 * 1. Take in a command line string and convert into
@@ -423,6 +416,12 @@ fn convert_str_to_vec(input: &str) -> Vec<u8> {
 */
 #[must_use]
 pub fn md5(input: &str) -> String {
+    md5_bytes(input.as_bytes())
+}
+
+/// [`md5`] of arbitrary bytes, such as a downloaded file.
+#[must_use]
+pub fn md5_bytes(input: &[u8]) -> String {
     let input_vec = bit_padding(input);
     compute_md5_digest(input_vec)
 }
@@ -441,6 +440,14 @@ fn abc_correct_hash() {
     assert_eq!("900150983cd24fb0d6963f7d28e17f72", md5("abc"));
 }
 
+#[test]
+fn non_utf8_bytes_correct_hash() {
+    assert_eq!(
+        "60cdccd4000580a3c394b8ad6ea9b899",
+        md5_bytes(&[0xff, 0x00, 0x80])
+    );
+}
+
 #[test]
 fn message_digest_correct_hash() {
     assert_eq!("f96b697d7cb7938d525a2f31aaf161d0", md5("message digest"));
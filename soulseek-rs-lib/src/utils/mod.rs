@@ -1,10 +1,15 @@
 #[macro_use]
 pub mod logger;
+pub mod charset;
+pub mod deprecation;
+pub mod disk;
 pub mod lock;
 pub mod md5;
 pub mod path;
 pub mod thread_pool;
+pub mod token_bucket;
+pub mod token_generator;
 pub mod zlib;
 
 // Re-export commonly used items
-pub use md5::md5;
+pub use md5::{md5, md5_bytes};
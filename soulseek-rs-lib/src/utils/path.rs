@@ -1,4 +1,25 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Longest file name most filesystems accept, in bytes.
+pub const MAX_FILENAME_BYTES: usize = 255;
+
+/// Longest path the local OS opens without special handling, in bytes.
+pub const MAX_PATH_BYTES: usize = if cfg!(windows) { 260 } else { 4096 };
+
+/// Characters the local OS does not allow in a file name, besides control
+/// characters.
+const INVALID_CHARS: &[char] = if cfg!(windows) {
+    &['<', '>', ':', '"', '/', '\\', '|', '?', '*']
+} else {
+    &['/']
+};
+
+/// Device names Windows reserves whatever the extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6",
+    "COM7", "COM8", "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6",
+    "LPT7", "LPT8", "LPT9",
+];
 
 #[must_use]
 pub fn expand_tilde(path: &str) -> PathBuf {
@@ -10,6 +31,86 @@ pub fn expand_tilde(path: &str) -> PathBuf {
     PathBuf::from(path)
 }
 
+/// Make a peer-supplied name safe to create as a single file.
+///
+/// Characters the local OS rejects (path separators included) become `_`,
+/// names that would mean something else (`..`, `CON`) are changed, and the
+/// result is at most [`MAX_FILENAME_BYTES`] long, keeping the extension.
+#[must_use]
+pub fn sanitize_filename(name: &str) -> String {
+    let mut clean: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || INVALID_CHARS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    if cfg!(windows) {
+        // Windows silently drops these, so `a.` would overwrite `a`.
+        clean.truncate(clean.trim_end_matches(['.', ' ']).len());
+        let stem = clean.split('.').next().unwrap_or_default();
+        if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+            clean.insert(0, '_');
+        }
+    }
+    if clean.is_empty() || clean == "." || clean == ".." {
+        clean = "download".to_string();
+    }
+    truncate_filename(&clean, MAX_FILENAME_BYTES)
+}
+
+/// Where to save the peer file `name` inside `dir`: `dir` joined with the
+/// sanitized name, shortened so the whole path stays under
+/// [`MAX_PATH_BYTES`]. `None` if `dir` alone leaves no room for a name.
+#[must_use]
+pub fn download_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let name = sanitize_filename(name);
+    let room = MAX_PATH_BYTES
+        .checked_sub(dir.as_os_str().len() + 1)
+        .filter(|&room| room >= 16)?;
+    Some(dir.join(truncate_filename(&name, room)))
+}
+
+/// `path` itself if nothing is there, else the first of `name (1).ext`,
+/// `name (2).ext`, ... that is free.
+#[must_use]
+pub fn unused_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..=u32::MAX)
+        .map(|n| path.with_file_name(format!("{stem} ({n}){ext}")))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Cut `name` to at most `max` bytes on a character boundary, keeping a
+/// short extension.
+fn truncate_filename(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() < max / 2 => {
+            (stem, &name[stem.len()..])
+        }
+        _ => (name, ""),
+    };
+    let mut end = max - ext.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{ext}", &stem[..end])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -33,4 +134,45 @@ mod tests {
             PathBuf::from("/home/test/Downloads")
         );
     }
+
+    #[test]
+    fn sanitized_names_cannot_leave_the_directory() {
+        assert_eq!(sanitize_filename("01 Intro.flac"), "01 Intro.flac");
+        assert_eq!(sanitize_filename(".."), "download");
+        assert_eq!(sanitize_filename(""), "download");
+        assert_eq!(sanitize_filename("../../.bashrc"), ".._.._.bashrc");
+        assert_eq!(sanitize_filename("a\0b\n.mp3"), "a_b_.mp3");
+        let dir = Path::new("/music");
+        assert_eq!(
+            download_path(dir, ".."),
+            Some(PathBuf::from("/music/download"))
+        );
+    }
+
+    #[test]
+    fn unused_paths_count_up_before_the_extension() {
+        let dir = std::env::temp_dir()
+            .join(format!("soulseek-unused-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let song = dir.join("song.flac");
+        assert_eq!(unused_path(&song), song);
+        std::fs::write(&song, b"a").unwrap();
+        std::fs::write(dir.join("song (1).flac"), b"b").unwrap();
+        assert_eq!(unused_path(&song), dir.join("song (2).flac"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_names_are_cut_keeping_the_extension() {
+        let long = format!("{}.flac", "é".repeat(200));
+        let clean = sanitize_filename(&long);
+        assert!(clean.len() <= MAX_FILENAME_BYTES);
+        assert!(clean.ends_with("é.flac"), "{clean}");
+
+        let deep = PathBuf::from("/").join("d".repeat(MAX_PATH_BYTES - 40));
+        let path = download_path(&deep, &long).unwrap();
+        assert!(path.as_os_str().len() <= MAX_PATH_BYTES);
+        assert!(path.to_string_lossy().ends_with(".flac"));
+        assert_eq!(download_path(&deep.join("d".repeat(30)), "a.mp3"), None);
+    }
 }
@@ -1,106 +1,278 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
+use std::time::Duration;
 
 use crate::utils::lock::MutexExt;
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// A queued job and where it was queued from, for the panic log.
+struct Job {
+    run: Box<dyn FnOnce() + Send + 'static>,
+    origin: String,
+}
+
+/// What the workers share: the job queue, every worker thread spawned
+/// (including replacements) and the counts the pool is sized by.
+struct Shared {
+    name: String,
+    min: usize,
+    max: usize,
+    idle_timeout: Duration,
+    receiver: Mutex<mpsc::Receiver<Job>>,
+    threads: Mutex<Vec<thread::JoinHandle<()>>>,
+    /// Workers running.
+    workers: AtomicUsize,
+    /// Workers waiting for a job.
+    idle: AtomicUsize,
+    /// Jobs sent that no worker has taken yet.
+    queued: AtomicUsize,
+    next_id: AtomicUsize,
+}
+
+impl Shared {
+    /// Count one more worker if there is room for it.
+    fn reserve_worker(&self) -> bool {
+        self.workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .is_ok()
+    }
 
-enum Message {
-    NewJob(Job),
-    Terminate,
+    /// Count one worker fewer if that leaves at least `min`.
+    fn release_worker(&self) -> bool {
+        self.workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n > self.min).then(|| n - 1)
+            })
+            .is_ok()
+    }
 }
 
+/// Runs jobs on between `min` and `max` worker threads.
+///
+/// A job queued while no worker is idle starts another worker, up to `max`,
+/// so long-running jobs (each actor holds its worker for its lifetime)
+/// cannot starve the rest. Workers above `min` exit after `idle_timeout`
+/// without a job.
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Message>>,
+    shared: Arc<Shared>,
+    sender: Option<mpsc::Sender<Job>>,
 }
 
 impl ThreadPool {
+    pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// A pool of exactly `size` workers.
     #[must_use]
     pub fn new(size: usize) -> Self {
-        assert!(size > 0);
+        Self::with_name(size, "pool-worker")
+    }
 
-        let (sender, receiver) = mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
-        let mut workers = Vec::with_capacity(size);
+    /// A pool of exactly `size` workers, named `<name>-<n>` as shown by
+    /// debuggers and `ps`.
+    #[must_use]
+    pub fn with_name(size: usize, name: &str) -> Self {
+        Self::with_bounds(name, size, size, Self::DEFAULT_IDLE_TIMEOUT)
+    }
+
+    /// A pool that starts `min` workers named `<name>-<n>` and grows to at
+    /// most `max` while jobs wait.
+    #[must_use]
+    pub fn with_bounds(
+        name: &str,
+        min: usize,
+        max: usize,
+        idle_timeout: Duration,
+    ) -> Self {
+        assert!(max > 0 && min <= max);
 
-        for _ in 0..size {
-            workers.push(Worker::new(Arc::clone(&receiver)));
+        let (sender, receiver) = mpsc::channel();
+        let shared = Arc::new(Shared {
+            name: name.to_string(),
+            min,
+            max,
+            idle_timeout,
+            receiver: Mutex::new(receiver),
+            threads: Mutex::new(Vec::with_capacity(min)),
+            workers: AtomicUsize::new(0),
+            idle: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            next_id: AtomicUsize::new(0),
+        });
+        for _ in 0..min {
+            if shared.reserve_worker() {
+                spawn_worker(&shared, None);
+            }
         }
 
         Self {
-            workers,
+            shared,
             sender: Some(sender),
         }
     }
 
+    /// Worker threads currently running.
+    #[must_use]
+    pub fn workers(&self) -> usize {
+        self.shared.workers.load(Ordering::SeqCst)
+    }
+
+    /// Run `f` on a worker. A panic in it is logged with the caller's
+    /// location.
+    #[track_caller]
     pub fn execute<F>(&self, f: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
-        if let Some(ref sender) = self.sender {
-            let _ = sender.send(Message::NewJob(job));
+        self.execute_as(Location::caller().to_string(), f);
+    }
+
+    /// Run `f` on a worker, logging a panic in it as coming from `origin`.
+    pub fn execute_as<F>(&self, origin: impl Into<String>, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Job {
+            run: Box::new(f),
+            origin: origin.into(),
+        };
+        let Some(ref sender) = self.sender else {
+            return;
+        };
+        let queued = self.shared.queued.fetch_add(1, Ordering::SeqCst) + 1;
+        if sender.send(job).is_err() {
+            self.shared.queued.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+        if queued > self.shared.idle.load(Ordering::SeqCst)
+            && self.shared.reserve_worker()
+        {
+            spawn_worker(&self.shared, None);
         }
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        if let Some(sender) = self.sender.take() {
-            for _ in &self.workers {
-                let _ = sender.send(Message::Terminate);
-            }
-        }
+        // Workers finish the queued jobs, then see the channel closed.
+        self.sender.take();
 
-        // Ignore join errors: a worker whose job panicked returns Err here, but
-        // Drop must never itself panic (that would abort the process).
-        for worker in &mut self.workers {
-            if let Some(thread) = worker.thread.take() {
-                let _ = thread.join();
-            }
+        // Ignore join errors: Drop must never itself panic (that would abort
+        // the process).
+        let threads = self
+            .shared
+            .threads
+            .lock_safe()
+            .map(|mut threads| std::mem::take(&mut *threads))
+            .unwrap_or_default();
+        for thread in threads {
+            let _ = thread.join();
         }
     }
 }
 
-struct Worker {
-    thread: Option<thread::JoinHandle<()>>,
+/// Start a worker already counted in `shared.workers`, under a new id
+/// unless it replaces worker `id`.
+fn spawn_worker(shared: &Arc<Shared>, id: Option<usize>) {
+    let id =
+        id.unwrap_or_else(|| shared.next_id.fetch_add(1, Ordering::SeqCst));
+    let name = format!("{}-{id}", shared.name);
+    let for_thread = shared.clone();
+    let spawned = thread::Builder::new()
+        .name(name.clone())
+        .spawn(move || run_worker(&for_thread, id));
+    match spawned {
+        Ok(thread) => {
+            if let Ok(mut threads) = shared.threads.lock_safe() {
+                // Workers that shrank the pool are done with.
+                threads.retain(|thread| !thread.is_finished());
+                threads.push(thread);
+            }
+        }
+        Err(e) => {
+            shared.workers.fetch_sub(1, Ordering::SeqCst);
+            error!("[thread_pool] Failed to spawn {name}: {e}");
+        }
+    }
 }
 
-impl Worker {
-    fn new(receiver: Arc<Mutex<mpsc::Receiver<Message>>>) -> Self {
-        let thread = thread::spawn(move || {
-            loop {
-                let message = match receiver.lock_safe() {
-                    Ok(rx) => rx.recv(),
-                    Err(_) => break,
-                };
-                match message {
-                    // Contain a panicking job so it kills only that job, not the
-                    // worker: the pool has a fixed number of workers and a lost
-                    // one is never replaced. The lock is already released here,
-                    // so a panic cannot poison the shared receiver.
-                    Ok(Message::NewJob(job)) => {
-                        let _ = std::panic::catch_unwind(
-                            std::panic::AssertUnwindSafe(job),
-                        );
-                    }
-                    Ok(Message::Terminate) | Err(_) => break,
+fn run_worker(shared: &Arc<Shared>, id: usize) {
+    let _respawn = Respawn { shared, id };
+    loop {
+        shared.idle.fetch_add(1, Ordering::SeqCst);
+        let received = shared
+            .receiver
+            .lock_safe()
+            .map(|rx| rx.recv_timeout(shared.idle_timeout));
+        shared.idle.fetch_sub(1, Ordering::SeqCst);
+        match received {
+            // Contain a panicking job so it kills only that job, not the
+            // worker. The lock is already released here, so a panic cannot
+            // poison the shared receiver.
+            Ok(Ok(job)) => {
+                shared.queued.fetch_sub(1, Ordering::SeqCst);
+                if let Err(payload) =
+                    panic::catch_unwind(AssertUnwindSafe(job.run))
+                {
+                    error!(
+                        "[thread_pool] {}-{id}: job from {} panicked: {}",
+                        shared.name,
+                        job.origin,
+                        panic_message(payload.as_ref())
+                    );
                 }
             }
-        });
+            Ok(Err(mpsc::RecvTimeoutError::Timeout)) => {
+                // A job queued while this worker stopped counting as idle
+                // may not have started another one, so stay for it.
+                if shared.release_worker()
+                    && !(shared.queued.load(Ordering::SeqCst) > 0
+                        && shared.reserve_worker())
+                {
+                    return;
+                }
+            }
+            Ok(Err(mpsc::RecvTimeoutError::Disconnected)) | Err(_) => break,
+        }
+    }
+    shared.workers.fetch_sub(1, Ordering::SeqCst);
+}
 
-        Self {
-            thread: Some(thread),
+/// Replaces its worker if the thread unwinds past the job's `catch_unwind`,
+/// so the pool never loses a worker.
+struct Respawn<'a> {
+    shared: &'a Arc<Shared>,
+    id: usize,
+}
+
+impl Drop for Respawn<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            error!(
+                "[thread_pool] {}-{} died, respawning it",
+                self.shared.name, self.id
+            );
+            spawn_worker(self.shared, Some(self.id));
         }
     }
 }
 
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("(non-string payload)")
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ThreadPool;
-    use std::sync::mpsc;
-    use std::time::Duration;
+    use super::{ThreadPool, panic_message};
+    use std::sync::{Arc, Barrier, mpsc};
+    use std::time::{Duration, Instant};
 
     // A single panicking job must not permanently kill its worker: subsequent
     // jobs still need to run. Otherwise one malformed network message could
@@ -121,4 +293,52 @@ mod tests {
             "worker died after a panicking job"
         );
     }
+
+    #[test]
+    fn workers_are_named_after_the_pool() {
+        let pool = ThreadPool::with_name(2, "peer-worker");
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || {
+            let _ = tx.send(std::thread::current().name().map(String::from));
+        });
+        let name = rx.recv_timeout(Duration::from_secs(5)).unwrap().unwrap();
+        assert!(name.starts_with("peer-worker-"), "{name}");
+    }
+
+    #[test]
+    fn grows_while_jobs_wait_and_shrinks_back_when_idle() {
+        let pool = ThreadPool::with_bounds(
+            "test-worker",
+            1,
+            3,
+            Duration::from_millis(50),
+        );
+        assert_eq!(pool.workers(), 1);
+
+        // Three jobs that only finish together need three workers at once.
+        let barrier = Arc::new(Barrier::new(4));
+        for _ in 0..3 {
+            let barrier = barrier.clone();
+            pool.execute(move || {
+                barrier.wait();
+            });
+        }
+        barrier.wait();
+        assert!(pool.workers() <= 3);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pool.workers() > 1 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(pool.workers(), 1);
+    }
+
+    #[test]
+    fn panic_payloads_are_read_as_text() {
+        let payload =
+            std::panic::catch_unwind(|| panic!("at {}", 3)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "at 3");
+        let payload = std::panic::catch_unwind(|| panic!("plain")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "plain");
+    }
 }
@@ -0,0 +1,99 @@
+//! A token bucket for smoothing a flow (bytes, messages) to a target rate.
+
+use std::time::{Duration, Instant};
+
+/// Tokens refill continuously at `rate` per second up to `capacity`.
+///
+/// Callers either [`try_take`](Self::try_take) (non-blocking admission) or
+/// [`reserve`](Self::reserve), which always succeeds but may leave the bucket
+/// in debt and returns how long the caller should wait before proceeding.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket refilling at `rate` tokens per second, holding at most
+    /// `capacity` tokens.
+    #[must_use]
+    pub fn new(rate: f64, capacity: f64) -> Self {
+        Self {
+            rate: rate.max(f64::MIN_POSITIVE),
+            capacity,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens =
+            elapsed.mul_add(self.rate, self.tokens).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Take `amount` tokens if they are all available right now.
+    pub fn try_take(&mut self, amount: f64) -> bool {
+        self.refill();
+        if self.tokens >= amount {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take `amount` tokens unconditionally and return how long to wait until
+    /// the bucket is out of debt. Large requests (bigger than `capacity`) are
+    /// therefore paced rather than refused.
+    pub fn reserve(&mut self, amount: f64) -> Duration {
+        self.refill();
+        self.tokens -= amount;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    /// Tokens currently available (negative while in debt).
+    pub fn available(&mut self) -> f64 {
+        self.refill();
+        self.tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenBucket;
+    use std::time::Duration;
+
+    #[test]
+    fn try_take_drains_then_refuses() {
+        let mut bucket = TokenBucket::new(1.0, 3.0);
+        assert!(bucket.try_take(2.0));
+        assert!(bucket.try_take(1.0));
+        assert!(!bucket.try_take(1.0));
+    }
+
+    #[test]
+    fn reserve_paces_requests_larger_than_the_bucket() {
+        let mut bucket = TokenBucket::new(1000.0, 1000.0);
+        assert_eq!(bucket.reserve(1000.0), Duration::ZERO);
+        // 500 more tokens at 1000/s is about half a second of debt.
+        let wait = bucket.reserve(500.0);
+        assert!(wait > Duration::from_millis(400), "{wait:?}");
+        assert!(wait <= Duration::from_millis(500), "{wait:?}");
+    }
+
+    #[test]
+    fn refill_is_capped_at_capacity() {
+        let mut bucket = TokenBucket::new(1_000_000.0, 10.0);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(bucket.available() <= 10.0);
+    }
+}
@@ -0,0 +1,120 @@
+//! Random, unique tokens for searches and downloads, and what each was
+//! issued for.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+
+/// Tokens stay below this so they never collide with upload tokens, which
+/// are counted up from it.
+const TOKEN_LIMIT: u32 = 0x8000_0000;
+
+/// What a token was issued for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenOwner {
+    Search {
+        query: String,
+    },
+    Download {
+        username: String,
+        filename: String,
+    },
+    /// A search for another source of a download's file.
+    SourceSearch {
+        filename: String,
+    },
+}
+
+/// Issues non-zero tokens that are unpredictable and distinct from every
+/// token still held, and maps each back to its owner.
+///
+/// Randomness comes from a randomly keyed SipHash over a counter, so equal
+/// queries or file names get different tokens each time.
+#[derive(Debug, Default)]
+pub struct TokenGenerator {
+    keys: RandomState,
+    counter: u64,
+    issued: HashMap<u32, TokenOwner>,
+}
+
+impl TokenGenerator {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A fresh token for `owner`.
+    pub fn issue(&mut self, owner: TokenOwner) -> u32 {
+        loop {
+            let token = self.next_candidate();
+            if token != 0 && !self.issued.contains_key(&token) {
+                self.issued.insert(token, owner);
+                return token;
+            }
+        }
+    }
+
+    /// What `token` was issued for, while it is held.
+    #[must_use]
+    pub fn owner(&self, token: u32) -> Option<&TokenOwner> {
+        self.issued.get(&token)
+    }
+
+    /// Give `token` back, so it may be issued again.
+    pub fn release(&mut self, token: u32) -> Option<TokenOwner> {
+        self.issued.remove(&token)
+    }
+
+    /// Release every token `keep` returns false for.
+    pub fn retain(&mut self, mut keep: impl FnMut(u32, &TokenOwner) -> bool) {
+        self.issued.retain(|&token, owner| keep(token, owner));
+    }
+
+    /// How many tokens are held.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.issued.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.issued.is_empty()
+    }
+
+    fn next_candidate(&mut self) -> u32 {
+        self.counter += 1;
+        let mut hasher = self.keys.build_hasher();
+        hasher.write_u64(self.counter);
+        (hasher.finish() as u32) % TOKEN_LIMIT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TOKEN_LIMIT, TokenGenerator, TokenOwner};
+
+    fn search(query: &str) -> TokenOwner {
+        TokenOwner::Search {
+            query: query.to_string(),
+        }
+    }
+
+    #[test]
+    fn tokens_are_unique_and_map_back_to_their_owner() {
+        let mut tokens = TokenGenerator::new();
+        let first = tokens.issue(search("aphex twin"));
+        let second = tokens.issue(search("aphex twin"));
+        assert_ne!(first, second);
+        assert_eq!(tokens.owner(first), Some(&search("aphex twin")));
+
+        let issued: Vec<u32> =
+            (0..1000).map(|_| tokens.issue(search("x"))).collect();
+        assert!(issued.iter().all(|&t| t != 0 && t < TOKEN_LIMIT));
+        assert_eq!(tokens.len(), 1002);
+
+        assert_eq!(tokens.release(first), Some(search("aphex twin")));
+        assert_eq!(tokens.owner(first), None);
+        tokens.retain(|token, _| token != second);
+        assert_eq!(tokens.owner(second), None);
+        assert_eq!(tokens.len(), 1000);
+    }
+}
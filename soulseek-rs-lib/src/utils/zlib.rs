@@ -1,50 +1,82 @@
 //https://www.rfc-editor.org/rfc/rfc1950
 
-struct BitReader {
-    mem: Vec<u8>,
+use std::io::Write;
+
+/// Reads DEFLATE's least-significant-first bit stream through a 64-bit
+/// buffer, so Huffman decoding can look several bits ahead at once.
+struct BitReader<'a> {
+    mem: &'a [u8],
+    /// Next byte of `mem` not yet loaded into `bits`.
     pos: usize,
-    b: u8,
-    numbits: i32,
+    bits: u64,
+    nbits: u32,
 }
 
-impl BitReader {
-    const fn new(mem: Vec<u8>) -> Self {
+impl<'a> BitReader<'a> {
+    const fn new(mem: &'a [u8]) -> Self {
         Self {
             mem,
             pos: 0,
-            b: 0,
-            numbits: 0,
+            bits: 0,
+            nbits: 0,
         }
     }
 
-    fn read_byte(&mut self) -> std::result::Result<u8, String> {
-        self.numbits = 0; // discard unread bits
-        if self.pos >= self.mem.len() {
+    fn refill(&mut self) {
+        while self.nbits <= 56 {
+            let Some(&byte) = self.mem.get(self.pos) else {
+                break;
+            };
+            self.bits |= u64::from(byte) << self.nbits;
+            self.pos += 1;
+            self.nbits += 8;
+        }
+    }
+
+    /// The next `n` bits (n <= 32) without consuming them. Past the end of
+    /// the input the missing bits read as zero; [`Self::consume`] is what
+    /// reports running out.
+    fn peek(&mut self, n: u32) -> u32 {
+        if self.nbits < n {
+            self.refill();
+        }
+        (self.bits & ((1u64 << n) - 1)) as u32
+    }
+
+    fn consume(&mut self, n: u32) -> std::result::Result<(), String> {
+        if n > self.nbits {
             return Err("End of data".to_string());
         }
-        let b = self.mem[self.pos];
+        self.bits >>= n;
+        self.nbits -= n;
+        Ok(())
+    }
+
+    /// Drop the rest of a partially read byte and hand any whole buffered
+    /// bytes back to the input, for the byte-aligned reads below.
+    const fn align(&mut self) {
+        self.pos -= (self.nbits / 8) as usize;
+        self.bits = 0;
+        self.nbits = 0;
+    }
+
+    fn read_byte(&mut self) -> std::result::Result<u8, String> {
+        self.align(); // discard unread bits
+        let Some(&b) = self.mem.get(self.pos) else {
+            return Err("End of data".to_string());
+        };
         self.pos += 1;
         Ok(b)
     }
 
     fn read_bit(&mut self) -> std::result::Result<u8, String> {
-        if self.numbits <= 0 {
-            self.b = self.read_byte()?;
-            self.numbits = 8;
-        }
-        self.numbits -= 1;
-        // shift bit out of byte
-        let bit = self.b & 1;
-        self.b >>= 1;
-        Ok(bit)
+        Ok(self.read_bits(1)? as u8)
     }
 
     fn read_bits(&mut self, n: usize) -> std::result::Result<u32, String> {
-        let mut o = 0u32;
-        for i in 0..n {
-            o |= u32::from(self.read_bit()?) << i;
-        }
-        Ok(o)
+        let value = self.peek(n as u32);
+        self.consume(n as u32)?;
+        Ok(value)
     }
 
     fn read_bytes(&mut self, n: usize) -> std::result::Result<u32, String> {
@@ -55,13 +87,127 @@ impl BitReader {
         }
         Ok(o)
     }
+
+    /// The next `n` whole bytes, borrowed from the input.
+    fn read_slice(
+        &mut self,
+        n: usize,
+    ) -> std::result::Result<&'a [u8], String> {
+        self.align(); // discard unread bits
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.mem.len());
+        let Some(end) = end else {
+            return Err("End of data".to_string());
+        };
+        let slice = &self.mem[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+}
+
+/// Output is written to the sink in chunks of this size, so the decoder holds
+/// at most this plus the back-reference window in memory.
+const FLUSH_SIZE: usize = 64 * 1024;
+
+/// Decompressed output on its way to the sink. Only the last `WINDOW_SIZE`
+/// bytes (the furthest a back-reference can reach) must stay in memory;
+/// older bytes are checksummed and written out.
+struct Output<'w, W: Write> {
+    sink: &'w mut W,
+    buf: Vec<u8>,
+    written: u64,
+    checksum: Adler32,
+}
+
+impl<'w, W: Write> Output<'w, W> {
+    const fn new(sink: &'w mut W) -> Self {
+        Self {
+            sink,
+            buf: Vec::new(),
+            written: 0,
+            checksum: Adler32::new(),
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> std::result::Result<(), String> {
+        self.buf.push(byte);
+        if self.buf.len() >= WINDOW_SIZE + FLUSH_SIZE {
+            self.flush_keeping(WINDOW_SIZE)?;
+        }
+        Ok(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> std::result::Result<(), String> {
+        self.buf.extend_from_slice(bytes);
+        if self.buf.len() >= WINDOW_SIZE + FLUSH_SIZE {
+            self.flush_keeping(WINDOW_SIZE)?;
+        }
+        Ok(())
+    }
+
+    /// Repeat `length` bytes starting `distance` back.
+    fn copy_match(
+        &mut self,
+        distance: usize,
+        length: usize,
+    ) -> std::result::Result<(), String> {
+        if distance == 0 || distance > self.buf.len() {
+            return Err("Distance too large".to_string());
+        }
+        // An overlapping match repeats its own output, so copy at most
+        // `distance` bytes at a time.
+        let mut remaining = length;
+        while remaining > 0 {
+            let n = remaining.min(distance);
+            let start = self.buf.len() - distance;
+            self.buf.extend_from_within(start..start + n);
+            remaining -= n;
+        }
+        if self.buf.len() >= WINDOW_SIZE + FLUSH_SIZE {
+            self.flush_keeping(WINDOW_SIZE)?;
+        }
+        Ok(())
+    }
+
+    fn flush_keeping(
+        &mut self,
+        keep: usize,
+    ) -> std::result::Result<(), String> {
+        let n = self.buf.len().saturating_sub(keep);
+        for chunk in self.buf[..n].chunks(FLUSH_SIZE) {
+            self.sink
+                .write_all(chunk)
+                .map_err(|e| format!("Write failed: {e}"))?;
+            self.checksum.update(chunk);
+        }
+        self.written += n as u64;
+        self.buf.drain(..n);
+        Ok(())
+    }
+
+    /// Flush everything, returning the total length and its adler32.
+    fn finish(mut self) -> std::result::Result<(u64, u32), String> {
+        self.flush_keeping(0)?;
+        Ok((self.written, self.checksum.finish()))
+    }
 }
 
 use crate::error::{Result, SoulseekRs};
 
+/// Decompress a zlib stream.
 pub fn deflate(input: &[u8]) -> Result<Vec<u8>> {
-    let mut r = BitReader::new(input.to_vec());
-    let cmf = r.read_byte()?;
+    let mut out = Vec::new();
+    deflate_to(input, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress a zlib stream into `sink`, returning the number of bytes
+/// written. Output goes out in chunks as it is produced, so only a window of
+/// it is held in memory.
+///
+/// On error, `sink` may already hold part of the output.
+pub fn deflate_to<W: Write>(input: &[u8], sink: &mut W) -> Result<u64> {
+    let mut r = BitReader::new(input);
+    let cmf = r.read_byte().map_err(SoulseekRs::CompressionError)?;
     let cm = cmf & 15; // Compression method
     if cm != 8 {
         // only CM=8 is supported
@@ -71,7 +217,7 @@ pub fn deflate(input: &[u8]) -> Result<Vec<u8>> {
     if cinfo > 7 {
         return Err(SoulseekRs::CompressionError("invalid CINFO".to_string()));
     }
-    let flg = r.read_byte()?;
+    let flg = r.read_byte().map_err(SoulseekRs::CompressionError)?;
     if !(u32::from(cmf) * 256 + u32::from(flg)).is_multiple_of(31) {
         return Err(SoulseekRs::CompressionError(
             "CMF+FLG checksum failed".to_string(),
@@ -83,16 +229,26 @@ pub fn deflate(input: &[u8]) -> Result<Vec<u8>> {
             "preset dictionary not supported".to_string(),
         ));
     }
-    let out = inflate(&mut r).map_err(SoulseekRs::CompressionError)?; // decompress DEFLATE data
-    let _adler32 = r.read_bytes(4)?; // Adler-32 checksum (for this exercise, we ignore it)
-    Ok(out)
+    let mut out = Output::new(sink);
+    inflate(&mut r, &mut out).map_err(SoulseekRs::CompressionError)?; // decompress DEFLATE data
+    let (written, actual) =
+        out.finish().map_err(SoulseekRs::CompressionError)?;
+    let expected = r
+        .read_bytes(4)
+        .map_err(SoulseekRs::CompressionError)?
+        .swap_bytes(); // Adler-32, big-endian
+    if expected != actual {
+        return Err(SoulseekRs::ChecksumMismatch { expected, actual });
+    }
+    Ok(written)
 }
 
 /// Compress `data` into a valid zlib stream using only STORED blocks.
 ///
-/// No real compressor is needed: standard zlib decoders (and [`deflate`] above)
-/// accept a `0x78 0x01` header, one or more uncompressed DEFLATE "stored" blocks
-/// of at most `0xFFFF` bytes, then a big-endian adler32 checksum.
+/// Standard zlib decoders (and [`deflate`] above) accept a `0x78 0x01` header,
+/// one or more uncompressed DEFLATE "stored" blocks of at most `0xFFFF` bytes,
+/// then a big-endian adler32 checksum. [`compress`] falls back to this for
+/// input that doesn't shrink.
 #[must_use]
 pub fn compress_stored(data: &[u8]) -> Vec<u8> {
     let mut out = vec![0x78, 0x01];
@@ -114,102 +270,371 @@ pub fn compress_stored(data: &[u8]) -> Vec<u8> {
     out
 }
 
-/// RFC 1950 adler32 checksum.
-fn adler32(data: &[u8]) -> u32 {
-    let mut a: u32 = 1;
-    let mut b: u32 = 0;
-    for &byte in data {
-        a = (a + u32::from(byte)) % 65521;
-        b = (b + a) % 65521;
+/// Compress `data` into a zlib stream: LZ77 matching coded with the fixed
+/// Huffman tables of RFC 1951 section 3.2.6, in a single final block.
+///
+/// Soulseek payloads (file paths) are highly repetitive, so even fixed codes
+/// shrink them well. Input that doesn't compress falls back to stored blocks.
+#[must_use]
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    w.write_bits(1, 1); // BFINAL
+    w.write_bits(1, 2); // BTYPE=01, fixed Huffman
+    for token in lz77(data) {
+        match token {
+            Lz77Token::Literal(byte) => {
+                write_fixed_literal(&mut w, byte.into());
+            }
+            Lz77Token::Match { length, distance } => {
+                write_fixed_match(&mut w, length, distance);
+            }
+        }
+    }
+    write_fixed_literal(&mut w, 256); // end of block
+
+    let mut out = vec![0x78, 0x9C];
+    out.extend(w.finish());
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    let stored = compress_stored(data);
+    if stored.len() < out.len() {
+        stored
+    } else {
+        out
+    }
+}
+
+/// Writes bits least-significant first, as DEFLATE packs them.
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    nbits: u32,
+}
+
+impl BitWriter {
+    const fn new() -> Self {
+        Self {
+            out: Vec::new(),
+            acc: 0,
+            nbits: 0,
+        }
+    }
+
+    /// Append the low `n` bits of `value` (n <= 16).
+    fn write_bits(&mut self, value: u32, n: u32) {
+        self.acc |= (value & ((1 << n) - 1)) << self.nbits;
+        self.nbits += n;
+        while self.nbits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    /// Huffman codes are defined most-significant bit first, so they go out
+    /// reversed relative to ordinary values.
+    fn write_code(&mut self, code: u32, n: u32) {
+        self.write_bits(code.reverse_bits() >> (32 - n), n);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+fn write_fixed_literal(w: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => w.write_code(0x30 + symbol, 8),
+        144..=255 => w.write_code(0x190 + symbol - 144, 9),
+        256..=279 => w.write_code(symbol - 256, 7),
+        _ => w.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_fixed_match(w: &mut BitWriter, length: usize, distance: usize) {
+    let length = length as u32;
+    let li = LENGTH_BASE.iter().rposition(|&b| b <= length).unwrap_or(0);
+    write_fixed_literal(w, 257 + li as u32);
+    w.write_bits(length - LENGTH_BASE[li], LENGTH_EXTRA_BITS[li] as u32);
+
+    let distance = distance as u32;
+    let di = DISTANCE_BASE
+        .iter()
+        .rposition(|&b| b <= distance)
+        .unwrap_or(0);
+    w.write_code(di as u32, 5);
+    w.write_bits(distance - DISTANCE_BASE[di], DISTANCE_EXTRA_BITS[di] as u32);
+}
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// How many earlier positions with the same 3-byte prefix are tried per
+/// match; bounds the worst case on repetitive input.
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+enum Lz77Token {
+    Literal(u8),
+    Match { length: usize, distance: usize },
+}
+
+fn hash3(data: &[u8], i: usize) -> usize {
+    let v = u32::from(data[i]) << 16
+        | u32::from(data[i + 1]) << 8
+        | u32::from(data[i + 2]);
+    (v.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+const NONE: usize = usize::MAX;
+
+/// Record position `i` as the newest with its 3-byte prefix.
+fn insert_hash(data: &[u8], head: &mut [usize], prev: &mut [usize], i: usize) {
+    if i + MIN_MATCH <= data.len() {
+        let h = hash3(data, i);
+        prev[i] = head[h];
+        head[h] = i;
+    }
+}
+
+/// Greedy LZ77 over a 32 KiB window using hash chains.
+fn lz77(data: &[u8]) -> Vec<Lz77Token> {
+    let mut head = vec![NONE; 1 << HASH_BITS];
+    let mut prev = vec![NONE; data.len()];
+    let mut tokens = Vec::new();
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash3(data, i)];
+            let mut chain = 0;
+            while candidate != NONE
+                && i - candidate <= WINDOW_SIZE
+                && chain < MAX_CHAIN
+            {
+                let len = data[candidate..]
+                    .iter()
+                    .zip(&data[i..i + max_len])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best.0 {
+                    best = (len, i - candidate);
+                    if len == max_len {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+        }
+
+        if best.0 >= MIN_MATCH {
+            tokens.push(Lz77Token::Match {
+                length: best.0,
+                distance: best.1,
+            });
+            for j in i..i + best.0 {
+                insert_hash(data, &mut head, &mut prev, j);
+            }
+            i += best.0;
+        } else {
+            tokens.push(Lz77Token::Literal(data[i]));
+            insert_hash(data, &mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+    tokens
+}
+
+const ADLER_MOD: u32 = 65521;
+/// Bytes that can be summed before `b` must be reduced to stay within `u32`.
+const ADLER_NMAX: usize = 5552;
+
+/// RFC 1950 adler32 checksum, updated as data becomes available.
+struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    const fn new() -> Self {
+        Self { a: 1, b: 0 }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(ADLER_NMAX) {
+            for &byte in chunk {
+                self.a += u32::from(byte);
+                self.b += self.a;
+            }
+            self.a %= ADLER_MOD;
+            self.b %= ADLER_MOD;
+        }
+    }
+
+    const fn finish(&self) -> u32 {
+        (self.b << 16) | self.a
     }
-    (b << 16) | a
 }
 
-fn inflate(r: &mut BitReader) -> std::result::Result<Vec<u8>, String> {
+fn adler32(data: &[u8]) -> u32 {
+    let mut checksum = Adler32::new();
+    checksum.update(data);
+    checksum.finish()
+}
+
+fn inflate<W: Write>(
+    r: &mut BitReader,
+    out: &mut Output<W>,
+) -> std::result::Result<(), String> {
     let mut bfinal = 0;
-    let mut out = Vec::new();
     while bfinal == 0 {
         bfinal = r.read_bit()?;
         let btype = r.read_bits(2)?;
         match btype {
-            0 => inflate_block_no_compression(r, &mut out)?,
-            1 => inflate_block_fixed(r, &mut out)?,
-            2 => inflate_block_dynamic(r, &mut out)?,
+            0 => inflate_block_no_compression(r, out)?,
+            1 => inflate_block_fixed(r, out)?,
+            2 => inflate_block_dynamic(r, out)?,
             _ => return Err("invalid BTYPE".to_string()),
         }
     }
-    Ok(out)
+    Ok(())
 }
 
-fn inflate_block_no_compression(
+fn inflate_block_no_compression<W: Write>(
     r: &mut BitReader,
-    o: &mut Vec<u8>,
+    o: &mut Output<W>,
 ) -> std::result::Result<(), String> {
     let len = r.read_bytes(2)?;
     let _nlen = r.read_bytes(2)?;
-    for _ in 0..len {
-        o.push(r.read_byte()?);
-    }
-    Ok(())
+    o.extend(r.read_slice(len as usize)?)
 }
 
-#[derive(Clone)]
-struct Node {
-    symbol: Option<u32>,
-    left: Option<Box<Self>>,
-    right: Option<Box<Self>>,
+/// Longest code DEFLATE allows.
+const MAX_CODE_BITS: usize = 15;
+/// Input bits resolved by one lookup in [`Huffman::fast`]. Nearly every
+/// literal/length and distance code fits, and the table stays small enough
+/// to rebuild for each dynamic block.
+const FAST_BITS: u32 = 9;
+
+/// A canonical Huffman code, decoded by table lookup.
+///
+/// Codes of up to [`FAST_BITS`] bits are resolved by indexing `fast` with
+/// the next input bits; longer (rare) codes fall back to walking the
+/// per-length counts, which a canonical code is fully described by.
+struct Huffman {
+    /// `symbol << 4 | length` for each possible next `FAST_BITS` bits whose
+    /// code is that short; 0 where it is longer or unassigned.
+    fast: [u16; 1 << FAST_BITS],
+    /// How many codes there are of each length.
+    counts: [u16; MAX_CODE_BITS + 1],
+    /// Symbols ordered by code length, then value (i.e. by code).
+    symbols: Vec<u16>,
 }
 
-impl Node {
-    const fn new() -> Self {
-        Self {
-            symbol: None,
-            left: None,
-            right: None,
+impl Huffman {
+    /// Build the code where symbol `i` has length `lengths[i]` (0 = unused).
+    fn new(lengths: &[usize]) -> std::result::Result<Self, String> {
+        let mut counts = [0u16; MAX_CODE_BITS + 1];
+        for &len in lengths {
+            if len > MAX_CODE_BITS {
+                return Err("Invalid code length".to_string());
+            }
+            counts[len] += 1;
         }
-    }
-}
+        counts[0] = 0;
 
-struct HuffmanTree {
-    root: Node,
-}
+        // Reject over-subscribed codes; incomplete ones are allowed (a
+        // single distance code is legal) and fail only if an unused code
+        // turns up in the data.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+            if left < 0 {
+                return Err("Over-subscribed Huffman code".to_string());
+            }
+        }
 
-impl HuffmanTree {
-    const fn new() -> Self {
-        Self { root: Node::new() }
+        let mut next_code = [0u32; MAX_CODE_BITS + 1];
+        let mut offsets = [0usize; MAX_CODE_BITS + 1];
+        for len in 1..MAX_CODE_BITS {
+            next_code[len + 1] = (next_code[len] + u32::from(counts[len])) << 1;
+            offsets[len + 1] = offsets[len] + usize::from(counts[len]);
+        }
+
+        let mut fast = [0u16; 1 << FAST_BITS];
+        let mut symbols = vec![
+            0u16;
+            offsets[MAX_CODE_BITS]
+                + usize::from(counts[MAX_CODE_BITS])
+        ];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            symbols[offsets[len]] = symbol as u16;
+            offsets[len] += 1;
+
+            let code = next_code[len];
+            next_code[len] += 1;
+            if len as u32 <= FAST_BITS {
+                // Input arrives least-significant bit first, but codes are
+                // defined most-significant first: index by the reversed
+                // code, for every value of the bits that follow it.
+                let reversed = (code.reverse_bits() >> (32 - len)) as usize;
+                let entry = (symbol as u16) << 4 | len as u16;
+                for slot in (reversed..fast.len()).step_by(1 << len) {
+                    fast[slot] = entry;
+                }
+            }
+        }
+
+        Ok(Self {
+            fast,
+            counts,
+            symbols,
+        })
     }
 
-    fn insert(&mut self, codeword: u32, n: usize, symbol: u32) {
-        // Insert an entry into the tree mapping `codeword` of len `n` to `symbol`
-        let mut node = &mut self.root;
-        for i in (0..n).rev() {
-            let b = (codeword >> i) & 1;
-            let child = if b != 0 {
-                &mut node.right
-            } else {
-                &mut node.left
-            };
-            node = child.get_or_insert_with(|| Box::new(Node::new()));
+    fn decode(&self, r: &mut BitReader) -> std::result::Result<u32, String> {
+        let entry = self.fast[r.peek(FAST_BITS) as usize];
+        if entry != 0 {
+            r.consume(u32::from(entry & 15))?;
+            return Ok(u32::from(entry >> 4));
         }
-        node.symbol = Some(symbol);
+        self.decode_slow(r)
     }
-}
 
-fn decode_symbol(
-    r: &mut BitReader,
-    t: &HuffmanTree,
-) -> std::result::Result<u32, String> {
-    let mut node = &t.root;
-    while node.left.is_some() || node.right.is_some() {
-        let b = r.read_bit()?;
-        let next = if b != 0 { &node.right } else { &node.left };
-        // A degenerate/incomplete Huffman code (attacker-controlled) can point
-        // at a missing child; error out instead of unwrap-panicking.
-        node = next
-            .as_ref()
-            .ok_or_else(|| "Invalid Huffman code".to_string())?;
-    }
-    node.symbol.ok_or_else(|| "No symbol found".to_string())
+    /// Canonical decoding one length at a time: codes of each length are
+    /// consecutive, starting right after the previous length's last code.
+    fn decode_slow(
+        &self,
+        r: &mut BitReader,
+    ) -> std::result::Result<u32, String> {
+        let bits = r.peek(MAX_CODE_BITS as u32);
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0usize);
+        for len in 1..=MAX_CODE_BITS {
+            code |= (bits >> (len - 1)) & 1;
+            let count = u32::from(self.counts[len]);
+            if code - first < count {
+                r.consume(len as u32)?;
+                return Ok(u32::from(
+                    self.symbols[index + (code - first) as usize],
+                ));
+            }
+            index += count as usize;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        // A degenerate/incomplete Huffman code (attacker-controlled) can
+        // leave bit patterns unassigned.
+        Err("Invalid Huffman code".to_string())
+    }
 }
 
 const LENGTH_EXTRA_BITS: [usize; 29] = [
@@ -229,17 +654,17 @@ const DISTANCE_BASE: [u32; 30] = [
     769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
 ];
 
-fn inflate_block_data(
+fn inflate_block_data<W: Write>(
     r: &mut BitReader,
-    literal_length_tree: &HuffmanTree,
-    distance_tree: &HuffmanTree,
-    out: &mut Vec<u8>,
+    literal_length: &Huffman,
+    distance: &Huffman,
+    out: &mut Output<W>,
 ) -> std::result::Result<(), String> {
     loop {
-        let sym = decode_symbol(r, literal_length_tree)?;
+        let sym = literal_length.decode(r)?;
         if sym <= 255 {
             // Literal byte
-            out.push(sym as u8);
+            out.push(sym as u8)?;
         } else if sym == 256 {
             // End of block
             return Ok(());
@@ -251,55 +676,24 @@ fn inflate_block_data(
             }
             let length =
                 r.read_bits(LENGTH_EXTRA_BITS[sym_idx])? + LENGTH_BASE[sym_idx];
-            let dist_sym = decode_symbol(r, distance_tree)?;
+            let dist_sym = distance.decode(r)?;
             if dist_sym as usize >= DISTANCE_EXTRA_BITS.len() {
                 return Err("Invalid distance symbol".to_string());
             }
             let dist = r.read_bits(DISTANCE_EXTRA_BITS[dist_sym as usize])?
                 + DISTANCE_BASE[dist_sym as usize];
-            if dist as usize > out.len() {
-                return Err("Distance too large".to_string());
-            }
-            for _ in 0..length {
-                let idx = out.len() - dist as usize;
-                let byte = out[idx];
-                out.push(byte);
-            }
+            out.copy_match(dist as usize, length as usize)?;
         }
     }
 }
 
-fn bl_list_to_tree(bl: &[usize], alphabet: &[u32]) -> HuffmanTree {
-    let max_bits = *bl.iter().max().unwrap_or(&0);
-    let mut bl_count = vec![0; max_bits + 1];
-    for &bitlen in bl {
-        if bitlen != 0 {
-            bl_count[bitlen] += 1;
-        }
-    }
-
-    let mut next_code = vec![0; max_bits + 1];
-    for bits in 2..=max_bits {
-        next_code[bits] = (next_code[bits - 1] + bl_count[bits - 1]) << 1;
-    }
-
-    let mut t = HuffmanTree::new();
-    for (i, &bitlen) in bl.iter().enumerate() {
-        if bitlen != 0 && i < alphabet.len() {
-            t.insert(next_code[bitlen], bitlen, alphabet[i]);
-            next_code[bitlen] += 1;
-        }
-    }
-    t
-}
-
 const CODE_LENGTH_CODES_ORDER: [usize; 19] = [
     16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
 ];
 
 fn decode_trees(
     r: &mut BitReader,
-) -> std::result::Result<(HuffmanTree, HuffmanTree), String> {
+) -> std::result::Result<(Huffman, Huffman), String> {
     // The number of literal/length codes
     let hlit = r.read_bits(5)? + 257;
 
@@ -316,15 +710,13 @@ fn decode_trees(
             r.read_bits(3)? as usize;
     }
 
-    // Construct code length tree
-    let code_length_alphabet: Vec<u32> = (0..19).collect();
-    let code_length_tree =
-        bl_list_to_tree(&code_length_tree_bl, &code_length_alphabet);
+    // Construct code length code
+    let code_length_code = Huffman::new(&code_length_tree_bl)?;
 
     // Read literal/length + distance code length list
     let mut bl = Vec::new();
     while bl.len() < (hlit + hdist) as usize {
-        let sym = decode_symbol(r, &code_length_tree)?;
+        let sym = code_length_code.decode(r)?;
         if sym <= 15 {
             // literal value
             bl.push(sym as usize);
@@ -352,29 +744,27 @@ fn decode_trees(
         }
     }
 
-    // Construct trees
-    let literal_length_alphabet: Vec<u32> = (0..286).collect();
-    let literal_length_tree =
-        bl_list_to_tree(&bl[..hlit as usize], &literal_length_alphabet);
+    // A final repeat may run past the declared count; ignore the excess.
+    bl.truncate((hlit + hdist) as usize);
 
-    let distance_alphabet: Vec<u32> = (0..30).collect();
-    let distance_tree =
-        bl_list_to_tree(&bl[hlit as usize..], &distance_alphabet);
+    // Construct codes
+    let literal_length = Huffman::new(&bl[..hlit as usize])?;
+    let distance = Huffman::new(&bl[hlit as usize..])?;
 
-    Ok((literal_length_tree, distance_tree))
+    Ok((literal_length, distance))
 }
 
-fn inflate_block_dynamic(
+fn inflate_block_dynamic<W: Write>(
     r: &mut BitReader,
-    o: &mut Vec<u8>,
+    o: &mut Output<W>,
 ) -> std::result::Result<(), String> {
-    let (literal_length_tree, distance_tree) = decode_trees(r)?;
-    inflate_block_data(r, &literal_length_tree, &distance_tree, o)
+    let (literal_length, distance) = decode_trees(r)?;
+    inflate_block_data(r, &literal_length, &distance, o)
 }
 
-fn inflate_block_fixed(
+fn inflate_block_fixed<W: Write>(
     r: &mut BitReader,
-    o: &mut Vec<u8>,
+    o: &mut Output<W>,
 ) -> std::result::Result<(), String> {
     let mut bl = Vec::new();
     bl.extend(vec![8; 144]); // 0-143: 8 bits
@@ -382,14 +772,10 @@ fn inflate_block_fixed(
     bl.extend(vec![7; 24]); // 256-279: 7 bits
     bl.extend(vec![8; 8]); // 280-287: 8 bits
 
-    let literal_length_alphabet: Vec<u32> = (0..286).collect();
-    let literal_length_tree = bl_list_to_tree(&bl, &literal_length_alphabet);
-
-    let bl_dist = vec![5; 30];
-    let distance_alphabet: Vec<u32> = (0..30).collect();
-    let distance_tree = bl_list_to_tree(&bl_dist, &distance_alphabet);
+    let literal_length = Huffman::new(&bl)?;
+    let distance = Huffman::new(&[5; 30])?;
 
-    inflate_block_data(r, &literal_length_tree, &distance_tree, o)
+    inflate_block_data(r, &literal_length, &distance, o)
 }
 
 #[cfg(test)]
@@ -398,15 +784,45 @@ mod tests {
     use super::*;
 
     #[test]
-    fn decode_symbol_on_degenerate_tree_errors_instead_of_panicking() {
-        // A malformed Huffman table from an untrusted peer can produce a node
-        // with only one child. Inserting codeword "0" gives the root a left
-        // child but no right child; decoding a '1' bit must error rather than
-        // unwrap-panic.
-        let mut tree = HuffmanTree::new();
-        tree.insert(0, 1, 42);
-        let mut reader = BitReader::new(vec![0b0000_0001]); // first bit = 1
-        assert!(decode_symbol(&mut reader, &tree).is_err());
+    fn decode_on_degenerate_code_errors_instead_of_panicking() {
+        // A malformed Huffman table from an untrusted peer can leave codes
+        // unassigned. Giving only symbol 42 a length assigns it code "0";
+        // decoding a '1' bit must error rather than panic.
+        let mut lengths = vec![0; 43];
+        lengths[42] = 1;
+        let code = Huffman::new(&lengths).unwrap();
+        let mut reader = BitReader::new(&[0b0000_0001]); // first bit = 1
+        assert!(code.decode(&mut reader).is_err());
+        let mut reader = BitReader::new(&[0b0000_0000]);
+        assert_eq!(code.decode(&mut reader).unwrap(), 42);
+    }
+
+    #[test]
+    fn over_subscribed_code_is_rejected() {
+        assert!(Huffman::new(&[1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn long_codes_decode_through_the_slow_path() {
+        // Lengths 1, 2, ..., 14, 15, 15: a complete code whose longest
+        // codes exceed FAST_BITS. Canonical codes are 0, 10, 110, ...
+        let mut lengths: Vec<usize> = (1..=15).collect();
+        lengths.push(15);
+        let code = Huffman::new(&lengths).unwrap();
+        for (symbol, &len) in lengths.iter().enumerate() {
+            // `len - 1` ones then a zero, except the last all-ones code.
+            let ones = if symbol == lengths.len() - 1 {
+                len
+            } else {
+                len - 1
+            };
+            let mut w = BitWriter::new();
+            w.write_bits((1 << ones) - 1, ones as u32);
+            w.write_bits(0, 16);
+            let input = w.finish();
+            let mut reader = BitReader::new(&input);
+            assert_eq!(code.decode(&mut reader).unwrap(), symbol as u32);
+        }
     }
 
     #[test]
@@ -423,6 +839,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compress_roundtrips_through_the_decoder() {
+        let repetitive: Vec<u8> =
+            b"@@music\\Artist\\Album\\01 - Track.flac".repeat(200);
+        let noisy: Vec<u8> = (0u32..5000)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let long_run = vec![7u8; 70_000];
+        for data in [
+            &b""[..],
+            b"a",
+            b"abcabcabcabc",
+            &repetitive,
+            &noisy,
+            &long_run,
+        ] {
+            assert_eq!(deflate(&compress(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn compress_shrinks_repetitive_input_and_never_loses_to_stored() {
+        let data = b"@@music\\Artist\\Album\\01 - Track.flac".repeat(200);
+        let compressed = compress(&data);
+        assert_eq!(&compressed[0..2], &[0x78, 0x9C]);
+        assert!(compressed.len() * 10 < data.len(), "{}", compressed.len());
+
+        let noisy: Vec<u8> = (0u32..5000)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        assert!(compress(&noisy).len() <= compress_stored(&noisy).len());
+    }
+
     #[test]
     fn compress_stored_header_and_adler32_are_correct() {
         assert_eq!(&compress_stored(b"")[0..2], &[120, 1]);
@@ -431,10 +880,87 @@ mod tests {
         assert_eq!(adler32(b"abc"), 0x024D_0127);
     }
 
+    #[test]
+    fn adler32_matches_the_bytewise_definition_on_long_input() {
+        // Long enough to cross several NMAX reduction points.
+        let data: Vec<u8> = vec![0xFF; 20_000];
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &data {
+            a = (a + u32::from(byte)) % ADLER_MOD;
+            b = (b + a) % ADLER_MOD;
+        }
+        assert_eq!(adler32(&data), (b << 16) | a);
+    }
+
+    #[test]
+    fn corrupted_payload_fails_the_checksum() {
+        let mut data = compress_stored(b"hello World");
+        // Flip a byte of stored (uncompressed) content.
+        data[8] ^= 0x20;
+        match deflate(&data) {
+            Err(SoulseekRs::ChecksumMismatch { expected, actual }) => {
+                assert_eq!(expected, adler32(b"hello World"));
+                assert_ne!(expected, actual);
+            }
+            other => panic!("unexpected: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn corrupted_trailer_fails_the_checksum() {
+        let mut data = compress(b"hello hello hello World");
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        assert!(matches!(
+            deflate(&data),
+            Err(SoulseekRs::ChecksumMismatch { .. })
+        ));
+    }
+
+    /// A sink recording the size of every write it receives.
+    #[derive(Default)]
+    struct ChunkSink {
+        data: Vec<u8>,
+        largest_write: usize,
+    }
+
+    impl Write for ChunkSink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.largest_write = self.largest_write.max(buf.len());
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn deflate_to_streams_output_in_bounded_chunks() {
+        // Long runs exercise back-references across flush boundaries.
+        let data: Vec<u8> = (0u32..600_000)
+            .map(|i| ((i / 1000) % 7) as u8 ^ (i % 3) as u8)
+            .collect();
+        let mut sink = ChunkSink::default();
+        let written = deflate_to(&compress(&data), &mut sink).unwrap();
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(sink.data, data);
+        assert!(sink.largest_write <= FLUSH_SIZE, "{}", sink.largest_write);
+    }
+
+    #[test]
+    fn deflate_to_reports_sink_errors() {
+        let mut sink = [0u8; 4];
+        let result =
+            deflate_to(&compress(b"more than four bytes"), &mut &mut sink[..]);
+        assert!(matches!(result, Err(SoulseekRs::CompressionError(_))));
+    }
+
     #[test]
     fn test_bitreader_read_bits() {
-        let data = vec![0b11010010, 0b10110101];
-        let mut reader = BitReader::new(data);
+        let data = [0b11010010, 0b10110101];
+        let mut reader = BitReader::new(&data);
 
         assert_eq!(reader.read_bits(3).unwrap(), 0b010); // First 3 bits: 010
         assert_eq!(reader.read_bits(5).unwrap(), 0b11010); // Next 5 bits: 11010
@@ -442,8 +968,8 @@ mod tests {
 
     #[test]
     fn test_bitreader_read_bytes() {
-        let data = vec![0x12, 0x34, 0x56, 0x78];
-        let mut reader = BitReader::new(data);
+        let data = [0x12, 0x34, 0x56, 0x78];
+        let mut reader = BitReader::new(&data);
 
         assert_eq!(reader.read_bytes(2).unwrap(), 0x3412); // Little-endian: 0x3412
         assert_eq!(reader.read_bytes(2).unwrap(), 0x7856); // Little-endian: 0x7856
@@ -458,8 +984,8 @@ mod tests {
 
     #[test]
     fn test_extract_header_fail_to_short() {
-        let data = vec![120]; // Too short
-        let mut reader = BitReader::new(data);
+        let data = [120]; // Too short
+        let mut reader = BitReader::new(&data);
         let result = reader.read_byte();
         assert!(result.is_ok());
         let result = reader.read_byte();
@@ -468,10 +994,11 @@ mod tests {
 
     #[test]
     fn test_deflate() {
-        // Test data from the original test - this should work with our new implementation
+        // Test data from the original test. Its trailer used to be wrong
+        // (0x180b045d) and passed only while the checksum was ignored.
         let data = vec![
             120, 156, 203, 72, 205, 201, 201, 87, 8, 207, 47, 202, 73, 1, 0,
-            24, 11, 4, 93,
+            0x19, 0x6b, 0x04, 0x3d,
         ];
         let result = deflate(&data);
         assert!(result.is_ok());
@@ -1,6 +1,8 @@
 //! End-to-end integration tests against a real Soulseek server (soulfind).
 //!
-//! These tests are SERVER-OPTIONAL so `cargo test` stays green everywhere:
+//! The peer download tests script the server and the uploading peer with the
+//! `testing` mocks instead, so they always run. The rest are SERVER-OPTIONAL
+//! so `cargo test` stays green everywhere:
 //!   * If `SOULSEEK_TEST_SERVER=host:port` is set, they connect to it.
 //!   * Else if a soulfind binary is found (via `SOULFIND_BIN`, or a sibling
 //!     `../soulfind/bin/soulfind` checkout), they spawn it on an ephemeral port
@@ -14,21 +16,26 @@
 //! ```
 //!
 //! No external crates are used — the library forbids dependencies, so the
-//! harness sticks to `std` and the library's own public API.
+//! harness sticks to `std` and the library's own public API, with the
+//! `testing` feature on.
 
 #![allow(clippy::doc_markdown)]
 
 use std::io::{Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+use std::net::{Ipv4Addr, SocketAddrV4, TcpStream, ToSocketAddrs};
 use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::Receiver;
 use std::time::{Duration, Instant};
 
-use soulseek_rs::message::Message;
 use soulseek_rs::message::server::MessageFactory;
+use soulseek_rs::message::{Message, PeerInitMessage, ServerMessageOut};
 use soulseek_rs::peer::ConnectionType;
-use soulseek_rs::{Client, ClientSettings, DownloadStatus, PeerAddress};
+use soulseek_rs::testing::{MockPeerBuilder, MockServer, MockServerBuilder};
+use soulseek_rs::{
+    Client, ClientSettings, DownloadStatus, LoginOutcome, LoginRejection,
+    PeerAddress,
+};
 
 /// A Soulseek server to test against: either a child soulfind process we
 /// spawned, or an external server referenced by `SOULSEEK_TEST_SERVER`.
@@ -96,7 +103,7 @@ impl TestServer {
             server_address: PeerAddress::new(self.host.clone(), self.port),
             enable_listen: false,
             listen_port: 0,
-            shared_directories: Vec::new(),
+            ..ClientSettings::default()
         }
     }
 
@@ -204,8 +211,11 @@ fn connect_and_login_succeed() {
     let mut client =
         Client::with_settings(server.settings("e2e_user", "e2e_pw"));
     client.connect().expect("connect to soulfind");
-    let logged_in = client.login().expect("login to soulfind");
-    assert!(logged_in, "login should succeed (soulfind auto-registers)");
+    let outcome = client.login().expect("login to soulfind");
+    assert!(
+        outcome.is_accepted(),
+        "login should succeed (soulfind auto-registers): {outcome:?}"
+    );
 }
 
 #[test]
@@ -214,7 +224,7 @@ fn search_round_trips_without_error() {
     let mut client =
         Client::with_settings(server.settings("e2e_search", "e2e_pw"));
     client.connect().expect("connect");
-    assert!(client.login().expect("login"));
+    assert!(client.login().expect("login").is_accepted());
 
     // A fresh server has no shared files, so the search simply has to
     // round-trip without error and leave an (empty) queryable result set.
@@ -245,8 +255,8 @@ fn a_search_is_forwarded_to_a_connected_peer() {
         Client::with_settings(server.settings("e2e_receiver", "pw"));
     searcher.connect().expect("searcher connect");
     receiver.connect().expect("receiver connect");
-    assert!(searcher.login().expect("searcher login"));
-    assert!(receiver.login().expect("receiver login"));
+    assert!(searcher.login().expect("searcher login").is_accepted());
+    assert!(receiver.login().expect("receiver login").is_accepted());
 
     let _ = searcher.search("some shared song", Duration::from_secs(2));
 
@@ -266,8 +276,8 @@ fn a_private_message_is_delivered_between_users() {
     let mut bob = Client::with_settings(server.settings("e2e_bob_pm", "pw"));
     alice.connect().expect("alice connect");
     bob.connect().expect("bob connect");
-    assert!(alice.login().expect("alice login"));
-    assert!(bob.login().expect("bob login"));
+    assert!(alice.login().expect("alice login").is_accepted());
+    assert!(bob.login().expect("bob login").is_accepted());
 
     let body = "hello bob, this is alice";
     alice
@@ -303,8 +313,8 @@ fn a_chat_room_message_is_delivered_between_users() {
     let mut bob = Client::with_settings(server.settings("e2e_bob_room", "pw"));
     alice.connect().expect("alice connect");
     bob.connect().expect("bob connect");
-    assert!(alice.login().expect("alice login"));
-    assert!(bob.login().expect("bob login"));
+    assert!(alice.login().expect("alice login").is_accepted());
+    assert!(bob.login().expect("bob login").is_accepted());
 
     alice.join_room(room).expect("alice joins room");
     bob.join_room(room).expect("bob joins room");
@@ -354,7 +364,7 @@ fn the_room_list_includes_a_joined_room() {
     let mut alice =
         Client::with_settings(server.settings("e2e_alice_list", "pw"));
     alice.connect().expect("alice connect");
-    assert!(alice.login().expect("alice login"));
+    assert!(alice.login().expect("alice login").is_accepted());
     alice.join_room(room).expect("alice joins room");
 
     // Once a user is in the room the server should advertise it in RoomList.
@@ -385,7 +395,10 @@ fn login_succeeds_with_listener_enabled() {
     ));
     client.connect().expect("connect with listener");
     assert!(
-        client.login().expect("login with listener enabled"),
+        client
+            .login()
+            .expect("login with listener enabled")
+            .is_accepted(),
         "the handshake including SetWaitPort should still log in"
     );
 }
@@ -401,7 +414,7 @@ fn wrong_password_is_rejected() {
         Client::with_settings(server.settings(user, "correct-horse"));
     first.connect().expect("connect (registering login)");
     assert!(
-        first.login().expect("first login"),
+        first.login().expect("first login").is_accepted(),
         "registration should log in"
     );
     drop(first);
@@ -409,11 +422,12 @@ fn wrong_password_is_rejected() {
     let mut second =
         Client::with_settings(server.settings(user, "wrong-password"));
     second.connect().expect("connect (wrong password)");
-    // The server may signal rejection either as a non-success status or as an
-    // authentication error; both mean "not logged in", only `Ok(true)` accepts.
     assert!(
-        !matches!(second.login(), Ok(true)),
-        "a mismatched password must not be accepted"
+        matches!(
+            second.login(),
+            Ok(LoginOutcome::Rejected(LoginRejection::InvalidPassword))
+        ),
+        "a mismatched password must be rejected as INVALIDPASS"
     );
 }
 
@@ -428,7 +442,7 @@ fn registered_username_can_relogin_with_same_password() {
     let mut first = Client::with_settings(server.settings(user, "pw-123"));
     first.connect().expect("connect (registering login)");
     assert!(
-        first.login().expect("registering login"),
+        first.login().expect("registering login").is_accepted(),
         "a fresh username should be auto-registered"
     );
     drop(first);
@@ -436,7 +450,7 @@ fn registered_username_can_relogin_with_same_password() {
     let mut second = Client::with_settings(server.settings(user, "pw-123"));
     second.connect().expect("connect (relogin)");
     assert!(
-        second.login().expect("relogin"),
+        second.login().expect("relogin").is_accepted(),
         "the same credentials must log in again after a restart"
     );
 }
@@ -453,42 +467,62 @@ fn two_clients_can_be_logged_in_together() {
     alice.connect().expect("alice connect");
     bob.connect().expect("bob connect");
 
-    assert!(alice.login().expect("alice login"));
-    assert!(bob.login().expect("bob login"));
+    assert!(alice.login().expect("alice login").is_accepted());
+    assert!(bob.login().expect("bob login").is_accepted());
 }
 
 // ---------------------------------------------------------------------------
 // Peer-to-peer download coverage.
 //
-// The client cannot serve files (the upload side is not implemented), so a real
-// download is exercised with a minimal in-process "mock uploader" that speaks
-// the peer protocol using the library's own public `Message` wire format. It
-// drives the same path a real peer would after a search: a `P` control
-// connection to our listener, a `QueueUpload` → `TransferRequest` →
-// `TransferResponse` negotiation, then an `F` connection that streams the bytes.
+// These run against the `testing` mocks rather than soulfind, so they always
+// run: a `MockServer` logs the client in and answers `GetPeerAddress`, and a
+// `MockPeer` shares the file. It answers the client's `QueueUpload` with a
+// `TransferRequest`, and once allowed, streams the bytes over an `F`
+// connection to the client's listener.
 // ---------------------------------------------------------------------------
 
-/// Configuration for a one-shot mock uploader.
-struct MockUpload {
-    listen_addr: String,
-    peer_username: String,
-    filename: String,
-    content: Vec<u8>,
-    token: u32,
-    ready: Sender<()>,
+const MOCK_PASSWORD: &str = "pw";
+
+/// A client logged in to `server`, with its peer listener on `port`.
+fn mock_client(server: &MockServer, username: &str, port: u16) -> Client {
+    let mut client = Client::with_settings(ClientSettings {
+        server_address: server.address(),
+        enable_listen: true,
+        listen_port: port,
+        ..ClientSettings::new(username, MOCK_PASSWORD)
+    });
+    client.connect().expect("connect");
+    assert!(client.login().expect("login").is_accepted());
+    client
 }
 
-/// Build a `PeerInit` (peer code 1) frame: `[len][1][username][conn_type][token]`.
-fn peer_init_bytes(username: &str, conn_type: &str, token: u32) -> Vec<u8> {
-    let mut m = Message::new();
-    m.write_int8(1)
-        .write_string(username)
-        .write_string(conn_type)
-        .write_int32(token);
-    m.get_buffer()
+/// The bytes a mock peer shares.
+fn mock_content() -> Vec<u8> {
+    (0..2000u32).map(|i| (i % 251) as u8).collect()
 }
 
-/// Read one length-prefixed peer message (`[len:4 LE][payload]`) from a blocking
+/// Wait up to `timeout` for the download reporting on `status_rx` to finish,
+/// and return how it did.
+fn final_status(
+    status_rx: &Receiver<DownloadStatus>,
+    timeout: Duration,
+) -> Option<DownloadStatus> {
+    let deadline = Instant::now() + timeout;
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        match status_rx.recv_timeout(left) {
+            Ok(
+                status @ (DownloadStatus::Completed(_)
+                | DownloadStatus::Failed(_)
+                | DownloadStatus::TimedOut),
+            ) => return Some(status),
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Read one length-prefixed message (`[len:4 LE][payload]`) from a blocking
 /// stream. The returned `Message` keeps the length prefix, so `get_message_code`
 /// and `set_pointer(8)` behave exactly as they do inside the library.
 fn read_framed(stream: &mut TcpStream) -> std::io::Result<Message> {
@@ -517,80 +551,6 @@ fn connect_retry(addr: &str, timeout: Duration) -> std::io::Result<TcpStream> {
     }
 }
 
-fn run_mock_uploader(cfg: &MockUpload) -> std::io::Result<()> {
-    // 1. P (control) connection: register ourselves with the downloader.
-    let mut p = connect_retry(&cfg.listen_addr, Duration::from_secs(5))?;
-    p.set_read_timeout(Some(Duration::from_secs(10)))?;
-    p.write_all(&peer_init_bytes(&cfg.peer_username, "P", 0))?;
-    p.flush()?;
-    let _ = cfg.ready.send(());
-
-    // 2. Wait for the downloader's QueueUpload (peer code 43).
-    loop {
-        let mut msg = read_framed(&mut p)?;
-        if msg.get_message_code() == 43 {
-            msg.set_pointer(8);
-            let _requested = msg.read_string();
-            break;
-        }
-    }
-
-    // 3. Offer the transfer with a TransferRequest (peer code 40). The size here
-    //    becomes the download's expected size, so it must match the content.
-    let mut tr = Message::new();
-    tr.write_int32(40)
-        .write_int32(1) // direction: upload
-        .write_int32(cfg.token)
-        .write_string(&cfg.filename)
-        .write_int64(cfg.content.len() as u64);
-    p.write_all(&tr.get_buffer())?;
-    p.flush()?;
-
-    // 4. Wait for the downloader to allow it (TransferResponse, peer code 41).
-    loop {
-        let msg = read_framed(&mut p)?;
-        if msg.get_message_code() == 41 {
-            break;
-        }
-    }
-
-    // 5. Open the F (file) connection to the downloader's listener and stream.
-    serve_file_over_f(
-        &cfg.listen_addr,
-        &cfg.peer_username,
-        cfg.token,
-        &cfg.content,
-    )
-}
-
-/// Open an F (file transfer) connection to `downloader_addr` and stream
-/// `content`. PeerInit(F) and the 4-byte transfer token are written together so
-/// the token lands in the listener's read buffer, where the download is looked
-/// up by token; the downloader then sends an 8-byte START_DOWNLOAD offset before
-/// we send the bytes.
-fn serve_file_over_f(
-    downloader_addr: &str,
-    username: &str,
-    token: u32,
-    content: &[u8],
-) -> std::io::Result<()> {
-    let mut f = connect_retry(downloader_addr, Duration::from_secs(5))?;
-    f.set_read_timeout(Some(Duration::from_secs(10)))?;
-    let mut init = peer_init_bytes(username, "F", token);
-    init.extend_from_slice(&token.to_le_bytes());
-    f.write_all(&init)?;
-    f.flush()?;
-
-    let mut start = [0u8; 8];
-    f.read_exact(&mut start)?;
-    f.write_all(content)?;
-    f.flush()?;
-
-    // Keep the connection open briefly so the reader drains everything.
-    std::thread::sleep(Duration::from_millis(500));
-    Ok(())
-}
-
 /// Log a raw socket in to the server and drain up to the login response,
 /// returning the still-open stream (the user stays online while it lives).
 fn login_raw(
@@ -631,20 +591,6 @@ fn read_until_code(
     None
 }
 
-/// Like [`read_until_code`] but turns a miss into an `io::Error` for `?`.
-fn expect_code(
-    stream: &mut TcpStream,
-    code: u8,
-    timeout: Duration,
-) -> std::io::Result<Message> {
-    read_until_code(stream, code, timeout).ok_or_else(|| {
-        std::io::Error::new(
-            std::io::ErrorKind::TimedOut,
-            format!("timed out waiting for message code {code}"),
-        )
-    })
-}
-
 fn unique_download_dir() -> PathBuf {
     let dir = std::env::temp_dir().join(format!(
         "soulseek-e2e-dl-{}-{:?}",
@@ -657,42 +603,23 @@ fn unique_download_dir() -> PathBuf {
 
 #[test]
 fn a_file_downloads_from_a_peer_over_p_and_f_connections() {
-    let server = server_or_skip!();
-
-    // Downloader: connected to the server with its peer listener enabled.
     let listen_port = free_port().expect("free listen port");
-    let mut client = Client::with_settings(server.listening_settings(
-        "e2e_downloader",
-        "pw",
-        listen_port,
-    ));
-    client.connect().expect("connect");
-    assert!(client.login().expect("login"));
-
     let filename = "mock_song.mp3";
-    let content: Vec<u8> = (0..2000u32).map(|i| (i % 251) as u8).collect();
-    let size = content.len() as u64;
-    let token = 424_242_u32;
+    let content = mock_content();
     let download_dir = unique_download_dir();
 
-    // Start the mock uploader; it signals once its P connection is established.
-    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
-    let cfg = MockUpload {
-        listen_addr: format!("127.0.0.1:{listen_port}"),
-        peer_username: "e2e_mockpeer".to_string(),
-        filename: filename.to_string(),
-        content: content.clone(),
-        token,
-        ready: ready_tx,
-    };
-    let uploader = std::thread::spawn(move || {
-        if let Err(e) = run_mock_uploader(&cfg) {
-            eprintln!("[mock uploader] {e}");
-        }
-    });
-
-    ready_rx
-        .recv_timeout(Duration::from_secs(5))
+    let server = MockServerBuilder::new().build().expect("mock server");
+    let client = mock_client(&server, "e2e_downloader", listen_port);
+    let uploader = MockPeerBuilder::new("e2e_mockpeer")
+        .share(filename, content.clone())
+        .client_port(listen_port)
+        .build()
+        .expect("mock uploader");
+
+    // The uploader opens the P connection, as a peer returning search
+    // results does.
+    uploader
+        .connect_to(listen_port, [])
         .expect("mock uploader P connection");
 
     // The listener registers an incoming peer under "<username>:direct"; give
@@ -703,37 +630,19 @@ fn a_file_downloads_from_a_peer_over_p_and_f_connections() {
         .download(
             filename.to_string(),
             "e2e_mockpeer:direct".to_string(),
-            size,
+            content.len() as u64,
             download_dir.display().to_string(),
         )
         .expect("start download");
 
-    // Wait for completion via the per-download status channel.
-    let mut completed = false;
-    let deadline = Instant::now() + Duration::from_secs(20);
-    while Instant::now() < deadline {
-        match status_rx.recv_timeout(Duration::from_millis(500)) {
-            Ok(DownloadStatus::Completed) => {
-                completed = true;
-                break;
-            }
-            Ok(DownloadStatus::Failed(_) | DownloadStatus::TimedOut) => {
-                break;
-            }
-            _ => {}
-        }
-        if client
-            .get_all_downloads()
-            .iter()
-            .any(|d| matches!(d.status, DownloadStatus::Completed))
-        {
-            completed = true;
-            break;
+    match final_status(&status_rx, Duration::from_secs(20)) {
+        Some(DownloadStatus::Completed(summary)) => {
+            let summary = summary.expect("a transfer reports its summary");
+            assert_eq!(summary.path, download_dir.join(filename));
+            assert!(summary.average_speed_bytes_per_sec > 0.0);
         }
+        other => panic!("the download should reach Completed, got {other:?}"),
     }
-    let _ = uploader.join();
-
-    assert!(completed, "the download should reach Completed");
 
     let written = std::fs::read(download_dir.join(filename))
         .expect("downloaded file should exist");
@@ -791,195 +700,55 @@ fn soulfind_brokers_connect_to_peer_between_users() {
 // ---------------------------------------------------------------------------
 // Direct-connection download: the client initiates the peer connection.
 //
-// Here the mock is a server-registered peer. It logs in to soulfind and
-// advertises a listen port (SetWaitPort), so when our client asks the server
-// for the peer's address (GetPeerAddress) and dials it directly, the mock
-// accepts that inbound P connection and then serves the file. This exercises
-// the outbound PeerInit handshake and the auto-connecting download() path.
+// The mock server hands out the mock peer's address, so when our client asks
+// for it (GetPeerAddress) and dials it directly, the mock accepts that inbound
+// P connection and then serves the file. This exercises the outbound PeerInit
+// handshake and the auto-connecting download() path.
 // ---------------------------------------------------------------------------
 
-struct MockDirectUpload {
-    server_addr: String,
-    username: String,
-    password: String,
-    listen_port: u16,
-    downloader_listen_addr: String,
-    downloader_username: String,
-    filename: String,
-    content: Vec<u8>,
-    token: u32,
-    ready: Sender<()>,
-}
-
-fn run_mock_direct_peer(cfg: &MockDirectUpload) -> std::io::Result<()> {
-    // 1. Log in to the server so it knows this user is online.
-    let mut srv = connect_retry(&cfg.server_addr, Duration::from_secs(5))?;
-    srv.set_read_timeout(Some(Duration::from_secs(10)))?;
-    srv.write_all(
-        &MessageFactory::build_login_message(&cfg.username, &cfg.password)
-            .get_buffer(),
-    )?;
-    srv.flush()?;
-    loop {
-        let msg = read_framed(&mut srv)?;
-        if msg.get_message_code() == 1 {
-            break; // login response
-        }
-    }
-
-    // 2. Bind the peer listener, then advertise its port so the server can hand
-    //    our address to the downloader. Bind all interfaces: soulfind reports
-    //    the host's LAN address (not 127.0.0.1), and the downloader dials that.
-    let listener = std::net::TcpListener::bind(("0.0.0.0", cfg.listen_port))?;
-    srv.write_all(
-        &MessageFactory::build_set_wait_port_message(cfg.listen_port)
-            .get_buffer(),
-    )?;
-    srv.flush()?;
-    let _ = cfg.ready.send(());
-
-    // 3. Accept the downloader's inbound P (control) connection and validate its
-    //    PeerInit (peer code 1, int8 code so the fields start at offset 5). The
-    //    accept is bounded so a misrouted connection fails the test instead of
-    //    hanging it.
-    listener.set_nonblocking(true)?;
-    let accept_deadline = Instant::now() + Duration::from_secs(15);
-    let (mut p, _addr) = loop {
-        match listener.accept() {
-            Ok(pair) => break pair,
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                if Instant::now() >= accept_deadline {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::TimedOut,
-                        "no inbound P connection from the downloader",
-                    ));
-                }
-                std::thread::sleep(Duration::from_millis(50));
-            }
-            Err(e) => return Err(e),
-        }
-    };
-    p.set_nonblocking(false)?;
-    p.set_read_timeout(Some(Duration::from_secs(10)))?;
-    let mut init = read_framed(&mut p)?;
-    assert_eq!(init.get_message_code(), 1, "expected inbound PeerInit");
-    init.set_pointer(5);
-    assert_eq!(init.read_string(), cfg.downloader_username, "PeerInit user");
-    assert_eq!(init.read_string(), "P", "PeerInit connection type");
-
-    // 4. Negotiate the transfer exactly as in the passive path.
-    loop {
-        let mut msg = read_framed(&mut p)?;
-        if msg.get_message_code() == 43 {
-            msg.set_pointer(8);
-            let _requested = msg.read_string();
-            break;
-        }
-    }
-    let mut tr = Message::new();
-    tr.write_int32(40)
-        .write_int32(1)
-        .write_int32(cfg.token)
-        .write_string(&cfg.filename)
-        .write_int64(cfg.content.len() as u64);
-    p.write_all(&tr.get_buffer())?;
-    p.flush()?;
-    loop {
-        let msg = read_framed(&mut p)?;
-        if msg.get_message_code() == 41 {
-            break; // TransferResponse
-        }
-    }
-
-    // 5. Stream the bytes over an F connection to the downloader's listener.
-    //    `srv` stays in scope so the peer remains online for the whole transfer.
-    serve_file_over_f(
-        &cfg.downloader_listen_addr,
-        &cfg.username,
-        cfg.token,
-        &cfg.content,
-    )
-}
-
 #[test]
 fn a_file_downloads_from_a_peer_via_direct_connection() {
-    let server = server_or_skip!();
-
-    // Downloader with a listener enabled (needed for the F leg).
     let client_port = free_port().expect("free client listen port");
-    let mut client = Client::with_settings(server.listening_settings(
-        "e2e_direct_dl",
-        "pw",
-        client_port,
-    ));
-    client.connect().expect("connect");
-    assert!(client.login().expect("login"));
-
-    let mock_port = free_port().expect("free mock listen port");
     let filename = "direct_song.mp3";
-    let content: Vec<u8> = (0..2000u32).map(|i| (i % 251) as u8).collect();
-    let size = content.len() as u64;
-    let token = 515_151_u32;
+    let content = mock_content();
     let download_dir = unique_download_dir();
 
-    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
-    let cfg = MockDirectUpload {
-        server_addr: format!("{}:{}", server.host, server.port),
-        username: "e2e_directpeer".to_string(),
-        password: "pw".to_string(),
-        listen_port: mock_port,
-        downloader_listen_addr: format!("127.0.0.1:{client_port}"),
-        downloader_username: "e2e_direct_dl".to_string(),
-        filename: filename.to_string(),
-        content: content.clone(),
-        token,
-        ready: ready_tx,
-    };
-    let uploader = std::thread::spawn(move || {
-        if let Err(e) = run_mock_direct_peer(&cfg) {
-            eprintln!("[mock direct peer] {e}");
-        }
-    });
-
-    ready_rx
-        .recv_timeout(Duration::from_secs(10))
-        .expect("mock direct peer ready");
-    // Let the server finish processing SetWaitPort before we resolve the address.
-    std::thread::sleep(Duration::from_secs(1));
+    let uploader = MockPeerBuilder::new("e2e_directpeer")
+        .share(filename, content.clone())
+        .client_port(client_port)
+        .build()
+        .expect("mock direct peer");
+    let server = MockServerBuilder::new()
+        .peer("e2e_directpeer", uploader.address())
+        .build()
+        .expect("mock server");
+    let client = mock_client(&server, "e2e_direct_dl", client_port);
 
     // Target the plain username so download() takes the direct-connect path.
     let (_download, status_rx) = client
         .download(
             filename.to_string(),
             "e2e_directpeer".to_string(),
-            size,
+            content.len() as u64,
             download_dir.display().to_string(),
         )
         .expect("start download");
 
-    let mut completed = false;
-    let deadline = Instant::now() + Duration::from_secs(20);
-    while Instant::now() < deadline {
-        match status_rx.recv_timeout(Duration::from_millis(500)) {
-            Ok(DownloadStatus::Completed) => {
-                completed = true;
-                break;
-            }
-            Ok(DownloadStatus::Failed(_) | DownloadStatus::TimedOut) => break,
-            _ => {}
-        }
-        if client
-            .get_all_downloads()
-            .iter()
-            .any(|d| matches!(d.status, DownloadStatus::Completed))
-        {
-            completed = true;
-            break;
-        }
-    }
-    let _ = uploader.join();
-
-    assert!(completed, "the direct download should reach Completed");
+    assert!(
+        matches!(
+            final_status(&status_rx, Duration::from_secs(20)),
+            Some(DownloadStatus::Completed(_))
+        ),
+        "the direct download should reach Completed"
+    );
+    assert!(
+        uploader.introductions().iter().any(|init| matches!(
+            init,
+            PeerInitMessage::PeerInit { username, connection_type: ConnectionType::P, .. }
+                if username == "e2e_direct_dl"
+        )),
+        "the downloader should introduce itself on a P connection"
+    );
 
     let written = std::fs::read(download_dir.join(filename))
         .expect("downloaded file should exist");
@@ -992,153 +761,67 @@ fn a_file_downloads_from_a_peer_via_direct_connection() {
 // Firewalled download: the peer is unreachable directly, so the connection is
 // brokered through the server.
 //
-// The mock advertises a port nobody listens on, so the downloader's direct
-// connection fails. The client then asks the server to broker the connection
-// (ConnectToPeer); the mock, reading its server stream, sees the forwarded
-// request and connects back to the downloader with a PierceFirewall. That
-// pierced connection becomes the P control channel, and the file is served.
+// The mock server hands out a port nobody listens on, so the downloader's
+// direct connection fails. The client then asks the server to broker the
+// connection (ConnectToPeer); the mock server has the mock peer connect back
+// to the downloader with a PierceFirewall. That pierced connection becomes the
+// P control channel, and the file is served.
 // ---------------------------------------------------------------------------
 
-struct MockFirewalledUpload {
-    server_addr: String,
-    username: String,
-    password: String,
-    bogus_port: u16,
-    downloader_listen_addr: String,
-    filename: String,
-    content: Vec<u8>,
-    token: u32,
-    ready: Sender<()>,
-}
-
-fn run_mock_firewalled_peer(cfg: &MockFirewalledUpload) -> std::io::Result<()> {
-    // 1. Log in and advertise a port that nobody listens on, so the downloader's
-    //    direct connection is refused and it falls back to server brokering.
-    let mut srv = login_raw(&cfg.server_addr, &cfg.username, &cfg.password)?;
-    srv.write_all(
-        &MessageFactory::build_set_wait_port_message(cfg.bogus_port)
-            .get_buffer(),
-    )?;
-    srv.flush()?;
-    let _ = cfg.ready.send(());
-
-    // 2. Wait for the server-brokered ConnectToPeer (server code 18) and read
-    //    the correlation token (after username, type, ip and port).
-    let mut ctp = expect_code(&mut srv, 18, Duration::from_secs(15))?;
-    ctp.set_pointer(8);
-    let _who = ctp.read_string();
-    let _conn_type = ctp.read_string();
-    let _ip = ctp.read_int32();
-    let _port = ctp.read_int32();
-    let connect_token = ctp.read_int32();
-
-    // 3. Connect back to the downloader with a PierceFirewall (peer code 0);
-    //    this becomes the P control connection.
-    let mut p =
-        connect_retry(&cfg.downloader_listen_addr, Duration::from_secs(5))?;
-    p.set_read_timeout(Some(Duration::from_secs(10)))?;
-    p.write_all(
-        &MessageFactory::build_pierce_firewall_message(connect_token)
-            .get_buffer(),
-    )?;
-    p.flush()?;
-
-    // 4. Negotiate the transfer over the pierced connection.
-    let _queue = expect_code(&mut p, 43, Duration::from_secs(10))?;
-    let mut tr = Message::new();
-    tr.write_int32(40)
-        .write_int32(1)
-        .write_int32(cfg.token)
-        .write_string(&cfg.filename)
-        .write_int64(cfg.content.len() as u64);
-    p.write_all(&tr.get_buffer())?;
-    p.flush()?;
-    let _response = expect_code(&mut p, 41, Duration::from_secs(10))?;
-
-    // 5. Serve the bytes over an F connection to the downloader's listener.
-    serve_file_over_f(
-        &cfg.downloader_listen_addr,
-        &cfg.username,
-        cfg.token,
-        &cfg.content,
-    )
-}
-
 #[test]
 fn a_file_downloads_from_a_firewalled_peer_via_server_broker() {
-    let server = server_or_skip!();
-
     let client_port = free_port().expect("free client listen port");
-    let mut client = Client::with_settings(server.listening_settings(
-        "e2e_fw_dl",
-        "pw",
-        client_port,
-    ));
-    client.connect().expect("connect");
-    assert!(client.login().expect("login"));
-
     let bogus_port = free_port().expect("bogus port"); // advertised, unlistened
     let filename = "firewalled_song.mp3";
-    let content: Vec<u8> = (0..2000u32).map(|i| (i % 251) as u8).collect();
-    let size = content.len() as u64;
-    let token = 606_060_u32;
+    let content = mock_content();
     let download_dir = unique_download_dir();
 
-    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
-    let cfg = MockFirewalledUpload {
-        server_addr: format!("{}:{}", server.host, server.port),
-        username: "e2e_fw_peer".to_string(),
-        password: "pw".to_string(),
-        bogus_port,
-        downloader_listen_addr: format!("127.0.0.1:{client_port}"),
-        filename: filename.to_string(),
-        content: content.clone(),
-        token,
-        ready: ready_tx,
-    };
-    let uploader = std::thread::spawn(move || {
-        if let Err(e) = run_mock_firewalled_peer(&cfg) {
-            eprintln!("[mock firewalled peer] {e}");
-        }
-    });
-
-    ready_rx
-        .recv_timeout(Duration::from_secs(10))
-        .expect("mock firewalled peer ready");
-    std::thread::sleep(Duration::from_secs(1));
+    let uploader = MockPeerBuilder::new("e2e_fw_peer")
+        .share(filename, content.clone())
+        .client_port(client_port)
+        .build()
+        .expect("mock firewalled peer");
+    let server = MockServerBuilder::new()
+        .peer(
+            "e2e_fw_peer",
+            SocketAddrV4::new(Ipv4Addr::LOCALHOST, bogus_port),
+        )
+        .on(18, move |request| {
+            if let ServerMessageOut::ConnectToPeer { token, .. } = request {
+                uploader
+                    .pierce(client_port, *token)
+                    .expect("pierce the downloader's firewall");
+            }
+            Vec::new()
+        })
+        .build()
+        .expect("mock server");
+    let client = mock_client(&server, "e2e_fw_dl", client_port);
 
     let (_download, status_rx) = client
         .download(
             filename.to_string(),
             "e2e_fw_peer".to_string(),
-            size,
+            content.len() as u64,
             download_dir.display().to_string(),
         )
         .expect("start download");
 
-    let mut completed = false;
-    let deadline = Instant::now() + Duration::from_secs(25);
-    while Instant::now() < deadline {
-        match status_rx.recv_timeout(Duration::from_millis(500)) {
-            Ok(DownloadStatus::Completed) => {
-                completed = true;
-                break;
-            }
-            Ok(DownloadStatus::Failed(_) | DownloadStatus::TimedOut) => break,
-            _ => {}
-        }
-        if client
-            .get_all_downloads()
-            .iter()
-            .any(|d| matches!(d.status, DownloadStatus::Completed))
-        {
-            completed = true;
-            break;
-        }
-    }
-    let _ = uploader.join();
-
-    assert!(completed, "the firewalled download should reach Completed");
+    assert!(
+        matches!(
+            final_status(&status_rx, Duration::from_secs(25)),
+            Some(DownloadStatus::Completed(_))
+        ),
+        "the firewalled download should reach Completed"
+    );
+    assert!(
+        server.requests().iter().any(|request| matches!(
+            request,
+            ServerMessageOut::ConnectToPeer { username, .. }
+                if username == "e2e_fw_peer"
+        )),
+        "the connection should be brokered through the server"
+    );
 
     let written = std::fs::read(download_dir.join(filename))
         .expect("downloaded file should exist");
@@ -1168,7 +851,7 @@ fn two_real_clients_search_and_download() {
         ..server.listening_settings("e2e_sharer", "pw", sharer_port)
     });
     sharer.connect().expect("sharer connect");
-    assert!(sharer.login().expect("sharer login"));
+    assert!(sharer.login().expect("sharer login").is_accepted());
 
     let leecher_port = free_port().expect("leecher port");
     let mut leecher = Client::with_settings(server.listening_settings(
@@ -1177,7 +860,7 @@ fn two_real_clients_search_and_download() {
         leecher_port,
     ));
     leecher.connect().expect("leecher connect");
-    assert!(leecher.login().expect("leecher login"));
+    assert!(leecher.login().expect("leecher login").is_accepted());
 
     // Let soulfind register both SetWaitPorts before the search resolves peers.
     std::thread::sleep(Duration::from_secs(1));
@@ -1221,7 +904,7 @@ fn two_real_clients_search_and_download() {
     let deadline = Instant::now() + Duration::from_secs(25);
     while Instant::now() < deadline {
         match status_rx.recv_timeout(Duration::from_millis(500)) {
-            Ok(DownloadStatus::Completed) => {
+            Ok(DownloadStatus::Completed(_)) => {
                 completed = true;
                 break;
             }
@@ -1231,7 +914,7 @@ fn two_real_clients_search_and_download() {
         if leecher
             .get_all_downloads()
             .iter()
-            .any(|d| matches!(d.status, DownloadStatus::Completed))
+            .any(|d| matches!(d.status, DownloadStatus::Completed(_)))
         {
             completed = true;
             break;
@@ -1280,7 +963,7 @@ fn a_runtime_share_update_is_visible_to_browsers() {
         sharer_port,
     ));
     sharer.connect().expect("sharer connect");
-    assert!(sharer.login().expect("sharer login"));
+    assert!(sharer.login().expect("sharer login").is_accepted());
     assert!(sharer.shared_directories().is_empty());
 
     let share_dir = unique_download_dir();
@@ -1298,7 +981,7 @@ fn a_runtime_share_update_is_visible_to_browsers() {
         browser_port,
     ));
     browser.connect().expect("browser connect");
-    assert!(browser.login().expect("browser login"));
+    assert!(browser.login().expect("browser login").is_accepted());
 
     std::thread::sleep(Duration::from_secs(1));
     browser.browse_user("e2e_reshare").expect("browse request");
@@ -1338,7 +1021,7 @@ fn browse_a_peers_shared_files() {
         ..server.listening_settings("e2e_browsee", "pw", sharer_port)
     });
     sharer.connect().expect("sharer connect");
-    assert!(sharer.login().expect("sharer login"));
+    assert!(sharer.login().expect("sharer login").is_accepted());
 
     let browser_port = free_port().expect("browser port");
     let mut browser = Client::with_settings(server.listening_settings(
@@ -1347,7 +1030,7 @@ fn browse_a_peers_shared_files() {
         browser_port,
     ));
     browser.connect().expect("browser connect");
-    assert!(browser.login().expect("browser login"));
+    assert!(browser.login().expect("browser login").is_accepted());
 
     std::thread::sleep(Duration::from_secs(1));
 
@@ -1394,7 +1077,7 @@ fn browse_a_firewalled_peer_via_broker() {
         ..server.settings("e2e_fw_sharer", "pw")
     });
     sharer.connect().expect("sharer connect");
-    assert!(sharer.login().expect("sharer login"));
+    assert!(sharer.login().expect("sharer login").is_accepted());
 
     // Browser listens so the firewalled peer can connect back to it.
     let browser_port = free_port().expect("browser port");
@@ -1404,7 +1087,7 @@ fn browse_a_firewalled_peer_via_broker() {
         browser_port,
     ));
     browser.connect().expect("browser connect");
-    assert!(browser.login().expect("browser login"));
+    assert!(browser.login().expect("browser login").is_accepted());
 
     std::thread::sleep(Duration::from_secs(1));
 
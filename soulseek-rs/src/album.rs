@@ -0,0 +1,270 @@
+//! Picking an album for `album`: the search results grouped by uploader
+//! folder, the best folder chosen, then its full contents fetched from the
+//! uploader's shares.
+
+use crate::models::{FileDisplayData, QualityClass, folder_of};
+use soulseek_rs::utils::path::sanitize_filename;
+use soulseek_rs::{File, SearchResult, SharedDirectory};
+use std::cmp::Reverse;
+use std::path::Path;
+
+/// File extensions counted as tracks.
+const AUDIO_EXTENSIONS: [&str; 12] = [
+    "flac", "wav", "aiff", "aif", "ape", "alac", "mp3", "ogg", "opus", "m4a",
+    "aac", "wma",
+];
+
+/// One uploader's folder among the search results.
+#[derive(Debug, Clone)]
+pub struct Album<'a> {
+    pub result: &'a SearchResult,
+    /// The folder's remote path.
+    pub folder: &'a str,
+    /// The folder's files that matched the search.
+    pub files: Vec<&'a File>,
+}
+
+impl Album<'_> {
+    /// The files that are tracks rather than cover art, cue sheets or logs.
+    pub fn tracks(&self) -> impl Iterator<Item = &File> {
+        self.files
+            .iter()
+            .copied()
+            .filter(|file| is_track(&file.name))
+    }
+
+    /// The quality of the worst track; an album is only as lossless as all
+    /// of it.
+    #[must_use]
+    pub fn quality(&self) -> QualityClass {
+        self.tracks()
+            .map(|file| FileDisplayData::new(self.result, file).quality_class())
+            .max()
+            .unwrap_or(QualityClass::Other)
+    }
+
+    #[must_use]
+    pub fn score(&self) -> Score {
+        Score {
+            tracks: self.tracks().count(),
+            quality: Reverse(self.quality()),
+            free_slot: self.result.free_slot,
+            queue_length: Reverse(self.result.queue_length),
+            speed: self.result.speed,
+        }
+    }
+}
+
+/// How good a folder is as the album; higher is better. Compared field by
+/// field: the most tracks, then the quality of the worst one, a free slot,
+/// the shorter queue and finally the faster uploader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score {
+    tracks: usize,
+    quality: Reverse<QualityClass>,
+    free_slot: bool,
+    queue_length: Reverse<u32>,
+    speed: u32,
+}
+
+fn is_track(filename: &str) -> bool {
+    filename.rsplit_once('.').is_some_and(|(_, ext)| {
+        AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+    })
+}
+
+/// Every uploader folder holding a track, in result order.
+#[must_use]
+pub fn albums(results: &[SearchResult]) -> Vec<Album<'_>> {
+    let mut albums: Vec<Album<'_>> = Vec::new();
+    for result in results {
+        let first = albums.len();
+        for file in &result.files {
+            let folder = folder_of(&file.name);
+            match albums[first..].iter_mut().find(|a| a.folder == folder) {
+                Some(album) => album.files.push(file),
+                None => albums.push(Album {
+                    result,
+                    folder,
+                    files: vec![file],
+                }),
+            }
+        }
+    }
+    albums.retain(|album| album.tracks().next().is_some());
+    albums
+}
+
+/// The top-scoring folder across all `results`. Ties go to whichever
+/// arrived first.
+#[must_use]
+pub fn best(results: &[SearchResult]) -> Option<Album<'_>> {
+    albums(results).into_iter().rev().max_by_key(Album::score)
+}
+
+/// Every file (remote path, size) in `folder` and its subfolders, from the
+/// uploader's share listing.
+#[must_use]
+pub fn listed_files(
+    directories: &[SharedDirectory],
+    folder: &str,
+) -> Vec<(String, u64)> {
+    directories
+        .iter()
+        .filter(|dir| {
+            let name = dir.name.trim_end_matches('\\');
+            name == folder
+                || name
+                    .strip_prefix(folder)
+                    .is_some_and(|rest| rest.starts_with('\\'))
+        })
+        .flat_map(|dir| {
+            let name = dir.name.trim_end_matches('\\');
+            dir.files
+                .iter()
+                .map(move |(file, size)| (format!("{name}\\{file}"), *size))
+        })
+        .collect()
+}
+
+/// Where `filename` from the album `folder` is saved: a folder named after
+/// the album's inside `download_dir`, keeping any subfolders (like `CD1`).
+#[must_use]
+pub fn destination(download_dir: &str, folder: &str, filename: &str) -> String {
+    let album = folder.rsplit('\\').next().unwrap_or(folder);
+    let subfolders = folder_of(filename)
+        .strip_prefix(folder)
+        .unwrap_or_default()
+        .split('\\')
+        .filter(|part| !part.is_empty());
+    std::iter::once(album)
+        .chain(subfolders)
+        .fold(Path::new(download_dir).to_path_buf(), |path, part| {
+            path.join(sanitize_filename(part))
+        })
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soulseek_rs::FileAttributes;
+
+    fn file(name: &str, bitrate: Option<u32>) -> File {
+        File {
+            username: String::new(),
+            name: name.into(),
+            size: 10,
+            attributes: FileAttributes {
+                bitrate,
+                ..FileAttributes::default()
+            },
+            name_charset: None,
+            locked: false,
+        }
+    }
+
+    fn result(
+        username: &str,
+        free_slot: bool,
+        files: Vec<File>,
+    ) -> SearchResult {
+        SearchResult {
+            token: 1,
+            files,
+            free_slot,
+            speed: 0,
+            queue_length: 0,
+            username: username.into(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_most_complete_folder_wins_then_the_best_quality() {
+        let results = [
+            result(
+                "amy",
+                true,
+                vec![
+                    file("Music\\Xtal\\01 Xtal.mp3", Some(320)),
+                    file("Music\\Xtal\\cover.jpg", None),
+                    file("Music\\Xtal\\cover2.jpg", None),
+                ],
+            ),
+            result(
+                "bob",
+                false,
+                vec![
+                    file("A\\SAW\\01 Xtal.mp3", Some(320)),
+                    file("A\\SAW\\02 Tha.mp3", Some(192)),
+                    file("A\\Other\\03 Pulsewidth.mp3", Some(320)),
+                ],
+            ),
+            result(
+                "cat",
+                false,
+                vec![
+                    file("SAW 85-92\\01 Xtal.flac", None),
+                    file("SAW 85-92\\02 Tha.flac", None),
+                ],
+            ),
+        ];
+        let best = best(&results).unwrap();
+        assert_eq!(
+            (best.result.username.as_str(), best.folder),
+            ("cat", "SAW 85-92")
+        );
+        assert_eq!(best.quality(), QualityClass::Lossless);
+
+        // bob's SAW has as many tracks, but one is only 192 kbps.
+        let albums = albums(&results);
+        assert_eq!(albums.len(), 4);
+        assert_eq!(albums[1].quality(), QualityClass::Other);
+        // Cover art doesn't make amy's folder more complete.
+        assert_eq!(albums[0].tracks().count(), 1);
+    }
+
+    #[test]
+    fn the_listing_covers_the_folder_and_its_subfolders() {
+        let dir = |name: &str, files: &[&str]| SharedDirectory {
+            name: name.into(),
+            files: files.iter().map(|f| ((*f).to_string(), 1)).collect(),
+        };
+        let directories = [
+            dir("Music\\SAW", &["01 Xtal.flac", "cover.jpg"]),
+            dir("Music\\SAW\\CD2\\", &["01 Stone.flac"]),
+            dir("Music\\SAW II", &["01 Cliffs.flac"]),
+        ];
+        let files: Vec<_> = listed_files(&directories, "Music\\SAW")
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "Music\\SAW\\01 Xtal.flac",
+                "Music\\SAW\\cover.jpg",
+                "Music\\SAW\\CD2\\01 Stone.flac"
+            ]
+        );
+    }
+
+    #[test]
+    fn files_land_in_a_folder_named_after_the_album() {
+        let dest = |filename| destination("/music", "Music\\SAW", filename);
+        assert_eq!(
+            Path::new(&dest("Music\\SAW\\01 Xtal.flac")),
+            Path::new("/music/SAW")
+        );
+        assert_eq!(
+            Path::new(&dest("Music\\SAW\\CD2\\01 Stone.flac")),
+            Path::new("/music/SAW/CD2")
+        );
+        assert_eq!(
+            Path::new(&destination("/music", "..", "..\\x.flac")),
+            Path::new("/music/download")
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! `batch`: search for a list of queries and download the best result of
+//! each, then report what happened as JSON.
+//!
+//! ```json
+//! { "downloaded": 1, "failed": 0, "no_results": 1, "queries": [
+//!   { "query": "aphex twin xtal", "outcome": "downloaded",
+//!     "file": { "username": "alice", "filename": "...", ... },
+//!     "path": "/home/me/Music/01 Xtal.flac" },
+//!   { "query": "nothing by nobody", "outcome": "no_results" } ] }
+//! ```
+
+use crate::download_list::Entry;
+use serde::Serialize;
+use soulseek_rs::DownloadStatus;
+use std::path::PathBuf;
+
+/// The queries in a batch file: one per line, skipping blank lines and `#`
+/// comments.
+#[must_use]
+pub fn parse_queries(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Downloaded,
+    NoResults,
+    Failed,
+}
+
+/// What became of one query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryReport {
+    pub query: String,
+    pub outcome: Outcome,
+    /// The file picked for the query, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<Entry>,
+    /// Where the download was saved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl QueryReport {
+    #[must_use]
+    pub fn no_results(query: &str) -> Self {
+        Self {
+            query: query.to_string(),
+            outcome: Outcome::NoResults,
+            file: None,
+            path: None,
+            error: None,
+        }
+    }
+
+    /// `query` failed before or while downloading `file`.
+    #[must_use]
+    pub fn failed(
+        query: &str,
+        file: Option<Entry>,
+        error: impl Into<String>,
+    ) -> Self {
+        Self {
+            query: query.to_string(),
+            outcome: Outcome::Failed,
+            file,
+            path: None,
+            error: Some(error.into()),
+        }
+    }
+
+    /// `file`, picked for `query`, finished as `status`.
+    #[must_use]
+    pub fn finished(query: &str, file: Entry, status: &DownloadStatus) -> Self {
+        match status {
+            DownloadStatus::Completed(summary) => Self {
+                query: query.to_string(),
+                outcome: Outcome::Downloaded,
+                file: Some(file),
+                path: summary.as_ref().map(|summary| summary.path.clone()),
+                error: None,
+            },
+            DownloadStatus::Failed(Some(reason)) => {
+                Self::failed(query, Some(file), reason.to_string())
+            }
+            DownloadStatus::Failed(None) => {
+                Self::failed(query, Some(file), "failed")
+            }
+            _ => Self::failed(query, Some(file), "timed out"),
+        }
+    }
+}
+
+/// The whole batch, in input order, with counts per outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub downloaded: usize,
+    pub failed: usize,
+    pub no_results: usize,
+    pub queries: Vec<QueryReport>,
+}
+
+impl Report {
+    #[must_use]
+    pub fn new(queries: Vec<QueryReport>) -> Self {
+        let count = |outcome| {
+            queries
+                .iter()
+                .filter(|query| query.outcome == outcome)
+                .count()
+        };
+        Self {
+            downloaded: count(Outcome::Downloaded),
+            failed: count(Outcome::Failed),
+            no_results: count(Outcome::NoResults),
+            queries,
+        }
+    }
+
+    /// Whether every query ended in a download.
+    #[must_use]
+    pub const fn succeeded(&self) -> bool {
+        self.downloaded == self.queries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soulseek_rs::types::{DownloadSummary, FailureReason};
+    use std::time::Duration;
+
+    #[test]
+    fn queries_skip_blank_lines_and_comments() {
+        let text =
+            "# to get\naphex twin xtal\n\n  boards of canada roygbiv  \n";
+        assert_eq!(
+            parse_queries(text),
+            ["aphex twin xtal", "boards of canada roygbiv"]
+        );
+    }
+
+    #[test]
+    fn report_counts_outcomes_and_serializes_what_is_known() {
+        let file = Entry::new("alice".into(), "Music\\01 Xtal.flac".into(), 42);
+        let summary = DownloadSummary::new(
+            "/music/01 Xtal.flac".into(),
+            42,
+            Duration::from_secs(1),
+        );
+        let report = Report::new(vec![
+            QueryReport::finished(
+                "xtal",
+                file.clone(),
+                &DownloadStatus::Completed(Some(summary)),
+            ),
+            QueryReport::no_results("nothing"),
+            QueryReport::finished(
+                "roygbiv",
+                file,
+                &DownloadStatus::Failed(Some(FailureReason::Banned)),
+            ),
+        ]);
+        assert_eq!(
+            (report.downloaded, report.failed, report.no_results),
+            (1, 1, 1)
+        );
+        assert!(!report.succeeded());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["queries"][0]["outcome"], "downloaded");
+        assert_eq!(json["queries"][0]["path"], "/music/01 Xtal.flac");
+        assert_eq!(json["queries"][0]["file"]["username"], "alice");
+        assert_eq!(
+            json["queries"][1],
+            serde_json::json!({ "query": "nothing", "outcome": "no_results" })
+        );
+        assert_eq!(json["queries"][2]["error"], "Banned by the user");
+    }
+}
@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -37,6 +37,11 @@ pub struct Cli {
     )]
     pub log_file: Option<PathBuf>,
 
+    /// Log levels per subsystem over the -v level, e.g.
+    /// `peer=trace,server=info,zlib=warn`
+    #[arg(long, env = "LOG_FILTER")]
+    pub log_filter: Option<String>,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 
@@ -61,24 +66,143 @@ pub struct Cli {
     pub search_timeout: Option<u64>,
 }
 
+/// What `search --auto` and `batch --auto` download.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoMode {
+    /// The single top-ranked file: a free slot first, then lossless, the
+    /// higher bitrate, the shorter queue and the faster uploader
+    Best,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Search {
         query: String,
 
-        #[arg(short, long, default_value = "10")]
-        timeout: u64,
+        /// Seconds to collect results (default: search_timeout, then 10)
+        #[arg(short, long)]
+        timeout: Option<u64>,
+
+        /// Directory to save to (default: download_dir from config.toml)
+        #[arg(short, long)]
+        download_dir: Option<String>,
+
+        /// Maximum simultaneous downloads (default: 5)
+        #[arg(short = 'c', long, env = "MAX_CONCURRENT_DOWNLOADS")]
+        max_concurrent_downloads: Option<usize>,
+
+        /// Print the results as JSON instead of picking files to download
+        /// (the format `download --from-json` reads)
+        #[arg(long)]
+        json: bool,
+
+        /// Skip the file picker and download automatically
+        #[arg(long, value_enum, conflicts_with = "json")]
+        auto: Option<AutoMode>,
+    },
+
+    /// Download files without the TUI, for scripts and cron jobs. Exits
+    /// non-zero if any download fails.
+    Download {
+        /// Username of the peer sharing the file
+        #[arg(long, requires = "file", required_unless_present = "from_json")]
+        user: Option<String>,
+
+        /// Full remote path of the file, as shown in search results
+        #[arg(long, requires = "user")]
+        file: Option<String>,
+
+        /// File size in bytes, if known (the peer reports it otherwise)
+        #[arg(long, requires = "file")]
+        size: Option<u64>,
+
+        /// Download every file listed in this JSON file (`-` for stdin),
+        /// e.g. saved from `search --json`
+        #[arg(long, conflicts_with = "user")]
+        from_json: Option<PathBuf>,
+
+        /// Directory to save to (default: download_dir from config.toml)
+        #[arg(long)]
+        dest: Option<String>,
+
+        /// Give up on files not finished after this many seconds (default:
+        /// wait as long as the peer's queue takes)
+        #[arg(short, long)]
+        timeout: Option<u64>,
+    },
+
+    /// Search for an album and download the whole folder of the uploader
+    /// with the most complete, best-quality copy
+    Album {
+        /// What to search for, e.g. "<artist> <album>"
+        query: String,
+
+        /// Seconds to collect results (default: search_timeout, then 10)
+        #[arg(short, long)]
+        timeout: Option<u64>,
+
+        /// Directory the album's folder is created in (default:
+        /// download_dir from config.toml)
+        #[arg(long)]
+        dest: Option<String>,
+
+        /// Maximum simultaneous downloads (default: 5)
+        #[arg(short = 'c', long)]
+        max_concurrent_downloads: Option<usize>,
+    },
 
-        #[arg(short, long, default_value = "~/Downloads")]
-        download_dir: String,
+    /// Search for every query in a file and download the best result of
+    /// each, then print a JSON report. Exits non-zero unless every query
+    /// was downloaded.
+    Batch {
+        /// File with one query per line (`-` for stdin); blank lines and
+        /// lines starting with `#` are skipped
+        #[arg(long)]
+        input: PathBuf,
 
+        /// How to pick each query's download
+        #[arg(long, value_enum, default_value = "best")]
+        auto: AutoMode,
+
+        /// Directory to save to (default: download_dir from config.toml)
+        #[arg(long)]
+        dest: Option<String>,
+
+        /// Downloads at once; by default one after another
+        #[arg(short = 'c', long, default_value = "1")]
+        max_concurrent_downloads: usize,
+
+        /// Seconds to collect each query's results (default:
+        /// search_timeout, then 10)
+        #[arg(short, long)]
+        timeout: Option<u64>,
+
+        /// Give up on files not finished after this many seconds (default:
+        /// wait as long as the peer's queue takes)
+        #[arg(long)]
+        download_timeout: Option<u64>,
+
+        /// Write the report to this file instead of stdout
+        #[arg(long)]
+        report: Option<PathBuf>,
+    },
+
+    /// Stay logged in and take search/download/status/cancel commands as
+    /// JSON-RPC over a local control socket
+    Daemon {
+        /// Address the control socket listens on. Clients authenticate with
+        /// the token in `control_token` in the state directory; still, keep
+        /// it on localhost.
         #[arg(
-            short = 'c',
             long,
-            env = "MAX_CONCURRENT_DOWNLOADS",
-            default_value = "5"
+            env = "SOULSEEK_CONTROL_ADDRESS",
+            default_value = "127.0.0.1:2244"
         )]
-        max_concurrent_downloads: usize,
+        control: String,
+
+        /// Serve Prometheus metrics at `/metrics` on this address
+        #[arg(long, env = "SOULSEEK_METRICS_ADDRESS")]
+        metrics: Option<String>,
     },
 
     /// Send a private message to another user
@@ -96,9 +220,34 @@ pub enum Commands {
         username: String,
     },
 
+    /// Show a user's status, stats, country, shares and upload queue
+    User {
+        /// Username to inspect
+        username: String,
+
+        /// Print a JSON object instead of a human-readable summary
+        #[arg(long)]
+        json: bool,
+
+        /// Seconds to wait for the server's and the peer's replies
+        #[arg(short, long, default_value = "10")]
+        timeout: u64,
+    },
+
     /// List the public chat rooms and their user counts
     Rooms,
 
+    /// Run a saved search from config.toml, downloading per its
+    /// auto_download policy
+    RunSaved {
+        /// Name of the `[saved_searches.<name>]` entry
+        name: String,
+
+        /// Seconds to collect results before acting on them
+        #[arg(short, long, default_value = "10")]
+        timeout: u64,
+    },
+
     /// Join a chat room: print messages, or send one and exit
     Chat {
         /// Name of the room to join
@@ -11,4 +11,5 @@ pub struct SearchConfig {
     pub verbose: u8,
     pub max_concurrent_downloads: usize,
     pub shared_directories: Vec<String>,
+    pub keymap: crate::models::Keymap,
 }
@@ -0,0 +1,718 @@
+//! `soulseek-rs daemon`: stay logged in and take commands over a local
+//! control socket, so other programs can drive the client without linking
+//! the library.
+//!
+//! The protocol is JSON-RPC 2.0 over TCP, one request per line and one
+//! response per line. Each connection first authenticates with the token the
+//! daemon writes, readable only by its user, to `control_token` in the state
+//! directory:
+//!
+//! ```text
+//! → {"jsonrpc": "2.0", "id": 0, "method": "auth", "params": {"token": "…"}}
+//! ← {"jsonrpc": "2.0", "id": 0, "result": true}
+//! → {"jsonrpc": "2.0", "id": 1, "method": "search", "params": {"query": "aphex twin"}}
+//! ← {"jsonrpc": "2.0", "id": 1, "result": [{"username": "alice", "filename": "…", "size": 123}]}
+//! ```
+//!
+//! | method     | params                                      | result                   |
+//! |------------|---------------------------------------------|--------------------------|
+//! | `auth`     | `token`                                     | `true`                   |
+//! | `search`   | `query`, `timeout` (seconds, optional)      | a [`download_list`]      |
+//! | `download` | `username`, `filename`, `size`?, `dest`?    | `true`                   |
+//! | `status`   | none                                        | connection and downloads |
+//! | `cancel`   | `username`, `filename`                      | whether it was found     |
+//! | `shutdown` | none                                        | `true`, then exits       |
+//!
+//! A `dest` is a folder inside the download directory. The connection is
+//! closed on a failed `auth` and on the first line that isn't a JSON-RPC
+//! request, so a web page posting to the port gets no further than its
+//! request line.
+//!
+//! [`download_list`]: crate::download_list
+
+use crate::download_list::Entry;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use soulseek_rs::{Client, ConnectionState, DownloadStatus};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How often blocked accepts and reads check for shutdown.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+const UNAUTHORIZED: i64 = -32001;
+
+const METHODS: [&str; 5] =
+    ["search", "download", "status", "cancel", "shutdown"];
+
+/// What the control socket drives: the logged-in client, or a fake in tests.
+pub trait Backend: Sync {
+    fn search(
+        &self,
+        query: &str,
+        timeout: Duration,
+    ) -> Result<Vec<Entry>, String>;
+    fn download(
+        &self,
+        entry: Entry,
+        dest: Option<String>,
+    ) -> Result<(), String>;
+    fn status(&self) -> Value;
+    fn cancel(&self, username: &str, filename: &str) -> bool;
+}
+
+/// A [`Backend`] over a logged-in client.
+pub struct ClientBackend<'a> {
+    pub client: &'a Client,
+    pub download_dir: String,
+    pub search_timeout: Duration,
+}
+
+impl Backend for ClientBackend<'_> {
+    fn search(
+        &self,
+        query: &str,
+        timeout: Duration,
+    ) -> Result<Vec<Entry>, String> {
+        let timeout = if timeout.is_zero() {
+            self.search_timeout
+        } else {
+            timeout
+        };
+        let results: Vec<_> = self
+            .client
+            .search_stream(query, timeout)
+            .map_err(|e| e.to_string())?
+            .collect();
+        Ok(crate::download_list::from_results(&results))
+    }
+
+    fn download(
+        &self,
+        entry: Entry,
+        dest: Option<String>,
+    ) -> Result<(), String> {
+        let dest = match dest {
+            Some(dest) => download_dest(&self.download_dir, &dest)?,
+            None => self.download_dir.clone(),
+        };
+        // Progress is read back through `status`, not the channel.
+        self.client
+            .download(entry.filename, entry.username, entry.size, dest)
+            .map(drop)
+            .map_err(|e| e.to_string())
+    }
+
+    fn status(&self) -> Value {
+        let connection = match self.client.connection_state() {
+            ConnectionState::Disconnected { reason } => {
+                json!({ "state": "disconnected", "reason": reason })
+            }
+            ConnectionState::Connecting => json!({ "state": "connecting" }),
+            ConnectionState::Connected => json!({ "state": "connected" }),
+            ConnectionState::LoggedIn => json!({ "state": "logged_in" }),
+        };
+        let downloads: Vec<Value> = self
+            .client
+            .get_all_downloads()
+            .iter()
+            .map(|download| {
+                let mut value = status_json(&download.status);
+                value.insert("username".into(), json!(download.username));
+                value.insert("filename".into(), json!(download.filename));
+                value.insert("size".into(), json!(download.size));
+                value.insert(
+                    "queue_position".into(),
+                    json!(download.queue_position),
+                );
+                Value::Object(value)
+            })
+            .collect();
+        json!({ "connection": connection, "downloads": downloads })
+    }
+
+    fn cancel(&self, username: &str, filename: &str) -> bool {
+        // Stop the transfer before forgetting it, so it doesn't keep writing.
+        let _ = self.client.cancel_download(username, filename);
+        self.client.remove_download(username, filename)
+    }
+}
+
+/// The folder `dest` names inside `download_dir`: a relative path, or an
+/// absolute one under `download_dir`. Anything reaching outside it is
+/// refused.
+fn download_dest(download_dir: &str, dest: &str) -> Result<String, String> {
+    let path = Path::new(dest);
+    let relative = if path.is_absolute() {
+        path.strip_prefix(download_dir).map_err(|_| {
+            format!("dest {dest} is outside the download directory")
+        })?
+    } else {
+        path
+    };
+    let mut folder = PathBuf::from(download_dir);
+    for component in relative.components() {
+        match component {
+            Component::Normal(part) => folder.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(format!(
+                    "dest {dest} is outside the download directory"
+                ));
+            }
+        }
+    }
+    Ok(folder.display().to_string())
+}
+
+/// A download status as `status` plus whichever fields it carries.
+fn status_json(status: &DownloadStatus) -> serde_json::Map<String, Value> {
+    let value = match status {
+        DownloadStatus::Queued => json!({ "status": "queued" }),
+        DownloadStatus::InProgress {
+            bytes_downloaded,
+            speed_bytes_per_sec,
+            average_speed_bytes_per_sec,
+            eta,
+            ..
+        } => json!({
+            "status": "in_progress",
+            "bytes_downloaded": bytes_downloaded,
+            "speed_bytes_per_sec": speed_bytes_per_sec,
+            "average_speed_bytes_per_sec": average_speed_bytes_per_sec,
+            "eta_secs": eta.map(|eta| eta.as_secs()),
+        }),
+        DownloadStatus::Paused {
+            bytes_downloaded, ..
+        } => json!({
+            "status": "paused",
+            "bytes_downloaded": bytes_downloaded,
+        }),
+        DownloadStatus::Completed(summary) => json!({
+            "status": "completed",
+            "path": summary.as_ref().map(|s| s.path.display().to_string()),
+        }),
+        DownloadStatus::Failed(reason) => {
+            let reason = reason.as_ref().map(ToString::to_string);
+            json!({ "status": "failed", "reason": reason })
+        }
+        DownloadStatus::TimedOut => json!({ "status": "timed_out" }),
+    };
+    match value {
+        Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "lowercase")]
+enum Call {
+    Search {
+        query: String,
+        #[serde(default)]
+        timeout: u64,
+    },
+    Download {
+        username: String,
+        filename: String,
+        #[serde(default)]
+        size: u64,
+        dest: Option<String>,
+    },
+    Status,
+    Cancel {
+        username: String,
+        filename: String,
+    },
+    Shutdown,
+}
+
+/// `line` as a JSON-RPC 2.0 request and its method, or the error to reply
+/// with before closing the connection.
+fn parse_request(line: &str) -> Result<(Value, String), String> {
+    let request: Value = serde_json::from_str(line)
+        .map_err(|e| error(&Value::Null, PARSE_ERROR, &e.to_string()))?;
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(error(&id, INVALID_REQUEST, "not a JSON-RPC 2.0 request"));
+    }
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return Err(error(&id, INVALID_REQUEST, "missing method"));
+    };
+    let method = method.to_string();
+    Ok((request, method))
+}
+
+/// Check the first request on a connection, which must be an `auth` with
+/// `token`. Returns its reply, or the error to reply with before closing
+/// the connection.
+pub fn authenticate(line: &str, token: &str) -> Result<String, String> {
+    let (request, method) = parse_request(line)?;
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let given = request
+        .get("params")
+        .and_then(|params| params.get("token"))
+        .and_then(Value::as_str);
+    if method != "auth" || !given.is_some_and(|given| same_token(given, token))
+    {
+        return Err(error(&id, UNAUTHORIZED, "authenticate first"));
+    }
+    Ok(json!({ "jsonrpc": "2.0", "id": id, "result": true }).to_string())
+}
+
+/// Compare tokens without returning early, so the time taken doesn't tell
+/// how much of a guess was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// A fresh control token: 256 bits from the OS random number generator, in
+/// hex.
+pub fn new_token() -> std::io::Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes)
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    Ok(bytes.iter().fold(String::new(), |mut token, byte| {
+        let _ = write!(token, "{byte:02x}");
+        token
+    }))
+}
+
+/// Write `token` to `path`, readable and writable only by the user.
+pub fn write_token(path: &Path, token: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // A file left from an earlier run keeps its mode otherwise.
+        if path.exists() {
+            std::fs::set_permissions(
+                path,
+                std::fs::Permissions::from_mode(0o600),
+            )?;
+        }
+    }
+    options.open(path)?.write_all(token.as_bytes())
+}
+
+/// Handle one request line. Returns the response line, if the request
+/// wants one (notifications, without an `id`, get none), and sets `stop`
+/// on `shutdown`. A line that isn't a JSON-RPC request is an error, with
+/// the reply to send before closing the connection.
+pub fn handle_line(
+    backend: &impl Backend,
+    line: &str,
+    stop: &AtomicBool,
+) -> Result<Option<String>, String> {
+    let (request, method) = parse_request(line)?;
+    let method = method.as_str();
+    let id = request.get("id").cloned();
+    let reply_id = id.clone().unwrap_or(Value::Null);
+
+    let call = json!({ "method": method, "params": request.get("params") });
+    let response = match serde_json::from_value::<Call>(call) {
+        Ok(call) => match dispatch(backend, call, stop) {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "id": reply_id,
+                "result": result,
+            })
+            .to_string(),
+            Err(message) => error(&reply_id, SERVER_ERROR, &message),
+        },
+        Err(_) if !METHODS.contains(&method) => error(
+            &reply_id,
+            METHOD_NOT_FOUND,
+            &format!("unknown method {method}"),
+        ),
+        Err(e) => error(&reply_id, INVALID_PARAMS, &e.to_string()),
+    };
+    Ok(id.map(|_| response))
+}
+
+fn dispatch(
+    backend: &impl Backend,
+    call: Call,
+    stop: &AtomicBool,
+) -> Result<Value, String> {
+    match call {
+        Call::Search { query, timeout } => {
+            let entries =
+                backend.search(&query, Duration::from_secs(timeout))?;
+            serde_json::to_value(entries).map_err(|e| e.to_string())
+        }
+        Call::Download {
+            username,
+            filename,
+            size,
+            dest,
+        } => backend
+            .download(Entry::new(username, filename, size), dest)
+            .map(|()| Value::Bool(true)),
+        Call::Status => Ok(backend.status()),
+        Call::Cancel { username, filename } => {
+            Ok(Value::Bool(backend.cancel(&username, &filename)))
+        }
+        Call::Shutdown => {
+            stop.store(true, Ordering::Relaxed);
+            Ok(Value::Bool(true))
+        }
+    }
+}
+
+fn error(id: &Value, code: i64, message: &str) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+    .to_string()
+}
+
+/// Serve requests on `listener`, one thread per connection, until a
+/// `shutdown` request or `stop` is set. Connections authenticate with
+/// `token`.
+pub fn serve(
+    backend: &impl Backend,
+    listener: &TcpListener,
+    stop: &AtomicBool,
+    token: &str,
+) -> std::io::Result<()> {
+    listener.set_nonblocking(true)?;
+    std::thread::scope(|scope| {
+        while !stop.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    scope.spawn(move || {
+                        serve_connection(backend, stream, stop, token);
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })
+}
+
+fn serve_connection(
+    backend: &impl Backend,
+    stream: TcpStream,
+    stop: &AtomicBool,
+    token: &str,
+) {
+    // Accepted sockets may inherit the listener's non-blocking mode.
+    if stream.set_nonblocking(false).is_err()
+        || stream.set_read_timeout(Some(POLL_INTERVAL)).is_err()
+    {
+        return;
+    }
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    let mut authenticated = false;
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => return,
+            Ok(_) => {
+                if !line.trim().is_empty() {
+                    let reply = if authenticated {
+                        handle_line(backend, &line, stop)
+                    } else {
+                        authenticate(&line, token).map(Some)
+                    };
+                    let (response, close) = match reply {
+                        Ok(response) => (response, false),
+                        Err(response) => (Some(response), true),
+                    };
+                    // Whatever didn't close the connection got past `auth`.
+                    authenticated = true;
+                    if let Some(response) = response
+                        && writeln!(writer, "{response}").is_err()
+                    {
+                        return;
+                    }
+                    if close {
+                        return;
+                    }
+                }
+                line.clear();
+            }
+            // A partial line stays in `line` until the rest arrives.
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Fake {
+        downloads: Mutex<Vec<(Entry, Option<String>)>>,
+    }
+
+    impl Backend for Fake {
+        fn search(
+            &self,
+            query: &str,
+            _timeout: Duration,
+        ) -> Result<Vec<Entry>, String> {
+            if query.is_empty() {
+                return Err("empty query".into());
+            }
+            Ok(vec![Entry::new("alice".into(), format!("{query}.flac"), 1)])
+        }
+
+        fn download(
+            &self,
+            entry: Entry,
+            dest: Option<String>,
+        ) -> Result<(), String> {
+            self.downloads.lock().unwrap().push((entry, dest));
+            Ok(())
+        }
+
+        fn status(&self) -> Value {
+            json!({ "downloads": self.downloads.lock().unwrap().len() })
+        }
+
+        fn cancel(&self, _username: &str, filename: &str) -> bool {
+            filename == "queued.mp3"
+        }
+    }
+
+    fn call(backend: &Fake, line: &str) -> Value {
+        let stop = AtomicBool::new(false);
+        let reply = handle_line(backend, line, &stop)
+            .unwrap_or_else(Some)
+            .unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+
+    #[test]
+    fn methods_reply_with_their_result_and_id() {
+        let fake = Fake::default();
+        let search = call(
+            &fake,
+            r#"{"jsonrpc":"2.0","id":1,"method":"search","params":{"query":"intro"}}"#,
+        );
+        assert_eq!(search["id"], 1);
+        assert_eq!(search["result"][0]["filename"], "intro.flac");
+
+        let download = call(
+            &fake,
+            r#"{"jsonrpc":"2.0","id":"a","method":"download","params":{"username":"bob","filename":"x.mp3","dest":"jazz"}}"#,
+        );
+        assert_eq!(download["result"], true);
+        assert_eq!(
+            *fake.downloads.lock().unwrap(),
+            [(
+                Entry::new("bob".into(), "x.mp3".into(), 0),
+                Some("jazz".into())
+            )]
+        );
+
+        let status =
+            call(&fake, r#"{"jsonrpc":"2.0","id":2,"method":"status"}"#);
+        assert_eq!(status["result"]["downloads"], 1);
+
+        let cancel = call(
+            &fake,
+            r#"{"jsonrpc":"2.0","id":3,"method":"cancel","params":{"username":"bob","filename":"queued.mp3"}}"#,
+        );
+        assert_eq!(cancel["result"], true);
+    }
+
+    #[test]
+    fn errors_use_json_rpc_codes() {
+        let fake = Fake::default();
+        assert_eq!(call(&fake, "{oops")["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            call(&fake, r#"{"id":1,"method":"status"}"#)["error"]["code"],
+            INVALID_REQUEST
+        );
+        assert_eq!(
+            call(&fake, r#"{"jsonrpc":"2.0","id":1,"method":"dance"}"#)["error"]
+                ["code"],
+            METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            call(
+                &fake,
+                r#"{"jsonrpc":"2.0","id":1,"method":"cancel","params":{}}"#
+            )["error"]["code"],
+            INVALID_PARAMS
+        );
+        let failed = call(
+            &fake,
+            r#"{"jsonrpc":"2.0","id":1,"method":"search","params":{"query":""}}"#,
+        );
+        assert_eq!(failed["error"]["code"], SERVER_ERROR);
+        assert_eq!(failed["error"]["message"], "empty query");
+    }
+
+    #[test]
+    fn notifications_get_no_reply_and_shutdown_stops() {
+        let fake = Fake::default();
+        let stop = AtomicBool::new(false);
+        assert_eq!(
+            handle_line(
+                &fake,
+                r#"{"jsonrpc":"2.0","method":"shutdown"}"#,
+                &stop
+            ),
+            Ok(None)
+        );
+        assert!(stop.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn dest_stays_inside_the_download_directory() {
+        let music = std::env::temp_dir().join("music");
+        let dir = music.display().to_string();
+        let inside = |path: &str| music.join(path).display().to_string();
+        assert_eq!(
+            download_dest(&dir, "jazz/live").unwrap(),
+            inside("jazz/live")
+        );
+        assert_eq!(
+            download_dest(&dir, &inside("jazz")).unwrap(),
+            inside("jazz")
+        );
+        let outside = std::env::temp_dir().join("etc").display().to_string();
+        for dest in [outside.as_str(), "../music-old", "jazz/../../etc"] {
+            assert!(download_dest(&dir, dest).is_err(), "{dest}");
+        }
+    }
+
+    #[test]
+    fn connections_must_authenticate_first() {
+        let token = new_token().unwrap();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, new_token().unwrap());
+        let auth = |given: &str| {
+            format!(
+                r#"{{"jsonrpc":"2.0","id":0,"method":"auth","params":{{"token":"{given}"}}}}"#
+            )
+        };
+        assert!(authenticate(&auth(&token), &token).is_ok());
+        assert!(authenticate(&auth("guess"), &token).is_err());
+        assert!(
+            authenticate(
+                r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#,
+                &token
+            )
+            .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_token_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir()
+            .join(format!("soulseek-control-{}", std::process::id()))
+            .join("control_token");
+        write_token(&path, "secret").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "secret");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    /// Send `lines` on a fresh connection and read the replies until the
+    /// daemon closes it or stops answering.
+    fn exchange(address: std::net::SocketAddr, lines: &[&str]) -> Vec<Value> {
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let mut writer = stream.try_clone().unwrap();
+        for line in lines {
+            // The daemon may already have closed the connection.
+            let _ = writeln!(writer, "{line}");
+        }
+        BufReader::new(stream)
+            .lines()
+            .map_while(Result::ok)
+            .map(|line| serde_json::from_str(&line).unwrap())
+            .take(lines.len())
+            .collect()
+    }
+
+    #[test]
+    fn serves_requests_over_tcp() {
+        let fake = Fake::default();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let stop = AtomicBool::new(false);
+        let token = new_token().unwrap();
+        let auth = format!(
+            r#"{{"jsonrpc":"2.0","id":0,"method":"auth","params":{{"token":"{token}"}}}}"#
+        );
+        std::thread::scope(|scope| {
+            let server = scope.spawn(|| serve(&fake, &listener, &stop, &token));
+
+            // A browser's POST: the request line closes the connection, so
+            // the body never runs.
+            let replies = exchange(
+                address,
+                &[
+                    "POST / HTTP/1.1",
+                    "Content-Type: text/plain",
+                    "",
+                    r#"{"jsonrpc":"2.0","id":1,"method":"download","params":{"username":"eve","filename":"x"}}"#,
+                ],
+            );
+            assert_eq!(replies.len(), 1);
+            assert_eq!(replies[0]["error"]["code"], PARSE_ERROR);
+            // Neither does anything before a successful `auth`.
+            let replies = exchange(
+                address,
+                &[r#"{"jsonrpc":"2.0","id":1,"method":"status"}"#],
+            );
+            assert_eq!(replies[0]["error"]["code"], UNAUTHORIZED);
+            assert!(fake.downloads.lock().unwrap().is_empty());
+
+            let replies = exchange(
+                address,
+                &[&auth, r#"{"jsonrpc":"2.0","id":7,"method":"shutdown"}"#],
+            );
+            assert_eq!(replies[0]["result"], true);
+            assert_eq!(replies[1]["id"], 7);
+            assert_eq!(replies[1]["result"], true);
+            server.join().unwrap().unwrap();
+        });
+    }
+}
@@ -0,0 +1,125 @@
+//! Files to download as JSON: what `search --json` prints and
+//! `download --from-json` reads, so a script can filter one into the other.
+//!
+//! ```json
+//! [{ "username": "alice", "filename": "Music\\Album\\01 Intro.flac", "size": 31457280 }]
+//! ```
+//!
+//! Only `username` and `filename` are required; other fields are
+//! informational and ignored when reading.
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use soulseek_rs::SearchResult;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub username: String,
+    /// The full remote path, as the peer shares it.
+    pub filename: String,
+    /// Size in bytes; 0 if unknown (the peer reports it when the transfer
+    /// starts).
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitrate: Option<u32>,
+    #[serde(default)]
+    pub free_slot: bool,
+    /// The peer's average upload speed in bytes/s.
+    #[serde(default)]
+    pub speed: u32,
+    /// Uploads the peer has queued ahead of us.
+    #[serde(default)]
+    pub queue_length: u32,
+}
+
+impl Entry {
+    #[must_use]
+    pub const fn new(username: String, filename: String, size: u64) -> Self {
+        Self {
+            username,
+            filename,
+            size,
+            bitrate: None,
+            free_slot: false,
+            speed: 0,
+            queue_length: 0,
+        }
+    }
+}
+
+/// One entry per file, in result order.
+#[must_use]
+pub fn from_results(results: &[SearchResult]) -> Vec<Entry> {
+    results
+        .iter()
+        .flat_map(|result| {
+            result.files.iter().map(|file| Entry {
+                username: result.username.clone(),
+                filename: file.name.clone(),
+                size: file.size,
+                bitrate: file.attributes.bitrate,
+                free_slot: result.free_slot,
+                speed: result.speed,
+                queue_length: result.queue_length,
+            })
+        })
+        .collect()
+}
+
+/// Parse a JSON array of entries.
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    serde_json::from_str(text)
+        .map_err(|e| color_eyre::eyre::eyre!("Malformed download list: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soulseek_rs::{File, FileAttributes};
+
+    #[test]
+    fn search_results_round_trip() {
+        let results = [SearchResult {
+            token: 1,
+            files: vec![File {
+                username: "alice".into(),
+                name: "Music\\01 Intro.flac".into(),
+                size: 42,
+                attributes: FileAttributes {
+                    bitrate: Some(320),
+                    ..FileAttributes::default()
+                },
+                name_charset: None,
+                locked: false,
+            }],
+            free_slot: true,
+            speed: 1000,
+            queue_length: 3,
+            username: "alice".into(),
+            locked_files: Vec::new(),
+        }];
+        let entries = from_results(&results);
+        assert_eq!(
+            entries,
+            [Entry {
+                bitrate: Some(320),
+                free_slot: true,
+                speed: 1000,
+                queue_length: 3,
+                ..Entry::new("alice".into(), "Music\\01 Intro.flac".into(), 42)
+            }]
+        );
+        let json = serde_json::to_string(&entries).unwrap();
+        assert_eq!(parse(&json).unwrap(), entries);
+    }
+
+    #[test]
+    fn only_user_and_file_are_required() {
+        let entries =
+            parse(r#"[{"username": "bob", "filename": "a.mp3", "extra": 1}]"#)
+                .unwrap();
+        assert_eq!(entries, [Entry::new("bob".into(), "a.mp3".into(), 0)]);
+        assert!(parse(r#"[{"username": "bob"}]"#).is_err());
+    }
+}
@@ -3,4 +3,6 @@
 
 pub mod cli;
 pub mod models;
+pub mod notifications;
 pub mod persist;
+pub mod saved_search;
@@ -1,22 +1,32 @@
+mod album;
+mod batch;
 mod cli;
 mod config;
+mod daemon;
 mod directories;
+mod download_list;
 mod models;
+mod notifications;
 mod persist;
 mod port_mapping;
+mod ranking;
+mod saved_search;
 mod ui;
 
 use clap::Parser;
-use cli::{Cli, Commands, parse_server_address};
+use cli::{AutoMode, Cli, Commands, parse_server_address};
 use color_eyre::Result;
 use config::SearchConfig;
-use soulseek_rs::{Client, ClientSettings, PeerAddress};
+use soulseek_rs::utils::logger::{self, LogFile, LogFilter, LogLevel};
+use soulseek_rs::{Client, ClientSettings, LoginOutcome, PeerAddress};
 use std::{
-    env,
     sync::{Arc, atomic::AtomicBool},
     time::Duration,
 };
-use ui::{FileSelector, launch_main_tui, show_multi_download_progress};
+use ui::{
+    FileSelector, download_report, launch_main_tui,
+    show_multi_download_progress,
+};
 
 fn main() -> Result<()> {
     dotenv::dotenv().ok();
@@ -24,8 +34,6 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    init_logging(&cli);
-
     // Layer CLI/env values over config.toml over built-in defaults.
     let config_path = persist::paths::config_file();
     let file_config = match &config_path {
@@ -34,6 +42,8 @@ fn main() -> Result<()> {
     };
     let resolved = persist::config::resolve(&cli, &file_config);
 
+    init_logging(&resolved);
+
     // `portmap` is a local network diagnostic; it needs no server credentials,
     // so handle it before requiring a username/password.
     if matches!(cli.command, Some(Commands::Portmap)) {
@@ -84,6 +94,16 @@ fn main() -> Result<()> {
         enable_listen: !resolved.disable_listener,
         listen_port: resolved.listener_port,
         shared_directories: shared_directories.clone(),
+        upload_slots: resolved.upload_slots,
+        max_upload_rate_kbps: resolved.max_upload_rate,
+        fallback_charsets: resolved.fallback_charsets.clone(),
+        leech_filter: resolved.leech_filter.clone(),
+        ignored_users: resolved.ignored_users.clone(),
+        favorite_users: resolved.favorite_users.clone(),
+        on_download_complete: resolved.on_download_complete.clone(),
+        history_file: persist::paths::download_history_file(),
+        share_index_file: persist::paths::share_index_file(),
+        ..ClientSettings::default()
     };
 
     match cli.command {
@@ -92,7 +112,22 @@ fn main() -> Result<()> {
             timeout,
             download_dir,
             max_concurrent_downloads,
+            json,
+            auto,
         }) => {
+            let timeout = timeout.unwrap_or(resolved.search_timeout);
+            if json {
+                return search_json(&settings, &query, timeout);
+            }
+            if auto == Some(AutoMode::Best) {
+                return search_best(
+                    &settings,
+                    &query,
+                    timeout,
+                    download_dir
+                        .unwrap_or_else(|| resolved.download_dir.clone()),
+                );
+            }
             let config = SearchConfig {
                 username,
                 password,
@@ -102,13 +137,77 @@ fn main() -> Result<()> {
                 listener_port: resolved.listener_port,
                 query,
                 timeout,
-                download_dir,
-                verbose: cli.verbose,
-                max_concurrent_downloads,
+                download_dir: download_dir
+                    .unwrap_or_else(|| resolved.download_dir.clone()),
+                verbose: resolved.verbose,
+                max_concurrent_downloads: max_concurrent_downloads
+                    .unwrap_or(resolved.max_concurrent_downloads),
                 shared_directories,
+                keymap: resolved.keymap,
             };
             search_and_download(config)
         }
+        Some(Commands::Download {
+            user,
+            file,
+            size,
+            from_json,
+            dest,
+            timeout,
+        }) => {
+            let entries = match (from_json, user, file) {
+                (Some(path), ..) => download_list::parse(&read_input(&path)?)?,
+                (None, Some(user), Some(file)) => {
+                    vec![download_list::Entry::new(
+                        user,
+                        file,
+                        size.unwrap_or(0),
+                    )]
+                }
+                // clap requires either --from-json or --user with --file.
+                _ => unreachable!(),
+            };
+            download_files(
+                &settings,
+                &entries,
+                dest.unwrap_or_else(|| resolved.download_dir.clone()),
+                resolved.max_concurrent_downloads,
+                timeout.map(Duration::from_secs),
+            )
+        }
+        Some(Commands::Album {
+            query,
+            timeout,
+            dest,
+            max_concurrent_downloads,
+        }) => download_album(
+            &settings,
+            &query,
+            Duration::from_secs(timeout.unwrap_or(resolved.search_timeout)),
+            &dest.unwrap_or_else(|| resolved.download_dir.clone()),
+            max_concurrent_downloads
+                .unwrap_or(resolved.max_concurrent_downloads),
+        ),
+        Some(Commands::Batch {
+            input,
+            auto: AutoMode::Best,
+            dest,
+            max_concurrent_downloads,
+            timeout,
+            download_timeout,
+            report,
+        }) => run_batch(
+            &settings,
+            &input,
+            Duration::from_secs(timeout.unwrap_or(resolved.search_timeout)),
+            dest.unwrap_or_else(|| resolved.download_dir.clone()),
+            max_concurrent_downloads,
+            download_timeout.map(Duration::from_secs),
+            report.as_deref(),
+        ),
+        Some(Commands::Daemon { control, metrics }) => {
+            run_daemon(&settings, &resolved, &control, metrics.as_deref())
+        }
         Some(Commands::Message {
             username: recipient,
             message,
@@ -116,7 +215,18 @@ fn main() -> Result<()> {
         Some(Commands::Browse { username: target }) => {
             browse_user(&settings, &target)
         }
+        Some(Commands::User {
+            username: target,
+            json,
+            timeout,
+        }) => inspect_user(&settings, &target, json, timeout),
         Some(Commands::Rooms) => list_rooms(&settings),
+        Some(Commands::RunSaved { name, timeout }) => run_saved_search(
+            &settings,
+            &resolved,
+            &name,
+            Duration::from_secs(timeout),
+        ),
         Some(Commands::Chat {
             room,
             message,
@@ -128,22 +238,28 @@ fn main() -> Result<()> {
     }
 }
 
-fn init_logging(cli: &Cli) {
-    let log_level = match cli.verbose {
-        0 => "ERROR",
-        1 => "WARN",
-        2 => "INFO",
-        3 => "DEBUG",
-        _ => "TRACE",
+fn init_logging(resolved: &persist::config::Resolved) {
+    let log_level = match resolved.verbose {
+        0 => LogLevel::Error,
+        1 => LogLevel::Warn,
+        2 => LogLevel::Info,
+        3 => LogLevel::Debug,
+        _ => LogLevel::Trace,
     };
-    // SAFETY: Called before any threads are spawned
-    unsafe { env::set_var("LOG_LEVEL", log_level) };
-
-    if let Some(log_file) = &cli.log_file {
-        // SAFETY: Called before any threads are spawned
-        unsafe {
-            env::set_var("LOG_FILE", log_file.to_string_lossy().to_string());
-        };
+    let spec = resolved.log_filter.as_deref().unwrap_or_default();
+    let filter = format!("{log_level:?},{spec}").parse().unwrap_or_else(|e| {
+        eprintln!("Ignoring log filter '{spec}': {e}");
+        LogFilter::new(log_level)
+    });
+    logger::set_log_filter(filter);
+
+    if let Some(path) = &resolved.log_file {
+        let log_file = LogFile::new(path)
+            .with_max_bytes(resolved.log_max_bytes)
+            .with_max_files(resolved.log_max_files);
+        if let Err(e) = logger::set_log_file(Some(log_file)) {
+            eprintln!("Failed to open log file '{}': {e}", path.display());
+        }
     }
 }
 
@@ -158,7 +274,7 @@ fn run_default_tui(
     file_config: &persist::config::FileConfig,
 ) -> Result<()> {
     use ratatui::crossterm::{
-        event::EnableMouseCapture,
+        event::{EnableFocusChange, EnableMouseCapture},
         execute,
         terminal::{Clear, ClearType},
     };
@@ -196,6 +312,14 @@ fn run_default_tui(
 
     let enable_listen = !resolved.disable_listener;
     let listen_port = resolved.listener_port;
+    let upload_slots = resolved.upload_slots;
+    let max_upload_rate_kbps = resolved.max_upload_rate;
+    let fallback_charsets = resolved.fallback_charsets.clone();
+    let leech_filter = resolved.leech_filter.clone();
+    let ignored_users = resolved.ignored_users.clone();
+    let favorite_users = resolved.favorite_users.clone();
+    let on_download_complete = resolved.on_download_complete.clone();
+    let max_concurrent_downloads = resolved.max_concurrent_downloads;
     let make_settings =
         move |username: String, password: String| ClientSettings {
             username,
@@ -204,11 +328,27 @@ fn run_default_tui(
             enable_listen,
             listen_port,
             shared_directories: shared_directories.clone(),
+            upload_slots,
+            max_upload_rate_kbps,
+            fallback_charsets: fallback_charsets.clone(),
+            leech_filter: leech_filter.clone(),
+            ignored_users: ignored_users.clone(),
+            favorite_users: favorite_users.clone(),
+            on_download_complete: on_download_complete.clone(),
+            max_active_downloads: Some(max_concurrent_downloads),
+            history_file: persist::paths::download_history_file(),
+            share_index_file: persist::paths::share_index_file(),
+            ..ClientSettings::default()
         };
 
-    // Clear screen and enable mouse capture before initializing TUI
-    let _ =
-        execute!(std::io::stdout(), Clear(ClearType::All), EnableMouseCapture);
+    // Clear screen and enable mouse capture and focus reports (for
+    // notifications) before initializing TUI
+    let _ = execute!(
+        std::io::stdout(),
+        Clear(ClearType::All),
+        EnableMouseCapture,
+        EnableFocusChange
+    );
     let mut terminal = ratatui::init();
 
     let outcome = ui::login::run_login_flow(
@@ -245,6 +385,10 @@ fn run_default_tui(
         resolved.max_concurrent_downloads,
         Duration::from_secs(resolved.search_timeout),
         store,
+        resolved.saved_searches.clone(),
+        resolved.keymap.clone(),
+        resolved.auto_away,
+        resolved.notifications,
     )
 }
 
@@ -283,11 +427,11 @@ fn browse_user(settings: &ClientSettings, target: &str) -> Result<()> {
     client
         .connect()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to connect: {}", e))?;
-    if !client
+    if let LoginOutcome::Rejected(reason) = client
         .login()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to login: {}", e))?
     {
-        return Err(color_eyre::eyre::eyre!("Login rejected by server"));
+        return Err(color_eyre::eyre::eyre!("Login rejected: {}", reason));
     }
 
     client
@@ -316,17 +460,129 @@ fn browse_user(settings: &ClientSettings, target: &str) -> Result<()> {
     ))
 }
 
+/// Look `target` up via the server (status, stats, country) and the peer
+/// itself (queue, slots, description), then print what arrived in time.
+fn inspect_user(
+    settings: &ClientSettings,
+    target: &str,
+    json: bool,
+    timeout_secs: u64,
+) -> Result<()> {
+    use std::time::Instant;
+
+    let _port_mapper = settings
+        .enable_listen
+        .then(|| port_mapping::PortMapper::spawn(settings.listen_port));
+    let client = connect_and_login(settings)?;
+    client
+        .request_user_info(target)
+        .map_err(|e| color_eyre::eyre::eyre!("Failed to look up: {}", e))?;
+
+    if !json {
+        println!("🔎 Looking up {target}...");
+    }
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    let mut info = None;
+    while Instant::now() < deadline {
+        info = client.user_info(target);
+        if info.as_ref().is_some_and(user_lookup_complete) {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    let info = info.ok_or_else(|| {
+        color_eyre::eyre::eyre!("No reply about {target} from the server")
+    })?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&user_info_json(&info))?);
+    } else {
+        print_user_info(&info);
+    }
+    Ok(())
+}
+
+/// The server has answered, and either the peer has too or it can't (the
+/// account doesn't exist or is offline).
+fn user_lookup_complete(info: &soulseek_rs::types::UserInfo) -> bool {
+    use soulseek_rs::types::UserStatus;
+
+    if info.exists == Some(false) {
+        return true;
+    }
+    let server_done = info.status.is_some() && info.avg_speed.is_some();
+    let peer_done =
+        info.queue_length.is_some() || info.status == Some(UserStatus::Offline);
+    server_done && peer_done
+}
+
+fn user_info_json(info: &soulseek_rs::types::UserInfo) -> serde_json::Value {
+    serde_json::json!({
+        "username": info.username,
+        "exists": info.exists,
+        "status": info.status.map(|status| status.to_string()),
+        "privileged": info.privileged,
+        "country": info.country,
+        "avg_speed": info.avg_speed,
+        "upload_count": info.upload_count,
+        "shared_files": info.shared_files,
+        "shared_folders": info.shared_folders,
+        "queue_length": info.queue_length,
+        "free_slots": info.free_slots,
+        "total_uploads": info.total_uploads,
+        "description": info.description,
+    })
+}
+
+fn print_user_info(info: &soulseek_rs::types::UserInfo) {
+    fn show<T: std::fmt::Display>(value: Option<T>) -> String {
+        value.map_or_else(|| "unknown".to_string(), |v| v.to_string())
+    }
+
+    if info.exists == Some(false) {
+        println!("{} does not exist", info.username);
+        return;
+    }
+    println!("👤 {}", info.username);
+    println!("  Status:       {}", show(info.status));
+    println!(
+        "  Privileged:   {}",
+        show(info.privileged.map(|p| if p { "yes" } else { "no" }))
+    );
+    println!("  Country:      {}", show(info.country.as_deref()));
+    println!(
+        "  Avg speed:    {}",
+        show(info.avg_speed.map(|speed| format!("{speed} B/s")))
+    );
+    println!("  Uploads:      {}", show(info.upload_count));
+    println!(
+        "  Shares:       {} files in {} folders",
+        show(info.shared_files),
+        show(info.shared_folders)
+    );
+    println!("  Queue length: {}", show(info.queue_length));
+    println!(
+        "  Free slots:   {}",
+        show(info.free_slots.map(|f| if f { "yes" } else { "no" }))
+    );
+    if let Some(description) =
+        info.description.as_deref().filter(|d| !d.is_empty())
+    {
+        println!("  Description:  {description}");
+    }
+}
+
 /// Connect and log in, returning the ready client or a descriptive error.
 fn connect_and_login(settings: &ClientSettings) -> Result<Client> {
     let mut client = Client::with_settings(settings.clone());
     client
         .connect()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to connect: {}", e))?;
-    if !client
+    if let LoginOutcome::Rejected(reason) = client
         .login()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to login: {}", e))?
     {
-        return Err(color_eyre::eyre::eyre!("Login rejected by server"));
+        return Err(color_eyre::eyre::eyre!("Login rejected: {}", reason));
     }
     Ok(client)
 }
@@ -363,6 +619,591 @@ fn list_rooms(settings: &ClientSettings) -> Result<()> {
     Ok(())
 }
 
+/// Run the saved search `name`: collect filtered results for `timeout`,
+/// then either list them or download what its `auto_download` policy picks,
+/// failing over between sources.
+fn run_saved_search(
+    settings: &ClientSettings,
+    resolved: &persist::config::Resolved,
+    name: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let saved = resolved.saved_searches.get(name).ok_or_else(|| {
+        let known: Vec<&str> =
+            resolved.saved_searches.keys().map(String::as_str).collect();
+        color_eyre::eyre::eyre!(
+            "No saved search named '{name}' (configured: {})",
+            if known.is_empty() {
+                "none".to_string()
+            } else {
+                known.join(", ")
+            }
+        )
+    })?;
+
+    let client = connect_and_login(settings)?;
+    println!("🔍 {name}: searching for {}...", saved.query);
+    let filter = saved.filter();
+    let results: Vec<_> = client
+        .search_stream(&saved.query, timeout)
+        .map_err(|e| color_eyre::eyre::eyre!("Search failed: {}", e))?
+        .filter_map(|result| filter.apply(result))
+        .collect();
+    let file_count: usize = results.iter().map(|r| r.files.len()).sum();
+    println!("{file_count} matching files from {} peers", results.len());
+
+    let picks = saved.picks(&results);
+    if picks.is_empty() {
+        for result in &results {
+            for file in &result.files {
+                println!(
+                    "{:>10}  {:<20}  {}",
+                    ui::format_bytes(file.size),
+                    result.username,
+                    file.name
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let download_dir = resolved.download_dir.clone();
+    let started: Vec<_> = picks
+        .into_iter()
+        .filter_map(|sources| {
+            let label = saved_search::basename(&sources[0].name).to_string();
+            match client.download_any(sources, download_dir.clone()) {
+                Ok(statuses) => Some((label, statuses)),
+                Err(e) => {
+                    println!("✗ {label}: {e}");
+                    None
+                }
+            }
+        })
+        .collect();
+    println!("⬇️  Downloading {} files...", started.len());
+
+    for (label, statuses) in started {
+        report_download(&label, &statuses, None);
+    }
+    Ok(())
+}
+
+/// Wait for a download to finish (or `timeout` to pass) and print a line
+/// with its outcome. Returns whether it completed.
+fn report_download(
+    label: &str,
+    statuses: &std::sync::mpsc::Receiver<soulseek_rs::DownloadStatus>,
+    timeout: Option<Duration>,
+) -> bool {
+    use soulseek_rs::DownloadStatus;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    match wait_for_download(statuses, timeout) {
+        Ok(DownloadStatus::Completed(Some(summary))) => {
+            println!(
+                "✓ {} ({:.1}s, {})",
+                summary.path.display(),
+                summary.elapsed.as_secs_f64(),
+                ui::format_speed(summary.average_speed_bytes_per_sec)
+            );
+            true
+        }
+        Ok(DownloadStatus::Completed(None)) => {
+            println!("✓ {label}");
+            true
+        }
+        Ok(DownloadStatus::Failed(reason)) => {
+            let reason = reason.map_or_else(
+                || "failed".to_string(),
+                |reason| reason.to_string(),
+            );
+            println!("✗ {label}: {reason}");
+            false
+        }
+        Ok(_) | Err(RecvTimeoutError::Timeout) => {
+            println!("✗ {label}: timed out");
+            false
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            println!("✗ {label}: download stopped");
+            false
+        }
+    }
+}
+
+/// The status a download finished with, once it finishes or `timeout`
+/// passes.
+fn wait_for_download(
+    statuses: &std::sync::mpsc::Receiver<soulseek_rs::DownloadStatus>,
+    timeout: Option<Duration>,
+) -> Result<soulseek_rs::DownloadStatus, std::sync::mpsc::RecvTimeoutError> {
+    use soulseek_rs::DownloadStatus;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::time::Instant;
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        let status = match deadline {
+            Some(deadline) => statuses.recv_timeout(
+                deadline.saturating_duration_since(Instant::now()),
+            ),
+            None => statuses.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match status {
+            Ok(
+                status @ (DownloadStatus::Completed(_)
+                | DownloadStatus::Failed(_)
+                | DownloadStatus::TimedOut),
+            ) => return Ok(status),
+            Ok(_) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Search for `query` and print every result as a JSON download list.
+/// Progress goes to stderr so stdout stays valid JSON.
+fn search_json(
+    settings: &ClientSettings,
+    query: &str,
+    timeout: u64,
+) -> Result<()> {
+    let client = connect_and_login(settings)?;
+    eprintln!("🔍 Searching for {query} ({timeout}s)...");
+    let results: Vec<_> = client
+        .search_stream(query, Duration::from_secs(timeout))
+        .map_err(|e| color_eyre::eyre::eyre!("Search failed: {}", e))?
+        .collect();
+    let entries = download_list::from_results(&results);
+    eprintln!("{} files from {} peers", entries.len(), results.len());
+    println!("{}", serde_json::to_string_pretty(&entries)?);
+    Ok(())
+}
+
+/// Search for `query` and download the top-ranked file, without the picker.
+fn search_best(
+    settings: &ClientSettings,
+    query: &str,
+    timeout: u64,
+    download_dir: String,
+) -> Result<()> {
+    create_download_dir(&download_dir)?;
+    let client = connect_and_login(settings)?;
+    eprintln!("🔍 Searching for {query} ({timeout}s)...");
+    let results: Vec<_> = client
+        .search_stream(query, Duration::from_secs(timeout))
+        .map_err(|e| color_eyre::eyre::eyre!("Search failed: {}", e))?
+        .collect();
+    let Some((result, file)) = ranking::best(&results) else {
+        return Err(color_eyre::eyre::eyre!("No results for {query}"));
+    };
+    println!("🏆 {} from {}", file.name, result.username);
+    let entry = download_list::Entry::new(
+        result.username.clone(),
+        file.name.clone(),
+        file.size,
+    );
+    download_entries(&client, &[entry], &|_| download_dir.clone(), 1, None)
+}
+
+/// How long `album` waits for the uploader's share listing before settling
+/// for the files the search found.
+const ALBUM_LISTING_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Search for `query`, pick the best uploader folder and download all of it
+/// into a folder named after it in `download_dir`.
+fn download_album(
+    settings: &ClientSettings,
+    query: &str,
+    timeout: Duration,
+    download_dir: &str,
+    max_concurrent: usize,
+) -> Result<()> {
+    use std::time::Instant;
+
+    create_download_dir(download_dir)?;
+    // Browsing the uploader needs them to reach us if we can't reach them.
+    let _port_mapper = settings
+        .enable_listen
+        .then(|| port_mapping::PortMapper::spawn(settings.listen_port));
+    let client = connect_and_login(settings)?;
+    println!("🔍 Searching for {query} ({}s)...", timeout.as_secs());
+    let results: Vec<_> = client
+        .search_stream(query, timeout)
+        .map_err(|e| color_eyre::eyre::eyre!("Search failed: {}", e))?
+        .collect();
+    let Some(album) = album::best(&results) else {
+        return Err(color_eyre::eyre::eyre!("No albums found for {query}"));
+    };
+    let username = album.result.username.clone();
+    let folder = album.folder.to_string();
+    println!(
+        "💿 {folder} from {username}: {} tracks, {}",
+        album.tracks().count(),
+        album.quality().label()
+    );
+
+    // The search only returns the files that matched; the share listing
+    // has the rest of the folder, like cover art and other discs.
+    let mut files = Vec::new();
+    match client.browse_user(&username) {
+        Ok(()) => {
+            println!("📂 Listing the folder...");
+            let deadline = Instant::now() + ALBUM_LISTING_TIMEOUT;
+            while Instant::now() < deadline {
+                if let Some(directories) = client.take_browse_result(&username)
+                {
+                    files = album::listed_files(&directories, &folder);
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(200));
+            }
+        }
+        Err(e) => soulseek_rs::warn!("Could not browse {username}: {e}"),
+    }
+    if files.is_empty() {
+        println!("Using the {} files the search found", album.files.len());
+        files = album
+            .files
+            .iter()
+            .map(|file| (file.name.clone(), file.size))
+            .collect();
+    }
+
+    let entries: Vec<_> = files
+        .into_iter()
+        .map(|(filename, size)| {
+            download_list::Entry::new(username.clone(), filename, size)
+        })
+        .collect();
+    download_entries(
+        &client,
+        &entries,
+        &|entry| album::destination(download_dir, &folder, &entry.filename),
+        max_concurrent,
+        None,
+    )
+}
+
+/// Run every query in `input`, downloading each one's top-ranked file into
+/// `download_dir`, `max_concurrent` at a time while later queries are
+/// still searching. The JSON report goes to `report`, or stdout, and
+/// progress to stderr. Fails unless every query was downloaded.
+fn run_batch(
+    settings: &ClientSettings,
+    input: &std::path::Path,
+    search_timeout: Duration,
+    download_dir: String,
+    max_concurrent: usize,
+    download_timeout: Option<Duration>,
+    report: Option<&std::path::Path>,
+) -> Result<()> {
+    use batch::QueryReport;
+    use std::sync::{Mutex, mpsc};
+
+    let queries = batch::parse_queries(&read_input(input)?);
+    if queries.is_empty() {
+        eprintln!("Nothing to search for");
+        return Ok(());
+    }
+    create_download_dir(&download_dir)?;
+    let client = connect_and_login(settings)?;
+
+    let (picks, pending) = mpsc::channel::<(usize, download_list::Entry)>();
+    let pending = Mutex::new(pending);
+    let mut reports = Vec::new();
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..max_concurrent.max(1))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut reports = Vec::new();
+                    while let Some((index, file)) =
+                        pending.lock().ok().and_then(|picks| picks.recv().ok())
+                    {
+                        reports.push((
+                            index,
+                            batch_download(
+                                &client,
+                                &queries[index],
+                                file,
+                                &download_dir,
+                                download_timeout,
+                            ),
+                        ));
+                    }
+                    reports
+                })
+            })
+            .collect();
+
+        for (index, query) in queries.iter().enumerate() {
+            eprintln!("🔍 [{}/{}] {query}", index + 1, queries.len());
+            let results: Vec<_> = match client
+                .search_stream(query, search_timeout)
+            {
+                Ok(results) => results.collect(),
+                Err(e) => {
+                    let error = format!("search failed: {e}");
+                    eprintln!("✗ {query}: {error}");
+                    reports
+                        .push((index, QueryReport::failed(query, None, error)));
+                    continue;
+                }
+            };
+            let Some((result, file)) = ranking::best(&results) else {
+                eprintln!("✗ {query}: no results");
+                reports.push((index, QueryReport::no_results(query)));
+                continue;
+            };
+            eprintln!("🏆 {} from {}", file.name, result.username);
+            let _ = picks.send((
+                index,
+                download_list::Entry {
+                    bitrate: file.attributes.bitrate,
+                    free_slot: result.free_slot,
+                    speed: result.speed,
+                    queue_length: result.queue_length,
+                    ..download_list::Entry::new(
+                        result.username.clone(),
+                        file.name.clone(),
+                        file.size,
+                    )
+                },
+            ));
+        }
+        // Let the workers finish once the queue drains.
+        drop(picks);
+        for worker in workers {
+            reports.extend(worker.join().unwrap_or_default());
+        }
+    });
+
+    reports.sort_by_key(|(index, _)| *index);
+    let batch_report = batch::Report::new(
+        reports.into_iter().map(|(_, report)| report).collect(),
+    );
+    let json = serde_json::to_string_pretty(&batch_report)?;
+    match report {
+        Some(path) => std::fs::write(path, json + "\n").map_err(|e| {
+            color_eyre::eyre::eyre!("Cannot write {}: {e}", path.display())
+        })?,
+        None => println!("{json}"),
+    }
+    if batch_report.succeeded() {
+        Ok(())
+    } else {
+        Err(color_eyre::eyre::eyre!(
+            "{} of {} queries were not downloaded",
+            batch_report.queries.len() - batch_report.downloaded,
+            batch_report.queries.len()
+        ))
+    }
+}
+
+/// Download `file`, picked for `query`, and report how it ended.
+fn batch_download(
+    client: &Client,
+    query: &str,
+    file: download_list::Entry,
+    download_dir: &str,
+    timeout: Option<Duration>,
+) -> batch::QueryReport {
+    use batch::QueryReport;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let label = saved_search::basename(&file.filename).to_string();
+    let report = match client.download(
+        file.filename.clone(),
+        file.username.clone(),
+        file.size,
+        download_dir.to_string(),
+    ) {
+        Ok((_, statuses)) => match wait_for_download(&statuses, timeout) {
+            Ok(status) => QueryReport::finished(query, file, &status),
+            Err(RecvTimeoutError::Timeout) => {
+                QueryReport::failed(query, Some(file), "timed out")
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                QueryReport::failed(query, Some(file), "download stopped")
+            }
+        },
+        Err(e) => QueryReport::failed(query, Some(file), e.to_string()),
+    };
+    match &report.error {
+        None => eprintln!("✓ {label}"),
+        Some(error) => eprintln!("✗ {label}: {error}"),
+    }
+    report
+}
+
+/// Log in and serve the control socket on `control` until a `shutdown`
+/// request. The server actor reconnects by itself if the connection drops.
+fn run_daemon(
+    settings: &ClientSettings,
+    resolved: &persist::config::Resolved,
+    control: &str,
+    metrics: Option<&str>,
+) -> Result<()> {
+    let token = daemon::new_token().map_err(|e| {
+        color_eyre::eyre::eyre!("Cannot generate a control token: {e}")
+    })?;
+    let token_file = persist::paths::control_token_file().ok_or_else(|| {
+        color_eyre::eyre::eyre!("No state directory for the control token")
+    })?;
+    daemon::write_token(&token_file, &token).map_err(|e| {
+        color_eyre::eyre::eyre!(
+            "Cannot write the control token to {}: {e}",
+            token_file.display()
+        )
+    })?;
+    let listener = std::net::TcpListener::bind(control).map_err(|e| {
+        color_eyre::eyre::eyre!("Cannot listen on {control}: {e}")
+    })?;
+    let _port_mapper = (!resolved.disable_listener)
+        .then(|| port_mapping::PortMapper::spawn(resolved.listener_port));
+    let queue_file = persist::paths::download_queue_file();
+    let client = connect_and_login(&ClientSettings {
+        download_queue_file: queue_file.clone(),
+        ..settings.clone()
+    })?;
+    println!(
+        "🛰️  Logged in; control socket on {control}, token in {}",
+        token_file.display()
+    );
+    if let Some(file) = &queue_file {
+        match client.restore_downloads(file) {
+            Ok(restored) if !restored.is_empty() => {
+                println!(
+                    "♻️  Restored {} unfinished downloads",
+                    restored.len()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Cannot restore downloads: {e}"),
+        }
+    }
+    let exporter = metrics
+        .map(|address| {
+            client.serve_metrics(address).map_err(|e| {
+                color_eyre::eyre::eyre!(
+                    "Cannot serve metrics on {address}: {e}"
+                )
+            })
+        })
+        .transpose()?;
+    if let Some(exporter) = &exporter {
+        println!("📈 Metrics on http://{}/metrics", exporter.local_addr());
+    }
+
+    let backend = daemon::ClientBackend {
+        client: &client,
+        download_dir: resolved.download_dir.clone(),
+        search_timeout: Duration::from_secs(resolved.search_timeout),
+    };
+    daemon::serve(&backend, &listener, &AtomicBool::new(false), &token)?;
+    println!("👋 Shutting down");
+    Ok(())
+}
+
+/// The contents of `path`, or of stdin for `-`.
+fn read_input(path: &std::path::Path) -> Result<String> {
+    use std::io::Read;
+
+    if path.as_os_str() == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        return Ok(text);
+    }
+    std::fs::read_to_string(path).map_err(|e| {
+        color_eyre::eyre::eyre!("Cannot read {}: {e}", path.display())
+    })
+}
+
+/// Download `entries` into `download_dir`, `max_concurrent` at a time,
+/// printing one line per file. Fails if any download does.
+fn download_files(
+    settings: &ClientSettings,
+    entries: &[download_list::Entry],
+    download_dir: String,
+    max_concurrent: usize,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    if entries.is_empty() {
+        println!("Nothing to download");
+        return Ok(());
+    }
+    create_download_dir(&download_dir)?;
+    let client = connect_and_login(settings)?;
+    download_entries(
+        &client,
+        entries,
+        &|_| download_dir.clone(),
+        max_concurrent,
+        timeout,
+    )
+}
+
+fn create_download_dir(download_dir: &str) -> Result<()> {
+    std::fs::create_dir_all(soulseek_rs::utils::path::expand_tilde(
+        download_dir,
+    ))
+    .map_err(|e| color_eyre::eyre::eyre!("Cannot create {download_dir}: {e}"))
+}
+
+/// Download `entries` with up to `max_concurrent` at a time, each into the
+/// directory `destination` gives for it, failing if any of them does.
+fn download_entries(
+    client: &Client,
+    entries: &[download_list::Entry],
+    destination: &(dyn Fn(&download_list::Entry) -> String + Sync),
+    max_concurrent: usize,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    println!("⬇️  Downloading {} files...", entries.len());
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..max_concurrent.clamp(1, entries.len()) {
+            scope.spawn(|| {
+                while let Some(entry) =
+                    entries.get(next.fetch_add(1, Ordering::Relaxed))
+                {
+                    let label = saved_search::basename(&entry.filename);
+                    let completed = match client.download(
+                        entry.filename.clone(),
+                        entry.username.clone(),
+                        entry.size,
+                        destination(entry),
+                    ) {
+                        Ok((_, statuses)) => {
+                            report_download(label, &statuses, timeout)
+                        }
+                        Err(e) => {
+                            println!("✗ {label}: {e}");
+                            false
+                        }
+                    };
+                    if !completed {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+
+    match failed.into_inner() {
+        0 => Ok(()),
+        failed => Err(color_eyre::eyre::eyre!(
+            "{failed} of {} downloads failed",
+            entries.len()
+        )),
+    }
+}
+
 fn chat_room(
     settings: &ClientSettings,
     room: &str,
@@ -423,11 +1264,11 @@ fn send_private_message(
     client
         .connect()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to connect: {}", e))?;
-    if !client
+    if let LoginOutcome::Rejected(reason) = client
         .login()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to login: {}", e))?
     {
-        return Err(color_eyre::eyre::eyre!("Login rejected by server"));
+        return Err(color_eyre::eyre::eyre!("Login rejected: {}", reason));
     }
 
     client
@@ -462,6 +1303,7 @@ fn search_and_download(config: SearchConfig) -> Result<()> {
         enable_listen: config.enable_listener,
         listen_port: config.listener_port,
         shared_directories: config.shared_directories.clone(),
+        ..ClientSettings::default()
     };
 
     let _port_mapper = settings
@@ -472,9 +1314,12 @@ fn search_and_download(config: SearchConfig) -> Result<()> {
     client
         .connect()
         .map_err(|e| color_eyre::eyre::eyre!("Failed to connect: {}", e))?;
-    client
+    if let LoginOutcome::Rejected(reason) = client
         .login()
-        .map_err(|e| color_eyre::eyre::eyre!("Failed to login: {}", e))?;
+        .map_err(|e| color_eyre::eyre::eyre!("Failed to login: {}", e))?
+    {
+        return Err(color_eyre::eyre::eyre!("Login rejected: {}", reason));
+    }
 
     if config.verbose > 0 {
         println!("🔍 Searching for: {}", config.query);
@@ -505,7 +1350,8 @@ fn search_and_download(config: SearchConfig) -> Result<()> {
         config.query.clone(),
         Duration::from_secs(config.timeout),
         cancel_flag.clone(),
-    );
+    )
+    .with_keymap(config.keymap.clone());
     let (terminal, selected_indices) = file_selector.run(terminal)?;
 
     // Cancel search thread - no need to wait for it
@@ -548,7 +1394,7 @@ fn search_and_download(config: SearchConfig) -> Result<()> {
     }
 
     // Show multi-download progress view immediately (initializes downloads asynchronously)
-    show_multi_download_progress(
+    let downloads = show_multi_download_progress(
         terminal,
         client,
         selected_files,
@@ -557,6 +1403,7 @@ fn search_and_download(config: SearchConfig) -> Result<()> {
     )?;
 
     println!("\n✨ Download complete!");
+    println!("{}", download_report(&downloads));
 
     Ok(())
 }
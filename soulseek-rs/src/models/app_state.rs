@@ -1,4 +1,8 @@
-use crate::models::{BrowseTabs, FileDisplayData, RoomsState, SettingsState};
+use crate::models::{
+    BrowseTabs, FileDisplayData, QualityFilter, ResultGroup, ResultsSort,
+    RoomsState, SettingsState,
+};
+use crate::saved_search::{SavedSearch, basename};
 use ratatui::{layout::Rect, widgets::TableState};
 use soulseek_rs::{DownloadStatus, types::Download};
 use std::sync::atomic::AtomicBool;
@@ -18,11 +22,51 @@ pub struct SearchEntry {
     pub start_time: Instant,
     #[allow(dead_code)]
     pub cancel_flag: Arc<AtomicBool>,
+    /// Set when the search was run from a saved search, whose filter then
+    /// applies to the results and whose auto-download runs on completion.
+    pub saved: Option<SavedSearch>,
 }
 
+/// How many speed samples a download keeps for its sparkline.
+pub const SPEED_HISTORY_LEN: usize = 12;
+
 pub struct DownloadEntry {
     pub download: Download,
     pub receiver: Option<Receiver<DownloadStatus>>,
+    /// The most recent transfer speeds in bytes/s, oldest first.
+    pub speed_history: Vec<f64>,
+    /// When the uploader was last asked for our place in their queue.
+    pub queue_polled_at: Option<Instant>,
+}
+
+impl DownloadEntry {
+    #[must_use]
+    pub const fn new(
+        download: Download,
+        receiver: Option<Receiver<DownloadStatus>>,
+    ) -> Self {
+        Self {
+            download,
+            receiver,
+            speed_history: Vec::new(),
+            queue_polled_at: None,
+        }
+    }
+
+    /// Take a status update, sampling its speed while in progress.
+    pub fn apply_status(&mut self, status: DownloadStatus) {
+        if let DownloadStatus::InProgress {
+            speed_bytes_per_sec,
+            ..
+        } = status
+        {
+            if self.speed_history.len() == SPEED_HISTORY_LEN {
+                self.speed_history.remove(0);
+            }
+            self.speed_history.push(speed_bytes_per_sec);
+        }
+        self.download.status = status;
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -70,6 +114,12 @@ pub struct AppState {
     pub results_selected_indices: std::collections::HashSet<usize>,
     pub results_filter_query: String,
     pub results_is_filtering: bool,
+    pub results_quality_filter: QualityFilter,
+    pub results_sort: ResultsSort,
+    /// Show user and folder nodes instead of single files.
+    pub results_grouped: bool,
+    /// The grouped view's rows, built from the filtered view while grouped.
+    pub results_groups: Vec<ResultGroup>,
 
     // Downloads
     pub downloads: Vec<DownloadEntry>,
@@ -94,6 +144,10 @@ pub struct AppState {
     /// Incoming private messages received while the inbox was closed.
     pub unread_messages: usize,
 
+    /// Favorite users who came online this session and still are, in the
+    /// order they did.
+    pub favorites_online: Vec<String>,
+
     // Browse users' shared files (one tab per user)
     pub browse: BrowseTabs,
     pub show_browse: bool,
@@ -140,6 +194,10 @@ impl AppState {
             results_selected_indices: std::collections::HashSet::new(),
             results_filter_query: String::new(),
             results_is_filtering: false,
+            results_quality_filter: QualityFilter::All,
+            results_sort: ResultsSort::Arrival,
+            results_grouped: false,
+            results_groups: Vec::new(),
 
             downloads: Vec::new(),
             downloads_table_state,
@@ -158,6 +216,8 @@ impl AppState {
             show_messages: false,
             unread_messages: 0,
 
+            favorites_online: Vec::new(),
+
             browse: BrowseTabs::new(),
             show_browse: false,
             browse_table_state: TableState::default(),
@@ -176,6 +236,37 @@ impl AppState {
         }
     }
 
+    /// Whether the results pane shows a filtered or re-sorted view (text
+    /// query, quality filter or sort order) rather than the full result list
+    /// in arrival order.
+    #[must_use]
+    pub fn results_filter_active(&self) -> bool {
+        !self.results_filter_query.is_empty()
+            || self.results_quality_filter != QualityFilter::All
+            || self.results_sort != ResultsSort::Arrival
+    }
+
+    /// Another user's copy of `download`'s file among the search results:
+    /// the same name and size, unlocked, preferring a free slot and then
+    /// speed.
+    #[must_use]
+    pub fn alternate_source(
+        &self,
+        download: &Download,
+    ) -> Option<&FileDisplayData> {
+        let name = basename(&download.filename).to_lowercase();
+        self.searches
+            .iter()
+            .flat_map(|search| &search.results)
+            .filter(|file| {
+                file.username != download.username
+                    && file.size == download.size
+                    && !file.locked
+                    && basename(&file.filename).to_lowercase() == name
+            })
+            .max_by_key(|file| (file.free_slot, file.speed))
+    }
+
     #[allow(dead_code)]
     #[must_use]
     pub fn get_selected_search(&self) -> Option<&SearchEntry> {
@@ -195,3 +286,93 @@ impl Default for AppState {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soulseek_rs::types::DownloadMetadata;
+
+    #[test]
+    fn speed_history_keeps_the_latest_in_progress_samples() {
+        let mut entry = DownloadEntry::new(
+            Download {
+                username: "peer".into(),
+                filename: "a.flac".into(),
+                token: 1,
+                size: 100,
+                download_directory: "/music".into(),
+                status: DownloadStatus::Queued,
+                sender: std::sync::mpsc::channel().0,
+                queue_position: None,
+                metadata: DownloadMetadata::default(),
+            },
+            None,
+        );
+        for speed in 0..=SPEED_HISTORY_LEN {
+            entry.apply_status(DownloadStatus::InProgress {
+                bytes_downloaded: 0,
+                total_bytes: 100,
+                speed_bytes_per_sec: speed as f64,
+                average_speed_bytes_per_sec: speed as f64,
+                eta: None,
+            });
+        }
+        entry.apply_status(DownloadStatus::Failed(None));
+
+        assert_eq!(entry.speed_history.len(), SPEED_HISTORY_LEN);
+        assert_eq!(entry.speed_history.first(), Some(&1.0));
+        assert_eq!(
+            entry.speed_history.last(),
+            Some(&(SPEED_HISTORY_LEN as f64))
+        );
+        assert!(matches!(
+            entry.download.status,
+            DownloadStatus::Failed(None)
+        ));
+    }
+
+    #[test]
+    fn alternate_sources_have_the_same_file_from_someone_else() {
+        let file =
+            |username: &str, filename: &str, size, speed| FileDisplayData {
+                filename: filename.into(),
+                username: username.into(),
+                size,
+                speed,
+                ..FileDisplayData::default()
+            };
+        let mut state = AppState::new();
+        state.searches.push(SearchEntry {
+            query: "intro".into(),
+            status: SearchStatus::Completed,
+            results: vec![
+                file("peer", "Music\\01 Intro.flac", 100, 900),
+                file("amy", "other\\01 intro.flac", 100, 10),
+                file("bob", "x\\01 Intro.flac", 100, 50),
+                file("cat", "x\\01 Intro.flac", 99, 500),
+                file("dan", "x\\02 Outro.flac", 100, 500),
+            ],
+            start_time: Instant::now(),
+            cancel_flag: Arc::new(AtomicBool::new(false)),
+            saved: None,
+        });
+        let download = Download {
+            username: "peer".into(),
+            filename: "Music\\01 Intro.flac".into(),
+            token: 1,
+            size: 100,
+            download_directory: "/music".into(),
+            status: DownloadStatus::Failed(None),
+            sender: std::sync::mpsc::channel().0,
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        };
+        let source = state.alternate_source(&download).unwrap();
+        assert_eq!(source.username, "bob");
+
+        state.searches[0]
+            .results
+            .retain(|file| file.username == "peer");
+        assert!(state.alternate_source(&download).is_none());
+    }
+}
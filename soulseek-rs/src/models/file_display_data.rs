@@ -1,10 +1,344 @@
+use soulseek_rs::{Charset, File, SearchResult};
+use std::cmp::Ordering;
+
 #[derive(Clone, Default)]
 pub struct FileDisplayData {
     pub filename: String,
     pub size: u64,
     pub username: String,
     pub speed: u32,
-    pub slots: u8,
+    pub free_slot: bool,
+    /// Uploads the peer has queued ahead of us.
+    pub queue_length: u32,
     pub bitrate: Option<u32>,
     pub length_seconds: Option<u32>,
+    pub vbr: Option<bool>,
+    pub sample_rate: Option<u32>,
+    pub bit_depth: Option<u32>,
+    /// The legacy charset the file name was repaired from, if it wasn't
+    /// UTF-8.
+    pub name_charset: Option<Charset>,
+    /// From the result's locked section; the peer will likely deny it.
+    pub locked: bool,
+}
+
+/// File extensions treated as lossless audio.
+const LOSSLESS_EXTENSIONS: [&str; 6] =
+    ["flac", "wav", "aiff", "aif", "ape", "alac"];
+
+/// Lowest average bitrate still counted as LAME V0 (nominally ~245 kbps).
+const V0_MIN_KBPS: u32 = 220;
+
+/// Audio quality bucket of a search result, best first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QualityClass {
+    Lossless,
+    Kbps320,
+    V0,
+    Other,
+}
+
+impl QualityClass {
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Lossless => "lossless",
+            Self::Kbps320 => "320 kbps",
+            Self::V0 => "V0",
+            Self::Other => "lossy",
+        }
+    }
+}
+
+impl FileDisplayData {
+    /// One row per file of a search result.
+    #[must_use]
+    pub fn new(result: &SearchResult, file: &File) -> Self {
+        let attributes = file.attributes;
+        Self {
+            filename: file.name.clone(),
+            size: file.size,
+            username: result.username.clone(),
+            speed: result.speed,
+            free_slot: result.free_slot,
+            queue_length: result.queue_length,
+            bitrate: attributes.bitrate,
+            length_seconds: attributes.duration_seconds,
+            vbr: attributes.vbr,
+            sample_rate: attributes.sample_rate,
+            bit_depth: attributes.bit_depth,
+            name_charset: file.name_charset,
+            locked: file.locked,
+        }
+    }
+
+    /// The bitrate like `320 kbps`, with `~` marking VBR; `-` if unknown.
+    #[must_use]
+    pub fn bitrate_label(&self) -> String {
+        self.bitrate.map_or_else(
+            || "-".to_string(),
+            |kbps| {
+                let vbr = if self.vbr == Some(true) { "~" } else { "" };
+                format!("{vbr}{kbps} kbps")
+            },
+        )
+    }
+
+    /// The track length like `3:07`; `-` if unknown.
+    #[must_use]
+    pub fn length_label(&self) -> String {
+        self.length_seconds.map_or_else(
+            || "-".to_string(),
+            |secs| format!("{}:{:02}", secs / 60, secs % 60),
+        )
+    }
+
+    /// Sample rate and bit depth like `44.1k/16`; `-` if neither is known.
+    #[must_use]
+    pub fn format_label(&self) -> String {
+        let rate = self.sample_rate.map(|hz| {
+            let khz = format!("{:.1}", f64::from(hz) / 1000.0);
+            format!("{}k", khz.trim_end_matches(".0"))
+        });
+        match (rate, self.bit_depth) {
+            (Some(rate), Some(bits)) => format!("{rate}/{bits}"),
+            (Some(rate), None) => rate,
+            (None, Some(bits)) => format!("{bits}-bit"),
+            (None, None) => "-".to_string(),
+        }
+    }
+
+    /// Classify by extension first (lossless files often carry no bitrate
+    /// attribute), then by the advertised bitrate.
+    #[must_use]
+    pub fn quality_class(&self) -> QualityClass {
+        let extension = self
+            .filename
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        if extension
+            .as_deref()
+            .is_some_and(|ext| LOSSLESS_EXTENSIONS.contains(&ext))
+        {
+            return QualityClass::Lossless;
+        }
+        match self.bitrate {
+            Some(kbps) if kbps >= 320 => QualityClass::Kbps320,
+            Some(kbps) if kbps >= V0_MIN_KBPS => QualityClass::V0,
+            _ => QualityClass::Other,
+        }
+    }
+}
+
+/// The results pane's quick quality filter, cycled with `F`. Each step keeps
+/// its own class and everything better.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QualityFilter {
+    #[default]
+    All,
+    Lossless,
+    Kbps320,
+    V0,
+}
+
+impl QualityFilter {
+    pub const ALL: [Self; 4] =
+        [Self::All, Self::Lossless, Self::Kbps320, Self::V0];
+
+    #[must_use]
+    pub const fn next(self) -> Self {
+        match self {
+            Self::All => Self::Lossless,
+            Self::Lossless => Self::Kbps320,
+            Self::Kbps320 => Self::V0,
+            Self::V0 => Self::All,
+        }
+    }
+
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Lossless => "lossless",
+            Self::Kbps320 => "320+",
+            Self::V0 => "V0+",
+        }
+    }
+
+    #[must_use]
+    pub fn admits(self, file: &FileDisplayData) -> bool {
+        let worst_allowed = match self {
+            Self::All => return true,
+            Self::Lossless => QualityClass::Lossless,
+            Self::Kbps320 => QualityClass::Kbps320,
+            Self::V0 => QualityClass::V0,
+        };
+        file.quality_class() <= worst_allowed
+    }
+
+    /// How many of `items` each filter would show, in [`Self::ALL`] order.
+    #[must_use]
+    pub fn counts(items: &[FileDisplayData]) -> [usize; 4] {
+        Self::ALL
+            .map(|filter| items.iter().filter(|f| filter.admits(f)).count())
+    }
+}
+
+/// The results pane's sort order, cycled with `o`/`O`. Apart from the
+/// filename, each column sorts best first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResultsSort {
+    /// The order results arrived from the network.
+    #[default]
+    Arrival,
+    Filename,
+    Size,
+    Bitrate,
+    Speed,
+    Slots,
+    Queue,
+}
+
+impl ResultsSort {
+    const ALL: [Self; 7] = [
+        Self::Arrival,
+        Self::Filename,
+        Self::Size,
+        Self::Bitrate,
+        Self::Speed,
+        Self::Slots,
+        Self::Queue,
+    ];
+
+    #[must_use]
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    #[must_use]
+    pub fn prev(self) -> Self {
+        let idx = Self::ALL.iter().position(|&s| s == self).unwrap_or(0);
+        Self::ALL[(idx + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Arrival => "arrival",
+            Self::Filename => "filename",
+            Self::Size => "size",
+            Self::Bitrate => "bitrate",
+            Self::Speed => "speed",
+            Self::Slots => "slots",
+            Self::Queue => "queue",
+        }
+    }
+
+    /// Order `a` against `b`. Ties (and [`Self::Arrival`]) compare equal,
+    /// so a stable sort keeps them in arrival order.
+    #[must_use]
+    pub fn compare(self, a: &FileDisplayData, b: &FileDisplayData) -> Ordering {
+        match self {
+            Self::Arrival => Ordering::Equal,
+            Self::Filename => {
+                a.filename.to_lowercase().cmp(&b.filename.to_lowercase())
+            }
+            Self::Size => b.size.cmp(&a.size),
+            Self::Bitrate => b.bitrate.cmp(&a.bitrate),
+            Self::Speed => b.speed.cmp(&a.speed),
+            Self::Slots => b.free_slot.cmp(&a.free_slot),
+            Self::Queue => a.queue_length.cmp(&b.queue_length),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileDisplayData, QualityClass, QualityFilter, ResultsSort};
+
+    fn file(filename: &str, bitrate: Option<u32>) -> FileDisplayData {
+        FileDisplayData {
+            filename: filename.to_string(),
+            bitrate,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn classifies_by_extension_then_bitrate() {
+        assert_eq!(
+            file("a.FLAC", None).quality_class(),
+            QualityClass::Lossless
+        );
+        assert_eq!(
+            file("a.mp3", Some(320)).quality_class(),
+            QualityClass::Kbps320
+        );
+        assert_eq!(file("a.mp3", Some(245)).quality_class(), QualityClass::V0);
+        assert_eq!(
+            file("a.mp3", Some(192)).quality_class(),
+            QualityClass::Other
+        );
+        assert_eq!(file("a.mp3", None).quality_class(), QualityClass::Other);
+    }
+
+    #[test]
+    fn filters_cycle_and_keep_better_classes() {
+        assert_eq!(QualityFilter::V0.next(), QualityFilter::All);
+        let items = vec![
+            file("a.flac", None),
+            file("b.mp3", Some(320)),
+            file("c.mp3", Some(256)),
+            file("d.mp3", Some(128)),
+        ];
+        assert_eq!(QualityFilter::counts(&items), [4, 1, 2, 3]);
+    }
+
+    #[test]
+    fn sorts_best_first_and_cycles_both_ways() {
+        let mut low = file("b.mp3", Some(128));
+        low.speed = 900;
+        let mut high = file("A.mp3", Some(320));
+        high.queue_length = 40;
+        let unknown = file("c.flac", None);
+        let items = [low, high, unknown];
+        let order = |sort: ResultsSort| {
+            let mut idx: Vec<usize> = (0..items.len()).collect();
+            idx.sort_by(|&a, &b| sort.compare(&items[a], &items[b]));
+            idx
+        };
+        assert_eq!(order(ResultsSort::Arrival), [0, 1, 2]);
+        assert_eq!(order(ResultsSort::Filename), [1, 0, 2]);
+        assert_eq!(order(ResultsSort::Bitrate), [1, 0, 2]);
+        assert_eq!(order(ResultsSort::Speed), [0, 1, 2]);
+        assert_eq!(order(ResultsSort::Queue), [0, 2, 1]);
+
+        assert_eq!(ResultsSort::Arrival.prev(), ResultsSort::Queue);
+        assert_eq!(ResultsSort::Queue.next(), ResultsSort::Arrival);
+        assert_eq!(ResultsSort::Size.next().prev(), ResultsSort::Size);
+    }
+
+    #[test]
+    fn labels_the_audio_attributes() {
+        let file = FileDisplayData {
+            bitrate: Some(245),
+            vbr: Some(true),
+            length_seconds: Some(187),
+            sample_rate: Some(44100),
+            bit_depth: Some(24),
+            ..Default::default()
+        };
+        assert_eq!(file.bitrate_label(), "~245 kbps");
+        assert_eq!(file.length_label(), "3:07");
+        assert_eq!(file.format_label(), "44.1k/24");
+
+        let bare = FileDisplayData {
+            sample_rate: Some(48000),
+            ..Default::default()
+        };
+        assert_eq!(bare.bitrate_label(), "-");
+        assert_eq!(bare.length_label(), "-");
+        assert_eq!(bare.format_label(), "48k");
+    }
 }
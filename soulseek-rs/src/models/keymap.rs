@@ -0,0 +1,374 @@
+//! Rebindable keys for the TUI's commands, set from the `[keys]` table of
+//! `config.toml`:
+//!
+//! ```toml
+//! [keys]
+//! quit = "Ctrl-q"
+//! up = ["Up", "k", "Ctrl-p"]
+//! ```
+//!
+//! A listed action's keys replace its defaults (the vim-style bindings);
+//! unlisted actions keep theirs. Text entry and the structural keys (Enter,
+//! Esc, Tab, Backspace) are not rebindable.
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+macro_rules! actions {
+    ($($(#[$doc:meta])* $variant:ident = $name:literal => [$($key:literal),*],)*) => {
+        /// A command that can be bound to keys.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum Action {
+            $($(#[$doc])* $variant,)*
+        }
+
+        impl Action {
+            pub const ALL: &[Self] = &[$(Self::$variant,)*];
+
+            /// The action's name in the `[keys]` table.
+            #[must_use]
+            pub const fn name(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)*
+                }
+            }
+
+            const fn default_keys(self) -> &'static [&'static str] {
+                match self {
+                    $(Self::$variant => &[$($key),*],)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    /// Quit, or close the open popup.
+    Quit = "quit" => ["q"],
+    FocusSearches = "focus_searches" => ["1"],
+    FocusResults = "focus_results" => ["2"],
+    FocusDownloads = "focus_downloads" => ["3"],
+    /// Open the chat pane. In the Downloads pane `clear_finished` wins.
+    Chat = "chat" => ["c", "4"],
+    Search = "search" => ["s"],
+    Message = "message" => ["m"],
+    Inbox = "inbox" => ["i"],
+    Settings = "settings" => ["o"],
+    Browse = "browse" => ["b"],
+    Up = "up" => ["Up", "k"],
+    Down = "down" => ["Down", "j"],
+    /// Collapse a folder in the browse popup.
+    Left = "left" => ["Left", "h"],
+    /// Expand a folder in the browse popup.
+    Right = "right" => ["Right", "l"],
+    First = "first" => ["Home", "g"],
+    Last = "last" => ["End", "G"],
+    Filter = "filter" => ["/"],
+    RemoveSearch = "remove_search" => ["d"],
+    ClearSearches = "clear_searches" => ["C"],
+    ToggleSelect = "toggle_select" => ["Space"],
+    SelectAll = "select_all" => ["a"],
+    SelectNone = "select_none" => ["A"],
+    QualityFilter = "quality_filter" => ["F"],
+    /// Sort results by the next column. In the Results pane this wins over
+    /// `settings`.
+    SortNext = "sort_next" => ["o"],
+    SortPrev = "sort_prev" => ["O"],
+    /// Toggle grouping results by user and folder.
+    GroupView = "group_view" => ["v"],
+    /// Ignore the highlighted result's user, saved to `config.toml`.
+    IgnoreUser = "ignore_user" => ["I"],
+    /// Add the highlighted result's user to the favorites, or remove them,
+    /// saved to `config.toml`.
+    Favorite = "favorite" => ["f"],
+    Pause = "pause" => ["p"],
+    RemoveDownload = "remove_download" => ["d"],
+    /// Move the highlighted download up the list, so it is asked for
+    /// sooner.
+    MoveDownloadUp = "move_download_up" => ["K"],
+    MoveDownloadDown = "move_download_down" => ["J"],
+    Retry = "retry" => ["r"],
+    /// Retry a download from another user in the search results who has
+    /// the same file.
+    RetryElsewhere = "retry_elsewhere" => ["R"],
+    ClearFinished = "clear_finished" => ["c"],
+    CancelDownload = "cancel_download" => ["x"],
+    CancelUpload = "cancel_upload" => ["x"],
+    /// Download the highlighted folder in the browse popup.
+    DownloadFolder = "download_folder" => ["d"],
+    CloseTab = "close_tab" => ["w"],
+    LeaveRoom = "leave_room" => ["x"],
+    RoomList = "room_list" => ["l"],
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+            .ok_or_else(|| format!("unknown action `{name}`"))
+    }
+}
+
+/// One key with its modifiers, written like `q`, `G`, `Ctrl-a`, `Space` or
+/// `PageDown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Whether `key` is this binding. Shift is implied by the character (or
+    /// by `BackTab`), so it is ignored.
+    #[must_use]
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        key.code == self.code
+            && key.modifiers.difference(KeyModifiers::SHIFT) == self.modifiers
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // A lone "-" is the minus key, not a separator.
+        while let Some((modifier, key)) =
+            rest.split_once('-').filter(|(_, key)| !key.is_empty())
+        {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                _ => return Err(format!("unknown modifier in `{text}`")),
+            };
+            rest = key;
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_ascii_lowercase().as_str() {
+                "space" => KeyCode::Char(' '),
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "insert" => KeyCode::Insert,
+                "delete" => KeyCode::Delete,
+                name => name
+                    .strip_prefix('f')
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| (1..=12).contains(n))
+                    .map(KeyCode::F)
+                    .ok_or_else(|| format!("unknown key `{text}`"))?,
+            },
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("Ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("Alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::Up => f.write_str("↑"),
+            KeyCode::Down => f.write_str("↓"),
+            KeyCode::Left => f.write_str("←"),
+            KeyCode::Right => f.write_str("→"),
+            KeyCode::PageUp => f.write_str("PgUp"),
+            KeyCode::PageDown => f.write_str("PgDn"),
+            KeyCode::Delete => f.write_str("Del"),
+            KeyCode::Insert => f.write_str("Ins"),
+            code => write!(f, "{code}"),
+        }
+    }
+}
+
+/// The `[keys]` table: each action's key, or list of keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeySpec {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeySpec {
+    fn keys(&self) -> &[String] {
+        match self {
+            Self::One(key) => std::slice::from_ref(key),
+            Self::Many(keys) => keys,
+        }
+    }
+}
+
+/// The keys bound to each action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: BTreeMap<Action, Vec<KeyBinding>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys = action
+                    .default_keys()
+                    .iter()
+                    .filter_map(|key| key.parse().ok())
+                    .collect();
+                (action, keys)
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl Keymap {
+    /// The defaults with `overrides` applied. Unknown action names are
+    /// skipped, so a config written for a newer build still loads.
+    pub fn with_overrides(
+        overrides: &BTreeMap<String, KeySpec>,
+    ) -> Result<Self, String> {
+        let mut keymap = Self::default();
+        for (name, spec) in overrides {
+            let Ok(action) = name.parse::<Action>() else {
+                continue;
+            };
+            let keys = spec
+                .keys()
+                .iter()
+                .map(|key| {
+                    key.parse().map_err(|e| format!("[keys] {name}: {e}"))
+                })
+                .collect::<Result<_, _>>()?;
+            keymap.bindings.insert(action, keys);
+        }
+        Ok(keymap)
+    }
+
+    /// Whether `key` is bound to `action`.
+    #[must_use]
+    pub fn pressed(&self, action: Action, key: &KeyEvent) -> bool {
+        self.bindings
+            .get(&action)
+            .is_some_and(|keys| keys.iter().any(|binding| binding.matches(key)))
+    }
+
+    /// The first key bound to `action`, for the shortcuts bar; empty if
+    /// it is unbound.
+    #[must_use]
+    pub fn label(&self, action: Action) -> String {
+        self.bindings
+            .get(&action)
+            .and_then(|keys| keys.first())
+            .map(ToString::to_string)
+            .unwrap_or_default()
+    }
+
+    /// Every key bound to `action` except those `shadowed_by` takes, joined
+    /// like `c/4`.
+    #[must_use]
+    pub fn labels(
+        &self,
+        action: Action,
+        shadowed_by: Option<Action>,
+    ) -> String {
+        let shadowing = shadowed_by.and_then(|other| self.bindings.get(&other));
+        self.bindings
+            .get(&action)
+            .into_iter()
+            .flatten()
+            .filter(|key| shadowing.is_none_or(|keys| !keys.contains(key)))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn every_default_key_parses() {
+        for action in Action::ALL {
+            for name in action.default_keys() {
+                assert!(
+                    name.parse::<KeyBinding>().is_ok(),
+                    "{}: {name}",
+                    action.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn defaults_are_the_vim_style_bindings() {
+        let keymap = Keymap::default();
+        let none = KeyModifiers::NONE;
+        assert!(keymap.pressed(Action::Down, &key(KeyCode::Char('j'), none)));
+        assert!(keymap.pressed(Action::Down, &key(KeyCode::Down, none)));
+        // Capitals arrive with Shift held.
+        assert!(keymap.pressed(
+            Action::Last,
+            &key(KeyCode::Char('G'), KeyModifiers::SHIFT)
+        ));
+        assert!(!keymap.pressed(
+            Action::Quit,
+            &key(KeyCode::Char('q'), KeyModifiers::CONTROL)
+        ));
+        assert_eq!(keymap.label(Action::Up), "↑");
+        assert_eq!(keymap.label(Action::ToggleSelect), "Space");
+    }
+
+    #[test]
+    fn overrides_replace_an_actions_keys() {
+        let overrides = BTreeMap::from([
+            ("quit".to_string(), KeySpec::One("Ctrl-x".into())),
+            (
+                "down".to_string(),
+                KeySpec::Many(vec!["n".into(), "PageDown".into()]),
+            ),
+            ("from_a_newer_build".to_string(), KeySpec::One("z".into())),
+        ]);
+        let keymap = Keymap::with_overrides(&overrides).unwrap();
+        let none = KeyModifiers::NONE;
+        assert!(keymap.pressed(
+            Action::Quit,
+            &key(KeyCode::Char('x'), KeyModifiers::CONTROL)
+        ));
+        assert!(!keymap.pressed(Action::Quit, &key(KeyCode::Char('q'), none)));
+        assert!(keymap.pressed(Action::Down, &key(KeyCode::PageDown, none)));
+        assert!(!keymap.pressed(Action::Down, &key(KeyCode::Char('j'), none)));
+        assert_eq!(keymap.label(Action::Quit), "Ctrl-x");
+
+        let bad = BTreeMap::from([(
+            "quit".to_string(),
+            KeySpec::One("Hyper-q".into()),
+        )]);
+        assert!(Keymap::with_overrides(&bad).is_err());
+    }
+}
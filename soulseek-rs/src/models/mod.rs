@@ -1,16 +1,22 @@
 mod app_state;
 mod browse;
 mod file_display_data;
+mod keymap;
+mod result_groups;
 mod rooms;
 mod settings;
 
 pub use app_state::{
     AppState, ChatMessage, CommandBarMode, DownloadEntry, FocusedPane,
-    MessageDirection, SearchEntry, SearchStatus,
+    MessageDirection, SPEED_HISTORY_LEN, SearchEntry, SearchStatus,
 };
 pub use browse::{
     BrowseState, BrowseStatus, BrowseTabs, files_under, find_node,
 };
-pub use file_display_data::FileDisplayData;
+pub use file_display_data::{
+    FileDisplayData, QualityClass, QualityFilter, ResultsSort,
+};
+pub use keymap::{Action, KeySpec, Keymap};
+pub use result_groups::{ResultGroup, folder_of, group_results};
 pub use rooms::{RoomLine, RoomsState, RoomsView};
 pub use settings::{SettingsAction, SettingsMode, SettingsState};
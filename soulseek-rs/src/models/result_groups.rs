@@ -0,0 +1,133 @@
+use super::FileDisplayData;
+
+/// A node of the results pane's grouped view: every file one user shares,
+/// or every file in one of their folders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultGroup {
+    pub username: String,
+    /// The folder's remote path, or `None` for the user's node.
+    pub folder: Option<String>,
+    /// Indices of the member files in the unfiltered results.
+    pub files: Vec<usize>,
+    pub size: u64,
+}
+
+impl ResultGroup {
+    /// Whether `self` is the same node as `other`, for keeping the highlight
+    /// across regroupings.
+    #[must_use]
+    pub fn same_node(&self, other: &Self) -> bool {
+        self.username == other.username && self.folder == other.folder
+    }
+}
+
+/// The folder part of a remote path (peers use `\` as the separator).
+#[must_use]
+pub fn folder_of(filename: &str) -> &str {
+    filename.rsplit_once('\\').map_or("", |(folder, _)| folder)
+}
+
+/// Group the results listed in `order` (indices into `items`) into a user
+/// node followed by one node per folder.
+///
+/// Users and folders keep the order their first file has in `order`, so a
+/// sorted view puts the group holding the best file first.
+#[must_use]
+pub fn group_results(
+    items: &[FileDisplayData],
+    order: &[usize],
+) -> Vec<ResultGroup> {
+    let mut users: Vec<(ResultGroup, Vec<ResultGroup>)> = Vec::new();
+    for &idx in order {
+        let Some(file) = items.get(idx) else {
+            continue;
+        };
+        let user_pos = users
+            .iter()
+            .position(|(user, _)| user.username == file.username)
+            .unwrap_or_else(|| {
+                users.push((
+                    ResultGroup {
+                        username: file.username.clone(),
+                        folder: None,
+                        files: Vec::new(),
+                        size: 0,
+                    },
+                    Vec::new(),
+                ));
+                users.len() - 1
+            });
+        let (user, folders) = &mut users[user_pos];
+        user.files.push(idx);
+        user.size += file.size;
+
+        let folder = folder_of(&file.filename);
+        let folder_pos = folders
+            .iter()
+            .position(|group| group.folder.as_deref() == Some(folder))
+            .unwrap_or_else(|| {
+                folders.push(ResultGroup {
+                    username: file.username.clone(),
+                    folder: Some(folder.to_string()),
+                    files: Vec::new(),
+                    size: 0,
+                });
+                folders.len() - 1
+            });
+        folders[folder_pos].files.push(idx);
+        folders[folder_pos].size += file.size;
+    }
+    users
+        .into_iter()
+        .flat_map(|(user, folders)| std::iter::once(user).chain(folders))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_results;
+    use crate::models::FileDisplayData;
+
+    fn file(username: &str, filename: &str, size: u64) -> FileDisplayData {
+        FileDisplayData {
+            filename: filename.to_string(),
+            username: username.to_string(),
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn groups_by_user_then_folder_in_view_order() {
+        let items = [
+            file("bob", "Music\\Album\\01.flac", 10),
+            file("amy", "Music\\Live\\01.mp3", 5),
+            file("bob", "Music\\Album\\02.flac", 20),
+            file("bob", "Music\\Single.flac", 7),
+        ];
+        // A view sorted so amy's file comes first.
+        let groups = group_results(&items, &[1, 0, 2, 3]);
+        let nodes: Vec<_> = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.username.as_str(),
+                    g.folder.as_deref(),
+                    g.files.len(),
+                    g.size,
+                )
+            })
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                ("amy", None, 1, 5),
+                ("amy", Some("Music\\Live"), 1, 5),
+                ("bob", None, 3, 37),
+                ("bob", Some("Music\\Album"), 2, 30),
+                ("bob", Some("Music"), 1, 7),
+            ]
+        );
+        assert_eq!(groups[3].files, [0, 2]);
+    }
+}
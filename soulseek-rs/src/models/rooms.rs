@@ -1,4 +1,5 @@
 use soulseek_rs::types::{RoomEvent, RoomInfo};
+use std::collections::HashMap;
 
 /// One line in a room's chat log. A `None` username marks a system line
 /// (joins/leaves), rendered dimmed.
@@ -23,12 +24,17 @@ impl RoomLine {
     }
 }
 
-/// A chat room the user has open (a tab).
+/// A chat room the user has open (a tab), or a private conversation.
 #[derive(Debug, Clone, Default)]
 pub struct OpenRoom {
     pub name: String,
+    /// A private conversation with the user `name` rather than a room: it
+    /// has no member list and isn't joined on the server.
+    pub private: bool,
     pub users: Vec<String>,
     pub lines: Vec<RoomLine>,
+    /// Members' tickers (short status lines), by username.
+    pub tickers: HashMap<String, String>,
     /// Unread messages received while this room was not being viewed.
     pub unread: usize,
     /// Per-room compose buffer.
@@ -42,6 +48,14 @@ impl OpenRoom {
             ..Default::default()
         }
     }
+
+    fn conversation(user: String) -> Self {
+        Self {
+            name: user,
+            private: true,
+            ..Default::default()
+        }
+    }
 }
 
 /// Which sub-view of the rooms popup is showing.
@@ -124,7 +138,13 @@ impl RoomsState {
 
     #[must_use]
     pub fn open_index(&self, name: &str) -> Option<usize> {
-        self.open.iter().position(|r| r.name == name)
+        self.open.iter().position(|r| !r.private && r.name == name)
+    }
+
+    /// Index of the private conversation with `user`, if open.
+    #[must_use]
+    pub fn conversation_index(&self, user: &str) -> Option<usize> {
+        self.open.iter().position(|r| r.private && r.name == user)
     }
 
     #[must_use]
@@ -148,6 +168,46 @@ impl RoomsState {
         }
     }
 
+    /// Open (or focus) the private conversation with `user`, switching to
+    /// the Chat view.
+    pub fn focus_or_open_conversation(&mut self, user: &str) {
+        self.view = RoomsView::Chat;
+        self.user_selected = 0;
+        self.active = self.conversation_index(user).unwrap_or_else(|| {
+            self.open.push(OpenRoom::conversation(user.to_string()));
+            self.open.len() - 1
+        });
+        self.mark_active_read();
+    }
+
+    /// Add a line written by `from` to the conversation with `peer`, opening
+    /// its tab (without focusing it) if needed.
+    pub fn push_private_message(
+        &mut self,
+        peer: &str,
+        from: String,
+        text: String,
+        unread: bool,
+    ) {
+        let idx = self.conversation_index(peer).unwrap_or_else(|| {
+            self.open.push(OpenRoom::conversation(peer.to_string()));
+            self.open.len() - 1
+        });
+        self.open[idx].lines.push(RoomLine::chat(from, text));
+        if unread {
+            self.open[idx].unread += 1;
+        }
+    }
+
+    /// Whether the Chat view, if showing, is on the conversation with `user`.
+    #[must_use]
+    pub fn is_active_conversation(&self, user: &str) -> bool {
+        self.view == RoomsView::Chat
+            && self
+                .active_room()
+                .is_some_and(|r| r.private && r.name == user)
+    }
+
     /// The username highlighted in the active room's member list, if any.
     #[must_use]
     pub fn selected_user(&self) -> Option<String> {
@@ -283,6 +343,25 @@ impl RoomsState {
                         .push(RoomLine::system(format!("← {username}")));
                 }
             }
+            RoomEvent::Tickers { room, tickers } => {
+                if let Some(idx) = self.open_index(&room) {
+                    self.open[idx].tickers = tickers.into_iter().collect();
+                }
+            }
+            RoomEvent::TickerSet {
+                room,
+                username,
+                ticker,
+            } => {
+                if let Some(idx) = self.open_index(&room) {
+                    self.open[idx].tickers.insert(username, ticker);
+                }
+            }
+            RoomEvent::TickerRemoved { room, username } => {
+                if let Some(idx) = self.open_index(&room) {
+                    self.open[idx].tickers.remove(&username);
+                }
+            }
         }
         // The active room's member list may have grown/shrunk (join/leave or a
         // wholesale replace on Joined); keep the selection highlight in range so
@@ -315,6 +394,7 @@ mod tests {
         RoomInfo {
             name: name.to_string(),
             user_count: users,
+            ..RoomInfo::default()
         }
     }
 
@@ -611,4 +691,25 @@ mod tests {
         );
         assert_eq!(state.open[0].users, vec!["bob"]);
     }
+
+    #[test]
+    fn conversations_are_tabs_apart_from_same_named_rooms() {
+        let mut state = RoomsState::new();
+        state.focus_or_open("jazz");
+        state.push_private_message("jazz", "jazz".into(), "hi".into(), true);
+        assert_eq!(state.open.len(), 2);
+        assert_eq!(state.active, 0, "an incoming message doesn't steal focus");
+        assert_eq!(state.conversation_index("jazz"), Some(1));
+        assert_eq!(state.open_index("jazz"), Some(0));
+        assert_eq!(state.total_unread(), 1);
+
+        state.focus_or_open_conversation("jazz");
+        assert_eq!(state.active, 1);
+        assert!(state.is_active_conversation("jazz"));
+        assert_eq!(state.total_unread(), 0);
+
+        state.push_private_message("jazz", "me".into(), "hey".into(), false);
+        assert_eq!(state.open[1].lines.len(), 2);
+        assert_eq!(state.total_unread(), 0);
+    }
 }
@@ -0,0 +1,143 @@
+//! Desktop notifications for finished downloads.
+//!
+//! Shown only while the terminal is unfocused, and only in builds with the
+//! `notifications` feature; without it the config is still read but nothing
+//! is shown.
+
+use crate::saved_search::basename;
+use serde::{Deserialize, Serialize};
+use soulseek_rs::DownloadStatus;
+use soulseek_rs::types::{Download, FailureReason};
+
+/// Which download events raise a notification (the `[notifications]`
+/// table). Both default to on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Notifications {
+    pub completed: bool,
+    pub failed: bool,
+}
+
+impl Default for Notifications {
+    fn default() -> Self {
+        Self {
+            completed: true,
+            failed: true,
+        }
+    }
+}
+
+impl Notifications {
+    /// The summary and body to show for `download` having just finished,
+    /// if that event is enabled. Downloads we cancelled ourselves aren't
+    /// news.
+    #[must_use]
+    pub fn message(self, download: &Download) -> Option<(String, String)> {
+        let name = basename(&download.filename);
+        let (summary, wanted) = match &download.status {
+            DownloadStatus::Completed(_) => {
+                ("Download complete".to_string(), self.completed)
+            }
+            DownloadStatus::Failed(Some(FailureReason::Aborted)) => {
+                return None;
+            }
+            DownloadStatus::Failed(Some(reason)) => {
+                (format!("Download failed: {reason}"), self.failed)
+            }
+            DownloadStatus::Failed(None) => {
+                ("Download failed".to_string(), self.failed)
+            }
+            DownloadStatus::TimedOut => {
+                ("Download timed out".to_string(), self.failed)
+            }
+            _ => return None,
+        };
+        wanted.then(|| (summary, format!("{name} from {}", download.username)))
+    }
+
+    /// Show the notification for `download`, if any, in the background.
+    pub fn notify(self, download: &Download) {
+        if let Some((summary, body)) = self.message(download) {
+            show(&summary, &body);
+        }
+    }
+}
+
+#[cfg(feature = "notifications")]
+fn show(summary: &str, body: &str) {
+    let (summary, body) = (summary.to_string(), body.to_string());
+    // Talking to the notification daemon can block; keep it off the UI
+    // thread.
+    std::thread::spawn(move || {
+        if let Err(e) = notify_rust::Notification::new()
+            .appname("soulseek-rs")
+            .summary(&summary)
+            .body(&body)
+            .show()
+        {
+            soulseek_rs::debug!("Could not show notification: {e}");
+        }
+    });
+}
+
+#[cfg(not(feature = "notifications"))]
+const fn show(_summary: &str, _body: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::Notifications;
+    use soulseek_rs::DownloadStatus;
+    use soulseek_rs::types::{Download, DownloadMetadata, FailureReason};
+
+    fn download(status: DownloadStatus) -> Download {
+        Download {
+            username: "amy".into(),
+            filename: "Music\\Album\\01 Song.flac".into(),
+            token: 1,
+            size: 42,
+            download_directory: String::new(),
+            status,
+            sender: std::sync::mpsc::channel().0,
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn finished_downloads_are_announced_per_event() {
+        let all = Notifications::default();
+        assert_eq!(
+            all.message(&download(DownloadStatus::Completed(None))),
+            Some(("Download complete".into(), "01 Song.flac from amy".into()))
+        );
+        assert_eq!(
+            all.message(&download(DownloadStatus::Failed(Some(
+                FailureReason::Banned
+            ))))
+            .map(|(summary, _)| summary),
+            Some("Download failed: Banned by the user".into())
+        );
+        assert_eq!(all.message(&download(DownloadStatus::Queued)), None);
+
+        let failures_only = Notifications {
+            completed: false,
+            failed: true,
+        };
+        assert_eq!(
+            failures_only.message(&download(DownloadStatus::Completed(None))),
+            None
+        );
+        assert!(
+            failures_only
+                .message(&download(DownloadStatus::TimedOut))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn our_own_cancellations_are_not_announced() {
+        let download =
+            download(DownloadStatus::Failed(Some(FailureReason::Aborted)));
+        assert_eq!(Notifications::default().message(&download), None);
+    }
+}
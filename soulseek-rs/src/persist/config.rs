@@ -1,6 +1,13 @@
+use crate::models::{KeySpec, Keymap};
+use crate::notifications::Notifications;
+use crate::saved_search::SavedSearch;
 use color_eyre::Result;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use soulseek_rs::utils::logger::{LogFile, LogFilter};
+use soulseek_rs::{Charset, DownloadHook, LeechFilter};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 /// Optional settings read from `config.toml`. Every field is optional so a
 /// partial file (or none at all) is valid; unknown keys are ignored so newer
@@ -20,9 +27,77 @@ pub struct FileConfig {
     pub shared_dirs: Option<Vec<String>>,
     pub max_concurrent_downloads: Option<usize>,
     pub search_timeout: Option<u64>,
+    /// Uploads served at once; further requests queue. 0 counts as 1, since
+    /// queued requests would otherwise wait forever.
+    pub upload_slots: Option<usize>,
+    /// Total upload bandwidth cap in KiB/s; unset or 0 means unlimited.
+    pub max_upload_rate: Option<u32>,
     /// Command whose stdout is the password (headless fallback, like mutt's
     /// `password_cmd`). Never store the password itself in the file.
     pub password_cmd: Option<String>,
+    /// Named searches runnable with `run-saved <name>` or `@<name>` in the
+    /// TUI (see [`crate::saved_search`]).
+    pub saved_searches: Option<BTreeMap<String, SavedSearch>>,
+    /// Charsets to repair non-UTF-8 file names with, e.g.
+    /// `["cp1252", "cp1251", "shift_jis"]`. Unset keeps Latin-1 only.
+    pub fallback_charsets: Option<Vec<String>>,
+    /// Deny uploads to users sharing too little (a `[leech_filter]` table).
+    pub leech_filter: Option<LeechFilterConfig>,
+    /// Users whose results, messages and connections are ignored, like
+    /// known fake-file spammers. The TUI's `ignore_user` key (`I` on a
+    /// result) adds to it.
+    pub ignored_users: Option<Vec<String>>,
+    /// Users whose coming online is shown in the TUI, like collectors you
+    /// wait for. The TUI's `favorite` key (`f` on a result) adds to it.
+    pub favorite_users: Option<Vec<String>>,
+    /// Command run after each completed download, like `beet import
+    /// {path}`; `{path}`, `{user}` and `{size}` are filled in.
+    pub on_download_complete: Option<String>,
+    /// Desktop notifications per event (a `[notifications]` table with
+    /// `completed` and `failed`); only in builds with the `notifications`
+    /// feature.
+    pub notifications: Option<Notifications>,
+    /// Log verbosity, like passing `-v` that many times (0 = errors only).
+    pub verbose: Option<u8>,
+    /// Write logs to this file instead of stderr.
+    pub log_file: Option<PathBuf>,
+    /// Log levels per subsystem over `verbose`, like
+    /// `"peer=trace,server=info"`.
+    pub log_filter: Option<String>,
+    /// Rotate the log file once it reaches this many MiB (default 10).
+    pub log_max_size_mb: Option<u64>,
+    /// Rotated log files kept besides the current one (default 5).
+    pub log_max_files: Option<usize>,
+    /// Minutes without input before the TUI shows us as away (default 15;
+    /// 0 never does).
+    pub auto_away_minutes: Option<u64>,
+    /// TUI key bindings by action (a `[keys]` table; see
+    /// [`crate::models::Keymap`]).
+    pub keys: Option<BTreeMap<String, KeySpec>>,
+}
+
+/// The `[leech_filter]` table. Users below either threshold get
+/// `deny_reason` instead of a queued upload; `whitelist` is always served.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeechFilterConfig {
+    pub min_shared_files: Option<u32>,
+    pub min_shared_folders: Option<u32>,
+    pub whitelist: Option<Vec<String>>,
+    pub deny_reason: Option<String>,
+}
+
+impl LeechFilterConfig {
+    fn to_filter(&self) -> LeechFilter {
+        let mut filter = LeechFilter::new()
+            .min_shared_files(self.min_shared_files.unwrap_or(0))
+            .min_shared_folders(self.min_shared_folders.unwrap_or(0))
+            .whitelist(self.whitelist.iter().flatten().cloned());
+        if let Some(reason) = &self.deny_reason {
+            filter = filter.deny_reason(reason.clone());
+        }
+        filter
+    }
 }
 
 impl FileConfig {
@@ -41,9 +116,32 @@ impl FileConfig {
                 ));
             }
         };
-        toml::from_str(&text).map_err(|e| {
+        let config: Self = toml::from_str(&text).map_err(|e| {
             color_eyre::eyre::eyre!("Malformed {}: {e}", path.display())
-        })
+        })?;
+        for name in config.fallback_charsets.iter().flatten() {
+            name.parse::<Charset>().map_err(|e| {
+                color_eyre::eyre::eyre!("Malformed {}: {e}", path.display())
+            })?;
+        }
+        if let Some(spec) = &config.log_filter {
+            spec.parse::<LogFilter>().map_err(|e| {
+                color_eyre::eyre::eyre!("Malformed {}: {e}", path.display())
+            })?;
+        }
+        if let Some(keys) = &config.keys {
+            Keymap::with_overrides(keys).map_err(|e| {
+                color_eyre::eyre::eyre!("Malformed {}: {e}", path.display())
+            })?;
+        }
+        Ok(config)
+    }
+
+    /// Load `path`, change it with `edit` and save it back.
+    pub fn update(path: &Path, edit: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut config = Self::load(path)?;
+        edit(&mut config);
+        config.save(path)
     }
 
     /// Save to `path`, creating parent directories as needed.
@@ -68,19 +166,38 @@ pub struct Resolved {
     pub shared_dirs: Vec<String>,
     pub max_concurrent_downloads: usize,
     pub search_timeout: u64,
+    pub upload_slots: usize,
+    pub max_upload_rate: Option<u32>,
     pub password_cmd: Option<String>,
+    pub saved_searches: BTreeMap<String, SavedSearch>,
+    pub fallback_charsets: Vec<Charset>,
+    pub leech_filter: Option<LeechFilter>,
+    pub ignored_users: Vec<String>,
+    pub favorite_users: Vec<String>,
+    pub on_download_complete: Option<DownloadHook>,
+    pub notifications: Notifications,
+    pub verbose: u8,
+    pub log_file: Option<PathBuf>,
+    pub log_filter: Option<String>,
+    pub log_max_bytes: u64,
+    pub log_max_files: usize,
+    pub auto_away: Option<Duration>,
+    pub keymap: Keymap,
 }
 
 pub const DEFAULT_SERVER: &str = "server.slsknet.org:2416";
 pub const DEFAULT_LISTENER_PORT: u16 = 2234;
 pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 5;
 pub const DEFAULT_SEARCH_TIMEOUT: u64 = 10;
+pub const DEFAULT_UPLOAD_SLOTS: usize = 2;
+pub const DEFAULT_AUTO_AWAY_MINUTES: u64 = 15;
 
 /// Layer CLI/env values over the config file over defaults.
 ///
 /// The `--disable-listener` flag can only enable the disable (a bare flag
 /// has no "explicitly off" form), so file `disable_listener = true` wins
-/// unless the flag is passed.
+/// unless the flag is passed. Likewise `-v` only raises the verbosity: with
+/// no `-v` the file's `verbose` applies.
 #[must_use]
 pub fn resolve(cli: &crate::cli::Cli, file: &FileConfig) -> Resolved {
     let download_dir = cli
@@ -111,10 +228,62 @@ pub fn resolve(cli: &crate::cli::Cli, file: &FileConfig) -> Resolved {
             .search_timeout
             .or(file.search_timeout)
             .unwrap_or(DEFAULT_SEARCH_TIMEOUT),
+        upload_slots: file.upload_slots.unwrap_or(DEFAULT_UPLOAD_SLOTS).max(1),
+        max_upload_rate: file.max_upload_rate.filter(|&rate| rate > 0),
         password_cmd: file.password_cmd.clone(),
+        saved_searches: file.saved_searches.clone().unwrap_or_default(),
+        fallback_charsets: file.fallback_charsets.as_ref().map_or_else(
+            || vec![Charset::Latin1],
+            |names| names.iter().filter_map(|n| n.parse().ok()).collect(),
+        ),
+        leech_filter: file
+            .leech_filter
+            .as_ref()
+            .map(LeechFilterConfig::to_filter),
+        ignored_users: usernames(file.ignored_users.as_deref()),
+        favorite_users: usernames(file.favorite_users.as_deref()),
+        on_download_complete: file
+            .on_download_complete
+            .as_deref()
+            .filter(|command| !command.trim().is_empty())
+            .map(DownloadHook::new),
+        notifications: file.notifications.unwrap_or_default(),
+        verbose: if cli.verbose > 0 {
+            cli.verbose
+        } else {
+            file.verbose.unwrap_or(0)
+        },
+        log_file: cli.log_file.clone().or_else(|| file.log_file.clone()),
+        log_filter: cli.log_filter.clone().or_else(|| file.log_filter.clone()),
+        log_max_bytes: file
+            .log_max_size_mb
+            .map_or(LogFile::DEFAULT_MAX_BYTES, |mb| mb * 1024 * 1024),
+        log_max_files: file.log_max_files.unwrap_or(LogFile::DEFAULT_MAX_FILES),
+        auto_away: Some(
+            file.auto_away_minutes.unwrap_or(DEFAULT_AUTO_AWAY_MINUTES),
+        )
+        .filter(|&minutes| minutes > 0)
+        .map(|minutes| Duration::from_secs(minutes * 60)),
+        // Checked by `FileConfig::load`, so only a hand-built config can
+        // fail here; it falls back to the defaults.
+        keymap: file
+            .keys
+            .as_ref()
+            .and_then(|keys| Keymap::with_overrides(keys).ok())
+            .unwrap_or_default(),
     }
 }
 
+/// The non-blank names in a list of usernames.
+fn usernames(list: Option<&[String]>) -> Vec<String> {
+    list.into_iter()
+        .flatten()
+        .map(|username| username.trim())
+        .filter(|username| !username.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
 /// Sharing follows the Soulseek convention of sharing what you download:
 /// with nothing configured, the download folder is shared. Configuring any
 /// of `--shared-dir` / `shared_dir` / `shared_dirs` replaces that default
@@ -156,6 +325,7 @@ mod tests {
             listener_port: None,
             verbose: 0,
             log_file: None,
+            log_filter: None,
             command: None,
             download_dir: None,
             shared_dir: None,
@@ -174,6 +344,17 @@ mod tests {
             DEFAULT_MAX_CONCURRENT_DOWNLOADS
         );
         assert_eq!(resolved.search_timeout, DEFAULT_SEARCH_TIMEOUT);
+        assert_eq!(resolved.upload_slots, DEFAULT_UPLOAD_SLOTS);
+        assert_eq!(resolved.max_upload_rate, None);
+        assert_eq!(resolved.leech_filter, None);
+        assert!(resolved.ignored_users.is_empty());
+        assert!(resolved.favorite_users.is_empty());
+        assert_eq!(resolved.on_download_complete, None);
+        assert_eq!(resolved.notifications, Notifications::default());
+        assert_eq!(
+            resolved.auto_away,
+            Some(Duration::from_secs(DEFAULT_AUTO_AWAY_MINUTES * 60))
+        );
         assert!(!resolved.disable_listener);
         assert_eq!(resolved.username, None);
     }
@@ -268,7 +449,27 @@ mod tests {
             shared_dirs: None,
             max_concurrent_downloads: Some(2),
             search_timeout: Some(30),
+            upload_slots: Some(4),
+            max_upload_rate: Some(512),
             password_cmd: Some("pass show slsk".into()),
+            saved_searches: None,
+            fallback_charsets: Some(vec!["cp1252".into(), "Shift_JIS".into()]),
+            leech_filter: Some(LeechFilterConfig {
+                min_shared_files: Some(50),
+                whitelist: Some(vec!["friend".into()]),
+                ..LeechFilterConfig::default()
+            }),
+            ignored_users: Some(vec!["spammer".into(), " ".into()]),
+            favorite_users: Some(vec!["collector".into()]),
+            on_download_complete: Some("beet import {path}".into()),
+            notifications: None,
+            verbose: Some(2),
+            log_file: Some("/tmp/slsk.log".into()),
+            log_filter: Some("peer=trace".into()),
+            log_max_size_mb: Some(2),
+            log_max_files: Some(1),
+            auto_away_minutes: Some(0),
+            keys: None,
         };
         let resolved = resolve(&bare_cli(), &file);
         assert_eq!(resolved.username.as_deref(), Some("alice"));
@@ -279,7 +480,33 @@ mod tests {
         assert_eq!(resolved.shared_dirs, vec!["/shared".to_string()]);
         assert_eq!(resolved.max_concurrent_downloads, 2);
         assert_eq!(resolved.search_timeout, 30);
+        assert_eq!(resolved.upload_slots, 4);
+        assert_eq!(resolved.max_upload_rate, Some(512));
         assert_eq!(resolved.password_cmd.as_deref(), Some("pass show slsk"));
+        assert_eq!(
+            resolved.fallback_charsets,
+            [Charset::Windows1252, Charset::ShiftJis]
+        );
+        assert_eq!(
+            resolved.leech_filter,
+            Some(
+                LeechFilter::new()
+                    .min_shared_files(50)
+                    .whitelist(["friend"])
+            )
+        );
+        assert_eq!(resolved.ignored_users, ["spammer"]);
+        assert_eq!(resolved.favorite_users, ["collector"]);
+        assert_eq!(
+            resolved.on_download_complete,
+            Some(DownloadHook::new("beet import {path}"))
+        );
+        assert_eq!(resolved.verbose, 2);
+        assert_eq!(resolved.log_file, Some(PathBuf::from("/tmp/slsk.log")));
+        assert_eq!(resolved.log_filter.as_deref(), Some("peer=trace"));
+        assert_eq!(resolved.log_max_bytes, 2 * 1024 * 1024);
+        assert_eq!(resolved.log_max_files, 1);
+        assert_eq!(resolved.auto_away, None);
     }
 
     #[test]
@@ -289,11 +516,15 @@ mod tests {
         cli.server = Some("cli-server:1".into());
         cli.listener_port = Some(1111);
         cli.download_dir = Some("/cli-dl".into());
+        cli.verbose = 1;
+        cli.log_file = Some("/cli.log".into());
         let file = FileConfig {
             username: Some("file-user".into()),
             server: Some("file-server:2".into()),
             listener_port: Some(2222),
             download_dir: Some("/file-dl".into()),
+            verbose: Some(3),
+            log_file: Some("/file.log".into()),
             ..FileConfig::default()
         };
         let resolved = resolve(&cli, &file);
@@ -301,6 +532,17 @@ mod tests {
         assert_eq!(resolved.server, "cli-server:1");
         assert_eq!(resolved.listener_port, 1111);
         assert_eq!(resolved.download_dir, "/cli-dl");
+        assert_eq!(resolved.verbose, 1);
+        assert_eq!(resolved.log_file, Some(PathBuf::from("/cli.log")));
+    }
+
+    #[test]
+    fn zero_upload_slots_still_serve_one_upload() {
+        let file = FileConfig {
+            upload_slots: Some(0),
+            ..FileConfig::default()
+        };
+        assert_eq!(resolve(&bare_cli(), &file).upload_slots, 1);
     }
 
     #[test]
@@ -336,11 +578,94 @@ mod tests {
         assert_eq!(config.server, None);
     }
 
+    #[test]
+    fn update_edits_the_file_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "username = \"bob\"\n").unwrap();
+        FileConfig::update(&path, |config| {
+            config.favorite_users = Some(vec!["collector".into()]);
+        })
+        .unwrap();
+        let config = FileConfig::load(&path).unwrap();
+        assert_eq!(config.username.as_deref(), Some("bob"));
+        assert_eq!(config.favorite_users, Some(vec!["collector".into()]));
+    }
+
+    #[test]
+    fn saved_searches_load_as_named_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[saved_searches.ambient]\nquery = \"selected ambient works\"\nextensions = [\"flac\"]\nauto_download = \"all\"\n",
+        )
+        .unwrap();
+        let config = FileConfig::load(&path).unwrap();
+        let resolved = resolve(&bare_cli(), &config);
+        let saved = &resolved.saved_searches["ambient"];
+        assert_eq!(saved.query, "selected ambient works");
+        assert_eq!(saved.extensions, ["flac"]);
+        assert_eq!(saved.auto_download, crate::saved_search::AutoDownload::All);
+
+        config.save(&path).unwrap();
+        assert_eq!(FileConfig::load(&path).unwrap(), config);
+    }
+
+    #[test]
+    fn notifications_table_turns_events_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[notifications]\ncompleted = false\n").unwrap();
+        let file = FileConfig::load(&path).unwrap();
+        assert_eq!(
+            resolve(&bare_cli(), &file).notifications,
+            Notifications {
+                completed: false,
+                failed: true,
+            }
+        );
+    }
+
     #[test]
     fn malformed_file_is_an_error() {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("config.toml");
         std::fs::write(&path, "username = [unclosed").unwrap();
         assert!(FileConfig::load(&path).is_err());
+
+        std::fs::write(&path, "fallback_charsets = [\"ebcdic\"]").unwrap();
+        assert!(FileConfig::load(&path).is_err());
+
+        std::fs::write(&path, "[keys]\nquit = \"Hyper-q\"").unwrap();
+        assert!(FileConfig::load(&path).is_err());
+    }
+
+    #[test]
+    fn keys_table_rebinds_actions() {
+        use crate::models::Action;
+        use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[keys]\nquit = \"Ctrl-q\"\ndown = [\"Down\", \"n\"]\n",
+        )
+        .unwrap();
+        let file = FileConfig::load(&path).unwrap();
+        let keymap = resolve(&bare_cli(), &file).keymap;
+        assert!(keymap.pressed(
+            Action::Quit,
+            &KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)
+        ));
+        assert!(keymap.pressed(
+            Action::Down,
+            &KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE)
+        ));
+        assert!(keymap.pressed(
+            Action::Up,
+            &KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE)
+        ));
     }
 }
@@ -20,6 +20,30 @@ pub fn state_dir() -> Option<PathBuf> {
     project_dirs().map(|d| d.data_dir().join("state"))
 }
 
+/// Where the client records finished downloads.
+#[must_use]
+pub fn download_history_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("download_history.jsonl"))
+}
+
+/// Where the daemon keeps unfinished downloads between runs.
+#[must_use]
+pub fn download_queue_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("download_queue.jsonl"))
+}
+
+/// Where the daemon writes the token control connections authenticate with.
+#[must_use]
+pub fn control_token_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("control_token"))
+}
+
+/// Where the client keeps the share index between runs.
+#[must_use]
+pub fn share_index_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("share_index.tsv"))
+}
+
 fn project_dirs() -> Option<ProjectDirs> {
     ProjectDirs::from("", "", "soulseek-rs")
 }
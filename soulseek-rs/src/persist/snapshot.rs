@@ -2,8 +2,8 @@
 //! parts of it). Live handles (channels, cancel flags) never leave the
 //! process; only plain data goes to disk.
 
-use super::state::PersistedDownload;
-use crate::models::{AppState, SearchEntry, SearchStatus};
+use super::state::{PersistedDownload, PersistedResult, PersistedSearch};
+use crate::models::{AppState, FileDisplayData, SearchEntry, SearchStatus};
 use soulseek_rs::DownloadStatus;
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -29,7 +29,7 @@ impl Snapshot {
                 download_directory: entry.download.download_directory.clone(),
                 completed: matches!(
                     entry.download.status,
-                    DownloadStatus::Completed
+                    DownloadStatus::Completed(_)
                 ),
             })
             .collect();
@@ -45,6 +45,7 @@ impl Snapshot {
             .rooms
             .open
             .iter()
+            .filter(|room| !room.private)
             .map(|room| room.name.clone())
             .collect();
 
@@ -56,9 +57,45 @@ impl Snapshot {
     }
 }
 
+/// The results of each search that has any, newest search per query. Too
+/// big to compare every tick, so this is only written on exit.
+#[must_use]
+pub fn capture_search_results(state: &AppState) -> Vec<PersistedSearch> {
+    let mut searches: Vec<PersistedSearch> = Vec::new();
+    for entry in &state.searches {
+        if entry.results.is_empty()
+            || searches.iter().any(|search| search.query == entry.query)
+        {
+            continue;
+        }
+        searches.push(PersistedSearch {
+            query: entry.query.clone(),
+            results: entry
+                .results
+                .iter()
+                .map(|file| PersistedResult {
+                    filename: file.filename.clone(),
+                    size: file.size,
+                    username: file.username.clone(),
+                    speed: file.speed,
+                    slots: u8::from(file.free_slot),
+                    bitrate: file.bitrate,
+                    length_seconds: file.length_seconds,
+                    vbr: file.vbr,
+                    sample_rate: file.sample_rate,
+                    bit_depth: file.bit_depth,
+                    locked: file.locked,
+                    queue_length: file.queue_length,
+                })
+                .collect(),
+        });
+    }
+    searches
+}
+
 /// Rebuild search history entries from persisted query strings. Restored
-/// searches are inert (completed, no results) — selecting one shows an
-/// empty result set until it is re-run.
+/// searches are inert (completed, no results) until
+/// [`restore_search_results`] fills them in.
 pub fn restore_searches(state: &mut AppState, queries: &[String]) {
     for query in queries {
         state.searches.push(SearchEntry {
@@ -69,10 +106,44 @@ pub fn restore_searches(state: &mut AppState, queries: &[String]) {
             cancel_flag: std::sync::Arc::new(
                 std::sync::atomic::AtomicBool::new(false),
             ),
+            saved: None,
         });
     }
 }
 
+/// Give restored searches back the results they had last session.
+pub fn restore_search_results(
+    state: &mut AppState,
+    searches: &[PersistedSearch],
+) {
+    for search in searches {
+        let Some(entry) = state.searches.iter_mut().find(|entry| {
+            entry.query == search.query && entry.results.is_empty()
+        }) else {
+            continue;
+        };
+        entry.results = search
+            .results
+            .iter()
+            .map(|result| FileDisplayData {
+                filename: result.filename.clone(),
+                size: result.size,
+                username: result.username.clone(),
+                speed: result.speed,
+                free_slot: result.slots > 0,
+                bitrate: result.bitrate,
+                length_seconds: result.length_seconds,
+                vbr: result.vbr,
+                sample_rate: result.sample_rate,
+                bit_depth: result.bit_depth,
+                name_charset: None,
+                locked: result.locked,
+                queue_length: result.queue_length,
+            })
+            .collect();
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -84,8 +155,8 @@ mod tests {
 
     fn download(filename: &str, status: DownloadStatus) -> DownloadEntry {
         let (sender, _receiver) = mpsc::channel();
-        DownloadEntry {
-            download: Download {
+        DownloadEntry::new(
+            Download {
                 username: "peer".into(),
                 filename: filename.into(),
                 token: 7,
@@ -96,8 +167,8 @@ mod tests {
                 queue_position: None,
                 metadata: DownloadMetadata::default(),
             },
-            receiver: None,
-        }
+            None,
+        )
     }
 
     fn search(query: &str) -> SearchEntry {
@@ -107,6 +178,7 @@ mod tests {
             results: Vec::new(),
             start_time: Instant::now(),
             cancel_flag: Arc::new(AtomicBool::new(false)),
+            saved: None,
         }
     }
 
@@ -115,7 +187,7 @@ mod tests {
         let mut state = AppState::new();
         state
             .downloads
-            .push(download("done.mp3", DownloadStatus::Completed));
+            .push(download("done.mp3", DownloadStatus::Completed(None)));
         state
             .downloads
             .push(download("queued.mp3", DownloadStatus::Queued));
@@ -180,6 +252,30 @@ mod tests {
         assert!(state.searches[0].results.is_empty());
     }
 
+    #[test]
+    fn search_results_are_restored_onto_their_query() {
+        let mut state = AppState::new();
+        restore_searches(&mut state, &["beatles".to_string()]);
+        state.searches[0].results.push(FileDisplayData {
+            filename: "help.mp3".into(),
+            username: "peer".into(),
+            bitrate: Some(320),
+            ..FileDisplayData::default()
+        });
+        let saved = capture_search_results(&state);
+
+        let mut state = AppState::new();
+        restore_searches(
+            &mut state,
+            &["beatles".to_string(), "miles davis".to_string()],
+        );
+        restore_search_results(&mut state, &saved);
+        assert_eq!(state.searches[0].results.len(), 1);
+        assert_eq!(state.searches[0].results[0].filename, "help.mp3");
+        assert_eq!(state.searches[0].results[0].bitrate, Some(320));
+        assert!(state.searches[1].results.is_empty());
+    }
+
     #[test]
     fn capture_of_restored_searches_round_trips() {
         let mut state = AppState::new();
@@ -1,4 +1,5 @@
-//! Versioned JSON state files (downloads, search queries, open rooms).
+//! Versioned JSON state files (downloads, search queries and results, open
+//! rooms).
 //!
 //! Each file is an envelope `{ "version": N, "data": ... }`. On load the
 //! data passes through the migration chain from its stored version up to
@@ -24,6 +25,35 @@ pub struct PersistedDownload {
     pub completed: bool,
 }
 
+/// One search result file, as shown in the results pane.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, serde::Deserialize)]
+pub struct PersistedResult {
+    pub filename: String,
+    pub size: u64,
+    pub username: String,
+    pub speed: u32,
+    pub slots: u8,
+    pub bitrate: Option<u32>,
+    pub length_seconds: Option<u32>,
+    #[serde(default)]
+    pub vbr: Option<bool>,
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    #[serde(default)]
+    pub bit_depth: Option<u32>,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub queue_length: u32,
+}
+
+/// A search's results, keyed by its query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, serde::Deserialize)]
+pub struct PersistedSearch {
+    pub query: String,
+    pub results: Vec<PersistedResult>,
+}
+
 pub struct StateStore {
     dir: PathBuf,
 }
@@ -61,6 +91,21 @@ impl StateStore {
         )
     }
 
+    pub fn load_search_results(&self) -> Vec<PersistedSearch> {
+        load(&self.dir.join("results.json"), RESULTS_MIGRATIONS)
+    }
+
+    pub fn save_search_results(
+        &self,
+        searches: &[PersistedSearch],
+    ) -> Result<()> {
+        save(
+            &self.dir.join("results.json"),
+            RESULTS_MIGRATIONS.len() as u32,
+            &searches,
+        )
+    }
+
     pub fn load_rooms(&self) -> Vec<String> {
         load(&self.dir.join("rooms.json"), ROOMS_MIGRATIONS)
     }
@@ -79,6 +124,7 @@ impl StateStore {
 /// are at version 0 today — add a fn here when the schema changes.
 const DOWNLOADS_MIGRATIONS: &[Migration] = &[];
 const SEARCHES_MIGRATIONS: &[Migration] = &[];
+const RESULTS_MIGRATIONS: &[Migration] = &[];
 const ROOMS_MIGRATIONS: &[Migration] = &[];
 
 /// Load `data` from an envelope file, migrating old versions forward.
@@ -190,6 +236,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn search_results_round_trip() {
+        let (_tmp, store) = store();
+        let searches = vec![PersistedSearch {
+            query: "beatles".into(),
+            results: vec![PersistedResult {
+                filename: "@@abc\\music\\help.mp3".into(),
+                size: 123,
+                username: "peer".into(),
+                speed: 1000,
+                slots: 1,
+                bitrate: Some(320),
+                length_seconds: None,
+                vbr: None,
+                sample_rate: Some(44100),
+                bit_depth: None,
+                locked: false,
+                queue_length: 0,
+            }],
+        }];
+        store.save_search_results(&searches).unwrap();
+        assert_eq!(store.load_search_results(), searches);
+    }
+
     #[test]
     fn corrupt_file_loads_as_empty_and_is_kept_as_bak() {
         let (tmp, store) = store();
@@ -0,0 +1,109 @@
+//! Picking the best copy of a file for unattended downloads
+//! (`search --auto best`).
+
+use crate::models::{FileDisplayData, QualityClass};
+use soulseek_rs::{File, SearchResult};
+use std::cmp::Reverse;
+
+/// How good a download source is; higher is better. Compared field by
+/// field: a free slot first, then lossless over lossy, the higher bitrate,
+/// the shorter queue and finally the faster uploader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Score {
+    free_slot: bool,
+    quality: Reverse<QualityClass>,
+    bitrate: u32,
+    queue_length: Reverse<u32>,
+    speed: u32,
+}
+
+#[must_use]
+pub fn score(result: &SearchResult, file: &File) -> Score {
+    Score {
+        free_slot: result.free_slot,
+        quality: Reverse(FileDisplayData::new(result, file).quality_class()),
+        bitrate: file.attributes.bitrate.unwrap_or(0),
+        queue_length: Reverse(result.queue_length),
+        speed: result.speed,
+    }
+}
+
+/// The top-scoring file across all `results`, with the result it came from.
+/// Ties go to whichever arrived first.
+#[must_use]
+pub fn best(results: &[SearchResult]) -> Option<(&SearchResult, &File)> {
+    results
+        .iter()
+        .flat_map(|result| result.files.iter().map(move |file| (result, file)))
+        .rev()
+        .max_by_key(|(result, file)| score(result, file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::best;
+    use soulseek_rs::{File, FileAttributes, SearchResult};
+
+    fn result(
+        username: &str,
+        free_slot: bool,
+        queue_length: u32,
+        files: &[(&str, Option<u32>)],
+    ) -> SearchResult {
+        SearchResult {
+            token: 1,
+            files: files
+                .iter()
+                .map(|&(name, bitrate)| File {
+                    username: username.into(),
+                    name: name.into(),
+                    size: 1,
+                    attributes: FileAttributes {
+                        bitrate,
+                        ..FileAttributes::default()
+                    },
+                    name_charset: None,
+                    locked: false,
+                })
+                .collect(),
+            free_slot,
+            speed: 100,
+            queue_length,
+            username: username.into(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    fn pick(results: &[SearchResult]) -> (&str, &str) {
+        let (result, file) = best(results).unwrap();
+        (result.username.as_str(), file.name.as_str())
+    }
+
+    #[test]
+    fn prefers_a_free_slot_then_lossless_then_bitrate() {
+        let results = [
+            result("busy", false, 0, &[("a.flac", None)]),
+            result(
+                "mp3",
+                true,
+                0,
+                &[("a.mp3", Some(192)), ("b.mp3", Some(320))],
+            ),
+            result("flac", true, 0, &[("a.flac", None)]),
+        ];
+        assert_eq!(pick(&results), ("flac", "a.flac"));
+        assert_eq!(pick(&results[..2]), ("mp3", "b.mp3"));
+        assert!(best(&[]).is_none());
+    }
+
+    #[test]
+    fn shorter_queue_wins_and_ties_keep_arrival_order() {
+        let results = [
+            result("first", true, 0, &[("a.mp3", Some(320))]),
+            result("second", true, 0, &[("a.mp3", Some(320))]),
+            result("queued", true, 40, &[("a.mp3", Some(320))]),
+        ];
+        assert_eq!(pick(&results), ("first", "a.mp3"));
+        assert_eq!(pick(&results[1..]), ("second", "a.mp3"));
+    }
+}
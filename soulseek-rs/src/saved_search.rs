@@ -0,0 +1,350 @@
+//! Named saved searches from `config.toml`, and the wishlist that re-runs
+//! them in the background.
+//!
+//! ```toml
+//! [saved_searches.ambient]
+//! query = "aphex twin selected ambient works"
+//! extensions = ["flac"]
+//! auto_download = "all"
+//! refresh_minutes = 60
+//! ```
+//!
+//! A saved search runs with `soulseek-rs run-saved <name>`, or from the TUI
+//! search bar as `@<name>`. Those with `refresh_minutes` are also kept
+//! fresh by the TUI while it runs.
+
+use serde::{Deserialize, Serialize};
+use soulseek_rs::{File, SearchFilter, SearchResult};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// What to download once a saved search has finished collecting results.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoDownload {
+    /// Only show the results.
+    #[default]
+    Off,
+    /// The single best-ranked file.
+    Best,
+    /// Every distinct matching file (name and size), each from its
+    /// best-ranked source.
+    All,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedSearch {
+    pub query: String,
+    /// Minimum bitrate in kbps; files without one (lossless) pass.
+    pub min_bitrate: Option<u32>,
+    /// Allowed extensions, e.g. `["flac", "mp3"]`. Empty allows any.
+    pub extensions: Vec<String>,
+    /// Minimum file size in bytes.
+    pub min_size: Option<u64>,
+    /// Maximum file size in bytes.
+    pub max_size: Option<u64>,
+    /// Skip peers without a free upload slot.
+    pub free_slots_only: bool,
+    /// Skip peers with more uploads than this queued.
+    pub max_queue_length: Option<u32>,
+    pub auto_download: AutoDownload,
+    /// Re-run every this many minutes while the TUI is open. Unset (or 0)
+    /// means the search only runs when asked to.
+    pub refresh_minutes: Option<u64>,
+}
+
+impl SavedSearch {
+    #[must_use]
+    pub fn filter(&self) -> SearchFilter {
+        let mut filter = SearchFilter::new().extensions(&self.extensions);
+        if let Some(kbps) = self.min_bitrate {
+            filter = filter.min_bitrate(kbps);
+        }
+        if let Some(bytes) = self.min_size {
+            filter = filter.min_size(bytes);
+        }
+        if let Some(bytes) = self.max_size {
+            filter = filter.max_size(bytes);
+        }
+        if self.free_slots_only {
+            filter = filter.free_slots_only();
+        }
+        if let Some(uploads) = self.max_queue_length {
+            filter = filter.max_queue_length(uploads);
+        }
+        filter
+    }
+
+    #[must_use]
+    pub fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh_minutes
+            .filter(|&minutes| minutes > 0)
+            .map(Duration::from_mins)
+    }
+
+    /// Files to download under the auto-download policy. Each entry lists
+    /// the sources for one file, best first, so callers can fail over with
+    /// `Client::download_any`: the sources share a file name (ignoring ASCII
+    /// case) and size. `results` should already have been through
+    /// [`Self::filter`].
+    #[must_use]
+    pub fn picks(&self, results: &[SearchResult]) -> Vec<Vec<File>> {
+        let mut ranked: Vec<(&SearchResult, &File)> = results
+            .iter()
+            .flat_map(|result| result.files.iter().map(move |f| (result, f)))
+            .collect();
+        ranked.sort_by_key(|&(result, file)| {
+            std::cmp::Reverse(rank(result, file))
+        });
+
+        let mut order: Vec<(String, u64)> = Vec::new();
+        let mut by_file: HashMap<(String, u64), Vec<File>> = HashMap::new();
+        for (_, file) in ranked {
+            let key = same_file_key(file);
+            if !by_file.contains_key(&key) {
+                order.push(key.clone());
+            }
+            by_file.entry(key).or_default().push(file.clone());
+        }
+        let take = match self.auto_download {
+            AutoDownload::Off => 0,
+            AutoDownload::Best => 1,
+            AutoDownload::All => order.len(),
+        };
+        order
+            .into_iter()
+            .take(take)
+            .filter_map(|key| by_file.remove(&key))
+            .collect()
+    }
+}
+
+/// Files with equal keys are the same file to `Client::download_any`.
+fn same_file_key(file: &File) -> (String, u64) {
+    (basename(&file.name).to_ascii_lowercase(), file.size)
+}
+
+/// Preference order among matching files: a free slot first, then the
+/// higher bitrate, then the faster peer.
+fn rank(result: &SearchResult, file: &File) -> (bool, u32, u32) {
+    (
+        result.free_slot,
+        file.attributes.bitrate.unwrap_or(0),
+        result.speed,
+    )
+}
+
+/// The last path component of a peer's (backslash-separated) file name.
+#[must_use]
+pub fn basename(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+/// Tracks when each saved search last ran, to tell which are due again.
+#[derive(Debug, Default)]
+pub struct Wishlist {
+    last_run: HashMap<String, Instant>,
+}
+
+impl Wishlist {
+    /// Names of the refreshable searches due at `now` (never run, or run at
+    /// least one interval ago), which are then counted as run.
+    pub fn due(
+        &mut self,
+        searches: &BTreeMap<String, SavedSearch>,
+        now: Instant,
+    ) -> Vec<String> {
+        let mut due = Vec::new();
+        for (name, saved) in searches {
+            let Some(interval) = saved.refresh_interval() else {
+                continue;
+            };
+            let is_due = self
+                .last_run
+                .get(name)
+                .is_none_or(|&last| now.duration_since(last) >= interval);
+            if is_due {
+                self.last_run.insert(name.clone(), now);
+                due.push(name.clone());
+            }
+        }
+        due
+    }
+
+    /// Record a manual run so the next refresh counts from now.
+    pub fn mark_run(&mut self, name: &str, now: Instant) {
+        self.last_run.insert(name.to_string(), now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use soulseek_rs::FileAttributes;
+
+    fn file(name: &str, bitrate: u32) -> File {
+        sized(name, 1, bitrate)
+    }
+
+    fn sized(name: &str, size: u64, bitrate: u32) -> File {
+        File {
+            username: "peer".into(),
+            name: name.into(),
+            size,
+            attributes: FileAttributes {
+                bitrate: Some(bitrate),
+                ..FileAttributes::default()
+            },
+            name_charset: None,
+            locked: false,
+        }
+    }
+
+    fn result(
+        username: &str,
+        free_slot: bool,
+        files: Vec<File>,
+    ) -> SearchResult {
+        SearchResult {
+            token: 1,
+            files: files
+                .into_iter()
+                .map(|f| File {
+                    username: username.into(),
+                    ..f
+                })
+                .collect(),
+            free_slot,
+            speed: 100,
+            queue_length: 0,
+            username: username.into(),
+            locked_files: Vec::new(),
+        }
+    }
+
+    fn saved(auto_download: AutoDownload) -> SavedSearch {
+        SavedSearch {
+            query: "q".into(),
+            auto_download,
+            ..SavedSearch::default()
+        }
+    }
+
+    #[test]
+    fn parses_from_toml_with_lowercase_policy() {
+        let saved: SavedSearch = toml::from_str(
+            "query = \"x\"\nextensions = [\"flac\"]\nauto_download = \"best\"\nrefresh_minutes = 30\n",
+        )
+        .unwrap();
+        assert_eq!(saved.auto_download, AutoDownload::Best);
+        assert_eq!(saved.refresh_interval(), Some(Duration::from_mins(30)));
+        assert!(!saved.filter().accepts_file(&file("a.mp3", 320)));
+    }
+
+    #[test]
+    fn best_ranks_free_slots_then_bitrate() {
+        let results = [
+            result("busy", false, vec![file("@@a\\song.mp3", 320)]),
+            result("free", true, vec![file("@@b\\song.mp3", 192)]),
+            result("better", true, vec![file("@@c\\song.mp3", 256)]),
+        ];
+        let picks = saved(AutoDownload::Best).picks(&results);
+        assert_eq!(picks.len(), 1);
+        let users: Vec<&str> =
+            picks[0].iter().map(|f| f.username.as_str()).collect();
+        assert_eq!(users, ["better", "free", "busy"]);
+        assert!(saved(AutoDownload::Off).picks(&results).is_empty());
+    }
+
+    #[test]
+    fn all_groups_sources_by_file_name() {
+        let results = [
+            result(
+                "x",
+                true,
+                vec![file("@@x\\01.flac", 0), file("@@x\\02.flac", 0)],
+            ),
+            result("y", false, vec![file("@@y\\Album\\01.FLAC", 0)]),
+        ];
+        let picks = saved(AutoDownload::All).picks(&results);
+        let shape: Vec<Vec<&str>> = picks
+            .iter()
+            .map(|p| p.iter().map(|f| f.username.as_str()).collect())
+            .collect();
+        assert_eq!(shape, [vec!["x", "y"], vec!["x"]]);
+    }
+
+    #[test]
+    fn picks_only_group_sources_download_any_accepts() {
+        let results = [
+            result(
+                "x",
+                true,
+                vec![
+                    sized("@@x\\01.flac", 10, 900),
+                    sized("@@x\\02.flac", 20, 0),
+                ],
+            ),
+            result(
+                "y",
+                true,
+                vec![
+                    sized("@@y\\01.FLAC", 10, 0),
+                    sized("@@y\\01.flac", 11, 0),
+                ],
+            ),
+            result("z", false, vec![sized("@@z\\Other\\01.flac", 10, 0)]),
+        ];
+
+        let best = saved(AutoDownload::Best).picks(&results);
+        let users: Vec<&str> =
+            best[0].iter().map(|f| f.username.as_str()).collect();
+        assert_eq!(best.len(), 1);
+        assert_eq!(users, ["x", "y", "z"]);
+
+        let all = saved(AutoDownload::All).picks(&results);
+        assert_eq!(all.len(), 3);
+        let client = soulseek_rs::Client::with_settings(
+            soulseek_rs::ClientSettings::default(),
+        );
+        for sources in best.into_iter().chain(all) {
+            let names: Vec<String> =
+                sources.iter().map(|f| f.name.clone()).collect();
+            assert!(
+                !matches!(
+                    client.download_any(sources, String::new()),
+                    Err(soulseek_rs::SoulseekRs::InvalidArgument(_))
+                ),
+                "{names:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn wishlist_runs_refreshable_searches_once_per_interval() {
+        let mut searches = BTreeMap::new();
+        searches.insert("manual".to_string(), saved(AutoDownload::Off));
+        searches.insert(
+            "hourly".to_string(),
+            SavedSearch {
+                refresh_minutes: Some(60),
+                ..saved(AutoDownload::All)
+            },
+        );
+        let mut wishlist = Wishlist::default();
+        let start = Instant::now();
+        assert_eq!(wishlist.due(&searches, start), ["hourly"]);
+        assert!(
+            wishlist
+                .due(&searches, start + Duration::from_mins(1))
+                .is_empty()
+        );
+        assert_eq!(
+            wishlist.due(&searches, start + Duration::from_hours(1)),
+            ["hourly"]
+        );
+    }
+}
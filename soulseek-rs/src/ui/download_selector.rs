@@ -1,8 +1,8 @@
-use crate::models::FileDisplayData;
+use crate::models::{Action, FileDisplayData, Keymap};
 use crate::ui::{
     BYTES_PER_MB, HIGHLIGHT_SYMBOL, border_style, border_type, format_bytes,
-    format_shortcuts_styled, get_bitrate, get_spinner_char, header_style,
-    highlight_style, primary_style, success_style, warning_style,
+    format_shortcuts_styled, get_spinner_char, header_style, highlight_style,
+    primary_style, success_style, warning_style,
 };
 use color_eyre::Result;
 use ratatui::text::{Line, Span};
@@ -44,6 +44,7 @@ pub struct FileSelector {
     spinner_state: usize,
     last_spinner_update: Instant,
     last_result_count: usize,
+    keymap: Keymap,
 }
 
 impl FileSelector {
@@ -74,9 +75,16 @@ impl FileSelector {
             spinner_state: 0,
             last_spinner_update: Instant::now(),
             last_result_count: 0,
+            keymap: Keymap::default(),
         }
     }
 
+    #[must_use]
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
     pub fn run(
         &mut self,
         mut terminal: DefaultTerminal,
@@ -130,15 +138,7 @@ impl FileSelector {
             let mut new_items = Vec::new();
             for result in &search_results {
                 for file in &result.files {
-                    new_items.push(FileDisplayData {
-                        filename: file.name.clone(),
-                        size: file.size,
-                        username: result.username.clone(),
-                        speed: result.speed,
-                        slots: result.slots,
-                        bitrate: get_bitrate(&file.attribs),
-                        length_seconds: file.attribs.get(&1).copied(),
-                    });
+                    new_items.push(FileDisplayData::new(result, file));
                 }
             }
 
@@ -210,24 +210,25 @@ impl FileSelector {
                 _ => {}
             }
         } else {
+            let pressed = |action| self.keymap.pressed(action, &key);
             match key.code {
-                KeyCode::Char(' ') => {
+                _ if pressed(Action::ToggleSelect) => {
                     self.toggle_selection();
                 }
-                KeyCode::Char('/') => {
+                _ if pressed(Action::Filter) => {
                     self.is_filtering = true;
                 }
-                KeyCode::Up | KeyCode::Char('k') => self.select_previous(),
-                KeyCode::Down | KeyCode::Char('j') => self.select_next(),
-                KeyCode::Home | KeyCode::Char('g') => self.select_first(),
-                KeyCode::End | KeyCode::Char('G') => self.select_last(),
-                KeyCode::Char('a') => {
+                _ if pressed(Action::Up) => self.select_previous(),
+                _ if pressed(Action::Down) => self.select_next(),
+                _ if pressed(Action::First) => self.select_first(),
+                _ if pressed(Action::Last) => self.select_last(),
+                _ if pressed(Action::SelectAll) => {
                     // Select all visible/filtered items
                     for &original_idx in &self.filtered_indices {
                         self.selected_indices.insert(original_idx);
                     }
                 }
-                KeyCode::Char('A') => {
+                _ if pressed(Action::SelectNone) => {
                     // Deselect all items
                     self.selected_indices.clear();
                 }
@@ -242,7 +243,7 @@ impl FileSelector {
                     }
                     self.should_exit = true;
                 }
-                KeyCode::Esc | KeyCode::Char('q') => {
+                _ if key.code == KeyCode::Esc || pressed(Action::Quit) => {
                     self.selected_indices.clear();
                     self.should_exit = true;
                 }
@@ -405,8 +406,11 @@ impl FileSelector {
             Cell::from("Size"),
             Cell::from("Username"),
             Cell::from("Speed"),
-            Cell::from("Slots"),
+            Cell::from("Slot"),
+            Cell::from("Queue"),
             Cell::from("Bitrate"),
+            Cell::from("Length"),
+            Cell::from("Format"),
         ])
         .style(header_style())
         .height(1);
@@ -427,21 +431,17 @@ impl FileSelector {
                     "-".to_string()
                 };
 
-                let slots_str = format!("{}", item.slots);
-
-                let bitrate_str = match item.bitrate {
-                    Some(br) => format!("{br} kbps"),
-                    None => "-".to_string(),
-                };
-
                 let cells = vec![
                     Cell::from(checkbox),
                     Cell::from(item.filename.clone()),
                     Cell::from(format_bytes(item.size)),
                     Cell::from(item.username.clone()),
                     Cell::from(speed_str),
-                    Cell::from(slots_str),
-                    Cell::from(bitrate_str),
+                    Cell::from(if item.free_slot { "free" } else { "-" }),
+                    Cell::from(item.queue_length.to_string()),
+                    Cell::from(item.bitrate_label()),
+                    Cell::from(item.length_label()),
+                    Cell::from(item.format_label()),
                 ];
 
                 let style = if is_selected {
@@ -460,8 +460,11 @@ impl FileSelector {
             Constraint::Length(12),
             Constraint::Length(15),
             Constraint::Length(12),
-            Constraint::Length(8),
+            Constraint::Length(5),
+            Constraint::Length(6),
             Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Length(9),
         ];
 
         let table = Table::new(rows, widths)
@@ -598,23 +601,19 @@ impl FileSelector {
                 ("Enter", "download"),
                 ("Esc", "exit filter"),
             ])
-        } else if self.search_active {
-            format_shortcuts_styled(&[
-                ("Space", "toggle"),
-                ("a", "select-all"),
-                ("A", "deselect-all"),
-                ("Enter", "download"),
-                ("Esc/q", "cancel"),
-            ])
         } else {
-            format_shortcuts_styled(&[
-                ("Space", "toggle"),
-                ("a", "select-all"),
-                ("A", "deselect-all"),
-                ("Enter", "download"),
-                ("Esc/q", "cancel"),
-                ("/", "filter"),
-            ])
+            let key = |action| self.keymap.label(action);
+            let mut shortcuts = vec![
+                (key(Action::ToggleSelect), "toggle"),
+                (key(Action::SelectAll), "select-all"),
+                (key(Action::SelectNone), "deselect-all"),
+                ("Enter".to_string(), "download"),
+                (format!("Esc/{}", key(Action::Quit)), "cancel"),
+            ];
+            if !self.search_active {
+                shortcuts.push((key(Action::Filter), "filter"));
+            }
+            format_shortcuts_styled(&shortcuts)
         };
 
         let controls_widget = Paragraph::new(controls_line).block(
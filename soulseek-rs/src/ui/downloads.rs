@@ -1,7 +1,7 @@
 use crate::models::DownloadEntry;
 use crate::ui::{
     BYTES_PER_MB, COLOR_PRIMARY, HIGHLIGHT_SYMBOL, border_style, border_type,
-    error_style, format_bytes_progress, format_progress_bar,
+    error_style, format_bytes, format_bytes_progress, format_progress_bar,
     format_shortcuts_styled, format_speed, header_style, highlight_style,
     inactive_style, info_style, primary_style, warning_style,
 };
@@ -17,7 +17,7 @@ use ratatui::{
         Table, TableState,
     },
 };
-use soulseek_rs::{Client, DownloadStatus};
+use soulseek_rs::{Client, DownloadStatus, types::Download};
 use std::{
     sync::{Arc, mpsc, mpsc::Receiver},
     thread,
@@ -68,10 +68,8 @@ impl MultiDownloadProgress {
             while let Ok((download, receiver)) =
                 self.receiver_channel.try_recv()
             {
-                self.downloads.push(DownloadEntry {
-                    download,
-                    receiver: Some(receiver),
-                });
+                self.downloads
+                    .push(DownloadEntry::new(download, Some(receiver)));
                 self.queuing_status =
                     format!("{} downloads queued", self.downloads.len());
             }
@@ -83,7 +81,7 @@ impl MultiDownloadProgress {
                     && let Ok(status) = receiver.try_recv()
                 {
                     let was_active = !download_entry.download.is_finished();
-                    download_entry.download.status = status;
+                    download_entry.apply_status(status);
                     let is_finished = download_entry.download.is_finished();
 
                     // If download just finished, decrement active count
@@ -225,7 +223,7 @@ impl MultiDownloadProgress {
                     DownloadStatus::Queued => "⋯",
                     DownloadStatus::InProgress { .. } => "⧗",
                     DownloadStatus::Paused { .. } => "⏸",
-                    DownloadStatus::Completed => "✓",
+                    DownloadStatus::Completed(_) => "✓",
                     DownloadStatus::Failed(_) => "✗",
                     DownloadStatus::TimedOut => "⏱",
                 };
@@ -266,7 +264,7 @@ impl MultiDownloadProgress {
                     DownloadStatus::Queued => inactive_style(),
                     DownloadStatus::InProgress { .. } => warning_style(),
                     DownloadStatus::Paused { .. } => info_style(),
-                    DownloadStatus::Completed => primary_style(),
+                    DownloadStatus::Completed(_) => primary_style(),
                     DownloadStatus::Failed(_) | DownloadStatus::TimedOut => {
                         error_style()
                     }
@@ -334,7 +332,7 @@ pub fn render_download_stats(
 ) {
     let completed = downloads
         .iter()
-        .filter(|d| matches!(d.download.status, DownloadStatus::Completed))
+        .filter(|d| matches!(d.download.status, DownloadStatus::Completed(_)))
         .count();
     let failed = downloads
         .iter()
@@ -476,7 +474,7 @@ pub fn show_multi_download_progress(
     selected_files: Vec<(String, String, u64)>,
     download_dir: String,
     max_concurrent: usize,
-) -> Result<()> {
+) -> Result<Vec<Download>> {
     soulseek_rs::utils::logger::enable_buffering();
 
     let (tx, rx) = mpsc::channel();
@@ -510,5 +508,117 @@ pub fn show_multi_download_progress(
 
     soulseek_rs::utils::logger::flush_buffered_logs();
 
-    result
+    result.map(|()| {
+        progress
+            .downloads
+            .into_iter()
+            .map(|entry| entry.download)
+            .collect()
+    })
+}
+
+/// The end-of-run report printed after the download view closes: one line
+/// per file with where it was saved, how long it took and its average speed,
+/// then totals.
+#[must_use]
+pub fn download_report(downloads: &[Download]) -> String {
+    let mut lines = Vec::new();
+    let mut completed = 0;
+    let mut total_bytes = 0;
+    let mut total_elapsed = Duration::ZERO;
+
+    for download in downloads {
+        let name = download
+            .filename
+            .rsplit(['\\', '/'])
+            .next()
+            .unwrap_or(&download.filename);
+        match &download.status {
+            DownloadStatus::Completed(summary) => {
+                completed += 1;
+                total_bytes += download.size;
+                match summary {
+                    Some(summary) => {
+                        total_elapsed += summary.elapsed;
+                        lines.push(format!(
+                            "✓ {} ({}, {:.1}s, {})",
+                            summary.path.display(),
+                            format_bytes(download.size),
+                            summary.elapsed.as_secs_f64(),
+                            format_speed(summary.average_speed_bytes_per_sec)
+                        ));
+                    }
+                    None => lines.push(format!("✓ {name}")),
+                }
+            }
+            DownloadStatus::Failed(reason) => lines.push(format!(
+                "✗ {name}: {}",
+                reason
+                    .as_ref()
+                    .map_or_else(|| "failed".to_string(), ToString::to_string)
+            )),
+            DownloadStatus::TimedOut => {
+                lines.push(format!("✗ {name}: timed out"));
+            }
+            _ => lines.push(format!("… {name}: not finished")),
+        }
+    }
+
+    lines.push(format!(
+        "{completed}/{} downloaded, {} in {:.1}s",
+        downloads.len(),
+        format_bytes(total_bytes),
+        total_elapsed.as_secs_f64()
+    ));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::download_report;
+    use soulseek_rs::types::{Download, DownloadMetadata};
+    use soulseek_rs::{DownloadStatus, DownloadSummary};
+    use std::time::Duration;
+
+    fn download(filename: &str, status: DownloadStatus) -> Download {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        Download {
+            username: "peer".into(),
+            filename: filename.into(),
+            token: 1,
+            size: 2 * 1_048_576,
+            download_directory: "/music".into(),
+            status,
+            sender,
+            queue_position: None,
+            metadata: DownloadMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn report_lists_saved_paths_failures_and_totals() {
+        let report = download_report(&[
+            download(
+                "@@share\\Album\\01.flac",
+                DownloadStatus::Completed(Some(DownloadSummary::new(
+                    "/music/01.flac".into(),
+                    2 * 1_048_576,
+                    Duration::from_secs(2),
+                ))),
+            ),
+            download(
+                "@@share\\Album\\02.flac",
+                DownloadStatus::Failed(Some("peer went away".into())),
+            ),
+        ]);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "✓ /music/01.flac (2.0 MB, 2.0s, 1.0 MB/s)",
+                "✗ 02.flac: peer went away",
+                "1/2 downloaded, 2.0 MB in 2.0s",
+            ]
+        );
+    }
 }
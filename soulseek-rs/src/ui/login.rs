@@ -215,11 +215,10 @@ fn spawn_attempt(settings: ClientSettings) -> Receiver<Result<Client, String>> {
             .connect()
             .map_err(|e| format!("Failed to connect: {e}"))
             .and_then(|()| match client.login() {
-                Ok(true) => Ok(()),
-                Ok(false) => Err(
-                    "Login rejected: wrong password, or the username is taken"
-                        .to_string(),
-                ),
+                Ok(soulseek_rs::LoginOutcome::Accepted { .. }) => Ok(()),
+                Ok(soulseek_rs::LoginOutcome::Rejected(reason)) => {
+                    Err(format!("Login rejected: {reason}"))
+                }
                 Err(e) => Err(format!("Login failed: {e}")),
             });
         let _ = tx.send(result.map(|()| client));
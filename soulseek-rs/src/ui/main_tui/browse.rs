@@ -1,5 +1,5 @@
 use super::MainTui;
-use crate::models::{BrowseStatus, files_under, find_node};
+use crate::models::{Action, BrowseStatus, files_under, find_node};
 use ratatui::crossterm::event::{KeyCode, KeyEvent};
 use std::{thread, time::Duration};
 
@@ -8,7 +8,7 @@ const BROWSE_TIMEOUT: Duration = Duration::from_secs(20);
 
 impl MainTui {
     pub(super) fn handle_browse_input(&mut self, key: KeyEvent) {
-        if matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+        if key.code == KeyCode::Esc || self.keymap.pressed(Action::Quit, &key) {
             self.state.show_browse = false;
             return;
         }
@@ -25,14 +25,14 @@ impl MainTui {
                 self.sync_browse_selection();
                 return;
             }
-            KeyCode::Char('w') => {
+            _ if self.keymap.pressed(Action::CloseTab, &key) => {
                 if !self.state.browse.close_active() {
                     self.state.show_browse = false;
                 }
                 self.sync_browse_selection();
                 return;
             }
-            KeyCode::Char('r') => {
+            _ if self.keymap.pressed(Action::Retry, &key) => {
                 // Retry a timed-out browse.
                 if let Some(username) = self.state.browse.retry_active() {
                     let _ = self.client.browse_user(&username);
@@ -69,7 +69,7 @@ impl MainTui {
                 )]);
                 return;
             }
-            KeyCode::Char('d') => {
+            _ if self.keymap.pressed(Action::DownloadFolder, &key) => {
                 let files = if row.is_folder {
                     self.browse_folder_files(&row.path)
                 } else {
@@ -84,20 +84,20 @@ impl MainTui {
         // Navigation and expand/collapse mutate the browse state.
         if let Some(browse) = self.state.browse.active_tab_mut() {
             match key.code {
-                KeyCode::Up | KeyCode::Char('k') => {
+                _ if self.keymap.pressed(Action::Up, &key) => {
                     browse.selected_row = sel.saturating_sub(1);
                 }
-                KeyCode::Down | KeyCode::Char('j') => {
+                _ if self.keymap.pressed(Action::Down, &key) => {
                     browse.selected_row = (sel + 1).min(rows.len() - 1);
                 }
-                KeyCode::Right | KeyCode::Char('l') => {
+                _ if self.keymap.pressed(Action::Right, &key) => {
                     if row.is_folder && !row.expanded {
                         browse.expanded.insert(row.path.clone());
                     } else if row.is_folder {
                         browse.selected_row = (sel + 1).min(rows.len() - 1);
                     }
                 }
-                KeyCode::Left | KeyCode::Char('h') => {
+                _ if self.keymap.pressed(Action::Left, &key) => {
                     if row.is_folder && row.expanded {
                         browse.expanded.remove(&row.path);
                     } else if let Some(parent) =
@@ -190,13 +190,17 @@ impl MainTui {
         self.sync_browse_selection();
     }
 
-    /// The username of the highlighted search result (filter-aware).
+    /// The username of the highlighted search result or group node
+    /// (filter-aware).
     pub(super) fn highlighted_result_owner(&self) -> Option<String> {
+        if self.state.results_grouped {
+            return self.highlighted_group().map(|g| g.username.clone());
+        }
         let selected = self.state.results_table_state.selected()?;
-        let items = if self.state.results_filter_query.is_empty() {
-            &self.state.results_items
-        } else {
+        let items = if self.state.results_filter_active() {
             &self.state.results_filtered_items
+        } else {
+            &self.state.results_items
         };
         items.get(selected).map(|f| f.username.clone())
     }
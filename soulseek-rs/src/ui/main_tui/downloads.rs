@@ -1,8 +1,13 @@
 use super::MainTui;
 use crate::models::{DownloadEntry, FileDisplayData};
-use soulseek_rs::{DownloadStatus, types::Download};
+use crate::saved_search::basename;
+use soulseek_rs::{Client, DownloadStatus, File, types::Download};
+use std::time::{Duration, Instant};
 use std::{sync::mpsc, thread};
 
+/// How often a queued download's uploader is asked for our place in line.
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
 impl MainTui {
     /// Channel for queued downloads, created on first use.
     pub(super) fn downloads_sender(
@@ -17,8 +22,27 @@ impl MainTui {
         sender
     }
 
-    /// Re-queue the selected download if it failed or timed out.
-    pub(super) fn retry_selected_download(&mut self) {
+    /// The highlighted row's download, if it is a download rather than an
+    /// upload.
+    pub(super) fn selected_download(&self) -> Option<&Download> {
+        let index = self.state.downloads_table_state.selected()?;
+        self.state.downloads.get(index).map(|entry| &entry.download)
+    }
+
+    /// Cancel the selected download if it hasn't finished. It stays listed,
+    /// failed, so it can be retried.
+    pub(super) fn cancel_selected_download(&self) {
+        if let Some(download) = self.selected_download() {
+            let _ = self
+                .client
+                .cancel_download(&download.username, &download.filename);
+        }
+    }
+
+    /// Re-queue the selected download if it failed or timed out, from the
+    /// same user or, with `elsewhere`, from another in the search results
+    /// who has the same file.
+    pub(super) fn retry_selected_download(&mut self, elsewhere: bool) {
         let Some(index) = self.state.downloads_table_state.selected() else {
             return;
         };
@@ -31,8 +55,17 @@ impl MainTui {
         ) {
             return;
         }
-        let filename = entry.download.filename.clone();
-        let username = entry.download.username.clone();
+        let old_username = entry.download.username.clone();
+        let old_filename = entry.download.filename.clone();
+        let (filename, username) = if elsewhere {
+            let Some(source) = self.state.alternate_source(&entry.download)
+            else {
+                return;
+            };
+            (source.filename.clone(), source.username.clone())
+        } else {
+            (old_filename.clone(), old_username.clone())
+        };
         let size = entry.download.size;
         let directory = entry.download.download_directory.clone();
 
@@ -40,12 +73,12 @@ impl MainTui {
         let client = self.client.clone();
 
         // Drop the old failed entry from both the UI list and the client's
-        // download store; otherwise the stale entry (same md5-derived token and
-        // filename) shadows the retry and its completion is misrouted, leaving
-        // the retried download stuck showing "Queued".
+        // download store; otherwise the stale entry (same user and filename)
+        // shadows the retry and its completion is misrouted, leaving the
+        // retried download stuck showing "Queued".
         self.state.downloads.remove(index);
         self.select_download_after_removal(index);
-        let _ = self.client.remove_download(&username, &filename);
+        let _ = self.client.remove_download(&old_username, &old_filename);
 
         thread::spawn(move || {
             match client.download(filename.clone(), username, size, directory) {
@@ -57,12 +90,116 @@ impl MainTui {
         });
     }
 
+    /// Queue what a saved search's auto-download policy picked, from each
+    /// pick's best source. Files already downloaded or on their way (by
+    /// name, from any peer) are skipped, so wishlist refreshes only fetch
+    /// what is new.
+    pub(super) fn auto_download(&mut self, picks: Vec<Vec<File>>) {
+        let sender = self.downloads_sender();
+        for sources in picks {
+            let Some(file) = sources.into_iter().next() else {
+                continue;
+            };
+            let name = basename(&file.name).to_lowercase();
+            let have = self.state.downloads.iter().any(|entry| {
+                !matches!(
+                    entry.download.status,
+                    DownloadStatus::Failed(_) | DownloadStatus::TimedOut
+                ) && basename(&entry.download.filename).to_lowercase() == name
+            });
+            if have {
+                continue;
+            }
+            let client = self.client.clone();
+            let sender = sender.clone();
+            let directory = self.download_dir.clone();
+            thread::spawn(move || {
+                match client.download(
+                    file.name.clone(),
+                    file.username,
+                    file.size,
+                    directory,
+                ) {
+                    Ok((download, rx)) => {
+                        let _ = sender.send((download, rx));
+                    }
+                    Err(e) => soulseek_rs::warn!(
+                        "Failed to auto-download {}: {e}",
+                        file.name
+                    ),
+                }
+            });
+        }
+    }
+
+    /// Follow a download to the user it now comes from, after its uploader
+    /// disconnected.
+    pub(super) fn download_source_replaced(
+        &mut self,
+        username: &str,
+        filename: &str,
+        new_username: String,
+        new_filename: String,
+    ) {
+        let Some(entry) = self.state.downloads.iter_mut().find(|entry| {
+            entry.download.username == username
+                && entry.download.filename == filename
+                && !entry.download.is_finished()
+        }) else {
+            return;
+        };
+        entry.download.username = new_username;
+        entry.download.filename = new_filename;
+        entry.download.queue_position = None;
+        entry.queue_polled_at = None;
+    }
+
+    /// Swap the selected download with the one above it (`up`) or below,
+    /// moving it the same way in the client's priority order so it is asked
+    /// for sooner or later.
+    pub(super) fn move_selected_download(&mut self, up: bool) {
+        let count = self.state.downloads.len();
+        let Some(index) = self
+            .state
+            .downloads_table_state
+            .selected()
+            .filter(|&index| index < count)
+        else {
+            return;
+        };
+        let Some(other) = (if up {
+            index.checked_sub(1)
+        } else {
+            Some(index + 1)
+        })
+        .filter(|&other| other < count) else {
+            return;
+        };
+        self.state.downloads.swap(index, other);
+        self.state.downloads_table_state.select(Some(other));
+
+        // Take the place its new neighbour has in the client's order.
+        let in_client = self.client.get_all_downloads();
+        let position = |entry: &DownloadEntry| {
+            in_client.iter().position(|download| {
+                download.username == entry.download.username
+                    && download.filename == entry.download.filename
+            })
+        };
+        if let (Some(from), Some(to)) = (
+            position(&self.state.downloads[other]),
+            position(&self.state.downloads[index]),
+        ) {
+            let _ = self.client.reorder_download(in_client[from].token, to);
+        }
+    }
+
     /// Remove all completed / failed / timed-out downloads from the list.
     pub(super) fn clear_finished_downloads(&mut self) {
         self.state.downloads.retain(|entry| {
             !matches!(
                 entry.download.status,
-                DownloadStatus::Completed
+                DownloadStatus::Completed(_)
                     | DownloadStatus::Failed(_)
                     | DownloadStatus::TimedOut
             )
@@ -162,7 +299,7 @@ impl MainTui {
                     bitrate: file.bitrate,
                     length_seconds: file.length_seconds,
                     peer_upload_speed: Some(file.speed),
-                    peer_free_slots: Some(file.slots),
+                    peer_free_slots: Some(u8::from(file.free_slot)),
                 };
                 match client.download_with_metadata(
                     file.filename.clone(),
@@ -218,21 +355,38 @@ impl MainTui {
     pub(super) fn update_downloads(&mut self) {
         if let Some(ref receiver) = self.state.downloads_receiver_channel {
             while let Ok((download, download_receiver)) = receiver.try_recv() {
-                self.state.downloads.push(DownloadEntry {
+                self.state.downloads.push(DownloadEntry::new(
                     download,
-                    receiver: Some(download_receiver),
-                });
+                    Some(download_receiver),
+                ));
             }
         }
 
         self.state.active_downloads_count = 0;
         for download_entry in &mut self.state.downloads {
             if let Some(ref receiver) = download_entry.receiver {
-                while let Ok(status) = receiver.try_recv() {
-                    download_entry.download.status = status;
+                let was_finished = download_entry.download.is_finished();
+                let statuses: Vec<_> = receiver.try_iter().collect();
+                for status in statuses {
+                    download_entry.apply_status(status);
+                }
+                // Only worth a desktop notification when the user isn't
+                // looking at the TUI.
+                if !was_finished
+                    && download_entry.download.is_finished()
+                    && !self.focused
+                {
+                    self.notifications.notify(&download_entry.download);
                 }
             }
 
+            // Held downloads weren't asked for, so have no place yet.
+            if matches!(download_entry.download.status, DownloadStatus::Queued)
+                && !self.client.is_download_held(download_entry.download.token)
+            {
+                poll_queue_position(&self.client, download_entry);
+            }
+
             if matches!(
                 download_entry.download.status,
                 DownloadStatus::InProgress { .. }
@@ -242,3 +396,25 @@ impl MainTui {
         }
     }
 }
+
+/// Refresh a queued download's place in the uploader's queue, asking them
+/// again every [`QUEUE_POLL_INTERVAL`].
+fn poll_queue_position(client: &Client, entry: &mut DownloadEntry) {
+    let download = &mut entry.download;
+    if let Some(place) =
+        client.download_queue_position(&download.username, &download.filename)
+    {
+        download.queue_position = Some(place);
+    }
+    if entry
+        .queue_polled_at
+        .is_none_or(|at| at.elapsed() >= QUEUE_POLL_INTERVAL)
+    {
+        entry.queue_polled_at = Some(Instant::now());
+        if let Err(e) = client
+            .request_queue_position(&download.username, &download.filename)
+        {
+            soulseek_rs::debug!("Failed to ask for queue position: {e}");
+        }
+    }
+}
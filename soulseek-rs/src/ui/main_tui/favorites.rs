@@ -0,0 +1,54 @@
+//! Favorite users: starring them from the results, and badging them in the
+//! shortcuts title while they are online.
+
+use super::MainTui;
+use crate::persist::config::FileConfig;
+use soulseek_rs::UserStatus;
+
+impl MainTui {
+    /// Badge `username`, a favorite who came online.
+    pub(super) fn favorite_online(&mut self, username: String) {
+        if !self.state.favorites_online.contains(&username) {
+            self.state.favorites_online.push(username);
+        }
+    }
+
+    /// Drop the badges of favorites who went offline or stopped being
+    /// favorites.
+    pub(super) fn prune_favorites_online(&mut self) {
+        let client = &self.client;
+        self.state.favorites_online.retain(|username| {
+            client.is_favorite(username)
+                && client.user_info(username).and_then(|info| info.status)
+                    != Some(UserStatus::Offline)
+        });
+    }
+
+    /// Make the highlighted result's user a favorite, or stop them being
+    /// one, and save the change to `favorite_users` in `config.toml`.
+    pub(super) fn toggle_highlighted_favorite(&mut self) {
+        let Some(username) = self.highlighted_result_owner() else {
+            return;
+        };
+        let favorite = !self.client.is_favorite(&username);
+        if favorite {
+            let _ = self.client.add_favorite(&username);
+        } else {
+            let _ = self.client.remove_favorite(&username);
+            self.state.favorites_online.retain(|name| *name != username);
+        }
+
+        if let Some(path) = crate::persist::paths::config_file() {
+            let result = FileConfig::update(&path, |config| {
+                let favorites = config.favorite_users.get_or_insert_default();
+                favorites.retain(|name| *name != username);
+                if favorite {
+                    favorites.push(username);
+                }
+            });
+            if let Err(e) = result {
+                soulseek_rs::warn!("Could not save the favorite: {e}");
+            }
+        }
+    }
+}
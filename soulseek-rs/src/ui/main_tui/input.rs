@@ -1,5 +1,5 @@
 use super::MainTui;
-use crate::models::{CommandBarMode, FocusedPane};
+use crate::models::{Action, CommandBarMode, FocusedPane};
 use ratatui::crossterm::event::{
     KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
 };
@@ -13,7 +13,10 @@ impl MainTui {
 
         // Messages popup: any of i/Esc/q closes it.
         if self.state.show_messages {
-            if matches!(key.code, KeyCode::Char('i' | 'q') | KeyCode::Esc) {
+            if key.code == KeyCode::Esc
+                || self.keymap.pressed(Action::Inbox, &key)
+                || self.keymap.pressed(Action::Quit, &key)
+            {
                 self.state.show_messages = false;
             }
             return;
@@ -41,71 +44,8 @@ impl MainTui {
             return self.handle_filter_input(key);
         }
 
-        // Global shortcuts
-        match key.code {
-            KeyCode::Char('q') => {
-                self.state.should_exit = true;
-                return;
-            }
-            KeyCode::Char('1') => {
-                self.state.focused_pane = FocusedPane::Searches;
-                return;
-            }
-            KeyCode::Char('2') => {
-                self.state.focused_pane = FocusedPane::Results;
-                return;
-            }
-            KeyCode::Char('3') => {
-                self.state.focused_pane = FocusedPane::Downloads;
-                return;
-            }
-            KeyCode::Char('s') => {
-                self.state.command_bar_active = true;
-                self.state.command_bar_mode = CommandBarMode::Search;
-                self.state.command_bar_input.clear();
-                self.state.command_bar_cursor_position = 0;
-                return;
-            }
-            KeyCode::Char('m') => {
-                self.state.command_bar_active = true;
-                self.state.command_bar_mode = CommandBarMode::Message;
-                self.state.command_bar_input.clear();
-                self.state.command_bar_cursor_position = 0;
-                return;
-            }
-            KeyCode::Char('i') => {
-                self.state.show_messages = true;
-                self.state.unread_messages = 0;
-                return;
-            }
-            // Chat rooms. In the Downloads pane `c` clears finished downloads,
-            // so only open chat from the other panes (like `b` is contextual).
-            KeyCode::Char('c')
-                if self.state.focused_pane != FocusedPane::Downloads =>
-            {
-                self.start_rooms();
-                return;
-            }
-            KeyCode::Char('o') => {
-                self.open_settings();
-                return;
-            }
-            KeyCode::Char('b') => {
-                // From a highlighted search result, browse its owner directly;
-                // otherwise prompt for a username.
-                if self.state.focused_pane == FocusedPane::Results
-                    && let Some(owner) = self.highlighted_result_owner()
-                {
-                    self.start_browse(owner);
-                } else {
-                    self.state.command_bar_active = true;
-                    self.state.command_bar_mode = CommandBarMode::Browse;
-                    self.state.command_bar_input.clear();
-                    self.state.command_bar_cursor_position = 0;
-                }
-                return;
-            }
-            _ => {}
+        if self.handle_global_key(key) {
+            return;
         }
 
         // Pane-specific shortcuts
@@ -116,6 +56,60 @@ impl MainTui {
         }
     }
 
+    /// Shortcuts that work from every pane. Returns whether `key` was one.
+    fn handle_global_key(&mut self, key: KeyEvent) -> bool {
+        let pressed = |action| self.keymap.pressed(action, &key);
+        if pressed(Action::Quit) {
+            self.state.should_exit = true;
+        } else if pressed(Action::FocusSearches) {
+            self.state.focused_pane = FocusedPane::Searches;
+        } else if pressed(Action::FocusResults) {
+            self.state.focused_pane = FocusedPane::Results;
+        } else if pressed(Action::FocusDownloads) {
+            self.state.focused_pane = FocusedPane::Downloads;
+        } else if pressed(Action::Search) {
+            self.open_command_bar(CommandBarMode::Search);
+        } else if pressed(Action::Message) {
+            self.open_command_bar(CommandBarMode::Message);
+        } else if pressed(Action::Inbox) {
+            self.state.show_messages = true;
+            self.state.unread_messages = 0;
+        } else if pressed(Action::Chat)
+            // In the Downloads pane `c` clears finished downloads, so that
+            // wins when both are bound to the key (like `b` is contextual).
+            && !(self.state.focused_pane == FocusedPane::Downloads
+                && pressed(Action::ClearFinished))
+        {
+            self.start_rooms();
+        } else if pressed(Action::Settings)
+            // Likewise `o` sorts the Results pane.
+            && !(self.state.focused_pane == FocusedPane::Results
+                && pressed(Action::SortNext))
+        {
+            self.open_settings();
+        } else if pressed(Action::Browse) {
+            // From a highlighted search result, browse its owner directly;
+            // otherwise prompt for a username.
+            if self.state.focused_pane == FocusedPane::Results
+                && let Some(owner) = self.highlighted_result_owner()
+            {
+                self.start_browse(owner);
+            } else {
+                self.open_command_bar(CommandBarMode::Browse);
+            }
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn open_command_bar(&mut self, mode: CommandBarMode) {
+        self.state.command_bar_active = true;
+        self.state.command_bar_mode = mode;
+        self.state.command_bar_input.clear();
+        self.state.command_bar_cursor_position = 0;
+    }
+
     fn handle_command_bar_input(&mut self, key: KeyEvent) {
         self.state.command_bar_cursor_position = self
             .state
@@ -227,10 +221,7 @@ impl MainTui {
             KeyCode::Esc => {
                 self.state.results_is_filtering = false;
                 self.state.results_filter_query.clear();
-                self.state.results_filtered_items =
-                    self.state.results_items.clone();
-                self.state.results_filtered_indices =
-                    (0..self.state.results_items.len()).collect();
+                self.apply_filter();
             }
             KeyCode::Char(c) => {
                 self.state.results_filter_query.push(c);
@@ -246,8 +237,8 @@ impl MainTui {
 
     fn handle_searches_input(&mut self, key: KeyEvent) {
         match key.code {
-            KeyCode::Up | KeyCode::Char('k')
-                if !self.state.searches.is_empty() =>
+            _ if self.keymap.pressed(Action::Up, &key)
+                && !self.state.searches.is_empty() =>
             {
                 let current =
                     self.state.searches_table_state.selected().unwrap_or(0);
@@ -258,8 +249,8 @@ impl MainTui {
                 };
                 self.state.searches_table_state.select(Some(new));
             }
-            KeyCode::Down | KeyCode::Char('j')
-                if !self.state.searches.is_empty() =>
+            _ if self.keymap.pressed(Action::Down, &key)
+                && !self.state.searches.is_empty() =>
             {
                 let current =
                     self.state.searches_table_state.selected().unwrap_or(0);
@@ -273,24 +264,21 @@ impl MainTui {
                     self.state.selected_search_index = Some(selected);
                     if let Some(search) = self.state.searches.get(selected) {
                         self.state.results_items = search.results.clone();
-                        self.state.results_filtered_items =
-                            search.results.clone();
-                        self.state.results_filtered_indices =
-                            (0..search.results.len()).collect();
                         self.state.results_selected_indices.clear();
                         self.state.results_table_state.select(Some(0));
+                        self.apply_filter();
                         self.state.focused_pane = FocusedPane::Results;
                     }
                 }
             }
-            KeyCode::Char('d') => {
+            _ if self.keymap.pressed(Action::RemoveSearch, &key) => {
                 if let Some(selected) =
                     self.state.searches_table_state.selected()
                 {
                     self.remove_search_at_index(selected);
                 }
             }
-            KeyCode::Char('C') => {
+            _ if self.keymap.pressed(Action::ClearSearches, &key) => {
                 self.clear_all_searches();
             }
             _ => {}
@@ -298,14 +286,16 @@ impl MainTui {
     }
 
     fn handle_results_input(&mut self, key: KeyEvent) {
-        let items_count = if self.state.results_filter_query.is_empty() {
-            self.state.results_items.len()
-        } else {
+        let items_count = if self.state.results_grouped {
+            self.state.results_groups.len()
+        } else if self.state.results_filter_active() {
             self.state.results_filtered_items.len()
+        } else {
+            self.state.results_items.len()
         };
 
         match key.code {
-            KeyCode::Up | KeyCode::Char('k') if items_count > 0 => {
+            _ if self.keymap.pressed(Action::Up, &key) && items_count > 0 => {
                 let current =
                     self.state.results_table_state.selected().unwrap_or(0);
                 let new = if current == 0 {
@@ -315,21 +305,25 @@ impl MainTui {
                 };
                 self.state.results_table_state.select(Some(new));
             }
-            KeyCode::Down | KeyCode::Char('j') if items_count > 0 => {
+            _ if self.keymap.pressed(Action::Down, &key) && items_count > 0 => {
                 let current =
                     self.state.results_table_state.selected().unwrap_or(0);
                 let new = (current + 1) % items_count;
                 self.state.results_table_state.select(Some(new));
             }
-            KeyCode::Char(' ') => {
+            _ if self.keymap.pressed(Action::ToggleSelect, &key)
+                && self.state.results_grouped =>
+            {
+                self.toggle_highlighted_group();
+            }
+            _ if self.keymap.pressed(Action::ToggleSelect, &key) => {
                 if let Some(current) = self.state.results_table_state.selected()
                 {
-                    let actual_index =
-                        if self.state.results_filter_query.is_empty() {
-                            current
-                        } else {
-                            self.state.results_filtered_indices[current]
-                        };
+                    let actual_index = if self.state.results_filter_active() {
+                        self.state.results_filtered_indices[current]
+                    } else {
+                        current
+                    };
 
                     if self
                         .state
@@ -346,23 +340,46 @@ impl MainTui {
                     }
                 }
             }
-            KeyCode::Char('/') => {
+            _ if self.keymap.pressed(Action::Filter, &key) => {
                 self.state.results_is_filtering = true;
                 self.state.results_filter_query.clear();
+                self.apply_filter();
             }
-            KeyCode::Char('a') => {
-                let indices: Vec<usize> =
-                    if self.state.results_filter_query.is_empty() {
-                        (0..self.state.results_items.len()).collect()
-                    } else {
-                        self.state.results_filtered_indices.clone()
-                    };
+            _ if self.keymap.pressed(Action::SelectAll, &key) => {
+                let indices: Vec<usize> = if self.state.results_filter_active()
+                {
+                    self.state.results_filtered_indices.clone()
+                } else {
+                    (0..self.state.results_items.len()).collect()
+                };
                 self.state.results_selected_indices.extend(indices);
             }
-            KeyCode::Char('A') => {
+            _ if self.keymap.pressed(Action::SelectNone, &key) => {
                 self.state.results_selected_indices.clear();
             }
+            _ if self.keymap.pressed(Action::QualityFilter, &key) => {
+                self.cycle_quality_filter();
+            }
+            _ if self.keymap.pressed(Action::SortNext, &key) => {
+                self.cycle_results_sort(true);
+            }
+            _ if self.keymap.pressed(Action::SortPrev, &key) => {
+                self.cycle_results_sort(false);
+            }
+            _ if self.keymap.pressed(Action::GroupView, &key) => {
+                self.toggle_results_grouping();
+            }
+            _ if self.keymap.pressed(Action::Favorite, &key) => {
+                self.toggle_highlighted_favorite();
+            }
+            _ if self.keymap.pressed(Action::IgnoreUser, &key) => {
+                self.ignore_highlighted_user();
+            }
             KeyCode::Enter => {
+                // With nothing selected, a group node downloads as a whole.
+                if self.state.results_selected_indices.is_empty() {
+                    self.toggle_highlighted_group();
+                }
                 self.queue_selected_downloads();
             }
             _ => {}
@@ -373,31 +390,45 @@ impl MainTui {
         // The pane lists downloads first, then uploads; navigation spans both.
         let rows = self.state.downloads.len() + self.state.uploads.len();
         match key.code {
-            KeyCode::Up | KeyCode::Char('k') if rows > 0 => {
+            _ if self.keymap.pressed(Action::Up, &key) && rows > 0 => {
                 let current =
                     self.state.downloads_table_state.selected().unwrap_or(0);
                 let new = if current == 0 { rows - 1 } else { current - 1 };
                 self.state.downloads_table_state.select(Some(new));
             }
-            KeyCode::Down | KeyCode::Char('j') if rows > 0 => {
+            _ if self.keymap.pressed(Action::Down, &key) && rows > 0 => {
                 let current =
                     self.state.downloads_table_state.selected().unwrap_or(0);
                 let new = (current + 1) % rows;
                 self.state.downloads_table_state.select(Some(new));
             }
-            KeyCode::Char('x') => {
+            _ if self.keymap.pressed(Action::CancelDownload, &key)
+                && self.selected_download().is_some() =>
+            {
+                self.cancel_selected_download();
+            }
+            _ if self.keymap.pressed(Action::CancelUpload, &key) => {
                 self.cancel_selected_upload();
             }
-            KeyCode::Char('p') => {
+            _ if self.keymap.pressed(Action::Pause, &key) => {
                 self.toggle_selected_download_pause();
             }
-            KeyCode::Char('d') => {
+            _ if self.keymap.pressed(Action::RemoveDownload, &key) => {
                 self.remove_selected_queued_download();
             }
-            KeyCode::Char('r') => {
-                self.retry_selected_download();
+            _ if self.keymap.pressed(Action::MoveDownloadUp, &key) => {
+                self.move_selected_download(true);
+            }
+            _ if self.keymap.pressed(Action::MoveDownloadDown, &key) => {
+                self.move_selected_download(false);
+            }
+            _ if self.keymap.pressed(Action::Retry, &key) => {
+                self.retry_selected_download(false);
+            }
+            _ if self.keymap.pressed(Action::RetryElsewhere, &key) => {
+                self.retry_selected_download(true);
             }
-            KeyCode::Char('c') => {
+            _ if self.keymap.pressed(Action::ClearFinished, &key) => {
                 self.clear_finished_downloads();
             }
             _ => {}
@@ -1,23 +1,33 @@
 mod browse;
 mod downloads;
+mod favorites;
 mod input;
 mod render;
 mod rooms;
 mod search;
 mod settings;
 
-use crate::models::AppState;
+use crate::models::{AppState, Keymap};
+use crate::notifications::Notifications;
 use crate::persist::{
-    snapshot::{Snapshot, restore_searches},
+    snapshot::{
+        Snapshot, capture_search_results, restore_search_results,
+        restore_searches,
+    },
     state::StateStore,
 };
+use crate::saved_search::{SavedSearch, Wishlist};
 use color_eyre::Result;
 use ratatui::{
     DefaultTerminal,
     crossterm::event::{self, Event, KeyEventKind, poll},
 };
-use soulseek_rs::Client;
-use std::{sync::Arc, time::Duration};
+use soulseek_rs::{Client, ClientEvent, UserStatus};
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub struct MainTui {
     client: Arc<Client>,
@@ -30,15 +40,31 @@ pub struct MainTui {
     store: Option<StateStore>,
     /// Last snapshot written to disk, to skip no-op saves.
     saved_snapshot: Snapshot,
+    saved_searches: BTreeMap<String, SavedSearch>,
+    wishlist: Wishlist,
+    keymap: Keymap,
+    /// Idle time after which we show as away; `None` never does.
+    auto_away: Option<Duration>,
+    last_input: Instant,
+    away: bool,
+    notifications: Notifications,
+    /// Whether the terminal has focus, as far as it reports; assumed until
+    /// told otherwise.
+    focused: bool,
 }
 
 impl MainTui {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         client: Arc<Client>,
         download_dir: String,
         max_concurrent_downloads: usize,
         search_timeout: Duration,
         store: Option<StateStore>,
+        saved_searches: BTreeMap<String, SavedSearch>,
+        keymap: Keymap,
+        auto_away: Option<Duration>,
+        notifications: Notifications,
     ) -> Self {
         let mut tui = Self {
             client,
@@ -49,18 +75,31 @@ impl MainTui {
             spinner_state: 0,
             store,
             saved_snapshot: Snapshot::default(),
+            saved_searches,
+            wishlist: Wishlist::default(),
+            keymap,
+            auto_away,
+            last_input: Instant::now(),
+            away: false,
+            notifications,
+            focused: true,
         };
         tui.restore_persisted_state();
+        // Shown in the shortcuts title once the server answers.
+        if let Err(e) = tui.client.request_privileges() {
+            soulseek_rs::warn!("Could not check privileges: {e}");
+        }
         tui
     }
 
-    /// Bring back last session's state: search history, chat rooms
-    /// (rejoined on the server), and downloads — incomplete ones are
+    /// Bring back last session's state: search history and results, chat
+    /// rooms (rejoined on the server), and downloads — incomplete ones are
     /// re-enqueued so they resume automatically.
     fn restore_persisted_state(&mut self) {
         let Some(store) = &self.store else { return };
 
         restore_searches(&mut self.state, &store.load_search_queries());
+        restore_search_results(&mut self.state, &store.load_search_results());
 
         for room in store.load_rooms() {
             if self.state.rooms.focus_or_open(&room)
@@ -79,21 +118,21 @@ impl MainTui {
         let sender = self.downloads_sender();
         for entry in downloads {
             if entry.completed {
-                self.state.downloads.push(crate::models::DownloadEntry {
-                    download: soulseek_rs::types::Download {
+                self.state.downloads.push(crate::models::DownloadEntry::new(
+                    soulseek_rs::types::Download {
                         username: entry.username,
                         filename: entry.filename,
                         token: 0,
                         size: entry.size,
                         download_directory: entry.download_directory,
-                        status: soulseek_rs::DownloadStatus::Completed,
+                        status: soulseek_rs::DownloadStatus::Completed(None),
                         sender: std::sync::mpsc::channel().0,
                         queue_position: None,
                         metadata: soulseek_rs::types::DownloadMetadata::default(
                         ),
                     },
-                    receiver: None,
-                });
+                    None,
+                ));
             } else {
                 let client = self.client.clone();
                 let sender = sender.clone();
@@ -143,7 +182,10 @@ impl MainTui {
     }
 
     pub fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
-        use ratatui::crossterm::{event::DisableMouseCapture, execute};
+        use ratatui::crossterm::{
+            event::{DisableFocusChange, DisableMouseCapture},
+            execute,
+        };
 
         // Run the event loop, then restore the terminal unconditionally: if the
         // loop returns early with an error the terminal must still be taken out
@@ -151,8 +193,18 @@ impl MainTui {
         // user is left with a corrupted terminal.
         let result = self.run_event_loop(&mut terminal);
         self.save_persisted_state();
+        if let Some(store) = &self.store
+            && let Err(e) =
+                store.save_search_results(&capture_search_results(&self.state))
+        {
+            soulseek_rs::warn!("Could not save search results: {e}");
+        }
 
-        let _ = execute!(std::io::stdout(), DisableMouseCapture);
+        let _ = execute!(
+            std::io::stdout(),
+            DisableMouseCapture,
+            DisableFocusChange
+        );
         ratatui::restore();
         soulseek_rs::utils::logger::disable_buffering();
 
@@ -163,7 +215,8 @@ impl MainTui {
         while !self.state.should_exit {
             terminal.draw(|frame| self.render(frame))?;
 
-            // Poll for search results updates
+            // Re-run saved searches that are due, then poll for results
+            self.refresh_saved_searches();
             self.update_search_results();
 
             // Poll for download updates
@@ -178,6 +231,9 @@ impl MainTui {
             // Poll for chat-room events
             self.poll_room_events();
 
+            // Track favorites coming online and downloads changing source
+            self.poll_client_events();
+
             // Refresh the uploads we are serving to peers
             self.state.uploads = self.client.uploads();
 
@@ -185,6 +241,8 @@ impl MainTui {
 
             self.save_persisted_state();
 
+            self.update_away();
+
             // Drain every queued input event before the next draw: key
             // autorepeat outpaces the frame time, and handling one event per
             // frame makes the backlog keep scrolling for seconds after the
@@ -193,11 +251,15 @@ impl MainTui {
                 loop {
                     match event::read()? {
                         Event::Key(key) if key.kind == KeyEventKind::Press => {
+                            self.mark_input();
                             self.handle_key_event(key);
                         }
                         Event::Mouse(mouse) => {
+                            self.mark_input();
                             self.handle_mouse_event(mouse);
                         }
+                        Event::FocusGained => self.focused = true,
+                        Event::FocusLost => self.focused = false,
                         _ => {}
                     }
                     if self.state.should_exit || !poll(Duration::ZERO)? {
@@ -209,8 +271,58 @@ impl MainTui {
 
         Ok(())
     }
+
+    /// Drain the client's events: favorites coming online and downloads
+    /// moved to another uploader.
+    fn poll_client_events(&mut self) {
+        for event in self.client.take_events() {
+            match event {
+                ClientEvent::FavoriteOnline(username) => {
+                    self.favorite_online(username);
+                }
+                ClientEvent::SourceReplaced {
+                    username,
+                    filename,
+                    new_username,
+                    new_filename,
+                } => self.download_source_replaced(
+                    &username,
+                    &filename,
+                    new_username,
+                    new_filename,
+                ),
+                _ => {}
+            }
+        }
+        self.prune_favorites_online();
+    }
+
+    /// Show us as away once there has been no input for `auto_away`, as
+    /// other clients do.
+    fn update_away(&mut self) {
+        let Some(idle) = self.auto_away else { return };
+        if !self.away && self.last_input.elapsed() >= idle {
+            self.set_status(UserStatus::Away);
+        }
+    }
+
+    /// Note user input, coming back online if we were away.
+    fn mark_input(&mut self) {
+        self.last_input = Instant::now();
+        if self.away {
+            self.set_status(UserStatus::Online);
+        }
+    }
+
+    fn set_status(&mut self, status: UserStatus) {
+        match self.client.set_status(status) {
+            Ok(()) => self.away = status == UserStatus::Away,
+            Err(e) => soulseek_rs::warn!("Could not set status: {e}"),
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn launch_main_tui(
     terminal: DefaultTerminal,
     client: Arc<Client>,
@@ -218,6 +330,10 @@ pub fn launch_main_tui(
     max_concurrent_downloads: usize,
     search_timeout: Duration,
     store: Option<StateStore>,
+    saved_searches: BTreeMap<String, SavedSearch>,
+    keymap: Keymap,
+    auto_away: Option<Duration>,
+    notifications: Notifications,
 ) -> Result<()> {
     let tui = MainTui::new(
         client,
@@ -225,6 +341,10 @@ pub fn launch_main_tui(
         max_concurrent_downloads,
         search_timeout,
         store,
+        saved_searches,
+        keymap,
+        auto_away,
+        notifications,
     );
     tui.run(terminal)
 }
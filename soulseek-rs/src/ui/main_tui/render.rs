@@ -1,18 +1,23 @@
 use super::MainTui;
-use crate::models::{CommandBarMode, FocusedPane, MessageDirection, RoomsView};
+use crate::models::{
+    Action, CommandBarMode, FocusedPane, MessageDirection, QualityFilter,
+    RoomsView,
+};
 use crate::ui::panes::{
     ResultsPaneParams, render_browse_pane, render_download_info_pane,
     render_downloads_pane, render_results_pane, render_rooms_pane,
     render_searches_pane,
 };
 use crate::ui::{
-    border_style, border_type, format_shortcuts_styled, render_download_stats,
+    border_style, border_type, format_shortcuts_styled, format_time_left,
+    render_download_stats,
 };
 use ratatui::{
     Frame,
     layout::{Constraint, Layout, Position, Rect},
     widgets::{Block, Borders, Paragraph},
 };
+use std::fmt::Write;
 
 const COMMAND_BAR_PREFIX: &str = "search: ";
 const MESSAGE_BAR_PREFIX: &str = "message (to: recipient text): ";
@@ -100,13 +105,13 @@ impl MainTui {
         // subset, so the pane also needs the mapping back to unfiltered indices
         // to render the selection checkboxes correctly.
         let (results_items, results_original_indices) =
-            if self.state.results_filter_query.is_empty() {
-                (&self.state.results_items, None)
-            } else {
+            if self.state.results_filter_active() {
                 (
                     &self.state.results_filtered_items,
                     Some(self.state.results_filtered_indices.as_slice()),
                 )
+            } else {
+                (&self.state.results_items, None)
             };
 
         let active_search_query = self
@@ -115,6 +120,7 @@ impl MainTui {
             .and_then(|idx| self.state.searches.get(idx))
             .map(|search| search.query.as_str());
 
+        let client = self.client.clone();
         render_results_pane(
             frame,
             right_chunks[0],
@@ -123,10 +129,23 @@ impl MainTui {
                 table_state: &mut self.state.results_table_state,
                 selected_indices: &self.state.results_selected_indices,
                 original_indices: results_original_indices,
+                groups: self
+                    .state
+                    .results_grouped
+                    .then_some(self.state.results_groups.as_slice()),
                 filter_query: &self.state.results_filter_query,
                 is_filtering: self.state.results_is_filtering,
+                quality_filter: self.state.results_quality_filter,
+                sort: self.state.results_sort,
+                quality_counts: QualityFilter::counts(
+                    &self.state.results_items,
+                ),
                 focused: self.state.focused_pane == FocusedPane::Results,
                 active_search_query,
+                downloaded: &|filename, size| {
+                    client.has_downloaded(filename, size)
+                },
+                favorite: &|username| client.is_favorite(username),
             },
         );
 
@@ -287,41 +306,58 @@ impl MainTui {
         frame.render_widget(popup, area);
     }
 
-    /// Context shortcuts for the chat-rooms popup.
-    fn rooms_shortcuts(&self) -> Vec<(&'static str, &'static str)> {
+    /// Context shortcuts for the chat pane.
+    fn rooms_shortcuts(&self) -> Vec<(String, &'static str)> {
+        let key = |action| self.keymap.label(action);
+        let fixed = |key: &str| key.to_string();
         if self.state.rooms.composing {
             return vec![
-                ("Type", "message"),
-                ("Enter", "send"),
-                ("Esc", "cancel"),
+                (fixed("Type"), "message"),
+                (fixed("Enter"), "send"),
+                (fixed("Esc"), "cancel"),
             ];
         }
+        let move_keys = format!("{}{}", key(Action::Up), key(Action::Down));
         match self.state.rooms.view {
             RoomsView::List => {
                 if self.state.rooms.list_is_filtering {
                     vec![
-                        ("Type", "filter"),
-                        ("Enter", "join match"),
-                        ("Esc", "clear filter"),
+                        (fixed("Type"), "filter"),
+                        (fixed("Enter"), "join match"),
+                        (fixed("Esc"), "clear filter"),
                     ]
                 } else {
                     vec![
-                        ("↑↓", "move"),
-                        ("Enter", "join"),
-                        ("/", "filter"),
-                        ("Tab", "open rooms"),
-                        ("Esc", "close"),
+                        (move_keys, "move"),
+                        (fixed("Enter"), "join"),
+                        (key(Action::Filter), "filter"),
+                        (fixed("Tab"), "open rooms"),
+                        (fixed("Esc"), "close"),
                     ]
                 }
             }
+            RoomsView::Chat
+                if self
+                    .state
+                    .rooms
+                    .active_room()
+                    .is_some_and(|r| r.private) =>
+            {
+                vec![
+                    (fixed("Enter"), "say"),
+                    (fixed("Tab"), "switch tab"),
+                    (key(Action::RoomList), "rooms"),
+                    (key(Action::LeaveRoom), "close"),
+                ]
+            }
             RoomsView::Chat => vec![
-                ("Enter", "say"),
-                ("↑↓", "pick user"),
-                ("b", "browse user"),
-                ("m", "message user"),
-                ("Tab", "switch room"),
-                ("l", "rooms"),
-                ("x", "leave"),
+                (fixed("Enter"), "say"),
+                (move_keys, "pick user"),
+                (key(Action::Browse), "browse user"),
+                (key(Action::Message), "message user"),
+                (fixed("Tab"), "switch tab"),
+                (key(Action::RoomList), "rooms"),
+                (key(Action::LeaveRoom), "leave"),
             ],
         }
     }
@@ -340,88 +376,138 @@ impl MainTui {
             "chat".to_string()
         };
 
-        let shortcuts = if self.state.settings.is_some() {
+        let key = |action| self.keymap.label(action);
+        let fixed = |key: &str| key.to_string();
+        let move_keys = format!("{}{}", key(Action::Up), key(Action::Down));
+        let focus_keys = format!(
+            "{}-{}",
+            key(Action::FocusSearches),
+            key(Action::FocusDownloads)
+        );
+        let chat_keys = self.keymap.labels(Action::Chat, None);
+        let quit_key = key(Action::Quit);
+
+        let shortcuts: Vec<(String, &str)> = if self.state.settings.is_some() {
             vec![
-                ("↑↓", "move"),
-                ("Enter/e", "edit download dir"),
-                ("a", "add share"),
-                ("d", "remove share"),
-                ("r", "re-index"),
-                ("Esc", "close"),
+                (fixed("↑↓"), "move"),
+                (fixed("Enter/e"), "edit download dir"),
+                (fixed("a"), "add share"),
+                (fixed("d"), "remove share"),
+                (fixed("r"), "re-index"),
+                (fixed("Esc"), "close"),
             ]
         } else if self.state.show_rooms {
             self.rooms_shortcuts()
         } else if self.state.show_browse {
             vec![
-                ("↑↓", "move"),
-                ("→←", "expand/collapse"),
-                ("Enter", "open/download"),
-                ("d", "download folder"),
-                ("Tab", "switch user"),
-                ("r", "retry"),
-                ("w", "close tab"),
-                ("Esc", "hide"),
+                (move_keys, "move"),
+                (
+                    format!("{}{}", key(Action::Right), key(Action::Left)),
+                    "expand/collapse",
+                ),
+                (fixed("Enter"), "open/download"),
+                (key(Action::DownloadFolder), "download folder"),
+                (fixed("Tab"), "switch user"),
+                (key(Action::Retry), "retry"),
+                (key(Action::CloseTab), "close tab"),
+                (fixed("Esc"), "hide"),
             ]
         } else if self.state.command_bar_active {
             match self.state.command_bar_mode {
                 CommandBarMode::Search => vec![
-                    ("Type", "search term"),
-                    ("←→", "move cursor"),
-                    ("Backspace/Del", "edit"),
-                    ("Enter", "search"),
-                    ("Esc", "cancel"),
+                    (fixed("Type"), "search term"),
+                    (fixed("←→"), "move cursor"),
+                    (fixed("Backspace/Del"), "edit"),
+                    (fixed("Enter"), "search"),
+                    (fixed("Esc"), "cancel"),
                 ],
                 CommandBarMode::Message => vec![
-                    ("Type", "recipient then message"),
-                    ("Enter", "send"),
-                    ("Esc", "cancel"),
+                    (fixed("Type"), "recipient then message"),
+                    (fixed("Enter"), "send"),
+                    (fixed("Esc"), "cancel"),
                 ],
                 CommandBarMode::Browse => vec![
-                    ("Type", "username"),
-                    ("Enter", "browse"),
-                    ("Esc", "cancel"),
+                    (fixed("Type"), "username"),
+                    (fixed("Enter"), "browse"),
+                    (fixed("Esc"), "cancel"),
                 ],
             }
         } else {
             match self.state.focused_pane {
                 FocusedPane::Searches => vec![
-                    ("s", "search"),
-                    ("m", "message"),
-                    ("i", inbox_label.as_str()),
-                    ("c", chat_label.as_str()),
-                    ("b", "browse user"),
-                    ("1-3", "focus pane"),
-                    ("↑↓", "navigate"),
-                    ("Enter", "results"),
-                    ("q", "quit"),
+                    (key(Action::Search), "search"),
+                    (key(Action::Message), "message"),
+                    (key(Action::Inbox), inbox_label.as_str()),
+                    (chat_keys, chat_label.as_str()),
+                    (key(Action::Browse), "browse user"),
+                    (focus_keys, "focus pane"),
+                    (move_keys, "navigate"),
+                    (fixed("Enter"), "results"),
+                    (quit_key, "quit"),
                 ],
                 FocusedPane::Results if self.state.results_is_filtering => {
                     vec![
-                        ("Type", "filter"),
-                        ("Esc", "clear filter"),
-                        ("1-3", "focus pane"),
-                        ("q", "quit"),
+                        (fixed("Type"), "filter"),
+                        (fixed("Esc"), "clear filter"),
+                        (focus_keys, "focus pane"),
+                        (quit_key, "quit"),
                     ]
                 }
                 FocusedPane::Results => vec![
-                    ("Space", "select"),
-                    ("Enter", "download"),
-                    ("b", "browse owner"),
-                    ("c", chat_label.as_str()),
-                    ("/", "filter"),
-                    ("a/A", "select all/none"),
-                    ("1-3", "focus pane"),
-                    ("q", "quit"),
+                    (key(Action::ToggleSelect), "select"),
+                    (fixed("Enter"), "download"),
+                    (key(Action::Browse), "browse owner"),
+                    (chat_keys, chat_label.as_str()),
+                    (key(Action::Filter), "filter"),
+                    (
+                        format!(
+                            "{}/{}",
+                            key(Action::SelectAll),
+                            key(Action::SelectNone)
+                        ),
+                        "select all/none",
+                    ),
+                    (key(Action::QualityFilter), "quality"),
+                    (
+                        format!(
+                            "{}/{}",
+                            key(Action::SortNext),
+                            key(Action::SortPrev)
+                        ),
+                        "sort",
+                    ),
+                    (key(Action::GroupView), "group"),
+                    (key(Action::Favorite), "favorite user"),
+                    (key(Action::IgnoreUser), "ignore user"),
+                    (focus_keys, "focus pane"),
+                    (quit_key, "quit"),
                 ],
                 FocusedPane::Downloads => {
                     vec![
-                        ("p", "pause/resume"),
-                        ("r", "retry failed"),
-                        ("d", "delete queued"),
-                        ("c", "clear finished"),
-                        ("b", "browse user"),
-                        ("1-3", "focus pane"),
-                        ("q", "quit"),
+                        (key(Action::Pause), "pause/resume"),
+                        (key(Action::CancelDownload), "cancel"),
+                        (key(Action::Retry), "retry failed"),
+                        (key(Action::RetryElsewhere), "retry elsewhere"),
+                        (key(Action::RemoveDownload), "delete queued"),
+                        (
+                            format!(
+                                "{}/{}",
+                                key(Action::MoveDownloadUp),
+                                key(Action::MoveDownloadDown)
+                            ),
+                            "move up/down",
+                        ),
+                        (key(Action::ClearFinished), "clear finished"),
+                        (key(Action::Browse), "browse user"),
+                        (
+                            self.keymap.labels(
+                                Action::Chat,
+                                Some(Action::ClearFinished),
+                            ),
+                            chat_label.as_str(),
+                        ),
+                        (focus_keys, "focus pane"),
+                        (quit_key, "quit"),
                     ]
                 }
             }
@@ -435,7 +521,25 @@ impl MainTui {
             [only] => only.clone(),
             more => format!("{} folders", more.len()),
         };
-        let title = format!("Shortcuts · Sharing: {sharing}");
+        let mut title = match self.client.privileges_left() {
+            Some(left) if !left.is_zero() => format!(
+                "Shortcuts · Sharing: {sharing} · Privileged: {}",
+                format_time_left(left)
+            ),
+            _ => format!("Shortcuts · Sharing: {sharing}"),
+        };
+        if self.away {
+            title.push_str(" · Away");
+        }
+        match self.state.favorites_online.as_slice() {
+            [] => {}
+            [one] => {
+                let _ = write!(title, " · ★ {one} online");
+            }
+            more => {
+                let _ = write!(title, " · ★ {} favorites online", more.len());
+            }
+        }
         let shortcuts_widget = Paragraph::new(shortcuts_line).block(
             Block::default()
                 .borders(Borders::ALL)
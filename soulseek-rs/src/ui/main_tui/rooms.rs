@@ -1,10 +1,12 @@
 use super::MainTui;
-use crate::models::{CommandBarMode, RoomsView};
+use crate::models::{Action, RoomsView};
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use soulseek_rs::types::RoomEvent;
 
 impl MainTui {
-    /// Open the chat-rooms popup and refresh the room list. If rooms are
-    /// already open, jump straight to the chat view; otherwise show the list.
+    /// Open the chat pane and refresh the room list. If rooms or
+    /// conversations are open, jump straight to the chat view; otherwise show
+    /// the room list.
     pub(super) fn start_rooms(&mut self) {
         let _ = self.client.request_room_list();
         self.state.show_rooms = true;
@@ -45,17 +47,18 @@ impl MainTui {
                 self.state.rooms.list_filter.clear();
                 self.state.rooms.list_selected = 0;
             }
-            KeyCode::Esc | KeyCode::Char('q') => {
+            KeyCode::Esc => self.state.show_rooms = false,
+            _ if self.keymap.pressed(Action::Quit, &key) => {
                 self.state.show_rooms = false;
             }
-            KeyCode::Char('/') => {
+            _ if self.keymap.pressed(Action::Filter, &key) => {
                 self.state.rooms.list_is_filtering = true;
             }
-            KeyCode::Up | KeyCode::Char('k') => {
+            _ if self.keymap.pressed(Action::Up, &key) => {
                 self.state.rooms.list_selected =
                     self.state.rooms.list_selected.saturating_sub(1);
             }
-            KeyCode::Down | KeyCode::Char('j') if len > 0 => {
+            _ if self.keymap.pressed(Action::Down, &key) && len > 0 => {
                 self.state.rooms.list_selected =
                     (self.state.rooms.list_selected + 1).min(len - 1);
             }
@@ -93,22 +96,31 @@ impl MainTui {
 
     fn handle_rooms_chat_input(&mut self, key: KeyEvent) {
         match key.code {
-            KeyCode::Char('q') => self.state.show_rooms = false,
-            KeyCode::Esc | KeyCode::Char('l') => {
-                self.state.rooms.view = RoomsView::List;
-            }
+            KeyCode::Esc => self.state.rooms.view = RoomsView::List,
             KeyCode::Tab => self.state.rooms.next_tab(),
             KeyCode::BackTab => self.state.rooms.prev_tab(),
-            KeyCode::Char('x') => self.leave_active_room(),
-            KeyCode::Up | KeyCode::Char('k') => {
+            _ if self.keymap.pressed(Action::Quit, &key) => {
+                self.state.show_rooms = false;
+            }
+            _ if self.keymap.pressed(Action::RoomList, &key) => {
+                self.state.rooms.view = RoomsView::List;
+            }
+            _ if self.keymap.pressed(Action::LeaveRoom, &key) => {
+                self.leave_active_room();
+            }
+            _ if self.keymap.pressed(Action::Up, &key) => {
                 self.state.rooms.select_user_up();
             }
-            KeyCode::Down | KeyCode::Char('j') => {
+            _ if self.keymap.pressed(Action::Down, &key) => {
                 self.state.rooms.select_user_down();
             }
             // Act on the highlighted member of the room.
-            KeyCode::Char('b') => self.browse_selected_room_user(),
-            KeyCode::Char('m') => self.message_selected_room_user(),
+            _ if self.keymap.pressed(Action::Browse, &key) => {
+                self.browse_selected_room_user();
+            }
+            _ if self.keymap.pressed(Action::Message, &key) => {
+                self.message_selected_room_user();
+            }
             KeyCode::Enter if self.state.rooms.active_room().is_some() => {
                 self.state.rooms.composing = true;
             }
@@ -125,17 +137,12 @@ impl MainTui {
         }
     }
 
-    /// Compose a private message to the member highlighted in the active room.
-    /// Closes the rooms popup and opens the message command bar pre-filled with
-    /// the recipient.
+    /// Open a private conversation with the member highlighted in the active
+    /// room, ready to type.
     fn message_selected_room_user(&mut self) {
         if let Some(user) = self.state.rooms.selected_user() {
-            self.state.show_rooms = false;
-            self.state.command_bar_active = true;
-            self.state.command_bar_mode = CommandBarMode::Message;
-            self.state.command_bar_input = format!("{user} ");
-            self.state.command_bar_cursor_position =
-                self.state.command_bar_input.len();
+            self.state.rooms.focus_or_open_conversation(&user);
+            self.state.rooms.composing = true;
         }
     }
 
@@ -182,9 +189,12 @@ impl MainTui {
         }
     }
 
-    /// Leave the active room and close its tab.
+    /// Leave the active room and close its tab. A conversation's tab just
+    /// closes.
     fn leave_active_room(&mut self) {
+        let private = self.state.rooms.active_room().is_some_and(|r| r.private);
         if let Some(name) = self.state.rooms.close_active()
+            && !private
             && let Err(e) = self.client.leave_room(&name)
         {
             soulseek_rs::warn!("Failed to leave {name}: {e}");
@@ -192,19 +202,24 @@ impl MainTui {
     }
 
     /// Send the active room's compose buffer. The server echoes the message
-    /// back as a RoomEvent, which is what actually renders it in the log.
+    /// back as a RoomEvent, which is what actually renders it in the log. A
+    /// private message isn't echoed, so it is logged on sending.
     fn send_room_message(&mut self) {
         let active = self.state.rooms.active;
-        let (room, text) = match self.state.rooms.open.get(active) {
-            Some(room) if !room.input.trim().is_empty() => {
-                (room.name.clone(), room.input.trim().to_string())
-            }
+        let (room, private, text) = match self.state.rooms.open.get(active) {
+            Some(room) if !room.input.trim().is_empty() => (
+                room.name.clone(),
+                room.private,
+                room.input.trim().to_string(),
+            ),
             _ => {
                 self.state.rooms.composing = false;
                 return;
             }
         };
-        if let Err(e) = self.client.say_in_room(&room, &text) {
+        if private {
+            self.send_private_message(&room, &text);
+        } else if let Err(e) = self.client.say_in_room(&room, &text) {
             soulseek_rs::warn!("Failed to say in {room}: {e}");
         }
         if let Some(room) = self.state.rooms.open.get_mut(active) {
@@ -214,15 +229,25 @@ impl MainTui {
     }
 
     /// Drain chat-room events into the rooms state, tracking unread badges.
+    /// Ignored users' messages are left out.
     pub(super) fn poll_room_events(&mut self) {
         let viewing = if self.state.show_rooms
             && self.state.rooms.view == RoomsView::Chat
         {
-            self.state.rooms.active_room().map(|r| r.name.clone())
+            self.state
+                .rooms
+                .active_room()
+                .filter(|r| !r.private)
+                .map(|r| r.name.clone())
         } else {
             None
         };
         for event in self.client.take_room_events() {
+            if let RoomEvent::Message { username, .. } = &event
+                && self.client.is_ignored(username)
+            {
+                continue;
+            }
             self.state.rooms.apply_event(event, viewing.as_deref());
         }
     }
@@ -1,8 +1,10 @@
 use super::MainTui;
 use crate::models::{
-    ChatMessage, FileDisplayData, FocusedPane, MessageDirection, SearchEntry,
-    SearchStatus,
+    ChatMessage, FileDisplayData, FocusedPane, MessageDirection, QualityFilter,
+    ResultGroup, ResultsSort, SearchEntry, SearchStatus, group_results,
 };
+use crate::persist::config::FileConfig;
+use crate::saved_search::SavedSearch;
 use std::{
     sync::{Arc, atomic::AtomicBool},
     thread,
@@ -76,6 +78,7 @@ impl MainTui {
         self.state.results_items.clear();
         self.state.results_filtered_items.clear();
         self.state.results_filtered_indices.clear();
+        self.state.results_groups.clear();
         self.state.results_selected_indices.clear();
         self.state.results_table_state.select(None);
         self.state.results_filter_query.clear();
@@ -86,9 +89,97 @@ impl MainTui {
         let (items, indices) = filter_results(
             &self.state.results_items,
             &self.state.results_filter_query,
+            self.state.results_quality_filter,
+            self.state.results_sort,
         );
         self.state.results_filtered_items = items;
         self.state.results_filtered_indices = indices;
+        self.state.results_groups = if self.state.results_grouped {
+            group_results(
+                &self.state.results_items,
+                &self.state.results_filtered_indices,
+            )
+        } else {
+            Vec::new()
+        };
+    }
+
+    /// The group node under the highlight, in the grouped view.
+    pub(super) fn highlighted_group(&self) -> Option<&ResultGroup> {
+        if !self.state.results_grouped {
+            return None;
+        }
+        let row = self.state.results_table_state.selected()?;
+        self.state.results_groups.get(row)
+    }
+
+    /// Switch between listing files and listing user and folder nodes.
+    pub(super) fn toggle_results_grouping(&mut self) {
+        self.state.results_grouped = !self.state.results_grouped;
+        self.apply_filter();
+    }
+
+    /// Select every file of the highlighted node, or deselect them if they
+    /// all are already.
+    pub(super) fn toggle_highlighted_group(&mut self) {
+        let Some(group) = self.highlighted_group() else {
+            return;
+        };
+        let files = group.files.clone();
+        let selected = &mut self.state.results_selected_indices;
+        if files.iter().all(|idx| selected.contains(idx)) {
+            for idx in &files {
+                selected.remove(idx);
+            }
+        } else {
+            selected.extend(files);
+        }
+    }
+
+    /// Step the sort order forwards (`forward`) or backwards, keeping the
+    /// highlighted result highlighted wherever it lands.
+    pub(super) fn cycle_results_sort(&mut self, forward: bool) {
+        let highlighted_group = self.highlighted_group().cloned();
+        let highlighted =
+            self.state.results_table_state.selected().and_then(|row| {
+                if self.state.results_filter_active() {
+                    self.state.results_filtered_indices.get(row).copied()
+                } else {
+                    Some(row)
+                }
+            });
+        let sort = self.state.results_sort;
+        self.state.results_sort =
+            if forward { sort.next() } else { sort.prev() };
+        self.recompute_results_filter();
+        // Selected results are tracked by their arrival index, which a
+        // re-sort doesn't change; only the highlight needs following.
+        let row = highlighted.and_then(|original| {
+            if self.state.results_filter_active() {
+                self.state
+                    .results_filtered_indices
+                    .iter()
+                    .position(|&idx| idx == original)
+            } else {
+                Some(original)
+            }
+        });
+        let row = match highlighted_group {
+            Some(group) => self
+                .state
+                .results_groups
+                .iter()
+                .position(|other| other.same_node(&group)),
+            None => row,
+        };
+        self.state.results_table_state.select(row.or(Some(0)));
+    }
+
+    /// Step the quick quality filter (all → lossless → 320+ → V0+).
+    pub(super) fn cycle_quality_filter(&mut self) {
+        self.state.results_quality_filter =
+            self.state.results_quality_filter.next();
+        self.apply_filter();
     }
 
     pub(super) fn apply_filter(&mut self) {
@@ -98,12 +189,55 @@ impl MainTui {
         }
     }
 
-    /// Drain any private messages received since the last tick into the inbox.
+    /// Ignore the highlighted result's user: drop their results from every
+    /// search and add them to `ignored_users` in `config.toml`.
+    pub(super) fn ignore_highlighted_user(&mut self) {
+        let Some(username) = self.highlighted_result_owner() else {
+            return;
+        };
+        let _ = self.client.ignore_user(&username);
+        for search in &mut self.state.searches {
+            search.results.retain(|file| file.username != username);
+        }
+        self.state
+            .results_items
+            .retain(|file| file.username != username);
+        // Selections are indices into the results just shortened.
+        self.state.results_selected_indices.clear();
+        self.apply_filter();
+
+        if let Some(path) = crate::persist::paths::config_file() {
+            let result = FileConfig::update(&path, |config| {
+                let ignored = config.ignored_users.get_or_insert_default();
+                if !ignored.contains(&username) {
+                    ignored.push(username);
+                }
+            });
+            if let Err(e) = result {
+                soulseek_rs::warn!("Could not save the ignored user: {e}");
+            }
+        }
+    }
+
+    /// Drain any private messages received since the last tick into the
+    /// inbox, leaving out ignored users'.
     pub(super) fn poll_private_messages(&mut self) {
         for msg in self.client.take_private_messages() {
+            if self.client.is_ignored(msg.username()) {
+                continue;
+            }
+            let peer = msg.username().to_string();
+            let viewing = self.state.show_rooms
+                && self.state.rooms.is_active_conversation(&peer);
+            self.state.rooms.push_private_message(
+                &peer,
+                peer.clone(),
+                msg.message().to_string(),
+                !viewing,
+            );
             self.state.messages.push(ChatMessage {
                 direction: MessageDirection::Incoming,
-                peer: msg.username().to_string(),
+                peer,
                 text: msg.message().to_string(),
             });
             // Badge the inbox when it isn't currently open.
@@ -125,17 +259,74 @@ impl MainTui {
             return;
         }
 
+        self.send_private_message(recipient, text);
+    }
+
+    /// Send `text` to `recipient`, logging it in the inbox and in their
+    /// conversation tab.
+    pub(super) fn send_private_message(&mut self, recipient: &str, text: &str) {
         match self.client.send_private_message(recipient, text) {
-            Ok(()) => self.state.messages.push(ChatMessage {
-                direction: MessageDirection::Outgoing,
-                peer: recipient.to_string(),
-                text: text.to_string(),
-            }),
+            Ok(()) => {
+                let me = self.client.username().to_string();
+                self.state.rooms.push_private_message(
+                    recipient,
+                    me,
+                    text.to_string(),
+                    false,
+                );
+                self.state.messages.push(ChatMessage {
+                    direction: MessageDirection::Outgoing,
+                    peer: recipient.to_string(),
+                    text: text.to_string(),
+                });
+            }
             Err(e) => soulseek_rs::warn!("Failed to send message: {e}"),
         }
     }
 
+    /// Start a search from the search bar. `@name` runs the saved search
+    /// `name` instead; an unknown name is searched for literally.
     pub(super) fn start_search(&mut self, query: String) {
+        if let Some(name) = query.strip_prefix('@')
+            && self.saved_searches.contains_key(name)
+        {
+            let name = name.to_string();
+            self.run_saved_search(&name, true);
+            return;
+        }
+
+        let search_index = self.launch_search(query, None);
+        self.focus_search(search_index);
+    }
+
+    /// Run the saved search `name`. A background (wishlist) run leaves the
+    /// selection and focus alone.
+    pub(super) fn run_saved_search(&mut self, name: &str, focus: bool) {
+        let Some(saved) = self.saved_searches.get(name).cloned() else {
+            return;
+        };
+        self.wishlist.mark_run(name, Instant::now());
+        let search_index = self.launch_search(saved.query.clone(), Some(saved));
+        if focus {
+            self.focus_search(search_index);
+        }
+    }
+
+    /// Run every saved search the wishlist says is due for a refresh.
+    pub(super) fn refresh_saved_searches(&mut self) {
+        for name in self.wishlist.due(&self.saved_searches, Instant::now()) {
+            self.run_saved_search(&name, false);
+        }
+    }
+
+    /// Spawn the search and return its index in the searches list. A saved
+    /// search that is already listed is re-run in place rather than added
+    /// again.
+    fn launch_search(
+        &mut self,
+        query: String,
+        saved: Option<SavedSearch>,
+    ) -> usize {
         let cancel_flag = Arc::new(AtomicBool::new(false));
         let search_entry = SearchEntry {
             query: query.clone(),
@@ -143,24 +334,34 @@ impl MainTui {
             results: Vec::new(),
             start_time: Instant::now(),
             cancel_flag: cancel_flag.clone(),
+            saved,
         };
 
-        self.state.searches.push(search_entry);
-        let search_index = self.state.searches.len() - 1;
-        self.state.searches_table_state.select(Some(search_index));
-
-        // Make this search the active one
-        self.state.selected_search_index = Some(search_index);
-
-        // Initialize results display (empty at first)
-        self.state.results_items.clear();
-        self.state.results_filtered_items.clear();
-        self.state.results_filtered_indices.clear();
-        self.state.results_selected_indices.clear();
-        self.state.results_table_state.select(Some(0));
-
-        // Switch focus to Results pane
-        self.state.focused_pane = FocusedPane::Results;
+        let existing = search_entry.saved.as_ref().and_then(|_| {
+            self.state
+                .searches
+                .iter()
+                .position(|s| s.saved.is_some() && s.query == query)
+        });
+        let search_index = if let Some(index) = existing {
+            let old = std::mem::replace(
+                &mut self.state.searches[index],
+                search_entry,
+            );
+            old.cancel_flag
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+            if self.state.selected_search_index == Some(index) {
+                self.state.results_items.clear();
+                self.state.results_filtered_items.clear();
+                self.state.results_filtered_indices.clear();
+                self.state.results_groups.clear();
+                self.state.results_selected_indices.clear();
+            }
+            index
+        } else {
+            self.state.searches.push(search_entry);
+            self.state.searches.len() - 1
+        };
 
         let client = self.client.clone();
         let timeout = self.search_timeout;
@@ -179,6 +380,26 @@ impl MainTui {
                 }
             }
         });
+        search_index
+    }
+
+    /// Make a search the active one and show its (initially empty) results.
+    fn focus_search(&mut self, search_index: usize) {
+        self.state.searches_table_state.select(Some(search_index));
+
+        // Make this search the active one
+        self.state.selected_search_index = Some(search_index);
+
+        // Initialize results display (empty at first)
+        self.state.results_items.clear();
+        self.state.results_filtered_items.clear();
+        self.state.results_filtered_indices.clear();
+        self.state.results_groups.clear();
+        self.state.results_selected_indices.clear();
+        self.state.results_table_state.select(Some(0));
+
+        // Switch focus to Results pane
+        self.state.focused_pane = FocusedPane::Results;
     }
 
     pub(super) fn update_search_results(&mut self) {
@@ -201,26 +422,32 @@ impl MainTui {
             .collect();
 
         // Now update state without holding any client locks
-        for (idx, search_results) in all_results {
+        let mut picks = Vec::new();
+        for (idx, mut search_results) in all_results {
             if let Some(search) = self.state.searches.get_mut(idx) {
+                if let Some(saved) = &search.saved {
+                    let filter = saved.filter();
+                    search_results = search_results
+                        .into_iter()
+                        .filter_map(|result| filter.apply(result))
+                        .collect();
+                }
                 // Results only accumulate, so an unchanged file count means
                 // nothing new arrived: skip the rebuild, which clones the
                 // full result list several times and dominates frame time.
-                let total_files: usize =
-                    search_results.iter().map(|r| r.files.len()).sum();
+                let total_files: usize = search_results
+                    .iter()
+                    .map(|r| r.files.len() + r.locked_files.len())
+                    .sum();
                 if total_files != search.results.len() {
                     search.results.clear();
-                    for result in search_results {
-                        for file in result.files {
-                            search.results.push(FileDisplayData {
-                                filename: file.name.clone(),
-                                size: file.size,
-                                username: result.username.clone(),
-                                speed: result.speed,
-                                slots: result.slots,
-                                bitrate: file.attribs.get(&0).copied(),
-                                length_seconds: file.attribs.get(&1).copied(),
-                            });
+                    for result in &search_results {
+                        for file in
+                            result.files.iter().chain(&result.locked_files)
+                        {
+                            search
+                                .results
+                                .push(FileDisplayData::new(result, file));
                         }
                     }
 
@@ -235,9 +462,17 @@ impl MainTui {
                         let (items, indices) = filter_results(
                             &self.state.results_items,
                             &self.state.results_filter_query,
+                            self.state.results_quality_filter,
+                            self.state.results_sort,
                         );
                         self.state.results_filtered_items = items;
                         self.state.results_filtered_indices = indices;
+                        if self.state.results_grouped {
+                            self.state.results_groups = group_results(
+                                &self.state.results_items,
+                                &self.state.results_filtered_indices,
+                            );
+                        }
                     }
                 }
 
@@ -246,42 +481,54 @@ impl MainTui {
                     && search.start_time.elapsed() > timeout
                 {
                     search.status = SearchStatus::Completed;
+                    if let Some(saved) = &search.saved {
+                        picks.extend(saved.picks(&search_results));
+                    }
                 }
             }
         }
+
+        if !picks.is_empty() {
+            self.auto_download(picks);
+        }
     }
 }
 
 /// Filter `items` by a case-insensitive substring match on filename or
-/// username. Returns the matching items alongside their indices in the original
-/// list, so callers can translate a filtered display index back to the
-/// unfiltered results. An empty query returns everything (identity mapping).
+/// username, and by the quality filter, then order them by `sort`. Returns
+/// the matching items alongside their indices in the original list, so
+/// callers can translate a display index back to the unfiltered results. An
+/// empty query with no quality filter, in arrival order, returns everything
+/// (identity mapping).
 fn filter_results(
     items: &[FileDisplayData],
     query: &str,
+    quality: QualityFilter,
+    sort: ResultsSort,
 ) -> (Vec<FileDisplayData>, Vec<usize>) {
     let query = query.to_lowercase();
-    if query.is_empty() {
-        return (items.to_vec(), (0..items.len()).collect());
+    let mut indices: Vec<usize> = items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| {
+            quality.admits(item)
+                && (item.filename.to_lowercase().contains(&query)
+                    || item.username.to_lowercase().contains(&query))
+        })
+        .map(|(idx, _)| idx)
+        .collect();
+    if sort != ResultsSort::Arrival {
+        indices.sort_by(|&a, &b| sort.compare(&items[a], &items[b]));
     }
-
-    let mut filtered_items = Vec::new();
-    let mut filtered_indices = Vec::new();
-    for (idx, item) in items.iter().enumerate() {
-        if item.filename.to_lowercase().contains(&query)
-            || item.username.to_lowercase().contains(&query)
-        {
-            filtered_items.push(item.clone());
-            filtered_indices.push(idx);
-        }
-    }
-    (filtered_items, filtered_indices)
+    let filtered_items =
+        indices.iter().map(|&idx| items[idx].clone()).collect();
+    (filtered_items, indices)
 }
 
 #[cfg(test)]
 mod tests {
     use super::filter_results;
-    use crate::models::FileDisplayData;
+    use crate::models::{FileDisplayData, QualityFilter, ResultsSort};
 
     fn file(filename: &str, username: &str) -> FileDisplayData {
         FileDisplayData {
@@ -294,7 +541,12 @@ mod tests {
     #[test]
     fn empty_query_returns_identity_mapping() {
         let items = vec![file("a.mp3", "bob"), file("b.flac", "amy")];
-        let (filtered, indices) = filter_results(&items, "");
+        let (filtered, indices) = filter_results(
+            &items,
+            "",
+            QualityFilter::All,
+            ResultsSort::Arrival,
+        );
         assert_eq!(filtered.len(), 2);
         assert_eq!(indices, vec![0, 1]);
     }
@@ -307,7 +559,12 @@ mod tests {
             file("alice_demo.mp3", "carol"),
         ];
         // "alice" matches item 1 (username) and item 2 (filename).
-        let (filtered, indices) = filter_results(&items, "alice");
+        let (filtered, indices) = filter_results(
+            &items,
+            "alice",
+            QualityFilter::All,
+            ResultsSort::Arrival,
+        );
         assert_eq!(filtered.len(), 2);
         assert_eq!(indices, vec![1, 2]);
         assert_eq!(filtered[0].filename, "song.flac");
@@ -317,8 +574,49 @@ mod tests {
     #[test]
     fn query_is_case_insensitive() {
         let items = vec![file("The Weeknd.mp3", "dj")];
-        let (filtered, indices) = filter_results(&items, "WEEKND");
+        let (filtered, indices) = filter_results(
+            &items,
+            "WEEKND",
+            QualityFilter::All,
+            ResultsSort::Arrival,
+        );
         assert_eq!(filtered.len(), 1);
         assert_eq!(indices, vec![0]);
     }
+
+    #[test]
+    fn quality_filter_combines_with_text_query() {
+        let mut lossless = file("live.flac", "bob");
+        lossless.bitrate = None;
+        let mut lossy = file("live.mp3", "bob");
+        lossy.bitrate = Some(128);
+        let items = vec![lossy, lossless, file("studio.flac", "amy")];
+        let (filtered, indices) = filter_results(
+            &items,
+            "live",
+            QualityFilter::Lossless,
+            ResultsSort::Arrival,
+        );
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(indices, vec![1]);
+    }
+
+    #[test]
+    fn sort_reorders_the_view_but_maps_back_to_arrival_indices() {
+        let mut small = file("live.mp3", "bob");
+        small.size = 10;
+        let mut big = file("live.flac", "amy");
+        big.size = 30;
+        let mut other = file("studio.flac", "amy");
+        other.size = 20;
+        let items = vec![small, other, big];
+        let (filtered, indices) = filter_results(
+            &items,
+            "live",
+            QualityFilter::All,
+            ResultsSort::Size,
+        );
+        assert_eq!(indices, vec![2, 0]);
+        assert_eq!(filtered[0].filename, "live.flac");
+    }
 }
@@ -7,7 +7,9 @@ mod styles;
 mod utils;
 
 pub use download_selector::FileSelector;
-pub use downloads::{render_download_stats, show_multi_download_progress};
+pub use downloads::{
+    download_report, render_download_stats, show_multi_download_progress,
+};
 pub use main_tui::launch_main_tui;
 pub use styles::*;
 pub use utils::*;
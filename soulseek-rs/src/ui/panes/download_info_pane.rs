@@ -71,16 +71,22 @@ fn build_info_lines(
             ("In progress".to_string(), warning_style())
         }
         DownloadStatus::Paused { .. } => ("Paused".to_string(), info_style()),
-        DownloadStatus::Completed => ("Completed".to_string(), success_style()),
+        DownloadStatus::Completed(_) => {
+            ("Completed".to_string(), success_style())
+        }
         DownloadStatus::Failed(_) => ("Failed".to_string(), error_style()),
         DownloadStatus::TimedOut => ("Timed out".to_string(), error_style()),
     };
     lines.push(label_value_styled("Status", status_text, status_style));
 
-    let save_path = expand_tilde(&download.download_directory)
-        .to_string_lossy()
-        .to_string();
-    lines.push(label_value("Save to", &save_path));
+    if let Some(summary) = download.summary() {
+        lines.push(label_value("Saved to", &summary.path.to_string_lossy()));
+    } else {
+        let save_path = expand_tilde(&download.download_directory)
+            .to_string_lossy()
+            .to_string();
+        lines.push(label_value("Save to", &save_path));
+    }
 
     if let Some(bitrate) = download.metadata.bitrate {
         lines.push(label_value("Bitrate", &format!("{bitrate} kbps")));
@@ -118,6 +124,8 @@ fn build_info_lines(
             bytes_downloaded,
             total_bytes,
             speed_bytes_per_sec,
+            average_speed_bytes_per_sec,
+            eta,
         } => {
             lines.push(Line::from(""));
             push_progress_lines(&mut lines, *bytes_downloaded, *total_bytes);
@@ -125,10 +133,15 @@ fn build_info_lines(
                 "Speed",
                 &format_speed(*speed_bytes_per_sec),
             ));
+            lines.push(label_value(
+                "Avg speed",
+                &format_speed(*average_speed_bytes_per_sec),
+            ));
 
-            if *speed_bytes_per_sec > 0.0 && *total_bytes > *bytes_downloaded {
-                let remaining = *total_bytes - *bytes_downloaded;
-                let eta_secs = (remaining as f64 / speed_bytes_per_sec) as u32;
+            if let Some(eta) = eta
+                && *total_bytes > *bytes_downloaded
+            {
+                let eta_secs = u32::try_from(eta.as_secs()).unwrap_or(u32::MAX);
                 lines.push(label_value("ETA", &format_duration(eta_secs)));
             }
         }
@@ -147,12 +160,22 @@ fn build_info_lines(
                     dimmed_style(),
                 )));
                 lines.push(Line::from(Span::styled(
-                    reason.clone(),
+                    reason.to_string(),
                     error_style(),
                 )));
             }
         }
-        DownloadStatus::Completed | DownloadStatus::TimedOut => {}
+        DownloadStatus::Completed(Some(summary)) => {
+            lines.push(Line::from(""));
+            let elapsed_secs =
+                u32::try_from(summary.elapsed.as_secs()).unwrap_or(u32::MAX);
+            lines.push(label_value("Took", &format_duration(elapsed_secs)));
+            lines.push(label_value(
+                "Avg speed",
+                &format_speed(summary.average_speed_bytes_per_sec),
+            ));
+        }
+        DownloadStatus::Completed(None) | DownloadStatus::TimedOut => {}
     }
 
     lines
@@ -211,7 +234,7 @@ fn split_filename(path: &str) -> (String, String) {
     }
 }
 
-fn format_duration(seconds: u32) -> String {
+pub(super) fn format_duration(seconds: u32) -> String {
     let hours = seconds / 3600;
     let minutes = (seconds % 3600) / 60;
     let secs = seconds % 60;
@@ -301,7 +324,7 @@ mod tests {
     #[test]
     fn failed_download_renders_its_error_reason() {
         let download = download_with_status(DownloadStatus::Failed(Some(
-            "Peer disconnected".to_string(),
+            "Peer disconnected".into(),
         )));
         let text = lines_to_text(&build_info_lines(&download));
         assert!(text.contains("Error"), "missing Error label: {text}");
@@ -315,4 +338,19 @@ mod tests {
         assert!(text.contains("Failed"), "status should still show Failed");
         assert!(!text.contains("Error"), "unexpected Error line: {text}");
     }
+
+    #[test]
+    fn completed_download_renders_its_summary() {
+        let download = download_with_status(DownloadStatus::Completed(Some(
+            soulseek_rs::DownloadSummary::new(
+                "/music/song.mp3".into(),
+                250 * 1_048_576,
+                std::time::Duration::from_secs(125),
+            ),
+        )));
+        let text = lines_to_text(&build_info_lines(&download));
+        assert!(text.contains("/music/song.mp3"), "missing path: {text}");
+        assert!(text.contains("2m 05s"), "missing elapsed: {text}");
+        assert!(text.contains("2.0 MB/s"), "missing speed: {text}");
+    }
 }
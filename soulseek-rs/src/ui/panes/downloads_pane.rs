@@ -1,8 +1,9 @@
-use crate::models::DownloadEntry;
+use super::download_info_pane::format_duration;
+use crate::models::{DownloadEntry, SPEED_HISTORY_LEN};
 use crate::ui::{
     HIGHLIGHT_SYMBOL, border_style, border_type, error_style, format_bytes,
     format_speed, header_style, highlight_style, inactive_style, info_style,
-    success_style, warning_style,
+    sparkline, success_style, warning_style,
 };
 use ratatui::{
     Frame,
@@ -44,6 +45,8 @@ pub fn render_downloads_pane(
         Cell::from("User").style(header_style()),
         Cell::from("Progress").style(header_style()),
         Cell::from("Speed").style(header_style()),
+        Cell::from("ETA").style(header_style()),
+        Cell::from("History").style(header_style()),
     ])
     .height(1);
 
@@ -55,13 +58,16 @@ pub fn render_downloads_pane(
                 DownloadStatus::Queued => ("⋯", inactive_style()),
                 DownloadStatus::InProgress { .. } => ("⧗", warning_style()),
                 DownloadStatus::Paused { .. } => ("⏸", info_style()),
-                DownloadStatus::Completed => ("✓", success_style()),
+                DownloadStatus::Completed(_) => ("✓", success_style()),
                 DownloadStatus::Failed(_) => ("✗", error_style()),
                 DownloadStatus::TimedOut => ("⏱", error_style()),
             };
 
             let progress_text = match &download.status {
-                DownloadStatus::Queued => "Queued".to_string(),
+                DownloadStatus::Queued => download.queue_position.map_or_else(
+                    || "Queued".to_string(),
+                    |place| format!("Queued (#{place})"),
+                ),
                 DownloadStatus::InProgress { .. } => {
                     let percent = if download.size > 0 {
                         (download.bytes_downloaded() as f64
@@ -92,7 +98,7 @@ pub fn render_downloads_pane(
                         percent
                     )
                 }
-                DownloadStatus::Completed => "Completed".to_string(),
+                DownloadStatus::Completed(_) => "Completed".to_string(),
                 DownloadStatus::Failed(_) => "Failed".to_string(),
                 DownloadStatus::TimedOut => "Timed out".to_string(),
             };
@@ -103,6 +109,14 @@ pub fn render_downloads_pane(
                 }
                 _ => "-".to_string(),
             };
+            let eta_text = download.eta().map_or_else(
+                || "-".to_string(),
+                |eta| {
+                    format_duration(
+                        u32::try_from(eta.as_secs()).unwrap_or(u32::MAX),
+                    )
+                },
+            );
 
             Row::new(vec![
                 Cell::from(status_icon).style(status_style),
@@ -110,12 +124,16 @@ pub fn render_downloads_pane(
                 Cell::from(download.username.clone()),
                 Cell::from(progress_text),
                 Cell::from(speed_text),
+                Cell::from(eta_text),
+                Cell::from(sparkline(&download_entry.speed_history))
+                    .style(info_style()),
             ])
         })
         .collect();
 
     rows.extend(uploads.iter().map(|upload| {
         let (status_icon, status_style) = match &upload.status {
+            UploadStatus::Queued { .. } => ("⋯", inactive_style()),
             UploadStatus::InProgress => ("⧗", warning_style()),
             UploadStatus::Completed => ("✓", success_style()),
             UploadStatus::Cancelled => ("✗", inactive_style()),
@@ -136,6 +154,7 @@ pub fn render_downloads_pane(
                     percent
                 )
             }
+            UploadStatus::Queued { place } => format!("Queued #{place}"),
             UploadStatus::Completed => format_bytes(upload.size),
             UploadStatus::Cancelled => "Cancelled".to_string(),
             UploadStatus::Failed(_) => "Failed".to_string(),
@@ -151,6 +170,8 @@ pub fn render_downloads_pane(
             Cell::from(upload.username.clone()),
             Cell::from(progress_text),
             Cell::from(String::new()),
+            Cell::from(String::new()),
+            Cell::from(String::new()),
         ])
     }));
 
@@ -160,6 +181,8 @@ pub fn render_downloads_pane(
         ratatui::layout::Constraint::Length(15),
         ratatui::layout::Constraint::Length(25),
         ratatui::layout::Constraint::Length(12),
+        ratatui::layout::Constraint::Length(10),
+        ratatui::layout::Constraint::Length(SPEED_HISTORY_LEN as u16),
     ];
 
     let table = Table::new(rows, widths)
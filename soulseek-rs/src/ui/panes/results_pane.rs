@@ -1,4 +1,4 @@
-use crate::models::FileDisplayData;
+use crate::models::{FileDisplayData, QualityFilter, ResultGroup, ResultsSort};
 use crate::ui::{
     BYTES_PER_MB, HIGHLIGHT_SYMBOL, border_style, border_type, format_bytes,
     header_style, highlight_style,
@@ -6,6 +6,8 @@ use crate::ui::{
 use ratatui::{
     Frame,
     layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::{
         Block, Borders, Cell, HighlightSpacing, Paragraph, Row, Table,
         TableState,
@@ -22,10 +24,57 @@ pub struct ResultsPaneParams<'a> {
     /// Maps a rendered row index to its index in the unfiltered results list.
     /// `None` means the rendered rows are the unfiltered list (identity map).
     pub original_indices: Option<&'a [usize]>,
+    /// The grouped view's nodes, shown instead of `items` when set.
+    pub groups: Option<&'a [ResultGroup]>,
     pub filter_query: &'a str,
     pub is_filtering: bool,
+    pub quality_filter: QualityFilter,
+    /// Per-filter result counts, in [`QualityFilter::ALL`] order.
+    pub quality_counts: [usize; 4],
+    pub sort: ResultsSort,
     pub focused: bool,
     pub active_search_query: Option<&'a str>,
+    /// Whether a file with this name and size was downloaded before.
+    pub downloaded: &'a dyn Fn(&str, u64) -> bool,
+    /// Whether a user is a favorite, starred in the user column.
+    pub favorite: &'a dyn Fn(&str) -> bool,
+}
+
+/// Title suffix listing each quality filter with its count, the active one
+/// bracketed, e.g. `all 80 · [lossless 12] · 320+ 42 · V0+ 50`.
+fn quality_summary(active: QualityFilter, counts: [usize; 4]) -> String {
+    QualityFilter::ALL
+        .iter()
+        .zip(counts)
+        .map(|(&filter, count)| {
+            if filter == active {
+                format!("[{} {count}]", filter.label())
+            } else {
+                format!("{} {count}", filter.label())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+/// A group node's checkbox: ticked when all its files are selected, dashed
+/// when only some are.
+fn group_checkbox(
+    group: &ResultGroup,
+    selected_indices: &HashSet<usize>,
+) -> &'static str {
+    let selected = group
+        .files
+        .iter()
+        .filter(|idx| selected_indices.contains(idx))
+        .count();
+    if selected == 0 {
+        "[ ]"
+    } else if selected == group.files.len() {
+        "[✓]"
+    } else {
+        "[-]"
+    }
 }
 
 /// Whether the rendered row `display_idx` is selected. `selected_indices` holds
@@ -56,14 +105,29 @@ pub fn render_results_pane(
         table_state,
         selected_indices,
         original_indices,
+        groups,
         filter_query,
         is_filtering,
+        quality_filter,
+        quality_counts,
+        sort,
         focused,
         active_search_query,
+        downloaded,
+        favorite,
     } = params;
+    let quality = if sort == ResultsSort::Arrival {
+        quality_summary(quality_filter, quality_counts)
+    } else {
+        format!(
+            "{} │ sort: {}",
+            quality_summary(quality_filter, quality_counts),
+            sort.label()
+        )
+    };
     if items.is_empty() {
         let title = if let Some(query) = active_search_query {
-            format!("[2] Results: {query}")
+            format!("[2] Results: {query} │ {quality}")
         } else {
             "[2] Results".to_string()
         };
@@ -76,6 +140,11 @@ pub fn render_results_pane(
 
         let message = if is_filtering {
             format!("No results match filter: '{filter_query}'")
+        } else if quality_filter != QualityFilter::All {
+            format!(
+                "No {} results. Press F to change the quality filter.",
+                quality_filter.label()
+            )
         } else {
             format!(
                 "soulseek-rs 🦀 v{VERSION}
@@ -89,14 +158,42 @@ No results. Select a search from the Searches pane [1]. Or start new search [s 
         return;
     }
 
+    let title = if is_filtering {
+        format!("[2] Results - Filter: '{filter_query}' │ {quality}")
+    } else if let Some(query) = active_search_query {
+        format!("[2] Results: {query} │ {quality}")
+    } else {
+        format!("[2] Results │ {quality}")
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style(focused))
+        .border_type(border_type(focused))
+        .title(title);
+
+    if let Some(groups) = groups {
+        render_groups(
+            frame,
+            area,
+            block,
+            groups,
+            table_state,
+            selected_indices,
+        );
+        return;
+    }
+
     let header = Row::new(vec![
         Cell::from("✓").style(header_style()),
         Cell::from("Filename").style(header_style()),
         Cell::from("Size").style(header_style()),
         Cell::from("User").style(header_style()),
         Cell::from("Bitrate").style(header_style()),
+        Cell::from("Length").style(header_style()),
+        Cell::from("Format").style(header_style()),
         Cell::from("Speed").style(header_style()),
-        Cell::from("Slots").style(header_style()),
+        Cell::from("Slot").style(header_style()),
+        Cell::from("Queue").style(header_style()),
     ])
     .height(1);
 
@@ -111,10 +208,6 @@ No results. Select a search from the Searches pane [1]. Or start new search [s 
                     "[ ]"
                 };
 
-            let bitrate_str = file
-                .bitrate
-                .map_or_else(|| "-".to_string(), |br| format!("{br} kbps"));
-
             let speed_str = if file.speed > 0 {
                 let speed_mb = (f64::from(file.speed) / BYTES_PER_MB * 100.0)
                     .round()
@@ -124,14 +217,44 @@ No results. Select a search from the Searches pane [1]. Or start new search [s 
                 "-".to_string()
             };
 
+            // Names repaired from a legacy charset may still be slightly
+            // off, so say which charset was guessed.
+            let mut name = vec![Span::raw(file.filename.clone())];
+            if file.locked {
+                name.insert(
+                    0,
+                    Span::styled("🔒 ", Style::default().fg(Color::Yellow)),
+                );
+            }
+            if let Some(charset) = file.name_charset {
+                name.push(Span::styled(
+                    format!(" [{charset}]"),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            if downloaded(&file.filename, file.size) {
+                name.push(Span::styled(
+                    " (downloaded)",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            let name = Line::from(name);
+
             Row::new(vec![
                 Cell::from(checkbox),
-                Cell::from(file.filename.clone()),
+                Cell::from(name),
                 Cell::from(format_bytes(file.size)),
-                Cell::from(file.username.clone()),
-                Cell::from(bitrate_str),
+                Cell::from(if favorite(&file.username) {
+                    format!("★ {}", file.username)
+                } else {
+                    file.username.clone()
+                }),
+                Cell::from(file.bitrate_label()),
+                Cell::from(file.length_label()),
+                Cell::from(file.format_label()),
                 Cell::from(speed_str),
-                Cell::from(file.slots.to_string()),
+                Cell::from(if file.free_slot { "free" } else { "-" }),
+                Cell::from(file.queue_length.to_string()),
             ])
         })
         .collect();
@@ -142,39 +265,92 @@ No results. Select a search from the Searches pane [1]. Or start new search [s 
         ratatui::layout::Constraint::Length(12),
         ratatui::layout::Constraint::Length(15),
         ratatui::layout::Constraint::Length(10),
+        ratatui::layout::Constraint::Length(7),
+        ratatui::layout::Constraint::Length(9),
         ratatui::layout::Constraint::Length(12),
+        ratatui::layout::Constraint::Length(5),
         ratatui::layout::Constraint::Length(6),
     ];
 
-    let title = if is_filtering {
-        format!("[2] Results - Filter: '{filter_query}'")
-    } else if let Some(query) = active_search_query {
-        format!("[2] Results: {query}")
-    } else {
-        "[2] Results".to_string()
-    };
+    let table = Table::new(rows, widths)
+        .header(header)
+        .row_highlight_style(highlight_style())
+        .highlight_symbol(HIGHLIGHT_SYMBOL)
+        .highlight_spacing(HighlightSpacing::Always)
+        .block(block);
+
+    frame.render_stateful_widget(table, area, table_state);
+}
+
+/// The grouped view: each user's node, then their folders indented below.
+fn render_groups(
+    frame: &mut Frame,
+    area: Rect,
+    block: Block,
+    groups: &[ResultGroup],
+    table_state: &mut TableState,
+    selected_indices: &HashSet<usize>,
+) {
+    let header = Row::new(vec![
+        Cell::from("✓").style(header_style()),
+        Cell::from("User / Folder").style(header_style()),
+        Cell::from("Files").style(header_style()),
+        Cell::from("Size").style(header_style()),
+    ])
+    .height(1);
+
+    let rows: Vec<Row> = groups
+        .iter()
+        .map(|group| {
+            let name = group.folder.as_ref().map_or_else(
+                || {
+                    Line::from(Span::styled(
+                        group.username.clone(),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ))
+                },
+                |folder| Line::from(format!("  └ {folder}")),
+            );
+            Row::new(vec![
+                Cell::from(group_checkbox(group, selected_indices)),
+                Cell::from(name),
+                Cell::from(group.files.len().to_string()),
+                Cell::from(format_bytes(group.size)),
+            ])
+        })
+        .collect();
+
+    let widths = [
+        ratatui::layout::Constraint::Length(3),
+        ratatui::layout::Constraint::Fill(3),
+        ratatui::layout::Constraint::Length(6),
+        ratatui::layout::Constraint::Length(12),
+    ];
 
     let table = Table::new(rows, widths)
         .header(header)
         .row_highlight_style(highlight_style())
         .highlight_symbol(HIGHLIGHT_SYMBOL)
         .highlight_spacing(HighlightSpacing::Always)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(border_style(focused))
-                .border_type(border_type(focused))
-                .title(title),
-        );
+        .block(block);
 
     frame.render_stateful_widget(table, area, table_state);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::row_is_selected;
+    use super::{group_checkbox, quality_summary, row_is_selected};
+    use crate::models::{QualityFilter, ResultGroup};
     use std::collections::HashSet;
 
+    #[test]
+    fn quality_summary_brackets_the_active_filter() {
+        assert_eq!(
+            quality_summary(QualityFilter::Kbps320, [9, 1, 4, 6]),
+            "all 9 · lossless 1 · [320+ 4] · V0+ 6"
+        );
+    }
+
     #[test]
     fn identity_mapping_used_when_no_filter() {
         let selected: HashSet<usize> = std::iter::once(1).collect();
@@ -199,4 +375,20 @@ mod tests {
         let selected: HashSet<usize> = [2, 5].into_iter().collect();
         assert!(!row_is_selected(9, Some(&original_indices), &selected));
     }
+
+    #[test]
+    fn group_checkbox_shows_partial_selection() {
+        let group = ResultGroup {
+            username: "bob".into(),
+            folder: None,
+            files: vec![1, 4],
+            size: 0,
+        };
+        let mut selected = HashSet::new();
+        assert_eq!(group_checkbox(&group, &selected), "[ ]");
+        selected.insert(4);
+        assert_eq!(group_checkbox(&group, &selected), "[-]");
+        selected.insert(1);
+        assert_eq!(group_checkbox(&group, &selected), "[✓]");
+    }
 }
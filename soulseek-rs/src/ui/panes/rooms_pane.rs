@@ -14,8 +14,8 @@ use ratatui::{
     },
 };
 
-/// Render the chat-rooms popup: either the browsable room list or the open
-/// rooms' tabbed chat view.
+/// Render the chat pane: either the browsable room list or the tabbed chat
+/// view of open rooms and private conversations.
 pub fn render_rooms_pane(
     frame: &mut Frame,
     area: Rect,
@@ -68,11 +68,14 @@ fn render_list(
         .iter()
         .map(|r| {
             let open = rooms.open_index(&r.name).is_some();
-            let name = if open {
+            let mut name = if open {
                 format!("● {}", r.name)
             } else {
                 format!("  {}", r.name)
             };
+            if r.private {
+                name.push_str(" (private)");
+            }
             let name_style = if open {
                 accent_style()
             } else {
@@ -103,7 +106,7 @@ fn render_chat(frame: &mut Frame, area: Rect, rooms: &RoomsState) {
         .border_style(border_style(true))
         .border_type(border_type(true))
         .title(
-            " Chat rooms  (Tab: switch, l: room list, x: leave, Esc: back) ",
+            " Chat  (Tab: switch, l: room list, x: leave/close, Esc: back) ",
         );
     let inner = block.inner(area);
     frame.render_widget(block, area);
@@ -119,20 +122,26 @@ fn render_chat(frame: &mut Frame, area: Rect, rooms: &RoomsState) {
 
     let Some(active) = rooms.active_room() else {
         frame.render_widget(
-            Paragraph::new("No open rooms. Press l for the room list.")
-                .style(dimmed_style()),
+            Paragraph::new(
+                "No open rooms or conversations. Press l for the room list.",
+            )
+            .style(dimmed_style()),
             chunks[1],
         );
         return;
     };
 
-    // Body: messages (left) + user list (right).
-    let body =
-        Layout::horizontal([Constraint::Fill(1), Constraint::Length(22)])
-            .split(chunks[1]);
+    if active.private {
+        render_messages(frame, chunks[1], active.lines.as_slice());
+    } else {
+        // Body: messages (left) + user list (right).
+        let body =
+            Layout::horizontal([Constraint::Fill(1), Constraint::Length(22)])
+                .split(chunks[1]);
 
-    render_messages(frame, body[0], active.lines.as_slice());
-    render_users(frame, body[1], &active.users, rooms.user_selected);
+        render_messages(frame, body[0], active.lines.as_slice());
+        render_users(frame, body[1], &active.users, rooms.user_selected);
+    }
 
     // Compose line or hint.
     if rooms.composing {
@@ -157,10 +166,16 @@ fn render_tab_bar(frame: &mut Frame, area: Rect, rooms: &RoomsState) {
     let mut spans: Vec<Span> = Vec::new();
     for (i, room) in rooms.open.iter().enumerate() {
         let active = i == rooms.active;
+        // Conversations are told apart from rooms by an @.
+        let name = if room.private {
+            format!("@{}", room.name)
+        } else {
+            room.name.clone()
+        };
         let label = if room.unread > 0 {
-            format!(" {} ({}) ", room.name, room.unread)
+            format!(" {name} ({}) ", room.unread)
         } else {
-            format!(" {} ", room.name)
+            format!(" {name} ")
         };
         let mut style = if active {
             highlight_style()
@@ -106,7 +106,9 @@ pub fn format_shortcuts(shortcuts: &[(&str, &str)]) -> String {
 }
 
 // Styled shortcut formatting helper (returns Line with colored Spans)
-pub fn format_shortcuts_styled(shortcuts: &[(&str, &str)]) -> Line<'static> {
+pub fn format_shortcuts_styled<K: AsRef<str>>(
+    shortcuts: &[(K, &str)],
+) -> Line<'static> {
     let mut spans = Vec::new();
 
     for (i, (key, action)) in shortcuts.iter().enumerate() {
@@ -116,7 +118,7 @@ pub fn format_shortcuts_styled(shortcuts: &[(&str, &str)]) -> Line<'static> {
 
         spans.push(Span::styled("[", dimmed_style()));
         spans.push(Span::styled(
-            key.to_string(),
+            key.as_ref().to_string(),
             Style::default()
                 .fg(COLOR_PRIMARY)
                 .add_modifier(Modifier::BOLD),
@@ -30,10 +30,35 @@ pub fn format_speed(speed_bytes_per_sec: f64) -> String {
     format!("{mb:.1} MB/s")
 }
 
-pub fn get_bitrate(
-    attribs: &std::collections::HashMap<u32, u32>,
-) -> Option<u32> {
-    attribs.get(&0).copied()
+/// A long span to the two largest units, like `3d 4h`, `2h 5m` or `12m`.
+pub fn format_time_left(left: std::time::Duration) -> String {
+    let minutes = left.as_secs() / 60;
+    let (days, hours, minutes) =
+        (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One bar per sample, scaled to the largest.
+pub fn sparkline(samples: &[f64]) -> String {
+    let max = samples.iter().copied().fold(0.0, f64::max);
+    samples
+        .iter()
+        .map(|&sample| {
+            if max <= 0.0 {
+                return SPARK_CHARS[0];
+            }
+            let level = (sample / max * 7.0).round() as usize;
+            SPARK_CHARS[level.min(7)]
+        })
+        .collect()
 }
 
 const SPINNER_CHARS: [&str; 10] =
@@ -4,14 +4,16 @@
 
 use soulseek_rs::DownloadStatus;
 use soulseek_rs::types::{Download, DownloadMetadata};
-use soulseek_rs_tui::models::{AppState, DownloadEntry};
+use soulseek_rs_tui::models::{AppState, DownloadEntry, FileDisplayData};
 use soulseek_rs_tui::persist::config::FileConfig;
-use soulseek_rs_tui::persist::snapshot::{Snapshot, restore_searches};
+use soulseek_rs_tui::persist::snapshot::{
+    Snapshot, capture_search_results, restore_search_results, restore_searches,
+};
 use soulseek_rs_tui::persist::state::StateStore;
 
 fn download_entry(filename: &str, status: DownloadStatus) -> DownloadEntry {
-    DownloadEntry {
-        download: Download {
+    DownloadEntry::new(
+        Download {
             username: "peer".into(),
             filename: filename.into(),
             token: 1,
@@ -22,8 +24,8 @@ fn download_entry(filename: &str, status: DownloadStatus) -> DownloadEntry {
             queue_position: None,
             metadata: DownloadMetadata::default(),
         },
-        receiver: None,
-    }
+        None,
+    )
 }
 
 #[test]
@@ -37,16 +39,23 @@ fn state_survives_a_restart() {
         let mut state = AppState::new();
         state
             .downloads
-            .push(download_entry("done.flac", DownloadStatus::Completed));
+            .push(download_entry("done.flac", DownloadStatus::Completed(None)));
         state.downloads.push(download_entry(
             "half.flac",
             DownloadStatus::InProgress {
                 bytes_downloaded: 500,
                 total_bytes: 1000,
                 speed_bytes_per_sec: 1.0,
+                average_speed_bytes_per_sec: 1.0,
+                eta: None,
             },
         ));
         restore_searches(&mut state, &["beatles".to_string()]);
+        state.searches[0].results.push(FileDisplayData {
+            filename: "help.mp3".into(),
+            username: "peer".into(),
+            ..FileDisplayData::default()
+        });
         state.rooms.focus_or_open("indie");
 
         let store = StateStore::new(state_dir.clone());
@@ -54,6 +63,9 @@ fn state_survives_a_restart() {
         store.save_downloads(&snapshot.downloads).unwrap();
         store.save_search_queries(&snapshot.queries).unwrap();
         store.save_rooms(&snapshot.rooms).unwrap();
+        store
+            .save_search_results(&capture_search_results(&state))
+            .unwrap();
     }
 
     // The files on disk are versioned envelopes.
@@ -82,7 +94,9 @@ fn state_survives_a_restart() {
 
     let mut state = AppState::new();
     restore_searches(&mut state, &store.load_search_queries());
+    restore_search_results(&mut state, &store.load_search_results());
     assert_eq!(state.searches[0].query, "beatles");
+    assert_eq!(state.searches[0].results[0].filename, "help.mp3");
 }
 
 #[test]